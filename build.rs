@@ -0,0 +1,25 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{env, path::Path, process::Command};
+
+fn main() {
+    // Commit shown in the about page, packagers building without git can set it themselves
+    println!("cargo:rerun-if-env-changed=COSMIC_EDIT_GIT_COMMIT");
+    for path in [".git/HEAD", ".git/refs"] {
+        if Path::new(path).exists() {
+            println!("cargo:rerun-if-changed={path}");
+        }
+    }
+    let commit = env::var("COSMIC_EDIT_GIT_COMMIT").ok().or_else(|| {
+        let output = Command::new("git")
+            .args(["rev-parse", "--short", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())?;
+        Some(String::from_utf8(output.stdout).ok()?.trim().to_string())
+    });
+    println!(
+        "cargo:rustc-env=COSMIC_EDIT_GIT_COMMIT={}",
+        commit.unwrap_or_default()
+    );
+}
@@ -8,6 +8,7 @@ todo = TODO
 ## About
 repository = Repository
 support = Support
+license-notice = This program comes with absolutely no warranty. It is free software under the GNU General Public License, version 3, and uses libraries that are under their own open source licenses.
 
 ## Document statistics
 document-statistics = Document statistics
@@ -15,6 +16,135 @@ word-count = Word count
 character-count = Characters
 character-count-no-spaces = Characters (without spaces)
 line-count = Lines
+reading-time = Reading time
+reading-time-minutes = {$minutes ->
+    [one] {$minutes} minute
+   *[other] {$minutes} minutes
+}
+selection = Selection
+
+## Document type
+document-type-title = Document type
+document-type-search = Search languages
+automatic = Automatic
+
+## Encoding
+encoding-title = Save with encoding
+
+## Changes on disk
+file-changed-on-disk = This file was changed by another program.
+compare = Compare
+keep-my-version = Keep my version
+reload = Reload
+changes-on-disk = Changes on disk
+
+## Compare files
+no-differences = The files are identical
+differences = {$total ->
+    [one] {$total} difference
+   *[other] {$total} differences
+}
+difference-count = Difference {$current} of {$total}
+previous-difference = Previous difference
+next-difference = Next difference
+
+## Merge conflicts
+conflicts = {$total ->
+    [one] {$total} merge conflict
+   *[other] {$total} merge conflicts
+}
+conflict-count = Merge conflict {$current} of {$total}
+accept-ours = Accept ours
+accept-theirs = Accept theirs
+accept-both = Accept both
+previous-conflict = Previous conflict
+next-conflict = Next conflict
+
+## Problems
+problems = Problems
+no-problems = No problems were found in the open files.
+
+## References
+references = References
+no-references = No references were found.
+
+## Outline
+outline = Outline
+no-symbols = No symbols were found in this document.
+
+## Undo history
+undo-history = Undo history
+undo-history-opened = Opened
+undo-history-edit = Edit {$number}
+no-undo-history = Edits of this document are not kept.
+time-just-now = Just now
+time-minutes-ago = {$minutes ->
+    [one] {$minutes} minute ago
+   *[other] {$minutes} minutes ago
+}
+time-hours-ago = {$hours ->
+    [one] {$hours} hour ago
+   *[other] {$hours} hours ago
+}
+time-days-ago = {$days ->
+    [one] {$days} day ago
+   *[other] {$days} days ago
+}
+
+## Remote files
+open-location = Open location
+open = Open
+location-placeholder = sftp://host/path/to/file
+loading-remote-file = Loading {$uri}...
+
+## Tasks
+run-task-title = Run task
+no-tasks = This project has no tasks. Add them to {$file} in the project folder.
+task-output = Task output
+task-finished = The task exited with code {$code}
+task-stopped = The task was stopped
+
+## Large files
+loading-large-file = Loading large file... {$percent}%
+large-file = Syntax highlighting and word wrap are turned off for large files.
+
+## Status bar
+status-position = Ln {$line}, Col {$column}
+status-selected = {" "}({$count} selected)
+recording-macro = Recording macro
+indent-spaces = Spaces
+indent-tabs = Tabs
+convert-line-endings = Convert line endings
+convert-indentation = Convert indentation
+status-problems = {$errors ->
+    [one] {$errors} error
+   *[other] {$errors} errors
+}, {$warnings ->
+    [one] {$warnings} warning
+   *[other] {$warnings} warnings
+}
+
+## Vim command line
+ex-argument-required = Argument required
+ex-file-exists = File exists (add ! to override)
+ex-invalid-pattern = Invalid pattern: {$error}
+ex-invalid-range = Invalid range
+ex-no-file-name = No file name
+ex-not-a-command = Not an editor command: {$command}
+ex-no-write-since-last-change = No write since last change (add ! to override)
+ex-pattern-not-found = Pattern not found: {$pattern}
+ex-trailing-characters = Trailing characters: {$text}
+ex-unknown-option = Unknown option: {$option}
+ex-write-failed = Cannot write: {$error}
+ex-not-saved = The file was not saved
+ex-substitutions = {$count ->
+    [one] {$count} substitution
+   *[other] {$count} substitutions
+}
+ex-matches = {$count ->
+    [one] {$count} match
+   *[other] {$count} matches
+}
 
 ## Git management
 git-management = Git management
@@ -24,17 +154,103 @@ stage = Stage
 staged-changes = Staged changes
 unstage = Unstage
 unstaged-changes = Unstaged changes
+revert-hunk = Revert change
+stage-hunk = Stage change
+
+## Project files
+new-folder = New folder
+rename = Rename
+duplicate = Duplicate
+move-to-trash = Move to trash
+name = Name
+name-exists = A file or folder with this name already exists.
+create = Create
+
+## Rename symbol
+new-name = New name
+
+## Filter through command
+filter-title = Filter through command
+filter-body = The selection, or the whole document without one, is replaced with what the command writes.
+filter-placeholder = Shell command, like sort or jq .
+filter = Filter
+
+## Macros
+play-macro-repeatedly = Play macro repeatedly
+macro-times = Number of times
+
+## Clipboard history
+paste-from-history-title = Paste from history
+clipboard-lines = {$count} lines
+
+## Quick open
+quick-open = Quick open...
+quick-open-placeholder = Search files by name
+symbol-search-placeholder = Search symbols by name
+command-palette-placeholder = Search commands by name
+no-tags-file = Run ctags in a project to search its symbols.
+
+## Spell check
+no-suggestions = No suggestions
+add-to-dictionary = Add to dictionary
+ignore-word = Ignore
+
+## Tab context menu
+close-tab = Close
+close-other-tabs = Close other tabs
+close-tabs-to-right = Close tabs to the right
+close-saved-tabs = Close saved tabs
+copy-path = Copy path
+reveal-in-files = Reveal in Files
+move-tab-to-new-window = Move tab to new window
+pin-tab = Pin tab
+tabs = Tabs
+
+## Page setup
+page-setup = Page setup
+paper-size = Paper size
+paper-a4 = A4
+paper-letter = Letter
+print-header-footer = Print header and footer
+print-line-numbers = Print line numbers
+print-syntax-colors = Print syntax colors
+print-page = Page {$page} of {$pages}
+print-unsupported = Only Latin-1 characters can be printed, but "{$character}" is not one of them.
+
+## Export
+export-html-title = Export as HTML
+export-pdf-title = Export as PDF
+export-button = Export...
+
+## Recover
+recover-title = Recover unsaved changes?
+recover-body = COSMIC Text Editor did not close properly. Changes to these documents can be recovered.
+recover = Recover
 
 ## Project search
 project-search = Project search
+project-search-in-progress = Searching...
+replace-selected = Replace selected
 
 ## Prompt save changes
 prompt-save-changes-title = Save changes before closing?
 prompt-unsaved-changes = You have unsaved changes. If you continue without saving, these changes will be lost.
 cancel = Cancel
 discard = Discard changes
+discard-one = Discard
 save-all = Save all
 
+## Save as administrator
+save-elevated-title = Save as administrator?
+save-elevated-body = You do not have permission to change {$path}. Saving as an administrator asks for a password.
+save-elevated = Save as administrator
+
+## Save with an encoding that cannot encode all characters
+save-encoding-title = Replace characters?
+save-encoding-body = Some characters cannot be saved as {$encoding}, and would be replaced with character references.
+save-as-utf8 = Save as UTF-8
+save-anyway = Save anyway
+
 ## Settings
 settings = Settings
 
@@ -46,25 +262,91 @@ dark = Dark
 light = Light
 syntax-dark = Syntax dark
 syntax-light = Syntax light
+syntax-themes = Syntax themes
+syntax-themes-description = Themes are loaded from the .tmTheme files in {$dir}
+import-syntax-theme = Import...
 default-font = Default font
+font-ligatures = Font ligatures
 default-font-size = Default font size
 default-zoom-step = Zoom steps
+line-height = Line height
+
+### Editor
+editor = Editor
+word-wrap-width = Word wrap width
+word-wrap-at-window = Window width
+word-wrap-at-column = {$column} columns
 
+### Advanced
+advanced = Advanced
+
+### Files
+files = Files
+restore-session = Reopen files from the last session
+save-find-history = Remember search history
+save-clipboard-history = Remember clipboard history
+save-undo-history-panel = Remember undo history panel
+save-undo-history-panel-description = Keep the states in the undo history panel of saved files, so that earlier versions can be restored after they are opened again. Undo and redo only go back to the opened text.
+auto-save = Autosave
+auto-save-off = Off
+auto-save-seconds = After {$seconds} seconds
+auto-save-minutes = {$minutes ->
+    [one] After {$minutes} minute
+   *[other] After {$minutes} minutes
+}
+auto-save-pending = Changes will be saved automatically
+atomic-save = Save files safely
+atomic-save-description = Write to a temporary file before replacing the original. Turn off if this breaks hard links on your file system.
+language-servers = Language servers
+language-servers-description = Start the language server of a file's language to show its errors and warnings.
+word-completion = Complete words
+word-completion-description = Suggest words from open documents while typing in files without a language server.
+primary-selection = Middle-click paste
+primary-selection-description = Paste the text that was last selected with the mouse by clicking the middle button.
+
+### File types
+file-types = File types
+file-type-placeholder = File name pattern, like *.conf.j2
+add-file-type = Add
 
 ### Keyboard shortcuts
 keyboard-shortcuts = Keyboard shortcuts
 enable-vim-bindings = Enable Vim bindings
+keymap = Keymap
+keymap-cosmic = COSMIC
+keyboard-shortcuts-search = Search commands
+set-shortcut = Set shortcut
+press-shortcut = Press a shortcut...
+shortcut-conflict = {$shortcut} is used by {$command}.
+reset-to-defaults = Reset to defaults
+import-shortcuts = Import...
+export-shortcuts = Export...
+close = Close
 
 # Find
 find-placeholder = Find...
 find-previous = Find previous
 find-next = Find next
+find-match-count = {$current} of {$total}
+find-matches = {$total ->
+    [one] {$total} match
+   *[other] {$total} matches
+}
+find-no-matches = No results
 replace-placeholder = Replace...
 replace = Replace
 replace-all = Replace all
+replace-preview = Replace with: {$text}
+all-open-documents = All open documents
+replace-count = {$title}: {$count ->
+    [one] {$count} replacement
+   *[other] {$count} replacements
+}
 case-sensitive = Case sensitive
 use-regex = Use regex
+whole-words = Whole words
 wrap-around = Wrap around
+selection-only = Selection only
 
 # Menu
 
@@ -73,18 +355,33 @@ file = File
 new-file = New file
 new-window = New window
 open-file = Open file...
+menu-open-location = Open location...
 open-recent-file = Open recent file
+clear-recent = Clear recent
 close-file = Close file
+reopen-closed-tab = Reopen closed tab
+recently-closed = Recently closed
 menu-open-project = Open project...
 open-recent-project = Open recent project
 close-project = Close project
+tasks = Tasks
+run-task = Run task...
+rerun-task = Run last task
+stop-task = Stop task
 save = Save
 save-as = Save as...
 revert-all-changes = Revert all changes
 menu-document-statistics = Document statistics...
 document-type = Document type...
 encoding = Encoding...
+line-endings = Line endings
+convert-to-lf = Convert to LF (Linux and macOS)
+convert-to-crlf = Convert to CRLF (Windows)
 menu-git-management = Git management...
+compare-files = Compare files...
+export = Export
+export-html = HTML...
+export-pdf = PDF...
 print = Print
 quit = Quit
 
@@ -95,16 +392,74 @@ redo = Redo
 cut = Cut
 copy = Copy
 paste = Paste
+paste-from-history = Paste from history...
 select-all = Select all
+add-caret-above = Add caret above
+add-caret-below = Add caret below
+add-next-occurrence = Add next occurrence
+show-completions = Show completions
+toggle-comment = Toggle comment
+format-document = Format document
+format-on-save = Format on save
+filter-through-command = Filter through command...
+go-to-matching-bracket = Go to matching bracket
+go-to-definition = Go to definition
+go-to-symbol = Go to symbol...
+find-references = Find references
+rename-symbol = Rename symbol
+navigate-back = Go back
+navigate-forward = Go forward
+move-line-up = Move line up
+move-line-down = Move line down
+duplicate-line = Duplicate line
+join-lines = Join lines
+delete-line = Delete line
+delete-character = Delete character
+kill-line = Kill to end of line
+kill-word = Kill word
+set-mark = Set mark
+clear-mark = Clear mark
+move-cursor = Move cursor
+move-backward = Back one character
+move-forward = Forward one character
+move-previous-line = Previous line
+move-next-line = Next line
+move-line-start = Start of line
+move-line-end = End of line
+move-word-backward = Back one word
+move-word-forward = Forward one word
+move-page-up = Page up
+move-page-down = Page down
+move-document-start = Start of document
+move-document-end = End of document
+merge-conflicts = Merge conflicts
+line-tools = Line tools
+sort-lines-ascending = Sort ascending
+sort-lines-descending = Sort descending
+reverse-lines = Reverse
+remove-duplicate-lines = Remove duplicate lines
+macros = Macros
+record-macro = Record macro
+play-macro = Play macro
+menu-play-macro-repeatedly = Play macro repeatedly...
 find = Find
 find-in-project = Find in project...
-spell-check = Spell check...
+spell-check = Spell check
+spell-check-languages = Spell check languages
 
 ## View
 view = View
 zoom-in = Zoom in
 default-size = Default size
 zoom-out = Zoom out
+split-horizontal = Split horizontally
+split-vertical = Split vertically
+focus-next-pane = Focus next pane
+close-split = Close split
+fullscreen = Fullscreen
+zen-mode = Zen mode
+next-tab = Next tab
+previous-tab = Previous tab
 indentation = Indentation
 
 ### Indentation
@@ -116,7 +471,23 @@ convert-indentation-to-tabs = Convert indentation to tabs
 word-wrap = Word wrap
 show-line-numbers = Show line numbers
 highlight-current-line = Highlight current line
+show-indent-guides = Show indentation guides
+show-whitespace = Show whitespace
+show-minimap = Show minimap
+markdown-preview = Markdown preview
+show-git-changes = Show Git changes
+show-git-blame = Show Git blame
+auto-close-brackets = Auto-close brackets and quotes
 syntax-highlighting = Syntax highlighting...
+reload-syntaxes = Reload syntaxes and themes
+menu-problems = Problems...
+menu-outline = Outline...
+menu-undo-history = Undo history...
+command-palette = Command palette...
 menu-settings = Settings...
 menu-keyboard-shortcuts = Keyboard shortcuts...
 menu-about = About COSMIC Text Editor...
+
+## Tools
+tools = Tools
+no-external-tools = Add external tools in the settings file
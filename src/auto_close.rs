@@ -0,0 +1,85 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Cursor, Edit, Selection, ViEditor};
+
+const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+
+// Characters before and after the cursor
+fn neighbors(editor: &ViEditor<'static, 'static>, cursor: Cursor) -> (Option<char>, Option<char>) {
+    editor.with_buffer(|buffer| match buffer.lines.get(cursor.line) {
+        Some(line) => {
+            let text = line.text();
+            (
+                text[..cursor.index].chars().next_back(),
+                text[cursor.index..].chars().next(),
+            )
+        }
+        None => (None, None),
+    })
+}
+
+/// Insert a character with its matching closer, wrap the selection in a pair, or type over a
+/// closer that is already there. Returns false if the character should be inserted normally.
+pub fn insert(editor: &mut ViEditor<'static, 'static>, character: char) -> bool {
+    let opener = PAIRS.iter().find(|(open, _close)| *open == character);
+    if let Some((start, end)) = editor.selection_bounds() {
+        let Some((open, close)) = opener else {
+            return false;
+        };
+        let cursor_at_end = editor.cursor() == end;
+        editor.start_change();
+        // Insert the closer first so that the start stays valid
+        editor.insert_at(end, &close.to_string(), None);
+        editor.insert_at(start, &open.to_string(), None);
+        editor.finish_change();
+
+        // Keep the wrapped text selected, with the cursor on the same side
+        let shift = |mut position: Cursor| {
+            if position.line == start.line {
+                position.index += open.len_utf8();
+            }
+            position
+        };
+        let (start, end) = (shift(start), shift(end));
+        if cursor_at_end {
+            editor.set_cursor(end);
+            editor.set_selection(Selection::Normal(start));
+        } else {
+            editor.set_cursor(start);
+            editor.set_selection(Selection::Normal(end));
+        }
+        editor.set_redraw(true);
+        return true;
+    }
+
+    let cursor = editor.cursor();
+    let (before, after) = neighbors(editor, cursor);
+    let is_closer = |c: char| PAIRS.iter().any(|(_open, close)| *close == c);
+    if is_closer(character) && after == Some(character) {
+        editor.set_cursor(Cursor::new(
+            cursor.line,
+            cursor.index + character.len_utf8(),
+        ));
+        editor.set_redraw(true);
+        return true;
+    }
+
+    let Some((open, close)) = opener else {
+        return false;
+    };
+    // Only pair before whitespace or a closer, not in front of a word
+    if !after.is_none_or(|c| c.is_whitespace() || is_closer(c)) {
+        return false;
+    }
+    // Apostrophes in words and quotes that end a string are not pairs
+    if open == close && before.is_some_and(|c| c.is_alphanumeric() || c == *open) {
+        return false;
+    }
+    editor.start_change();
+    editor.insert_at(cursor, &format!("{open}{close}"), None);
+    editor.finish_change();
+    editor.set_cursor(Cursor::new(cursor.line, cursor.index + open.len_utf8()));
+    editor.set_selection(Selection::None);
+    editor.set_redraw(true);
+    true
+}
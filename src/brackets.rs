@@ -0,0 +1,189 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Buffer, Cursor};
+
+use crate::comment;
+
+const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+
+// Lines searched on each side of the cursor, so that large files stay fast
+const SCAN_LINES: usize = 500;
+
+/// How a language writes comments and strings, so that brackets in them can be skipped
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Syntax {
+    comments: Option<comment::Tokens>,
+    strings: bool,
+    // Single quotes only start character literals, as lifetimes also use them
+    char_literals: bool,
+}
+
+impl Syntax {
+    pub fn new(syntax_name: &str) -> Self {
+        let comments = comment::tokens(syntax_name);
+        Self {
+            comments,
+            // Quotes in prose are not strings
+            strings: comments.is_some()
+                && !matches!(syntax_name, "LaTeX" | "Markdown" | "MultiMarkdown" | "TeX"),
+            char_literals: matches!(syntax_name, "Rust" | "OCaml"),
+        }
+    }
+}
+
+enum State {
+    Code,
+    String(char),
+    Comment(&'static str),
+}
+
+// Length of a character literal at the start of `text`, which begins after its opening quote
+fn char_literal_len(text: &str) -> Option<usize> {
+    let mut chars = text.char_indices();
+    let (_, c) = chars.next()?;
+    if c == '\\' {
+        // Escapes like '\n', '\'' and '\u{1F600}'
+        let end = text.get(2..)?.find('\'').filter(|end| *end < 10)?;
+        return Some(end + 3);
+    }
+    match chars.next()? {
+        (i, '\'') => Some(i + 1),
+        _ => None,
+    }
+}
+
+// Brackets of lines `first` to `last` that are not in strings or comments
+fn brackets(buffer: &Buffer, first: usize, last: usize, syntax: Syntax) -> Vec<(Cursor, char)> {
+    let mut brackets = Vec::new();
+    let mut state = State::Code;
+    for (line_i, line) in buffer.lines[first..=last].iter().enumerate() {
+        let line_i = first + line_i;
+        let text = line.text();
+        let mut i = 0;
+        while let Some(c) = text[i..].chars().next() {
+            let rest = &text[i..];
+            let mut len = c.len_utf8();
+            match state {
+                State::Comment(close) => {
+                    if rest.starts_with(close) {
+                        len = close.len();
+                        state = State::Code;
+                    }
+                }
+                State::String(quote) => {
+                    if c == '\\' {
+                        len += rest[len..].chars().next().map_or(0, char::len_utf8);
+                    } else if c == quote {
+                        state = State::Code;
+                    }
+                }
+                State::Code => {
+                    let comments = syntax.comments;
+                    if comments
+                        .and_then(|tokens| tokens.line)
+                        .is_some_and(|token| rest.starts_with(token))
+                    {
+                        break;
+                    }
+                    if let Some((open, close)) = comments
+                        .and_then(|tokens| tokens.block)
+                        .filter(|(open, _close)| rest.starts_with(*open))
+                    {
+                        len = open.len();
+                        state = State::Comment(close);
+                    } else if syntax.strings && c == '\'' && syntax.char_literals {
+                        len += char_literal_len(&rest[len..]).unwrap_or(0);
+                    } else if syntax.strings && (c == '"' || c == '\'') {
+                        state = State::String(c);
+                    } else if PAIRS.iter().any(|(open, close)| *open == c || *close == c) {
+                        brackets.push((Cursor::new(line_i, i), c));
+                    }
+                }
+            }
+            i += len.min(text.len() - i);
+        }
+        // Strings end with their line, block comments do not
+        if let State::String(_) = state {
+            state = State::Code;
+        }
+    }
+    brackets
+}
+
+fn is_closer(c: char) -> bool {
+    PAIRS.iter().any(|(_open, close)| *close == c)
+}
+
+// Position of the bracket that matches the one at `bracket_i`, as (opener, closer)
+fn matching(brackets: &[(Cursor, char)], bracket_i: usize) -> Option<(Cursor, Cursor)> {
+    let (position, c) = brackets[bracket_i];
+    let mut depth = 0;
+    if let Some((open, close)) = PAIRS.iter().find(|(open, _close)| *open == c) {
+        for (other, other_c) in brackets[bracket_i + 1..].iter() {
+            if other_c == open {
+                depth += 1;
+            } else if other_c == close {
+                if depth == 0 {
+                    return Some((position, *other));
+                }
+                depth -= 1;
+            }
+        }
+    } else if let Some((open, close)) = PAIRS.iter().find(|(_open, close)| *close == c) {
+        for (other, other_c) in brackets[..bracket_i].iter().rev() {
+            if other_c == close {
+                depth += 1;
+            } else if other_c == open {
+                if depth == 0 {
+                    return Some((*other, position));
+                }
+                depth -= 1;
+            }
+        }
+    }
+    None
+}
+
+/// Positions of the bracket pair next to the cursor, or of the innermost pair around it
+pub fn pair(buffer: &Buffer, cursor: Cursor, syntax: Syntax) -> Option<(Cursor, Cursor)> {
+    if buffer.lines.is_empty() {
+        return None;
+    }
+    let first = cursor.line.saturating_sub(SCAN_LINES);
+    let last = (cursor.line + SCAN_LINES).min(buffer.lines.len() - 1);
+    let brackets = brackets(buffer, first, last, syntax);
+    let after = brackets.partition_point(|(position, _c)| {
+        (position.line, position.index) < (cursor.line, cursor.index)
+    });
+
+    // The bracket after the cursor, then the one before it
+    if let Some((position, _c)) = brackets.get(after)
+        && position.line == cursor.line
+        && position.index == cursor.index
+        && let Some(pair) = matching(&brackets, after)
+    {
+        return Some(pair);
+    }
+    if let Some(before) = after.checked_sub(1) {
+        let (position, c) = brackets[before];
+        if position.line == cursor.line
+            && position.index + c.len_utf8() == cursor.index
+            && let Some(pair) = matching(&brackets, before)
+        {
+            return Some(pair);
+        }
+    }
+
+    // The nearest opener before the cursor that is not closed before it
+    let mut depth = 0;
+    for bracket_i in (0..after).rev() {
+        if is_closer(brackets[bracket_i].1) {
+            depth += 1;
+        } else if depth == 0 {
+            return matching(&brackets, bracket_i);
+        } else {
+            depth -= 1;
+        }
+    }
+    None
+}
@@ -0,0 +1,396 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::{Action, emacs::Movement, fl, fuzzy};
+
+// Actions that are listed, which are all the ones that do not need an argument
+const ACTIONS: &[Action] = &[
+    Action::About,
+    Action::AcceptBoth,
+    Action::AcceptOurs,
+    Action::AcceptTheirs,
+    Action::AddCaretAbove,
+    Action::AddCaretBelow,
+    Action::AddNextOccurrence,
+    Action::ClearMark,
+    Action::ClearRecentFiles,
+    Action::CloseFile,
+    Action::CloseOtherTabs,
+    Action::CloseSavedTabs,
+    Action::CloseTabsToRight,
+    Action::CommandPalette,
+    Action::CompareFilesDialog,
+    Action::ConvertLineEndingsCrLf,
+    Action::ConvertLineEndingsLf,
+    Action::Copy,
+    Action::CopyPath,
+    Action::Cut,
+    Action::DeleteCharacter,
+    Action::DeleteLine,
+    Action::DuplicateLine,
+    Action::ExportHtml,
+    Action::ExportPdf,
+    Action::FilterThroughCommand,
+    Action::Find,
+    Action::FindAndReplace,
+    Action::FindReferences,
+    Action::FormatDocument,
+    Action::GoToDefinition,
+    Action::GoToMatchingBracket,
+    Action::GoToSymbol,
+    Action::JoinLines,
+    Action::KillLine,
+    Action::KillWord,
+    Action::MoveLineDown,
+    Action::MoveLineUp,
+    Action::MoveTabToNewWindow,
+    Action::NavigateBack,
+    Action::NavigateForward,
+    Action::NewFile,
+    Action::NewWindow,
+    Action::NextConflict,
+    Action::NextDifference,
+    Action::OpenFileDialog,
+    Action::OpenLocationDialog,
+    Action::OpenProjectDialog,
+    Action::Paste,
+    Action::PasteFromHistory,
+    Action::PlayMacro,
+    Action::PlayMacroDialog,
+    Action::PreviousConflict,
+    Action::PreviousDifference,
+    Action::Print,
+    Action::QuickOpen,
+    Action::Quit,
+    Action::Redo,
+    Action::ReloadSyntaxes,
+    Action::RemoveDuplicateLines,
+    Action::RenameSymbol,
+    Action::ReopenClosedTab,
+    Action::RerunTask,
+    Action::RevealInFiles,
+    Action::ReverseLines,
+    Action::RevertAllChanges,
+    Action::RunTaskDialog,
+    Action::Save,
+    Action::SaveAsDialog,
+    Action::SelectAll,
+    Action::SetMark,
+    Action::ShowCompletions,
+    Action::SortLinesAscending,
+    Action::SortLinesDescending,
+    Action::SplitClose,
+    Action::SplitFocusNext,
+    Action::SplitHorizontal,
+    Action::SplitVertical,
+    Action::StopTask,
+    Action::TabNext,
+    Action::TabPrev,
+    Action::ToggleAutoClose,
+    Action::ToggleAutoIndent,
+    Action::ToggleComment,
+    Action::ToggleDocumentStatistics,
+    Action::ToggleDocumentType,
+    Action::ToggleEncoding,
+    Action::ToggleFormatOnSave,
+    Action::ToggleFullscreen,
+    Action::ToggleGitBlame,
+    Action::ToggleGitGutter,
+    Action::ToggleGitManagement,
+    Action::ToggleHighlightCurrentLine,
+    Action::ToggleIndentGuides,
+    Action::ToggleKeyboardShortcuts,
+    Action::ToggleLineNumbers,
+    Action::ToggleMacroRecording,
+    Action::ToggleMarkdownPreview,
+    Action::ToggleMinimap,
+    Action::ToggleOutline,
+    Action::TogglePinTab,
+    Action::ToggleProblems,
+    Action::ToggleProjectSearch,
+    Action::ToggleSettingsPage,
+    Action::ToggleSpellCheck,
+    Action::ToggleTaskOutput,
+    Action::ToggleUndoHistory,
+    Action::ToggleWhitespace,
+    Action::ToggleWordWrap,
+    Action::ToggleZenMode,
+    Action::Undo,
+    Action::ZoomIn,
+    Action::ZoomOut,
+    Action::ZoomReset,
+];
+
+/// Groups of commands in the keyboard shortcuts overlay, mostly the menus they are in
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Category {
+    File,
+    Edit,
+    View,
+    Tabs,
+    MoveCursor,
+}
+
+impl Category {
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::File,
+            Self::Edit,
+            Self::View,
+            Self::Tabs,
+            Self::MoveCursor,
+        ]
+    }
+
+    pub fn title(self) -> String {
+        match self {
+            Self::File => fl!("file"),
+            Self::Edit => fl!("edit"),
+            Self::View => fl!("view"),
+            Self::Tabs => fl!("tabs"),
+            Self::MoveCursor => fl!("move-cursor"),
+        }
+    }
+}
+
+/// Group of a command, which is the menu it is in or the group of similar commands
+pub fn category(action: Action) -> Category {
+    match action {
+        Action::ClearRecentFiles
+        | Action::CloseFile
+        | Action::CompareFilesDialog
+        | Action::ConvertLineEndingsCrLf
+        | Action::ConvertLineEndingsLf
+        | Action::ExportHtml
+        | Action::ExportPdf
+        | Action::NewFile
+        | Action::NewWindow
+        | Action::OpenFileDialog
+        | Action::OpenLocationDialog
+        | Action::OpenProjectDialog
+        | Action::Print
+        | Action::QuickOpen
+        | Action::Quit
+        | Action::ReopenClosedTab
+        | Action::RerunTask
+        | Action::RevertAllChanges
+        | Action::RunTaskDialog
+        | Action::Save
+        | Action::SaveAsDialog
+        | Action::StopTask
+        | Action::ToggleDocumentStatistics
+        | Action::ToggleDocumentType
+        | Action::ToggleEncoding
+        | Action::ToggleGitManagement => Category::File,
+        Action::About
+        | Action::CommandPalette
+        | Action::ReloadSyntaxes
+        | Action::SplitClose
+        | Action::SplitFocusNext
+        | Action::SplitHorizontal
+        | Action::SplitVertical
+        | Action::ToggleAutoClose
+        | Action::ToggleAutoIndent
+        | Action::ToggleFullscreen
+        | Action::ToggleGitBlame
+        | Action::ToggleGitGutter
+        | Action::ToggleHighlightCurrentLine
+        | Action::ToggleIndentGuides
+        | Action::ToggleKeyboardShortcuts
+        | Action::ToggleLineNumbers
+        | Action::ToggleMarkdownPreview
+        | Action::ToggleMinimap
+        | Action::ToggleOutline
+        | Action::ToggleProblems
+        | Action::ToggleSettingsPage
+        | Action::ToggleTaskOutput
+        | Action::ToggleUndoHistory
+        | Action::ToggleWhitespace
+        | Action::ToggleWordWrap
+        | Action::ToggleZenMode
+        | Action::ZoomIn
+        | Action::ZoomOut
+        | Action::ZoomReset => Category::View,
+        Action::CloseOtherTabs
+        | Action::CloseSavedTabs
+        | Action::CloseTabsToRight
+        | Action::CopyPath
+        | Action::MoveTabToNewWindow
+        | Action::RevealInFiles
+        | Action::TabNext
+        | Action::TabPrev
+        | Action::TogglePinTab => Category::Tabs,
+        Action::MoveCursor(_) => Category::MoveCursor,
+        _ => Category::Edit,
+    }
+}
+
+// Label of a command that is in a submenu, like "Line tools: Reverse"
+fn in_folder(folder: String, label: String) -> String {
+    format!("{folder}: {label}")
+}
+
+// Name of an action, which is the same as in the menus where it is in one
+fn label(action: Action) -> Option<String> {
+    Some(match action {
+        Action::About => fl!("menu-about"),
+        Action::AcceptBoth => in_folder(fl!("merge-conflicts"), fl!("accept-both")),
+        Action::AcceptOurs => in_folder(fl!("merge-conflicts"), fl!("accept-ours")),
+        Action::AcceptTheirs => in_folder(fl!("merge-conflicts"), fl!("accept-theirs")),
+        Action::AddCaretAbove => fl!("add-caret-above"),
+        Action::AddCaretBelow => fl!("add-caret-below"),
+        Action::AddNextOccurrence => fl!("add-next-occurrence"),
+        Action::ClearMark => fl!("clear-mark"),
+        Action::ClearRecentFiles => in_folder(fl!("open-recent-file"), fl!("clear-recent")),
+        Action::CloseFile => fl!("close-file"),
+        Action::CloseOtherTabs => fl!("close-other-tabs"),
+        Action::CloseSavedTabs => fl!("close-saved-tabs"),
+        Action::CloseTabsToRight => fl!("close-tabs-to-right"),
+        Action::CommandPalette => fl!("command-palette"),
+        Action::CompareFilesDialog => fl!("compare-files"),
+        Action::ConvertLineEndingsCrLf => in_folder(fl!("line-endings"), fl!("convert-to-crlf")),
+        Action::ConvertLineEndingsLf => in_folder(fl!("line-endings"), fl!("convert-to-lf")),
+        Action::Copy => fl!("copy"),
+        Action::CopyPath => fl!("copy-path"),
+        Action::Cut => fl!("cut"),
+        Action::DeleteCharacter => fl!("delete-character"),
+        Action::DeleteLine => fl!("delete-line"),
+        Action::DuplicateLine => fl!("duplicate-line"),
+        Action::ExportHtml => in_folder(fl!("export"), fl!("export-html")),
+        Action::ExportPdf => in_folder(fl!("export"), fl!("export-pdf")),
+        Action::FilterThroughCommand => fl!("filter-through-command"),
+        Action::Find => fl!("find"),
+        Action::FindAndReplace => fl!("replace"),
+        Action::FindReferences => fl!("find-references"),
+        Action::FormatDocument => fl!("format-document"),
+        Action::GoToDefinition => fl!("go-to-definition"),
+        Action::GoToMatchingBracket => fl!("go-to-matching-bracket"),
+        Action::GoToSymbol => fl!("go-to-symbol"),
+        Action::JoinLines => fl!("join-lines"),
+        Action::KillLine => fl!("kill-line"),
+        Action::KillWord => fl!("kill-word"),
+        Action::MoveCursor(movement) => in_folder(fl!("move-cursor"), movement.name()),
+        Action::MoveLineDown => fl!("move-line-down"),
+        Action::MoveLineUp => fl!("move-line-up"),
+        Action::MoveTabToNewWindow => fl!("move-tab-to-new-window"),
+        Action::NavigateBack => fl!("navigate-back"),
+        Action::NavigateForward => fl!("navigate-forward"),
+        Action::NewFile => fl!("new-file"),
+        Action::NewWindow => fl!("new-window"),
+        Action::NextConflict => in_folder(fl!("merge-conflicts"), fl!("next-conflict")),
+        Action::NextDifference => fl!("next-difference"),
+        Action::OpenFileDialog => fl!("open-file"),
+        Action::OpenLocationDialog => fl!("menu-open-location"),
+        Action::OpenProjectDialog => fl!("menu-open-project"),
+        Action::Paste => fl!("paste"),
+        Action::PasteFromHistory => fl!("paste-from-history"),
+        Action::PlayMacro => in_folder(fl!("macros"), fl!("play-macro")),
+        Action::PlayMacroDialog => in_folder(fl!("macros"), fl!("menu-play-macro-repeatedly")),
+        Action::PreviousConflict => in_folder(fl!("merge-conflicts"), fl!("previous-conflict")),
+        Action::PreviousDifference => fl!("previous-difference"),
+        Action::Print => fl!("print"),
+        Action::QuickOpen => fl!("quick-open"),
+        Action::Quit => fl!("quit"),
+        Action::Redo => fl!("redo"),
+        Action::ReloadSyntaxes => fl!("reload-syntaxes"),
+        Action::RemoveDuplicateLines => in_folder(fl!("line-tools"), fl!("remove-duplicate-lines")),
+        Action::RenameSymbol => fl!("rename-symbol"),
+        Action::ReopenClosedTab => fl!("reopen-closed-tab"),
+        Action::RerunTask => in_folder(fl!("tasks"), fl!("rerun-task")),
+        Action::RevealInFiles => fl!("reveal-in-files"),
+        Action::ReverseLines => in_folder(fl!("line-tools"), fl!("reverse-lines")),
+        Action::RevertAllChanges => fl!("revert-all-changes"),
+        Action::RunTaskDialog => in_folder(fl!("tasks"), fl!("run-task")),
+        Action::Save => fl!("save"),
+        Action::SaveAsDialog => fl!("save-as"),
+        Action::SelectAll => fl!("select-all"),
+        Action::SetMark => fl!("set-mark"),
+        Action::ShowCompletions => fl!("show-completions"),
+        Action::SortLinesAscending => in_folder(fl!("line-tools"), fl!("sort-lines-ascending")),
+        Action::SortLinesDescending => in_folder(fl!("line-tools"), fl!("sort-lines-descending")),
+        Action::SplitClose => fl!("close-split"),
+        Action::SplitFocusNext => fl!("focus-next-pane"),
+        Action::SplitHorizontal => fl!("split-horizontal"),
+        Action::SplitVertical => fl!("split-vertical"),
+        Action::StopTask => in_folder(fl!("tasks"), fl!("stop-task")),
+        Action::TabNext => fl!("next-tab"),
+        Action::TabPrev => fl!("previous-tab"),
+        Action::ToggleAutoClose => fl!("auto-close-brackets"),
+        Action::ToggleAutoIndent => in_folder(fl!("indentation"), fl!("automatic-indentation")),
+        Action::ToggleComment => fl!("toggle-comment"),
+        Action::ToggleDocumentStatistics => fl!("menu-document-statistics"),
+        Action::ToggleDocumentType => fl!("document-type"),
+        Action::ToggleEncoding => fl!("encoding"),
+        Action::ToggleFormatOnSave => fl!("format-on-save"),
+        Action::ToggleFullscreen => fl!("fullscreen"),
+        Action::ToggleGitBlame => fl!("show-git-blame"),
+        Action::ToggleGitGutter => fl!("show-git-changes"),
+        Action::ToggleGitManagement => fl!("menu-git-management"),
+        Action::ToggleHighlightCurrentLine => fl!("highlight-current-line"),
+        Action::ToggleIndentGuides => fl!("show-indent-guides"),
+        Action::ToggleKeyboardShortcuts => fl!("menu-keyboard-shortcuts"),
+        Action::ToggleLineNumbers => fl!("show-line-numbers"),
+        Action::ToggleMacroRecording => in_folder(fl!("macros"), fl!("record-macro")),
+        Action::ToggleMarkdownPreview => fl!("markdown-preview"),
+        Action::ToggleMinimap => fl!("show-minimap"),
+        Action::ToggleOutline => fl!("menu-outline"),
+        Action::TogglePinTab => fl!("pin-tab"),
+        Action::ToggleProblems => fl!("menu-problems"),
+        Action::ToggleProjectSearch => fl!("find-in-project"),
+        Action::ToggleSettingsPage => fl!("menu-settings"),
+        Action::ToggleSpellCheck => fl!("spell-check"),
+        Action::ToggleTaskOutput => fl!("task-output"),
+        Action::ToggleUndoHistory => fl!("menu-undo-history"),
+        Action::ToggleWhitespace => fl!("show-whitespace"),
+        Action::ToggleWordWrap => fl!("word-wrap"),
+        Action::ToggleZenMode => fl!("zen-mode"),
+        Action::Undo => fl!("undo"),
+        Action::ZoomIn => fl!("zoom-in"),
+        Action::ZoomOut => fl!("zoom-out"),
+        Action::ZoomReset => fl!("default-size"),
+        // Actions that only make sense from their menus, or that are repeated for each item
+        Action::Todo
+        | Action::CloseProject(_)
+        | Action::ExternalTool(_)
+        | Action::OpenClosedTab(_)
+        | Action::OpenRecentFile(_)
+        | Action::OpenRecentProject(_)
+        | Action::SpellLanguage(_)
+        | Action::TabActivate0
+        | Action::TabActivate1
+        | Action::TabActivate2
+        | Action::TabActivate3
+        | Action::TabActivate4
+        | Action::TabActivate5
+        | Action::TabActivate6
+        | Action::TabActivate7
+        | Action::TabActivate8
+        | Action::TabWidth(_) => return None,
+    })
+}
+
+/// Commands listed in the command palette, with their names
+pub fn commands() -> Vec<(Action, String)> {
+    let mut commands: Vec<(Action, String)> = ACTIONS
+        .iter()
+        .copied()
+        .chain(
+            Movement::all()
+                .iter()
+                .map(|movement| Action::MoveCursor(*movement)),
+        )
+        .filter_map(|action| Some((action, label(action)?)))
+        .collect();
+    commands.sort_by(|a, b| crate::localize::LANGUAGE_SORTER.compare(&a.1, &b.1));
+    commands
+}
+
+/// Indexes of the commands whose names match the query, best first
+pub fn search(commands: &[(Action, String)], query: &str) -> Vec<usize> {
+    let mut results: Vec<(i64, usize)> = commands
+        .iter()
+        .enumerate()
+        .filter_map(|(i, (_action, label))| Some((fuzzy::score(query, label)?, i)))
+        .collect();
+    results.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    results.into_iter().map(|(_score, i)| i).collect()
+}
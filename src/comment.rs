@@ -0,0 +1,226 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Cursor, Edit, Selection, ViEditor};
+
+/// Comment tokens of a language
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Tokens {
+    pub line: Option<&'static str>,
+    pub block: Option<(&'static str, &'static str)>,
+}
+
+const C_LIKE: Tokens = Tokens {
+    line: Some("//"),
+    block: Some(("/*", "*/")),
+};
+const HASH: Tokens = Tokens {
+    line: Some("#"),
+    block: None,
+};
+const MARKUP: Tokens = Tokens {
+    line: None,
+    block: Some(("<!--", "-->")),
+};
+
+/// Comment tokens of a syntax, by the name used by syntect
+pub fn tokens(syntax_name: &str) -> Option<Tokens> {
+    Some(match syntax_name {
+        "C" | "C++" | "C#" | "D" | "Dart" | "Go" | "Groovy" | "Java" | "JavaScript"
+        | "JavaScript (Babel)" | "JSON" | "Kotlin" | "Objective-C" | "Objective-C++" | "PHP"
+        | "Rust" | "Scala" | "SCSS" | "Swift" | "TypeScript" | "TypeScriptReact" | "Zig" => C_LIKE,
+        "Bourne Again Shell (bash)"
+        | "CMake"
+        | "Dockerfile"
+        | "Elixir"
+        | "fish"
+        | "Git Attributes"
+        | "Git Config"
+        | "Git Ignore"
+        | "Julia"
+        | "Makefile"
+        | "Nim"
+        | "Nix"
+        | "Perl"
+        | "PowerShell"
+        | "Python"
+        | "R"
+        | "Ruby"
+        | "Shell-Unix-Generic"
+        | "TOML"
+        | "YAML" => HASH,
+        "HTML" | "Markdown" | "MultiMarkdown" | "SVG" | "XML" => MARKUP,
+        "CSS" => Tokens {
+            line: None,
+            block: Some(("/*", "*/")),
+        },
+        "Ada" | "Elm" | "SQL" => Tokens {
+            line: Some("--"),
+            block: None,
+        },
+        "Haskell" => Tokens {
+            line: Some("--"),
+            block: Some(("{-", "-}")),
+        },
+        "Lua" => Tokens {
+            line: Some("--"),
+            block: Some(("--[[", "]]")),
+        },
+        "Erlang" | "LaTeX" | "MATLAB" | "TeX" => Tokens {
+            line: Some("%"),
+            block: None,
+        },
+        "Clojure" | "INI" | "Lisp" | "Scheme" => Tokens {
+            line: Some(";"),
+            block: None,
+        },
+        "OCaml" => Tokens {
+            line: None,
+            block: Some(("(*", "*)")),
+        },
+        "Batch File" => Tokens {
+            line: Some("REM"),
+            block: None,
+        },
+        "VimL" => Tokens {
+            line: Some("\""),
+            block: None,
+        },
+        _ => return None,
+    })
+}
+
+enum Change {
+    Insert(String),
+    Delete(usize),
+}
+
+// Byte length of the indentation of a line
+fn indent_len(text: &str) -> usize {
+    text.len() - text.trim_start().len()
+}
+
+// Length of a token to remove, including one space next to it
+fn token_len(rest: &str, token: &str) -> usize {
+    if rest[token.len()..].starts_with(' ') {
+        token.len() + 1
+    } else {
+        token.len()
+    }
+}
+
+/// Comment the selected lines, or uncomment them if they are all commented already. Line
+/// comments are placed at the smallest indentation of the lines so that they stay aligned.
+pub fn toggle(editor: &mut ViEditor<'static, 'static>, tokens: Tokens) {
+    let cursor = editor.cursor();
+    let (first, last) = match editor.selection_bounds() {
+        // A selection that ends at the start of a line does not include that line
+        Some((start, end)) if end.index == 0 && end.line > start.line => (start.line, end.line - 1),
+        Some((start, end)) => (start.line, end.line),
+        None => (cursor.line, cursor.line),
+    };
+    let lines: Vec<(usize, String)> = editor.with_buffer(|buffer| {
+        (first..=last)
+            .filter_map(|line_i| Some((line_i, buffer.lines.get(line_i)?.text().to_string())))
+            .filter(|(_line_i, text)| !text.trim().is_empty())
+            .collect()
+    });
+    if lines.is_empty() {
+        return;
+    }
+
+    // Changes at (line, index) of the original text
+    let mut changes = Vec::new();
+    if let Some(token) = tokens.line {
+        let commented = lines
+            .iter()
+            .all(|(_line_i, text)| text.trim_start().starts_with(token));
+        if commented {
+            for (line_i, text) in lines.iter() {
+                let indent = indent_len(text);
+                let len = token_len(&text[indent..], token);
+                changes.push((*line_i, indent, Change::Delete(len)));
+            }
+        } else {
+            let indent = lines
+                .iter()
+                .map(|(_line_i, text)| indent_len(text))
+                .min()
+                .unwrap_or(0);
+            for (line_i, _text) in lines.iter() {
+                changes.push((*line_i, indent, Change::Insert(format!("{token} "))));
+            }
+        }
+    } else if let Some((open, close)) = tokens.block {
+        let (first_i, first_text) = &lines[0];
+        let (last_i, last_text) = &lines[lines.len() - 1];
+        let first_indent = indent_len(first_text);
+        let last_end = last_text.trim_end().len();
+        let commented = first_text[first_indent..].starts_with(open)
+            && last_text[..last_end].ends_with(close)
+            && (first_i != last_i || last_end - first_indent >= open.len() + close.len());
+        if commented {
+            changes.push((
+                *first_i,
+                first_indent,
+                Change::Delete(token_len(&first_text[first_indent..], open)),
+            ));
+            let close_start = last_end - close.len();
+            if last_text[..close_start].ends_with(' ')
+                && (first_i != last_i || close_start > first_indent + open.len())
+            {
+                changes.push((*last_i, close_start - 1, Change::Delete(close.len() + 1)));
+            } else {
+                changes.push((*last_i, close_start, Change::Delete(close.len())));
+            }
+        } else {
+            changes.push((*first_i, first_indent, Change::Insert(format!("{open} "))));
+            changes.push((*last_i, last_end, Change::Insert(format!(" {close}"))));
+        }
+    } else {
+        return;
+    }
+
+    // Move a position by the changes made before it on its line
+    let adjust = |mut position: Cursor| {
+        let mut index = position.index as isize;
+        for (line_i, change_index, change) in changes.iter() {
+            if *line_i != position.line || *change_index > position.index {
+                continue;
+            }
+            match change {
+                Change::Insert(text) => index += text.len() as isize,
+                Change::Delete(len) => {
+                    index -= (*len).min(position.index - change_index) as isize;
+                }
+            }
+        }
+        position.index = index.max(0) as usize;
+        position
+    };
+    let cursor = adjust(cursor);
+    let selection = match editor.selection() {
+        Selection::None => Selection::None,
+        Selection::Normal(anchor) => Selection::Normal(adjust(anchor)),
+        Selection::Line(anchor) => Selection::Line(adjust(anchor)),
+        Selection::Word(anchor) => Selection::Word(adjust(anchor)),
+    };
+
+    // Apply from the end so that the positions of earlier changes stay valid
+    changes.sort_by(|a, b| (b.0, b.1).cmp(&(a.0, a.1)));
+    editor.start_change();
+    for (line_i, index, change) in changes {
+        let position = Cursor::new(line_i, index);
+        match change {
+            Change::Insert(text) => {
+                editor.insert_at(position, &text, None);
+            }
+            Change::Delete(len) => {
+                editor.delete_range(position, Cursor::new(line_i, index + len));
+            }
+        }
+    }
+    editor.finish_change();
+    editor.set_cursor(cursor);
+    editor.set_selection(selection);
+    editor.set_redraw(true);
+}
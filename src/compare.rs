@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use crate::git::{GitDiff, GitDiffLine};
+
+/// A line of each file shown side by side, with its line number counting from zero
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct CompareRow {
+    pub old: Option<(usize, String)>,
+    pub new: Option<(usize, String)>,
+    pub changed: bool,
+}
+
+/// Align the lines of two files, using a diff of them without context lines
+pub fn rows(old_text: &str, new_text: &str, diff: &GitDiff) -> Vec<CompareRow> {
+    let old_lines: Vec<&str> = old_text.lines().collect();
+    let new_lines: Vec<&str> = new_text.lines().collect();
+    let mut rows = Vec::with_capacity(old_lines.len().max(new_lines.len()));
+    let (mut old_i, mut new_i) = (0, 0);
+    let push_unchanged = |rows: &mut Vec<CompareRow>, old_i: usize, new_i: usize| {
+        rows.push(CompareRow {
+            old: Some((old_i, old_lines[old_i].to_string())),
+            new: Some((new_i, new_lines[new_i].to_string())),
+            changed: false,
+        });
+    };
+
+    for hunk in diff.hunks.iter() {
+        let (old_first, _) = hunk.old_lines();
+        let (new_first, _) = hunk.new_lines();
+        while old_i < old_first.min(old_lines.len()) && new_i < new_first.min(new_lines.len()) {
+            push_unchanged(&mut rows, old_i, new_i);
+            old_i += 1;
+            new_i += 1;
+        }
+
+        // Deleted lines are shown next to the lines added in their place
+        let mut deleted = Vec::new();
+        let mut added = Vec::new();
+        for line in hunk.lines.iter() {
+            match line {
+                GitDiffLine::Deleted { text, .. } => deleted.push(text.as_str()),
+                GitDiffLine::Added { text, .. } => added.push(text.as_str()),
+                GitDiffLine::Context { .. } => {}
+            }
+        }
+        for i in 0..deleted.len().max(added.len()) {
+            rows.push(CompareRow {
+                old: deleted.get(i).map(|text| (old_i + i, text.to_string())),
+                new: added.get(i).map(|text| (new_i + i, text.to_string())),
+                changed: true,
+            });
+        }
+        old_i += deleted.len();
+        new_i += added.len();
+    }
+
+    while old_i < old_lines.len() && new_i < new_lines.len() {
+        push_unchanged(&mut rows, old_i, new_i);
+        old_i += 1;
+        new_i += 1;
+    }
+    rows
+}
+
+/// Rows where each block of changed rows starts
+pub fn differences(rows: &[CompareRow]) -> Vec<usize> {
+    (0..rows.len())
+        .filter(|row_i| rows[*row_i].changed && (*row_i == 0 || !rows[row_i - 1].changed))
+        .collect()
+}
@@ -0,0 +1,235 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::iced::Point;
+use cosmic_text::{Buffer, Cursor, Edit, Selection, ViEditor};
+use std::{collections::HashMap, sync::Mutex};
+
+use crate::{
+    fuzzy,
+    lsp::CompletionItem,
+    snippet::{self, TabStops},
+};
+
+// Most completions shown at once, the rest are found by typing more
+const MAX_MATCHES: usize = 100;
+
+// Lines of each document that words are completed from
+const WORD_LINES: usize = 10_000;
+
+/// Completions shown at the cursor while typing a word
+pub struct Completion {
+    /// Start of the word that is completed
+    pub start: Cursor,
+    pub items: Vec<CompletionItem>,
+    /// The server gives other items when the word changes
+    pub incomplete: bool,
+    /// Indexes of the items that match the typed word, best first
+    pub matches: Vec<usize>,
+    /// Index in the matches
+    pub selected: usize,
+    /// Where the popup is shown, which stays in place while typing
+    pub point_opt: Option<Point>,
+}
+
+impl Completion {
+    pub fn new(start: Cursor) -> Self {
+        Self {
+            start,
+            items: Vec::new(),
+            incomplete: false,
+            matches: Vec::new(),
+            selected: 0,
+            point_opt: None,
+        }
+    }
+
+    /// Find the items that match the word typed since the start
+    pub fn filter(&mut self, word: &str) {
+        let mut scores: Vec<(i64, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(item_i, item)| Some((fuzzy::score(word, &item.filter_text)?, item_i)))
+            .collect();
+        // Servers order items that score the same
+        scores.sort_by(|(a_score, a_i), (b_score, b_i)| {
+            b_score
+                .cmp(a_score)
+                .then_with(|| self.items[*a_i].sort_text.cmp(&self.items[*b_i].sort_text))
+        });
+        self.matches = scores
+            .into_iter()
+            .take(MAX_MATCHES)
+            .map(|(_score, item_i)| item_i)
+            .collect();
+        self.selected = 0;
+    }
+
+    pub fn selected_item(&self) -> Option<&CompletionItem> {
+        self.matches
+            .get(self.selected)
+            .map(|item_i| &self.items[*item_i])
+    }
+
+    /// Select the next or previous match, wrapping around at the ends
+    pub fn select(&mut self, next: bool) {
+        let len = self.matches.len();
+        if len == 0 {
+            return;
+        }
+        self.selected = if next {
+            (self.selected + 1) % len
+        } else {
+            (self.selected + len - 1) % len
+        };
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Start of the word before the cursor, which is the cursor if there is none
+pub fn word_start(editor: &ViEditor<'static, 'static>, cursor: Cursor) -> Cursor {
+    editor.with_buffer(|buffer| {
+        let text = &buffer.lines[cursor.line].text()[..cursor.index];
+        let index = text
+            .char_indices()
+            .rev()
+            .take_while(|(_i, c)| is_word(*c))
+            .last()
+            .map_or(cursor.index, |(i, _c)| i);
+        Cursor::new(cursor.line, index)
+    })
+}
+
+/// Text from the start of the word to the cursor
+pub fn word(editor: &ViEditor<'static, 'static>, start: Cursor, cursor: Cursor) -> String {
+    editor.with_buffer(|buffer| {
+        let text = buffer.lines[cursor.line].text();
+        text.get(start.index..cursor.index)
+            .unwrap_or_default()
+            .to_string()
+    })
+}
+
+/// The word around a position, which is empty if there is none
+pub fn word_at(editor: &ViEditor<'static, 'static>, cursor: Cursor) -> String {
+    editor.with_buffer(|buffer| {
+        let text = buffer.lines[cursor.line].text();
+        let index = cursor.index.min(text.len());
+        let start = text[..index]
+            .char_indices()
+            .rev()
+            .take_while(|(_i, c)| is_word(*c))
+            .last()
+            .map_or(index, |(i, _c)| i);
+        let end = text[index..]
+            .char_indices()
+            .find(|(_i, c)| !is_word(*c))
+            .map_or(text.len(), |(i, _c)| index + i);
+        text[start..end].to_string()
+    })
+}
+
+// Count the words of a buffer, other than the one that starts at `skip_opt`
+fn count_words(buffer: &Buffer, skip_opt: Option<Cursor>, counts: &mut HashMap<String, usize>) {
+    for (line_i, line) in buffer.lines.iter().enumerate().take(WORD_LINES) {
+        let text = line.text();
+        let mut start_opt = None;
+        for (i, c) in text.char_indices().chain([(text.len(), ' ')]) {
+            match (start_opt, is_word(c)) {
+                (None, true) => start_opt = Some(i),
+                (Some(start), false) => {
+                    start_opt = None;
+                    let word = &text[start..i];
+                    if skip_opt == Some(Cursor::new(line_i, start))
+                        || word.len() < 2
+                        || word.starts_with(|c: char| c.is_ascii_digit())
+                    {
+                        continue;
+                    }
+                    *counts.entry(word.to_string()).or_default() += 1;
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Words of the documents, other than the one being typed at `start`, with the words of the
+/// edited document first and the most common words before others
+pub fn words<'a>(
+    editor: &ViEditor<'static, 'static>,
+    start: Cursor,
+    others: impl Iterator<Item = &'a Mutex<ViEditor<'static, 'static>>>,
+) -> Vec<CompletionItem> {
+    let mut counts = HashMap::new();
+    editor.with_buffer(|buffer| count_words(buffer, Some(start), &mut counts));
+    let mut other_counts = HashMap::new();
+    for other in others {
+        other
+            .lock()
+            .unwrap()
+            .with_buffer(|buffer| count_words(buffer, None, &mut other_counts));
+    }
+
+    let mut words: Vec<(bool, usize, String)> = other_counts
+        .into_iter()
+        .filter(|(word, _count)| !counts.contains_key(word))
+        .map(|(word, count)| (true, count, word))
+        .collect();
+    words.extend(counts.into_iter().map(|(word, count)| (false, count, word)));
+    words.sort_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+    words
+        .into_iter()
+        .enumerate()
+        .map(|(rank, (_other, _count, word))| CompletionItem {
+            label: word.clone(),
+            detail_opt: None,
+            documentation_opt: None,
+            insert_text: word.clone(),
+            snippet: false,
+            filter_text: word,
+            // Items that score the same keep this order
+            sort_text: format!("{rank:08}"),
+            start_opt: None,
+        })
+        .collect()
+}
+
+/// Whether a member is written at this position, after "." or "::" or "->"
+pub fn after_accessor(editor: &ViEditor<'static, 'static>, start: Cursor) -> bool {
+    editor.with_buffer(|buffer| {
+        let text = &buffer.lines[start.line].text()[..start.index];
+        text.ends_with('.') || text.ends_with("::") || text.ends_with("->")
+    })
+}
+
+/// Replace the word before the cursor with a completion, selecting the first tab stop of
+/// snippets and returning the other ones
+pub fn accept(
+    editor: &mut ViEditor<'static, 'static>,
+    start: Cursor,
+    item: &CompletionItem,
+) -> Option<TabStops> {
+    let cursor = editor.cursor();
+    // The server may replace more than the word, but never text after the cursor
+    let start = item
+        .start_opt
+        .filter(|item_start| item_start.line == cursor.line && item_start.index <= cursor.index)
+        .map_or(start, |item_start| {
+            Cursor::new(cursor.line, item_start.index)
+        });
+    if item.snippet {
+        return snippet::insert(editor, start, &item.insert_text);
+    }
+
+    editor.start_change();
+    editor.delete_range(start, cursor);
+    let end = editor.insert_at(start, &item.insert_text, None);
+    editor.finish_change();
+    editor.set_cursor(end);
+    editor.set_selection(Selection::None);
+    None
+}
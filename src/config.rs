@@ -4,12 +4,21 @@ use cosmic::{
     cosmic_config::{self, CosmicConfigEntry, cosmic_config_derive::CosmicConfigEntry},
     theme,
 };
-use cosmic_text::Metrics;
+use cosmic_text::{Attrs, FeatureTag, FontFeatures, Metrics};
 use serde::{Deserialize, Serialize};
-use std::{collections::VecDeque, path::PathBuf};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    path::PathBuf,
+};
+
+use crate::{Action, key_bind::Keymap};
 
 pub const CONFIG_VERSION: u64 = 1;
 
+/// Bounds of the line height, in hundredths of the font size
+pub const LINE_HEIGHT_MUL_100_MIN: u16 = 100;
+pub const LINE_HEIGHT_MUL_100_MAX: u16 = 250;
+
 #[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub enum AppTheme {
     Dark,
@@ -35,43 +44,218 @@ impl AppTheme {
     }
 }
 
+/// How the cursor is drawn
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum CursorShape {
+    Bar,
+    Block,
+    Underline,
+}
+
+/// A program that is run from the Tools menu, see [`crate::tools::ToolContext::expand`] for the
+/// placeholders in its command, arguments, and folder
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ExternalTool {
+    pub name: String,
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// Folder to run in instead of the project of the active document
+    #[serde(default)]
+    pub dir: Option<String>,
+    /// Show the output in the task output panel
+    #[serde(default)]
+    pub capture_output: bool,
+}
+
 #[derive(Clone, CosmicConfigEntry, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct Config {
     pub app_theme: AppTheme,
+    pub atomic_save: bool,
+    pub auto_close_brackets: bool,
+    /// Syntaxes in which brackets and quotes are not closed automatically
+    pub auto_close_exclude: Vec<String>,
     pub auto_indent: bool,
+    pub auto_save_secs: Option<u64>,
+    /// Copied and cut texts that are kept for pasting from the history
+    pub clipboard_history_size: usize,
+    pub cursor_blink: bool,
+    /// Milliseconds that a blinking cursor is shown, and then hidden
+    pub cursor_blink_ms: u64,
+    pub cursor_shape: CursorShape,
+    /// Logical pixels that a bar or underline cursor is thick
+    pub cursor_width: u16,
+    /// Programs in the Tools menu, which are bound to keys with their index
+    pub external_tools: Vec<ExternalTool>,
     pub find_case_sensitive: bool,
     pub find_use_regex: bool,
+    pub find_whole_words: bool,
     pub find_wrap_around: bool,
+    /// OpenType feature tags of the font, like `ss01` to turn one on, `zero=2` to choose an
+    /// alternate, or `-calt` to turn one off
+    pub font_features: Vec<String>,
+    pub font_ligatures: bool,
     pub font_name: String,
     pub font_size: u16,
     pub font_size_zoom_step_mul_100: u16,
+    /// Syntaxes whose files are formatted before they are saved
+    pub format_on_save: Vec<String>,
+    /// Commands that format each syntax, reading the text on stdin and writing the result to
+    /// stdout, where `{path}` is the path of the file. The language server formats the others.
+    pub formatters: HashMap<String, Vec<String>>,
+    /// Show the commit that last changed each line in the gutter
+    pub git_blame: bool,
+    /// Mark lines that differ from the last commit in the gutter
+    pub git_gutter: bool,
     pub highlight_current_line: bool,
+    pub indent_guides: bool,
+    /// Shortcuts like `Ctrl+Shift+K` that replace or add to the default key bindings
+    pub keybinds: HashMap<String, Action>,
+    /// Default shortcuts that are turned off
+    pub keybinds_removed: Vec<String>,
+    /// Preset that the key bindings are based on
+    pub keymap: Keymap,
+    /// Height of lines in hundredths of the font size
+    pub line_height_mul_100: u16,
     pub line_numbers: bool,
+    /// Start language servers for diagnostics
+    pub lsp: bool,
+    /// Commands of the language servers of each syntax
+    pub lsp_servers: HashMap<String, Vec<String>>,
+    /// Show the rendered document beside Markdown files
+    pub markdown_preview: bool,
+    pub minimap: bool,
+    /// Change the indentation of pasted lines to tabs or spaces like the document
+    pub paste_convert_indentation: bool,
+    /// Indent pasted lines to the level of the line they are pasted on
+    pub paste_reindent: bool,
+    /// Keep the undo history panel of files in the cache, so that its states are there after they
+    /// are reopened. Undo and redo of the editor are not kept
+    pub save_undo_history_panel: bool,
+    /// Selected text can be pasted with a middle click
+    pub primary_selection: bool,
+    pub restore_session: bool,
+    /// Columns to draw vertical guide lines at, such as the line length limit of a code style
+    pub rulers: Vec<u16>,
+    /// Keep the clipboard history when the editor is closed
+    pub save_clipboard_history: bool,
+    pub save_find_history: bool,
+    pub show_whitespace: bool,
+    /// Underline misspelled words in comments and strings, or everywhere in prose documents
+    pub spell_check: bool,
+    /// Dictionaries like `en_US` of documents that have none chosen, the language of the desktop
+    /// is used when empty
+    pub spell_languages: Vec<String>,
+    /// Syntaxes of files whose names match glob patterns like `*.conf.j2`, which are used
+    /// instead of the one for the extension
+    pub syntax_associations: HashMap<String, String>,
     pub syntax_theme_dark: String,
     pub syntax_theme_light: String,
     pub tab_width: u16,
     pub vim_bindings: bool,
+    /// Cursor shape outside of insert mode when Vim bindings are used
+    pub vim_cursor_shape: CursorShape,
+    /// Complete words of the open documents in files without a language server
+    pub word_completion: bool,
+    /// Characters of a word typed before its completions are shown without Ctrl+Space
+    pub word_completion_chars: usize,
     pub word_wrap: bool,
+    /// Wrap at this column instead of the width of the window
+    pub word_wrap_column: Option<u16>,
+    /// Columns of the text in zen mode, which is centered in the window
+    pub zen_columns: u16,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             app_theme: AppTheme::System,
+            atomic_save: true,
+            auto_close_brackets: false,
+            auto_close_exclude: vec!["Plain Text".to_string()],
             auto_indent: true,
+            auto_save_secs: None,
+            clipboard_history_size: 20,
+            cursor_blink: false,
+            cursor_blink_ms: 500,
+            cursor_shape: CursorShape::Bar,
+            cursor_width: 1,
+            external_tools: Vec::new(),
             find_case_sensitive: false,
             find_use_regex: false,
+            find_whole_words: false,
             find_wrap_around: true,
+            font_features: Vec::new(),
+            font_ligatures: true,
             font_name: "Noto Sans Mono".to_string(),
             font_size: 14,
             font_size_zoom_step_mul_100: 100,
+            format_on_save: Vec::new(),
+            formatters: [
+                ("CSS", &["prettier", "--stdin-filepath", "{path}"][..]),
+                ("HTML", &["prettier", "--stdin-filepath", "{path}"]),
+                ("JavaScript", &["prettier", "--stdin-filepath", "{path}"]),
+                ("JSON", &["prettier", "--stdin-filepath", "{path}"]),
+                ("Python", &["black", "--quiet", "-"]),
+                // The edition comes from the rustfmt.toml found from the folder of the file
+                ("Rust", &["rustfmt"]),
+                ("TypeScript", &["prettier", "--stdin-filepath", "{path}"]),
+            ]
+            .into_iter()
+            .map(|(syntax_name, command)| {
+                let command = command.iter().map(|arg| arg.to_string()).collect();
+                (syntax_name.to_string(), command)
+            })
+            .collect(),
+            git_blame: false,
+            git_gutter: true,
             highlight_current_line: true,
+            indent_guides: false,
+            keybinds: HashMap::new(),
+            keybinds_removed: Vec::new(),
+            keymap: Keymap::Cosmic,
+            line_height_mul_100: 140,
             line_numbers: true,
+            lsp: false,
+            lsp_servers: [
+                ("C", &["clangd"][..]),
+                ("C++", &["clangd"]),
+                ("Go", &["gopls"]),
+                ("JavaScript", &["typescript-language-server", "--stdio"]),
+                ("Python", &["pylsp"]),
+                ("Rust", &["rust-analyzer"]),
+                ("TypeScript", &["typescript-language-server", "--stdio"]),
+            ]
+            .into_iter()
+            .map(|(syntax_name, command)| {
+                let command = command.iter().map(|arg| arg.to_string()).collect();
+                (syntax_name.to_string(), command)
+            })
+            .collect(),
+            markdown_preview: false,
+            minimap: false,
+            paste_convert_indentation: true,
+            paste_reindent: false,
+            save_undo_history_panel: false,
+            primary_selection: true,
+            restore_session: false,
+            rulers: Vec::new(),
+            save_clipboard_history: false,
+            save_find_history: true,
+            show_whitespace: false,
+            spell_check: false,
+            spell_languages: Vec::new(),
+            syntax_associations: HashMap::new(),
             syntax_theme_dark: "COSMIC Dark".to_string(),
             syntax_theme_light: "COSMIC Light".to_string(),
             tab_width: 4,
             vim_bindings: false,
+            vim_cursor_shape: CursorShape::Block,
+            word_completion: true,
+            word_completion_chars: 3,
             word_wrap: true,
+            word_wrap_column: None,
+            zen_columns: 80,
         }
     }
 }
@@ -84,20 +268,114 @@ impl Config {
         (font_size + adj * adj_step).max(1.0)
     }
 
+    /// Whether brackets and quotes are closed automatically in a syntax
+    pub fn auto_close(&self, syntax_name: &str) -> bool {
+        self.auto_close_brackets
+            && !self
+                .auto_close_exclude
+                .iter()
+                .any(|name| name == syntax_name)
+    }
+
+    /// Command of the external formatter for a syntax, if there is one
+    pub fn formatter(&self, syntax_name: &str) -> Option<&[String]> {
+        self.formatters
+            .get(syntax_name)
+            .map(Vec::as_slice)
+            .filter(|command| !command.is_empty())
+    }
+
+    /// Command of the language server for a syntax, if there is one and they are enabled
+    pub fn lsp_command(&self, syntax_name: &str) -> Option<&[String]> {
+        self.lsp_servers
+            .get(syntax_name)
+            .map(Vec::as_slice)
+            .filter(|command| self.lsp && !command.is_empty())
+    }
+
     pub fn find_regex(&self, pattern: &str) -> Result<regex::Regex, regex::Error> {
-        let mut builder = if self.find_use_regex {
-            regex::RegexBuilder::new(pattern)
+        let pattern = if self.find_use_regex {
+            pattern.to_string()
         } else {
-            regex::RegexBuilder::new(&regex::escape(pattern))
+            regex::escape(pattern)
+        };
+        let mut builder = if self.find_whole_words {
+            regex::RegexBuilder::new(&format!(r"\b(?:{pattern})\b"))
+        } else {
+            regex::RegexBuilder::new(&pattern)
         };
         builder.case_insensitive(!self.find_case_sensitive);
         builder.build()
     }
 
+    /// Convert the replace field into a template for [`regex::Captures::expand`]. With regex
+    /// enabled, `$1` and `${name}` refer to capture groups and `\n`, `\t` and `\\` are escapes,
+    /// otherwise the text is inserted as is.
+    pub fn find_replace_template(&self, replace: &str) -> String {
+        if !self.find_use_regex {
+            return replace.replace('$', "$$");
+        }
+
+        let mut template = String::with_capacity(replace.len());
+        let mut chars = replace.chars();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                template.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some('n') => template.push('\n'),
+                Some('t') => template.push('\t'),
+                Some('\\') => template.push('\\'),
+                Some(other) => {
+                    template.push('\\');
+                    template.push(other);
+                }
+                None => template.push('\\'),
+            }
+        }
+        template
+    }
+
+    // Font features of documents, ligatures are on unless turned off
+    pub fn font_features(&self) -> FontFeatures {
+        let mut font_features = FontFeatures::new();
+        if !self.font_ligatures {
+            for tag in [b"liga", b"clig", b"calt", b"dlig"] {
+                font_features.disable(FeatureTag::new(tag));
+            }
+        }
+        for feature in self.font_features.iter() {
+            let (tag, value) = match feature.strip_prefix('-') {
+                Some(tag) => (tag, Some(0)),
+                None => match feature.split_once('=') {
+                    Some((tag, value)) => (tag, value.trim().parse().ok()),
+                    None => (feature.as_str(), Some(1)),
+                },
+            };
+            match (<&[u8; 4]>::try_from(tag.trim().as_bytes()), value) {
+                (Ok(tag), Some(value)) => {
+                    font_features.set(FeatureTag::new(tag), value);
+                }
+                _ => log::warn!("invalid font feature {:?}", feature),
+            }
+        }
+        font_features
+    }
+
+    // Attributes of the text of documents
+    pub fn attrs(&self) -> Attrs<'static> {
+        crate::monospace_attrs().font_features(self.font_features())
+    }
+
     // Calculate metrics from font size
     pub fn metrics(&self, zoom_adj: i8) -> Metrics {
         let font_size = self.font_size_adjusted(zoom_adj);
-        let line_height = (font_size * 1.4).ceil();
+        let line_height_mul = f32::from(
+            self.line_height_mul_100
+                .clamp(LINE_HEIGHT_MUL_100_MIN, LINE_HEIGHT_MUL_100_MAX),
+        ) / 100.0;
+        let line_height = (font_size * line_height_mul).ceil();
         Metrics::new(font_size, line_height)
     }
 
@@ -112,17 +390,54 @@ impl Config {
     }
 }
 
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SessionTab {
+    pub path: PathBuf,
+    pub cursor_line: usize,
+    pub cursor_index: usize,
+    pub scroll_line: usize,
+    #[serde(default)]
+    pub zoom_adj: i8,
+}
+
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct Session {
+    // Index into tabs of the active tab
+    pub active: usize,
+    pub tabs: Vec<SessionTab>,
+}
+
 #[derive(Clone, CosmicConfigEntry, Debug, Deserialize, Eq, PartialEq, Serialize)]
 pub struct ConfigState {
+    // Copied and cut texts, newest first
+    pub clipboard_history: VecDeque<String>,
+    // Files of the tabs that were closed, most recent first
+    pub closed_tabs: VecDeque<SessionTab>,
+    pub expanded_folders: HashSet<PathBuf>,
+    pub find_history: VecDeque<String>,
     pub recent_files: VecDeque<PathBuf>,
     pub recent_projects: VecDeque<PathBuf>,
+    pub replace_history: VecDeque<String>,
+    pub session: Session,
+    // Spell check dictionaries chosen for files, most recently used first
+    pub spell_languages: VecDeque<(PathBuf, Vec<String>)>,
+    // Syntaxes chosen for files, most recently used first
+    pub syntax_overrides: VecDeque<(PathBuf, String)>,
 }
 
 impl Default for ConfigState {
     fn default() -> Self {
         Self {
+            clipboard_history: VecDeque::new(),
+            closed_tabs: VecDeque::new(),
+            expanded_folders: HashSet::new(),
+            find_history: VecDeque::new(),
             recent_files: VecDeque::new(),
             recent_projects: VecDeque::new(),
+            replace_history: VecDeque::new(),
+            session: Session::default(),
+            spell_languages: VecDeque::new(),
+            syntax_overrides: VecDeque::new(),
         }
     }
 }
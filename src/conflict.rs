@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Buffer, Cursor, Edit, Selection, ViEditor};
+
+/// Lines of the markers of a merge conflict
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Conflict {
+    /// The `<<<<<<<` line, followed by our lines
+    pub start: usize,
+    /// The `|||||||` line of the diff3 style, followed by the lines of the common ancestor
+    pub base_opt: Option<usize>,
+    /// The `=======` line, followed by their lines
+    pub separator: usize,
+    /// The `>>>>>>>` line
+    pub end: usize,
+}
+
+impl Conflict {
+    pub fn contains(&self, line: usize) -> bool {
+        line >= self.start && line <= self.end
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Resolution {
+    Ours,
+    Theirs,
+    Both,
+}
+
+// Markers are seven characters, then a space and a label or the end of the line
+fn is_marker(text: &str, c: char) -> bool {
+    let mut chars = text.chars();
+    (0..7).all(|_| chars.next() == Some(c)) && chars.next().is_none_or(|c| c == ' ')
+}
+
+/// Merge conflicts of a buffer, in order of their lines
+pub fn conflicts(buffer: &Buffer) -> Vec<Conflict> {
+    let mut conflicts = Vec::new();
+    let mut start_opt = None;
+    let mut base_opt = None;
+    let mut separator_opt = None;
+    for (line_i, line) in buffer.lines.iter().enumerate() {
+        let text = line.text();
+        if is_marker(text, '<') {
+            // A new start discards a conflict that did not end
+            start_opt = Some(line_i);
+            base_opt = None;
+            separator_opt = None;
+        } else if start_opt.is_some() && separator_opt.is_none() && is_marker(text, '|') {
+            base_opt = Some(line_i);
+        } else if start_opt.is_some() && text == "=======" {
+            separator_opt = Some(line_i);
+        } else if let (Some(start), Some(separator)) = (start_opt, separator_opt)
+            && is_marker(text, '>')
+        {
+            conflicts.push(Conflict {
+                start,
+                base_opt,
+                separator,
+                end: line_i,
+            });
+            start_opt = None;
+            base_opt = None;
+            separator_opt = None;
+        }
+    }
+    conflicts
+}
+
+/// Replace a conflict and its markers with our lines, their lines, or both
+pub fn resolve(
+    editor: &mut ViEditor<'static, 'static>,
+    conflict: Conflict,
+    resolution: Resolution,
+) {
+    let lines = editor.with_buffer(|buffer| {
+        let line_texts = |first: usize, end: usize| -> Vec<String> {
+            buffer.lines[first..end]
+                .iter()
+                .map(|line| line.text().to_string())
+                .collect()
+        };
+        let ours = line_texts(
+            conflict.start + 1,
+            conflict.base_opt.unwrap_or(conflict.separator),
+        );
+        let theirs = line_texts(conflict.separator + 1, conflict.end);
+        match resolution {
+            Resolution::Ours => ours,
+            Resolution::Theirs => theirs,
+            Resolution::Both => [ours, theirs].concat(),
+        }
+    });
+
+    let start = Cursor::new(conflict.start, 0);
+    editor.start_change();
+    if lines.is_empty() {
+        // Remove the line ending of the last marker too, if there is a line after it
+        let (end, has_next) = editor.with_buffer(|buffer| {
+            (
+                buffer.lines[conflict.end].text().len(),
+                conflict.end + 1 < buffer.lines.len(),
+            )
+        });
+        if has_next {
+            editor.delete_range(start, Cursor::new(conflict.end + 1, 0));
+        } else {
+            editor.delete_range(start, Cursor::new(conflict.end, end));
+        }
+    } else {
+        let end_len = editor.with_buffer(|buffer| buffer.lines[conflict.end].text().len());
+        editor.delete_range(start, Cursor::new(conflict.end, end_len));
+        editor.insert_at(start, &lines.join("\n"), None);
+    }
+    editor.finish_change();
+    editor.set_cursor(start);
+    editor.set_selection(Selection::None);
+}
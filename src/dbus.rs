@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::iced::futures::{
+    SinkExt,
+    channel::{mpsc, oneshot},
+};
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+use zbus::{connection, fdo, interface};
+
+use crate::Message;
+
+/// Requests from other programs over D-Bus, which are handled like messages
+#[derive(Clone, Debug)]
+pub enum Request {
+    GetOpenFiles(Reply<Vec<String>>),
+    InsertText(String),
+    OpenFile(PathBuf, Option<(usize, usize)>),
+    SaveAll,
+}
+
+/// Answer to a request, which can only be sent once
+#[derive(Clone, Debug)]
+pub struct Reply<T>(Arc<Mutex<Option<oneshot::Sender<T>>>>);
+
+impl<T> Reply<T> {
+    pub fn send(&self, value: T) {
+        if let Some(sender) = self.0.lock().unwrap().take() {
+            // The caller may have stopped waiting
+            let _ = sender.send(value);
+        }
+    }
+}
+
+struct Editor {
+    output: mpsc::Sender<Message>,
+}
+
+impl Editor {
+    async fn request(&self, request: Request) -> fdo::Result<()> {
+        self.output
+            .clone()
+            .send(Message::DbusRequest(request))
+            .await
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+}
+
+#[interface(name = "com.system76.CosmicEdit")]
+impl Editor {
+    /// Paths of the files open in tabs
+    async fn get_open_files(&self) -> fdo::Result<Vec<String>> {
+        let (sender, receiver) = oneshot::channel();
+        self.request(Request::GetOpenFiles(Reply(Arc::new(Mutex::new(Some(
+            sender,
+        ))))))
+        .await?;
+        receiver
+            .await
+            .map_err(|err| fdo::Error::Failed(err.to_string()))
+    }
+
+    /// Insert text at the cursors of the active tab
+    async fn insert_text(&self, text: String) -> fdo::Result<()> {
+        self.request(Request::InsertText(text)).await
+    }
+
+    /// Open a file in a tab, or activate its tab if it is already open
+    async fn open_file(&self, path: String) -> fdo::Result<()> {
+        self.request(Request::OpenFile(path.into(), None)).await
+    }
+
+    /// Open a file with the cursor at a line and column, which start at one
+    async fn open_at_line(&self, path: String, line: u32, column: u32) -> fdo::Result<()> {
+        self.request(Request::OpenFile(
+            path.into(),
+            Some((line as usize, column as usize)),
+        ))
+        .await
+    }
+
+    /// Save all tabs that have a file
+    async fn save_all(&self) -> fdo::Result<()> {
+        self.request(Request::SaveAll).await
+    }
+}
+
+/// Serve the scripting interface, which is kept while the connection is
+pub async fn serve(output: mpsc::Sender<Message>) -> zbus::Result<zbus::Connection> {
+    connection::Builder::session()?
+        .name("com.system76.CosmicEdit")?
+        .serve_at("/com/system76/CosmicEdit", Editor { output })?
+        .build()
+        .await
+}
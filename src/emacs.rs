@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Action, BorrowedWithFontSystem, Cursor, Edit, Motion, Selection, ViEditor};
+use serde::{Deserialize, Serialize};
+
+use crate::fl;
+
+/// Cursor movements that can be bound to shortcuts, like Ctrl+A and Ctrl+E in Emacs
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, Ord, PartialEq, PartialOrd, Serialize)]
+pub enum Movement {
+    Backward,
+    Forward,
+    PreviousLine,
+    NextLine,
+    LineStart,
+    LineEnd,
+    WordBackward,
+    WordForward,
+    PageUp,
+    PageDown,
+    DocumentStart,
+    DocumentEnd,
+}
+
+impl Movement {
+    pub fn all() -> &'static [Self] {
+        &[
+            Self::Backward,
+            Self::Forward,
+            Self::PreviousLine,
+            Self::NextLine,
+            Self::LineStart,
+            Self::LineEnd,
+            Self::WordBackward,
+            Self::WordForward,
+            Self::PageUp,
+            Self::PageDown,
+            Self::DocumentStart,
+            Self::DocumentEnd,
+        ]
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Backward => fl!("move-backward"),
+            Self::Forward => fl!("move-forward"),
+            Self::PreviousLine => fl!("move-previous-line"),
+            Self::NextLine => fl!("move-next-line"),
+            Self::LineStart => fl!("move-line-start"),
+            Self::LineEnd => fl!("move-line-end"),
+            Self::WordBackward => fl!("move-word-backward"),
+            Self::WordForward => fl!("move-word-forward"),
+            Self::PageUp => fl!("move-page-up"),
+            Self::PageDown => fl!("move-page-down"),
+            Self::DocumentStart => fl!("move-document-start"),
+            Self::DocumentEnd => fl!("move-document-end"),
+        }
+    }
+
+    fn motion(&self) -> Motion {
+        match self {
+            Self::Backward => Motion::Left,
+            Self::Forward => Motion::Right,
+            Self::PreviousLine => Motion::Up,
+            Self::NextLine => Motion::Down,
+            Self::LineStart => Motion::Home,
+            Self::LineEnd => Motion::End,
+            Self::WordBackward => Motion::LeftWord,
+            Self::WordForward => Motion::RightWord,
+            Self::PageUp => Motion::PageUp,
+            Self::PageDown => Motion::PageDown,
+            Self::DocumentStart => Motion::BufferStart,
+            Self::DocumentEnd => Motion::BufferEnd,
+        }
+    }
+}
+
+/// Move the cursor, which extends the selection from the mark while there is one
+pub fn move_cursor(
+    editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
+    movement: Movement,
+) {
+    editor.action(Action::Motion(movement.motion()));
+    editor.set_redraw(true);
+}
+
+/// Start selecting at the cursor, so that movements select text like in Emacs
+pub fn set_mark(editor: &mut ViEditor<'static, 'static>) {
+    let cursor = editor.cursor();
+    editor.set_selection(Selection::Normal(cursor));
+    editor.set_redraw(true);
+}
+
+// Delete the text between two positions as one change, returning it
+fn kill(editor: &mut ViEditor<'static, 'static>, start: Cursor, end: Cursor) -> Option<String> {
+    if start == end {
+        return None;
+    }
+    editor.set_selection(Selection::Normal(start));
+    editor.set_cursor(end);
+    let text_opt = editor.copy_selection();
+    editor.start_change();
+    editor.delete_selection();
+    editor.finish_change();
+    editor.set_redraw(true);
+    text_opt
+}
+
+/// Delete from the cursor to the end of the line, or the line break if the cursor is at the end,
+/// returning the text that was deleted
+pub fn kill_line(
+    editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
+) -> Option<String> {
+    let start = editor.cursor();
+    let end = editor.with_buffer(|buffer| {
+        let len = buffer.lines[start.line].text().len();
+        if start.index < len {
+            Cursor::new(start.line, len)
+        } else if start.line + 1 < buffer.lines.len() {
+            Cursor::new(start.line + 1, 0)
+        } else {
+            start
+        }
+    });
+    kill(editor, start, end)
+}
+
+/// Delete from the cursor to the end of the word, returning the text that was deleted
+pub fn kill_word(
+    editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
+) -> Option<String> {
+    let start = editor.cursor();
+    editor.set_selection(Selection::None);
+    editor.action(Action::Motion(Motion::RightWord));
+    let end = editor.cursor();
+    kill(editor, start, end)
+}
@@ -0,0 +1,102 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use encoding_rs::{
+    BIG5, EUC_JP, EUC_KR, Encoding, GB18030, GBK, ISO_8859_2, ISO_8859_3, ISO_8859_4, ISO_8859_5,
+    ISO_8859_6, ISO_8859_7, ISO_8859_8, ISO_8859_10, ISO_8859_13, ISO_8859_14, ISO_8859_15,
+    ISO_8859_16, KOI8_R, SHIFT_JIS, UTF_8, UTF_16BE, UTF_16LE, WINDOWS_1250, WINDOWS_1251,
+    WINDOWS_1252,
+};
+use std::borrow::Cow;
+
+/// Encodings that documents can be saved with
+pub fn encodings() -> [&'static Encoding; 25] {
+    [
+        UTF_8,
+        UTF_16LE,
+        UTF_16BE,
+        // ISO-8859-1 is treated as its superset Windows-1252
+        WINDOWS_1252,
+        ISO_8859_2,
+        ISO_8859_3,
+        ISO_8859_4,
+        ISO_8859_5,
+        ISO_8859_6,
+        ISO_8859_7,
+        ISO_8859_8,
+        ISO_8859_10,
+        ISO_8859_13,
+        ISO_8859_14,
+        ISO_8859_15,
+        ISO_8859_16,
+        WINDOWS_1250,
+        WINDOWS_1251,
+        KOI8_R,
+        SHIFT_JIS,
+        EUC_JP,
+        EUC_KR,
+        GBK,
+        GB18030,
+        BIG5,
+    ]
+}
+
+/// Detect the encoding of `bytes`, using the byte order mark if there is one
+pub fn detect(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _bom_len)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    if std::str::from_utf8(bytes).is_ok() {
+        return UTF_8;
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, true);
+    detector.guess(None, true)
+}
+
+/// Detect the encoding of a file from its first bytes, for files that are read in chunks
+pub fn detect_start(bytes: &[u8]) -> &'static Encoding {
+    if let Some((encoding, _bom_len)) = Encoding::for_bom(bytes) {
+        return encoding;
+    }
+
+    match std::str::from_utf8(bytes) {
+        // A character may be cut off at the end
+        Ok(_) => return UTF_8,
+        Err(err) if err.error_len().is_none() => return UTF_8,
+        Err(_) => {}
+    }
+
+    let mut detector = chardetng::EncodingDetector::new();
+    detector.feed(bytes, false);
+    detector.guess(None, true)
+}
+
+/// Decode `bytes` from `encoding`, removing the byte order mark
+pub fn decode<'a>(bytes: &'a [u8], encoding: &'static Encoding) -> Cow<'a, str> {
+    let (text, had_errors) = encoding.decode_with_bom_removal(bytes);
+    if had_errors {
+        log::warn!("replaced invalid {} sequences", encoding.name());
+    }
+    text
+}
+
+/// Encode `text` to `encoding`, returning whether characters it cannot encode were replaced
+pub fn encode<'a>(text: &'a str, encoding: &'static Encoding) -> (Cow<'a, [u8]>, bool) {
+    // encoding_rs can only decode UTF-16, so it is encoded here with a byte order mark
+    if encoding == UTF_16LE || encoding == UTF_16BE {
+        let mut bytes = Vec::with_capacity(text.len().saturating_mul(2).saturating_add(2));
+        for unit in std::iter::once(0xFEFF).chain(text.encode_utf16()) {
+            if encoding == UTF_16LE {
+                bytes.extend_from_slice(&unit.to_le_bytes());
+            } else {
+                bytes.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+        return (Cow::Owned(bytes), false);
+    }
+
+    let (bytes, _encoding, had_errors) = encoding.encode(text);
+    (bytes, had_errors)
+}
@@ -0,0 +1,566 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Cursor, Edit, Selection, ViEditor};
+use regex::{Regex, RegexBuilder};
+use std::path::PathBuf;
+
+use crate::fl;
+
+/// Lines that a command applies to, from the first to the last
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Range {
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Command typed after `:` with Vim bindings
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Command {
+    Delete(Range),
+    Edit(Option<PathBuf>, bool),
+    GoToLine(usize),
+    NewFile,
+    Next,
+    NoHighlight,
+    Previous,
+    Quit {
+        all: bool,
+        force: bool,
+    },
+    Set(Vec<String>),
+    Split(bool),
+    Substitute {
+        range: Range,
+        pattern: String,
+        replacement: String,
+        flags: String,
+    },
+    Write {
+        path_opt: Option<PathBuf>,
+        all: bool,
+        quit: bool,
+        force: bool,
+    },
+}
+
+/// Why a command was not run, which is shown in the status line
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    ArgumentRequired,
+    FileExists,
+    InvalidPattern(String),
+    InvalidRange,
+    NoFileName,
+    NotACommand(String),
+    NoWriteSinceLastChange,
+    PatternNotFound(String),
+    TrailingCharacters(String),
+    UnknownOption(String),
+    WriteFailed(String),
+}
+
+impl Error {
+    pub fn message(&self) -> String {
+        match self {
+            Self::ArgumentRequired => fl!("ex-argument-required"),
+            Self::FileExists => fl!("ex-file-exists"),
+            Self::InvalidPattern(err) => fl!("ex-invalid-pattern", error = err.as_str()),
+            Self::InvalidRange => fl!("ex-invalid-range"),
+            Self::NoFileName => fl!("ex-no-file-name"),
+            Self::NotACommand(command) => fl!("ex-not-a-command", command = command.as_str()),
+            Self::NoWriteSinceLastChange => fl!("ex-no-write-since-last-change"),
+            Self::PatternNotFound(pattern) => {
+                fl!("ex-pattern-not-found", pattern = pattern.as_str())
+            }
+            Self::TrailingCharacters(text) => {
+                fl!("ex-trailing-characters", text = text.as_str())
+            }
+            Self::UnknownOption(option) => fl!("ex-unknown-option", option = option.as_str()),
+            Self::WriteFailed(err) => fl!("ex-write-failed", error = err.as_str()),
+        }
+    }
+}
+
+/// Lines of the document that addresses in a range are relative to
+#[derive(Clone, Copy, Debug)]
+pub struct Lines {
+    pub current: usize,
+    pub count: usize,
+    /// Lines of the selection, which `'<` and `'>` refer to
+    pub selection_opt: Option<(usize, usize)>,
+}
+
+fn number(text: &mut &str) -> Option<usize> {
+    let end = text
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(text.len());
+    if end == 0 {
+        return None;
+    }
+    let (digits, rest) = text.split_at(end);
+    *text = rest;
+    // Numbers too large for a line are past the end
+    Some(digits.parse().unwrap_or(usize::MAX))
+}
+
+// A line like `.`, `$`, `12`, or `'<`, followed by offsets like `+2`
+fn address(text: &mut &str, lines: Lines) -> Result<Option<usize>, Error> {
+    let last = lines.count.saturating_sub(1);
+    let mut line_opt = if let Some(rest) = text.strip_prefix('.') {
+        *text = rest;
+        Some(lines.current)
+    } else if let Some(rest) = text.strip_prefix('$') {
+        *text = rest;
+        Some(last)
+    } else if let Some(rest) = text.strip_prefix('\'') {
+        let (start, end) = lines.selection_opt.ok_or(Error::InvalidRange)?;
+        *text = rest.get(1..).unwrap_or("");
+        match rest.chars().next() {
+            Some('<') => Some(start),
+            Some('>') => Some(end),
+            _ => return Err(Error::InvalidRange),
+        }
+    } else {
+        // Lines are numbered from one, and zero is before the first
+        number(text).map(|number| number.saturating_sub(1))
+    };
+
+    while let Some(sign) = text.chars().next().filter(|c| *c == '+' || *c == '-') {
+        *text = &text[1..];
+        let offset = number(text).unwrap_or(1);
+        let line = line_opt.unwrap_or(lines.current);
+        line_opt = Some(if sign == '+' {
+            line.saturating_add(offset)
+        } else {
+            line.checked_sub(offset).ok_or(Error::InvalidRange)?
+        });
+    }
+
+    match line_opt {
+        Some(line) if line > last => Err(Error::InvalidRange),
+        _ => Ok(line_opt),
+    }
+}
+
+// A range like `%`, `5`, or `.,$`, which is `None` if there is none
+fn range(text: &mut &str, lines: Lines) -> Result<Option<Range>, Error> {
+    if let Some(rest) = text.strip_prefix('%') {
+        *text = rest;
+        return Ok(Some(Range {
+            start: 0,
+            end: lines.count.saturating_sub(1),
+        }));
+    }
+
+    let start_opt = address(text, lines)?;
+    let Some(rest) = text.strip_prefix([',', ';']) else {
+        return Ok(start_opt.map(|start| Range { start, end: start }));
+    };
+    *text = rest;
+    let start = start_opt.unwrap_or(lines.current);
+    let end = address(text, lines)?.unwrap_or(lines.current);
+    // Backwards ranges are swapped
+    Ok(Some(Range {
+        start: start.min(end),
+        end: start.max(end),
+    }))
+}
+
+// Split `/pattern/replacement/flags` at the delimiter, which is escaped with a backslash
+fn substitute_parts(text: &str) -> Result<(String, String, String), Error> {
+    let mut chars = text.chars();
+    let delimiter = chars
+        .next()
+        .filter(|c| !c.is_alphanumeric() && !c.is_whitespace() && !matches!(c, '\\' | '"' | '|'))
+        .ok_or(Error::ArgumentRequired)?;
+    let mut parts = vec![String::new()];
+    while let Some(c) = chars.next() {
+        if parts.len() == 3 {
+            parts[2].push(c);
+        } else if c == delimiter {
+            parts.push(String::new());
+        } else if c == '\\' {
+            let part = parts.last_mut().unwrap();
+            match chars.next() {
+                Some(next) if next == delimiter => part.push(next),
+                Some(next) => {
+                    part.push(c);
+                    part.push(next);
+                }
+                None => part.push(c),
+            }
+        } else {
+            parts.last_mut().unwrap().push(c);
+        }
+    }
+    parts.resize(3, String::new());
+    let flags = parts.pop().unwrap();
+    let replacement = parts.pop().unwrap();
+    let pattern = parts.pop().unwrap();
+    if pattern.is_empty() {
+        return Err(Error::ArgumentRequired);
+    }
+    if let Some(c) = flags
+        .chars()
+        .find(|c| !matches!(c, 'g' | 'i' | 'I' | 'n' | '&'))
+    {
+        return Err(Error::TrailingCharacters(
+            flags[flags.find(c).unwrap()..].to_string(),
+        ));
+    }
+    Ok((pattern, replacement, flags))
+}
+
+fn path(arg: &str) -> PathBuf {
+    match arg.strip_prefix("~/") {
+        Some(rest) => match dirs::home_dir() {
+            Some(home) => home.join(rest),
+            None => PathBuf::from(arg),
+        },
+        None => PathBuf::from(arg),
+    }
+}
+
+/// Parse the text typed after `:`
+pub fn parse(text: &str, lines: Lines) -> Result<Command, Error> {
+    let mut text = text.trim_start_matches([' ', ':']);
+    let range_opt = range(&mut text, lines)?;
+    let text = text.trim_start();
+
+    let name_end = text
+        .find(|c: char| !c.is_ascii_alphabetic())
+        .unwrap_or(text.len());
+    let (name, after_name) = text.split_at(name_end);
+    let (force, rest) = match after_name.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, after_name),
+    };
+    let arg = rest.trim();
+    let path_opt = (!arg.is_empty()).then(|| path(arg));
+    let no_arg = |command| {
+        if arg.is_empty() {
+            Ok(command)
+        } else {
+            Err(Error::TrailingCharacters(arg.to_string()))
+        }
+    };
+    let current = Range {
+        start: lines.current,
+        end: lines.current,
+    };
+
+    match name {
+        "" => match range_opt {
+            Some(range) => no_arg(Command::GoToLine(range.end)),
+            None => Err(Error::NotACommand(text.to_string())),
+        },
+        "d" | "de" | "del" | "delete" => no_arg(Command::Delete(range_opt.unwrap_or(current))),
+        "e" | "edit" | "tabe" | "tabedit" => Ok(Command::Edit(path_opt, force)),
+        "ene" | "enew" | "tabnew" => no_arg(Command::NewFile),
+        "bn" | "bnext" | "tabn" | "tabnext" => no_arg(Command::Next),
+        "noh" | "nohlsearch" => no_arg(Command::NoHighlight),
+        "bp" | "bprevious" | "bN" | "bNext" | "tabp" | "tabprevious" => no_arg(Command::Previous),
+        "q" | "quit" | "clo" | "close" => no_arg(Command::Quit { all: false, force }),
+        "qa" | "qall" | "quita" | "quitall" => no_arg(Command::Quit { all: true, force }),
+        "s" | "su" | "substitute" => {
+            // The delimiter may be `!`, so it is not taken as forcing the command
+            let (pattern, replacement, flags) = substitute_parts(after_name)?;
+            Ok(Command::Substitute {
+                range: range_opt.unwrap_or(current),
+                pattern,
+                replacement,
+                flags,
+            })
+        }
+        "se" | "set" => Ok(Command::Set(
+            arg.split_whitespace().map(str::to_string).collect(),
+        )),
+        "sp" | "split" => no_arg(Command::Split(false)),
+        "vs" | "vsplit" => no_arg(Command::Split(true)),
+        "w" | "write" => Ok(Command::Write {
+            path_opt,
+            all: false,
+            quit: false,
+            force,
+        }),
+        "wa" | "wall" => no_arg(Command::Write {
+            path_opt: None,
+            all: true,
+            quit: false,
+            force,
+        }),
+        "wq" | "x" | "xit" | "exi" | "exit" => Ok(Command::Write {
+            path_opt,
+            all: false,
+            quit: true,
+            force,
+        }),
+        "wqa" | "wqall" | "xa" | "xall" => no_arg(Command::Write {
+            path_opt: None,
+            all: true,
+            quit: true,
+            force,
+        }),
+        _ => Err(Error::NotACommand(text.to_string())),
+    }
+}
+
+/// Option of `:set`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SetOption {
+    AutoIndent,
+    CursorLine,
+    List,
+    Number,
+    TabStop,
+    Wrap,
+}
+
+impl SetOption {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "ai" | "autoindent" => Self::AutoIndent,
+            "cul" | "cursorline" => Self::CursorLine,
+            "list" => Self::List,
+            "nu" | "number" => Self::Number,
+            "ts" | "tabstop" | "sw" | "shiftwidth" | "sts" | "softtabstop" => Self::TabStop,
+            "wrap" => Self::Wrap,
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::AutoIndent => "autoindent",
+            Self::CursorLine => "cursorline",
+            Self::List => "list",
+            Self::Number => "number",
+            Self::TabStop => "tabstop",
+            Self::Wrap => "wrap",
+        }
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Self::TabStop)
+    }
+}
+
+/// Change asked for by an argument of `:set`
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Setting {
+    /// Show the value
+    Show(SetOption),
+    /// Turn on, turn off, or toggle if it is `None`
+    Bool(SetOption, Option<bool>),
+    Number(SetOption, u16),
+}
+
+/// Parse an argument of `:set` like `number`, `nowrap`, `list!`, `ts=4`, or `wrap?`
+pub fn setting(arg: &str) -> Result<Setting, Error> {
+    let unknown = || Error::UnknownOption(arg.to_string());
+    if let Some((name, value)) = arg.split_once(['=', ':']) {
+        let option = SetOption::from_name(name).ok_or_else(unknown)?;
+        if !option.is_number() {
+            return Err(Error::TrailingCharacters(arg.to_string()));
+        }
+        return match value.parse::<u16>() {
+            Ok(number) if number > 0 => Ok(Setting::Number(option, number)),
+            _ => Err(Error::TrailingCharacters(arg.to_string())),
+        };
+    }
+    if let Some(name) = arg.strip_suffix('?') {
+        return Ok(Setting::Show(
+            SetOption::from_name(name).ok_or_else(unknown)?,
+        ));
+    }
+    if let Some(name) = arg.strip_suffix('!').or_else(|| arg.strip_prefix("inv")) {
+        let option = SetOption::from_name(name).ok_or_else(unknown)?;
+        if option.is_number() {
+            return Err(unknown());
+        }
+        return Ok(Setting::Bool(option, None));
+    }
+    if let Some(option) = SetOption::from_name(arg) {
+        // Numbers are shown when they are not given a value
+        if option.is_number() {
+            return Ok(Setting::Show(option));
+        }
+        return Ok(Setting::Bool(option, Some(true)));
+    }
+    match arg.strip_prefix("no").and_then(SetOption::from_name) {
+        Some(option) if !option.is_number() => Ok(Setting::Bool(option, Some(false))),
+        _ => Err(unknown()),
+    }
+}
+
+// Convert a pattern where `\(`, `\|`, `\+`, and `\<` are special, like the default in Vim, to
+// one where they are not
+fn regex(pattern: &str, flags: &str) -> Result<Regex, Error> {
+    let mut converted = String::with_capacity(pattern.len());
+    let mut case_insensitive = flags.contains('i');
+    // The closing brace of `\{1,3}` does not need a backslash
+    let mut in_braces = false;
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some('{') => {
+                    converted.push('{');
+                    in_braces = true;
+                }
+                Some('}') if in_braces => {
+                    converted.push('}');
+                    in_braces = false;
+                }
+                Some(next @ ('(' | ')' | '|' | '+' | '?' | '{' | '}')) => converted.push(next),
+                Some('<' | '>') => converted.push_str("\\b"),
+                Some('=') => converted.push('?'),
+                Some('c') => case_insensitive = true,
+                Some('C') => case_insensitive = false,
+                Some('t') => converted.push_str("\\t"),
+                Some(next) => {
+                    converted.push('\\');
+                    converted.push(next);
+                }
+                None => converted.push_str("\\\\"),
+            },
+            '}' if in_braces => {
+                converted.push(c);
+                in_braces = false;
+            }
+            '(' | ')' | '|' | '+' | '?' | '{' | '}' => {
+                converted.push('\\');
+                converted.push(c);
+            }
+            _ => converted.push(c),
+        }
+    }
+    RegexBuilder::new(&converted)
+        .case_insensitive(case_insensitive && !flags.contains('I'))
+        .build()
+        .map_err(|err| Error::InvalidPattern(err.to_string()))
+}
+
+// Convert a replacement where `&` and `\1` are the match and its groups, and `\r` is a new line
+fn replacement(text: &str) -> String {
+    let mut converted = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => converted.push_str("${0}"),
+            '$' => converted.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => {
+                    converted.push_str("${");
+                    converted.push(digit);
+                    converted.push('}');
+                }
+                Some('r' | 'n') => converted.push('\n'),
+                Some('t') => converted.push('\t'),
+                Some('$') => converted.push_str("$$"),
+                Some(next) => converted.push(next),
+                None => converted.push('\\'),
+            },
+            _ => converted.push(c),
+        }
+    }
+    converted
+}
+
+/// Replace the matches of a pattern in a range, returning the number of replacements
+pub fn substitute(
+    editor: &mut ViEditor<'static, 'static>,
+    range: Range,
+    pattern: &str,
+    replacement: &str,
+    flags: &str,
+) -> Result<usize, Error> {
+    let regex = regex(pattern, flags)?;
+    let replacement = self::replacement(replacement);
+    let all = flags.contains('g');
+    let count_only = flags.contains('n');
+
+    // Lines are changed from the last, so that new lines do not move the ones before them
+    let mut changes = Vec::new();
+    let mut count = 0;
+    editor.with_buffer(|buffer| {
+        let end = range.end.min(buffer.lines.len().saturating_sub(1));
+        for line_i in (range.start..=end).rev() {
+            let text = buffer.lines[line_i].text();
+            let matches = if all {
+                regex.find_iter(text).count()
+            } else {
+                usize::from(regex.is_match(text))
+            };
+            if matches == 0 {
+                continue;
+            }
+            count += matches;
+            let limit = if all { 0 } else { 1 };
+            let changed = regex.replacen(text, limit, replacement.as_str());
+            changes.push((line_i, text.len(), changed.into_owned()));
+        }
+    });
+    if count == 0 {
+        return Err(Error::PatternNotFound(pattern.to_string()));
+    }
+    if count_only {
+        return Ok(count);
+    }
+
+    editor.start_change();
+    for (line_i, len, text) in changes.iter() {
+        editor.delete_range(Cursor::new(*line_i, 0), Cursor::new(*line_i, *len));
+        editor.insert_at(Cursor::new(*line_i, 0), text, None);
+    }
+    editor.finish_change();
+    // The cursor goes to the last line that was changed, like in Vim, which moves down by the new
+    // lines that were inserted
+    if let Some((line_i, _, _)) = changes.first() {
+        let added: usize = changes
+            .iter()
+            .map(|(_, _, text)| text.matches('\n').count())
+            .sum();
+        go_to_line(editor, line_i + added);
+    }
+    Ok(count)
+}
+
+/// Delete the lines of a range
+pub fn delete(editor: &mut ViEditor<'static, 'static>, range: Range) {
+    let (start, end) = editor.with_buffer(|buffer| {
+        let last = buffer.lines.len().saturating_sub(1);
+        let end_line = range.end.min(last);
+        if end_line < last {
+            (Cursor::new(range.start, 0), Cursor::new(end_line + 1, 0))
+        } else if range.start > 0 {
+            // The new line before the range is deleted when the range ends at the last line
+            let before = range.start - 1;
+            (
+                Cursor::new(before, buffer.lines[before].text().len()),
+                Cursor::new(last, buffer.lines[last].text().len()),
+            )
+        } else {
+            (
+                Cursor::new(0, 0),
+                Cursor::new(last, buffer.lines[last].text().len()),
+            )
+        }
+    });
+    editor.start_change();
+    editor.delete_range(start, end);
+    editor.finish_change();
+    go_to_line(editor, range.start);
+}
+
+/// Move the cursor to the first character that is not whitespace in a line
+pub fn go_to_line(editor: &mut ViEditor<'static, 'static>, line: usize) {
+    let cursor = editor.with_buffer(|buffer| {
+        let line = line.min(buffer.lines.len().saturating_sub(1));
+        let text = buffer.lines[line].text();
+        Cursor::new(line, text.len() - text.trim_start().len())
+    });
+    editor.set_selection(Selection::None);
+    editor.set_cursor(cursor);
+    editor.set_redraw(true);
+}
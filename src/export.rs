@@ -0,0 +1,173 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{fmt::Write as _, io, path::Path, process::Stdio};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{Color, FontStyle, Theme},
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Format {
+    Html,
+    Pdf,
+}
+
+impl Format {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Html => "html",
+            Self::Pdf => "pdf",
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\n' | '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn css_color(color: Color) -> String {
+    if color.a == 0xFF {
+        format!("#{:02x}{:02x}{:02x}", color.r, color.g, color.b)
+    } else {
+        format!(
+            "rgba({}, {}, {}, {:.3})",
+            color.r,
+            color.g,
+            color.b,
+            f32::from(color.a) / 255.0
+        )
+    }
+}
+
+/// Render `text` as an HTML document that needs no other files, with the colors of the theme
+pub fn html(
+    text: &str,
+    title: &str,
+    highlight_opt: Option<(&SyntaxSet, &SyntaxReference, &Theme)>,
+    tab_width: usize,
+    line_numbers: bool,
+) -> String {
+    let settings_opt = highlight_opt.map(|(_, _, theme)| &theme.settings);
+    let foreground = settings_opt
+        .and_then(|settings| settings.foreground)
+        .map_or_else(|| "#000000".to_string(), css_color);
+    let background = settings_opt
+        .and_then(|settings| settings.background)
+        .map_or_else(|| "#ffffff".to_string(), css_color);
+    let gutter = settings_opt
+        .and_then(|settings| settings.gutter_foreground)
+        .map_or_else(|| "#808080".to_string(), css_color);
+
+    let mut html = String::new();
+    let _ = writeln!(html, "<!DOCTYPE html>");
+    let _ = writeln!(html, "<html>");
+    let _ = writeln!(html, "<head>");
+    let _ = writeln!(html, "<meta charset=\"utf-8\">");
+    let _ = writeln!(html, "<title>{}</title>", escape(title));
+    let _ = writeln!(html, "<style>");
+    let _ = writeln!(
+        html,
+        "body {{ margin: 0; color: {foreground}; background: {background}; }}"
+    );
+    let _ = writeln!(
+        html,
+        "pre {{ margin: 0; padding: 1em; font-family: monospace; tab-size: {tab_width}; }}"
+    );
+    // Line numbers are left out when the text is copied
+    let _ = writeln!(
+        html,
+        ".line-number {{ color: {gutter}; user-select: none; }}"
+    );
+    let _ = writeln!(html, "</style>");
+    let _ = writeln!(html, "</head>");
+    let _ = writeln!(html, "<body>");
+    html.push_str("<pre>");
+
+    let line_count = text.lines().count().max(1);
+    let digits = line_count.to_string().len();
+    let mut highlighter_opt = highlight_opt
+        .map(|(syntax_set, syntax, theme)| (syntax_set, HighlightLines::new(syntax, theme)));
+    for (line_i, line) in LinesWithEndings::from(text).enumerate() {
+        if line_numbers {
+            let _ = write!(
+                html,
+                "<span class=\"line-number\">{:>digits$} </span>",
+                line_i + 1
+            );
+        }
+        match &mut highlighter_opt {
+            Some((syntax_set, highlighter)) => match highlighter.highlight_line(line, syntax_set) {
+                Ok(ranges) => {
+                    for (style, text) in ranges {
+                        let text = escape(text);
+                        if text.is_empty() {
+                            continue;
+                        }
+                        let mut css = format!("color: {}", css_color(style.foreground));
+                        if style.font_style.contains(FontStyle::BOLD) {
+                            css.push_str("; font-weight: bold");
+                        }
+                        if style.font_style.contains(FontStyle::ITALIC) {
+                            css.push_str("; font-style: italic");
+                        }
+                        if style.font_style.contains(FontStyle::UNDERLINE) {
+                            css.push_str("; text-decoration: underline");
+                        }
+                        let _ = write!(html, "<span style=\"{css}\">{text}</span>");
+                    }
+                }
+                Err(err) => {
+                    log::warn!("failed to highlight line {}: {}", line_i + 1, err);
+                    html.push_str(&escape(line));
+                }
+            },
+            None => html.push_str(&escape(line)),
+        }
+        html.push('\n');
+    }
+
+    let _ = writeln!(html, "</pre>");
+    let _ = writeln!(html, "</body>");
+    let _ = writeln!(html, "</html>");
+    html
+}
+
+/// Convert a PostScript document to a PDF file with Ghostscript
+pub async fn pdf(document: String, path: &Path) -> io::Result<()> {
+    let mut command = Command::new("ps2pdf");
+    command
+        .arg("-")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    log::info!("{:?}", command);
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(document.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "ps2pdf exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
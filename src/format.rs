@@ -0,0 +1,152 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::iced::futures::future;
+use cosmic_text::Cursor;
+use std::{
+    io,
+    path::Path,
+    process::{self, Stdio},
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::lsp::TextEdit;
+
+// Lines compared against each other at most, larger changes are replaced at once
+const DIFF_CELLS: usize = 4_000_000;
+
+/// Run a formatter that reads the text on stdin and writes the formatted text to stdout, where
+/// `{path}` in its arguments is replaced with the path of the file
+pub async fn external(
+    command: Vec<String>,
+    path_opt: Option<&Path>,
+    text: String,
+) -> io::Result<String> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+    let mut command = Command::new(program);
+    for arg in args {
+        match path_opt {
+            Some(path) if arg.contains("{path}") => {
+                command.arg(arg.replace("{path}", &path.to_string_lossy()))
+            }
+            _ => command.arg(arg),
+        };
+    }
+    if let Some(parent) = path_opt.and_then(Path::parent) {
+        // Formatters find their configuration next to the file
+        command.current_dir(parent);
+    }
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    log::info!("{:?}", command);
+    let mut child = command.spawn()?;
+    // Formatters may write before all input is read, so the input is written while waiting
+    let stdin_opt = child.stdin.take();
+    let write = async move {
+        if let Some(mut stdin) = stdin_opt {
+            stdin.write_all(text.as_bytes()).await?;
+        }
+        Ok::<(), io::Error>(())
+    };
+    let (write_res, output_res) = future::join(write, child.wait_with_output()).await;
+    let output = output_res?;
+    if !output.status.success() {
+        return Err(output_error(program, output));
+    }
+    write_res?;
+    String::from_utf8(output.stdout).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("failed to parse {} output: {}", program, err),
+        )
+    })
+}
+
+fn output_error(program: &str, output: process::Output) -> io::Error {
+    let mut msg = format!("{} exited with {}", program, output.status);
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        msg.push_str("\nstderr> ");
+        msg.push_str(line);
+    }
+    io::Error::other(msg)
+}
+
+// Position of the start of a line, or of the end of the text after the last line
+fn line_start(lines: &[&str], line: usize) -> Cursor {
+    match lines.get(..line).and_then(<[&str]>::last) {
+        Some(last) if !last.ends_with('\n') => Cursor::new(line - 1, last.len()),
+        _ => Cursor::new(line, 0),
+    }
+}
+
+/// Edits that change the old text into the new one, replacing only the lines that differ so
+/// that positions in other lines are kept
+pub fn diff_edits(old: &str, new: &str) -> Vec<TextEdit> {
+    let old_lines: Vec<&str> = old.split_inclusive('\n').collect();
+    let new_lines: Vec<&str> = new.split_inclusive('\n').collect();
+
+    // Lines that did not change at the start and end are not compared
+    let prefix = old_lines
+        .iter()
+        .zip(new_lines.iter())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let suffix = old_lines[prefix..]
+        .iter()
+        .rev()
+        .zip(new_lines[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let old_mid = &old_lines[prefix..old_lines.len() - suffix];
+    let new_mid = &new_lines[prefix..new_lines.len() - suffix];
+
+    // Pairs of lines that are kept, as indexes in the old and new lines
+    let mut kept = Vec::new();
+    if old_mid.len().saturating_mul(new_mid.len()) <= DIFF_CELLS {
+        // Longest common subsequence, counting from the end
+        let width = new_mid.len() + 1;
+        let mut lengths = vec![0u32; (old_mid.len() + 1) * width];
+        for i in (0..old_mid.len()).rev() {
+            for j in (0..new_mid.len()).rev() {
+                lengths[i * width + j] = if old_mid[i] == new_mid[j] {
+                    lengths[(i + 1) * width + j + 1] + 1
+                } else {
+                    lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < old_mid.len() && j < new_mid.len() {
+            if old_mid[i] == new_mid[j] {
+                kept.push((prefix + i, prefix + j));
+                i += 1;
+                j += 1;
+            } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+    }
+    kept.push((old_lines.len() - suffix, new_lines.len() - suffix));
+
+    // Lines between kept lines are replaced
+    let mut edits = Vec::new();
+    let (mut old_i, mut new_i) = (prefix, prefix);
+    for (old_kept, new_kept) in kept {
+        if old_i < old_kept || new_i < new_kept {
+            edits.push(TextEdit {
+                start: line_start(&old_lines, old_i),
+                end: line_start(&old_lines, old_kept),
+                text: new_lines[new_i..new_kept].concat(),
+            });
+        }
+        old_i = old_kept + 1;
+        new_i = new_kept + 1;
+    }
+    edits
+}
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+// Bonus for matches at the start of the text or of a word
+const BONUS_BOUNDARY: i64 = 12;
+// Bonus for matches directly after the previous match
+const BONUS_CONSECUTIVE: i64 = 8;
+// Bonus for matches in the file name rather than the folders
+const BONUS_FILE_NAME: i64 = 4;
+
+/// Score how well `query` matches `text` as a case insensitive subsequence, higher is better
+pub fn score(query: &str, text: &str) -> Option<i64> {
+    let mut query_chars = query.chars().flat_map(char::to_lowercase).peekable();
+    if query_chars.peek().is_none() {
+        return Some(0);
+    }
+
+    let file_name_start = text.rfind('/').map_or(0, |i| i + 1);
+    let mut score = 0;
+    let mut started = false;
+    let mut prev_opt: Option<char> = None;
+    let mut prev_matched = false;
+    for (i, c) in text.char_indices() {
+        let Some(&query_c) = query_chars.peek() else {
+            break;
+        };
+
+        let matched = c.to_lowercase().eq(std::iter::once(query_c));
+        if matched {
+            query_chars.next();
+            started = true;
+            score += 1;
+            if prev_matched {
+                score += BONUS_CONSECUTIVE;
+            }
+            match prev_opt {
+                None => score += BONUS_BOUNDARY,
+                Some('/' | '_' | '-' | '.' | ' ') => score += BONUS_BOUNDARY,
+                Some(prev) if prev.is_lowercase() && c.is_uppercase() => score += BONUS_BOUNDARY,
+                _ => {}
+            }
+            if i >= file_name_start {
+                score += BONUS_FILE_NAME;
+            }
+        } else if started {
+            // Penalty for gaps between matches
+            score -= 1;
+        }
+
+        prev_matched = matched;
+        prev_opt = Some(c);
+    }
+
+    if query_chars.peek().is_some() {
+        return None;
+    }
+
+    // Prefer shorter paths when other things are equal
+    Some(score - (text.len() / 8) as i64)
+}
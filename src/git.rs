@@ -1,10 +1,14 @@
 //TODO: try to use gitoxide
 
 use std::{
+    collections::HashMap,
+    ffi::OsString,
     fs, io,
     path::{Path, PathBuf},
+    process::{self, Stdio},
+    sync::atomic::{AtomicUsize, Ordering},
 };
-use tokio::process::Command;
+use tokio::{io::AsyncWriteExt, process::Command};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct GitDiff {
@@ -13,6 +17,75 @@ pub struct GitDiff {
     pub hunks: Vec<GitDiffHunk>,
 }
 
+impl GitDiff {
+    /// Lines of the new text, counting from zero, that were added or changed. Lines that were
+    /// only deleted mark the line after them.
+    pub fn changed_lines(&self) -> Vec<usize> {
+        let mut changed_lines = Vec::new();
+        for hunk in self.hunks.iter() {
+            let mut added = false;
+            for line in hunk.lines.iter() {
+                if let GitDiffLine::Added { new_line, .. } = line {
+                    changed_lines.push(new_line.saturating_sub(1) as usize);
+                    added = true;
+                }
+            }
+            if !added {
+                // A range with no lines starts at the line before the deletion
+                changed_lines.push(hunk.new_range.start as usize);
+            }
+        }
+        changed_lines
+    }
+
+    /// Lines of the new text, counting from zero, that were added, modified, or have deleted
+    /// lines before them, sorted by line
+    pub fn line_changes(&self) -> Vec<(usize, GitLineChange)> {
+        let mut changes = Vec::new();
+        for hunk in self.hunks.iter() {
+            // Deleted lines that were not replaced by added lines yet
+            let mut deleted = 0;
+            let mut next_line = hunk.new_lines().0;
+            for line in hunk.lines.iter() {
+                match line {
+                    GitDiffLine::Deleted { .. } => deleted += 1,
+                    GitDiffLine::Added { new_line, .. } => {
+                        let line_i = new_line.saturating_sub(1) as usize;
+                        if deleted > 0 {
+                            deleted -= 1;
+                            changes.push((line_i, GitLineChange::Modified));
+                        } else {
+                            changes.push((line_i, GitLineChange::Added));
+                        }
+                        next_line = line_i + 1;
+                    }
+                    GitDiffLine::Context { new_line, .. } => {
+                        let line_i = new_line.saturating_sub(1) as usize;
+                        if deleted > 0 {
+                            deleted = 0;
+                            changes.push((line_i, GitLineChange::Deleted));
+                        }
+                        next_line = line_i + 1;
+                    }
+                }
+            }
+            if deleted > 0 {
+                changes.push((next_line, GitLineChange::Deleted));
+            }
+        }
+        changes
+    }
+}
+
+/// How a line differs from the committed file
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum GitLineChange {
+    Added,
+    Modified,
+    /// Lines were deleted before this line
+    Deleted,
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct GitDiffHunk {
     pub old_range: patch::Range,
@@ -20,6 +93,81 @@ pub struct GitDiffHunk {
     pub lines: Vec<GitDiffLine>,
 }
 
+impl GitDiffHunk {
+    /// First line of the new text in the hunk counting from zero, and the number of lines
+    pub fn new_lines(&self) -> (usize, usize) {
+        // A range with no lines starts at the line before the hunk
+        let first = if self.new_range.count == 0 {
+            self.new_range.start
+        } else {
+            self.new_range.start.saturating_sub(1)
+        };
+        (first as usize, self.new_range.count as usize)
+    }
+
+    /// First line of the old text in the hunk counting from zero, and the number of lines
+    pub fn old_lines(&self) -> (usize, usize) {
+        let first = if self.old_range.count == 0 {
+            self.old_range.start
+        } else {
+            self.old_range.start.saturating_sub(1)
+        };
+        (first as usize, self.old_range.count as usize)
+    }
+
+    /// True if a line of the new text, counting from zero, is in the hunk or right after it
+    pub fn contains_line(&self, line: usize) -> bool {
+        let (first, count) = self.new_lines();
+        line >= first && line <= first + count
+    }
+
+    /// Text of the hunk before the change
+    pub fn old_text(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                GitDiffLine::Context { text, .. } | GitDiffLine::Deleted { text, .. } => {
+                    Some(text.as_str())
+                }
+                GitDiffLine::Added { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Text of the hunk after the change
+    pub fn new_text(&self) -> Vec<&str> {
+        self.lines
+            .iter()
+            .filter_map(|line| match line {
+                GitDiffLine::Context { text, .. } | GitDiffLine::Added { text, .. } => {
+                    Some(text.as_str())
+                }
+                GitDiffLine::Deleted { .. } => None,
+            })
+            .collect()
+    }
+
+    // The hunk as a patch for a file, in the format read by git apply
+    fn patch(&self, relative_path: &Path) -> String {
+        let path = relative_path.to_string_lossy();
+        let mut patch = format!(
+            "--- a/{path}\n+++ b/{path}\n@@ -{},{} +{},{} @@\n",
+            self.old_range.start, self.old_range.count, self.new_range.start, self.new_range.count
+        );
+        for line in self.lines.iter() {
+            let (prefix, text) = match line {
+                GitDiffLine::Context { text, .. } => (' ', text),
+                GitDiffLine::Added { text, .. } => ('+', text),
+                GitDiffLine::Deleted { text, .. } => ('-', text),
+            };
+            patch.push(prefix);
+            patch.push_str(text);
+            patch.push('\n');
+        }
+        patch
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum GitDiffLine {
     Context {
@@ -80,6 +228,235 @@ impl TryFrom<char> for GitStatusKind {
     }
 }
 
+/// The commit that last changed each line of a file
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GitBlame {
+    pub commits: Vec<GitBlameCommit>,
+    /// Index of the commit of each line
+    pub lines: Vec<usize>,
+}
+
+impl GitBlame {
+    /// Date, author, and summary of the commit of a line, if it is the first line of the lines
+    /// from that commit
+    pub fn annotation(&self, line: usize) -> Option<String> {
+        let commit_i = *self.lines.get(line)?;
+        if line > 0 && self.lines.get(line - 1) == Some(&commit_i) {
+            return None;
+        }
+        let commit = self.commits.get(commit_i)?;
+        Some(format!(
+            "{} {} {}",
+            format_date(commit.time),
+            commit.author,
+            commit.summary
+        ))
+    }
+}
+
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct GitBlameCommit {
+    pub author: String,
+    /// Seconds since the Unix epoch
+    pub time: i64,
+    pub summary: String,
+}
+
+// Format seconds since the Unix epoch as a UTC date like 2024-01-31
+fn format_date(time: i64) -> String {
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = time.div_euclid(86400) + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+// https://git-scm.com/docs/git-blame#_the_porcelain_format
+fn parse_blame(stdout: &str) -> GitBlame {
+    let mut blame = GitBlame::default();
+    let mut commit_indices = HashMap::new();
+    let mut current_opt = None;
+    for line in stdout.lines() {
+        if line.starts_with('\t') {
+            // The text of the line ends its entry
+            current_opt = None;
+            continue;
+        }
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        if current_opt.is_none() {
+            // Entries start with the commit, the original line, and the final line
+            let mut parts = value.split(' ');
+            let (Some(_orig_line), Some(Ok(final_line))) =
+                (parts.next(), parts.next().map(str::parse::<usize>))
+            else {
+                log::warn!("invalid git blame line {:?}", line);
+                continue;
+            };
+            let commit_i = *commit_indices.entry(key.to_string()).or_insert_with(|| {
+                blame.commits.push(GitBlameCommit::default());
+                blame.commits.len() - 1
+            });
+            let line_i = final_line.saturating_sub(1);
+            if blame.lines.len() <= line_i {
+                blame.lines.resize(line_i + 1, commit_i);
+            }
+            blame.lines[line_i] = commit_i;
+            current_opt = Some(commit_i);
+            continue;
+        }
+        let Some(commit) = current_opt.and_then(|commit_i| blame.commits.get_mut(commit_i)) else {
+            continue;
+        };
+        match key {
+            "author" => commit.author = value.to_string(),
+            "author-time" => commit.time = value.parse().unwrap_or_default(),
+            "summary" => commit.summary = value.to_string(),
+            _ => {}
+        }
+    }
+    blame
+}
+
+fn parse_diff(path: &Path, staged: bool, diff: &str) -> io::Result<GitDiff> {
+    let patch = patch::Patch::from_single(diff).map_err(|err| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("failed to parse diff: {}", err),
+        )
+    })?;
+
+    let mut hunks = Vec::with_capacity(patch.hunks.len());
+    for hunk in patch.hunks.iter() {
+        //TODO: validate range counts
+        let mut old_line = hunk.old_range.start;
+        let mut new_line = hunk.new_range.start;
+
+        let mut lines = Vec::with_capacity(hunk.lines.len());
+        for line in hunk.lines.iter() {
+            match line {
+                patch::Line::Context(text) => {
+                    lines.push(GitDiffLine::Context {
+                        old_line,
+                        new_line,
+                        text: text.to_string(),
+                    });
+                    old_line += 1;
+                    new_line += 1;
+                }
+                patch::Line::Add(text) => {
+                    lines.push(GitDiffLine::Added {
+                        new_line,
+                        text: text.to_string(),
+                    });
+                    new_line += 1;
+                }
+                patch::Line::Remove(text) => {
+                    lines.push(GitDiffLine::Deleted {
+                        old_line,
+                        text: text.to_string(),
+                    });
+                    old_line += 1;
+                }
+            }
+        }
+
+        hunks.push(GitDiffHunk {
+            old_range: hunk.old_range.clone(),
+            new_range: hunk.new_range.clone(),
+            lines,
+        });
+    }
+
+    Ok(GitDiff {
+        path: path.to_path_buf(),
+        staged,
+        hunks,
+    })
+}
+
+/// Compare `text` with the file at `path`, in the same format as a git diff
+pub async fn diff_text(path: &Path, text: &str) -> io::Result<GitDiff> {
+    diff_stdin(path, text, false).await
+}
+
+/// Compare the file at `path` with `text`, so that new lines are the lines of `text`
+pub async fn diff_file_text(path: &Path, text: &str) -> io::Result<GitDiff> {
+    diff_stdin(path, text, true).await
+}
+
+/// Compare two files without context lines, so that each hunk is one change
+pub async fn diff_files(old_path: &Path, new_path: &Path) -> io::Result<GitDiff> {
+    let mut command = Command::new("diff");
+    command
+        .arg("--unified=0")
+        .arg("--label")
+        .arg("a")
+        .arg("--label")
+        .arg("b")
+        .arg(old_path)
+        .arg(new_path);
+    log::info!("{:?}", command);
+    let output = command.output().await?;
+    diff_output(new_path, output)
+}
+
+async fn diff_stdin(path: &Path, text: &str, text_is_new: bool) -> io::Result<GitDiff> {
+    let mut command = Command::new("diff");
+    command
+        .arg("--unified")
+        .arg("--label")
+        .arg("a")
+        .arg("--label")
+        .arg("b");
+    if text_is_new {
+        command.arg(path).arg("-");
+    } else {
+        command.arg("-").arg(path);
+    }
+    command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    log::info!("{:?}", command);
+    let mut child = command.spawn()?;
+    // diff reads all input before writing output, so this cannot block on a full stdout pipe
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    diff_output(path, output)
+}
+
+fn diff_output(path: &Path, output: process::Output) -> io::Result<GitDiff> {
+    match output.status.code() {
+        // No differences
+        Some(0) => Ok(GitDiff {
+            path: path.to_path_buf(),
+            staged: false,
+            hunks: Vec::new(),
+        }),
+        Some(1) => parse_diff(path, false, &String::from_utf8_lossy(&output.stdout)),
+        _ => Err(io::Error::other(format!(
+            "diff exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))),
+    }
+}
+
 pub struct GitRepository {
     path: PathBuf,
 }
@@ -98,6 +475,56 @@ impl GitRepository {
         }
     }
 
+    /// Find the repository that contains `path`
+    pub fn discover(path: &Path) -> io::Result<Self> {
+        match path.ancestors().find(|dir| dir.join(".git").exists()) {
+            Some(dir) => Self::new(dir),
+            None => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{:?} is not in a git repository", path),
+            )),
+        }
+    }
+
+    /// Contents of a file at HEAD, or None if it is not committed
+    pub async fn head_bytes(&self, path: &Path) -> io::Result<Option<Vec<u8>>> {
+        let path = fs::canonicalize(path)?;
+        let Ok(relative) = path.strip_prefix(&self.path) else {
+            return Ok(None);
+        };
+        let mut spec = OsString::from("HEAD:");
+        spec.push(relative);
+        let mut command = self.command();
+        command.arg("show").arg(spec);
+        let output = command.output().await?;
+        if output.status.success() {
+            Ok(Some(output.stdout))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Compare a file at HEAD with `text`, so that new lines are the lines of `text`. Returns
+    /// None if the file is not committed.
+    pub async fn diff_head_text(&self, path: &Path, text: &str) -> io::Result<Option<GitDiff>> {
+        static TEMP_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+        let Some(head_bytes) = self.head_bytes(path).await? else {
+            return Ok(None);
+        };
+        let temp_path = std::env::temp_dir().join(format!(
+            "cosmic-edit-head-{}-{}",
+            process::id(),
+            TEMP_COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::write(&temp_path, head_bytes)?;
+        let res = diff_file_text(&temp_path, text).await;
+        if let Err(err) = fs::remove_file(&temp_path) {
+            log::warn!("failed to remove {:?}: {}", temp_path, err);
+        }
+        res.map(Some)
+    }
+
     fn command(&self) -> Command {
         let mut command = Command::new("git");
         command.arg("-C").arg(&self.path);
@@ -107,6 +534,25 @@ impl GitRepository {
     async fn command_stdout(mut command: Command) -> io::Result<String> {
         log::info!("{:?}", command);
         let output = command.output().await?;
+        Self::output_stdout(output)
+    }
+
+    async fn command_stdin_stdout(mut command: Command, input: &[u8]) -> io::Result<String> {
+        command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        log::info!("{:?}", command);
+        let mut child = command.spawn()?;
+        // Git reads all input before writing output, so this cannot block on a full stdout pipe
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(input).await?;
+        }
+        let output = child.wait_with_output().await?;
+        Self::output_stdout(output)
+    }
+
+    fn output_stdout(output: process::Output) -> io::Result<String> {
         if output.status.success() {
             String::from_utf8(output.stdout).map_err(|err| {
                 io::Error::new(
@@ -137,60 +583,7 @@ impl GitRepository {
         }
         command.arg("--").arg(path);
         let diff = Self::command_stdout(command).await?;
-        let patch = patch::Patch::from_single(&diff).map_err(|err| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("failed to parse diff: {}", err),
-            )
-        })?;
-
-        let mut hunks = Vec::with_capacity(patch.hunks.len());
-        for hunk in patch.hunks.iter() {
-            //TODO: validate range counts
-            let mut old_line = hunk.old_range.start;
-            let mut new_line = hunk.new_range.start;
-
-            let mut lines = Vec::with_capacity(hunk.lines.len());
-            for line in hunk.lines.iter() {
-                match line {
-                    patch::Line::Context(text) => {
-                        lines.push(GitDiffLine::Context {
-                            old_line,
-                            new_line,
-                            text: text.to_string(),
-                        });
-                        old_line += 1;
-                        new_line += 1;
-                    }
-                    patch::Line::Add(text) => {
-                        lines.push(GitDiffLine::Added {
-                            new_line,
-                            text: text.to_string(),
-                        });
-                        new_line += 1;
-                    }
-                    patch::Line::Remove(text) => {
-                        lines.push(GitDiffLine::Deleted {
-                            old_line,
-                            text: text.to_string(),
-                        });
-                        old_line += 1;
-                    }
-                }
-            }
-
-            hunks.push(GitDiffHunk {
-                old_range: hunk.old_range.clone(),
-                new_range: hunk.new_range.clone(),
-                lines,
-            });
-        }
-
-        Ok(GitDiff {
-            path: path.to_path_buf(),
-            staged,
-            hunks,
-        })
+        parse_diff(path, staged, &diff)
     }
 
     pub async fn status(&self) -> io::Result<Vec<GitStatus>> {
@@ -272,6 +665,35 @@ impl GitRepository {
         Ok(())
     }
 
+    /// Apply one hunk of a file to the index, leaving the rest of its changes unstaged
+    pub async fn stage_hunk(&self, path: &Path, hunk: &GitDiffHunk) -> io::Result<()> {
+        let path = fs::canonicalize(path)?;
+        let relative = path.strip_prefix(&self.path).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{:?} is not in {:?}", path, self.path),
+            )
+        })?;
+        let mut command = self.command();
+        command.arg("apply").arg("--cached").arg("-");
+        Self::command_stdin_stdout(command, hunk.patch(relative).as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Find the commit that last changed each line of `text`, which is the edited file at `path`
+    pub async fn blame_text(&self, path: &Path, text: &str) -> io::Result<GitBlame> {
+        let mut command = self.command();
+        command
+            .arg("blame")
+            .arg("--porcelain")
+            .arg("--contents")
+            .arg("-")
+            .arg("--")
+            .arg(path);
+        let stdout = Self::command_stdin_stdout(command, text.as_bytes()).await?;
+        Ok(parse_blame(&stdout))
+    }
+
     pub async fn unstage<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
         let path = path.as_ref();
         let mut command = self.command();
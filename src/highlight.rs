@@ -0,0 +1,285 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::widget::segmented_button::Entity;
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    ops::Range,
+    sync::mpsc,
+};
+use syntect::{
+    highlighting::{HighlightState, Highlighter, RangedHighlightIterator, Style},
+    parsing::{ParseState, ScopeStack},
+};
+
+use crate::syntax_system;
+
+// Lines highlighted before results are sent and newer requests are checked
+const BATCH_LINES: usize = 500;
+
+/// Lines of a tab that changed since its previous request, the worker keeps the other lines and
+/// the parse states they end with
+pub struct Request {
+    pub entity: Entity,
+    pub syntax: String,
+    pub theme: String,
+    /// Highlight every line, used when the editor replaced the colors of all lines
+    pub reset: bool,
+    /// First line that changed
+    pub start: usize,
+    /// Number of lines of the previous request that are replaced by `lines`
+    pub removed: usize,
+    pub lines: Vec<String>,
+}
+
+/// Colors of a changed line, which only apply if the line still has the same text
+#[derive(Clone, Debug)]
+pub struct LineSpans {
+    pub line_i: usize,
+    pub text_hash: u64,
+    pub spans: Vec<(Range<usize>, Style)>,
+}
+
+pub fn text_hash(text: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    text.hash(&mut hasher);
+    hasher.finish()
+}
+
+pub enum Job {
+    Highlight(Request),
+    Close(Entity),
+}
+
+/// Handle to the highlighting thread, which is started by a subscription
+#[derive(Clone, Debug)]
+pub struct Worker {
+    sender: mpsc::Sender<Job>,
+}
+
+impl Worker {
+    pub fn highlight(&self, request: Request) {
+        if self.sender.send(Job::Highlight(request)).is_err() {
+            log::warn!("highlighting thread has stopped");
+        }
+    }
+
+    /// Forget the cached states of a closed tab
+    pub fn close(&self, entity: Entity) {
+        let _ = self.sender.send(Job::Close(entity));
+    }
+}
+
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Highlight(request) => f.debug_tuple("Highlight").field(&request.entity).finish(),
+            Self::Close(entity) => f.debug_tuple("Close").field(entity).finish(),
+        }
+    }
+}
+
+// State at the end of a line, which the next line starts from
+type LineState = (ParseState, ScopeStack);
+
+#[derive(Default)]
+struct Cache {
+    syntax: String,
+    theme: String,
+    // Text of every line, as of the newest request
+    texts: Vec<String>,
+    // State of each line when it was last highlighted
+    states: Vec<Option<LineState>>,
+    // Lines that changed since they were highlighted
+    dirty_opt: Option<Range<usize>>,
+}
+
+impl Cache {
+    // Replace the lines that changed, the states of the lines after them are kept so that
+    // highlighting can stop where the states are the same as before
+    fn apply(&mut self, request: Request) {
+        let reset = request.reset || self.syntax != request.syntax || self.theme != request.theme;
+        if reset {
+            if self.theme != request.theme
+                && !syntax_system()
+                    .theme_set
+                    .themes
+                    .contains_key(&request.theme)
+            {
+                log::warn!("failed to find syntax theme {:?}", request.theme);
+            }
+            self.syntax = request.syntax;
+            self.theme = request.theme;
+        }
+
+        let end = request
+            .start
+            .saturating_add(request.removed)
+            .min(self.texts.len());
+        let start = request.start.min(end);
+        if end - start != request.removed {
+            log::warn!(
+                "highlight request removes lines {}..{} of {}",
+                request.start,
+                request.start.saturating_add(request.removed),
+                self.texts.len()
+            );
+        }
+        let removed = end - start;
+        let inserted = request.lines.len();
+        self.texts.splice(start..end, request.lines);
+        self.states.splice(start..end, (0..inserted).map(|_| None));
+
+        // Lines after the change moved by the number of lines added or removed
+        let shift = |line: usize| {
+            if line >= end {
+                line + inserted - removed
+            } else {
+                line.min(start + inserted)
+            }
+        };
+        self.dirty_opt = Some(if reset {
+            self.states.fill(None);
+            0..self.texts.len()
+        } else {
+            match self.dirty_opt.take() {
+                Some(dirty) => dirty.start.min(start)..shift(dirty.end).max(start + inserted),
+                None => start..start + inserted,
+            }
+        });
+    }
+
+    // Highlight the next batch of changed lines, returns true when all lines are highlighted
+    fn highlight_batch(&mut self, spans: &mut Vec<LineSpans>) -> bool {
+        let Some(dirty) = self.dirty_opt.take() else {
+            return true;
+        };
+        let syntax_system = syntax_system();
+        let syntax_set = &syntax_system.syntax_set;
+        let syntax = syntax_set
+            .find_syntax_by_name(&self.syntax)
+            .unwrap_or_else(|| syntax_set.find_syntax_plain_text());
+        let highlighter_opt = syntax_system
+            .theme_set
+            .themes
+            .get(&self.theme)
+            .map(Highlighter::new);
+
+        let end = (dirty.start + BATCH_LINES).min(self.texts.len());
+        for line_i in dirty.start..end {
+            let text = &self.texts[line_i];
+            let (mut parse_state, scope_stack) = match line_i.checked_sub(1) {
+                Some(prev_i) => self.states[prev_i]
+                    .clone()
+                    .unwrap_or_else(|| (ParseState::new(syntax), ScopeStack::new())),
+                None => (ParseState::new(syntax), ScopeStack::new()),
+            };
+            let ops = match parse_state.parse_line(text, syntax_set) {
+                Ok(ok) => ok,
+                Err(err) => {
+                    log::warn!("failed to parse line {}: {}", line_i + 1, err);
+                    Vec::new()
+                }
+            };
+            let mut ranges = Vec::new();
+            let scope_stack = match &highlighter_opt {
+                Some(highlighter) => {
+                    let mut highlight_state = HighlightState::new(highlighter, scope_stack);
+                    for (style, _text, range) in
+                        RangedHighlightIterator::new(&mut highlight_state, &ops, text, highlighter)
+                    {
+                        ranges.push((range, style));
+                    }
+                    highlight_state.path
+                }
+                None => scope_stack,
+            };
+            spans.push(LineSpans {
+                line_i,
+                text_hash: text_hash(text),
+                spans: ranges,
+            });
+
+            // Lines after the change start from the same state as before, so they keep their
+            // colors
+            let state = (parse_state, scope_stack);
+            let same_state = self.states[line_i].as_ref() == Some(&state);
+            self.states[line_i] = Some(state);
+            if line_i + 1 >= dirty.end && same_state {
+                return true;
+            }
+        }
+
+        if end < self.texts.len() {
+            self.dirty_opt = Some(end..dirty.end.max(end));
+            false
+        } else {
+            true
+        }
+    }
+}
+
+pub fn worker() -> (Worker, mpsc::Receiver<Job>) {
+    let (sender, receiver) = mpsc::channel();
+    (Worker { sender }, receiver)
+}
+
+/// Highlight requests until the worker is dropped or `send` returns false
+pub fn run(receiver: mpsc::Receiver<Job>, mut send: impl FnMut(Entity, Vec<LineSpans>) -> bool) {
+    let mut caches: HashMap<Entity, Cache> = HashMap::new();
+    let mut queue: Vec<Entity> = Vec::new();
+    loop {
+        if queue.is_empty() {
+            match receiver.recv() {
+                Ok(job) => push_job(&mut caches, &mut queue, job),
+                Err(_) => return,
+            }
+        }
+        // Changes that arrive together are highlighted together
+        while let Ok(job) = receiver.try_recv() {
+            push_job(&mut caches, &mut queue, job);
+        }
+        if queue.is_empty() {
+            continue;
+        }
+
+        let entity = queue.remove(0);
+        loop {
+            let Some(cache) = caches.get_mut(&entity) else {
+                break;
+            };
+            let mut spans = Vec::new();
+            let done = cache.highlight_batch(&mut spans);
+            if !spans.is_empty() && !send(entity, spans) {
+                return;
+            }
+            if done {
+                break;
+            }
+
+            // Stop if the tab changed again, the lines left to highlight are kept in its cache
+            while let Ok(job) = receiver.try_recv() {
+                push_job(&mut caches, &mut queue, job);
+            }
+            if queue.contains(&entity) {
+                break;
+            }
+        }
+    }
+}
+
+fn push_job(caches: &mut HashMap<Entity, Cache>, queue: &mut Vec<Entity>, job: Job) {
+    match job {
+        Job::Highlight(request) => {
+            let entity = request.entity;
+            caches.entry(entity).or_default().apply(request);
+            if !queue.contains(&entity) {
+                queue.push(entity);
+            }
+        }
+        Job::Close(entity) => {
+            caches.remove(&entity);
+            queue.retain(|queued| *queued != entity);
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    env, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::net::{UnixListener, UnixStream},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use crate::remote;
+
+/// Connection of an instance started with `--wait`, which returns once this is finished
+#[derive(Clone, Debug)]
+pub struct Waiter(Arc<UnixStream>);
+
+impl Waiter {
+    pub fn finish(&self) {
+        if let Err(err) = self.0.shutdown(Shutdown::Both) {
+            log::warn!("failed to finish waiting instance: {}", err);
+        }
+    }
+}
+
+// Socket that the first instance listens on for the arguments of later ones
+fn socket_path() -> Option<PathBuf> {
+    dirs::runtime_dir().map(|dir| dir.join("com.system76.CosmicEdit.sock"))
+}
+
+/// Send the arguments to a running instance, returning false if there is none
+pub fn send(args: &[String]) -> io::Result<bool> {
+    let Some(path) = socket_path() else {
+        return Ok(false);
+    };
+    let Ok(mut stream) = UnixStream::connect(&path) else {
+        return Ok(false);
+    };
+
+    // The running instance has its own working directory, so paths are made absolute
+    let cwd = env::current_dir()?;
+    let mut message = String::new();
+    for arg in args {
+        if arg.starts_with('-') || arg.starts_with('+') || remote::is_uri(arg) {
+            message.push_str(arg);
+        } else {
+            message.push_str(&cwd.join(arg).to_string_lossy());
+        }
+        message.push('\n');
+    }
+    stream.write_all(message.as_bytes())?;
+
+    // The running instance closes the connection once the tabs of the files are closed
+    if args.iter().any(|arg| arg == "--wait") {
+        stream.shutdown(Shutdown::Write)?;
+        stream.read_to_end(&mut Vec::new())?;
+    }
+    Ok(true)
+}
+
+/// Receive the arguments sent by later instances, which blocks while this is the first instance
+pub fn listen(mut f: impl FnMut(Vec<String>, Option<Waiter>)) {
+    let Some(path) = socket_path() else {
+        return;
+    };
+    // Windows opened with --new-window leave the socket to the first instance
+    if UnixStream::connect(&path).is_ok() {
+        return;
+    }
+    // The socket of an instance that did not exit properly refuses connections
+    let _ = fs::remove_file(&path);
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            log::warn!("failed to listen on {:?}: {}", path, err);
+            return;
+        }
+    };
+    for stream_res in listener.incoming() {
+        match stream_res {
+            Ok(stream) => {
+                let args_res: io::Result<Vec<String>> = BufReader::new(&stream).lines().collect();
+                match args_res {
+                    Ok(args) => {
+                        let waiter_opt = args
+                            .iter()
+                            .any(|arg| arg == "--wait")
+                            .then(|| Waiter(Arc::new(stream)));
+                        f(args, waiter_opt)
+                    }
+                    Err(err) => {
+                        log::warn!("failed to read arguments from {:?}: {}", path, err);
+                    }
+                }
+            }
+            Err(err) => {
+                log::warn!("failed to accept connection on {:?}: {}", path, err);
+            }
+        }
+    }
+}
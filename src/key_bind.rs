@@ -1,18 +1,262 @@
 use cosmic::widget::menu::key_bind::{KeyBind, Modifier};
-use cosmic::{iced::keyboard::Key, iced_core::keyboard::key::Named};
-use std::collections::HashMap;
+use cosmic::{
+    iced::keyboard::{Key, Modifiers},
+    iced_core::keyboard::key::Named,
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs, io, path::Path};
 
-use crate::Action;
+use crate::{Action, fl};
 
-//TODO: load from config
-pub fn key_binds() -> HashMap<KeyBind, Action> {
+/// Preset that changes the default key bindings to be like another editor's
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub enum Keymap {
+    #[default]
+    Cosmic,
+    Emacs,
+    Sublime,
+    VsCode,
+}
+
+impl Keymap {
+    pub fn all() -> &'static [Self] {
+        &[Self::Cosmic, Self::VsCode, Self::Sublime, Self::Emacs]
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            Self::Cosmic => fl!("keymap-cosmic"),
+            Self::Emacs => "Emacs".to_string(),
+            Self::Sublime => "Sublime Text".to_string(),
+            Self::VsCode => "Visual Studio Code".to_string(),
+        }
+    }
+
+    fn preset(&self) -> KeyBindsFile {
+        let data = match self {
+            Self::Cosmic => return KeyBindsFile::default(),
+            Self::Emacs => include_str!("../res/keymaps/emacs.json"),
+            Self::Sublime => include_str!("../res/keymaps/sublime.json"),
+            Self::VsCode => include_str!("../res/keymaps/vscode.json"),
+        };
+        serde_json::from_str(data).unwrap_or_else(|err| {
+            log::error!("failed to parse keymap {:?}: {}", self, err);
+            KeyBindsFile::default()
+        })
+    }
+}
+
+/// Changes to the default key bindings, in the format of the preset keymaps and of exported
+/// key bindings
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct KeyBindsFile {
+    /// Default shortcuts that are turned off
+    #[serde(default)]
+    pub removed: Vec<String>,
+    /// Shortcuts that replace or add to the default key bindings
+    #[serde(default)]
+    pub keybinds: HashMap<String, Action>,
+}
+
+impl KeyBindsFile {
+    pub fn read(path: &Path) -> io::Result<Self> {
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        fs::write(path, data)
+    }
+}
+
+// Names of the special keys in shortcuts
+const NAMED_KEYS: &[(&str, Named)] = &[
+    ("Up", Named::ArrowUp),
+    ("Down", Named::ArrowDown),
+    ("Left", Named::ArrowLeft),
+    ("Right", Named::ArrowRight),
+    ("Backspace", Named::Backspace),
+    ("Delete", Named::Delete),
+    ("End", Named::End),
+    ("Enter", Named::Enter),
+    ("Escape", Named::Escape),
+    ("Home", Named::Home),
+    ("Insert", Named::Insert),
+    ("PageDown", Named::PageDown),
+    ("PageUp", Named::PageUp),
+    ("Space", Named::Space),
+    ("Tab", Named::Tab),
+    ("F1", Named::F1),
+    ("F2", Named::F2),
+    ("F3", Named::F3),
+    ("F4", Named::F4),
+    ("F5", Named::F5),
+    ("F6", Named::F6),
+    ("F7", Named::F7),
+    ("F8", Named::F8),
+    ("F9", Named::F9),
+    ("F10", Named::F10),
+    ("F11", Named::F11),
+    ("F12", Named::F12),
+];
+
+/// Parse a shortcut like `Ctrl+Shift+K` or `Alt+Up`
+pub fn parse_key_bind(shortcut: &str) -> Option<KeyBind> {
+    let mut modifiers = Vec::new();
+    let mut parts = shortcut.split('+').map(str::trim).peekable();
+    let mut key_name = None;
+    while let Some(part) = parts.next() {
+        if parts.peek().is_none() {
+            key_name = Some(part);
+            break;
+        }
+        modifiers.push(match part.to_lowercase().as_str() {
+            "super" | "logo" => Modifier::Super,
+            "ctrl" | "control" => Modifier::Ctrl,
+            "alt" => Modifier::Alt,
+            "shift" => Modifier::Shift,
+            _ => return None,
+        });
+    }
+    let key_name = key_name.filter(|name| !name.is_empty())?;
+
+    let named = NAMED_KEYS
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key_name))
+        .map(|(_, named)| *named);
+    let key = match named {
+        Some(named) => Key::Named(named),
+        // The plus key cannot be written as itself, since it separates the keys
+        None if key_name.eq_ignore_ascii_case("plus") => Key::Character("+".into()),
+        None => {
+            let mut chars = key_name.chars();
+            let c = chars.next()?;
+            if chars.next().is_some() {
+                return None;
+            }
+            // Letters are uppercase while Shift is held, like in the default bindings
+            if modifiers.contains(&Modifier::Shift) {
+                Key::Character(c.to_uppercase().to_string().into())
+            } else {
+                Key::Character(c.to_lowercase().to_string().into())
+            }
+        }
+    };
+    Some(KeyBind { modifiers, key })
+}
+
+/// Shortcut of a key binding in the format of [`parse_key_bind`]
+pub fn shortcut(key_bind: &KeyBind) -> Option<String> {
+    let mut shortcut = String::new();
+    for modifier in key_bind.modifiers.iter() {
+        shortcut.push_str(match modifier {
+            Modifier::Super => "Super",
+            Modifier::Ctrl => "Ctrl",
+            Modifier::Alt => "Alt",
+            Modifier::Shift => "Shift",
+        });
+        shortcut.push('+');
+    }
+    match &key_bind.key {
+        Key::Named(named) => {
+            let (name, _) = NAMED_KEYS.iter().find(|(_, key)| key == named)?;
+            shortcut.push_str(name);
+        }
+        Key::Character(c) if c.as_str() == "+" => shortcut.push_str("Plus"),
+        Key::Character(c) => shortcut.push_str(&c.to_uppercase()),
+        _ => return None,
+    }
+    Some(shortcut)
+}
+
+/// Key binding of a pressed key, if it is a key that shortcuts can use
+pub fn pressed_key_bind(modifiers: Modifiers, key: &Key) -> Option<KeyBind> {
+    let mut key_bind_modifiers = Vec::new();
+    if modifiers.logo() {
+        key_bind_modifiers.push(Modifier::Super);
+    }
+    if modifiers.control() {
+        key_bind_modifiers.push(Modifier::Ctrl);
+    }
+    if modifiers.alt() {
+        key_bind_modifiers.push(Modifier::Alt);
+    }
+    if modifiers.shift() {
+        key_bind_modifiers.push(Modifier::Shift);
+    }
+    let key_bind = KeyBind {
+        modifiers: key_bind_modifiers,
+        key: key.clone(),
+    };
+    shortcut(&key_bind)?;
+    Some(key_bind)
+}
+
+/// Shortcuts to save in the config so that the key bindings are `key_binds`, which are the ones
+/// that differ from the keymap and the ones of the keymap that were removed
+pub fn customized(
+    key_binds: &HashMap<KeyBind, Action>,
+    keymap: Keymap,
+) -> (HashMap<String, Action>, Vec<String>) {
+    let defaults = self::key_binds(keymap, &HashMap::new(), &[]);
+    let custom = key_binds
+        .iter()
+        .filter(|(key_bind, action)| defaults.get(key_bind) != Some(action))
+        .filter_map(|(key_bind, action)| Some((shortcut(key_bind)?, *action)))
+        .collect();
+    let mut removed: Vec<String> = defaults
+        .keys()
+        .filter(|key_bind| !key_binds.contains_key(key_bind))
+        .filter_map(shortcut)
+        .collect();
+    removed.sort();
+    (custom, removed)
+}
+
+// Remove the shortcuts in `removed`, then add the ones in `custom`
+fn customize(
+    key_binds: &mut HashMap<KeyBind, Action>,
+    custom: &HashMap<String, Action>,
+    removed: &[String],
+) {
+    for shortcut in removed.iter() {
+        match parse_key_bind(shortcut) {
+            Some(key_bind) => {
+                key_binds.remove(&key_bind);
+            }
+            None => {
+                log::warn!("failed to parse key binding {:?}", shortcut);
+            }
+        }
+    }
+
+    for (shortcut, action) in custom.iter() {
+        match parse_key_bind(shortcut) {
+            Some(key_bind) => {
+                key_binds.insert(key_bind, *action);
+            }
+            None => {
+                log::warn!("failed to parse key binding {:?}", shortcut);
+            }
+        }
+    }
+}
+
+/// Default key bindings changed by a keymap, without the shortcuts in `removed`, and replaced or
+/// extended by the shortcuts in `custom`
+pub fn key_binds(
+    keymap: Keymap,
+    custom: &HashMap<String, Action>,
+    removed: &[String],
+) -> HashMap<KeyBind, Action> {
     let mut key_binds = HashMap::new();
 
     macro_rules! bind {
-        ([$($modifier:ident),+ $(,)?], $key:expr, $action:ident) => {{
+        ([$($modifier:ident),* $(,)?], $key:expr, $action:ident) => {{
             key_binds.insert(
                 KeyBind {
-                    modifiers: vec![$(Modifier::$modifier),+],
+                    modifiers: vec![$(Modifier::$modifier),*],
                     key: $key,
                 },
                 Action::$action,
@@ -20,21 +264,52 @@ pub fn key_binds() -> HashMap<KeyBind, Action> {
         }};
     }
 
+    bind!([Alt, Shift], Key::Named(Named::ArrowUp), AddCaretAbove);
+    bind!([Alt, Shift], Key::Named(Named::ArrowDown), AddCaretBelow);
+    bind!([Ctrl], Key::Character("d".into()), AddNextOccurrence);
     bind!([Ctrl], Key::Character("w".into()), CloseFile);
+    bind!([Ctrl, Shift], Key::Character("P".into()), CommandPalette);
     bind!([Ctrl], Key::Character("x".into()), Cut);
     bind!([Ctrl], Key::Character("c".into()), Copy);
+    bind!([Ctrl, Shift], Key::Character("K".into()), DeleteLine);
+    bind!([Ctrl, Shift], Key::Character("D".into()), DuplicateLine);
     bind!([Ctrl], Key::Character("f".into()), Find);
     bind!([Ctrl], Key::Character("h".into()), FindAndReplace);
+    bind!([Shift], Key::Named(Named::F12), FindReferences);
+    bind!([Ctrl, Shift], Key::Character("I".into()), FormatDocument);
+    bind!([], Key::Named(Named::F12), GoToDefinition);
+    bind!([Ctrl], Key::Character("m".into()), GoToMatchingBracket);
+    bind!([Ctrl], Key::Character("r".into()), GoToSymbol);
+    bind!([Ctrl], Key::Character("j".into()), JoinLines);
+    bind!([Alt], Key::Named(Named::ArrowDown), MoveLineDown);
+    bind!([Alt], Key::Named(Named::ArrowUp), MoveLineUp);
+    bind!([Alt], Key::Named(Named::ArrowLeft), NavigateBack);
+    bind!([Alt], Key::Named(Named::ArrowRight), NavigateForward);
     bind!([Ctrl], Key::Character("v".into()), Paste);
+    bind!([Ctrl, Shift], Key::Character("V".into()), PasteFromHistory);
     bind!([Ctrl], Key::Character("t".into()), NewFile);
     bind!([Ctrl], Key::Character("n".into()), NewWindow);
+    bind!([Ctrl, Shift], Key::Character("N".into()), NewWindow);
+    bind!([], Key::Named(Named::F7), NextDifference);
     bind!([Ctrl], Key::Character("o".into()), OpenFileDialog);
     bind!([Ctrl, Shift], Key::Character("O".into()), OpenProjectDialog);
+    bind!([Ctrl, Shift], Key::Character("E".into()), PlayMacro);
+    bind!([Shift], Key::Named(Named::F7), PreviousDifference);
+    bind!([Ctrl], Key::Character("p".into()), QuickOpen);
     bind!([Ctrl], Key::Character("q".into()), Quit);
     bind!([Ctrl, Shift], Key::Character("Z".into()), Redo);
+    bind!([], Key::Named(Named::F2), RenameSymbol);
+    bind!([Ctrl, Shift], Key::Character("T".into()), ReopenClosedTab);
+    bind!([], Key::Named(Named::F5), RerunTask);
+    bind!([Ctrl, Shift], Key::Character("B".into()), RunTaskDialog);
     bind!([Ctrl], Key::Character("s".into()), Save);
     bind!([Ctrl, Shift], Key::Character("S".into()), SaveAsDialog);
     bind!([Ctrl], Key::Character("a".into()), SelectAll);
+    bind!([Ctrl], Key::Named(Named::Space), ShowCompletions);
+    bind!([Ctrl], Key::Named(Named::F6), SplitFocusNext);
+    bind!([Ctrl, Shift], Key::Character("|".into()), SplitHorizontal);
+    bind!([Ctrl], Key::Character("\\".into()), SplitVertical);
+    bind!([Shift], Key::Named(Named::F5), StopTask);
     // Ctrl+0, Ctrl+-, and Ctrl+= are not special keys for terminals and are free to use
     bind!([Ctrl], Key::Character("0".into()), ZoomReset);
     bind!([Ctrl], Key::Character("-".into()), ZoomOut);
@@ -52,11 +327,18 @@ pub fn key_binds() -> HashMap<KeyBind, Action> {
     bind!([Ctrl], Key::Character("9".into()), TabActivate8);
     bind!([Ctrl], Key::Named(Named::Tab), TabNext);
     bind!([Ctrl, Shift], Key::Named(Named::Tab), TabPrev);
+    bind!([Ctrl], Key::Character("/".into()), ToggleComment);
+    bind!([], Key::Named(Named::F11), ToggleFullscreen);
     bind!(
         [Ctrl, Shift],
         Key::Character("G".into()),
         ToggleGitManagement
     );
+    bind!(
+        [Ctrl, Shift],
+        Key::Character("R".into()),
+        ToggleMacroRecording
+    );
     bind!(
         [Ctrl, Shift],
         Key::Character("F".into()),
@@ -64,7 +346,12 @@ pub fn key_binds() -> HashMap<KeyBind, Action> {
     );
     bind!([Ctrl], Key::Character(",".into()), ToggleSettingsPage);
     bind!([Alt], Key::Character("z".into()), ToggleWordWrap);
+    bind!([Ctrl, Alt], Key::Character("z".into()), ToggleZenMode);
     bind!([Ctrl], Key::Character("z".into()), Undo);
 
+    let preset = keymap.preset();
+    customize(&mut key_binds, &preset.keybinds, &preset.removed);
+    customize(&mut key_binds, custom, removed);
+
     key_binds
 }
@@ -0,0 +1,269 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Cursor, Edit, Selection, ViEditor};
+use std::collections::HashSet;
+
+use crate::{localize::LANGUAGE_SORTER, reindent};
+
+/// First and last line of the selection, or the line of the cursor
+pub fn selected_lines(editor: &ViEditor<'static, 'static>) -> (usize, usize) {
+    match editor.selection_bounds() {
+        // A selection that ends at the start of a line does not include that line
+        Some((start, end)) if end.index == 0 && end.line > start.line => (start.line, end.line - 1),
+        Some((start, end)) => (start.line, end.line),
+        None => {
+            let cursor = editor.cursor();
+            (cursor.line, cursor.line)
+        }
+    }
+}
+
+fn line_count(editor: &ViEditor<'static, 'static>) -> usize {
+    editor.with_buffer(|buffer| buffer.lines.len())
+}
+
+fn line_texts(editor: &ViEditor<'static, 'static>, first: usize, last: usize) -> Vec<String> {
+    editor.with_buffer(|buffer| {
+        buffer.lines[first..=last]
+            .iter()
+            .map(|line| line.text().to_string())
+            .collect()
+    })
+}
+
+// Replace the text of lines `first` to `last` with `lines`, which must not be empty
+fn replace_lines(
+    editor: &mut ViEditor<'static, 'static>,
+    first: usize,
+    last: usize,
+    lines: &[String],
+) {
+    let last_len = editor.with_buffer(|buffer| buffer.lines[last].text().len());
+    let start = Cursor::new(first, 0);
+    editor.delete_range(start, Cursor::new(last, last_len));
+    editor.insert_at(start, &lines.join("\n"), None);
+}
+
+/// Keep a position inside of the buffer and on a character boundary
+pub fn clamp(editor: &ViEditor<'static, 'static>, mut cursor: Cursor) -> Cursor {
+    editor.with_buffer(|buffer| {
+        cursor.line = cursor.line.min(buffer.lines.len().saturating_sub(1));
+        let text = buffer.lines[cursor.line].text();
+        cursor.index = cursor.index.min(text.len());
+        while !text.is_char_boundary(cursor.index) {
+            cursor.index -= 1;
+        }
+    });
+    cursor
+}
+
+/// Change the indentation of every line to tabs or spaces, keeping its width
+pub fn convert_indentation(editor: &mut ViEditor<'static, 'static>, tab_width: usize, tabs: bool) {
+    let changes: Vec<(usize, usize, String)> = editor.with_buffer(|buffer| {
+        buffer
+            .lines
+            .iter()
+            .enumerate()
+            .filter_map(|(line_i, line)| {
+                let (len, indentation) = reindent::convert(line.text(), tab_width, tabs)?;
+                Some((line_i, len, indentation))
+            })
+            .collect()
+    });
+    if changes.is_empty() {
+        return;
+    }
+
+    let cursor = editor.cursor();
+    editor.start_change();
+    for (line_i, len, indentation) in changes {
+        let start = Cursor::new(line_i, 0);
+        editor.delete_range(start, Cursor::new(line_i, len));
+        editor.insert_at(start, &indentation, None);
+    }
+    editor.finish_change();
+    let cursor = clamp(editor, cursor);
+    editor.set_cursor(cursor);
+    editor.set_selection(Selection::None);
+}
+
+// Move the cursor and selection by a number of lines
+fn shift_cursor(
+    editor: &mut ViEditor<'static, 'static>,
+    cursor: Cursor,
+    selection: Selection,
+    lines: isize,
+) {
+    let shift = |mut cursor: Cursor| {
+        cursor.line = cursor.line.saturating_add_signed(lines);
+        cursor
+    };
+    let cursor = clamp(editor, shift(cursor));
+    let selection = match selection {
+        Selection::None => Selection::None,
+        Selection::Normal(anchor) => Selection::Normal(clamp(editor, shift(anchor))),
+        Selection::Line(anchor) => Selection::Line(clamp(editor, shift(anchor))),
+        Selection::Word(anchor) => Selection::Word(clamp(editor, shift(anchor))),
+    };
+    editor.set_cursor(cursor);
+    editor.set_selection(selection);
+}
+
+/// Move the selected lines up or down by one line
+pub fn move_lines(editor: &mut ViEditor<'static, 'static>, up: bool) {
+    let (first, last) = selected_lines(editor);
+    if (up && first == 0) || (!up && last + 1 >= line_count(editor)) {
+        return;
+    }
+    let cursor = editor.cursor();
+    let selection = editor.selection();
+
+    editor.start_change();
+    if up {
+        let mut lines = line_texts(editor, first - 1, last);
+        lines.rotate_left(1);
+        replace_lines(editor, first - 1, last, &lines);
+    } else {
+        let mut lines = line_texts(editor, first, last + 1);
+        lines.rotate_right(1);
+        replace_lines(editor, first, last + 1, &lines);
+    }
+    editor.finish_change();
+    shift_cursor(editor, cursor, selection, if up { -1 } else { 1 });
+}
+
+/// Insert a copy of the selected lines after them
+pub fn duplicate_lines(editor: &mut ViEditor<'static, 'static>) {
+    let (first, last) = selected_lines(editor);
+    let cursor = editor.cursor();
+    let selection = editor.selection();
+    let lines = line_texts(editor, first, last);
+    let end = Cursor::new(last, lines[lines.len() - 1].len());
+
+    editor.start_change();
+    editor.insert_at(end, &format!("\n{}", lines.join("\n")), None);
+    editor.finish_change();
+    // The copy is selected, so that repeating duplicates it again
+    shift_cursor(editor, cursor, selection, lines.len() as isize);
+}
+
+/// Join the selected lines, or the line of the cursor and the next one, with single spaces
+pub fn join_lines(editor: &mut ViEditor<'static, 'static>) {
+    let (first, mut last) = selected_lines(editor);
+    if first == last {
+        if last + 1 >= line_count(editor) {
+            return;
+        }
+        last += 1;
+    }
+    let lines = line_texts(editor, first, last);
+    let mut joined = lines[0].trim_end().to_string();
+    let mut join_index = joined.len();
+    for line in lines[1..].iter() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if !joined.is_empty() {
+            joined.push(' ');
+        }
+        join_index = joined.len();
+        joined.push_str(line);
+    }
+
+    editor.start_change();
+    replace_lines(editor, first, last, &[joined]);
+    editor.finish_change();
+    // Place the cursor where the last line was joined
+    editor.set_cursor(Cursor::new(first, join_index));
+    editor.set_selection(Selection::None);
+}
+
+/// Delete the selected lines and their line endings
+pub fn delete_lines(editor: &mut ViEditor<'static, 'static>) {
+    let (first, last) = selected_lines(editor);
+    let cursor = editor.cursor();
+    let count = line_count(editor);
+
+    editor.start_change();
+    if last + 1 < count {
+        editor.delete_range(Cursor::new(first, 0), Cursor::new(last + 1, 0));
+    } else if first > 0 {
+        // The last line has no line ending, so the one before it is removed instead
+        let (start_len, end_len) = editor.with_buffer(|buffer| {
+            (
+                buffer.lines[first - 1].text().len(),
+                buffer.lines[last].text().len(),
+            )
+        });
+        editor.delete_range(
+            Cursor::new(first - 1, start_len),
+            Cursor::new(last, end_len),
+        );
+    } else {
+        let end_len = editor.with_buffer(|buffer| buffer.lines[last].text().len());
+        editor.delete_range(Cursor::new(0, 0), Cursor::new(last, end_len));
+    }
+    editor.finish_change();
+    let cursor = clamp(editor, Cursor::new(first, cursor.index));
+    editor.set_cursor(cursor);
+    editor.set_selection(Selection::None);
+}
+
+/// Commands in the line tools menu
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LineTool {
+    SortAscending,
+    SortDescending,
+    Reverse,
+    RemoveDuplicates,
+}
+
+/// Change the order of the selected lines, or of every line if nothing is selected
+pub fn line_tool(editor: &mut ViEditor<'static, 'static>, line_tool: LineTool) {
+    let has_selection = editor.selection_bounds().is_some();
+    let (first, mut last) = if has_selection {
+        selected_lines(editor)
+    } else {
+        (0, line_count(editor) - 1)
+    };
+    let mut lines = line_texts(editor, first, last);
+    // The empty line after a final line ending stays at the end
+    if !has_selection && last > first && lines[lines.len() - 1].is_empty() {
+        lines.pop();
+        last -= 1;
+    }
+
+    let old_lines = lines.clone();
+    match line_tool {
+        LineTool::SortAscending => {
+            lines.sort_by(|a, b| LANGUAGE_SORTER.compare(a, b));
+        }
+        LineTool::SortDescending => {
+            lines.sort_by(|a, b| LANGUAGE_SORTER.compare(b, a));
+        }
+        LineTool::Reverse => lines.reverse(),
+        LineTool::RemoveDuplicates => {
+            let mut seen = HashSet::new();
+            lines.retain(|line| seen.insert(line.clone()));
+        }
+    }
+    if lines == old_lines {
+        return;
+    }
+
+    let cursor = editor.cursor();
+    editor.start_change();
+    replace_lines(editor, first, last, &lines);
+    editor.finish_change();
+    if has_selection {
+        // Select the changed lines
+        let end = Cursor::new(first + lines.len() - 1, lines[lines.len() - 1].len());
+        editor.set_cursor(end);
+        editor.set_selection(Selection::Normal(Cursor::new(first, 0)));
+    } else {
+        let cursor = clamp(editor, cursor);
+        editor.set_cursor(cursor);
+        editor.set_selection(Selection::None);
+    }
+}
@@ -0,0 +1,1469 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::widget::segmented_button::Entity;
+use cosmic_text::{Cursor, Edit, Selection, ViEditor};
+use serde_json::{Value, json};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    io::{self, BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{self, Child, ChildStdin, Command, Stdio},
+    sync::mpsc,
+    thread,
+};
+
+use crate::{lines, outline::Symbol};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub enum Severity {
+    Error,
+    Warning,
+    Information,
+    Hint,
+}
+
+impl Severity {
+    fn from_lsp(value: &Value) -> Self {
+        match value.as_u64() {
+            Some(2) => Self::Warning,
+            Some(3) => Self::Information,
+            Some(4) => Self::Hint,
+            // Servers should treat a missing severity as an error
+            _ => Self::Error,
+        }
+    }
+}
+
+/// A problem reported by a language server, with positions as byte indexes
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Diagnostic {
+    pub start: Cursor,
+    pub end: Cursor,
+    pub severity: Severity,
+    pub message: String,
+    pub source_opt: Option<String>,
+}
+
+/// A suggestion for the word at the cursor
+#[derive(Clone, Debug)]
+pub struct CompletionItem {
+    pub label: String,
+    pub detail_opt: Option<String>,
+    pub documentation_opt: Option<String>,
+    /// Text that replaces the word, with tab stops like `$1` if it is a snippet
+    pub insert_text: String,
+    pub snippet: bool,
+    pub filter_text: String,
+    pub sort_text: String,
+    /// Start of the text to replace, when the server chose it
+    pub start_opt: Option<Cursor>,
+}
+
+/// What is looked up for the symbol at the cursor
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LocationKind {
+    Definition,
+    References,
+}
+
+/// A range in a file, with positions as byte indexes
+#[derive(Clone, Debug)]
+pub struct Location {
+    pub path: PathBuf,
+    pub start: Cursor,
+    pub end: Cursor,
+    /// Text of the line the range starts on, for showing it in a list
+    pub line_text: String,
+}
+
+/// The signature of the call being typed
+#[derive(Clone, Debug)]
+pub struct Signature {
+    pub label: String,
+    /// Byte range of the parameter at the cursor in the label
+    pub parameter_opt: Option<(usize, usize)>,
+    pub documentation_opt: Option<String>,
+}
+
+/// A replacement of a range, with positions as byte indexes
+#[derive(Clone, Debug)]
+pub struct TextEdit {
+    pub start: Cursor,
+    pub end: Cursor,
+    pub text: String,
+}
+
+// Edits that start later come first, so that applying them keeps the other positions valid
+fn sort_edits(edits: &mut [TextEdit]) {
+    edits.sort_by(|a, b| (b.start.line, b.start.index).cmp(&(a.start.line, a.start.index)));
+}
+
+// Where a position is after an edit, which keeps positions inside of it on the same line
+fn edited_cursor(cursor: Cursor, edit: &TextEdit) -> Cursor {
+    if (cursor.line, cursor.index) < (edit.start.line, edit.start.index) {
+        return cursor;
+    }
+    let newlines = edit.text.matches('\n').count();
+    if (cursor.line, cursor.index) < (edit.end.line, edit.end.index) {
+        return Cursor::new(
+            edit.start.line + (cursor.line - edit.start.line).min(newlines),
+            cursor.index,
+        );
+    }
+    let line = cursor.line - (edit.end.line - edit.start.line) + newlines;
+    if cursor.line != edit.end.line {
+        return Cursor::new(line, cursor.index);
+    }
+    let last_len = edit.text.len() - edit.text.rfind('\n').map_or(0, |i| i + 1);
+    let start_index = if newlines == 0 { edit.start.index } else { 0 };
+    Cursor::new(
+        line,
+        start_index + last_len + (cursor.index - edit.end.index),
+    )
+}
+
+/// Apply edits to an editor as one change, keeping the cursor on the text it was on
+pub fn edit_editor(editor: &mut ViEditor<'static, 'static>, mut edits: Vec<TextEdit>) {
+    sort_edits(&mut edits);
+    let mut cursor = editor.cursor();
+    editor.start_change();
+    for edit in edits {
+        editor.delete_range(edit.start, edit.end);
+        editor.insert_at(edit.start, &edit.text, None);
+        cursor = edited_cursor(cursor, &edit);
+    }
+    editor.finish_change();
+    editor.set_selection(Selection::None);
+    let cursor = lines::clamp(editor, cursor);
+    editor.set_cursor(cursor);
+}
+
+/// Apply edits to the text of a file
+pub fn edit_text(text: &mut String, mut edits: Vec<TextEdit>) {
+    let mut line_starts = vec![0];
+    line_starts.extend(text.match_indices('\n').map(|(i, _)| i + 1));
+    let offset = |cursor: Cursor| {
+        let line_start = line_starts.get(cursor.line).copied().unwrap_or(text.len());
+        (line_start + cursor.index).min(text.len())
+    };
+    sort_edits(&mut edits);
+    let ranges: Vec<_> = edits
+        .into_iter()
+        .map(|edit| (offset(edit.start), offset(edit.end), edit.text))
+        .collect();
+    for (start, end, edit_text) in ranges {
+        text.replace_range(start..end.max(start), &edit_text);
+    }
+}
+
+/// A file to open with the language server of its syntax
+pub struct Document {
+    pub entity: Entity,
+    pub path: PathBuf,
+    /// Folder the server is started in, which is usually the project of the file
+    pub root: PathBuf,
+    pub language_id: String,
+    pub command: Vec<String>,
+    pub text: String,
+}
+
+pub enum Job {
+    Open(Document),
+    Change(Entity, String),
+    Close(Entity),
+    // Ask for completions at a position, with the request the results are for
+    Completion(Entity, Cursor, u64),
+    Locations(Entity, Cursor, LocationKind),
+    // Ask for the hover or signature at a position, with the request the results are for
+    Hover(Entity, Cursor, u64),
+    SignatureHelp(Entity, Cursor, u64),
+    // Rename the symbol at a position
+    Rename(Entity, Cursor, String),
+    // Format a document with its tab width and whether to indent with spaces
+    Format(Entity, u64, u16, bool),
+    // Ask for the symbols of a document, with the request they are for
+    Symbols(Entity, u64),
+    // A message read from a server, or None when its output ended
+    Receive(usize, Option<Value>),
+}
+
+impl std::fmt::Debug for Job {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Open(document) => f.debug_tuple("Open").field(&document.entity).finish(),
+            Self::Change(entity, _text) => f.debug_tuple("Change").field(entity).finish(),
+            Self::Close(entity) => f.debug_tuple("Close").field(entity).finish(),
+            Self::Completion(entity, cursor, request) => f
+                .debug_tuple("Completion")
+                .field(entity)
+                .field(cursor)
+                .field(request)
+                .finish(),
+            Self::Locations(entity, cursor, kind) => f
+                .debug_tuple("Locations")
+                .field(entity)
+                .field(cursor)
+                .field(kind)
+                .finish(),
+            Self::Hover(entity, cursor, request) => f
+                .debug_tuple("Hover")
+                .field(entity)
+                .field(cursor)
+                .field(request)
+                .finish(),
+            Self::SignatureHelp(entity, cursor, request) => f
+                .debug_tuple("SignatureHelp")
+                .field(entity)
+                .field(cursor)
+                .field(request)
+                .finish(),
+            Self::Rename(entity, cursor, name) => f
+                .debug_tuple("Rename")
+                .field(entity)
+                .field(cursor)
+                .field(name)
+                .finish(),
+            Self::Format(entity, request, tab_width, insert_spaces) => f
+                .debug_tuple("Format")
+                .field(entity)
+                .field(request)
+                .field(tab_width)
+                .field(insert_spaces)
+                .finish(),
+            Self::Symbols(entity, request) => f
+                .debug_tuple("Symbols")
+                .field(entity)
+                .field(request)
+                .finish(),
+            Self::Receive(server_i, _) => f.debug_tuple("Receive").field(server_i).finish(),
+        }
+    }
+}
+
+/// Results from language servers, sent back to the application
+#[derive(Clone, Debug)]
+pub enum Event {
+    Diagnostics(Entity, Vec<Diagnostic>),
+    // Items for a completion request, and whether typing more gives other items
+    Completion(Entity, u64, Vec<CompletionItem>, bool),
+    Locations(LocationKind, Vec<Location>),
+    Hover(Entity, u64, Option<String>),
+    SignatureHelp(Entity, u64, Option<Signature>),
+    // Edits of a rename, by file
+    Edits(Vec<(PathBuf, Vec<TextEdit>)>),
+    // Edits that format a document, which is None if it cannot be formatted
+    Format(Entity, u64, Option<Vec<TextEdit>>),
+    // Symbols of a document, which is None if the server does not list them
+    Symbols(Entity, u64, Option<Vec<Symbol>>),
+}
+
+/// Handle to the language server thread, which is started by a subscription
+#[derive(Clone, Debug)]
+pub struct Worker {
+    sender: mpsc::Sender<Job>,
+}
+
+impl Worker {
+    fn send(&self, job: Job) {
+        if self.sender.send(job).is_err() {
+            log::warn!("language server thread has stopped");
+        }
+    }
+
+    /// Open a document, or open it again if its path or syntax changed
+    pub fn open(&self, document: Document) {
+        self.send(Job::Open(document));
+    }
+
+    /// Send the new text of an open document
+    pub fn change(&self, entity: Entity, text: String) {
+        self.send(Job::Change(entity, text));
+    }
+
+    pub fn close(&self, entity: Entity) {
+        self.send(Job::Close(entity));
+    }
+
+    /// Ask for completions at the cursor, which are sent back with the request
+    pub fn completion(&self, entity: Entity, cursor: Cursor, request: u64) {
+        self.send(Job::Completion(entity, cursor, request));
+    }
+
+    /// Look up the definition or the references of the symbol at the cursor
+    pub fn locations(&self, entity: Entity, cursor: Cursor, kind: LocationKind) {
+        self.send(Job::Locations(entity, cursor, kind));
+    }
+
+    /// Ask for information about the symbol at a position
+    pub fn hover(&self, entity: Entity, cursor: Cursor, request: u64) {
+        self.send(Job::Hover(entity, cursor, request));
+    }
+
+    /// Ask for the signature of the call at the cursor
+    pub fn signature_help(&self, entity: Entity, cursor: Cursor, request: u64) {
+        self.send(Job::SignatureHelp(entity, cursor, request));
+    }
+
+    /// Rename the symbol at the cursor in every file, the edits are sent back
+    pub fn rename(&self, entity: Entity, cursor: Cursor, name: String) {
+        self.send(Job::Rename(entity, cursor, name));
+    }
+
+    /// Format a whole document, the edits are sent back
+    pub fn format(&self, entity: Entity, request: u64, tab_width: u16, insert_spaces: bool) {
+        self.send(Job::Format(entity, request, tab_width, insert_spaces));
+    }
+
+    /// Ask for the functions, types, and other symbols of a document
+    pub fn symbols(&self, entity: Entity, request: u64) {
+        self.send(Job::Symbols(entity, request));
+    }
+}
+
+pub fn worker() -> (Worker, mpsc::Receiver<Job>) {
+    let (sender, receiver) = mpsc::channel();
+    (Worker { sender }, receiver)
+}
+
+/// Language identifier of a syntax, as used by language servers
+pub fn language_id(syntax_name: &str) -> String {
+    match syntax_name {
+        "Bourne Again Shell (bash)" => "shellscript".to_string(),
+        "C#" => "csharp".to_string(),
+        "C++" => "cpp".to_string(),
+        "JavaScript (Babel)" => "javascript".to_string(),
+        "Objective-C" => "objective-c".to_string(),
+        "TypeScriptReact" => "typescriptreact".to_string(),
+        _ => syntax_name.to_lowercase().replace(' ', ""),
+    }
+}
+
+/// Folder to start the language server of a file in, which is the innermost project that
+/// contains it, otherwise its git repository or its folder
+pub fn root<'a>(projects: impl Iterator<Item = &'a Path>, path: &'a Path) -> PathBuf {
+    projects
+        .filter(|project| path.starts_with(project))
+        .max_by_key(|project| project.components().count())
+        .or_else(|| {
+            path.ancestors()
+                .skip(1)
+                .find(|dir| dir.join(".git").exists())
+        })
+        .or_else(|| path.parent())
+        .unwrap_or(path)
+        .to_path_buf()
+}
+
+/// The `file://` URI of an absolute path
+pub fn path_uri(path: &Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.to_string_lossy().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                uri.push(char::from(byte))
+            }
+            _ => uri.push_str(&format!("%{byte:02X}")),
+        }
+    }
+    uri
+}
+
+/// The path of a `file://` URI
+pub fn uri_path(uri: &str) -> Option<PathBuf> {
+    let encoded = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut i = 0;
+    while i < encoded.len() {
+        if encoded[i] == b'%'
+            && let Some(byte) = encoded
+                .get(i + 1..i + 3)
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+        {
+            bytes.push(byte);
+            i += 3;
+        } else {
+            bytes.push(encoded[i]);
+            i += 1;
+        }
+    }
+    Some(PathBuf::from(String::from_utf8(bytes).ok()?))
+}
+
+fn read_message(reader: &mut impl BufRead) -> io::Result<Value> {
+    let mut len_opt = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(len) = header.strip_prefix("Content-Length:") {
+            len_opt = len.trim().parse::<usize>().ok();
+        }
+    }
+    let len = len_opt.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::InvalidData, "message without Content-Length")
+    })?;
+    let mut body = vec![0; len];
+    reader.read_exact(&mut body)?;
+    Ok(serde_json::from_slice(&body)?)
+}
+
+fn write_message(writer: &mut impl Write, message: &Value) -> io::Result<()> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()
+}
+
+// Byte index of the character of a position, which counts UTF-16 code units unless the server
+// agreed to UTF-8
+fn byte_index(text: &str, character: usize, utf8: bool) -> usize {
+    if utf8 {
+        let mut index = character.min(text.len());
+        while !text.is_char_boundary(index) {
+            index -= 1;
+        }
+        return index;
+    }
+    let mut units = 0;
+    for (index, c) in text.char_indices() {
+        if units >= character {
+            return index;
+        }
+        units += c.len_utf16();
+    }
+    text.len()
+}
+
+// Text of a line, without its line ending
+fn line_text(text: &str, line: usize) -> &str {
+    let line_text = text.split('\n').nth(line).unwrap_or_default();
+    line_text.strip_suffix('\r').unwrap_or(line_text)
+}
+
+// Position in a text, with the character as a byte index
+fn text_position(text: &str, position: &Value, utf8: bool) -> Option<Cursor> {
+    let line = position["line"].as_u64()? as usize;
+    let character = position["character"].as_u64()? as usize;
+    Some(Cursor::new(
+        line,
+        byte_index(line_text(text, line), character, utf8),
+    ))
+}
+
+// Edits of a text, as listed by a server
+fn text_edits(text: &str, edits: &Value, utf8: bool) -> Vec<TextEdit> {
+    edits
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|edit| {
+            Some(TextEdit {
+                start: text_position(text, &edit["range"]["start"], utf8)?,
+                end: text_position(text, &edit["range"]["end"], utf8)?,
+                text: edit["newText"].as_str()?.to_string(),
+            })
+        })
+        .collect()
+}
+
+// Symbols listed by a server with the ones they contain, or as a flat list with locations
+fn document_symbols(
+    text: &str,
+    result: &Value,
+    depth: usize,
+    utf8: bool,
+    symbols: &mut Vec<Symbol>,
+) {
+    let start = symbols.len();
+    for item in result.as_array().into_iter().flatten() {
+        let Some(name) = item["name"].as_str() else {
+            continue;
+        };
+        let range = if item["selectionRange"].is_object() {
+            &item["selectionRange"]
+        } else if item["range"].is_object() {
+            &item["range"]
+        } else {
+            &item["location"]["range"]
+        };
+        let Some(position) = text_position(text, &range["start"], utf8) else {
+            continue;
+        };
+        symbols.push(Symbol {
+            name: name.to_string(),
+            detail_opt: item["detail"]
+                .as_str()
+                .or_else(|| item["containerName"].as_str())
+                .filter(|detail| !detail.is_empty())
+                .map(str::to_string),
+            depth,
+            start: position,
+        });
+        document_symbols(text, &item["children"], depth + 1, utf8, symbols);
+    }
+    // Flat lists are not always in the order of the document
+    if depth == 0 && result[0].get("location").is_some() {
+        symbols[start..].sort_by_key(|symbol| (symbol.start.line, symbol.start.index));
+    }
+}
+
+// The active signature of signature help, with the byte range of its active parameter
+fn signature(result: &Value, utf8: bool) -> Option<Signature> {
+    let signatures = result["signatures"].as_array()?;
+    let signature_i = result["activeSignature"].as_u64().unwrap_or(0) as usize;
+    let signature = signatures.get(signature_i).or_else(|| signatures.first())?;
+    let label = signature["label"].as_str()?.to_string();
+    // The active parameter can be set by the signature or by the result
+    let parameter_i = signature["activeParameter"]
+        .as_u64()
+        .or_else(|| result["activeParameter"].as_u64())
+        .unwrap_or(0) as usize;
+    let parameter_opt = signature["parameters"]
+        .as_array()
+        .and_then(|parameters| parameters.get(parameter_i))
+        .and_then(|parameter| match &parameter["label"] {
+            Value::String(parameter_label) => label
+                .find(parameter_label.as_str())
+                .map(|start| (start, start + parameter_label.len())),
+            Value::Array(offsets) => {
+                let start = offsets.first()?.as_u64()? as usize;
+                let end = offsets.get(1)?.as_u64()? as usize;
+                Some((
+                    byte_index(&label, start, utf8),
+                    byte_index(&label, end, utf8),
+                ))
+            }
+            _ => None,
+        });
+    Some(Signature {
+        documentation_opt: markup(&signature["documentation"]),
+        label,
+        parameter_opt,
+    })
+}
+
+// Character of a byte index in a position, the reverse of `byte_index`
+fn character(text: &str, index: usize, utf8: bool) -> usize {
+    if utf8 {
+        index
+    } else {
+        text[..index].encode_utf16().count()
+    }
+}
+
+// Text of documentation, which is a string, markup content, or a list of marked strings
+fn markup(value: &Value) -> Option<String> {
+    let text = match value {
+        Value::Array(values) => values
+            .iter()
+            .filter_map(markup)
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        _ => value
+            .as_str()
+            .or_else(|| value["value"].as_str())?
+            .lines()
+            // Markdown code fences only mark the language
+            .filter(|line| !line.trim_start().starts_with("```"))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    };
+    let text = text.trim();
+    if text.is_empty() {
+        None
+    } else {
+        Some(text.to_string())
+    }
+}
+
+struct Server {
+    command: Vec<String>,
+    root: PathBuf,
+    child: Child,
+    stdin: ChildStdin,
+    next_id: u64,
+    // Requests and notifications are held until the server answered the initialize request
+    initialized: bool,
+    pending: Vec<Value>,
+    utf8: bool,
+    // The server does not want document contents when this is false
+    sync: bool,
+    completion: bool,
+    formatting: bool,
+    symbols: bool,
+}
+
+impl Server {
+    fn start(
+        server_i: usize,
+        command: &[String],
+        root: &Path,
+        sender: mpsc::Sender<Job>,
+    ) -> io::Result<Self> {
+        let (program, args) = command
+            .split_first()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty command"))?;
+        let mut child = Command::new(program)
+            .args(args)
+            .current_dir(root)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stdout);
+            loop {
+                match read_message(&mut reader) {
+                    Ok(message) => {
+                        if sender.send(Job::Receive(server_i, Some(message))).is_err() {
+                            return;
+                        }
+                    }
+                    Err(err) => {
+                        if err.kind() != io::ErrorKind::UnexpectedEof {
+                            log::warn!("failed to read from language server: {}", err);
+                        }
+                        let _ = sender.send(Job::Receive(server_i, None));
+                        return;
+                    }
+                }
+            }
+        });
+
+        let mut server = Self {
+            command: command.to_vec(),
+            root: root.to_path_buf(),
+            child,
+            stdin,
+            next_id: 0,
+            initialized: false,
+            pending: Vec::new(),
+            utf8: false,
+            sync: true,
+            completion: false,
+            formatting: false,
+            symbols: false,
+        };
+        let root_uri = path_uri(root);
+        let name = root
+            .file_name()
+            .map_or_else(|| root.to_string_lossy(), |name| name.to_string_lossy());
+        server.request(
+            "initialize",
+            json!({
+                "processId": process::id(),
+                "clientInfo": { "name": "cosmic-edit" },
+                "rootUri": root_uri,
+                "workspaceFolders": [{ "uri": root_uri, "name": name }],
+                "capabilities": {
+                    "general": { "positionEncodings": ["utf-8", "utf-16"] },
+                    "textDocument": {
+                        "completion": {
+                            "completionItem": {
+                                "snippetSupport": true,
+                                "documentationFormat": ["plaintext", "markdown"],
+                            },
+                        },
+                        "definition": { "linkSupport": true },
+                        "documentSymbol": { "hierarchicalDocumentSymbolSupport": true },
+                        "formatting": {},
+                        "hover": { "contentFormat": ["plaintext", "markdown"] },
+                        "publishDiagnostics": { "relatedInformation": false },
+                        "references": {},
+                        "rename": {},
+                        "signatureHelp": {
+                            "signatureInformation": {
+                                "documentationFormat": ["plaintext", "markdown"],
+                                "parameterInformation": { "labelOffsetSupport": true },
+                            },
+                        },
+                        "synchronization": { "didSave": false },
+                    },
+                    "workspace": {
+                        "workspaceEdit": { "documentChanges": true },
+                        "workspaceFolders": true,
+                    },
+                },
+            }),
+        )?;
+        Ok(server)
+    }
+
+    fn write(&mut self, message: Value) -> io::Result<()> {
+        write_message(&mut self.stdin, &message)
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> io::Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let message = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+        if !self.initialized && method != "initialize" {
+            self.pending.push(message);
+        } else {
+            self.write(message)?;
+        }
+        Ok(id)
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        let message = json!({ "jsonrpc": "2.0", "method": method, "params": params });
+        if !self.initialized {
+            self.pending.push(message);
+        } else if let Err(err) = self.write(message) {
+            log::warn!("failed to send {} to {:?}: {}", method, self.command, err);
+        }
+    }
+
+    fn respond(&mut self, id: Value, result: Value) {
+        if let Err(err) = self.write(json!({ "jsonrpc": "2.0", "id": id, "result": result })) {
+            log::warn!("failed to respond to {:?}: {}", self.command, err);
+        }
+    }
+
+    // Finish starting once the server answered the initialize request
+    fn initialize(&mut self, result: &Value) {
+        let capabilities = &result["capabilities"];
+        self.utf8 = capabilities["positionEncoding"] == "utf-8";
+        let sync = &capabilities["textDocumentSync"];
+        // The kind is either a number or a field of the sync options, where 0 is none
+        self.sync = sync.as_u64().or_else(|| sync["change"].as_u64()) != Some(0);
+        self.completion = capabilities.get("completionProvider").is_some();
+        // Providers are either a boolean or options
+        let provider = |name: &str| {
+            capabilities
+                .get(name)
+                .is_some_and(|provider| *provider != Value::Bool(false))
+        };
+        self.formatting = provider("documentFormattingProvider");
+        self.symbols = provider("documentSymbolProvider");
+        self.initialized = true;
+        if let Err(err) = self.write(json!({
+            "jsonrpc": "2.0",
+            "method": "initialized",
+            "params": {},
+        })) {
+            log::warn!("failed to initialize {:?}: {}", self.command, err);
+        }
+        for message in std::mem::take(&mut self.pending) {
+            if let Err(err) = self.write(message) {
+                log::warn!("failed to send to {:?}: {}", self.command, err);
+            }
+        }
+    }
+}
+
+// Requests that wait for a response from a server
+enum Request {
+    // The document and request that completions are for
+    Completion(Entity, u64),
+    Locations(LocationKind),
+    Hover(Entity, u64),
+    SignatureHelp(Entity, u64),
+    Rename,
+    Format(Entity, u64),
+    Symbols(Entity, u64),
+}
+
+struct OpenDocument {
+    server_i: usize,
+    path: PathBuf,
+    language_id: String,
+    uri: String,
+    version: i64,
+    text: String,
+}
+
+struct Client {
+    // Servers stay in their slot when they exit, so that indexes stay valid
+    servers: Vec<Option<Server>>,
+    // Commands that could not be started in a folder, which are not tried again
+    failed: HashSet<(Vec<String>, PathBuf)>,
+    documents: HashMap<Entity, OpenDocument>,
+    // Requests by server and id
+    requests: HashMap<(usize, u64), Request>,
+    sender: mpsc::Sender<Job>,
+}
+
+impl Client {
+    fn server_i(&mut self, command: &[String], root: &Path) -> Option<usize> {
+        let existing = self.servers.iter().position(|server_opt| {
+            server_opt
+                .as_ref()
+                .is_some_and(|server| server.command == command && server.root == root)
+        });
+        if existing.is_some() {
+            return existing;
+        }
+        let key = (command.to_vec(), root.to_path_buf());
+        if self.failed.contains(&key) {
+            return None;
+        }
+        let server_i = self.servers.len();
+        match Server::start(server_i, command, root, self.sender.clone()) {
+            Ok(server) => {
+                log::info!("started language server {:?} in {:?}", command, root);
+                self.servers.push(Some(server));
+                Some(server_i)
+            }
+            Err(err) => {
+                log::warn!("failed to start language server {:?}: {}", command, err);
+                self.failed.insert(key);
+                None
+            }
+        }
+    }
+
+    fn open(&mut self, document: Document) {
+        let server_i_opt = self.server_i(&document.command, &document.root);
+        if let Some(open) = self.documents.get(&document.entity)
+            && Some(open.server_i) == server_i_opt
+            && open.path == document.path
+            && open.language_id == document.language_id
+        {
+            // Already open, only the text may have changed
+            self.change(document.entity, document.text);
+            return;
+        }
+        self.close(document.entity);
+        let Some(server_i) = server_i_opt else {
+            return;
+        };
+        let uri = path_uri(&document.path);
+        if let Some(server) = &mut self.servers[server_i] {
+            server.notify(
+                "textDocument/didOpen",
+                json!({
+                    "textDocument": {
+                        "uri": uri,
+                        "languageId": document.language_id,
+                        "version": 0,
+                        "text": document.text,
+                    },
+                }),
+            );
+        }
+        self.documents.insert(
+            document.entity,
+            OpenDocument {
+                server_i,
+                path: document.path,
+                language_id: document.language_id,
+                uri,
+                version: 0,
+                text: document.text,
+            },
+        );
+    }
+
+    fn change(&mut self, entity: Entity, text: String) {
+        let Some(document) = self.documents.get_mut(&entity) else {
+            return;
+        };
+        if document.text == text {
+            return;
+        }
+        document.version += 1;
+        document.text = text;
+        if let Some(server) = &mut self.servers[document.server_i]
+            && server.sync
+        {
+            // Without a range, the change replaces the whole document
+            server.notify(
+                "textDocument/didChange",
+                json!({
+                    "textDocument": { "uri": document.uri, "version": document.version },
+                    "contentChanges": [{ "text": document.text }],
+                }),
+            );
+        }
+    }
+
+    fn close(&mut self, entity: Entity) {
+        let Some(document) = self.documents.remove(&entity) else {
+            return;
+        };
+        if let Some(server) = &mut self.servers[document.server_i] {
+            server.notify(
+                "textDocument/didClose",
+                json!({ "textDocument": { "uri": document.uri } }),
+            );
+        }
+    }
+
+    // Send a request about a position in a document, returning false if it has no server
+    fn request_at(
+        &mut self,
+        entity: Entity,
+        cursor: Cursor,
+        method: &str,
+        mut params: Value,
+        request: Request,
+    ) -> bool {
+        let Some(document) = self.documents.get(&entity) else {
+            return false;
+        };
+        let Some(server) = self.servers[document.server_i]
+            .as_mut()
+            .filter(|server| server.initialized)
+        else {
+            return false;
+        };
+        if method == "textDocument/completion" && !server.completion {
+            return false;
+        }
+        let line = document
+            .text
+            .split('\n')
+            .nth(cursor.line)
+            .unwrap_or_default();
+        let index = cursor.index.min(line.len());
+        params["textDocument"] = json!({ "uri": document.uri });
+        params["position"] = json!({
+            "line": cursor.line,
+            "character": character(line, index, server.utf8),
+        });
+        match server.request(method, params) {
+            Ok(id) => {
+                self.requests.insert((document.server_i, id), request);
+                true
+            }
+            Err(err) => {
+                log::warn!("failed to send {} to {:?}: {}", method, server.command, err);
+                false
+            }
+        }
+    }
+
+    fn completion(&mut self, entity: Entity, cursor: Cursor, request: u64) -> Vec<Event> {
+        if self.request_at(
+            entity,
+            cursor,
+            "textDocument/completion",
+            json!({}),
+            Request::Completion(entity, request),
+        ) {
+            Vec::new()
+        } else {
+            // Documents without a server have nothing to complete
+            vec![Event::Completion(entity, request, Vec::new(), false)]
+        }
+    }
+
+    fn locations(&mut self, entity: Entity, cursor: Cursor, kind: LocationKind) -> Vec<Event> {
+        let (method, params) = match kind {
+            LocationKind::Definition => ("textDocument/definition", json!({})),
+            LocationKind::References => (
+                "textDocument/references",
+                json!({ "context": { "includeDeclaration": true } }),
+            ),
+        };
+        if self.request_at(entity, cursor, method, params, Request::Locations(kind)) {
+            Vec::new()
+        } else {
+            vec![Event::Locations(kind, Vec::new())]
+        }
+    }
+
+    fn hover(&mut self, entity: Entity, cursor: Cursor, request: u64) -> Vec<Event> {
+        let sent = self.request_at(
+            entity,
+            cursor,
+            "textDocument/hover",
+            json!({}),
+            Request::Hover(entity, request),
+        );
+        if sent {
+            Vec::new()
+        } else {
+            vec![Event::Hover(entity, request, None)]
+        }
+    }
+
+    fn signature_help(&mut self, entity: Entity, cursor: Cursor, request: u64) -> Vec<Event> {
+        let sent = self.request_at(
+            entity,
+            cursor,
+            "textDocument/signatureHelp",
+            json!({}),
+            Request::SignatureHelp(entity, request),
+        );
+        if sent {
+            Vec::new()
+        } else {
+            vec![Event::SignatureHelp(entity, request, None)]
+        }
+    }
+
+    fn rename(&mut self, entity: Entity, cursor: Cursor, name: String) {
+        self.request_at(
+            entity,
+            cursor,
+            "textDocument/rename",
+            json!({ "newName": name }),
+            Request::Rename,
+        );
+    }
+
+    fn format(
+        &mut self,
+        entity: Entity,
+        request: u64,
+        tab_width: u16,
+        insert_spaces: bool,
+    ) -> Vec<Event> {
+        let none = vec![Event::Format(entity, request, None)];
+        let Some(document) = self.documents.get(&entity) else {
+            return none;
+        };
+        let Some(server) = self.servers[document.server_i]
+            .as_mut()
+            .filter(|server| server.initialized && server.formatting)
+        else {
+            return none;
+        };
+        let params = json!({
+            "textDocument": { "uri": document.uri },
+            "options": { "tabSize": tab_width, "insertSpaces": insert_spaces },
+        });
+        match server.request("textDocument/formatting", params) {
+            Ok(id) => {
+                self.requests
+                    .insert((document.server_i, id), Request::Format(entity, request));
+                Vec::new()
+            }
+            Err(err) => {
+                log::warn!("failed to send formatting to {:?}: {}", server.command, err);
+                none
+            }
+        }
+    }
+
+    fn symbols(&mut self, entity: Entity, request: u64) -> Vec<Event> {
+        let none = vec![Event::Symbols(entity, request, None)];
+        let Some(document) = self.documents.get(&entity) else {
+            return none;
+        };
+        let Some(server) = self.servers[document.server_i]
+            .as_mut()
+            .filter(|server| server.initialized && server.symbols)
+        else {
+            return none;
+        };
+        let params = json!({ "textDocument": { "uri": document.uri } });
+        match server.request("textDocument/documentSymbol", params) {
+            Ok(id) => {
+                self.requests
+                    .insert((document.server_i, id), Request::Symbols(entity, request));
+                Vec::new()
+            }
+            Err(err) => {
+                log::warn!(
+                    "failed to send documentSymbol to {:?}: {}",
+                    server.command,
+                    err
+                );
+                none
+            }
+        }
+    }
+
+    // Handle a message from a server, returning the events for the application
+    fn receive(&mut self, server_i: usize, message: Value) -> Vec<Event> {
+        let Some(server) = &mut self.servers[server_i] else {
+            return Vec::new();
+        };
+        let method_opt = message["method"].as_str();
+        match (message.get("id").cloned(), method_opt) {
+            // Requests from the server are answered with empty results
+            (Some(id), Some(method)) => {
+                let result = match method {
+                    "workspace/configuration" => {
+                        let items = message["params"]["items"].as_array().map_or(0, Vec::len);
+                        Value::Array(vec![Value::Null; items])
+                    }
+                    _ => Value::Null,
+                };
+                server.respond(id, result);
+                Vec::new()
+            }
+            (Some(id), None) => {
+                let request_opt = id
+                    .as_u64()
+                    .and_then(|id| self.requests.remove(&(server_i, id)));
+                if let Some(error) = message.get("error") {
+                    log::warn!("language server {:?} error: {}", server.command, error);
+                    // Saving waits for formatting, and the outline has a fallback, so these are
+                    // answered even when they failed
+                    match request_opt {
+                        Some(Request::Format(entity, request)) => {
+                            return vec![Event::Format(entity, request, None)];
+                        }
+                        Some(Request::Symbols(entity, request)) => {
+                            return vec![Event::Symbols(entity, request, None)];
+                        }
+                        _ => {}
+                    }
+                } else if !server.initialized && id == 0 {
+                    server.initialize(&message["result"]);
+                } else {
+                    match request_opt {
+                        Some(Request::Completion(entity, request)) => {
+                            return self.completion_items(
+                                server_i,
+                                entity,
+                                request,
+                                &message["result"],
+                            );
+                        }
+                        Some(Request::Locations(kind)) => {
+                            return self.location_items(server_i, kind, &message["result"]);
+                        }
+                        Some(Request::Hover(entity, request)) => {
+                            let text_opt = markup(&message["result"]["contents"]);
+                            return vec![Event::Hover(entity, request, text_opt)];
+                        }
+                        Some(Request::SignatureHelp(entity, request)) => {
+                            let signature_opt = signature(&message["result"], server.utf8);
+                            return vec![Event::SignatureHelp(entity, request, signature_opt)];
+                        }
+                        Some(Request::Rename) => {
+                            return self.workspace_edit(server_i, &message["result"]);
+                        }
+                        Some(Request::Symbols(entity, request)) => {
+                            let symbols = self.documents.get(&entity).map(|document| {
+                                let mut symbols = Vec::new();
+                                document_symbols(
+                                    &document.text,
+                                    &message["result"],
+                                    0,
+                                    server.utf8,
+                                    &mut symbols,
+                                );
+                                symbols
+                            });
+                            return vec![Event::Symbols(entity, request, symbols)];
+                        }
+                        Some(Request::Format(entity, request)) => {
+                            let edits = self.documents.get(&entity).map(|document| {
+                                text_edits(&document.text, &message["result"], server.utf8)
+                            });
+                            return vec![Event::Format(entity, request, edits)];
+                        }
+                        None => {}
+                    }
+                }
+                Vec::new()
+            }
+            (None, Some("textDocument/publishDiagnostics")) => {
+                self.diagnostics(server_i, &message["params"])
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn completion_items(
+        &self,
+        server_i: usize,
+        entity: Entity,
+        request: u64,
+        result: &Value,
+    ) -> Vec<Event> {
+        let (Some(server), Some(document)) = (&self.servers[server_i], self.documents.get(&entity))
+        else {
+            return Vec::new();
+        };
+        // The result is a list of items, or a list that may be incomplete
+        let (items, incomplete) = match result {
+            Value::Array(items) => (items.as_slice(), false),
+            _ => (
+                result["items"].as_array().map_or(&[][..], Vec::as_slice),
+                result["isIncomplete"].as_bool().unwrap_or(false),
+            ),
+        };
+        let items = items
+            .iter()
+            .filter_map(|item| {
+                let label = item["label"].as_str()?.to_string();
+                // Text edits replace a range, which is either given directly or for inserting
+                let text_edit = &item["textEdit"];
+                let range = if text_edit["range"].is_object() {
+                    &text_edit["range"]
+                } else {
+                    &text_edit["insert"]
+                };
+                let start_opt = range["start"]["line"].as_u64().and_then(|line| {
+                    let text = document.text.split('\n').nth(line as usize)?;
+                    let character = range["start"]["character"].as_u64()? as usize;
+                    Some(Cursor::new(
+                        line as usize,
+                        byte_index(text, character, server.utf8),
+                    ))
+                });
+                let insert_text = text_edit["newText"]
+                    .as_str()
+                    .or_else(|| item["insertText"].as_str())
+                    .unwrap_or(&label)
+                    .to_string();
+                Some(CompletionItem {
+                    detail_opt: item["detail"]
+                        .as_str()
+                        .map(str::trim)
+                        .filter(|detail| !detail.is_empty())
+                        .map(str::to_string),
+                    documentation_opt: markup(&item["documentation"]),
+                    insert_text,
+                    snippet: item["insertTextFormat"].as_u64() == Some(2),
+                    filter_text: item["filterText"].as_str().unwrap_or(&label).to_string(),
+                    sort_text: item["sortText"].as_str().unwrap_or(&label).to_string(),
+                    start_opt,
+                    label,
+                })
+            })
+            .collect();
+        vec![Event::Completion(entity, request, items, incomplete)]
+    }
+
+    // Text of a file, from its open document or otherwise from disk, to find byte indexes
+    fn file_text(&self, path: &Path) -> Option<String> {
+        if let Some(document) = self
+            .documents
+            .values()
+            .find(|document| document.path == path)
+        {
+            return Some(document.text.clone());
+        }
+        match fs::read_to_string(path) {
+            Ok(text) => Some(text),
+            Err(err) => {
+                log::warn!("failed to read {:?} for language server: {}", path, err);
+                None
+            }
+        }
+    }
+
+    fn location_items(&self, server_i: usize, kind: LocationKind, result: &Value) -> Vec<Event> {
+        let Some(server) = &self.servers[server_i] else {
+            return Vec::new();
+        };
+        // The result is a location, a list of them, or a list of links to them
+        let items = match result {
+            Value::Array(items) => items.iter().collect(),
+            Value::Null => Vec::new(),
+            _ => vec![result],
+        };
+        let mut texts: HashMap<PathBuf, String> = HashMap::new();
+        let mut locations = Vec::with_capacity(items.len());
+        for item in items {
+            let (uri, range) = match item["targetUri"].as_str() {
+                Some(uri) => (uri, &item["targetSelectionRange"]),
+                None => (item["uri"].as_str().unwrap_or_default(), &item["range"]),
+            };
+            let Some(path) = uri_path(uri) else {
+                continue;
+            };
+            if !texts.contains_key(&path) {
+                let Some(text) = self.file_text(&path) else {
+                    continue;
+                };
+                texts.insert(path.clone(), text);
+            }
+            let text = &texts[&path];
+            let (Some(start), Some(end)) = (
+                text_position(text, &range["start"], server.utf8),
+                text_position(text, &range["end"], server.utf8),
+            ) else {
+                continue;
+            };
+            locations.push(Location {
+                line_text: line_text(text, start.line).trim().to_string(),
+                path,
+                start,
+                end,
+            });
+        }
+        if kind == LocationKind::References {
+            locations.sort_by(|a, b| {
+                (&a.path, a.start.line, a.start.index).cmp(&(&b.path, b.start.line, b.start.index))
+            });
+        }
+        vec![Event::Locations(kind, locations)]
+    }
+
+    fn workspace_edit(&self, server_i: usize, result: &Value) -> Vec<Event> {
+        let Some(server) = &self.servers[server_i] else {
+            return Vec::new();
+        };
+        // Edits are listed by URI, or by document with operations on files that are skipped
+        let mut uri_edits: Vec<(&str, &Value)> = Vec::new();
+        if let Some(document_changes) = result["documentChanges"].as_array() {
+            for change in document_changes {
+                if let Some(uri) = change["textDocument"]["uri"].as_str() {
+                    uri_edits.push((uri, &change["edits"]));
+                }
+            }
+        } else if let Some(changes) = result["changes"].as_object() {
+            for (uri, edits) in changes {
+                uri_edits.push((uri, edits));
+            }
+        }
+
+        let mut file_edits = Vec::with_capacity(uri_edits.len());
+        for (uri, edits) in uri_edits {
+            let Some(path) = uri_path(uri) else {
+                continue;
+            };
+            let Some(text) = self.file_text(&path) else {
+                continue;
+            };
+            file_edits.push((path, text_edits(&text, edits, server.utf8)));
+        }
+        vec![Event::Edits(file_edits)]
+    }
+
+    fn diagnostics(&self, server_i: usize, params: &Value) -> Vec<Event> {
+        let Some(server) = &self.servers[server_i] else {
+            return Vec::new();
+        };
+        let Some(path) = params["uri"].as_str().and_then(uri_path) else {
+            return Vec::new();
+        };
+        let mut events = Vec::new();
+        for (entity, document) in self.documents.iter() {
+            if document.server_i != server_i || document.path != path {
+                continue;
+            }
+            let lines: Vec<&str> = document
+                .text
+                .split('\n')
+                .map(|line| line.strip_suffix('\r').unwrap_or(line))
+                .collect();
+            let position = |position: &Value| {
+                let line = (position["line"].as_u64()? as usize).min(lines.len() - 1);
+                let character = position["character"].as_u64()? as usize;
+                Some(Cursor::new(
+                    line,
+                    byte_index(lines[line], character, server.utf8),
+                ))
+            };
+            let mut diagnostics: Vec<Diagnostic> = params["diagnostics"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|diagnostic| {
+                    let mut start = position(&diagnostic["range"]["start"])?;
+                    let mut end = position(&diagnostic["range"]["end"])?;
+                    // Empty ranges are widened to a character, so that they can be seen
+                    if (start.line, start.index) == (end.line, end.index) {
+                        let text = lines[start.line];
+                        match text[start.index..].chars().next() {
+                            Some(c) => end.index += c.len_utf8(),
+                            None => {
+                                if let Some(c) = text.chars().next_back() {
+                                    start.index -= c.len_utf8();
+                                }
+                            }
+                        }
+                    }
+                    Some(Diagnostic {
+                        start,
+                        end,
+                        severity: Severity::from_lsp(&diagnostic["severity"]),
+                        message: diagnostic["message"].as_str()?.to_string(),
+                        source_opt: diagnostic["source"].as_str().map(str::to_string),
+                    })
+                })
+                .collect();
+            diagnostics.sort_by_key(|diagnostic| {
+                (
+                    diagnostic.start.line,
+                    diagnostic.start.index,
+                    diagnostic.severity,
+                )
+            });
+            events.push(Event::Diagnostics(*entity, diagnostics));
+        }
+        events
+    }
+
+    // Forget a server whose output ended, its documents lose their diagnostics
+    fn exited(&mut self, server_i: usize) -> Vec<Event> {
+        self.requests
+            .retain(|(request_server_i, _id), _| *request_server_i != server_i);
+        if let Some(mut server) = self.servers[server_i].take() {
+            log::warn!("language server {:?} exited", server.command);
+            // Closing its input lets a server that only closed its output exit
+            drop(server.stdin);
+            let _ = server.child.wait();
+            self.failed.insert((server.command, server.root));
+        }
+        let entities: Vec<Entity> = self
+            .documents
+            .iter()
+            .filter(|(_entity, document)| document.server_i == server_i)
+            .map(|(entity, _document)| *entity)
+            .collect();
+        entities
+            .into_iter()
+            .map(|entity| {
+                self.documents.remove(&entity);
+                Event::Diagnostics(entity, Vec::new())
+            })
+            .collect()
+    }
+}
+
+/// Handle jobs until `send` returns false, the worker keeps a sender for the server threads
+pub fn run(worker: Worker, receiver: mpsc::Receiver<Job>, mut send: impl FnMut(Event) -> bool) {
+    let mut client = Client {
+        servers: Vec::new(),
+        failed: HashSet::new(),
+        documents: HashMap::new(),
+        requests: HashMap::new(),
+        sender: worker.sender,
+    };
+    while let Ok(job) = receiver.recv() {
+        let events = match job {
+            Job::Open(document) => {
+                client.open(document);
+                Vec::new()
+            }
+            Job::Change(entity, text) => {
+                client.change(entity, text);
+                Vec::new()
+            }
+            Job::Close(entity) => {
+                client.close(entity);
+                Vec::new()
+            }
+            Job::Completion(entity, cursor, request) => client.completion(entity, cursor, request),
+            Job::Locations(entity, cursor, kind) => client.locations(entity, cursor, kind),
+            Job::Hover(entity, cursor, request) => client.hover(entity, cursor, request),
+            Job::SignatureHelp(entity, cursor, request) => {
+                client.signature_help(entity, cursor, request)
+            }
+            Job::Rename(entity, cursor, name) => {
+                client.rename(entity, cursor, name);
+                Vec::new()
+            }
+            Job::Format(entity, request, tab_width, insert_spaces) => {
+                client.format(entity, request, tab_width, insert_spaces)
+            }
+            Job::Symbols(entity, request) => client.symbols(entity, request),
+            Job::Receive(server_i, Some(message)) => client.receive(server_i, message),
+            Job::Receive(server_i, None) => client.exited(server_i),
+        };
+        for event in events {
+            if !send(event) {
+                return;
+            }
+        }
+    }
+}
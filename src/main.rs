@@ -11,7 +11,7 @@ use cosmic::{
     cosmic_theme, executor,
     font::Font,
     iced::{
-        self, Alignment, Background, Color, Length, Limits, Point, Subscription,
+        self, Alignment, Background, Border, Color, Length, Limits, Point, Size, Subscription,
         advanced::graphics::text::font_system,
         clipboard, event,
         futures::{self, SinkExt},
@@ -22,59 +22,190 @@ use cosmic::{
     widget::{self, about::About, button, icon, nav_bar, segmented_button},
 };
 use cosmic_files::{
+    clipboard::ClipboardPaste,
     dialog::{Dialog, DialogKind, DialogMessage, DialogResult, DialogSettings},
     mime_icon::{mime_for_path, mime_icon},
 };
-use cosmic_text::{Cursor, Edit, Family, Selection, SwashCache, SyntaxSystem, ViMode};
+use cosmic_text::{
+    BorrowedWithFontSystem, Cursor, Edit, Family, LineEnding, Motion, Selection, SwashCache,
+    SyntaxSystem, ViEditor, ViMode,
+};
 use notify::{RecursiveMode, Watcher};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 use std::{
     any::TypeId,
-    collections::{HashMap, HashSet},
-    env, fs, io,
+    collections::{HashMap, HashSet, VecDeque},
+    env, fs,
+    io::{self, Write},
+    iter, mem,
     path::{self, Path, PathBuf},
     process,
-    sync::{Mutex, OnceLock},
+    sync::{
+        Arc, Mutex, OnceLock, RwLock,
+        atomic::{AtomicU64, Ordering},
+    },
 };
 use tokio::time;
 use unicode_segmentation::UnicodeSegmentation;
 
-use config::{AppTheme, CONFIG_VERSION, Config, ConfigState};
+mod auto_close;
+
+mod brackets;
+
+mod command_palette;
+
+mod comment;
+
+use compare::CompareRow;
+mod compare;
+
+use completion::Completion;
+mod completion;
+
+use conflict::Resolution;
+mod conflict;
+
+use config::{
+    AppTheme, CONFIG_VERSION, Config, ConfigState, LINE_HEIGHT_MUL_100_MAX,
+    LINE_HEIGHT_MUL_100_MIN, Session, SessionTab,
+};
 mod config;
 
-use git::{GitDiff, GitDiffLine, GitRepository, GitStatus, GitStatusKind};
+#[cfg(unix)]
+mod dbus;
+
+use emacs::Movement;
+mod emacs;
+
+use encoding_rs::{Encoding, UTF_8};
+mod encoding;
+
+mod ex;
+
+mod export;
+
+mod format;
+
+mod fuzzy;
+
+use git::{GitBlame, GitDiff, GitDiffHunk, GitDiffLine, GitRepository, GitStatus, GitStatusKind};
 mod git;
 
+mod highlight;
+
 use icon_cache::IconCache;
 mod icon_cache;
 
-use key_bind::key_binds;
+#[cfg(unix)]
+mod instance;
+
+use key_bind::{KeyBindsFile, Keymap, key_binds};
 mod key_bind;
 
 use line_number::LineNumberCache;
 mod line_number;
 
+use lines::LineTool;
+mod lines;
+
 mod localize;
 
+mod lsp;
+
+mod markdown;
+
 use self::menu::menu_bar;
 mod menu;
 
+mod multi_cursor;
+
+mod outline;
+
+use self::print::{Paper, PrintOptions};
+mod print;
+
 use self::project::ProjectNode;
 mod project;
 
-use self::search::ProjectSearchResult;
+use self::recovery::Snapshot;
+mod recovery;
+
+mod reindent;
+
+mod remote;
+
+use self::search::{FileSearchResult, ProjectSearchResult};
 mod search;
 
-use self::tab::{EditorTab, GitDiffTab, Tab};
+mod shell;
+
+use self::snippet::Snippet;
+mod snippet;
+
+mod spell;
+
+mod syntax;
+
+use self::tab::{CompareTab, EditorTab, GitDiffTab, Tab};
 mod tab;
 
-use self::text_box::text_box;
+use self::tags::Tag;
+mod tags;
+
+mod tasks;
+
+use self::text_box::{CompletionKey, EditorView, MacroKey, Marker, text_box};
 mod text_box;
 
+mod tools;
+
+mod undo_history;
+
+mod vim;
+
 static ICON_CACHE: OnceLock<Mutex<IconCache>> = OnceLock::new();
 static LINE_NUMBER_CACHE: OnceLock<Mutex<LineNumberCache>> = OnceLock::new();
 static SWASH_CACHE: OnceLock<Mutex<SwashCache>> = OnceLock::new();
-static SYNTAX_SYSTEM: OnceLock<SyntaxSystem> = OnceLock::new();
+static SYNTAX_SYSTEM: RwLock<Option<&'static SyntaxSystem>> = RwLock::new(None);
+
+/// Syntaxes and themes, which are loaded at startup and replaced by [`syntax::reload`]
+pub fn syntax_system() -> &'static SyntaxSystem {
+    SYNTAX_SYSTEM
+        .read()
+        .unwrap()
+        .expect("syntax system is not loaded")
+}
+
+// Number of find and replace strings that are remembered
+const FIND_HISTORY_LEN: usize = 20;
+
+// Type of the data of a tab that is dragged in the tab bar
+const TAB_DRAG_MIME: &str = "application/x-cosmic-edit-tab";
+
+// Number of closed tabs that can be reopened
+const CLOSED_TABS_LEN: usize = 10;
+
+// Number of files whose chosen syntax is remembered
+const SYNTAX_OVERRIDES_LEN: usize = 100;
+
+// Number of files whose chosen spell check dictionaries are remembered
+const SPELL_LANGUAGES_LEN: usize = 100;
+
+// Number of positions that navigating back returns to
+const JUMP_HISTORY_LEN: usize = 100;
+
+// Time the mouse rests on text before asking about it
+const HOVER_DELAY_MS: u64 = 500;
+
+// Number of completions shown at once
+const COMPLETION_ROWS: usize = 10;
+
+// Height of the task output panel
+const TASK_OUTPUT_HEIGHT: f32 = 240.0;
+
+// Most times a macro is played at once
+const MACRO_TIMES_MAX: usize = 10_000;
 
 pub fn icon_cache_get(name: &'static str, size: u16) -> icon::Icon {
     let mut icon_cache = ICON_CACHE.get().unwrap().lock().unwrap();
@@ -87,60 +218,215 @@ pub fn monospace_attrs() -> cosmic_text::Attrs<'static> {
     cosmic_text::Attrs::new().family(Family::Monospace)
 }
 
+fn selectable_item<'a>(name: String, selected: bool, message: Message) -> Element<'a, Message> {
+    let spacing = theme::active().cosmic().spacing;
+    let mut row = widget::row::with_capacity(3)
+        .align_y(Alignment::Center)
+        .spacing(spacing.space_xs)
+        .push(widget::text(name))
+        .push(widget::horizontal_space());
+    if selected {
+        row = row.push(icon_cache_get("object-select-symbolic", 16));
+    }
+    widget::button::custom(row)
+        .on_press(message)
+        .class(theme::Button::AppletMenu)
+        .width(Length::Fill)
+        .into()
+}
+
+fn git_diff_line<'a>(line: &'a GitDiffLine) -> Element<'a, Message> {
+    let container = match line {
+        GitDiffLine::Context {
+            old_line,
+            new_line,
+            text,
+        } => widget::container(widget::text::monotext(format!(
+            "{:4} {:4}   {}",
+            old_line, new_line, text
+        ))),
+        GitDiffLine::Added { new_line, text } => {
+            widget::container(widget::text::monotext(format!(
+                "{:4} {:4} + {}",
+                "", new_line, text
+            )))
+            .style(|_theme| {
+                //TODO: theme this color
+                widget::container::Style {
+                    background: Some(Background::Color(Color::from_rgb8(0x00, 0x40, 0x00))),
+                    ..Default::default()
+                }
+            })
+        }
+        GitDiffLine::Deleted { old_line, text } => {
+            widget::container(widget::text::monotext(format!(
+                "{:4} {:4} - {}",
+                old_line, "", text
+            )))
+            .style(|_theme| {
+                //TODO: theme this color
+                widget::container::Style {
+                    background: Some(Background::Color(Color::from_rgb8(0x40, 0x00, 0x00))),
+                    ..Default::default()
+                }
+            })
+        }
+    };
+    container.width(Length::Fill).into()
+}
+
+// One side of a row of the compare view, colored if it changed
+fn compare_side<'a>(
+    side: &'a Option<(usize, String)>,
+    changed: bool,
+    color: Color,
+) -> Element<'a, Message> {
+    let text = match side {
+        Some((line, text)) => format!("{:4} {}", line + 1, text),
+        None => String::new(),
+    };
+    let background = match side {
+        Some(_) if changed => Some(Background::Color(color)),
+        // Lines missing from this side are shown as a gap
+        None => Some(Background::Color(Color::from_rgba8(0x80, 0x80, 0x80, 0.1))),
+        Some(_) => None,
+    };
+    widget::container(widget::text::monotext(text))
+        .style(move |_theme| {
+            //TODO: theme this color
+            widget::container::Style {
+                background,
+                ..Default::default()
+            }
+        })
+        .width(Length::Fill)
+        .into()
+}
+
+// Split `path:line:column` from the command line into the path and the location, where the
+// column is optional and both start at one
+fn parse_location(arg: &str) -> (PathBuf, Option<(usize, usize)>) {
+    // Compiler output often ends locations with a colon
+    let arg = arg.trim_end_matches(':');
+    let number = |text: &str| text.parse::<usize>().ok().filter(|number| *number > 0);
+    if let Some((rest, last)) = arg.rsplit_once(':')
+        && let Some(last) = number(last)
+    {
+        if let Some((path, line)) = rest.rsplit_once(':')
+            && let Some(line) = number(line)
+        {
+            return (PathBuf::from(path), Some((line, last)));
+        }
+        return (PathBuf::from(rest), Some((last, 1)));
+    }
+    (PathBuf::from(arg), None)
+}
+
+// Names of the syntax themes, sorted like the theme set
+fn theme_names() -> Vec<String> {
+    syntax_system().theme_set.themes.keys().cloned().collect()
+}
+
+// Names of the syntaxes that can be chosen for a document, sorted for the language
+fn syntax_names() -> Vec<String> {
+    let mut syntax_names = Vec::new();
+    for syntax in syntax_system().syntax_set.syntaxes().iter() {
+        // Syntaxes without extensions cannot be selected by the editor
+        if !syntax.hidden && !syntax.file_extensions.is_empty() {
+            syntax_names.push(syntax.name.clone());
+        }
+    }
+    syntax_names.sort_by(|a, b| crate::localize::LANGUAGE_SORTER.compare(a, b));
+    syntax_names.dedup();
+    syntax_names
+}
+
+// How long ago something happened, like "5 minutes ago"
+fn time_ago(time: std::time::SystemTime) -> String {
+    let secs = time.elapsed().map_or(0, |elapsed| elapsed.as_secs());
+    match secs {
+        0..60 => fl!("time-just-now"),
+        60..3600 => fl!("time-minutes-ago", minutes = secs / 60),
+        3600..86400 => fl!("time-hours-ago", hours = secs / 3600),
+        _ => fl!("time-days-ago", days = secs / 86400),
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+
+    // `-` opens the text piped to the editor, which `--output -` writes back once its tab is closed
+    let stdin_opt = if args.iter().any(|arg| arg == "-") {
+        match io::read_to_string(io::stdin()) {
+            Ok(text) => Some(text),
+            Err(err) => {
+                eprintln!("failed to read standard input: {}", err);
+                None
+            }
+        }
+    } else {
+        None
+    };
+    let mut stdout = false;
+    if let Some(i) = args.iter().position(|arg| arg == "--output") {
+        match args.get(i + 1).map(String::as_str) {
+            Some("-") => stdout = true,
+            _ => eprintln!("--output only supports - for standard output"),
+        }
+    }
+
+    // Open files in the running instance, unless a new window was asked for or the editor is
+    // part of a pipeline
+    #[cfg(unix)]
+    let single_instance = !stdout && !args.iter().any(|arg| arg == "-" || arg == "--new-window");
+    #[cfg(unix)]
+    if single_instance {
+        match instance::send(&args) {
+            Ok(true) => return Ok(()),
+            Ok(false) => {}
+            Err(err) => {
+                eprintln!("failed to send arguments to the running instance: {}", err);
+            }
+        }
+    }
+
+    // A pipeline waits for the text written back by `--output -` and for the exit status, so the
+    // editor stays in the foreground
     #[cfg(all(unix, not(target_os = "redox")))]
-    match fork::daemon(true, true) {
-        Ok(fork::Fork::Child) => (),
-        Ok(fork::Fork::Parent(_child_pid)) => process::exit(0),
-        Err(err) => {
-            eprintln!("failed to daemonize: {:?}", err);
-            process::exit(1);
+    if !stdout {
+        match fork::daemon(true, true) {
+            Ok(fork::Fork::Child) => (),
+            Ok(fork::Fork::Parent(_child_pid)) => {
+                // With `--wait`, wait on the new instance like on a running one once it listens
+                if single_instance && args.iter().any(|arg| arg == "--wait") {
+                    for _ in 0..100 {
+                        match instance::send(&args) {
+                            Ok(true) => break,
+                            Ok(false) => std::thread::sleep(time::Duration::from_millis(100)),
+                            Err(err) => {
+                                eprintln!("failed to wait for the new instance: {}", err);
+                                break;
+                            }
+                        }
+                    }
+                }
+                process::exit(0)
+            }
+            Err(err) => {
+                eprintln!("failed to daemonize: {:?}", err);
+                process::exit(1);
+            }
         }
     }
 
     ICON_CACHE.get_or_init(|| Mutex::new(IconCache::new()));
     LINE_NUMBER_CACHE.get_or_init(|| Mutex::new(LineNumberCache::new()));
     SWASH_CACHE.get_or_init(|| Mutex::new(SwashCache::new()));
-    SYNTAX_SYSTEM.get_or_init(|| {
-        let lazy_theme_set = two_face::theme::LazyThemeSet::from(two_face::theme::extra());
-        let mut theme_set = syntect::highlighting::ThemeSet::from(&lazy_theme_set);
-        // Hardcoded COSMIC themes
-        for (theme_name, theme_data) in &[
-            ("COSMIC Dark", cosmic_syntax_theme::COSMIC_DARK_TM_THEME),
-            ("COSMIC Light", cosmic_syntax_theme::COSMIC_LIGHT_TM_THEME),
-        ] {
-            let mut cursor = io::Cursor::new(theme_data);
-            match syntect::highlighting::ThemeSet::load_from_reader(&mut cursor) {
-                Ok(mut theme) => {
-                    // Use libcosmic theme for background and gutter
-                    theme.settings.background = Some(syntect::highlighting::Color {
-                        r: 0,
-                        g: 0,
-                        b: 0,
-                        a: 0,
-                    });
-                    theme.settings.gutter = Some(syntect::highlighting::Color {
-                        r: 0,
-                        g: 0,
-                        b: 0,
-                        a: 0,
-                    });
-                    theme_set.themes.insert(theme_name.to_string(), theme);
-                }
-                Err(err) => {
-                    eprintln!("failed to load {:?} syntax theme: {}", theme_name, err);
-                }
-            }
-        }
-        SyntaxSystem {
-            //TODO: store newlines in buffer
-            syntax_set: two_face::syntax::extra_no_newlines(),
-            theme_set,
-        }
-    });
 
     env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("warn")).init();
 
+    syntax::reload();
+
     localize::localize();
 
     let (config_handler, config) = match cosmic_config::Config::new(App::APP_ID, CONFIG_VERSION) {
@@ -184,6 +470,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         config,
         config_state_handler,
         config_state,
+        stdin_opt,
+        stdout,
     };
     cosmic::app::run::<App>(settings, flags)?;
 
@@ -194,25 +482,91 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 pub enum Action {
     Todo,
     About,
+    AcceptBoth,
+    AcceptOurs,
+    AcceptTheirs,
+    AddCaretAbove,
+    AddCaretBelow,
+    AddNextOccurrence,
+    ClearMark,
+    ClearRecentFiles,
     CloseFile,
+    CloseOtherTabs,
     CloseProject(usize),
+    CloseSavedTabs,
+    CloseTabsToRight,
+    CommandPalette,
+    CompareFilesDialog,
+    ConvertLineEndingsCrLf,
+    ConvertLineEndingsLf,
     Copy,
+    CopyPath,
     Cut,
+    DeleteCharacter,
+    DeleteLine,
+    DuplicateLine,
+    ExportHtml,
+    ExportPdf,
+    ExternalTool(usize),
+    FilterThroughCommand,
     Find,
     FindAndReplace,
+    FindReferences,
+    FormatDocument,
+    GoToDefinition,
+    GoToMatchingBracket,
+    GoToSymbol,
+    JoinLines,
+    KillLine,
+    KillWord,
+    MoveCursor(Movement),
+    MoveLineDown,
+    MoveLineUp,
+    MoveTabToNewWindow,
+    NavigateBack,
+    NavigateForward,
     NewFile,
     NewWindow,
+    NextConflict,
+    NextDifference,
+    OpenClosedTab(usize),
     OpenFileDialog,
+    OpenLocationDialog,
     OpenProjectDialog,
     OpenRecentFile(usize),
     OpenRecentProject(usize),
     Paste,
+    PasteFromHistory,
+    PlayMacro,
+    PlayMacroDialog,
+    PreviousConflict,
+    PreviousDifference,
+    Print,
+    QuickOpen,
     Quit,
     Redo,
+    ReloadSyntaxes,
+    RemoveDuplicateLines,
+    RenameSymbol,
+    ReopenClosedTab,
+    RerunTask,
+    RevealInFiles,
+    ReverseLines,
     RevertAllChanges,
+    RunTaskDialog,
     Save,
     SaveAsDialog,
     SelectAll,
+    SetMark,
+    ShowCompletions,
+    SortLinesAscending,
+    SortLinesDescending,
+    SpellLanguage(usize),
+    SplitClose,
+    SplitFocusNext,
+    SplitHorizontal,
+    SplitVertical,
+    StopTask,
     TabActivate0,
     TabActivate1,
     TabActivate2,
@@ -225,14 +579,35 @@ pub enum Action {
     TabNext,
     TabPrev,
     TabWidth(u16),
+    ToggleAutoClose,
     ToggleAutoIndent,
+    ToggleComment,
     ToggleDocumentStatistics,
+    ToggleDocumentType,
+    ToggleEncoding,
+    ToggleFormatOnSave,
+    ToggleFullscreen,
+    ToggleGitBlame,
+    ToggleGitGutter,
     ToggleGitManagement,
     ToggleHighlightCurrentLine,
+    ToggleIndentGuides,
+    ToggleKeyboardShortcuts,
     ToggleLineNumbers,
+    ToggleMacroRecording,
+    ToggleMarkdownPreview,
+    ToggleMinimap,
+    ToggleOutline,
+    TogglePinTab,
+    ToggleProblems,
     ToggleProjectSearch,
     ToggleSettingsPage,
+    ToggleSpellCheck,
+    ToggleTaskOutput,
+    ToggleUndoHistory,
+    ToggleWhitespace,
     ToggleWordWrap,
+    ToggleZenMode,
     Undo,
     ZoomIn,
     ZoomOut,
@@ -244,25 +619,91 @@ impl Action {
         match self {
             Self::Todo => Message::Todo,
             Self::About => Message::ToggleContextPage(ContextPage::About),
-            Self::CloseFile => Message::CloseFile,
+            Self::AcceptBoth => Message::ResolveConflict(Resolution::Both),
+            Self::AcceptOurs => Message::ResolveConflict(Resolution::Ours),
+            Self::AcceptTheirs => Message::ResolveConflict(Resolution::Theirs),
+            Self::AddCaretAbove => Message::AddCaret(false),
+            Self::ConvertLineEndingsCrLf => Message::LineEnding(LineEnding::CrLf),
+            Self::ConvertLineEndingsLf => Message::LineEnding(LineEnding::Lf),
+            Self::AddCaretBelow => Message::AddCaret(true),
+            Self::AddNextOccurrence => Message::AddNextOccurrence,
+            Self::ClearMark => Message::ClearMark,
+            Self::ClearRecentFiles => Message::ClearRecentFiles,
+            Self::CloseFile => Message::CloseFile(entity_opt),
+            Self::CloseOtherTabs => Message::CloseOtherTabs(entity_opt),
             Self::CloseProject(project_i) => Message::CloseProject(*project_i),
+            Self::CloseSavedTabs => Message::CloseSavedTabs,
+            Self::CloseTabsToRight => Message::CloseTabsToRight(entity_opt),
+            Self::CommandPalette => Message::CommandPalette,
+            Self::CompareFilesDialog => Message::CompareFilesDialog,
             Self::Copy => Message::Copy,
+            Self::CopyPath => Message::CopyPath(entity_opt),
             Self::Cut => Message::Cut,
+            Self::DeleteCharacter => Message::DeleteCharacter,
+            Self::DeleteLine => Message::DeleteLines,
+            Self::DuplicateLine => Message::DuplicateLines,
+            Self::JoinLines => Message::JoinLines,
+            Self::KillLine => Message::KillLine,
+            Self::KillWord => Message::KillWord,
+            Self::MoveCursor(movement) => Message::MoveCursor(*movement),
+            Self::MoveLineDown => Message::MoveLines(false),
+            Self::MoveLineUp => Message::MoveLines(true),
+            Self::MoveTabToNewWindow => Message::MoveTabToNewWindow(entity_opt),
+            Self::RemoveDuplicateLines => Message::LineTool(LineTool::RemoveDuplicates),
+            Self::ReverseLines => Message::LineTool(LineTool::Reverse),
+            Self::SortLinesAscending => Message::LineTool(LineTool::SortAscending),
+            Self::SortLinesDescending => Message::LineTool(LineTool::SortDescending),
             Self::Find => Message::Find(Some(false)),
             Self::FindAndReplace => Message::Find(Some(true)),
+            Self::ExternalTool(index) => Message::ExternalTool(*index),
+            Self::FilterThroughCommand => Message::FilterDialog(entity_opt),
+            Self::FindReferences => Message::LspLocations(lsp::LocationKind::References),
+            Self::FormatDocument => Message::FormatDocument(entity_opt),
+            Self::GoToDefinition => Message::LspLocations(lsp::LocationKind::Definition),
+            Self::GoToMatchingBracket => Message::GoToMatchingBracket,
+            Self::GoToSymbol => Message::GoToSymbol,
             Self::NewFile => Message::NewFile,
             Self::NewWindow => Message::NewWindow,
+            Self::NavigateBack => Message::Navigate(false),
+            Self::NavigateForward => Message::Navigate(true),
+            Self::NextConflict => Message::GoToConflict(true),
+            Self::NextDifference => Message::CompareDifference(true),
+            Self::OpenClosedTab(index) => Message::ReopenClosedTab(*index),
             Self::OpenFileDialog => Message::OpenFileDialog,
+            Self::OpenLocationDialog => Message::OpenLocationDialog,
             Self::OpenProjectDialog => Message::OpenProjectDialog,
             Self::OpenRecentFile(index) => Message::OpenRecentFile(*index),
             Self::OpenRecentProject(index) => Message::OpenRecentProject(*index),
             Self::Paste => Message::Paste,
+            Self::PasteFromHistory => Message::PasteHistoryDialog,
+            Self::PlayMacro => Message::PlayMacro(1),
+            Self::PlayMacroDialog => Message::PlayMacroDialog,
+            Self::PreviousConflict => Message::GoToConflict(false),
+            Self::PreviousDifference => Message::CompareDifference(false),
+            Self::ExportHtml => Message::ExportDialog(entity_opt, export::Format::Html),
+            Self::ExportPdf => Message::ExportDialog(entity_opt, export::Format::Pdf),
+            Self::Print => Message::PrintDialog(entity_opt),
+            Self::QuickOpen => Message::QuickOpen,
             Self::Quit => Message::Quit,
             Self::Redo => Message::Redo,
+            Self::ReloadSyntaxes => Message::ReloadSyntaxes,
+            Self::RenameSymbol => Message::RenameSymbol,
+            Self::ReopenClosedTab => Message::ReopenClosedTab(0),
+            Self::RerunTask => Message::RerunTask,
+            Self::RevealInFiles => Message::RevealInFiles(entity_opt),
             Self::RevertAllChanges => Message::RevertAllChanges,
+            Self::RunTaskDialog => Message::RunTaskDialog,
             Self::Save => Message::Save(entity_opt),
             Self::SaveAsDialog => Message::SaveAsDialog(entity_opt),
             Self::SelectAll => Message::SelectAll,
+            Self::SetMark => Message::SetMark,
+            Self::ShowCompletions => Message::ShowCompletions,
+            Self::SpellLanguage(index) => Message::SpellLanguage(*index),
+            Self::SplitClose => Message::SplitClose,
+            Self::SplitFocusNext => Message::SplitFocusNext,
+            Self::SplitHorizontal => Message::SplitHorizontal,
+            Self::SplitVertical => Message::SplitVertical,
+            Self::StopTask => Message::StopTask,
             Self::TabActivate0 => Message::TabActivateJump(0),
             Self::TabActivate1 => Message::TabActivateJump(1),
             Self::TabActivate2 => Message::TabActivateJump(2),
@@ -275,16 +716,37 @@ impl Action {
             Self::TabNext => Message::TabNext,
             Self::TabPrev => Message::TabPrev,
             Self::TabWidth(tab_width) => Message::TabWidth(*tab_width),
+            Self::ToggleAutoClose => Message::ToggleAutoClose,
             Self::ToggleAutoIndent => Message::ToggleAutoIndent,
+            Self::ToggleComment => Message::ToggleComment,
             Self::ToggleDocumentStatistics => {
                 Message::ToggleContextPage(ContextPage::DocumentStatistics)
             }
+            Self::ToggleDocumentType => Message::ToggleContextPage(ContextPage::DocumentType),
+            Self::ToggleEncoding => Message::ToggleContextPage(ContextPage::Encoding),
+            Self::ToggleFormatOnSave => Message::ToggleFormatOnSave,
+            Self::ToggleFullscreen => Message::ToggleFullscreen,
+            Self::ToggleGitBlame => Message::ToggleGitBlame,
+            Self::ToggleGitGutter => Message::ToggleGitGutter,
             Self::ToggleGitManagement => Message::ToggleContextPage(ContextPage::GitManagement),
             Self::ToggleHighlightCurrentLine => Message::ToggleHighlightCurrentLine,
+            Self::ToggleIndentGuides => Message::ToggleIndentGuides,
+            Self::ToggleKeyboardShortcuts => Message::KeyboardShortcuts,
             Self::ToggleLineNumbers => Message::ToggleLineNumbers,
+            Self::ToggleMacroRecording => Message::ToggleMacroRecording,
+            Self::ToggleMarkdownPreview => Message::ToggleMarkdownPreview,
+            Self::ToggleMinimap => Message::ToggleMinimap,
+            Self::ToggleOutline => Message::ToggleContextPage(ContextPage::Outline),
+            Self::TogglePinTab => Message::TogglePinTab(entity_opt),
+            Self::ToggleProblems => Message::ToggleContextPage(ContextPage::Problems),
             Self::ToggleProjectSearch => Message::ToggleContextPage(ContextPage::ProjectSearch),
-            Self::ToggleSettingsPage => Message::ToggleContextPage(ContextPage::Settings),
+            Self::ToggleSettingsPage => Message::SettingsWindow,
+            Self::ToggleSpellCheck => Message::ToggleSpellCheck,
+            Self::ToggleTaskOutput => Message::ToggleTaskOutput,
+            Self::ToggleUndoHistory => Message::ToggleContextPage(ContextPage::UndoHistory),
+            Self::ToggleWhitespace => Message::ToggleWhitespace,
             Self::ToggleWordWrap => Message::ToggleWordWrap,
+            Self::ToggleZenMode => Message::ToggleZenMode,
             Self::Undo => Message::Undo,
             Self::ZoomIn => Message::ZoomIn,
             Self::ZoomOut => Message::ZoomOut,
@@ -293,6 +755,80 @@ impl Action {
     }
 }
 
+impl Action {
+    /// Whether the action edits the text or moves the cursor right away, so that macros can
+    /// replay it. Clipboard actions wait for the clipboard, so they are not recorded.
+    fn recordable(&self) -> bool {
+        matches!(
+            self,
+            Self::AddCaretAbove
+                | Self::AddCaretBelow
+                | Self::AddNextOccurrence
+                | Self::ClearMark
+                | Self::DeleteCharacter
+                | Self::DeleteLine
+                | Self::DuplicateLine
+                | Self::GoToMatchingBracket
+                | Self::JoinLines
+                | Self::MoveCursor(_)
+                | Self::MoveLineDown
+                | Self::MoveLineUp
+                | Self::Redo
+                | Self::RemoveDuplicateLines
+                | Self::ReverseLines
+                | Self::SelectAll
+                | Self::SetMark
+                | Self::SortLinesAscending
+                | Self::SortLinesDescending
+                | Self::ToggleComment
+                | Self::Undo
+        )
+    }
+}
+
+impl Message {
+    /// Whether the message edits the text of the active tab
+    fn edits(&self) -> bool {
+        matches!(
+            self,
+            Self::Completion(_)
+                | Self::CompletionSelect(_)
+                | Self::Cut
+                | Self::DeleteCharacter
+                | Self::DeleteLines
+                | Self::DropFilesText(..)
+                | Self::DuplicateLines
+                | Self::ExCommand(..)
+                | Self::ExternalTool(_)
+                | Self::FindReplace
+                | Self::FindReplaceAll
+                | Self::FormatDocument(_)
+                | Self::GitRevertHunk(_)
+                | Self::JoinLines
+                | Self::KillLine
+                | Self::KillWord
+                | Self::LineEnding(_)
+                | Self::LineTool(_)
+                | Self::MacroKey(_)
+                | Self::MoveLines(_)
+                | Self::Paste
+                | Self::PasteHistory(_)
+                | Self::PastePrimary
+                | Self::PasteValue(_)
+                | Self::PlayMacro(_)
+                | Self::Redo
+                | Self::RenameSymbol
+                | Self::ResolveConflict(_)
+                | Self::RevertAllChanges
+                | Self::SnippetTab
+                | Self::SpellingReplace(..)
+                | Self::ToggleComment
+                | Self::Undo
+                | Self::UndoHistoryJump(_)
+        )
+    }
+}
+
 impl MenuAction for Action {
     type Message = Message;
     fn message(&self) -> Message {
@@ -300,12 +836,43 @@ impl MenuAction for Action {
     }
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum NavMenuAction {
+    Duplicate(nav_bar::Id),
+    MoveToTrash(nav_bar::Id),
+    NewFile(nav_bar::Id),
+    NewFolder(nav_bar::Id),
+    Rename(nav_bar::Id),
+}
+
+impl MenuAction for NavMenuAction {
+    type Message = cosmic::Action<Message>;
+    fn message(&self) -> Self::Message {
+        action::app(Message::NavMenuAction(*self))
+    }
+}
+
+/// An action from the context menu of a tab, which applies to that tab instead of the active one
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct TabMenuAction(pub segmented_button::Entity, pub Action);
+
+impl MenuAction for TabMenuAction {
+    type Message = Message;
+    fn message(&self) -> Self::Message {
+        Message::TabMenuAction(*self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Flags {
     config_handler: Option<cosmic_config::Config>,
     config: Config,
     config_state_handler: Option<cosmic_config::Config>,
     config_state: ConfigState,
+    // Text piped to the editor with `-`
+    stdin_opt: Option<String>,
+    // Write a tab to standard output when it is closed, for `--output -`
+    stdout: bool,
 }
 
 #[derive(Debug)]
@@ -333,48 +900,146 @@ enum NewTab {
 #[allow(dead_code)]
 #[derive(Clone, Debug)]
 pub enum Message {
+    AddCaret(bool),
+    AddNextOccurrence,
     AppTheme(AppTheme),
+    AtomicSave(bool),
+    AutoSave,
+    AutoSaveInterval(usize),
     AutoScroll(Option<f32>),
+    ChangedLines(segmented_button::Entity, u64, Vec<usize>),
+    ClearMark,
+    ClearRecentFiles,
     Config(Config),
     ConfigState(ConfigState),
-    CloseFile,
+    CloseFile(Option<segmented_button::Entity>),
+    CloseOtherTabs(Option<segmented_button::Entity>),
     CloseProject(usize),
+    CloseSavedTabs,
+    CloseTabsToRight(Option<segmented_button::Entity>),
     CloseWindow(window::Id),
+    CommandPalette,
+    CommandPaletteSelect(usize),
+    CommandPaletteSubmit,
+    CommandPaletteValue(String),
+    CompareDifference(bool),
+    CompareFiles(PathBuf, PathBuf),
+    CompareFilesDialog,
+    CompareFilesResult(DialogResult),
+    CompareOpen(PathBuf, PathBuf, Vec<CompareRow>),
+    Completion(CompletionKey),
+    CompletionSelect(usize),
+    ConvertIndentation(bool),
     Copy,
+    CopyPath(Option<segmented_button::Entity>),
     Cut,
+    #[cfg(unix)]
+    DbusRequest(dbus::Request),
     DefaultFont(usize),
     DefaultFontSize(usize),
     ZoomIn,
     ZoomOut,
     ZoomReset,
     DefaultZoomStep(usize),
+    DeleteCharacter,
+    DeleteLines,
     DialogCancel,
+    DialogComplete,
     DialogMessage(DialogMessage),
+    DialogUpdate(DialogPage),
+    DocumentType(Option<String>),
+    DocumentTypeSearchValue(String),
+    DropFiles(Option<ClipboardPaste>),
+    DropFilesText(segmented_button::Entity, Option<ClipboardPaste>),
+    ExCommand(String, Option<(usize, usize)>),
+    ExportDialog(Option<segmented_button::Entity>, export::Format),
+    ExportResult(segmented_button::Entity, export::Format, DialogResult),
+    ExportSubmit(segmented_button::Entity, export::Format),
+    ExternalTool(usize),
+    DuplicateLines,
+    FilterDialog(Option<segmented_button::Entity>),
     Find(Option<bool>),
+    FindAllTabs(bool),
     FindCaseSensitive(bool),
+    FindInSelection(bool),
     FindFocused(bool),
     FindNext,
     FindPrevious,
     FindReplace,
     FindReplaceAll,
+    FindReplaceFocused,
     FindReplaceValueChanged(String),
     FindSearchValueChanged(String),
     FindUseRegex(bool),
+    FindWholeWords(bool),
     FindWrapAround(bool),
     Focus(window::Id),
+    FontLigatures(bool),
+    FormatDocument(Option<segmented_button::Entity>),
+    Formatted(segmented_button::Entity, u64, Option<Vec<lsp::TextEdit>>),
+    Fullscreen(window::Mode),
+    GitBlame(segmented_button::Entity, u64, Option<GitBlame>),
+    GitChanges(segmented_button::Entity, u64, Option<GitDiff>),
+    GitHunkPopup(segmented_button::Entity, Option<(usize, Point)>),
     GitProjectStatus(Vec<(String, PathBuf, Vec<GitStatus>)>),
+    GitRevertHunk(segmented_button::Entity),
     GitStage(PathBuf, PathBuf),
+    GitStageHunk(segmented_button::Entity),
     GitUnstage(PathBuf, PathBuf),
+    GoToConflict(bool),
+    GoToLocation(PathBuf, Cursor),
+    GoToDiagnostic(segmented_button::Entity, Cursor),
+    GoToMatchingBracket,
+    GoToSymbol,
+    HighlightSpans(segmented_button::Entity, Vec<highlight::LineSpans>),
+    HighlightWorker(highlight::Worker),
+    Hover(segmented_button::Entity, Option<(Cursor, Point)>),
+    HoverDelay(segmented_button::Entity, u64),
+    ImportSyntaxTheme,
+    ImportSyntaxThemeResult(DialogResult),
+    JoinLines,
     Key(Modifiers, keyboard::Key),
+    KeyBindCancel,
+    KeyBindRecord(Action),
+    KeyBindRemove(Action),
+    KeyBindSearchValue(String),
+    KeyBindSet(Action, KeyBind),
+    KeyBindsExport,
+    KeyBindsExportResult(DialogResult),
+    KeyBindsImport,
+    KeyBindsImportResult(DialogResult),
+    KeyBindsReset,
+    KeyboardShortcuts,
+    Keymap(Keymap),
+    KillLine,
+    KillWord,
+    LanguageServers(bool),
     LaunchUrl(String),
+    LineEnding(LineEnding),
+    LineHeight(u16),
+    LineTool(LineTool),
+    Lsp(lsp::Event),
+    LspLocations(lsp::LocationKind),
+    LspWorker(lsp::Worker),
+    MacroKey(MacroKey),
+    MarkdownScroll(usize),
     Modifiers(Modifiers),
+    MoveCursor(Movement),
+    MoveLines(bool),
+    MoveTabToNewWindow(Option<segmented_button::Entity>),
+    Navigate(bool),
+    NavMenuAction(NavMenuAction),
     NewFile,
     NewWindow,
     NotifyEvent(notify::Event),
     NotifyWatcher(WatcherWrapper),
+    #[cfg(unix)]
+    OpenArgs(Vec<String>, Option<instance::Waiter>),
     OpenFile(PathBuf),
     OpenFileDialog,
     OpenFileResult(DialogResult),
+    OpenLocationDialog,
+    OpenDiskDiff(GitDiff),
     OpenGitDiff(PathBuf, GitDiff),
     OpenProjectDialog,
     OpenProjectResult(DialogResult),
@@ -382,61 +1047,246 @@ pub enum Message {
     OpenRecentProject(usize),
     OpenSearchResult(usize, usize),
     Paste,
+    PasteHistory(usize),
+    PasteHistoryDialog,
+    PastePrimary,
     PasteValue(String),
+    SaveUndoHistoryPanel(bool),
+    PlayMacro(usize),
+    PlayMacroDialog,
     PrepareGitDiff(PathBuf, PathBuf, bool),
-    ProjectSearchResult(ProjectSearchResult),
+    PrintDialog(Option<segmented_button::Entity>),
+    PrintOptions(PrintOptions),
+    PrintSubmit(segmented_button::Entity),
+    PrimarySelection(String),
+    PrimarySelectionEnabled(bool),
+    ProjectReplaceAll,
+    ProjectReplaceMode(bool),
+    ProjectReplaceSelected,
+    ProjectReplaceToggle(usize, usize, bool),
+    ProjectReplaceValue(String),
+    ProjectSearchFile(u64, FileSearchResult),
+    ProjectSearchFinished(u64),
     ProjectSearchSubmit,
     ProjectSearchValue(String),
     PromptSaveChanges(segmented_button::Entity),
+    QuickOpen,
+    QuickOpenIndex(Vec<PathBuf>),
+    QuickOpenSelect(usize),
+    QuickOpenSubmit,
+    QuickOpenValue(String),
     Quit,
     QuitForce,
+    RecoveryDiscard,
+    RecoveryRestore,
+    RecoverySnapshot,
     Redo,
+    ReloadSyntaxes,
+    RenameSymbol,
+    ReopenClosedTab(usize),
+    RerunTask,
+    ResolveConflict(Resolution),
+    RestoreSession(bool),
+    SaveClipboardHistory(bool),
+    SaveFindHistory(bool),
+    RevealInFiles(Option<segmented_button::Entity>),
     RevertAllChanges,
+    RunTask(PathBuf, tasks::ProjectTask),
+    RunTaskDialog,
     Save(Option<segmented_button::Entity>),
     SaveAll,
     SaveAsDialog(Option<segmented_button::Entity>),
     SaveAsResult(segmented_button::Entity, DialogResult),
+    SaveElevated(segmented_button::Entity),
+    SaveElevatedResult(segmented_button::Entity, PathBuf, u64, bool),
+    SaveWithEncoding(&'static Encoding),
+    SaveWithEncodingForce(segmented_button::Entity, &'static Encoding),
+    SettingsPage(segmented_button::Entity),
+    SettingsWindow,
     Scroll(f32),
     SelectAll,
+    ShowCompletions,
+    SnippetTab,
+    SpellLanguage(usize),
+    Spelling(segmented_button::Entity, u64, Vec<spell::Misspelling>),
+    SpellingAdd(segmented_button::Entity, String),
+    SpellingIgnore(segmented_button::Entity, String),
+    SpellingReplace(segmented_button::Entity, String),
+    SplitClose,
+    SplitFocusNext,
+    SplitHorizontal,
+    SplitVertical,
+    StopTask,
     Surface(surface::Action),
+    SymbolSearchSelect(usize),
+    SymbolSearchSubmit,
+    SymbolSearchValue(String),
     SystemThemeModeChange(cosmic_theme::ThemeMode),
+    SyntaxAssociationAdd,
+    SyntaxAssociationPattern(String),
+    SyntaxAssociationRemove(String),
+    SyntaxAssociationSyntax(usize),
     SyntaxTheme(usize, bool),
     TabActivate(segmented_button::Entity),
+    TabBarContext(segmented_button::Entity),
     TabActivateJump(usize),
+    TagLocations(Vec<lsp::Location>),
+    Tags(Vec<Tag>),
+    TaskFinished(Option<i32>),
+    TaskOutput(String),
     TabChanged(segmented_button::Entity),
     TabClose(segmented_button::Entity),
     TabCloseForce(segmented_button::Entity),
     TabContextAction(segmented_button::Entity, Action),
     TabContextMenu(segmented_button::Entity, Option<Point>),
+    TabDiskCompare(segmented_button::Entity),
+    TabDiskKeep(segmented_button::Entity),
+    TabDiskReload(segmented_button::Entity),
+    TabLoadChunk(
+        segmented_button::Entity,
+        u64,
+        Vec<(String, LineEnding)>,
+        f32,
+    ),
+    TabLoadFinished(segmented_button::Entity, u64),
+    TabLoadRemote(segmented_button::Entity, Option<Vec<u8>>),
+    TabMenuAction(TabMenuAction),
     TabNext,
     TabPrev,
+    TabReorder(
+        segmented_button::Entity,
+        segmented_button::Entity,
+        segmented_button::InsertPosition,
+    ),
     TabSetCursor(segmented_button::Entity, Cursor),
+    TabSetScroll(segmented_button::Entity, usize),
     TabWidth(u16),
     Todo,
+    ToggleAutoClose,
     ToggleAutoIndent,
+    ToggleComment,
     ToggleContextPage(ContextPage),
+    ToggleFormatOnSave,
+    ToggleFullscreen,
+    ToggleGitBlame,
+    ToggleGitGutter,
     ToggleHighlightCurrentLine,
+    ToggleIndentGuides,
     ToggleLineNumbers,
+    ToggleMacroRecording,
+    ToggleMarkdownPreview,
+    ToggleMinimap,
+    TogglePinTab(Option<segmented_button::Entity>),
+    ToggleSpellCheck,
+    ToggleTaskOutput,
+    ToggleWhitespace,
     ToggleWordWrap,
+    ToggleZenMode,
     Undo,
+    UndoHistoryJump(usize),
     UpdateGitProjectStatus,
     VimBindings(bool),
+    WordCompletion(bool),
+    WordWrapColumn(usize),
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub enum ContextPage {
     About,
     DocumentStatistics,
+    DocumentType,
+    Encoding,
     GitManagement,
+    Outline,
+    Problems,
     //TODO: Move search to pop-up
     ProjectSearch,
-    Settings,
+    References,
+    UndoHistory,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum SettingsPage {
+    Appearance,
+    Editor,
+    KeyboardShortcuts,
+    Advanced,
+}
+
+impl SettingsPage {
+    fn all() -> &'static [Self] {
+        &[
+            Self::Appearance,
+            Self::Editor,
+            Self::KeyboardShortcuts,
+            Self::Advanced,
+        ]
+    }
+
+    fn title(&self) -> String {
+        match self {
+            Self::Appearance => fl!("appearance"),
+            Self::Editor => fl!("editor"),
+            Self::KeyboardShortcuts => fl!("keyboard-shortcuts"),
+            Self::Advanced => fl!("advanced"),
+        }
+    }
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
-enum DialogPage {
+pub enum DialogPage {
+    CommandPalette,
+    Export(segmented_button::Entity, export::Format),
+    Filter(segmented_button::Entity, String),
+    KeyboardShortcuts(String),
+    NewFile(PathBuf, String),
+    NewFolder(PathBuf, String),
+    OpenLocation(String),
+    PasteHistory,
+    PlayMacro(String),
+    PrintSetup(segmented_button::Entity),
     PromptSaveClose(segmented_button::Entity),
+    PromptSaveElevated(segmented_button::Entity),
+    PromptSaveEncoding(segmented_button::Entity, &'static Encoding),
     PromptSaveQuit(Vec<segmented_button::Entity>),
+    QuickOpen,
+    Recover,
+    SymbolSearch,
+    Rename(PathBuf, String),
+    RenameSymbol(segmented_button::Entity, Cursor, String),
+    RunTask(PathBuf, Vec<tasks::ProjectTask>),
+}
+
+impl DialogPage {
+    /// Path that a dialog taking a file name will create, if the name is valid
+    fn name_target(&self) -> Option<PathBuf> {
+        let name = match self {
+            Self::NewFile(_, name) | Self::NewFolder(_, name) | Self::Rename(_, name) => name,
+            _ => return None,
+        };
+        if name.is_empty() || name == "." || name == ".." || name.contains('/') {
+            return None;
+        }
+        match self {
+            Self::Rename(path, _) => Some(path.with_file_name(name)),
+            Self::NewFile(parent, _) | Self::NewFolder(parent, _) => Some(parent.join(name)),
+            _ => None,
+        }
+    }
+
+    fn with_name(&self, name: String) -> Self {
+        match self {
+            Self::NewFile(parent, _) => Self::NewFile(parent.clone(), name),
+            Self::NewFolder(parent, _) => Self::NewFolder(parent.clone(), name),
+            Self::Rename(path, _) => Self::Rename(path.clone(), name),
+            Self::RenameSymbol(entity, cursor, _) => Self::RenameSymbol(*entity, *cursor, name),
+            Self::OpenLocation(_) => Self::OpenLocation(name),
+            Self::PlayMacro(_) => Self::PlayMacro(name),
+            Self::Filter(entity, _) => Self::Filter(*entity, name),
+            Self::KeyboardShortcuts(_) => Self::KeyboardShortcuts(name),
+            _ => self.clone(),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
@@ -446,38 +1296,172 @@ pub enum Find {
     FindAndReplace,
 }
 
+/// Something done while recording a macro
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum MacroStep {
+    Key(MacroKey),
+    Action(Action),
+}
+
 pub struct App {
     core: Core,
     about: About,
     nav_model: segmented_button::SingleSelectModel,
+    // Tabs of all windows
     tab_model: segmented_button::SingleSelectModel,
+    main_window: EditorWindow,
+    // Windows opened with new window or by moving a tab
+    windows: HashMap<window::Id, EditorWindow>,
+    // Window that the active tab and new tabs belong to, the main window if this is None
+    focused_window_opt: Option<window::Id>,
     config_handler: Option<cosmic_config::Config>,
     config: Config,
     config_state_handler: Option<cosmic_config::Config>,
     config_state: ConfigState,
     zoom_step_names: Vec<String>,
+    tab_width_names: Vec<String>,
+    indentation_names: Vec<String>,
+    line_ending_names: Vec<String>,
     zoom_steps: Vec<u16>,
+    word_wrap_column_names: Vec<String>,
+    word_wrap_columns: Vec<Option<u16>>,
+    auto_save_names: Vec<String>,
+    auto_save_intervals: Vec<Option<u64>>,
+    // Changed tabs that will be saved when the autosave timer fires
+    auto_save_pending: HashSet<segmented_button::Entity>,
+    // Tabs that are kept at the start of the tab bar and are not closed with the others
+    pinned_tabs: HashSet<segmented_button::Entity>,
+    // Tabs that `--wait` instances wait on, which return once all of theirs are closed
+    #[cfg(unix)]
+    waiters: Vec<(HashSet<segmented_button::Entity>, instance::Waiter)>,
+    // Tab that is written to standard output when it is closed, which then quits
+    stdout_tab_opt: Option<segmented_button::Entity>,
+    // Set when the tab could not be written to standard output, so the exit status is an error
+    stdout_failed: bool,
+    // Tab whose context menu was opened in the tab bar
+    tab_bar_context_opt: Option<segmented_button::Entity>,
+    // Text copied from several carets, which is pasted as a rectangle
+    block_clipboard_opt: Option<String>,
+    // Text killed in a tab and where, which the next kill there adds to like in Emacs
+    kill_opt: Option<(segmented_button::Entity, Cursor, String)>,
     key_binds: HashMap<KeyBind, Action>,
     app_themes: Vec<String>,
+    keymap_names: Vec<String>,
+    paper_names: Vec<String>,
     font_names: Vec<String>,
     font_size_names: Vec<String>,
     font_sizes: Vec<u16>,
     theme_names: Vec<String>,
+    syntax_names: Vec<String>,
     context_page: ContextPage,
     text_box_id: widget::Id,
+    split_opt: Option<Split>,
+    split_text_box_id: widget::Id,
+    markdown_preview_id: widget::Id,
     auto_scroll: Option<f32>,
     dialog_opt: Option<Dialog<Message>>,
     dialog_page_opt: Option<DialogPage>,
+    dialog_text_input_id: widget::Id,
+    print_options: PrintOptions,
+    // First character of the document in the print or PDF export dialog that cannot be printed
+    print_unsupported_opt: Option<char>,
+    document_type_search_id: widget::Id,
+    document_type_search_value: String,
     find_opt: Option<FindField>,
     find_replace_id: widget::Id,
     find_replace_value: String,
     find_search_id: widget::Id,
     find_search_value: String,
+    // Matches of the find query in the active tab
+    find_matches: Vec<(Cursor, Cursor)>,
+    find_regex_opt: Option<Regex>,
+    // Recent find and replace strings, newest first
+    find_history: VecDeque<String>,
+    replace_history: VecDeque<String>,
+    // Recent copied and cut texts, newest first
+    clipboard_history: VecDeque<String>,
+    // Position in the history of the find field that was focused last
+    find_history_index: Option<usize>,
+    find_replace_focused: bool,
+    // Replace all changes every open document
+    find_all_tabs: bool,
+    // Replacements made by the last replace all, by document title
+    find_replace_counts: Vec<(String, usize)>,
+    // Steps of the macro being recorded, and of the last one that was recorded
+    macro_recording: Option<Vec<MacroStep>>,
+    macro_steps: Vec<MacroStep>,
+    // Shell command that text was last filtered through
+    filter_command: String,
+    // Snippets of each language, read when they are first used
+    snippets: HashMap<String, Vec<Snippet>>,
     git_project_status: Option<Vec<(String, PathBuf, Vec<GitStatus>)>>,
     projects: Vec<(String, PathBuf)>,
+    project_replace_excluded: HashSet<(usize, usize)>,
+    project_replace_mode: bool,
+    project_replace_value: String,
     project_search_id: widget::Id,
     project_search_value: String,
     project_search_result: Option<ProjectSearchResult>,
+    // Incremented to cancel running searches
+    project_search_generation: Arc<AtomicU64>,
+    quick_open_id: widget::Id,
+    // Cached files of all projects
+    quick_open_index: Vec<PathBuf>,
+    quick_open_results: Vec<PathBuf>,
+    quick_open_selected: usize,
+    quick_open_value: String,
+    // Snapshots of processes that crashed, with the folders holding them
+    recovery_orphans: Vec<(PathBuf, Vec<Snapshot>)>,
+    // Whether this process has snapshots on disk
+    recovery_written: bool,
+    // Positions to return to before and after jumps to definitions and references
+    jumps_back: Vec<Jump>,
+    jumps_forward: Vec<Jump>,
+    // Results of the last find references
+    references: Vec<lsp::Location>,
+    // Tags of the projects, which are read again when symbols are searched
+    tags: Vec<Tag>,
+    symbol_search_results: Vec<usize>,
+    symbol_search_selected: usize,
+    symbol_search_value: String,
+    // Result of the last Vim command line, shown in the status line
+    ex_message_opt: Option<String>,
+    // Action waiting for a shortcut to be pressed in the keyboard shortcuts page
+    key_bind_recording_opt: Option<Action>,
+    // Action and shortcut that would replace the shortcut of another action
+    key_bind_conflict_opt: Option<(Action, KeyBind, Action)>,
+    key_bind_search_value: String,
+    // Glob pattern and syntax of the file type that is added in the settings
+    syntax_association_pattern: String,
+    syntax_association_opt: Option<usize>,
+    // Commands with their names in the current language
+    command_palette: Vec<(Action, String)>,
+    command_palette_results: Vec<usize>,
+    command_palette_selected: usize,
+    command_palette_value: String,
+    highlight_worker_opt: Option<highlight::Worker>,
+    // Names of the installed dictionaries
+    spell_dictionaries: Vec<String>,
+    // Words that are not marked as misspelled until the editor is closed
+    spelling_ignored: HashSet<String>,
+    // Words of the user dictionary
+    spelling_words: HashSet<String>,
+    // Output of the last task with the file location that each line links to, if any
+    task_output_shown: bool,
+    task_output: Vec<(String, Option<(PathBuf, Cursor)>)>,
+    // Task and project folder while it runs, it is stopped once this is cleared
+    task_running_opt: Option<(tasks::ProjectTask, PathBuf)>,
+    task_last_opt: Option<(tasks::ProjectTask, PathBuf)>,
+    task_session: u64,
+    task_scrollable_id: widget::Id,
+    // Only the text is shown in zen mode, the nav bar is opened again when it was open before
+    zen_mode: bool,
+    zen_nav_bar: bool,
+    // Mode of the window before it was made fullscreen
+    window_mode_opt: Option<window::Mode>,
+    settings_page_model: segmented_button::SingleSelectModel,
+    settings_window_opt: Option<window::Id>,
+    lsp_worker_opt: Option<lsp::Worker>,
     watcher_opt: Option<(
         notify::RecommendedWatcher,
         HashSet<(PathBuf, RecursiveMode)>,
@@ -491,6 +1475,77 @@ struct FindField {
     has_focus: bool,
 }
 
+/// Direction of a split, like in Vim horizontal splits stack the panes and vertical splits place
+/// them side by side
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum SplitAxis {
+    Horizontal,
+    Vertical,
+}
+
+/// A position that navigating back and forward returns to
+#[derive(Clone, Debug)]
+struct Jump {
+    entity: segmented_button::Entity,
+    // Closed tabs are opened again from their file
+    path_opt: Option<PathBuf>,
+    cursor: Cursor,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Split {
+    axis: SplitAxis,
+    // Tab shown in the pane without focus, the focused pane always shows the active tab
+    entity: segmented_button::Entity,
+    second_focused: bool,
+}
+
+/// A window with its own tab bar. The tabs themselves stay in the tab model of the app, so
+/// messages refer to them by the same entity from every window
+struct EditorWindow {
+    // Items in the order of the tab bar, with the entity of their tab as data
+    tab_bar: segmented_button::SingleSelectModel,
+    // Split of the window, kept here while another window has focus
+    split_opt: Option<Split>,
+}
+
+impl EditorWindow {
+    fn new() -> Self {
+        Self {
+            tab_bar: segmented_button::Model::builder().build(),
+            split_opt: None,
+        }
+    }
+
+    /// Item of the tab bar that shows a tab
+    fn item(&self, entity: segmented_button::Entity) -> Option<segmented_button::Entity> {
+        self.tab_bar
+            .iter()
+            .find(|item| self.tab_bar.data::<segmented_button::Entity>(*item) == Some(&entity))
+    }
+
+    /// Tab shown by an item of the tab bar
+    fn tab(&self, item: segmented_button::Entity) -> Option<segmented_button::Entity> {
+        self.tab_bar.data::<segmented_button::Entity>(item).copied()
+    }
+
+    /// Tabs in the order of the tab bar
+    fn tabs(&self) -> Vec<segmented_button::Entity> {
+        self.tab_bar
+            .iter()
+            .filter_map(|item| self.tab(item))
+            .collect()
+    }
+
+    fn active_tab(&self) -> Option<segmented_button::Entity> {
+        self.tab(self.tab_bar.active())
+    }
+
+    fn insert(&mut self, entity: segmented_button::Entity) -> segmented_button::Entity {
+        self.tab_bar.insert().data(entity).closable().id()
+    }
+}
+
 impl App {
     pub fn active_tab(&self) -> Option<&Tab> {
         self.tab_model.active_data()
@@ -500,7 +1555,52 @@ impl App {
         self.tab_model.active_data_mut()
     }
 
-    fn open_folder<P: AsRef<Path>>(&mut self, path: P, mut position: u16, indent: u16) {
+    /// Reload the children of a folder in the nav bar, if it is open
+    fn refresh_folder(&mut self, folder: &Path) {
+        let id_opt = self.nav_model.iter().find(|id| {
+            matches!(
+                self.nav_model.data::<ProjectNode>(*id),
+                Some(ProjectNode::Folder { path, open: true, .. }) if path == folder
+            )
+        });
+        if let Some(id) = id_opt {
+            let position = self.nav_model.position(id).unwrap_or(0);
+            let indent = self.nav_model.indent(id).unwrap_or(0);
+            while let Some(child_id) = self.nav_model.entity_at(position + 1) {
+                if self.nav_model.indent(child_id).unwrap_or(0) > indent {
+                    self.nav_model.remove(child_id);
+                } else {
+                    break;
+                }
+            }
+            self.open_folder(folder, position + 1, indent + 1);
+        }
+    }
+
+    /// Update tabs with paths inside of `from` after it was moved to `to`
+    fn move_tab_paths(&mut self, from: &Path, to: &Path) -> Task<Message> {
+        let mut tasks = Vec::new();
+        let entities: Vec<_> = self.tab_model.iter().collect();
+        for entity in entities {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                let new_path_opt = tab
+                    .path_opt
+                    .as_ref()
+                    .and_then(|path| path.strip_prefix(from).ok())
+                    .map(|relative| to.join(relative));
+                if let Some(new_path) = new_path_opt {
+                    tab.path_opt = Some(new_path);
+                    tab.update_syntax();
+                    tasks.push(self.update(Message::TabChanged(entity)));
+                }
+            }
+        }
+        self.update_watcher();
+        Task::batch(tasks)
+    }
+
+    /// Insert the children of a folder into the nav bar, returning the position after them
+    fn open_folder<P: AsRef<Path>>(&mut self, path: P, mut position: u16, indent: u16) -> u16 {
         let mut nodes = Vec::new();
         for entry_res in ignore::WalkBuilder::new(&path)
             .filter_entry(|entry| entry.file_name() != ".git")
@@ -540,7 +1640,22 @@ impl App {
 
         nodes.sort();
 
-        for node in nodes {
+        for mut node in nodes {
+            // Restore folders that were expanded previously, skipping links to parent folders
+            let expanded_opt = match &mut node {
+                ProjectNode::Folder {
+                    path: folder_path,
+                    open,
+                    ..
+                } if self.config_state.expanded_folders.contains(folder_path)
+                    && !path.as_ref().starts_with(&folder_path) =>
+                {
+                    *open = true;
+                    Some(folder_path.clone())
+                }
+                _ => None,
+            };
+
             self.nav_model
                 .insert()
                 .position(position)
@@ -550,7 +1665,13 @@ impl App {
                 .data(node);
 
             position += 1;
+
+            if let Some(expanded) = expanded_opt {
+                position = self.open_folder(expanded, position, indent + 1);
+            }
         }
+
+        position
     }
 
     pub fn open_project<P: AsRef<Path>>(&mut self, path: P) {
@@ -629,6 +1750,8 @@ impl App {
                     .activate()
                     .id();
                 self.update_watcher();
+                self.highlight_tab(entity);
+                self.lsp_open(entity);
                 Some(entity)
             }
         }
@@ -654,6 +1777,8 @@ impl App {
                 self.tab_model.data_set::<Tab>(entity, Tab::Editor(tab));
                 self.tab_model.activate(entity);
                 self.update_watcher();
+                self.highlight_tab(entity);
+                self.lsp_open(entity);
                 Some(entity)
             }
         }
@@ -688,1473 +1813,7614 @@ impl App {
                     return Some(NewTab::Exists(entity));
                 }
 
-                // Add to recent files, ensuring only one entry
-                self.config_state.recent_files.retain(|x| x != &canonical);
-                self.config_state
-                    .recent_files
-                    .push_front(canonical.to_path_buf());
-                self.config_state.recent_files.truncate(10);
-                self.save_config_state();
+                // This is saved with the recent files
+                let syntax_override = self.syntax_override(&canonical);
+                let spell_languages = self.spell_languages(&canonical);
+                self.add_recent_file(&canonical);
 
+                let large = fs::metadata(&canonical)
+                    .is_ok_and(|metadata| metadata.len() > tab::LARGE_FILE_SIZE);
                 let mut tab = EditorTab::new(&self.config);
-                tab.open(canonical);
+                if large {
+                    // Loading continues in a subscription
+                    tab.open_large(canonical);
+                } else {
+                    tab.open(canonical);
+                }
+                if syntax_override.is_some() && !large {
+                    tab.syntax_override = syntax_override;
+                    tab.update_syntax();
+                }
+                if let Some(languages) = spell_languages {
+                    tab.spell_languages = languages;
+                }
                 Some(NewTab::Tab(tab))
             }
             None => Some(NewTab::Tab(EditorTab::new(&self.config))),
         }
     }
 
-    fn update_config(&mut self) -> Task<Message> {
-        //TODO: provide iterator over data
-        let entities: Vec<_> = self.tab_model.iter().collect();
-        for entity in entities {
-            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
-                tab.set_config(&self.config);
-            }
+    fn add_recent_file(&mut self, path: &Path) {
+        // Add to recent files, ensuring only one entry
+        self.config_state.recent_files.retain(|x| x != path);
+        self.config_state
+            .recent_files
+            .push_front(path.to_path_buf());
+        self.config_state.recent_files.truncate(10);
+        self.save_config_state();
+    }
+
+    // Find the syntax chosen for a file, moving it to the front so it is forgotten last
+    fn syntax_override(&mut self, path: &Path) -> Option<String> {
+        let syntax_overrides = &mut self.config_state.syntax_overrides;
+        let position = syntax_overrides.iter().position(|(x, _)| x == path)?;
+        let entry = syntax_overrides.remove(position)?;
+        let name = entry.1.clone();
+        syntax_overrides.push_front(entry);
+        Some(name)
+    }
+
+    fn set_syntax_override(&mut self, path: PathBuf, syntax_override: Option<String>) {
+        // Ensure only one entry
+        self.config_state
+            .syntax_overrides
+            .retain(|(x, _)| *x != path);
+        if let Some(name) = syntax_override {
+            self.config_state.syntax_overrides.push_front((path, name));
+            self.config_state
+                .syntax_overrides
+                .truncate(SYNTAX_OVERRIDES_LEN);
         }
-        cosmic::command::set_theme(self.config.app_theme.theme())
+        self.save_config_state();
     }
 
-    fn update_render_active_tab_zoom(&mut self, zoom_message: Message) -> Task<Message> {
-        if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
-            let current_zoom_adj = tab.zoom_adj();
-            match zoom_message {
-                Message::ZoomIn => tab.set_zoom_adj(current_zoom_adj.saturating_add(1)),
-                Message::ZoomOut => tab.set_zoom_adj(current_zoom_adj.saturating_sub(1)),
-                _ => {}
-            }
-            let entities: Vec<_> = self.tab_model.iter().collect();
-            for entity in entities {
-                if self.tab_model.is_active(entity) {
-                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
-                        tab.set_config(&self.config);
-                    }
-                }
-            }
+    // Find the dictionaries chosen for a file, moving them to the front so they are forgotten last
+    fn spell_languages(&mut self, path: &Path) -> Option<Vec<String>> {
+        let spell_languages = &mut self.config_state.spell_languages;
+        let position = spell_languages.iter().position(|(x, _)| x == path)?;
+        let entry = spell_languages.remove(position)?;
+        let languages = entry.1.clone();
+        spell_languages.push_front(entry);
+        Some(languages)
+    }
+
+    fn set_spell_languages(&mut self, path: PathBuf, languages: Vec<String>) {
+        // Ensure only one entry
+        self.config_state
+            .spell_languages
+            .retain(|(x, _)| *x != path);
+        if !languages.is_empty() {
+            self.config_state
+                .spell_languages
+                .push_front((path, languages));
+            self.config_state
+                .spell_languages
+                .truncate(SPELL_LANGUAGES_LEN);
         }
-        Task::none()
+        self.save_config_state();
     }
 
-    fn reset_tabs_zoom(&mut self) {
-        let entities: Vec<_> = self.tab_model.iter().collect();
-        for entity in entities {
-            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
-                tab.set_zoom_adj(0);
-            }
+    // Remove recent files that were moved or deleted, so the menu does not have to check them
+    fn prune_recent_files(&mut self) {
+        let len = self.config_state.recent_files.len();
+        self.config_state.recent_files.retain(|path| path.exists());
+        if self.config_state.recent_files.len() != len {
+            self.save_config_state();
         }
     }
 
-    fn save_config_state(&mut self) {
-        if let Some(ref config_state_handler) = self.config_state_handler {
-            if let Err(err) = self.config_state.write_entry(config_state_handler) {
-                log::error!("failed to save config_state: {}", err);
+    // Close tabs without unsaved changes, then ask to save the first tab that has them
+    fn close_tabs(&mut self, entities: Vec<segmented_button::Entity>) -> Task<Message> {
+        // Closing a tab activates its neighbor, so keep the active tab if it stays open
+        let active = self.tab_model.active();
+        let mut tasks = Vec::with_capacity(entities.len() + 2);
+        let mut changed_opt = None;
+        for entity in entities {
+            match self.tab_model.data::<Tab>(entity) {
+                Some(Tab::Editor(tab)) if tab.changed() => {
+                    changed_opt.get_or_insert(entity);
+                }
+                _ => tasks.push(self.update(Message::TabCloseForce(entity))),
             }
         }
+        if self.tab_model.position(active).is_some() {
+            self.tab_model.activate(active);
+            tasks.push(self.update_tab());
+        }
+        if let Some(entity) = changed_opt {
+            tasks.push(self.update(Message::TabClose(entity)));
+        }
+        Task::batch(tasks)
     }
 
-    fn update_dialogs(&mut self) -> Task<Message> {
-        match self.dialog_page_opt {
-            Some(DialogPage::PromptSaveClose(entity)) => {
-                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
-                    if !tab.changed() {
-                        // Tab has been saved, close it (which also closes this dialog)
-                        return self.update(Message::TabCloseForce(entity));
-                    }
-                } else {
-                    // Tab no longer found, close dialog
-                    self.dialog_page_opt = None;
-                }
+    fn add_closed_tab(&mut self, session_tab: SessionTab) {
+        // Add to closed tabs, ensuring only one entry
+        self.config_state
+            .closed_tabs
+            .retain(|x| x.path != session_tab.path);
+        self.config_state.closed_tabs.push_front(session_tab);
+        self.config_state.closed_tabs.truncate(CLOSED_TABS_LEN);
+        self.save_config_state();
+    }
+
+    fn add_find_history(&mut self) {
+        fn push(history: &mut VecDeque<String>, value: &str) {
+            if value.is_empty() {
+                return;
             }
-            Some(DialogPage::PromptSaveQuit(ref _entities)) => {
-                let mut unsaved = Vec::new();
-                for entity in self.tab_model.iter() {
-                    if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
-                        if tab.changed() {
-                            unsaved.push(entity);
-                        }
+            history.retain(|x| x != value);
+            history.push_front(value.to_string());
+            history.truncate(FIND_HISTORY_LEN);
+        }
+
+        push(&mut self.find_history, &self.find_search_value);
+        if self.find_opt.is_some_and(|find| find.replace) {
+            push(&mut self.replace_history, &self.find_replace_value);
+        }
+        self.find_history_index = None;
+        if self.config.save_find_history
+            && (self.config_state.find_history != self.find_history
+                || self.config_state.replace_history != self.replace_history)
+        {
+            self.config_state.find_history = self.find_history.clone();
+            self.config_state.replace_history = self.replace_history.clone();
+            self.save_config_state();
+        }
+    }
+
+    // Remember text that was copied or cut so that it can be pasted from the history
+    fn add_clipboard_history(&mut self, text: &str) {
+        if text.is_empty() || self.config.clipboard_history_size == 0 {
+            return;
+        }
+        self.clipboard_history.retain(|x| x != text);
+        self.clipboard_history.push_front(text.to_string());
+        self.clipboard_history
+            .truncate(self.config.clipboard_history_size);
+        if self.config.save_clipboard_history
+            && self.config_state.clipboard_history != self.clipboard_history
+        {
+            self.config_state.clipboard_history = self.clipboard_history.clone();
+            self.save_config_state();
+        }
+    }
+
+    // Step through the history of the focused find field, older if `older` is true
+    fn recall_find_history(&mut self, older: bool) -> Task<Message> {
+        let history = if self.find_replace_focused {
+            &self.replace_history
+        } else {
+            &self.find_history
+        };
+        let index_opt = match (self.find_history_index, older) {
+            (None, true) => (!history.is_empty()).then_some(0),
+            (None, false) => None,
+            (Some(index), true) => Some((index + 1).min(history.len().saturating_sub(1))),
+            (Some(index), false) => index.checked_sub(1),
+        };
+        let value = index_opt
+            .and_then(|index| history.get(index).cloned())
+            .unwrap_or_default();
+        let task = if self.find_replace_focused {
+            self.update(Message::FindReplaceValueChanged(value))
+        } else {
+            self.update(Message::FindSearchValueChanged(value))
+        };
+        self.find_history_index = index_opt;
+        task
+    }
+
+    fn update_config(&mut self) -> Task<Message> {
+        self.key_binds = key_binds(
+            self.config.keymap,
+            &self.config.keybinds,
+            &self.config.keybinds_removed,
+        );
+        // Text always wraps at the zen mode width
+        let mut config = self.config.clone();
+        config.word_wrap |= self.zen_mode;
+        //TODO: provide iterator over data
+        let entities: Vec<_> = self.tab_model.iter().collect();
+        for entity in entities {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                tab.set_config(&config);
+            }
+            self.highlight_tab(entity);
+            self.lsp_open(entity);
+        }
+        // Code blocks are highlighted with the syntax theme
+        self.update_markdown_preview(self.tab_model.active());
+        cosmic::command::set_theme(self.config.app_theme.theme())
+    }
+
+    // Detect the syntax of open files again, after the associations changed
+    fn update_syntax_associations(&mut self) -> Task<Message> {
+        let task = self.update_config();
+        let entities: Vec<_> = self.tab_model.iter().collect();
+        for entity in entities {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                tab.update_syntax();
+            }
+            self.highlight_tab(entity);
+        }
+        task
+    }
+
+    fn update_render_active_tab_zoom(&mut self, zoom_message: Message) -> Task<Message> {
+        if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
+            let current_zoom_adj = tab.zoom_adj();
+            match zoom_message {
+                Message::ZoomIn => tab.set_zoom_adj(current_zoom_adj.saturating_add(1)),
+                Message::ZoomOut => tab.set_zoom_adj(current_zoom_adj.saturating_sub(1)),
+                Message::ZoomReset => tab.set_zoom_adj(0),
+                _ => {}
+            }
+            let entities: Vec<_> = self.tab_model.iter().collect();
+            for entity in entities {
+                if self.tab_model.is_active(entity) {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        tab.set_config(&self.config);
                     }
                 }
-                if unsaved.is_empty() {
-                    // All tabs have been saved, we can exit
-                    return self.update(Message::QuitForce);
-                } else {
-                    // Update dialog
-                    self.dialog_page_opt = Some(DialogPage::PromptSaveQuit(unsaved));
-                }
             }
-            None => {}
         }
         Task::none()
     }
 
-    fn update_focus(&self) -> Task<Message> {
-        if self.core.window.show_context {
-            match self.context_page {
-                ContextPage::ProjectSearch => {
-                    widget::text_input::focus(self.project_search_id.clone())
-                }
-                _ => Task::none(),
+    fn reset_tabs_zoom(&mut self) {
+        let entities: Vec<_> = self.tab_model.iter().collect();
+        for entity in entities {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                tab.set_zoom_adj(0);
             }
-        } else if self.find_opt.is_some_and(
-            |FindField {
-                 replace: _,
-                 has_focus,
-             }| has_focus,
-        ) {
-            widget::text_input::focus(self.find_search_id.clone())
-        } else {
-            widget::text_input::focus(self.text_box_id.clone())
         }
     }
 
-    fn update_nav_bar_active(&mut self) {
-        let tab_path_opt = match self.active_tab() {
-            Some(Tab::Editor(tab)) => tab.path_opt.clone(),
-            Some(Tab::GitDiff(tab)) => Some(tab.diff.path.clone()),
-            None => None,
+    fn project_replace(&mut self, selected_only: bool) -> Task<Message> {
+        let Some(mut project_search_result) = self.project_search_result.take() else {
+            return Task::none();
+        };
+        let regex = match Regex::new(&project_search_result.value) {
+            Ok(ok) => ok,
+            Err(err) => {
+                log::error!(
+                    "failed to create regex with value {:?}: {}",
+                    project_search_result.value,
+                    err
+                );
+                self.project_search_result = Some(project_search_result);
+                return Task::none();
+            }
         };
 
-        // Locate tree node to activate
-        let mut active_id = segmented_button::Entity::default();
+        let mut tasks = Vec::new();
+        for (file_i, file_search_result) in project_search_result.files.iter_mut().enumerate() {
+            let (replaced, kept): (Vec<_>, Vec<_>) = file_search_result
+                .lines
+                .drain(..)
+                .enumerate()
+                .partition(|(line_i, _)| {
+                    !selected_only || !self.project_replace_excluded.contains(&(file_i, *line_i))
+                });
+            file_search_result.lines = kept.into_iter().map(|(_, line)| line).collect();
+            if replaced.is_empty() {
+                continue;
+            }
+            let replaced: Vec<_> = replaced.into_iter().map(|(_, line)| line).collect();
 
-        if let Some(tab_path) = tab_path_opt {
-            // Automatically expand tree to find and select active file
-            loop {
-                let mut expand_opt = None;
-                for id in self.nav_model.iter() {
-                    if let Some(node) = self.nav_model.data(id) {
-                        match node {
-                            ProjectNode::Folder { path, open, .. } => {
-                                if tab_path.starts_with(path) && !*open {
-                                    expand_opt = Some(id);
-                                    break;
-                                }
-                            }
-                            ProjectNode::File { path, .. } => {
-                                if path == &tab_path {
-                                    active_id = id;
-                                    break;
-                                }
-                            }
-                        }
+            // Open documents are changed in the editor so the replacement can be undone
+            let path = fs::canonicalize(&file_search_result.path)
+                .unwrap_or_else(|_| file_search_result.path.clone());
+            let entity_opt =
+                self.tab_model
+                    .iter()
+                    .find(|entity| match self.tab_model.data::<Tab>(*entity) {
+                        Some(Tab::Editor(tab)) => tab.path_opt.as_ref() == Some(&path),
+                        _ => false,
+                    });
+            match entity_opt {
+                Some(entity) => {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                        let count =
+                            tab.replace_matches(&regex, &self.project_replace_value, &replaced);
+                        log::info!("replaced {} lines in {:?}", count, path);
                     }
+                    tasks.push(self.update(Message::TabChanged(entity)));
                 }
-                match expand_opt {
-                    Some(id) => {
-                        //TODO: can this be optimized?
-                        // Task not used becuase opening a folder just returns Task::none
-                        let _ = self.on_nav_select(id);
+                None => match file_search_result.replace_on_disk(
+                    &regex,
+                    &self.project_replace_value,
+                    &replaced,
+                    self.config.atomic_save,
+                ) {
+                    Ok(count) => {
+                        log::info!("replaced {} lines in {:?}", count, path);
                     }
-                    None => {
-                        break;
+                    Err(err) => {
+                        log::error!("failed to replace in {:?}: {}", path, err);
                     }
-                }
+                },
             }
         }
-        self.nav_model.activate(active_id);
-    }
 
-    fn update_nav_bar_placeholder(&mut self) {
-        // Remove all placeholder items
-        let mut remove = Vec::new();
-        for entity in self.nav_model.iter() {
-            if self.nav_model.data::<ProjectNode>(entity).is_none() {
-                remove.push(entity);
+        // Only the matches that were not replaced remain, and they stay unselected
+        project_search_result
+            .files
+            .retain(|file_search_result| !file_search_result.lines.is_empty());
+        self.project_replace_excluded.clear();
+        for (file_i, file_search_result) in project_search_result.files.iter().enumerate() {
+            for line_i in 0..file_search_result.lines.len() {
+                self.project_replace_excluded.insert((file_i, line_i));
             }
         }
-        for entity in remove {
-            self.nav_model.remove(entity);
-        }
+        self.project_search_result = Some(project_search_result);
 
-        // Add button to open a project if none provided
-        if self.nav_model.iter().next().is_none() {
-            self.nav_model
-                .insert()
-                .icon(icon_cache_get("folder-open-symbolic", 16))
-                .text(fl!("open-project"));
-        }
+        Task::batch(tasks)
     }
 
-    // Call this any time the tab changes
-    pub fn update_tab(&mut self) -> Task<Message> {
-        self.update_nav_bar_active();
-
-        let title = match self.active_tab() {
-            Some(tab) => {
-                if let Tab::Editor(inner) = tab {
-                    // Force redraw on tab switches
-                    inner.editor.lock().unwrap().set_redraw(true);
-                }
-                tab.title()
+    /// Path shown in quick open, relative to its project if there is one
+    fn quick_open_label(&self, path: &Path) -> String {
+        for (project_name, project_path) in self.projects.iter() {
+            if let Ok(relative) = path.strip_prefix(project_path) {
+                return format!("{}/{}", project_name, relative.display());
             }
-            None => "No Open File".to_string(),
-        };
-
-        let window_title = format!("{title} - {}", fl!("cosmic-text-editor"));
-        Task::batch([
-            if let Some(window_id) = self.core.main_window_id() {
-                self.set_window_title(window_title, window_id)
-            } else {
-                Task::none()
-            },
-            self.update_focus(),
-        ])
+        }
+        format!("{}", path.display())
     }
 
-    fn update_watcher(&mut self) {
-        if let Some((mut watcher, old_paths)) = self.watcher_opt.take() {
-            let mut new_paths = HashSet::new();
+    fn update_command_palette_results(&mut self) {
+        self.command_palette_results =
+            command_palette::search(&self.command_palette, &self.command_palette_value);
+        self.command_palette_selected = 0;
+    }
 
-            for (_, project_path) in self.projects.iter() {
-                new_paths.insert((project_path.clone(), RecursiveMode::Recursive));
-            }
+    fn update_symbol_search_results(&mut self) {
+        let mut results: Vec<(i64, usize)> = self
+            .tags
+            .iter()
+            .enumerate()
+            .filter_map(|(i, tag)| Some((fuzzy::score(&self.symbol_search_value, &tag.name)?, i)))
+            .collect();
+        results.sort_by(|a, b| {
+            b.0.cmp(&a.0)
+                .then_with(|| self.tags[a.1].name.cmp(&self.tags[b.1].name))
+        });
+        results.truncate(50);
+
+        self.symbol_search_results = results.into_iter().map(|(_, i)| i).collect();
+        self.symbol_search_selected = 0;
+    }
 
-            'tabs: for entity in self.tab_model.iter() {
-                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
-                    if let Some(path) = &tab.path_opt {
-                        for (_, project_path) in self.projects.iter() {
-                            if path.starts_with(&project_path) {
-                                // Do not watch tabs inside of already watched projects
-                                continue 'tabs;
-                            }
-                        }
-                        new_paths.insert((path.to_path_buf(), RecursiveMode::NonRecursive));
+    // Read the tags files of the projects in the background
+    fn load_tags(&self, then: impl FnOnce(Vec<Tag>) -> Message + Send + 'static) -> Task<Message> {
+        let projects = self.projects.clone();
+        Task::perform(
+            async move {
+                let task_res = tokio::task::spawn_blocking(move || {
+                    let mut tags = Vec::new();
+                    for (_, project_path) in projects.iter() {
+                        tags.extend(tags::load(project_path));
+                    }
+                    tags
+                })
+                .await;
+                match task_res {
+                    Ok(tags) => action::app(then(tags)),
+                    Err(err) => {
+                        log::error!("failed to run tags task: {}", err);
+                        action::none()
                     }
                 }
-            }
+            },
+            |x| x,
+        )
+    }
 
-            // Unwatch paths no longer used
-            for path_mode in old_paths.iter() {
-                if !new_paths.contains(path_mode) {
-                    let (path, _) = path_mode;
-                    match watcher.unwatch(path) {
-                        Ok(()) => {
-                            log::debug!("unwatching {:?}", path);
-                        }
-                        Err(err) => {
-                            log::debug!("failed to unwatch {:?}: {}", path, err);
+    // Find definitions in the tags files
+    fn go_to_tags(&self, name: String) -> Task<Message> {
+        let projects = self.projects.clone();
+        Task::perform(
+            async move {
+                let task_res = tokio::task::spawn_blocking(move || {
+                    let mut locations = Vec::new();
+                    for (_, project_path) in projects.iter() {
+                        for tag in tags::load(project_path) {
+                            if tag.name == name
+                                && let Some(location) = tag.locate()
+                            {
+                                locations.push(location);
+                            }
                         }
                     }
+                    locations
+                })
+                .await;
+                match task_res {
+                    Ok(locations) => action::app(Message::TagLocations(locations)),
+                    Err(err) => {
+                        log::error!("failed to run tags task: {}", err);
+                        action::none()
+                    }
                 }
+            },
+            |x| x,
+        )
+    }
+
+    fn update_quick_open_results(&mut self) {
+        // Recent files are listed first when their score is the same
+        let mut seen = HashSet::new();
+        let mut results: Vec<(i64, usize, PathBuf)> = Vec::new();
+        let candidates = self
+            .config_state
+            .recent_files
+            .iter()
+            .chain(self.quick_open_index.iter());
+        for (i, path) in candidates.enumerate() {
+            if !seen.insert(path) {
+                continue;
+            }
+            let label = self.quick_open_label(path);
+            if let Some(score) = fuzzy::score(&self.quick_open_value, &label) {
+                results.push((score, i, path.clone()));
             }
+        }
+        results.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        results.truncate(50);
 
-            // Watch new paths
-            for path_mode in new_paths.iter() {
-                if !old_paths.contains(path_mode) {
-                    let (path, mode) = path_mode;
-                    match watcher.watch(path, *mode) {
-                        Ok(()) => {
-                            log::debug!("watching {:?} {:?}", path, mode);
-                        }
-                        Err(err) => {
-                            log::debug!("failed to watch {:?} {:?}: {}", path, mode, err);
-                        }
-                    }
+        self.quick_open_results = results.into_iter().map(|(_, _, path)| path).collect();
+        self.quick_open_selected = 0;
+    }
+
+    /// Record the open files in the config state so they can be reopened on the next launch
+    fn save_session(&mut self) {
+        let mut session = Session::default();
+        // Tabs of other windows are reopened after those of the main window
+        let entities: Vec<_> = iter::once(&self.main_window)
+            .chain(self.windows.values())
+            .flat_map(EditorWindow::tabs)
+            .collect();
+        for entity in entities {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                let Some(session_tab) = tab.session_tab() else {
+                    continue;
+                };
+                if entity == self.tab_model.active() {
+                    session.active = session.tabs.len();
                 }
+                session.tabs.push(session_tab);
             }
-
-            self.watcher_opt = Some((watcher, new_paths));
         }
+        self.config_state.session = session;
+        self.save_config_state();
     }
 
-    fn document_statistics(&self) -> Element<'_, Message> {
-        //TODO: calculate in the background
-        let mut character_count = 0;
-        let mut character_count_no_spaces = 0;
-        let mut line_count = 0;
-        let mut word_count = 0;
-
-        if let Some(Tab::Editor(tab)) = self.active_tab() {
-            let editor = tab.editor.lock().unwrap();
-            editor.with_buffer(|buffer| {
-                line_count = buffer.lines.len();
-                for line in buffer.lines.iter() {
-                    let text = line.text();
-                    let mut last_whitespace = true;
-
-                    // Count graphemes instead of Unicode scalar values for accurate character count
-                    for grapheme in text.graphemes(true) {
-                        character_count += 1;
-                        let is_whitespace = grapheme.chars().all(|c| c.is_whitespace());
-                        if !is_whitespace {
-                            character_count_no_spaces += 1;
-                            if last_whitespace {
-                                word_count += 1;
-                            }
-                        }
-                        last_whitespace = is_whitespace;
-                    }
-                }
-            });
+    // Open the file of a tab from the session or closed tabs, with its cursor and scroll position
+    fn open_session_tab(
+        &mut self,
+        session_tab: SessionTab,
+    ) -> Option<(segmented_button::Entity, Task<Message>)> {
+        // Skip files that were removed since the tab was closed
+        if !session_tab.path.is_file() {
+            return None;
         }
+        let entity = self.open_tab(Some(session_tab.path))?;
 
-        widget::settings::view_column(vec![
-            widget::settings::section()
-                .add(
-                    widget::settings::item::builder(fl!("word-count"))
-                        .control(widget::text(word_count.to_string())),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("character-count"))
-                        .control(widget::text(character_count.to_string())),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("character-count-no-spaces"))
-                        .control(widget::text(character_count_no_spaces.to_string())),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("line-count"))
-                        .control(widget::text(line_count.to_string())),
-                )
-                .into(),
-        ])
-        .into()
+        // The file may have changed since the tab was closed, so keep the cursor in bounds
+        let cursor = match self.tab_model.data_mut::<Tab>(entity) {
+            Some(Tab::Editor(tab)) => {
+                tab.set_zoom_adj(session_tab.zoom_adj);
+                tab.editor.lock().unwrap().with_buffer(|buffer| {
+                    let line = session_tab
+                        .cursor_line
+                        .min(buffer.lines.len().saturating_sub(1));
+                    let text = buffer.lines.get(line).map_or("", |line| line.text());
+                    let mut index = session_tab.cursor_index.min(text.len());
+                    while !text.is_char_boundary(index) {
+                        index -= 1;
+                    }
+                    Cursor::new(line, index)
+                })
+            }
+            _ => return None,
+        };
+        let scroll_line = session_tab.scroll_line;
+        let task = Task::batch([
+            Task::perform(
+                async move { action::app(Message::TabSetCursor(entity, cursor)) },
+                |x| x,
+            ),
+            Task::perform(
+                async move { action::app(Message::TabSetScroll(entity, scroll_line)) },
+                |x| x,
+            ),
+        ]);
+        Some((entity, task))
     }
 
-    fn git_management(&self) -> Element<'_, Message> {
-        let spacing = self.core().system_theme().cosmic().spacing;
-
-        if let Some(project_status) = &self.git_project_status {
-            let (success_color, destructive_color, warning_color) = {
-                let cosmic_theme = self.core().system_theme().cosmic();
-                (
-                    cosmic_theme.success_color(),
-                    cosmic_theme.destructive_color(),
-                    cosmic_theme.warning_color(),
-                )
-            };
-            let added = || widget::text("[+]").class(theme::Text::Color(success_color.into()));
-            let deleted =
-                || widget::text("[-]").class(theme::Text::Color(destructive_color.into()));
-            let modified = || widget::text("[*]").class(theme::Text::Color(warning_color.into()));
-
-            let mut items =
-                Vec::with_capacity(project_status.len().saturating_mul(3).saturating_add(1));
-            items.push(widget::text(fl!("git-management-description")).into());
-
-            for (project_name, project_path, status) in project_status.iter() {
-                let mut unstaged_items = Vec::with_capacity(status.len());
-                let mut staged_items = Vec::with_capacity(status.len());
-                for item in status.iter() {
-                    let relative_path = match item.path.strip_prefix(project_path) {
-                        Ok(ok) => ok,
-                        Err(err) => {
-                            log::warn!(
-                                "failed to find relative path of {:?} in project {:?}: {}",
-                                item.path,
-                                project_path,
-                                err
-                            );
-                            &item.path
-                        }
-                    };
-
-                    let text = match &item.old_path {
-                        Some(old_path) => {
-                            let old_relative_path = match old_path.strip_prefix(project_path) {
-                                Ok(ok) => ok,
-                                Err(err) => {
-                                    log::warn!(
-                                        "failed to find relative path of {:?} in project {:?}: {}",
-                                        old_path,
-                                        project_path,
-                                        err
-                                    );
-                                    old_path
-                                }
-                            };
-                            format!(
-                                "{} -> {}",
-                                old_relative_path.display(),
-                                relative_path.display()
-                            )
-                        }
-                        None => format!("{}", relative_path.display()),
-                    };
-
-                    let unstaged_opt = match item.unstaged {
-                        GitStatusKind::Unmodified => None,
-                        GitStatusKind::Modified => Some(modified()),
-                        GitStatusKind::FileTypeChanged => Some(modified()),
-                        GitStatusKind::Added => Some(added()),
-                        GitStatusKind::Deleted => Some(deleted()),
-                        GitStatusKind::Renamed => Some(modified()), //TODO
-                        GitStatusKind::Copied => Some(modified()),  // TODO
-                        GitStatusKind::Updated => Some(modified()),
-                        GitStatusKind::Untracked => Some(added()),
-                        GitStatusKind::SubmoduleModified => Some(modified()),
-                    };
+    // Open a remote file in a new tab, its contents are read in the background
+    fn open_remote(&mut self, uri: String) -> Option<(segmented_button::Entity, Task<Message>)> {
+        for entity in self.tab_model.iter() {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                && tab.uri_opt.as_ref() == Some(&uri)
+            {
+                self.tab_model.activate(entity);
+                return Some((entity, Task::none()));
+            }
+        }
 
-                    if let Some(icon) = unstaged_opt {
-                        unstaged_items.push(
-                            widget::button::custom(
-                                widget::row::with_children(vec![
-                                    icon.into(),
-                                    widget::text(text.clone()).into(),
-                                    widget::horizontal_space().into(),
-                                    widget::button::standard(fl!("stage"))
-                                        .on_press(Message::GitStage(
-                                            project_path.clone(),
-                                            item.path.clone(),
-                                        ))
-                                        .into(),
-                                ])
-                                .align_y(Alignment::Center)
-                                .spacing(spacing.space_xs),
-                            )
-                            .on_press(Message::PrepareGitDiff(
-                                project_path.clone(),
-                                item.path.clone(),
-                                false,
-                            ))
-                            .class(theme::Button::AppletMenu)
-                            .width(Length::Fill)
-                            .into(),
-                        );
+        let mut tab = EditorTab::new(&self.config);
+        tab.open_remote(uri.clone());
+        let entity = self
+            .tab_model
+            .insert()
+            .text(tab.title())
+            .icon(tab.icon(16))
+            .data::<Tab>(Tab::Editor(tab))
+            .closable()
+            .activate()
+            .id();
+        let task = Task::perform(
+            async move {
+                let bytes_opt = match remote::read(uri.clone()).await {
+                    Ok(bytes) => Some(bytes),
+                    Err(err) => {
+                        log::error!("failed to open {}: {}", uri, err);
+                        None
                     }
+                };
+                action::app(Message::TabLoadRemote(entity, bytes_opt))
+            },
+            |x| x,
+        );
+        Some((entity, task))
+    }
 
-                    let staged_opt = match item.staged {
-                        GitStatusKind::Unmodified => None,
-                        GitStatusKind::Modified => Some(modified()),
-                        GitStatusKind::FileTypeChanged => Some(modified()),
-                        GitStatusKind::Added => Some(added()),
-                        GitStatusKind::Deleted => Some(deleted()),
-                        GitStatusKind::Renamed => Some(modified()), //TODO
-                        GitStatusKind::Copied => Some(modified()),  // TODO
-                        GitStatusKind::Updated => Some(modified()),
-                        GitStatusKind::Untracked => None,
-                        GitStatusKind::SubmoduleModified => Some(modified()),
-                    };
-
-                    if let Some(icon) = staged_opt {
-                        staged_items.push(
-                            widget::button::custom(
-                                widget::row::with_children(vec![
-                                    icon.into(),
-                                    widget::text(text.clone()).into(),
-                                    widget::horizontal_space().into(),
-                                    widget::button::standard(fl!("unstage"))
-                                        .on_press(Message::GitUnstage(
-                                            project_path.clone(),
-                                            item.path.clone(),
-                                        ))
-                                        .into(),
-                                ])
-                                .align_y(Alignment::Center)
-                                .spacing(spacing.space_xs),
-                            )
-                            .on_press(Message::PrepareGitDiff(
-                                project_path.clone(),
-                                item.path.clone(),
-                                true,
-                            ))
-                            .class(theme::Button::AppletMenu)
-                            .width(Length::Fill)
-                            .into(),
-                        );
+    // Open the files and projects from the command line, here or of a later instance
+    // Returns the tasks to run, and the tabs of the files so that `--wait` can wait on them
+    fn open_args(
+        &mut self,
+        args: Vec<String>,
+    ) -> (Vec<Task<Message>>, HashSet<segmented_button::Entity>) {
+        let mut tasks = Vec::new();
+        let mut entities = HashSet::new();
+        let mut goto_line_opt = None;
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            // Handled before the app starts, see `instance::send` and `Flags`
+            if arg == "-" || arg == "--new-window" || arg == "--wait" {
+                continue;
+            }
+            if arg == "--output" {
+                args.next();
+                continue;
+            }
+            // `--diff a b` opens two files side by side
+            if arg == "--diff" {
+                match (args.next(), args.next()) {
+                    (Some(old), Some(new)) => {
+                        tasks.push(self.compare_files(old.into(), new.into()));
                     }
+                    _ => log::warn!("--diff needs two files"),
                 }
-
-                items.push(widget::text::heading(project_name.clone()).into());
-
-                if !unstaged_items.is_empty() {
-                    items.push(
-                        widget::settings::section()
-                            .title(fl!("unstaged-changes"))
-                            .add(widget::column::with_children(unstaged_items))
-                            .into(),
-                    );
+                continue;
+            }
+            // `+line file` opens the file at a line, like in Vim
+            if let Some(line) = arg.strip_prefix('+').and_then(|line| line.parse().ok()) {
+                goto_line_opt = Some(line);
+                continue;
+            }
+            // Remote files are read with GIO
+            if remote::is_uri(&arg) {
+                if let Some((entity, task)) = self.open_remote(arg) {
+                    entities.insert(entity);
+                    tasks.push(task);
                 }
-
-                if !staged_items.is_empty() {
-                    items.push(
-                        widget::settings::section()
-                            .title(fl!("staged-changes"))
-                            .add(widget::column::with_children(staged_items))
-                            .into(),
-                    );
+                continue;
+            }
+            // `--goto file:line:column` reads the location even if a file has the whole name
+            let (path, location_opt) = if arg == "--goto" {
+                match args.next() {
+                    Some(arg) => parse_location(&arg),
+                    None => {
+                        log::warn!("--goto needs a file");
+                        continue;
+                    }
+                }
+            } else if Path::new(&arg).exists() {
+                (PathBuf::from(arg), None)
+            } else {
+                parse_location(&arg)
+            };
+            let location_opt = location_opt.or(goto_line_opt.take().map(|line| (line, 1)));
+            if path.is_dir() {
+                self.open_project(path);
+            } else if let Some(entity) = self.open_tab(Some(path)) {
+                entities.insert(entity);
+                if let Some((line, column)) = location_opt {
+                    tasks.push(self.tab_go_to(entity, line, column));
                 }
             }
+        }
+        (tasks, entities)
+    }
 
-            widget::column::with_children(items)
-                .spacing(spacing.space_s)
-                .padding([spacing.space_xxs, spacing.space_none])
-                .into()
+    // Keep a `--wait` instance waiting until the tabs of its files are closed
+    #[cfg(unix)]
+    fn add_waiter(
+        &mut self,
+        entities: HashSet<segmented_button::Entity>,
+        waiter: instance::Waiter,
+    ) {
+        if entities.is_empty() {
+            waiter.finish();
         } else {
-            widget::column::with_children(vec![
-                widget::text(fl!("git-management-description")).into(),
-                widget::text(fl!("git-management-loading")).into(),
-            ])
-            .spacing(spacing.space_s)
-            .padding([spacing.space_xxs, spacing.space_none])
-            .into()
+            self.waiters.push((entities, waiter));
         }
     }
 
-    fn project_search(&self) -> Element<'_, Message> {
-        let spacing = self.core().system_theme().cosmic().spacing;
-
-        let search_input = widget::text_input::search_input(
-            fl!("project-search"),
-            self.project_search_value.clone(),
+    // Move the cursor of a tab to a line and column, which start at one
+    fn tab_go_to(
+        &self,
+        entity: segmented_button::Entity,
+        line: usize,
+        column: usize,
+    ) -> Task<Message> {
+        let cursor = match self.tab_model.data::<Tab>(entity) {
+            Some(Tab::Editor(tab)) => tab.editor.lock().unwrap().with_buffer(|buffer| {
+                let line = line
+                    .saturating_sub(1)
+                    .min(buffer.lines.len().saturating_sub(1));
+                let text = buffer.lines.get(line).map_or("", |line| line.text());
+                let index = text
+                    .char_indices()
+                    .nth(column.saturating_sub(1))
+                    .map_or(text.len(), |(index, _)| index);
+                Cursor::new(line, index)
+            }),
+            _ => return Task::none(),
+        };
+        Task::perform(
+            async move { action::app(Message::TabSetCursor(entity, cursor)) },
+            |x| x,
         )
-        .id(self.project_search_id.clone());
-
-        let items = match &self.project_search_result {
-            Some(project_search_result) => {
-                let mut items =
-                    Vec::with_capacity(project_search_result.files.len().saturating_add(1));
+    }
 
-                if project_search_result.in_progress {
-                    items.push(search_input.into());
-                } else {
-                    items.push(
-                        search_input
-                            .on_input(Message::ProjectSearchValue)
-                            .on_submit(|_| Message::ProjectSearchSubmit)
-                            .into(),
-                    );
-                }
+    /// Reopen the files recorded by [`App::save_session`]
+    fn restore_session(&mut self) -> Task<Message> {
+        let session = self.config_state.session.clone();
+        let mut tasks = Vec::with_capacity(session.tabs.len());
+        let mut active_opt = None;
+        for (tab_i, session_tab) in session.tabs.into_iter().enumerate() {
+            let Some((entity, task)) = self.open_session_tab(session_tab) else {
+                continue;
+            };
+            tasks.push(task);
 
-                for (file_i, file_search_result) in project_search_result.files.iter().enumerate() {
-                    let mut column = widget::column::with_capacity(file_search_result.lines.len());
-                    let mut line_number_width = 1;
-                    if let Some(line_search_result) = file_search_result.lines.last() {
-                        let mut number = line_search_result.number;
-                        while number >= 10 {
-                            number /= 10;
-                            line_number_width += 1;
+            if tab_i == session.active {
+                active_opt = Some(entity);
+            }
+        }
+
+        if let Some(entity) = active_opt {
+            self.tab_model.activate(entity);
+        }
+
+        Task::batch(tasks)
+    }
+
+    fn save_config_state(&mut self) {
+        if let Some(ref config_state_handler) = self.config_state_handler {
+            if let Err(err) = self.config_state.write_entry(config_state_handler) {
+                log::error!("failed to save config_state: {}", err);
+            }
+        }
+    }
+
+    fn update_dialogs(&mut self) -> Task<Message> {
+        match self.dialog_page_opt {
+            Some(DialogPage::PromptSaveClose(entity)) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    if !tab.changed() {
+                        // Tab has been saved, close it (which also closes this dialog)
+                        return self.update(Message::TabCloseForce(entity));
+                    }
+                } else {
+                    // Tab no longer found, close dialog
+                    self.dialog_page_opt = None;
+                }
+            }
+            Some(DialogPage::PromptSaveQuit(ref _entities)) => {
+                let mut unsaved = Vec::new();
+                for entity in self.tab_model.iter() {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                        if tab.changed() {
+                            unsaved.push(entity);
                         }
                     }
-                    for (line_i, line_search_result) in file_search_result.lines.iter().enumerate()
-                    {
-                        column = column.push(
-                            widget::button::custom(
-                                widget::row::with_children(vec![
-                                    widget::text(format!(
-                                        "{:width$}",
-                                        line_search_result.number,
-                                        width = line_number_width,
-                                    ))
-                                    .font(Font::MONOSPACE)
+                }
+                if unsaved.is_empty() {
+                    // All tabs have been saved, we can exit
+                    return self.update(Message::QuitForce);
+                } else {
+                    // Update dialog
+                    self.dialog_page_opt = Some(DialogPage::PromptSaveQuit(unsaved));
+                }
+            }
+            Some(
+                DialogPage::Export(entity, _)
+                | DialogPage::PrintSetup(entity)
+                | DialogPage::PromptSaveEncoding(entity, _),
+            ) => {
+                if self.tab_model.data::<Tab>(entity).is_none() {
+                    // Tab no longer found, close dialog
+                    self.dialog_page_opt = None;
+                }
+            }
+            None => {}
+        }
+        Task::none()
+    }
+
+    /// Build the pane showing the tab `tab_id`, only the focused pane receives keyboard input
+    fn tab_view(&self, tab_id: segmented_button::Entity, focused: bool) -> Element<'_, Message> {
+        let mut pane = widget::column::with_capacity(3)
+            .width(Length::Fill)
+            .height(Length::Fill);
+        match self.tab_model.data::<Tab>(tab_id) {
+            Some(Tab::Editor(tab)) => {
+                let cosmic_theme::Spacing { space_xxs, .. } =
+                    self.core().system_theme().cosmic().spacing;
+                if tab.disk_changed {
+                    pane = pane.push(
+                        widget::layer_container(
+                            widget::row::with_children(vec![
+                                widget::text::body(fl!("file-changed-on-disk")).into(),
+                                widget::horizontal_space().into(),
+                                widget::button::text(fl!("compare"))
+                                    .on_press(Message::TabDiskCompare(tab_id))
                                     .into(),
-                                    widget::text(line_search_result.text.to_string())
-                                        .font(Font::MONOSPACE)
-                                        .into(),
-                                ])
-                                .spacing(spacing.space_xs),
-                            )
-                            .on_press(Message::OpenSearchResult(file_i, line_i))
-                            .width(Length::Fill)
-                            .class(theme::Button::AppletMenu),
-                        );
+                                widget::button::text(fl!("keep-my-version"))
+                                    .on_press(Message::TabDiskKeep(tab_id))
+                                    .into(),
+                                widget::button::standard(fl!("reload"))
+                                    .on_press(Message::TabDiskReload(tab_id))
+                                    .into(),
+                            ])
+                            .align_y(Alignment::Center)
+                            .padding(space_xxs)
+                            .spacing(space_xxs),
+                        )
+                        .layer(cosmic_theme::Layer::Primary),
+                    );
+                }
+                if let (Some(uri), Some(_)) = (&tab.uri_opt, tab.loading_opt) {
+                    pane = pane.push(
+                        widget::layer_container(widget::text::body(fl!(
+                            "loading-remote-file",
+                            uri = uri.as_str()
+                        )))
+                        .padding(space_xxs)
+                        .layer(cosmic_theme::Layer::Primary),
+                    );
+                } else if let Some(progress) = tab.loading_opt {
+                    pane = pane.push(
+                        widget::layer_container(
+                            widget::column::with_children(vec![
+                                widget::text::body(fl!(
+                                    "loading-large-file",
+                                    percent = (progress * 100.0) as u32
+                                ))
+                                .into(),
+                                widget::progress_bar(0.0..=1.0, progress).into(),
+                            ])
+                            .padding(space_xxs)
+                            .spacing(space_xxs),
+                        )
+                        .layer(cosmic_theme::Layer::Primary),
+                    );
+                } else if tab.large {
+                    pane = pane.push(
+                        widget::layer_container(widget::text::caption(fl!("large-file")))
+                            .padding(space_xxs)
+                            .layer(cosmic_theme::Layer::Primary),
+                    );
+                }
+                if !tab.conflicts.is_empty() {
+                    let total = tab.conflicts.len();
+                    let conflict_opt = tab.cursor_conflict();
+                    let status = match conflict_opt
+                        .and_then(|conflict| tab.conflicts.iter().position(|c| *c == conflict))
+                    {
+                        Some(conflict_i) => {
+                            fl!("conflict-count", current = conflict_i + 1, total = total)
+                        }
+                        None => fl!("conflicts", total = total),
+                    };
+                    // Only the focused pane edits its tab
+                    let resolve_button = |label: String, resolution| {
+                        let mut button = widget::button::text(label);
+                        if conflict_opt.is_some() && focused {
+                            button = button.on_press(Message::ResolveConflict(resolution));
+                        }
+                        button
+                    };
+                    let mut previous_button = button::custom(icon_cache_get("go-up-symbolic", 16))
+                        .padding(space_xxs)
+                        .class(style::Button::Icon);
+                    let mut next_button = button::custom(icon_cache_get("go-down-symbolic", 16))
+                        .padding(space_xxs)
+                        .class(style::Button::Icon);
+                    if focused {
+                        previous_button = previous_button.on_press(Message::GoToConflict(false));
+                        next_button = next_button.on_press(Message::GoToConflict(true));
                     }
-
-                    items.push(
-                        widget::settings::section()
-                            .title(format!("{}", file_search_result.path.display(),))
-                            .add(column)
-                            .into(),
+                    pane = pane.push(
+                        widget::layer_container(
+                            widget::row::with_children(vec![
+                                widget::text::body(status).into(),
+                                widget::horizontal_space().into(),
+                                resolve_button(fl!("accept-ours"), Resolution::Ours).into(),
+                                resolve_button(fl!("accept-theirs"), Resolution::Theirs).into(),
+                                resolve_button(fl!("accept-both"), Resolution::Both).into(),
+                                widget::tooltip(
+                                    previous_button,
+                                    widget::text::body(fl!("previous-conflict")),
+                                    widget::tooltip::Position::Bottom,
+                                )
+                                .into(),
+                                widget::tooltip(
+                                    next_button,
+                                    widget::text::body(fl!("next-conflict")),
+                                    widget::tooltip::Position::Bottom,
+                                )
+                                .into(),
+                            ])
+                            .align_y(Alignment::Center)
+                            .padding(space_xxs)
+                            .spacing(space_xxs),
+                        )
+                        .layer(cosmic_theme::Layer::Primary),
                     );
                 }
-
-                items
+                let mut text_box = text_box(&tab.editor, self.config.metrics(tab.zoom_adj()))
+                    .conflicts(&tab.conflicts)
+                    .on_changed(Message::TabChanged(tab_id));
+                if let Some(diagnostics) = &tab.diagnostics_opt {
+                    text_box = text_box.diagnostics(diagnostics);
+                }
+                if self.config.spell_check {
+                    text_box = text_box.misspellings(&tab.misspellings);
+                }
+                if tab.loading_opt.is_some() {
+                    text_box = text_box.read_only();
+                }
+                if focused {
+                    text_box = text_box
+                        .id(self.text_box_id.clone())
+                        .on_focus(Message::FindFocused(false))
+                        .on_auto_scroll(Message::AutoScroll)
+                        .has_context_menu(tab.context_menu.is_some())
+                        .on_context_menu(move |position_opt| {
+                            Message::TabContextMenu(tab_id, position_opt)
+                        })
+                        .on_git_gutter(move |change_opt| Message::GitHunkPopup(tab_id, change_opt))
+                        .caret_point(&tab.caret_point);
+                    if tab.diagnostics_opt.is_some() {
+                        text_box = text_box
+                            .on_definition(Message::LspLocations(lsp::LocationKind::Definition));
+                    }
+                } else if self.split_opt.is_some_and(|split| split.entity == tab_id) {
+                    // Clicking the other pane moves focus to it
+                    text_box = text_box
+                        .id(self.split_text_box_id.clone())
+                        .on_focus(Message::SplitFocusNext);
+                }
+                let shared = self
+                    .split_opt
+                    .is_some_and(|split| split.entity == self.tab_model.active());
+                match &tab.split_view_opt {
+                    // Carets belong to the view of the focused pane
+                    Some(split_view) if shared && !focused => {
+                        text_box = text_box.view(split_view);
+                    }
+                    _ => {
+                        if shared {
+                            text_box = text_box.shared();
+                        }
+                        text_box = text_box.carets(&tab.carets);
+                    }
+                }
+                if self.config.vim_bindings {
+                    text_box = text_box.vim(&tab.vim);
+                }
+                if focused && self.find_opt.is_some() {
+                    text_box = text_box.find_matches(&self.find_matches);
+                }
+                // Keys in other Vim modes are commands, not text
+                if self.config.auto_close(tab.syntax_name())
+                    && (!self.config.vim_bindings
+                        || matches!(tab.editor.lock().unwrap().parser().mode, ViMode::Insert))
+                {
+                    text_box = text_box.auto_close();
+                }
+                if self.config.highlight_current_line {
+                    text_box = text_box.highlight_current_line();
+                }
+                text_box = text_box.brackets(brackets::Syntax::new(tab.syntax_name()));
+                if self.config.git_gutter && !self.zen_mode {
+                    text_box = text_box.git_changes(&tab.git_changes);
+                }
+                if let Some(git_blame) = tab
+                    .git_blame_opt
+                    .as_ref()
+                    .filter(|_| self.config.git_blame && !self.zen_mode)
+                {
+                    text_box = text_box.git_blame(git_blame);
+                }
+                if self.config.indent_guides {
+                    text_box = text_box.indent_guides();
+                }
+                if self.config.line_numbers && !self.zen_mode {
+                    text_box = text_box.line_numbers();
+                }
+                if self.config.minimap && !self.zen_mode {
+                    text_box = text_box.minimap();
+                }
+                if self.config.show_whitespace {
+                    text_box = text_box.show_whitespace();
+                }
+                if !self.zen_mode {
+                    text_box = text_box.rulers(&self.config.rulers);
+                }
+                let mut markers = Vec::new();
+                if focused && self.find_opt.is_some() {
+                    markers.extend(
+                        self.find_matches
+                            .iter()
+                            .map(|(start, _end)| (start.line, Marker::Match)),
+                    );
+                }
+                markers.extend(tab.changed_lines.iter().map(|line| (*line, Marker::Change)));
+                if let Some(diagnostics) = &tab.diagnostics_opt {
+                    for (severity, marker) in [
+                        (lsp::Severity::Error, Marker::Error),
+                        (lsp::Severity::Warning, Marker::Warning),
+                    ] {
+                        markers.extend(
+                            diagnostics
+                                .iter()
+                                .filter(|diagnostic| diagnostic.severity == severity)
+                                .map(|diagnostic| (diagnostic.start.line, marker)),
+                        );
+                    }
+                }
+                text_box = text_box.markers(markers);
+                text_box = text_box.cursor(
+                    self.config.cursor_shape,
+                    self.config.vim_cursor_shape,
+                    self.config.cursor_width,
+                );
+                if self.config.cursor_blink {
+                    text_box = text_box
+                        .cursor_blink(time::Duration::from_millis(self.config.cursor_blink_ms));
+                }
+                if self.zen_mode {
+                    text_box = text_box.wrap_column(self.config.zen_columns).centered();
+                } else if let Some(word_wrap_column) = self.config.word_wrap_column
+                    && self.config.word_wrap
+                {
+                    text_box = text_box.wrap_column(word_wrap_column);
+                }
+                let completion_opt = tab
+                    .completion_opt
+                    .as_ref()
+                    .filter(|completion| focused && !completion.matches.is_empty());
+                if completion_opt.is_some() {
+                    text_box = text_box.on_completion(Message::Completion);
+                }
+                if focused && self.macro_recording.is_some() {
+                    text_box = text_box.on_key(Message::MacroKey);
+                }
+                if focused && self.config.vim_bindings {
+                    text_box = text_box.on_ex_command(Message::ExCommand);
+                }
+                if focused {
+                    text_box = text_box.on_zoom(Message::ZoomIn, Message::ZoomOut);
+                }
+                if focused && self.markdown_preview_shown() {
+                    text_box = text_box.on_scroll(Message::MarkdownScroll);
+                }
+                if focused && self.config.primary_selection {
+                    text_box = text_box
+                        .on_select(Message::PrimarySelection)
+                        .on_middle_click(Message::PastePrimary);
+                }
+                if focused && tab.snippet_tab {
+                    text_box = text_box.on_snippet(Message::SnippetTab);
+                }
+                if focused && tab.diagnostics_opt.is_some() {
+                    text_box =
+                        text_box.on_hover(move |hover_opt| Message::Hover(tab_id, hover_opt));
+                }
+                let caret_point_opt = *tab.caret_point.lock().unwrap();
+                // Files dropped on the text are inserted as paths, or as text with Ctrl held
+                let text_box = widget::dnd_destination::dnd_destination_for_data(
+                    text_box,
+                    move |paste_opt: Option<ClipboardPaste>, _action| {
+                        Message::DropFilesText(tab_id, paste_opt)
+                    },
+                );
+                let mut popover = widget::popover(text_box);
+                if let Some(point) = tab.context_menu.filter(|_| focused) {
+                    popover = popover
+                        .popup(menu::context_menu(
+                            &self.key_binds,
+                            tab_id,
+                            tab.cursor_misspelling(),
+                        ))
+                        .position(widget::popover::Position::Point(point));
+                } else if let Some((hunk_i, point)) = tab.git_hunk_popup.filter(|_| focused)
+                    && let Some(hunk) = tab
+                        .git_diff_opt
+                        .as_ref()
+                        .and_then(|diff| diff.hunks.get(hunk_i))
+                {
+                    popover = popover
+                        .popup(self.git_hunk_popup(tab_id, hunk))
+                        .position(widget::popover::Position::Point(point));
+                } else if let Some(completion) = completion_opt
+                    && let Some(point) = completion.point_opt
+                {
+                    popover = popover
+                        .popup(self.completion_popup(completion))
+                        .position(widget::popover::Position::Point(point));
+                } else if let Some((line, signature)) =
+                    tab.signature_opt.as_ref().filter(|_| focused)
+                    && let Some(point) = caret_point_opt
+                    && tab.editor.lock().unwrap().cursor().line == *line
+                {
+                    popover = popover
+                        .popup(self.signature_popup(signature))
+                        .position(widget::popover::Position::Point(point));
+                } else if let Some((_cursor, point, Some(text))) =
+                    tab.hover_opt.as_ref().filter(|_| focused)
+                {
+                    popover = popover
+                        .popup(self.hover_popup(text))
+                        .position(widget::popover::Position::Point(*point));
+                }
+                pane = pane.push(popover);
+                if self.config.vim_bindings {
+                    let status = {
+                        let editor = tab.editor.lock().unwrap();
+                        let parser = editor.parser();
+                        let vim_status_opt = match &parser.mode {
+                            ViMode::Normal | ViMode::Visual | ViMode::VisualLine => {
+                                tab.vim.lock().unwrap().status()
+                            }
+                            _ => None,
+                        };
+                        if let Some(vim_status) = vim_status_opt {
+                            vim_status
+                        } else {
+                            match &parser.mode {
+                                ViMode::Normal => {
+                                    let cmd = format!("{}", parser.cmd);
+                                    match &self.ex_message_opt {
+                                        Some(message) if cmd.is_empty() => message.clone(),
+                                        _ => cmd,
+                                    }
+                                }
+                                ViMode::Insert => "-- INSERT --".to_string(),
+                                ViMode::Extra(extra) => {
+                                    format!("{}{}", parser.cmd, extra)
+                                }
+                                ViMode::Replace => "-- REPLACE --".to_string(),
+                                ViMode::Visual => {
+                                    format!("-- VISUAL -- {}", parser.cmd)
+                                }
+                                ViMode::VisualLine => {
+                                    format!("-- VISUAL LINE -- {}", parser.cmd)
+                                }
+                                ViMode::Command { value } => {
+                                    format!(":{value}|")
+                                }
+                                ViMode::Search { value, forwards } => {
+                                    if *forwards {
+                                        format!("/{value}|")
+                                    } else {
+                                        format!("?{value}|")
+                                    }
+                                }
+                            }
+                        }
+                    };
+                    pane = pane.push(widget::text(status).font(Font::MONOSPACE));
+                }
             }
-            None => {
-                vec![
-                    search_input
-                        .on_input(Message::ProjectSearchValue)
-                        .on_submit(|_| Message::ProjectSearchSubmit)
+            Some(Tab::GitDiff(tab)) => {
+                let mut diff_widget = widget::column::with_capacity(tab.diff.hunks.len());
+                for hunk in tab.diff.hunks.iter() {
+                    let mut hunk_widget = widget::column::with_capacity(hunk.lines.len());
+                    for line in hunk.lines.iter() {
+                        hunk_widget = hunk_widget.push(git_diff_line(line));
+                    }
+                    diff_widget = diff_widget.push(hunk_widget);
+                }
+                pane = pane.push(widget::scrollable(
+                    widget::layer_container(diff_widget).layer(cosmic_theme::Layer::Primary),
+                ));
+            }
+            Some(Tab::Compare(tab)) => {
+                let cosmic_theme::Spacing { space_xxs, .. } =
+                    self.core().system_theme().cosmic().spacing;
+                let status = if tab.differences.is_empty() {
+                    fl!("no-differences")
+                } else {
+                    match tab.difference_opt {
+                        Some(difference) => fl!(
+                            "difference-count",
+                            current = difference + 1,
+                            total = tab.differences.len()
+                        ),
+                        None => fl!("differences", total = tab.differences.len()),
+                    }
+                };
+                let mut previous_button = button::custom(icon_cache_get("go-up-symbolic", 16))
+                    .padding(space_xxs)
+                    .class(style::Button::Icon);
+                let mut next_button = button::custom(icon_cache_get("go-down-symbolic", 16))
+                    .padding(space_xxs)
+                    .class(style::Button::Icon);
+                if !tab.differences.is_empty() {
+                    previous_button = previous_button.on_press(Message::CompareDifference(false));
+                    next_button = next_button.on_press(Message::CompareDifference(true));
+                }
+                pane = pane.push(
+                    widget::row::with_children(vec![
+                        widget::text::body(tab.old_path.display().to_string())
+                            .width(Length::Fill)
+                            .into(),
+                        widget::text::body(tab.new_path.display().to_string())
+                            .width(Length::Fill)
+                            .into(),
+                        widget::text::body(status).into(),
+                        widget::tooltip(
+                            previous_button,
+                            widget::text::body(fl!("previous-difference")),
+                            widget::tooltip::Position::Bottom,
+                        )
                         .into(),
-                ]
+                        widget::tooltip(
+                            next_button,
+                            widget::text::body(fl!("next-difference")),
+                            widget::tooltip::Position::Bottom,
+                        )
+                        .into(),
+                    ])
+                    .align_y(Alignment::Center)
+                    .padding(space_xxs)
+                    .spacing(space_xxs),
+                );
+
+                let mut rows_widget = widget::column::with_capacity(tab.rows.len());
+                for row in tab.rows.iter() {
+                    rows_widget = rows_widget.push(widget::row::with_children(vec![
+                        compare_side(&row.old, row.changed, Color::from_rgb8(0x40, 0x00, 0x00)),
+                        compare_side(&row.new, row.changed, Color::from_rgb8(0x00, 0x40, 0x00)),
+                    ]));
+                }
+                pane = pane.push(
+                    widget::scrollable(
+                        widget::layer_container(rows_widget).layer(cosmic_theme::Layer::Primary),
+                    )
+                    .id(tab.scrollable_id.clone()),
+                );
             }
-        };
+            None => {}
+        }
+        pane.into()
+    }
 
-        widget::column::with_children(items)
-            .spacing(spacing.space_s)
-            .padding([spacing.space_xxs, spacing.space_none])
+    // Preview of a hunk opened from the git gutter, with buttons to revert or stage it
+    fn git_hunk_popup<'a>(
+        &'a self,
+        entity: segmented_button::Entity,
+        hunk: &'a GitDiffHunk,
+    ) -> Element<'a, Message> {
+        let cosmic_theme::Spacing {
+            space_xxs, space_s, ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let mut lines = widget::column::with_capacity(hunk.lines.len());
+        for line in hunk.lines.iter() {
+            lines = lines.push(git_diff_line(line));
+        }
+        let buttons = widget::row::with_children(vec![
+            widget::horizontal_space().into(),
+            widget::button::standard(fl!("revert-hunk"))
+                .on_press(Message::GitRevertHunk(entity))
+                .into(),
+            widget::button::standard(fl!("stage-hunk"))
+                .on_press(Message::GitStageHunk(entity))
+                .into(),
+        ])
+        .spacing(space_xxs);
+
+        widget::container(
+            widget::column::with_children(vec![
+                widget::scrollable(lines).height(Length::Shrink).into(),
+                buttons.into(),
+            ])
+            .spacing(space_s),
+        )
+        .padding(space_s)
+        //TODO: move style to libcosmic
+        .style(|theme| {
+            let cosmic = theme.cosmic();
+            let component = &cosmic.background.component;
+            widget::container::Style {
+                icon_color: Some(component.on.into()),
+                text_color: Some(component.on.into()),
+                background: Some(Background::Color(component.base.into())),
+                border: Border {
+                    radius: cosmic.radius_s().map(|x| x + 1.0).into(),
+                    width: 1.0,
+                    color: component.divider.into(),
+                },
+                ..Default::default()
+            }
+        })
+        .width(Length::Fixed(480.0))
+        .max_height(320.0)
+        .into()
+    }
+
+    // Container of the popups shown next to the text
+    fn text_popup<'a>(&self, content: impl Into<Element<'a, Message>>) -> Element<'a, Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
+
+        widget::container(content)
+            .padding(space_xxs)
+            //TODO: move style to libcosmic
+            .style(|theme| {
+                let cosmic = theme.cosmic();
+                let component = &cosmic.background.component;
+                widget::container::Style {
+                    icon_color: Some(component.on.into()),
+                    text_color: Some(component.on.into()),
+                    background: Some(Background::Color(component.base.into())),
+                    border: Border {
+                        radius: cosmic.radius_s().map(|x| x + 1.0).into(),
+                        width: 1.0,
+                        color: component.divider.into(),
+                    },
+                    ..Default::default()
+                }
+            })
+            .max_width(480.0)
+            .max_height(320.0)
             .into()
     }
 
-    fn settings(&self) -> Element<'_, Message> {
-        let app_theme_selected = match self.config.app_theme {
-            AppTheme::Dark => 1,
-            AppTheme::Light => 2,
-            AppTheme::System => 0,
-        };
-        let dark_selected = self
-            .theme_names
-            .iter()
-            .position(|theme_name| theme_name == &self.config.syntax_theme_dark);
-        let light_selected = self
-            .theme_names
-            .iter()
-            .position(|theme_name| theme_name == &self.config.syntax_theme_light);
-        let font_selected = {
-            let mut font_system = font_system().write().unwrap();
-            let current_font_name = font_system.raw().db().family_name(&Family::Monospace);
-            self.font_names
-                .iter()
-                .position(|font_name| font_name == current_font_name)
-        };
-        let font_size_selected = self
-            .font_sizes
-            .iter()
-            .position(|font_size| font_size == &self.config.font_size);
-        let zoom_step_selected = self
-            .zoom_steps
-            .iter()
-            .position(|zoom_step| zoom_step == &self.config.font_size_zoom_step_mul_100);
-        widget::settings::view_column(vec![
-            widget::settings::section()
-                .title(fl!("appearance"))
-                .add(
-                    widget::settings::item::builder(fl!("theme")).control(widget::dropdown(
-                        &self.app_themes,
-                        Some(app_theme_selected),
-                        move |index| {
-                            Message::AppTheme(match index {
-                                1 => AppTheme::Dark,
-                                2 => AppTheme::Light,
-                                _ => AppTheme::System,
-                            })
-                        },
-                    )),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("syntax-dark")).control(widget::dropdown(
-                        &self.theme_names,
-                        dark_selected,
-                        move |index| Message::SyntaxTheme(index, true),
-                    )),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("syntax-light")).control(widget::dropdown(
-                        &self.theme_names,
-                        light_selected,
-                        move |index| Message::SyntaxTheme(index, false),
-                    )),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("default-font")).control(widget::dropdown(
-                        &self.font_names,
-                        font_selected,
-                        Message::DefaultFont,
-                    )),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("default-font-size")).control(
-                        widget::dropdown(&self.font_size_names, font_size_selected, |index| {
-                            Message::DefaultFontSize(index)
-                        }),
-                    ),
-                )
-                .add(
-                    widget::settings::item::builder(fl!("default-zoom-step")).control(
-                        widget::dropdown(&self.zoom_step_names, zoom_step_selected, |index| {
-                            Message::DefaultZoomStep(index)
-                        }),
-                    ),
-                )
-                .into(),
-            widget::settings::section()
-                .title(fl!("keyboard-shortcuts"))
-                .add(
-                    widget::settings::item::builder(fl!("enable-vim-bindings"))
-                        .toggler(self.config.vim_bindings, Message::VimBindings),
-                )
-                .into(),
-        ])
-        .into()
+    fn hover_popup<'a>(&self, text: &'a str) -> Element<'a, Message> {
+        self.text_popup(widget::scrollable(widget::text::body(text)).height(Length::Shrink))
     }
-}
-
-/// Implement [`cosmic::Application`] to integrate with COSMIC.
-impl Application for App {
-    /// Default async executor to use with the app.
-    type Executor = executor::Default;
-
-    /// Argument received [`cosmic::Application::new`].
-    type Flags = Flags;
-
-    /// Message type specific to our [`App`].
-    type Message = Message;
 
-    /// The unique application ID to supply to the window manager.
-    const APP_ID: &'static str = "com.system76.CosmicEdit";
+    fn signature_popup<'a>(&self, signature: &'a lsp::Signature) -> Element<'a, Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
 
-    fn core(&self) -> &Core {
-        &self.core
-    }
+        // The parameter being typed is bold
+        let label = &signature.label;
+        let (start, end) = signature
+            .parameter_opt
+            .filter(|(start, end)| {
+                start <= end && label.is_char_boundary(*start) && label.is_char_boundary(*end)
+            })
+            .unwrap_or((label.len(), label.len()));
+        let bold = Font {
+            weight: cosmic::iced::font::Weight::Bold,
+            ..Font::MONOSPACE
+        };
+        let label_row = widget::row::with_capacity(3)
+            .push(widget::text(&label[..start]).font(Font::MONOSPACE))
+            .push(widget::text(&label[start..end]).font(bold))
+            .push(widget::text(&label[end..]).font(Font::MONOSPACE));
 
-    fn core_mut(&mut self) -> &mut Core {
-        &mut self.core
+        let mut column = widget::column::with_capacity(3)
+            .push(label_row)
+            .spacing(space_xxs);
+        if let Some(documentation) = &signature.documentation_opt {
+            column = column.push(widget::divider::horizontal::default()).push(
+                widget::scrollable(widget::text::caption(documentation)).height(Length::Shrink),
+            );
+        }
+        self.text_popup(column)
     }
 
-    /// Creates the application, and optionally emits command on initialize.
-    fn init(mut core: Core, flags: Self::Flags) -> (Self, Task<Self::Message>) {
-        core.window.context_is_overlay = false;
+    fn completion_popup<'a>(&'a self, completion: &'a Completion) -> Element<'a, Message> {
+        let cosmic_theme::Spacing {
+            space_xxs, space_s, ..
+        } = self.core().system_theme().cosmic().spacing;
 
-        // Update font name from config
+        // Only a window of the matches is shown, which follows the selection
+        let first = completion
+            .selected
+            .saturating_sub(COMPLETION_ROWS - 1)
+            .min(completion.matches.len().saturating_sub(COMPLETION_ROWS));
+        let mut items = widget::column::with_capacity(COMPLETION_ROWS);
+        for (match_i, item_i) in completion
+            .matches
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(COMPLETION_ROWS)
         {
-            let mut font_system = font_system().write().unwrap();
-            font_system
-                .raw()
-                .db_mut()
-                .set_monospace_family(&flags.config.font_name);
+            let item = &completion.items[*item_i];
+            let mut row = widget::row::with_capacity(3)
+                .push(widget::text(&item.label).font(Font::MONOSPACE))
+                .push(widget::horizontal_space());
+            if let Some(detail) = &item.detail_opt {
+                row = row.push(widget::text::caption(detail));
+            }
+            items = items.push(
+                widget::button::custom(row.align_y(Alignment::Center).spacing(space_s))
+                    .on_press(Message::CompletionSelect(match_i))
+                    .selected(match_i == completion.selected)
+                    .class(theme::Button::MenuItem)
+                    .padding([0, space_xxs])
+                    .width(Length::Fill),
+            );
         }
 
-        let app_themes = vec![fl!("match-desktop"), fl!("dark"), fl!("light")];
+        let mut column = widget::column::with_capacity(3).push(items);
+        if let Some(documentation) = completion
+            .selected_item()
+            .and_then(|item| item.documentation_opt.as_ref())
+        {
+            column = column.push(widget::divider::horizontal::default()).push(
+                widget::scrollable(widget::text::caption(documentation)).height(Length::Shrink),
+            );
+        }
 
-        let font_names = {
-            let mut font_names = Vec::new();
-            let mut font_system = font_system().write().unwrap();
-            let attrs = monospace_attrs();
-            for face in font_system.raw().db().faces() {
-                if attrs.matches(face) && face.monospaced {
-                    //TODO: get localized name if possible
-                    let font_name = face
-                        .families
-                        .first()
-                        .map_or_else(|| face.post_script_name.to_string(), |x| x.0.to_string());
-                    if !font_names.contains(&font_name) {
-                        font_names.push(font_name);
-                    }
+        widget::container(column.spacing(space_xxs))
+            .padding(space_xxs)
+            //TODO: move style to libcosmic
+            .style(|theme| {
+                let cosmic = theme.cosmic();
+                let component = &cosmic.background.component;
+                widget::container::Style {
+                    icon_color: Some(component.on.into()),
+                    text_color: Some(component.on.into()),
+                    background: Some(Background::Color(component.base.into())),
+                    border: Border {
+                        radius: cosmic.radius_s().map(|x| x + 1.0).into(),
+                        width: 1.0,
+                        color: component.divider.into(),
+                    },
+                    ..Default::default()
                 }
-            }
-            font_names.sort();
-            font_names
-        };
+            })
+            .width(Length::Fixed(400.0))
+            .max_height(320.0)
+            .into()
+    }
 
-        let mut font_size_names = Vec::new();
-        let mut font_sizes = Vec::new();
-        for font_size in 4..=32 {
-            font_size_names.push(format!("{}px", font_size));
-            font_sizes.push(font_size);
-        }
+    /// Split the editor into two panes, or change the direction of an existing split
+    fn status_bar(&self, tab: &EditorTab) -> Element<'_, Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
 
-        let mut theme_names =
-            Vec::with_capacity(SYNTAX_SYSTEM.get().unwrap().theme_set.themes.len());
-        for (theme_name, _theme) in SYNTAX_SYSTEM.get().unwrap().theme_set.themes.iter() {
-            theme_names.push(theme_name.to_string());
+        let (line, column, selected) = tab.cursor_status();
+        let mut position = fl!("status-position", line = line, column = column);
+        if selected > 0 {
+            position.push_str(&fl!("status-selected", count = selected));
         }
-
-        let mut zoom_step_names = Vec::new();
-        let mut zoom_steps = Vec::new();
-        for zoom_step in [25, 50, 75, 100, 150, 200] {
-            zoom_step_names.push(format!("{}px", f32::from(zoom_step) / 100.0));
-            zoom_steps.push(zoom_step);
+        let line_ending_selected = match tab.line_ending() {
+            LineEnding::CrLf => 1,
+            _ => 0,
+        };
+        let indentation_selected = if tab.indent_tabs() { 0 } else { 1 };
+        let tab_width_selected = usize::from(self.config.tab_width)
+            .checked_sub(1)
+            .filter(|index| *index < self.tab_width_names.len());
+
+        let mut children: Vec<Element<'_, Message>> = vec![
+            widget::button::text(position)
+                .on_press(Message::ToggleContextPage(ContextPage::DocumentStatistics))
+                .into(),
+        ];
+        if self.macro_recording.is_some() {
+            children.push(
+                widget::button::text(fl!("recording-macro"))
+                    .on_press(Message::ToggleMacroRecording)
+                    .into(),
+            );
         }
-
-        let about = About::default()
-            .name(fl!("cosmic-text-editor"))
-            .icon(icon::from_name(Self::APP_ID))
-            .version(env!("CARGO_PKG_VERSION"))
-            .author("System76")
-            .license("GPL-3.0-only")
-            .developers([("Jeremy Soller", "jeremy@system76.com")])
-            .links([
-                (fl!("repository"), "https://github.com/pop-os/cosmic-edit"),
-                (
-                    fl!("support"),
-                    "https://github.com/pop-os/cosmic-edit/issues",
+        if let Some(diagnostics) = &tab.diagnostics_opt {
+            let count = |severity| {
+                diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.severity == severity)
+                    .count()
+            };
+            children.push(
+                widget::button::text(fl!(
+                    "status-problems",
+                    errors = count(lsp::Severity::Error),
+                    warnings = count(lsp::Severity::Warning)
+                ))
+                .on_press(Message::ToggleContextPage(ContextPage::Problems))
+                .into(),
+            );
+        }
+        children.extend([
+            widget::horizontal_space().into(),
+            widget::button::text(tab.syntax_name().to_string())
+                .on_press(Message::ToggleContextPage(ContextPage::DocumentType))
+                .into(),
+            widget::button::text(tab.encoding.name())
+                .on_press(Message::ToggleContextPage(ContextPage::Encoding))
+                .into(),
+            // Picking from these converts the whole document
+            widget::tooltip(
+                widget::dropdown(
+                    &self.line_ending_names,
+                    Some(line_ending_selected),
+                    |index| {
+                        Message::LineEnding(if index == 1 {
+                            LineEnding::CrLf
+                        } else {
+                            LineEnding::Lf
+                        })
+                    },
                 ),
-            ]);
-
-        let mut app = App {
-            core,
-            about,
-            nav_model: nav_bar::Model::builder().build(),
-            tab_model: segmented_button::Model::builder().build(),
-            config_handler: flags.config_handler,
-            config: flags.config,
-            config_state_handler: flags.config_state_handler,
-            config_state: flags.config_state,
-            key_binds: key_binds(),
-            zoom_step_names,
-            zoom_steps,
-            app_themes,
-            font_names,
-            font_size_names,
-            font_sizes,
-            theme_names,
-            context_page: ContextPage::Settings,
-            text_box_id: widget::Id::unique(),
-            auto_scroll: None,
-            dialog_opt: None,
-            dialog_page_opt: None,
-            find_opt: None,
-            find_replace_id: widget::Id::unique(),
-            find_replace_value: String::new(),
-            find_search_id: widget::Id::unique(),
-            find_search_value: String::new(),
-            git_project_status: None,
-            projects: Vec::new(),
-            project_search_id: widget::Id::unique(),
-            project_search_value: String::new(),
-            project_search_result: None,
-            watcher_opt: None,
-            modifiers: Modifiers::empty(),
-        };
+                widget::text::body(fl!("convert-line-endings")),
+                widget::tooltip::Position::Top,
+            )
+            .into(),
+            widget::tooltip(
+                widget::dropdown(
+                    &self.indentation_names,
+                    Some(indentation_selected),
+                    |index| Message::ConvertIndentation(index == 0),
+                ),
+                widget::text::body(fl!("convert-indentation")),
+                widget::tooltip::Position::Top,
+            )
+            .into(),
+            widget::dropdown(&self.tab_width_names, tab_width_selected, |index| {
+                Message::TabWidth(index as u16 + 1)
+            })
+            .into(),
+        ]);
+
+        widget::row::with_children(children)
+            .align_y(Alignment::Center)
+            .padding([0, space_xxs])
+            .spacing(space_xxs)
+            .into()
+    }
 
-        // Do not show nav bar by default. Will be opened by open_project if needed
-        app.core.nav_bar_set_toggled(false);
-        for arg in env::args().skip(1) {
-            let path = PathBuf::from(arg);
-            if path.is_dir() {
-                app.open_project(path);
-            } else {
-                app.open_tab(Some(path));
+    // Replay the last macro in the active tab, where keys are applied at every caret
+    fn play_macro(&mut self, times: usize) -> Task<Message> {
+        if self.macro_recording.is_some() {
+            return Task::none();
+        }
+        let entity = self.tab_model.active();
+        let steps = self.macro_steps.clone();
+        let mut tasks = Vec::new();
+        for _ in 0..times {
+            for step in steps.iter() {
+                match step {
+                    MacroStep::Key(key) => {
+                        let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+                            return Task::batch(tasks);
+                        };
+                        let auto_close = self.config.auto_close(tab.syntax_name());
+                        let mut editor = tab.editor.lock().unwrap();
+                        let mut font_system = font_system().write().unwrap();
+                        let mut editor = editor.borrow_with(font_system.raw());
+                        text_box::apply_key(
+                            &mut editor,
+                            &mut tab.carets.lock().unwrap(),
+                            *key,
+                            auto_close,
+                        );
+                    }
+                    MacroStep::Action(action) => {
+                        tasks.push(self.update(action.message(None)));
+                    }
+                }
             }
         }
-
-        app.update_nav_bar_placeholder();
-
-        // Open an empty file if no arguments provided
-        if app.tab_model.iter().next().is_none() {
-            app.open_tab(None);
+        if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+            tab.editor.lock().unwrap().set_redraw(true);
         }
-
-        //TODO: try update_config here? It breaks loading system theme by default
-        let command = app.update_tab();
-        (app, command)
+        tasks.push(self.update(Message::TabChanged(entity)));
+        Task::batch(tasks)
     }
 
-    // The default nav_bar widget needs to be condensed for cosmic-edit
-    fn nav_bar(&self) -> Option<Element<'_, action::Action<Self::Message>>> {
-        if !self.core().nav_bar_active() {
-            return None;
+    fn split(&mut self, axis: SplitAxis) -> Task<Message> {
+        match self.split_opt.as_mut() {
+            Some(split) => {
+                split.axis = axis;
+                Task::none()
+            }
+            None => {
+                // Both panes start with the active tab, with focus on the new pane
+                self.split_opt = Some(Split {
+                    axis,
+                    entity: self.tab_model.active(),
+                    second_focused: true,
+                });
+                self.update_split_view();
+                self.update_focus()
+            }
         }
+    }
 
-        let nav_model = self.nav_model()?;
-
-        let cosmic_theme::Spacing {
-            space_none,
-            space_s,
-            space_xxxs,
-            ..
-        } = self.core().system_theme().cosmic().spacing;
-
-        let mut nav = segmented_button::vertical(nav_model)
-            .button_height(space_xxxs + 20 /* line height */ + space_xxxs)
-            .button_padding([space_s, space_xxxs, space_s, space_xxxs])
-            .button_spacing(space_xxxs)
-            .on_activate(|entity| action::cosmic(cosmic::app::Action::NavBar(entity)))
-            .spacing(space_none)
-            .style(theme::SegmentedButton::FileNav)
-            .apply(widget::container)
-            .padding(space_s)
-            .width(Length::Shrink);
-
-        if !self.core().is_condensed() {
-            nav = nav.max_width(280);
+    // Give the other pane its own cursor and scroll position when both panes show the active tab
+    fn update_split_view(&mut self) {
+        let entity = self.tab_model.active();
+        if self.split_opt.is_some_and(|split| split.entity == entity)
+            && let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+            && tab.split_view_opt.is_none()
+        {
+            let split_view = EditorView::new(&tab.editor.lock().unwrap());
+            tab.split_view_opt = Some(Mutex::new(split_view));
         }
+    }
 
-        Some(
-            nav.apply(widget::scrollable)
-                .apply(widget::container)
-                .height(Length::Fill)
-                .class(theme::Container::custom(nav_bar::nav_bar_style))
-                .into(),
-        )
+    fn update_focus(&self) -> Task<Message> {
+        if self.core.window.show_context {
+            match self.context_page {
+                ContextPage::DocumentType => {
+                    widget::text_input::focus(self.document_type_search_id.clone())
+                }
+                ContextPage::ProjectSearch => {
+                    widget::text_input::focus(self.project_search_id.clone())
+                }
+                _ => Task::none(),
+            }
+        } else if self.find_opt.is_some_and(
+            |FindField {
+                 replace: _,
+                 has_focus,
+             }| has_focus,
+        ) {
+            widget::text_input::focus(self.find_search_id.clone())
+        } else {
+            widget::text_input::focus(self.text_box_id.clone())
+        }
     }
 
-    fn nav_model(&self) -> Option<&nav_bar::Model> {
-        Some(&self.nav_model)
+    fn update_nav_bar_active(&mut self) {
+        let tab_path_opt = match self.active_tab() {
+            Some(Tab::Editor(tab)) => tab.path_opt.clone(),
+            Some(Tab::GitDiff(tab)) => Some(tab.diff.path.clone()),
+            Some(Tab::Compare(tab)) => Some(tab.new_path.clone()),
+            None => None,
+        };
+
+        // Locate tree node to activate
+        let mut active_id = segmented_button::Entity::default();
+
+        if let Some(tab_path) = tab_path_opt {
+            // Automatically expand tree to find and select active file
+            loop {
+                let mut expand_opt = None;
+                for id in self.nav_model.iter() {
+                    if let Some(node) = self.nav_model.data(id) {
+                        match node {
+                            ProjectNode::Folder { path, open, .. } => {
+                                if tab_path.starts_with(path) && !*open {
+                                    expand_opt = Some(id);
+                                    break;
+                                }
+                            }
+                            ProjectNode::File { path, .. } => {
+                                if path == &tab_path {
+                                    active_id = id;
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                match expand_opt {
+                    Some(id) => {
+                        //TODO: can this be optimized?
+                        // Task not used becuase opening a folder just returns Task::none
+                        let _ = self.on_nav_select(id);
+                    }
+                    None => {
+                        break;
+                    }
+                }
+            }
+        }
+        self.nav_model.activate(active_id);
     }
 
-    fn on_app_exit(&mut self) -> Option<Message> {
-        Some(Message::Quit)
+    fn update_nav_bar_placeholder(&mut self) {
+        // Remove all placeholder items
+        let mut remove = Vec::new();
+        for entity in self.nav_model.iter() {
+            if self.nav_model.data::<ProjectNode>(entity).is_none() {
+                remove.push(entity);
+            }
+        }
+        for entity in remove {
+            self.nav_model.remove(entity);
+        }
+
+        // Add button to open a project if none provided
+        if self.nav_model.iter().next().is_none() {
+            self.nav_model
+                .insert()
+                .icon(icon_cache_get("folder-open-symbolic", 16))
+                .text(fl!("open-project"));
+        }
     }
 
-    fn on_context_drawer(&mut self) -> Task<Message> {
-        // Focus correct widget
-        self.update_focus()
+    /// Editor window, `None` is the main window
+    fn editor_window(&self, window_opt: Option<window::Id>) -> Option<&EditorWindow> {
+        match window_opt {
+            Some(window_id) => self.windows.get(&window_id),
+            None => Some(&self.main_window),
+        }
     }
 
-    //TODO: currently the first escape unfocuses, and the second calls this function
-    fn on_escape(&mut self) -> Task<Message> {
-        if self.core.window.show_context {
-            // Close context drawer if open
-            self.core.window.show_context = false;
-        } else if self.find_opt.is_some() {
-            // Close find if open
-            self.find_opt = None;
+    fn editor_window_mut(&mut self, window_opt: Option<window::Id>) -> Option<&mut EditorWindow> {
+        match window_opt {
+            Some(window_id) => self.windows.get_mut(&window_id),
+            None => Some(&mut self.main_window),
         }
+    }
 
-        // Focus correct widget
-        self.update_focus()
+    /// Window that shows a tab, `Some(None)` is the main window
+    fn tab_window(&self, entity: segmented_button::Entity) -> Option<Option<window::Id>> {
+        if self.main_window.item(entity).is_some() {
+            return Some(None);
+        }
+        self.windows
+            .iter()
+            .find(|(_window_id, window)| window.item(entity).is_some())
+            .map(|(window_id, _window)| Some(*window_id))
     }
 
-    fn on_nav_select(&mut self, id: nav_bar::Id) -> Task<Message> {
-        // Toggle open state and get clone of node data
-        let node_opt = match self.nav_model.data_mut::<ProjectNode>(id) {
-            Some(node) => {
-                if let ProjectNode::Folder { open, .. } = node {
-                    *open = !*open;
+    /// Tabs of the window that shows a tab, in the order of its tab bar
+    fn window_tabs(&self, entity: segmented_button::Entity) -> Vec<segmented_button::Entity> {
+        self.tab_window(entity)
+            .and_then(|window_opt| self.editor_window(window_opt))
+            .map(EditorWindow::tabs)
+            .unwrap_or_default()
+    }
+
+    fn focused_window_id(&self) -> Option<window::Id> {
+        self.focused_window_opt.or(self.core.main_window_id())
+    }
+
+    /// Open an editor window, which gets focus so that new tabs are opened in it
+    fn open_window(&mut self) -> Task<Message> {
+        let (window_id, task) = window::open(window::Settings {
+            size: Size::new(1024.0, 768.0),
+            min_size: Some(Size::new(360.0, 180.0)),
+            // Closed by Message::CloseWindow
+            exit_on_close_request: false,
+            ..Default::default()
+        });
+        self.windows.insert(window_id, EditorWindow::new());
+        self.set_focused_window(Some(window_id));
+        task.discard()
+    }
+
+    /// Give focus to an editor window, its active tab becomes the active tab
+    fn set_focused_window(&mut self, window_opt: Option<window::Id>) {
+        if window_opt == self.focused_window_opt {
+            return;
+        }
+        let split_opt = self.split_opt.take();
+        if let Some(window) = self.editor_window_mut(self.focused_window_opt) {
+            window.split_opt = split_opt;
+        }
+        self.focused_window_opt = window_opt;
+        let (split_opt, active_opt) = match self.editor_window_mut(window_opt) {
+            Some(window) => (window.split_opt.take(), window.active_tab()),
+            None => (None, None),
+        };
+        self.split_opt = split_opt;
+        if let Some(entity) = active_opt {
+            self.tab_model.activate(entity);
+        }
+    }
+
+    /// Remove a tab from the tab bar of its window, activating the closest tab of that window
+    fn tab_bar_remove(&mut self, entity: segmented_button::Entity) {
+        let Some(window_opt) = self.tab_window(entity) else {
+            return;
+        };
+        let focused = window_opt == self.focused_window_opt;
+        let Some(window) = self.editor_window_mut(window_opt) else {
+            return;
+        };
+        let Some(item) = window.item(entity) else {
+            return;
+        };
+
+        // Activate closest item
+        if let Some(position) = window.tab_bar.position(item) {
+            if position > 0 {
+                window.tab_bar.activate_position(position - 1);
+            } else {
+                window.tab_bar.activate_position(position + 1);
+            }
+        }
+
+        window.tab_bar.remove(item);
+        let active_opt = window.active_tab();
+        if focused && let Some(active) = active_opt {
+            self.tab_model.activate(active);
+        }
+    }
+
+    /// Add new tabs to the focused window, and copy the titles, icons, and active tab of the tab
+    /// model to the tab bars
+    fn update_tab_bars(&mut self) {
+        let shown: HashSet<_> = iter::once(&self.main_window)
+            .chain(self.windows.values())
+            .flat_map(EditorWindow::tabs)
+            .collect();
+        let opened: Vec<_> = self
+            .tab_model
+            .iter()
+            .filter(|entity| !shown.contains(entity))
+            .collect();
+        let focused_window_opt = self.focused_window_opt;
+        if let Some(window) = self.editor_window_mut(focused_window_opt) {
+            for entity in opened {
+                window.insert(entity);
+            }
+        }
+
+        let active = self.tab_model.active();
+        for window in iter::once(&mut self.main_window).chain(self.windows.values_mut()) {
+            let items: Vec<_> = window.tab_bar.iter().collect();
+            for item in items {
+                let Some(entity) = window
+                    .tab(item)
+                    .filter(|entity| self.tab_model.position(*entity).is_some())
+                else {
+                    // The tab was closed
+                    window.tab_bar.remove(item);
+                    continue;
+                };
+                if let Some(text) = self.tab_model.text(entity) {
+                    window.tab_bar.text_set(item, text.to_string());
+                }
+                if let Some(icon) = self.tab_model.icon(entity) {
+                    window.tab_bar.icon_set(item, icon.clone());
+                }
+                window
+                    .tab_bar
+                    .closable_set(item, !self.pinned_tabs.contains(&entity));
+                if entity == active {
+                    window.tab_bar.activate(item);
                 }
-                Some(node.clone())
             }
-            None => None,
+        }
+    }
+
+    /// Update the tab bars, close other windows without tabs, and focus the window of the active
+    /// tab
+    fn update_windows(&mut self) -> Task<Message> {
+        self.update_tab_bars();
+
+        let mut tasks = Vec::new();
+        let empty: Vec<_> = self
+            .windows
+            .iter()
+            .filter(|(_window_id, window)| window.tab_bar.iter().next().is_none())
+            .map(|(window_id, _window)| *window_id)
+            .collect();
+        for window_id in empty {
+            if self.focused_window_opt == Some(window_id) {
+                self.set_focused_window(None);
+            }
+            self.windows.remove(&window_id);
+            tasks.push(window::close(window_id));
+        }
+
+        // The active tab may be in another window, for example when a file that is open there is
+        // opened again
+        if let Some(window_opt) = self.tab_window(self.tab_model.active())
+            && window_opt != self.focused_window_opt
+        {
+            self.set_focused_window(window_opt);
+            if let Some(window_id) = self.focused_window_id() {
+                tasks.push(window::gain_focus(window_id));
+            }
+        }
+
+        Task::batch(tasks)
+    }
+
+    // Call this any time the tab changes
+    pub fn update_tab(&mut self) -> Task<Message> {
+        let window_task = self.update_windows();
+        self.update_split_view();
+        self.update_nav_bar_active();
+        self.update_find_matches();
+
+        let title = match self.active_tab() {
+            Some(tab) => {
+                if let Tab::Editor(inner) = tab {
+                    // Force redraw on tab switches
+                    inner.editor.lock().unwrap().set_redraw(true);
+                }
+                tab.title()
+            }
+            None => "No Open File".to_string(),
         };
 
-        match node_opt {
-            Some(node) => {
-                // Update icon
-                self.nav_model.icon_set(id, node.icon(16));
+        // Files that were just opened have not been compared with git yet
+        let entity = self.tab_model.active();
+        self.update_outline(entity);
+        self.update_markdown_preview(entity);
+        let git_tasks = match self.tab_model.data::<Tab>(entity) {
+            Some(Tab::Editor(tab)) if tab.git_changes_request == 0 => Task::batch([
+                self.update_git_changes(entity),
+                self.update_git_blame(entity),
+                self.update_spelling(entity),
+            ]),
+            _ => Task::none(),
+        };
+
+        let window_title = format!("{title} - {}", fl!("cosmic-text-editor"));
+        Task::batch([
+            window_task,
+            if let Some(window_id) = self.focused_window_id() {
+                self.set_window_title(window_title, window_id)
+            } else {
+                Task::none()
+            },
+            self.update_focus(),
+            git_tasks,
+        ])
+    }
+
+    fn update_find_matches(&mut self) {
+        self.find_matches.clear();
+        self.find_regex_opt = None;
+        if self.find_opt.is_none() || self.find_search_value.is_empty() {
+            return;
+        }
+        if let Some(Tab::Editor(tab)) = self.active_tab() {
+            match self.config.find_regex(&self.find_search_value) {
+                Ok(regex) => {
+                    self.find_matches = tab.find_matches(&regex);
+                    self.find_regex_opt = Some(regex);
+                }
+                Err(err) => {
+                    log::debug!(
+                        "failed to compile regex {:?}: {}",
+                        self.find_search_value,
+                        err
+                    );
+                }
+            }
+        }
+    }
+
+    // Index of the match selected by the last search
+    fn find_match_index(&self) -> Option<usize> {
+        let Some(Tab::Editor(tab)) = self.active_tab() else {
+            return None;
+        };
+        let editor = tab.editor.lock().unwrap();
+        let (start, end) = editor.selection_bounds()?;
+        self.find_matches
+            .binary_search_by_key(&(start.line, start.index), |(start, _end)| {
+                (start.line, start.index)
+            })
+            .ok()
+            .filter(|index| self.find_matches[*index].1 == end)
+    }
+
+    // Edit the lines of the active tab as one change, which is not repeated at the carets
+    fn edit_lines(&mut self, f: impl FnOnce(&mut ViEditor<'static, 'static>)) -> Task<Message> {
+        let Some(Tab::Editor(tab)) = self.active_tab() else {
+            return Task::none();
+        };
+        {
+            let mut editor = tab.editor.lock().unwrap();
+            f(&mut *editor);
+            editor.set_redraw(true);
+        }
+        tab.carets.lock().unwrap().clear();
+        self.update(Message::TabChanged(self.tab_model.active()))
+    }
+
+    // Delete text at the cursor of the active tab and copy it, adding to the last kill if nothing
+    // was changed since
+    fn kill(
+        &mut self,
+        f: impl FnOnce(&mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>) -> Option<String>,
+    ) -> Task<Message> {
+        let entity = self.tab_model.active();
+        let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+            return Task::none();
+        };
+        let (cursor, text_opt) = {
+            let mut editor = tab.editor.lock().unwrap();
+            let mut font_system = font_system().write().unwrap();
+            let mut editor = editor.borrow_with(font_system.raw());
+            let cursor = editor.cursor();
+            (cursor, f(&mut editor))
+        };
+        let Some(text) = text_opt else {
+            return Task::none();
+        };
+        let text = match self.kill_opt.take() {
+            Some((kill_entity, kill_cursor, mut killed))
+                if kill_entity == entity && kill_cursor == cursor =>
+            {
+                killed.push_str(&text);
+                killed
+            }
+            _ => text,
+        };
+        let task = self.update(Message::TabChanged(entity));
+        self.kill_opt = Some((entity, cursor, text.clone()));
+        self.block_clipboard_opt = None;
+        self.add_clipboard_history(&text);
+        Task::batch([clipboard::write(text), task])
+    }
+
+    // Compare two files in the background, and open them side by side
+    fn compare_files(&self, old_path: PathBuf, new_path: PathBuf) -> Task<Message> {
+        Task::perform(
+            async move {
+                let read = |path: &Path| {
+                    fs::read(path).map(|bytes| String::from_utf8_lossy(&bytes).into_owned())
+                };
+                let texts = read(&old_path).and_then(|old| Ok((old, read(&new_path)?)));
+                let res = match texts {
+                    Ok((old_text, new_text)) => git::diff_files(&old_path, &new_path)
+                        .await
+                        .map(|diff| compare::rows(&old_text, &new_text, &diff)),
+                    Err(err) => Err(err),
+                };
+                match res {
+                    Ok(rows) => action::app(Message::CompareOpen(old_path, new_path, rows)),
+                    Err(err) => {
+                        log::error!(
+                            "failed to compare {:?} and {:?}: {}",
+                            old_path,
+                            new_path,
+                            err
+                        );
+                        action::none()
+                    }
+                }
+            },
+            |x| x,
+        )
+    }
+
+    // Compare a tab with its file on disk in the background, to mark the changed lines
+    fn update_changed_lines(&mut self, entity: segmented_button::Entity) -> Task<Message> {
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return Task::none();
+        };
+        tab.changed_lines_request += 1;
+        let request = tab.changed_lines_request;
+        let path = match &tab.path_opt {
+            Some(path) if tab.changed() && !tab.large && tab.loading_opt.is_none() => path.clone(),
+            _ => {
+                tab.changed_lines.clear();
+                return Task::none();
+            }
+        };
+        let text = tab.text();
+        Task::perform(
+            async move {
+                match git::diff_file_text(&path, &text).await {
+                    Ok(diff) => {
+                        action::app(Message::ChangedLines(entity, request, diff.changed_lines()))
+                    }
+                    Err(err) => {
+                        log::warn!("failed to compare {:?} with disk: {}", path, err);
+                        action::none()
+                    }
+                }
+            },
+            |x| x,
+        )
+    }
+
+    // Check the spelling of a tab in the background, to underline the misspelled words
+    fn update_spelling(&mut self, entity: segmented_button::Entity) -> Task<Message> {
+        let spell_check = self.config.spell_check;
+        let default_languages = self.default_spell_languages();
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return Task::none();
+        };
+        tab.spelling_request += 1;
+        let request = tab.spelling_request;
+        if !spell_check || tab.large || tab.loading_opt.is_some() {
+            tab.misspellings.clear();
+            return Task::none();
+        }
+        let lines: Vec<String> = tab.editor.lock().unwrap().with_buffer(|buffer| {
+            buffer
+                .lines
+                .iter()
+                .map(|line| line.text().to_string())
+                .collect()
+        });
+        let mut words = spell::words(&lines, tab.syntax_name());
+        words.retain(|(_, _, word)| {
+            !self.spelling_ignored.contains(word) && !self.spelling_words.contains(word)
+        });
+        let languages = if tab.spell_languages.is_empty() {
+            default_languages
+        } else {
+            tab.spell_languages.clone()
+        };
+        Task::perform(
+            async move {
+                let unique = words.iter().map(|(_, _, word)| word.clone()).collect();
+                match spell::check(unique, languages).await {
+                    Ok(misspelled) => {
+                        let misspellings = words
+                            .into_iter()
+                            .filter_map(|(start, end, word)| {
+                                let suggestions = misspelled.get(&word)?.clone();
+                                Some(spell::Misspelling {
+                                    start,
+                                    end,
+                                    word,
+                                    suggestions,
+                                })
+                            })
+                            .collect();
+                        action::app(Message::Spelling(entity, request, misspellings))
+                    }
+                    Err(err) => {
+                        log::warn!("failed to check spelling: {}", err);
+                        action::none()
+                    }
+                }
+            },
+            |x| x,
+        )
+    }
+
+    // Dictionaries of documents without their own, from the config or the desktop language
+    fn default_spell_languages(&self) -> Vec<String> {
+        if self.config.spell_languages.is_empty() {
+            spell::locale_dictionary(&self.spell_dictionaries)
+                .into_iter()
+                .collect()
+        } else {
+            self.config.spell_languages.clone()
+        }
+    }
+
+    // Stop underlining a word in every tab, after it was added to the dictionary or ignored
+    fn forget_misspelling(&mut self, entity: segmented_button::Entity, word: &str) {
+        let entities: Vec<_> = self.tab_model.iter().collect();
+        for other in entities {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(other) {
+                if other == entity {
+                    tab.context_menu = None;
+                }
+                tab.misspellings
+                    .retain(|misspelling| misspelling.word != word);
+            }
+        }
+    }
+
+    // Compare a tab with its file at HEAD in the background, to mark git changes in the gutter
+    fn update_git_changes(&mut self, entity: segmented_button::Entity) -> Task<Message> {
+        let git_gutter = self.config.git_gutter;
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return Task::none();
+        };
+        tab.git_changes_request += 1;
+        let request = tab.git_changes_request;
+        let path = match &tab.path_opt {
+            Some(path) if git_gutter && !tab.large && tab.loading_opt.is_none() => path.clone(),
+            _ => {
+                tab.git_changes.clear();
+                return Task::none();
+            }
+        };
+        let text = tab.text();
+        Task::perform(
+            async move {
+                // Files outside of repositories have no changes to show
+                let Ok(repo) = GitRepository::discover(&path) else {
+                    return action::app(Message::GitChanges(entity, request, None));
+                };
+                match repo.diff_head_text(&path, &text).await {
+                    Ok(diff_opt) => action::app(Message::GitChanges(entity, request, diff_opt)),
+                    Err(err) => {
+                        log::warn!("failed to compare {:?} with HEAD: {}", path, err);
+                        action::none()
+                    }
+                }
+            },
+            |x| x,
+        )
+    }
+
+    // Find the commits of the lines of a tab in the background, to show them in the gutter
+    fn update_git_blame(&mut self, entity: segmented_button::Entity) -> Task<Message> {
+        let git_blame = self.config.git_blame;
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return Task::none();
+        };
+        tab.git_blame_request += 1;
+        let request = tab.git_blame_request;
+        let path = match &tab.path_opt {
+            Some(path) if git_blame && !tab.large && tab.loading_opt.is_none() => path.clone(),
+            _ => {
+                tab.git_blame_opt = None;
+                return Task::none();
+            }
+        };
+        let text = tab.text();
+        Task::perform(
+            async move {
+                let Ok(repo) = GitRepository::discover(&path) else {
+                    return action::app(Message::GitBlame(entity, request, None));
+                };
+                match repo.blame_text(&path, &text).await {
+                    Ok(blame) => action::app(Message::GitBlame(entity, request, Some(blame))),
+                    Err(err) => {
+                        // Files that are not committed yet cannot be blamed
+                        log::info!("failed to blame {:?}: {}", path, err);
+                        action::app(Message::GitBlame(entity, request, None))
+                    }
+                }
+            },
+            |x| x,
+        )
+    }
+
+    fn highlight_tab(&mut self, entity: segmented_button::Entity) {
+        let Some(worker) = &self.highlight_worker_opt else {
+            return;
+        };
+        if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+            if let Some(request) = tab.highlight_request(entity, &self.config) {
+                worker.highlight(request);
+            }
+        }
+    }
+
+    // Open a tab with the language server of its syntax, or close it if there is none
+    fn lsp_open(&mut self, entity: segmented_button::Entity) {
+        let Some(worker) = &self.lsp_worker_opt else {
+            return;
+        };
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return;
+        };
+        let command_opt = self
+            .config
+            .lsp_command(tab.syntax_name())
+            .filter(|_| !tab.large);
+        match (tab.path_opt.clone(), command_opt) {
+            (Some(path), Some(command)) => {
+                worker.open(lsp::Document {
+                    entity,
+                    root: lsp::root(
+                        self.projects.iter().map(|(_name, path)| path.as_path()),
+                        &path,
+                    ),
+                    path,
+                    language_id: lsp::language_id(tab.syntax_name()),
+                    command: command.to_vec(),
+                    text: tab.text(),
+                });
+                if tab.diagnostics_opt.is_none() {
+                    tab.diagnostics_opt = Some(Vec::new());
+                }
+            }
+            _ => {
+                worker.close(entity);
+                tab.diagnostics_opt = None;
+                tab.completion_opt = None;
+            }
+        }
+    }
+
+    fn jump_here(&self) -> Option<Jump> {
+        let entity = self.tab_model.active();
+        match self.tab_model.data::<Tab>(entity) {
+            Some(Tab::Editor(tab)) => Some(Jump {
+                entity,
+                path_opt: tab.path_opt.clone(),
+                cursor: tab.editor.lock().unwrap().cursor(),
+            }),
+            _ => None,
+        }
+    }
+
+    // Show a position, opening its file again if its tab was closed
+    fn show_jump(&mut self, jump: Jump) -> Task<Message> {
+        let entity = if self.tab_model.data::<Tab>(jump.entity).is_some() {
+            jump.entity
+        } else {
+            match jump.path_opt.and_then(|path| self.open_tab(Some(path))) {
+                Some(entity) => entity,
+                None => return Task::none(),
+            }
+        };
+        self.tab_model.activate(entity);
+        let cursor = match self.tab_model.data::<Tab>(entity) {
+            Some(Tab::Editor(tab)) => {
+                let mut editor = tab.editor.lock().unwrap();
+                // The text may have changed since the position was remembered
+                let cursor = lines::clamp(&editor, jump.cursor);
+                editor.set_cursor(cursor);
+                editor.set_selection(Selection::None);
+                cursor
+            }
+            _ => return Task::none(),
+        };
+        Task::batch([
+            //TODO: why must this be done in a command?
+            Task::perform(
+                async move { action::app(Message::TabSetCursor(entity, cursor)) },
+                |x| x,
+            ),
+            self.update_tab(),
+        ])
+    }
+
+    // Go to a position in a file, so that navigating back returns to the cursor
+    fn jump_to(&mut self, path: PathBuf, cursor: Cursor) -> Task<Message> {
+        if let Some(here) = self.jump_here() {
+            self.jumps_back.push(here);
+            if self.jumps_back.len() > JUMP_HISTORY_LEN {
+                self.jumps_back.remove(0);
+            }
+            self.jumps_forward.clear();
+        }
+        let Some(entity) = self.open_tab(Some(path.clone())) else {
+            return Task::none();
+        };
+        self.show_jump(Jump {
+            entity,
+            path_opt: Some(path),
+            cursor,
+        })
+    }
+
+    // Ask for completions of the word being typed, or filter the ones that are shown
+    fn update_completion(&mut self, entity: segmented_button::Entity, manual: bool) {
+        let Some(syntax_name) = self.load_snippets(entity) else {
+            return;
+        };
+        let snippets = &self.snippets[&syntax_name];
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return;
+        };
+        // Without a language server for the file, snippets and words of the documents are
+        // completed
+        let worker_opt = self
+            .lsp_worker_opt
+            .as_ref()
+            .filter(|_| tab.diagnostics_opt.is_some());
+        let words = worker_opt.is_none() && self.config.word_completion;
+        if worker_opt.is_none() && snippets.is_empty() && !words {
+            tab.completion_opt = None;
+            return;
+        }
+        let (cursor, start, word, after_accessor, indentation) = {
+            let editor = tab.editor.lock().unwrap();
+            // Selections, additional carets, and Vim commands are not completed
+            if editor.selection_bounds().is_some()
+                || !tab.carets.lock().unwrap().is_empty()
+                || (self.config.vim_bindings && !matches!(editor.parser().mode, ViMode::Insert))
+            {
+                tab.completion_opt = None;
+                return;
+            }
+            let cursor = editor.cursor();
+            let start = completion::word_start(&editor, cursor);
+            (
+                cursor,
+                start,
+                completion::word(&editor, start, cursor),
+                worker_opt.is_some() && completion::after_accessor(&editor, start),
+                snippet::indentation(&editor, cursor.line),
+            )
+        };
+
+        if manual || word.is_empty() || tab.completion_closed != Some(start) {
+            tab.completion_closed = None;
+        } else {
+            // Completions stay closed until another word is typed
+            return;
+        }
+        let request = match &mut tab.completion_opt {
+            Some(_) if word.is_empty() && !after_accessor && !manual => false,
+            Some(completion) if completion.start == start => {
+                completion.filter(&word);
+                if !completion.incomplete {
+                    return;
+                }
+                // Other items may match the new word
+                true
+            }
+            // Words are completed once enough of them is typed
+            _ if worker_opt.is_none() => {
+                manual || word.chars().count() >= self.config.word_completion_chars.max(1)
+            }
+            _ => manual || !word.is_empty() || after_accessor,
+        };
+        if !request {
+            tab.completion_opt = None;
+            return;
+        }
+        if tab
+            .completion_opt
+            .as_ref()
+            .is_none_or(|completion| completion.start != start)
+        {
+            tab.completion_opt = Some(Completion::new(start));
+        }
+        match worker_opt {
+            Some(worker) => {
+                tab.completion_request += 1;
+                worker.completion(entity, cursor, tab.completion_request);
+            }
+            None => {
+                let mut items = snippet::completion_items(snippets, &indentation);
+                if words {
+                    items.extend(self.document_words(entity, start));
+                }
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                    && let Some(completion) = &mut tab.completion_opt
+                {
+                    completion.items = items;
+                    completion.filter(&word);
+                    if completion.point_opt.is_none() {
+                        completion.point_opt = *tab.caret_point.lock().unwrap();
+                    }
+                }
+            }
+        }
+    }
+
+    // Words of the open documents, for completing the word that starts at `start`
+    fn document_words(
+        &self,
+        entity: segmented_button::Entity,
+        start: Cursor,
+    ) -> Vec<lsp::CompletionItem> {
+        let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+            return Vec::new();
+        };
+        let others = self
+            .tab_model
+            .iter()
+            .filter(|other| *other != entity)
+            .filter_map(|other| match self.tab_model.data::<Tab>(other) {
+                Some(Tab::Editor(other_tab)) => Some(&other_tab.editor),
+                _ => None,
+            });
+        completion::words(&tab.editor.lock().unwrap(), start, others)
+    }
+
+    // Read the snippets of the language of a tab if they were not read yet, returning its name
+    fn load_snippets(&mut self, entity: segmented_button::Entity) -> Option<String> {
+        let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+            return None;
+        };
+        let syntax_name = tab.syntax_name().to_string();
+        if !self.snippets.contains_key(&syntax_name) {
+            self.snippets
+                .insert(syntax_name.clone(), snippet::load(&syntax_name));
+        }
+        Some(syntax_name)
+    }
+
+    // Tab expands the snippet whose prefix is before the cursor, or goes to the next tab stop
+    fn update_snippet_tab(&mut self, entity: segmented_button::Entity) {
+        let Some(syntax_name) = self.load_snippets(entity) else {
+            return;
+        };
+        let snippets = &self.snippets[&syntax_name];
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return;
+        };
+        let editor = tab.editor.lock().unwrap();
+        if !tab.carets.lock().unwrap().is_empty()
+            || (self.config.vim_bindings && !matches!(editor.parser().mode, ViMode::Insert))
+        {
+            tab.snippet_tab = false;
+            return;
+        }
+        if tab
+            .snippet_stops_opt
+            .as_ref()
+            .is_some_and(|stops| !stops.contains(&editor))
+        {
+            // Moving out of the tab stop finishes the snippet
+            tab.snippet_stops_opt = None;
+        }
+        let cursor = editor.cursor();
+        tab.snippet_tab = tab.snippet_stops_opt.is_some()
+            || (editor.selection_bounds().is_none()
+                && editor.with_buffer(|buffer| {
+                    let before = &buffer.lines[cursor.line].text()[..cursor.index];
+                    snippet::find(snippets, before).is_some()
+                }));
+    }
+
+    fn save_tab(&mut self, entity: segmented_button::Entity) -> Task<Message> {
+        let mut title_opt = None;
+        if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+            if tab.path_opt.is_none() && tab.uri_opt.is_none() {
+                return self.update(Message::SaveAsDialog(Some(entity)));
+            }
+            title_opt = Some(tab.title());
+            if let Err(err) = tab.save() {
+                match err.kind() {
+                    io::ErrorKind::PermissionDenied => {
+                        self.dialog_page_opt = Some(DialogPage::PromptSaveElevated(entity));
+                    }
+                    io::ErrorKind::InvalidData => {
+                        self.dialog_page_opt =
+                            Some(DialogPage::PromptSaveEncoding(entity, tab.encoding));
+                    }
+                    _ => {}
+                }
+            }
+            // Edited snippets are read again when they are next used
+            if let Some(path) = &tab.path_opt
+                && snippet::dir().is_some_and(|dir| path.starts_with(dir))
+            {
+                self.snippets.clear();
+            }
+        }
+        if let Some(title) = title_opt {
+            self.tab_model.text_set(self.tab_model.active(), title);
+        }
+        Task::batch([self.update_dialogs(), self.save_undo_histories()])
+    }
+
+    // Write the undo histories of saved files to the cache, which serializes them in the
+    // background
+    fn save_undo_histories(&mut self) -> Task<Message> {
+        let mut histories = Vec::new();
+        for entity in self.tab_model.iter().collect::<Vec<_>>() {
+            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                && let Some(history) = tab.take_undo_history()
+            {
+                histories.push(history);
+            }
+        }
+        if histories.is_empty() {
+            return Task::none();
+        }
+        Task::perform(
+            async move {
+                let task_res = tokio::task::spawn_blocking(move || {
+                    for (path, history) in histories {
+                        if let Err(err) = undo_history::save(&path, history) {
+                            log::warn!("failed to save undo history of {:?}: {}", path, err);
+                        }
+                    }
+                    undo_history::prune();
+                })
+                .await;
+                if let Err(err) = task_res {
+                    log::warn!("failed to join undo history task: {}", err);
+                }
+                action::none()
+            },
+            |x| x,
+        )
+    }
+
+    // Paths typed in the Vim command line are relative to the folder of the active file
+    fn ex_path(&self, path: &Path) -> PathBuf {
+        if path.is_absolute() {
+            return path.to_path_buf();
+        }
+        let dir_opt = match self.active_tab() {
+            Some(Tab::Editor(tab)) => tab
+                .path_opt
+                .as_ref()
+                .and_then(|path| path.parent())
+                .map(Path::to_path_buf),
+            _ => None,
+        };
+        match dir_opt.or_else(|| env::current_dir().ok()) {
+            Some(dir) => dir.join(path),
+            None => path.to_path_buf(),
+        }
+    }
+
+    // Run the text typed after `:` with Vim bindings
+    fn ex_command(
+        &mut self,
+        text: &str,
+        selection_opt: Option<(usize, usize)>,
+    ) -> Result<Task<Message>, ex::Error> {
+        let entity = self.tab_model.active();
+        let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+            return Ok(Task::none());
+        };
+        let lines = {
+            let editor = tab.editor.lock().unwrap();
+            ex::Lines {
+                current: editor.cursor().line,
+                count: editor.with_buffer(|buffer| buffer.lines.len()),
+                selection_opt,
+            }
+        };
+        let changed = tab.changed();
+        let has_path = tab.path_opt.is_some();
+
+        match ex::parse(text, lines)? {
+            ex::Command::Delete(range) => Ok(self.edit_lines(|editor| ex::delete(editor, range))),
+            ex::Command::Edit(None, force) => {
+                if !has_path {
+                    return Err(ex::Error::NoFileName);
+                }
+                if changed && !force {
+                    return Err(ex::Error::NoWriteSinceLastChange);
+                }
+                Ok(self.update(Message::RevertAllChanges))
+            }
+            ex::Command::Edit(Some(path), _) => {
+                let path = self.ex_path(&path);
+                Ok(self.update(Message::OpenFile(path)))
+            }
+            ex::Command::GoToLine(line) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    ex::go_to_line(&mut tab.editor.lock().unwrap(), line);
+                }
+                Ok(Task::none())
+            }
+            ex::Command::NewFile => Ok(self.update(Message::NewFile)),
+            ex::Command::Next => Ok(self.update(Message::TabNext)),
+            ex::Command::NoHighlight => match self.find_opt {
+                Some(_) => Ok(self.update(Message::Find(None))),
+                None => Ok(Task::none()),
+            },
+            ex::Command::Previous => Ok(self.update(Message::TabPrev)),
+            ex::Command::Quit { all: false, force } => {
+                if changed && !force {
+                    return Err(ex::Error::NoWriteSinceLastChange);
+                }
+                Ok(self.update(Message::TabCloseForce(entity)))
+            }
+            ex::Command::Quit { all: true, force } => {
+                if !force && self.tab_model.iter().any(|entity| self.tab_changed(entity)) {
+                    return Err(ex::Error::NoWriteSinceLastChange);
+                }
+                Ok(self.update(Message::QuitForce))
+            }
+            ex::Command::Set(args) => {
+                if args.is_empty() {
+                    return Err(ex::Error::ArgumentRequired);
+                }
+                let mut tasks = Vec::with_capacity(args.len());
+                for arg in args.iter() {
+                    tasks.push(self.ex_set(arg)?);
+                }
+                Ok(Task::batch(tasks))
+            }
+            ex::Command::Split(vertical) => Ok(self.update(if vertical {
+                Message::SplitVertical
+            } else {
+                Message::SplitHorizontal
+            })),
+            ex::Command::Substitute {
+                range,
+                pattern,
+                replacement,
+                flags,
+            } => {
+                let mut result = Ok(0);
+                let task = self.edit_lines(|editor| {
+                    result = ex::substitute(editor, range, &pattern, &replacement, &flags);
+                });
+                let count = result?;
+                self.ex_message_opt = Some(if flags.contains('n') {
+                    fl!("ex-matches", count = count)
+                } else {
+                    fl!("ex-substitutions", count = count)
+                });
+                Ok(task)
+            }
+            ex::Command::Write {
+                path_opt: Some(path),
+                all: false,
+                quit,
+                force,
+            } => {
+                let path = self.ex_path(&path);
+                let task = if has_path {
+                    // The document keeps its path, and a copy is written to the other one
+                    if path.exists() && !force {
+                        return Err(ex::Error::FileExists);
+                    }
+                    let text = match self.tab_model.data::<Tab>(entity) {
+                        Some(Tab::Editor(tab)) => tab.text(),
+                        _ => String::new(),
+                    };
+                    fs::write(&path, text)
+                        .map_err(|err| ex::Error::WriteFailed(err.to_string()))?;
+                    Task::none()
+                } else {
+                    self.update(Message::SaveAsResult(
+                        entity,
+                        DialogResult::Open(vec![path]),
+                    ))
+                };
+                if quit && !self.tab_changed(entity) {
+                    return Ok(Task::batch([
+                        task,
+                        self.update(Message::TabCloseForce(entity)),
+                    ]));
+                }
+                Ok(task)
+            }
+            ex::Command::Write {
+                path_opt: None,
+                all: false,
+                quit,
+                force: _,
+            } => {
+                if !has_path {
+                    if quit {
+                        return Err(ex::Error::NoFileName);
+                    }
+                    return Ok(self.update(Message::SaveAsDialog(Some(entity))));
+                }
+                if !quit {
+                    return Ok(self.update(Message::Save(Some(entity))));
+                }
+                let task = self.save_tab(entity);
+                if self.tab_changed(entity) {
+                    return Err(ex::Error::WriteFailed(fl!("ex-not-saved")));
+                }
+                Ok(Task::batch([
+                    task,
+                    self.update(Message::TabCloseForce(entity)),
+                ]))
+            }
+            ex::Command::Write {
+                path_opt: _,
+                all: true,
+                quit,
+                force: _,
+            } => {
+                let task = self.update(Message::SaveAll);
+                if quit {
+                    // Documents without a path are not saved
+                    if self.tab_model.iter().any(|entity| self.tab_changed(entity)) {
+                        return Err(ex::Error::NoFileName);
+                    }
+                    return Ok(Task::batch([task, self.update(Message::QuitForce)]));
+                }
+                Ok(task)
+            }
+        }
+    }
+
+    fn tab_changed(&self, entity: segmented_button::Entity) -> bool {
+        match self.tab_model.data::<Tab>(entity) {
+            Some(Tab::Editor(tab)) => tab.changed(),
+            _ => false,
+        }
+    }
+
+    // Change the config like an argument of `:set`
+    fn ex_set(&mut self, arg: &str) -> Result<Task<Message>, ex::Error> {
+        let value = |option: ex::SetOption| match option {
+            ex::SetOption::AutoIndent => self.config.auto_indent,
+            ex::SetOption::CursorLine => self.config.highlight_current_line,
+            ex::SetOption::List => self.config.show_whitespace,
+            ex::SetOption::Number => self.config.line_numbers,
+            ex::SetOption::TabStop => false,
+            ex::SetOption::Wrap => self.config.word_wrap,
+        };
+        match ex::setting(arg)? {
+            ex::Setting::Show(option) => {
+                self.ex_message_opt = Some(if option.is_number() {
+                    format!("{}={}", option.name(), self.config.tab_width)
+                } else if value(option) {
+                    option.name().to_string()
+                } else {
+                    format!("no{}", option.name())
+                });
+                Ok(Task::none())
+            }
+            ex::Setting::Bool(option, value_opt) => {
+                if value_opt == Some(value(option)) {
+                    return Ok(Task::none());
+                }
+                Ok(self.update(match option {
+                    ex::SetOption::AutoIndent => Message::ToggleAutoIndent,
+                    ex::SetOption::CursorLine => Message::ToggleHighlightCurrentLine,
+                    ex::SetOption::List => Message::ToggleWhitespace,
+                    ex::SetOption::Number => Message::ToggleLineNumbers,
+                    ex::SetOption::TabStop => return Ok(Task::none()),
+                    ex::SetOption::Wrap => Message::ToggleWordWrap,
+                }))
+            }
+            ex::Setting::Number(_, number) => Ok(self.update(Message::TabWidth(number))),
+        }
+    }
+
+    // Placeholders of external tools from the active document
+    fn tool_context(&self) -> tools::ToolContext {
+        let mut context = tools::ToolContext {
+            file_opt: None,
+            line: 1,
+            column: 1,
+            selection: String::new(),
+            project_opt: self.project_dir(),
+        };
+        if let Some(Tab::Editor(tab)) = self.active_tab() {
+            let (line, column, _selected) = tab.cursor_status();
+            context.file_opt = tab.path_opt.clone();
+            context.line = line;
+            context.column = column;
+            context.selection = tab
+                .editor
+                .lock()
+                .unwrap()
+                .copy_selection()
+                .unwrap_or_default();
+        }
+        context
+    }
+
+    // Replace the selection, or else the whole text, with the output of a shell command that
+    // reads it on stdin
+    fn filter_tab(&mut self, entity: segmented_button::Entity, command: String) -> Task<Message> {
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return Task::none();
+        };
+        // The output is applied like formatting, so it is dropped when the text changes
+        tab.format_request += 1;
+        let request = tab.format_request;
+        let selection_opt = {
+            let editor = tab.editor.lock().unwrap();
+            editor.selection_bounds().zip(editor.copy_selection())
+        };
+        let path_opt = tab.path_opt.clone();
+        let text = tab.text();
+        Task::perform(
+            async move {
+                let input = match &selection_opt {
+                    Some((_, selected)) => selected.clone(),
+                    None => text.clone(),
+                };
+                let shell = vec!["sh".to_string(), "-c".to_string(), command.clone()];
+                let edits_opt = match format::external(shell, path_opt.as_deref(), input).await {
+                    Ok(mut output) => Some(match selection_opt {
+                        Some(((start, end), selected)) => {
+                            // Commands end their output with a newline that the selection may
+                            // not have had
+                            if !selected.ends_with('\n') && output.ends_with('\n') {
+                                output.pop();
+                            }
+                            vec![lsp::TextEdit {
+                                start,
+                                end,
+                                text: output,
+                            }]
+                        }
+                        None => format::diff_edits(
+                            &text.replace("\r\n", "\n"),
+                            &output.replace("\r\n", "\n"),
+                        ),
+                    }),
+                    Err(err) => {
+                        log::warn!(
+                            "failed to filter {:?} through {:?}: {}",
+                            path_opt,
+                            command,
+                            err
+                        );
+                        None
+                    }
+                };
+                action::app(Message::Formatted(entity, request, edits_opt))
+            },
+            |x| x,
+        )
+    }
+
+    // Format with the external formatter of the syntax, or else with the language server
+    fn format_tab(&mut self, entity: segmented_button::Entity, save: bool) -> Task<Message> {
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return Task::none();
+        };
+        tab.format_request += 1;
+        let request = tab.format_request;
+        if save {
+            tab.save_after_format = Some(request);
+        }
+        if let Some(command) = self.config.formatter(tab.syntax_name()) {
+            let command = command.to_vec();
+            let path_opt = tab.path_opt.clone();
+            let text = tab.text();
+            return Task::perform(
+                async move {
+                    let edits_opt =
+                        match format::external(command, path_opt.as_deref(), text.clone()).await {
+                            // Line endings are compared as they are in the editor
+                            Ok(formatted) => Some(format::diff_edits(
+                                &text.replace("\r\n", "\n"),
+                                &formatted.replace("\r\n", "\n"),
+                            )),
+                            Err(err) => {
+                                log::warn!("failed to format {:?}: {}", path_opt, err);
+                                None
+                            }
+                        };
+                    action::app(Message::Formatted(entity, request, edits_opt))
+                },
+                |x| x,
+            );
+        }
+        if let Some(worker) = &self.lsp_worker_opt
+            && tab.diagnostics_opt.is_some()
+        {
+            worker.format(entity, request, self.config.tab_width, !tab.indent_tabs());
+            return Task::none();
+        }
+        self.update(Message::Formatted(entity, request, None))
+    }
+
+    // Find the symbols of the active document while the outline is shown
+    fn update_outline(&mut self, entity: segmented_button::Entity) {
+        if !self.core.window.show_context
+            || self.context_page != ContextPage::Outline
+            || entity != self.tab_model.active()
+        {
+            return;
+        }
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return;
+        };
+        tab.outline_request += 1;
+        match &self.lsp_worker_opt {
+            Some(worker) if tab.diagnostics_opt.is_some() => {
+                worker.symbols(entity, tab.outline_request);
+            }
+            _ => tab.update_outline(),
+        }
+    }
+
+    // Render the preview of the active document again, if it is a Markdown document
+    fn update_markdown_preview(&mut self, entity: segmented_button::Entity) {
+        if !self.config.markdown_preview || entity != self.tab_model.active() {
+            return;
+        }
+        let syntax_system = syntax_system();
+        let highlight_opt = syntax_system
+            .theme_set
+            .themes
+            .get(self.config.syntax_theme())
+            .map(|theme| (&syntax_system.syntax_set, theme));
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return;
+        };
+        tab.markdown_blocks = if markdown::is_markdown(tab.syntax_name()) && !tab.large {
+            markdown::parse(&tab.text(), highlight_opt)
+        } else {
+            Vec::new()
+        };
+    }
+
+    // The preview is shown beside the active document instead of a split
+    fn markdown_preview_shown(&self) -> bool {
+        self.config.markdown_preview
+            && self.split_opt.is_none()
+            && matches!(
+                self.active_tab(),
+                Some(Tab::Editor(tab)) if markdown::is_markdown(tab.syntax_name())
+            )
+    }
+
+    // Folder of the project that the active document is in, or of the first project
+    fn project_dir(&self) -> Option<PathBuf> {
+        let path_opt = match self.active_tab() {
+            Some(Tab::Editor(tab)) => tab.path_opt.as_deref(),
+            _ => None,
+        };
+        self.projects
+            .iter()
+            .map(|(_name, project_path)| project_path)
+            .find(|project_path| path_opt.is_some_and(|path| path.starts_with(project_path)))
+            .or_else(|| {
+                self.projects
+                    .first()
+                    .map(|(_name, project_path)| project_path)
+            })
+            .cloned()
+            .or_else(|| path_opt.and_then(Path::parent).map(Path::to_path_buf))
+    }
+
+    // Add output of the task, linking it to the first file location in it
+    fn task_push(&mut self, line: String) -> Task<Message> {
+        let location_opt = self.task_running_opt.as_ref().and_then(|(_task, dir)| {
+            tasks::location(&line, dir)
+                .map(|(path, line_i, column)| (path, Cursor::new(line_i, column)))
+        });
+        self.task_output.push((line, location_opt));
+        if self.task_output.len() > tasks::SCROLLBACK {
+            let extra = self.task_output.len() - tasks::SCROLLBACK;
+            self.task_output.drain(..extra);
+        }
+        iced::widget::scrollable::snap_to(
+            self.task_scrollable_id.clone(),
+            iced::widget::scrollable::RelativeOffset::END,
+        )
+    }
+
+    fn task_panel(&self) -> Element<'_, Message> {
+        let cosmic_theme::Spacing {
+            space_xxs,
+            space_xs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+        let mut header = widget::row::with_capacity(6)
+            .align_y(Alignment::Center)
+            .spacing(space_xs);
+        match &self.task_last_opt {
+            Some((task, dir)) => {
+                header = header
+                    .push(widget::text::heading(&task.name))
+                    .push(widget::text::caption(dir.to_string_lossy()));
+            }
+            None => header = header.push(widget::text::heading(fl!("task-output"))),
+        }
+        header = header.push(widget::horizontal_space());
+        if self.task_running_opt.is_some() {
+            header = header.push(
+                button::custom(icon_cache_get("media-playback-stop-symbolic", 16))
+                    .on_press(Message::StopTask)
+                    .padding(space_xxs)
+                    .class(style::Button::Icon),
+            );
+        } else if self.task_last_opt.is_some() {
+            header = header.push(
+                button::custom(icon_cache_get("view-refresh-symbolic", 16))
+                    .on_press(Message::RerunTask)
+                    .padding(space_xxs)
+                    .class(style::Button::Icon),
+            );
+        }
+        header = header.push(
+            button::custom(icon_cache_get("window-close-symbolic", 16))
+                .on_press(Message::ToggleTaskOutput)
+                .padding(space_xxs)
+                .class(style::Button::Icon),
+        );
+
+        let mut column = widget::column::with_capacity(self.task_output.len());
+        for (line, location_opt) in self.task_output.iter() {
+            let text = widget::text::monotext(line).width(Length::Fill);
+            column = column.push(match location_opt {
+                // Locations in errors open the file at the line and column
+                Some((path, cursor)) => Element::from(
+                    widget::button::custom(text)
+                        .on_press(Message::GoToLocation(path.clone(), *cursor))
+                        .padding(0)
+                        .class(theme::Button::Link),
+                ),
+                None => Element::from(text),
+            });
+        }
+        let output = widget::scrollable(column)
+            .id(self.task_scrollable_id.clone())
+            .height(Length::Fill);
+        widget::column::with_children(vec![header.into(), output.into()])
+            .padding([space_xxs, 0])
+            .spacing(space_xxs)
+            .height(Length::Fixed(TASK_OUTPUT_HEIGHT))
+            .into()
+    }
+
+    // Options of printed and exported documents, the paper ones are only shown for pages
+    // Printed text is only shown correctly if it has no characters outside of Latin-1, this
+    // includes the header and footer if they are printed
+    fn print_unsupported(&self, entity: segmented_button::Entity) -> Option<char> {
+        self.print_unsupported_opt.or_else(|| {
+            if !self.print_options.header_footer {
+                return None;
+            }
+            let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+                return None;
+            };
+            print::unsupported_char(&tab.title())
+                .or_else(|| print::unsupported_char(&fl!("print-page", page = 1, pages = 1)))
+        })
+    }
+
+    fn print_options_section(&self, paper: bool) -> Element<'_, Message> {
+        let options = self.print_options;
+        let paper_selected = match options.paper {
+            Paper::A4 => 0,
+            Paper::Letter => 1,
+        };
+        let mut section = widget::settings::section();
+        if paper {
+            section =
+                section
+                    .add(widget::settings::item::builder(fl!("paper-size")).control(
+                        widget::dropdown(&self.paper_names, Some(paper_selected), move |index| {
+                            Message::PrintOptions(PrintOptions {
+                                paper: match index {
+                                    1 => Paper::Letter,
+                                    _ => Paper::A4,
+                                },
+                                ..options
+                            })
+                        }),
+                    ))
+                    .add(
+                        widget::settings::item::builder(fl!("print-header-footer")).toggler(
+                            options.header_footer,
+                            move |header_footer| {
+                                Message::PrintOptions(PrintOptions {
+                                    header_footer,
+                                    ..options
+                                })
+                            },
+                        ),
+                    );
+        }
+        section
+            .add(
+                widget::settings::item::builder(fl!("print-line-numbers")).toggler(
+                    options.line_numbers,
+                    move |line_numbers| {
+                        Message::PrintOptions(PrintOptions {
+                            line_numbers,
+                            ..options
+                        })
+                    },
+                ),
+            )
+            .add(
+                widget::settings::item::builder(fl!("print-syntax-colors")).toggler(
+                    options.syntax_colors,
+                    move |syntax_colors| {
+                        Message::PrintOptions(PrintOptions {
+                            syntax_colors,
+                            ..options
+                        })
+                    },
+                ),
+            )
+            .into()
+    }
+
+    // Ask for the signature of the call being typed, which is asked again while it is shown
+    fn update_signature_help(&mut self, entity: segmented_button::Entity) {
+        let Some(worker) = &self.lsp_worker_opt else {
+            return;
+        };
+        let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+            return;
+        };
+        if tab.diagnostics_opt.is_none() {
+            tab.signature_opt = None;
+            return;
+        }
+        let (cursor, before_opt) = {
+            let editor = tab.editor.lock().unwrap();
+            let cursor = editor.cursor();
+            let before_opt = editor.with_buffer(|buffer| {
+                buffer.lines[cursor.line].text()[..cursor.index]
+                    .chars()
+                    .next_back()
+            });
+            (cursor, before_opt)
+        };
+        match before_opt {
+            Some('(' | ',') => {}
+            Some(')') => {
+                tab.signature_opt = None;
+                return;
+            }
+            _ if tab.signature_opt.is_some() => {}
+            _ => return,
+        }
+        tab.signature_request += 1;
+        worker.signature_help(entity, cursor, tab.signature_request);
+    }
+
+    fn update_watcher(&mut self) {
+        if let Some((mut watcher, old_paths)) = self.watcher_opt.take() {
+            let mut new_paths = HashSet::new();
+
+            for (_, project_path) in self.projects.iter() {
+                new_paths.insert((project_path.clone(), RecursiveMode::Recursive));
+            }
+
+            'tabs: for entity in self.tab_model.iter() {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    if let Some(path) = &tab.path_opt {
+                        for (_, project_path) in self.projects.iter() {
+                            if path.starts_with(&project_path) {
+                                // Do not watch tabs inside of already watched projects
+                                continue 'tabs;
+                            }
+                        }
+                        // Watch the folder as the file is replaced by atomic saves
+                        let watch_path = path.parent().unwrap_or(path);
+                        new_paths.insert((watch_path.to_path_buf(), RecursiveMode::NonRecursive));
+                    }
+                }
+            }
+
+            // Unwatch paths no longer used
+            for path_mode in old_paths.iter() {
+                if !new_paths.contains(path_mode) {
+                    let (path, _) = path_mode;
+                    match watcher.unwatch(path) {
+                        Ok(()) => {
+                            log::debug!("unwatching {:?}", path);
+                        }
+                        Err(err) => {
+                            log::debug!("failed to unwatch {:?}: {}", path, err);
+                        }
+                    }
+                }
+            }
+
+            // Watch new paths
+            for path_mode in new_paths.iter() {
+                if !old_paths.contains(path_mode) {
+                    let (path, mode) = path_mode;
+                    match watcher.watch(path, *mode) {
+                        Ok(()) => {
+                            log::debug!("watching {:?} {:?}", path, mode);
+                        }
+                        Err(err) => {
+                            log::debug!("failed to watch {:?} {:?}: {}", path, mode, err);
+                        }
+                    }
+                }
+            }
+
+            self.watcher_opt = Some((watcher, new_paths));
+        }
+    }
+
+    fn document_statistics(&self) -> Element<'_, Message> {
+        #[derive(Default)]
+        struct Statistics {
+            character_count: usize,
+            character_count_no_spaces: usize,
+            line_count: usize,
+            word_count: usize,
+        }
+
+        impl Statistics {
+            fn add_line(&mut self, text: &str) {
+                let mut last_whitespace = true;
+
+                // Count graphemes instead of Unicode scalar values for accurate character count
+                for grapheme in text.graphemes(true) {
+                    self.character_count += 1;
+                    let is_whitespace = grapheme.chars().all(|c| c.is_whitespace());
+                    if !is_whitespace {
+                        self.character_count_no_spaces += 1;
+                        if last_whitespace {
+                            self.word_count += 1;
+                        }
+                    }
+                    last_whitespace = is_whitespace;
+                }
+                self.line_count += 1;
+            }
+
+            fn section<'a>(&self) -> widget::settings::Section<'a, Message> {
+                // Based on an average silent reading speed of 200 words per minute
+                let reading_minutes = self.word_count.div_ceil(200);
+                widget::settings::section()
+                    .add(
+                        widget::settings::item::builder(fl!("word-count"))
+                            .control(widget::text(self.word_count.to_string())),
+                    )
+                    .add(
+                        widget::settings::item::builder(fl!("character-count"))
+                            .control(widget::text(self.character_count.to_string())),
+                    )
+                    .add(
+                        widget::settings::item::builder(fl!("character-count-no-spaces"))
+                            .control(widget::text(self.character_count_no_spaces.to_string())),
+                    )
+                    .add(
+                        widget::settings::item::builder(fl!("line-count"))
+                            .control(widget::text(self.line_count.to_string())),
+                    )
+                    .add(
+                        widget::settings::item::builder(fl!("reading-time")).control(widget::text(
+                            fl!("reading-time-minutes", minutes = reading_minutes),
+                        )),
+                    )
+            }
+        }
+
+        //TODO: calculate in the background
+        let mut document = Statistics::default();
+        let mut selection_opt = None;
+
+        if let Some(Tab::Editor(tab)) = self.active_tab() {
+            let editor = tab.editor.lock().unwrap();
+            editor.with_buffer(|buffer| {
+                for line in buffer.lines.iter() {
+                    document.add_line(line.text());
+                }
+            });
+
+            if let Some(text) = editor.copy_selection() {
+                let mut selection = Statistics::default();
+                for line in text.lines() {
+                    selection.add_line(line);
+                }
+                selection_opt = Some(selection);
+            }
+        }
+
+        let mut sections = Vec::with_capacity(2);
+        sections.push(document.section().into());
+        if let Some(selection) = selection_opt {
+            sections.push(selection.section().title(fl!("selection")).into());
+        }
+        widget::settings::view_column(sections).into()
+    }
+
+    fn document_type(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        let (syntax_override, syntax_name) = match self.active_tab() {
+            Some(Tab::Editor(tab)) => (tab.syntax_override.as_deref(), Some(tab.syntax_name())),
+            _ => (None, None),
+        };
+
+        let search = self.document_type_search_value.to_lowercase();
+        let mut column = widget::column::with_capacity(self.syntax_names.len().saturating_add(1));
+        if search.is_empty() {
+            let automatic = match (syntax_override, syntax_name) {
+                (None, Some(syntax_name)) => format!("{} ({})", fl!("automatic"), syntax_name),
+                _ => fl!("automatic"),
+            };
+            column = column.push(selectable_item(
+                automatic,
+                syntax_override.is_none(),
+                Message::DocumentType(None),
+            ));
+        }
+        for name in self.syntax_names.iter() {
+            if !search.is_empty() && !name.to_lowercase().contains(&search) {
+                continue;
+            }
+            column = column.push(selectable_item(
+                name.clone(),
+                syntax_override == Some(name.as_str()),
+                Message::DocumentType(Some(name.clone())),
+            ));
+        }
+
+        widget::column::with_children(vec![
+            widget::text_input::search_input(
+                fl!("document-type-search"),
+                self.document_type_search_value.clone(),
+            )
+            .id(self.document_type_search_id.clone())
+            .on_input(Message::DocumentTypeSearchValue)
+            .into(),
+            widget::settings::section().add(column).into(),
+        ])
+        .spacing(spacing.space_s)
+        .padding([spacing.space_xxs, spacing.space_none])
+        .into()
+    }
+
+    fn encoding(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        let current_opt = match self.active_tab() {
+            Some(Tab::Editor(tab)) => Some(tab.encoding),
+            _ => None,
+        };
+
+        let encodings = encoding::encodings();
+        let mut column = widget::column::with_capacity(encodings.len());
+        for encoding in encodings {
+            column = column.push(selectable_item(
+                encoding.name().to_string(),
+                current_opt == Some(encoding),
+                Message::SaveWithEncoding(encoding),
+            ));
+        }
+
+        widget::settings::section()
+            .add(column)
+            .apply(widget::container)
+            .padding([spacing.space_xxs, spacing.space_none])
+            .into()
+    }
+
+    fn git_management(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        if let Some(project_status) = &self.git_project_status {
+            let (success_color, destructive_color, warning_color) = {
+                let cosmic_theme = self.core().system_theme().cosmic();
+                (
+                    cosmic_theme.success_color(),
+                    cosmic_theme.destructive_color(),
+                    cosmic_theme.warning_color(),
+                )
+            };
+            let added = || widget::text("[+]").class(theme::Text::Color(success_color.into()));
+            let deleted =
+                || widget::text("[-]").class(theme::Text::Color(destructive_color.into()));
+            let modified = || widget::text("[*]").class(theme::Text::Color(warning_color.into()));
+
+            let mut items =
+                Vec::with_capacity(project_status.len().saturating_mul(3).saturating_add(1));
+            items.push(widget::text(fl!("git-management-description")).into());
+
+            for (project_name, project_path, status) in project_status.iter() {
+                let mut unstaged_items = Vec::with_capacity(status.len());
+                let mut staged_items = Vec::with_capacity(status.len());
+                for item in status.iter() {
+                    let relative_path = match item.path.strip_prefix(project_path) {
+                        Ok(ok) => ok,
+                        Err(err) => {
+                            log::warn!(
+                                "failed to find relative path of {:?} in project {:?}: {}",
+                                item.path,
+                                project_path,
+                                err
+                            );
+                            &item.path
+                        }
+                    };
+
+                    let text = match &item.old_path {
+                        Some(old_path) => {
+                            let old_relative_path = match old_path.strip_prefix(project_path) {
+                                Ok(ok) => ok,
+                                Err(err) => {
+                                    log::warn!(
+                                        "failed to find relative path of {:?} in project {:?}: {}",
+                                        old_path,
+                                        project_path,
+                                        err
+                                    );
+                                    old_path
+                                }
+                            };
+                            format!(
+                                "{} -> {}",
+                                old_relative_path.display(),
+                                relative_path.display()
+                            )
+                        }
+                        None => format!("{}", relative_path.display()),
+                    };
+
+                    let unstaged_opt = match item.unstaged {
+                        GitStatusKind::Unmodified => None,
+                        GitStatusKind::Modified => Some(modified()),
+                        GitStatusKind::FileTypeChanged => Some(modified()),
+                        GitStatusKind::Added => Some(added()),
+                        GitStatusKind::Deleted => Some(deleted()),
+                        GitStatusKind::Renamed => Some(modified()), //TODO
+                        GitStatusKind::Copied => Some(modified()),  // TODO
+                        GitStatusKind::Updated => Some(modified()),
+                        GitStatusKind::Untracked => Some(added()),
+                        GitStatusKind::SubmoduleModified => Some(modified()),
+                    };
+
+                    if let Some(icon) = unstaged_opt {
+                        unstaged_items.push(
+                            widget::button::custom(
+                                widget::row::with_children(vec![
+                                    icon.into(),
+                                    widget::text(text.clone()).into(),
+                                    widget::horizontal_space().into(),
+                                    widget::button::standard(fl!("stage"))
+                                        .on_press(Message::GitStage(
+                                            project_path.clone(),
+                                            item.path.clone(),
+                                        ))
+                                        .into(),
+                                ])
+                                .align_y(Alignment::Center)
+                                .spacing(spacing.space_xs),
+                            )
+                            .on_press(Message::PrepareGitDiff(
+                                project_path.clone(),
+                                item.path.clone(),
+                                false,
+                            ))
+                            .class(theme::Button::AppletMenu)
+                            .width(Length::Fill)
+                            .into(),
+                        );
+                    }
+
+                    let staged_opt = match item.staged {
+                        GitStatusKind::Unmodified => None,
+                        GitStatusKind::Modified => Some(modified()),
+                        GitStatusKind::FileTypeChanged => Some(modified()),
+                        GitStatusKind::Added => Some(added()),
+                        GitStatusKind::Deleted => Some(deleted()),
+                        GitStatusKind::Renamed => Some(modified()), //TODO
+                        GitStatusKind::Copied => Some(modified()),  // TODO
+                        GitStatusKind::Updated => Some(modified()),
+                        GitStatusKind::Untracked => None,
+                        GitStatusKind::SubmoduleModified => Some(modified()),
+                    };
+
+                    if let Some(icon) = staged_opt {
+                        staged_items.push(
+                            widget::button::custom(
+                                widget::row::with_children(vec![
+                                    icon.into(),
+                                    widget::text(text.clone()).into(),
+                                    widget::horizontal_space().into(),
+                                    widget::button::standard(fl!("unstage"))
+                                        .on_press(Message::GitUnstage(
+                                            project_path.clone(),
+                                            item.path.clone(),
+                                        ))
+                                        .into(),
+                                ])
+                                .align_y(Alignment::Center)
+                                .spacing(spacing.space_xs),
+                            )
+                            .on_press(Message::PrepareGitDiff(
+                                project_path.clone(),
+                                item.path.clone(),
+                                true,
+                            ))
+                            .class(theme::Button::AppletMenu)
+                            .width(Length::Fill)
+                            .into(),
+                        );
+                    }
+                }
+
+                items.push(widget::text::heading(project_name.clone()).into());
+
+                if !unstaged_items.is_empty() {
+                    items.push(
+                        widget::settings::section()
+                            .title(fl!("unstaged-changes"))
+                            .add(widget::column::with_children(unstaged_items))
+                            .into(),
+                    );
+                }
+
+                if !staged_items.is_empty() {
+                    items.push(
+                        widget::settings::section()
+                            .title(fl!("staged-changes"))
+                            .add(widget::column::with_children(staged_items))
+                            .into(),
+                    );
+                }
+            }
+
+            widget::column::with_children(items)
+                .spacing(spacing.space_s)
+                .padding([spacing.space_xxs, spacing.space_none])
+                .into()
+        } else {
+            widget::column::with_children(vec![
+                widget::text(fl!("git-management-description")).into(),
+                widget::text(fl!("git-management-loading")).into(),
+            ])
+            .spacing(spacing.space_s)
+            .padding([spacing.space_xxs, spacing.space_none])
+            .into()
+        }
+    }
+
+    fn project_replace_controls(&self, can_replace: bool) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        let mut column = widget::column::with_capacity(3)
+            .spacing(spacing.space_xxs)
+            .push(
+                widget::checkbox(fl!("replace"), self.project_replace_mode)
+                    .on_toggle(Message::ProjectReplaceMode),
+            );
+        if self.project_replace_mode {
+            let mut replace_selected = widget::button::standard(fl!("replace-selected"));
+            let mut replace_all = widget::button::standard(fl!("replace-all"));
+            if can_replace {
+                replace_selected = replace_selected.on_press(Message::ProjectReplaceSelected);
+                replace_all = replace_all.on_press(Message::ProjectReplaceAll);
+            }
+            column = column
+                .push(
+                    widget::text_input::text_input(
+                        fl!("replace-placeholder"),
+                        &self.project_replace_value,
+                    )
+                    .on_input(Message::ProjectReplaceValue),
+                )
+                .push(
+                    widget::row::with_children(vec![
+                        widget::horizontal_space().into(),
+                        replace_selected.into(),
+                        replace_all.into(),
+                    ])
+                    .spacing(spacing.space_xxs),
+                );
+        }
+        column.into()
+    }
+
+    fn problems(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        let mut items = Vec::new();
+        for entity in self.tab_model.iter() {
+            let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+                continue;
+            };
+            let Some(diagnostics) = tab.diagnostics_opt.as_ref().filter(|d| !d.is_empty()) else {
+                continue;
+            };
+            let mut column = widget::column::with_capacity(diagnostics.len());
+            for diagnostic in diagnostics.iter() {
+                let icon_name = match diagnostic.severity {
+                    lsp::Severity::Error => "dialog-error-symbolic",
+                    lsp::Severity::Warning => "dialog-warning-symbolic",
+                    lsp::Severity::Information | lsp::Severity::Hint => {
+                        "dialog-information-symbolic"
+                    }
+                };
+                let mut message = diagnostic.message.clone();
+                if let Some(source) = &diagnostic.source_opt {
+                    message = format!("{source}: {message}");
+                }
+                column = column.push(
+                    widget::button::custom(
+                        widget::row::with_children(vec![
+                            icon_cache_get(icon_name, 16).into(),
+                            widget::text(format!(
+                                "{}:{}",
+                                diagnostic.start.line + 1,
+                                diagnostic.start.index + 1
+                            ))
+                            .font(Font::MONOSPACE)
+                            .into(),
+                            widget::text(message).into(),
+                        ])
+                        .spacing(spacing.space_xs),
+                    )
+                    .on_press(Message::GoToDiagnostic(entity, diagnostic.start))
+                    .width(Length::Fill)
+                    .class(theme::Button::AppletMenu),
+                );
+            }
+            let title = match &tab.path_opt {
+                Some(path) => path.display().to_string(),
+                None => tab.title(),
+            };
+            items.push(widget::settings::section().title(title).add(column).into());
+        }
+        if items.is_empty() {
+            items.push(widget::text(fl!("no-problems")).into());
+        }
+
+        widget::column::with_children(items)
+            .spacing(spacing.space_s)
+            .padding([spacing.space_xxs, spacing.space_none])
+            .into()
+    }
+
+    fn outline(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        let entity = self.tab_model.active();
+        let mut items = Vec::new();
+        if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+            && let Some(symbols) = &tab.outline_opt
+        {
+            // The symbol that the cursor is in is selected
+            let cursor = tab.editor.lock().unwrap().cursor();
+            let current_opt = symbols.iter().rposition(|symbol| {
+                (symbol.start.line, symbol.start.index) <= (cursor.line, cursor.index)
+            });
+            for (symbol_i, symbol) in symbols.iter().enumerate() {
+                let mut row = widget::row::with_capacity(3)
+                    .push(widget::horizontal_space().width(Length::Fixed(
+                        f32::from(spacing.space_m) * symbol.depth as f32,
+                    )))
+                    .push(widget::text(&symbol.name));
+                if let Some(detail) = &symbol.detail_opt {
+                    row = row.push(widget::text::caption(detail));
+                }
+                items.push(
+                    widget::button::custom(
+                        row.align_y(Alignment::Center).spacing(spacing.space_xs),
+                    )
+                    .on_press(Message::GoToDiagnostic(entity, symbol.start))
+                    .selected(current_opt == Some(symbol_i))
+                    .width(Length::Fill)
+                    .class(theme::Button::AppletMenu)
+                    .into(),
+                );
+            }
+        }
+        if items.is_empty() {
+            items.push(widget::text(fl!("no-symbols")).into());
+        }
+
+        widget::column::with_children(items)
+            .padding([spacing.space_xxs, spacing.space_none])
+            .into()
+    }
+
+    fn undo_history(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        let mut items = Vec::new();
+        if let Some(Tab::Editor(tab)) = self.active_tab()
+            && !tab.large
+        {
+            let history = &tab.undo_history;
+            let columns = history.columns();
+            // The newest edits are at the top
+            for (index, state) in history.states().iter().enumerate().rev() {
+                let label = match state.parent_opt {
+                    Some(_) => fl!("undo-history-edit", number = index),
+                    None => fl!("undo-history-opened"),
+                };
+                let row = widget::row::with_children(vec![
+                    widget::horizontal_space()
+                        .width(Length::Fixed(
+                            f32::from(spacing.space_m) * columns[index] as f32,
+                        ))
+                        .into(),
+                    widget::text(label).into(),
+                    widget::horizontal_space().into(),
+                    widget::text::caption(time_ago(state.time)).into(),
+                ]);
+                items.push(
+                    widget::button::custom(
+                        row.align_y(Alignment::Center).spacing(spacing.space_xs),
+                    )
+                    .on_press(Message::UndoHistoryJump(index))
+                    .selected(index == history.current())
+                    .width(Length::Fill)
+                    .class(theme::Button::AppletMenu)
+                    .into(),
+                );
+            }
+        }
+        if items.is_empty() {
+            items.push(widget::text(fl!("no-undo-history")).into());
+        }
+
+        widget::column::with_children(items)
+            .padding([spacing.space_xxs, spacing.space_none])
+            .into()
+    }
+
+    fn references(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        // References are sorted by file, each file gets a section
+        let mut items = Vec::new();
+        for references in self.references.chunk_by(|a, b| a.path == b.path) {
+            let mut column = widget::column::with_capacity(references.len());
+            for reference in references.iter() {
+                column = column.push(
+                    widget::button::custom(
+                        widget::row::with_children(vec![
+                            widget::text(format!(
+                                "{}:{}",
+                                reference.start.line + 1,
+                                reference.start.index + 1
+                            ))
+                            .font(Font::MONOSPACE)
+                            .into(),
+                            widget::text(&reference.line_text)
+                                .font(Font::MONOSPACE)
+                                .into(),
+                        ])
+                        .spacing(spacing.space_xs),
+                    )
+                    .on_press(Message::GoToLocation(
+                        reference.path.clone(),
+                        reference.start,
+                    ))
+                    .width(Length::Fill)
+                    .class(theme::Button::AppletMenu),
+                );
+            }
+            items.push(
+                widget::settings::section()
+                    .title(references[0].path.display().to_string())
+                    .add(column)
+                    .into(),
+            );
+        }
+        if items.is_empty() {
+            items.push(widget::text(fl!("no-references")).into());
+        }
+
+        widget::column::with_children(items)
+            .spacing(spacing.space_s)
+            .padding([spacing.space_xxs, spacing.space_none])
+            .into()
+    }
+
+    fn project_search(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+        let (success_color, destructive_color) = {
+            let cosmic_theme = self.core().system_theme().cosmic();
+            (
+                cosmic_theme.success_color(),
+                cosmic_theme.destructive_color(),
+            )
+        };
+
+        let search_input = widget::text_input::search_input(
+            fl!("project-search"),
+            self.project_search_value.clone(),
+        )
+        .id(self.project_search_id.clone());
+
+        let items = match &self.project_search_result {
+            Some(project_search_result) => {
+                let mut items =
+                    Vec::with_capacity(project_search_result.files.len().saturating_add(1));
+
+                items.push(
+                    search_input
+                        .on_input(Message::ProjectSearchValue)
+                        .on_submit(|_| Message::ProjectSearchSubmit)
+                        .into(),
+                );
+                items.push(self.project_replace_controls(!project_search_result.in_progress));
+                if project_search_result.in_progress {
+                    items.push(widget::text(fl!("project-search-in-progress")).into());
+                }
+
+                let replace_regex_opt = if self.project_replace_mode {
+                    Regex::new(&project_search_result.value).ok()
+                } else {
+                    None
+                };
+
+                for (file_i, file_search_result) in project_search_result.files.iter().enumerate() {
+                    let mut column = widget::column::with_capacity(file_search_result.lines.len());
+                    let mut line_number_width = 1;
+                    if let Some(line_search_result) = file_search_result.lines.last() {
+                        let mut number = line_search_result.number;
+                        while number >= 10 {
+                            number /= 10;
+                            line_number_width += 1;
+                        }
+                    }
+                    for (line_i, line_search_result) in file_search_result.lines.iter().enumerate()
+                    {
+                        let number = widget::text(format!(
+                            "{:width$}",
+                            line_search_result.number,
+                            width = line_number_width,
+                        ))
+                        .font(Font::MONOSPACE);
+                        let button = match &replace_regex_opt {
+                            Some(regex) => {
+                                // Show a preview of the replacement
+                                let replaced = regex
+                                    .replace_all(
+                                        &line_search_result.text,
+                                        self.project_replace_value.as_str(),
+                                    )
+                                    .into_owned();
+                                widget::button::custom(
+                                    widget::row::with_children(vec![
+                                        number.into(),
+                                        widget::column::with_children(vec![
+                                            widget::text(format!("- {}", line_search_result.text))
+                                                .font(Font::MONOSPACE)
+                                                .class(theme::Text::Color(destructive_color.into()))
+                                                .into(),
+                                            widget::text(format!("+ {}", replaced))
+                                                .font(Font::MONOSPACE)
+                                                .class(theme::Text::Color(success_color.into()))
+                                                .into(),
+                                        ])
+                                        .into(),
+                                    ])
+                                    .spacing(spacing.space_xs),
+                                )
+                            }
+                            None => widget::button::custom(
+                                widget::row::with_children(vec![
+                                    number.into(),
+                                    widget::text(line_search_result.text.to_string())
+                                        .font(Font::MONOSPACE)
+                                        .into(),
+                                ])
+                                .spacing(spacing.space_xs),
+                            ),
+                        }
+                        .on_press(Message::OpenSearchResult(file_i, line_i))
+                        .width(Length::Fill)
+                        .class(theme::Button::AppletMenu);
+
+                        if self.project_replace_mode {
+                            column = column.push(
+                                widget::row::with_children(vec![
+                                    widget::checkbox(
+                                        "",
+                                        !self.project_replace_excluded.contains(&(file_i, line_i)),
+                                    )
+                                    .on_toggle(move |checked| {
+                                        Message::ProjectReplaceToggle(file_i, line_i, checked)
+                                    })
+                                    .into(),
+                                    button.into(),
+                                ])
+                                .align_y(Alignment::Center)
+                                .spacing(spacing.space_xxs),
+                            );
+                        } else {
+                            column = column.push(button);
+                        }
+                    }
+
+                    items.push(
+                        widget::settings::section()
+                            .title(format!("{}", file_search_result.path.display(),))
+                            .add(column)
+                            .into(),
+                    );
+                }
+
+                items
+            }
+            None => {
+                vec![
+                    search_input
+                        .on_input(Message::ProjectSearchValue)
+                        .on_submit(|_| Message::ProjectSearchSubmit)
+                        .into(),
+                    self.project_replace_controls(false),
+                ]
+            }
+        };
+
+        widget::column::with_children(items)
+            .spacing(spacing.space_s)
+            .padding([spacing.space_xxs, spacing.space_none])
+            .into()
+    }
+
+    fn keyboard_shortcuts(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+
+        let mut section = widget::settings::section();
+        for (action, label) in self.command_palette.iter() {
+            if fuzzy::score(&self.key_bind_search_value, label).is_none() {
+                continue;
+            }
+
+            let mut item = widget::settings::item::builder(label.clone());
+            let control: Element<_> = match self.key_bind_conflict_opt {
+                Some((conflict_action, ref key_bind, other_action))
+                    if conflict_action == *action =>
+                {
+                    let other_label = self
+                        .command_palette
+                        .iter()
+                        .find(|(command_action, _)| *command_action == other_action)
+                        .map_or_else(|| format!("{:?}", other_action), |(_, label)| label.clone());
+                    item = item.description(fl!(
+                        "shortcut-conflict",
+                        shortcut = key_bind.to_string(),
+                        command = other_label
+                    ));
+                    widget::row::with_children(vec![
+                        widget::button::text(fl!("cancel"))
+                            .on_press(Message::KeyBindCancel)
+                            .into(),
+                        widget::button::destructive(fl!("replace"))
+                            .on_press(Message::KeyBindSet(*action, key_bind.clone()))
+                            .into(),
+                    ])
+                    .spacing(spacing.space_xxs)
+                    .into()
+                }
+                _ if self.key_bind_recording_opt == Some(*action) => {
+                    widget::row::with_children(vec![
+                        widget::text::body(fl!("press-shortcut")).into(),
+                        widget::button::text(fl!("cancel"))
+                            .on_press(Message::KeyBindCancel)
+                            .into(),
+                    ])
+                    .align_y(Alignment::Center)
+                    .spacing(spacing.space_xxs)
+                    .into()
+                }
+                _ => {
+                    let mut shortcuts: Vec<String> = self
+                        .key_binds
+                        .iter()
+                        .filter(|(_, key_action)| *key_action == action)
+                        .map(|(key_bind, _)| key_bind.to_string())
+                        .collect();
+                    shortcuts.sort();
+                    let mut row = widget::row::with_capacity(2)
+                        .align_y(Alignment::Center)
+                        .spacing(spacing.space_xxs);
+                    if shortcuts.is_empty() {
+                        row = row.push(
+                            widget::button::text(fl!("set-shortcut"))
+                                .on_press(Message::KeyBindRecord(*action)),
+                        );
+                    } else {
+                        row = row
+                            .push(
+                                widget::button::text(shortcuts.join(", "))
+                                    .on_press(Message::KeyBindRecord(*action)),
+                            )
+                            .push(
+                                widget::button::icon(icon::from_name("edit-clear-symbolic"))
+                                    .on_press(Message::KeyBindRemove(*action)),
+                            );
+                    }
+                    row.into()
+                }
+            };
+            section = section.add(item.control(control));
+        }
+
+        widget::column::with_children(vec![
+            widget::text_input::search_input(
+                fl!("keyboard-shortcuts-search"),
+                self.key_bind_search_value.clone(),
+            )
+            .on_input(Message::KeyBindSearchValue)
+            .into(),
+            section.into(),
+            widget::row::with_children(vec![
+                widget::button::standard(fl!("import-shortcuts"))
+                    .on_press(Message::KeyBindsImport)
+                    .into(),
+                widget::button::standard(fl!("export-shortcuts"))
+                    .on_press(Message::KeyBindsExport)
+                    .into(),
+                widget::horizontal_space().into(),
+                widget::button::standard(fl!("reset-to-defaults"))
+                    .on_press(Message::KeyBindsReset)
+                    .into(),
+            ])
+            .spacing(spacing.space_xxs)
+            .into(),
+        ])
+        .spacing(spacing.space_s)
+        .padding([spacing.space_xxs, spacing.space_none])
+        .into()
+    }
+
+    fn settings_appearance(&self) -> Element<'_, Message> {
+        let app_theme_selected = match self.config.app_theme {
+            AppTheme::Dark => 1,
+            AppTheme::Light => 2,
+            AppTheme::System => 0,
+        };
+        let dark_selected = self
+            .theme_names
+            .iter()
+            .position(|theme_name| theme_name == &self.config.syntax_theme_dark);
+        let light_selected = self
+            .theme_names
+            .iter()
+            .position(|theme_name| theme_name == &self.config.syntax_theme_light);
+        let font_selected = {
+            let mut font_system = font_system().write().unwrap();
+            let current_font_name = font_system.raw().db().family_name(&Family::Monospace);
+            self.font_names
+                .iter()
+                .position(|font_name| font_name == current_font_name)
+        };
+        let font_size_selected = self
+            .font_sizes
+            .iter()
+            .position(|font_size| font_size == &self.config.font_size);
+        let zoom_step_selected = self
+            .zoom_steps
+            .iter()
+            .position(|zoom_step| zoom_step == &self.config.font_size_zoom_step_mul_100);
+        widget::settings::section()
+            .title(fl!("appearance"))
+            .add(
+                widget::settings::item::builder(fl!("theme")).control(widget::dropdown(
+                    &self.app_themes,
+                    Some(app_theme_selected),
+                    move |index| {
+                        Message::AppTheme(match index {
+                            1 => AppTheme::Dark,
+                            2 => AppTheme::Light,
+                            _ => AppTheme::System,
+                        })
+                    },
+                )),
+            )
+            .add(
+                widget::settings::item::builder(fl!("syntax-dark")).control(widget::dropdown(
+                    &self.theme_names,
+                    dark_selected,
+                    move |index| Message::SyntaxTheme(index, true),
+                )),
+            )
+            .add(
+                widget::settings::item::builder(fl!("syntax-light")).control(widget::dropdown(
+                    &self.theme_names,
+                    light_selected,
+                    move |index| Message::SyntaxTheme(index, false),
+                )),
+            )
+            .add(
+                widget::settings::item::builder(fl!("syntax-themes"))
+                    .description(fl!(
+                        "syntax-themes-description",
+                        dir = syntax::themes_dir()
+                            .map(|dir| dir.display().to_string())
+                            .unwrap_or_default()
+                    ))
+                    .control(
+                        widget::button::standard(fl!("import-syntax-theme"))
+                            .on_press(Message::ImportSyntaxTheme),
+                    ),
+            )
+            .add(
+                widget::settings::item::builder(fl!("default-font")).control(widget::dropdown(
+                    &self.font_names,
+                    font_selected,
+                    Message::DefaultFont,
+                )),
+            )
+            .add(
+                widget::settings::item::builder(fl!("font-ligatures"))
+                    .toggler(self.config.font_ligatures, Message::FontLigatures),
+            )
+            .add(
+                widget::settings::item::builder(fl!("default-font-size")).control(
+                    widget::dropdown(&self.font_size_names, font_size_selected, |index| {
+                        Message::DefaultFontSize(index)
+                    }),
+                ),
+            )
+            .add(
+                widget::settings::item::builder(fl!("default-zoom-step")).control(
+                    widget::dropdown(&self.zoom_step_names, zoom_step_selected, |index| {
+                        Message::DefaultZoomStep(index)
+                    }),
+                ),
+            )
+            .add(
+                widget::settings::item::builder(fl!("line-height")).control(
+                    widget::row::with_children(vec![
+                        widget::text::body(format!(
+                            "{:.2}",
+                            f32::from(self.config.line_height_mul_100) / 100.0
+                        ))
+                        .into(),
+                        widget::slider(
+                            LINE_HEIGHT_MUL_100_MIN..=LINE_HEIGHT_MUL_100_MAX,
+                            self.config.line_height_mul_100,
+                            Message::LineHeight,
+                        )
+                        .step(5u16)
+                        .width(Length::Fixed(160.0))
+                        .into(),
+                    ])
+                    .spacing(self.core().system_theme().cosmic().spacing.space_xs)
+                    .align_y(Alignment::Center),
+                ),
+            )
+            .into()
+    }
+
+    fn settings_editor(&self) -> Element<'_, Message> {
+        let tab_width_selected = usize::from(self.config.tab_width)
+            .checked_sub(1)
+            .filter(|index| *index < self.tab_width_names.len());
+        let word_wrap_column_selected = self
+            .word_wrap_columns
+            .iter()
+            .position(|word_wrap_column| word_wrap_column == &self.config.word_wrap_column);
+        widget::settings::section()
+            .title(fl!("editor"))
+            .add(
+                widget::settings::item::builder(fl!("indentation")).control(widget::dropdown(
+                    &self.tab_width_names,
+                    tab_width_selected,
+                    |index| Message::TabWidth(index as u16 + 1),
+                )),
+            )
+            .add(
+                widget::settings::item::builder(fl!("automatic-indentation"))
+                    .toggler(self.config.auto_indent, |_| Message::ToggleAutoIndent),
+            )
+            .add(
+                widget::settings::item::builder(fl!("auto-close-brackets"))
+                    .toggler(self.config.auto_close_brackets, |_| {
+                        Message::ToggleAutoClose
+                    }),
+            )
+            .add(
+                widget::settings::item::builder(fl!("word-wrap"))
+                    .toggler(self.config.word_wrap, |_| Message::ToggleWordWrap),
+            )
+            .add(
+                widget::settings::item::builder(fl!("word-wrap-width")).control(widget::dropdown(
+                    &self.word_wrap_column_names,
+                    word_wrap_column_selected,
+                    Message::WordWrapColumn,
+                )),
+            )
+            .add(
+                widget::settings::item::builder(fl!("show-line-numbers"))
+                    .toggler(self.config.line_numbers, |_| Message::ToggleLineNumbers),
+            )
+            .add(
+                widget::settings::item::builder(fl!("highlight-current-line"))
+                    .toggler(self.config.highlight_current_line, |_| {
+                        Message::ToggleHighlightCurrentLine
+                    }),
+            )
+            .add(
+                widget::settings::item::builder(fl!("show-indent-guides"))
+                    .toggler(self.config.indent_guides, |_| Message::ToggleIndentGuides),
+            )
+            .add(
+                widget::settings::item::builder(fl!("show-whitespace"))
+                    .toggler(self.config.show_whitespace, |_| Message::ToggleWhitespace),
+            )
+            .add(
+                widget::settings::item::builder(fl!("show-minimap"))
+                    .toggler(self.config.minimap, |_| Message::ToggleMinimap),
+            )
+            .into()
+    }
+
+    fn settings_keyboard_shortcuts(&self) -> Element<'_, Message> {
+        let keymap_selected = Keymap::all()
+            .iter()
+            .position(|keymap| keymap == &self.config.keymap);
+        widget::column::with_children(vec![
+            widget::settings::section()
+                .title(fl!("keyboard-shortcuts"))
+                .add(
+                    widget::settings::item::builder(fl!("enable-vim-bindings"))
+                        .toggler(self.config.vim_bindings, Message::VimBindings),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("keymap")).control(widget::dropdown(
+                        &self.keymap_names,
+                        keymap_selected,
+                        |index| Message::Keymap(Keymap::all()[index]),
+                    )),
+                )
+                .into(),
+            self.keyboard_shortcuts(),
+        ])
+        .spacing(self.core().system_theme().cosmic().spacing.space_m)
+        .into()
+    }
+
+    fn settings_advanced(&self) -> Element<'_, Message> {
+        let auto_save_selected = self
+            .auto_save_intervals
+            .iter()
+            .position(|auto_save_secs| auto_save_secs == &self.config.auto_save_secs);
+        let mut file_types = widget::settings::section().title(fl!("file-types"));
+        let mut associations: Vec<_> = self.config.syntax_associations.iter().collect();
+        associations.sort();
+        for (pattern, syntax_name) in associations {
+            file_types = file_types.add(
+                widget::settings::item::builder(pattern.clone())
+                    .description(syntax_name.clone())
+                    .control(
+                        widget::button::icon(icon::from_name("edit-delete-symbolic"))
+                            .on_press(Message::SyntaxAssociationRemove(pattern.clone())),
+                    ),
+            );
+        }
+        let can_add = !self.syntax_association_pattern.trim().is_empty()
+            && self.syntax_association_opt.is_some();
+        file_types = file_types.add(
+            widget::row::with_children(vec![
+                widget::text_input::text_input(
+                    fl!("file-type-placeholder"),
+                    &self.syntax_association_pattern,
+                )
+                .on_input(Message::SyntaxAssociationPattern)
+                .on_submit(|_| Message::SyntaxAssociationAdd)
+                .into(),
+                widget::dropdown(
+                    &self.syntax_names,
+                    self.syntax_association_opt,
+                    Message::SyntaxAssociationSyntax,
+                )
+                .into(),
+                widget::button::standard(fl!("add-file-type"))
+                    .on_press_maybe(can_add.then_some(Message::SyntaxAssociationAdd))
+                    .into(),
+            ])
+            .spacing(self.core().system_theme().cosmic().spacing.space_xs)
+            .align_y(Alignment::Center),
+        );
+
+        widget::settings::view_column(vec![
+            widget::settings::section()
+                .title(fl!("files"))
+                .add(
+                    widget::settings::item::builder(fl!("restore-session"))
+                        .toggler(self.config.restore_session, Message::RestoreSession),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("save-find-history"))
+                        .toggler(self.config.save_find_history, Message::SaveFindHistory),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("save-clipboard-history")).toggler(
+                        self.config.save_clipboard_history,
+                        Message::SaveClipboardHistory,
+                    ),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("save-undo-history-panel"))
+                        .description(fl!("save-undo-history-panel-description"))
+                        .toggler(
+                            self.config.save_undo_history_panel,
+                            Message::SaveUndoHistoryPanel,
+                        ),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("atomic-save"))
+                        .description(fl!("atomic-save-description"))
+                        .toggler(self.config.atomic_save, Message::AtomicSave),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("auto-save")).control(widget::dropdown(
+                        &self.auto_save_names,
+                        auto_save_selected,
+                        Message::AutoSaveInterval,
+                    )),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("language-servers"))
+                        .description(fl!("language-servers-description"))
+                        .toggler(self.config.lsp, Message::LanguageServers),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("word-completion"))
+                        .description(fl!("word-completion-description"))
+                        .toggler(self.config.word_completion, Message::WordCompletion),
+                )
+                .add(
+                    widget::settings::item::builder(fl!("primary-selection"))
+                        .description(fl!("primary-selection-description"))
+                        .toggler(
+                            self.config.primary_selection,
+                            Message::PrimarySelectionEnabled,
+                        ),
+                )
+                .into(),
+            file_types.into(),
+        ])
+        .into()
+    }
+
+    fn settings_window(&self) -> Element<'_, Message> {
+        let spacing = self.core().system_theme().cosmic().spacing;
+        let page = match self.settings_page_model.active_data::<SettingsPage>() {
+            Some(SettingsPage::Appearance) | None => self.settings_appearance(),
+            Some(SettingsPage::Editor) => self.settings_editor(),
+            Some(SettingsPage::KeyboardShortcuts) => self.settings_keyboard_shortcuts(),
+            Some(SettingsPage::Advanced) => self.settings_advanced(),
+        };
+        let content = widget::row::with_children(vec![
+            widget::segmented_control::vertical(&self.settings_page_model)
+                .on_activate(Message::SettingsPage)
+                .width(Length::Fixed(200.0))
+                .into(),
+            widget::scrollable(
+                widget::container(page).padding([spacing.space_none, spacing.space_s]),
+            )
+            .width(Length::Fill)
+            .into(),
+        ])
+        .spacing(spacing.space_s)
+        .padding(spacing.space_s);
+        // Other windows do not get the background of the main window
+        widget::container(content)
+            .width(Length::Fill)
+            .height(Length::Fill)
+            .class(theme::Container::Background)
+            .into()
+    }
+
+    fn dialog_view(&self) -> Option<Element<'_, Message>> {
+        let Some(ref dialog) = self.dialog_page_opt else {
+            return None;
+        };
+
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
+
+        match dialog {
+            DialogPage::QuickOpen => {
+                let mut column = widget::column::with_capacity(self.quick_open_results.len());
+                for (i, path) in self.quick_open_results.iter().enumerate() {
+                    column = column.push(
+                        widget::button::custom(
+                            widget::row::with_children(vec![
+                                icon::icon(mime_icon(mime_for_path(path, None, false), 16))
+                                    .size(16)
+                                    .into(),
+                                widget::text(self.quick_open_label(path)).into(),
+                            ])
+                            .align_y(Alignment::Center)
+                            .spacing(space_xxs),
+                        )
+                        .on_press(Message::QuickOpenSelect(i))
+                        .selected(i == self.quick_open_selected)
+                        .class(theme::Button::MenuItem)
+                        .width(Length::Fill),
+                    );
+                }
+
+                let dialog = widget::dialog()
+                    .control(
+                        widget::column::with_children(vec![
+                            widget::text_input::search_input(
+                                fl!("quick-open-placeholder"),
+                                self.quick_open_value.as_str(),
+                            )
+                            .id(self.quick_open_id.clone())
+                            .on_input(Message::QuickOpenValue)
+                            .on_submit(|_| Message::QuickOpenSubmit)
+                            .into(),
+                            widget::scrollable(column)
+                                .height(Length::Fixed(320.0))
+                                .into(),
+                        ])
+                        .spacing(space_xxs),
+                    )
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::CommandPalette => {
+                let mut column = widget::column::with_capacity(self.command_palette_results.len());
+                for (i, command_i) in self.command_palette_results.iter().enumerate() {
+                    let (action, label) = &self.command_palette[*command_i];
+                    let mut row = widget::row::with_capacity(3)
+                        .push(widget::text(label))
+                        .push(widget::horizontal_space());
+                    // Shortcuts are shown so that they can be learned
+                    if let Some((key_bind, _)) = self
+                        .key_binds
+                        .iter()
+                        .filter(|(_, key_action)| *key_action == action)
+                        .min_by_key(|(key_bind, _)| key_bind.to_string())
+                    {
+                        row = row.push(widget::text::caption(key_bind.to_string()));
+                    }
+                    column = column.push(
+                        widget::button::custom(row.align_y(Alignment::Center).spacing(space_xxs))
+                            .on_press(Message::CommandPaletteSelect(i))
+                            .selected(i == self.command_palette_selected)
+                            .class(theme::Button::MenuItem)
+                            .width(Length::Fill),
+                    );
+                }
+
+                let dialog = widget::dialog()
+                    .control(
+                        widget::column::with_children(vec![
+                            widget::text_input::search_input(
+                                fl!("command-palette-placeholder"),
+                                self.command_palette_value.as_str(),
+                            )
+                            .id(self.dialog_text_input_id.clone())
+                            .on_input(Message::CommandPaletteValue)
+                            .on_submit(|_| Message::CommandPaletteSubmit)
+                            .into(),
+                            widget::scrollable(column)
+                                .height(Length::Fixed(320.0))
+                                .into(),
+                        ])
+                        .spacing(space_xxs),
+                    )
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::SymbolSearch => {
+                let mut column = widget::column::with_capacity(self.symbol_search_results.len());
+                for (i, tag_i) in self.symbol_search_results.iter().enumerate() {
+                    let tag = &self.tags[*tag_i];
+                    let mut row = widget::row::with_capacity(4)
+                        .push(widget::text(&tag.name).font(Font::MONOSPACE));
+                    if let Some(kind) = &tag.kind_opt {
+                        row = row.push(widget::text::caption(kind));
+                    }
+                    row = row
+                        .push(widget::horizontal_space())
+                        .push(widget::text::caption(self.quick_open_label(&tag.path)));
+                    column = column.push(
+                        widget::button::custom(row.align_y(Alignment::Center).spacing(space_xxs))
+                            .on_press(Message::SymbolSearchSelect(i))
+                            .selected(i == self.symbol_search_selected)
+                            .class(theme::Button::MenuItem)
+                            .width(Length::Fill),
+                    );
+                }
+                if self.tags.is_empty() {
+                    column = column.push(widget::text::caption(fl!("no-tags-file")));
+                }
+
+                let dialog = widget::dialog()
+                    .control(
+                        widget::column::with_children(vec![
+                            widget::text_input::search_input(
+                                fl!("symbol-search-placeholder"),
+                                self.symbol_search_value.as_str(),
+                            )
+                            .id(self.dialog_text_input_id.clone())
+                            .on_input(Message::SymbolSearchValue)
+                            .on_submit(|_| Message::SymbolSearchSubmit)
+                            .into(),
+                            widget::scrollable(column)
+                                .height(Length::Fixed(320.0))
+                                .into(),
+                        ])
+                        .spacing(space_xxs),
+                    )
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::NewFile(_, name)
+            | DialogPage::NewFolder(_, name)
+            | DialogPage::Rename(_, name) => {
+                let (title, action) = match dialog {
+                    DialogPage::NewFile(..) => (fl!("new-file"), fl!("create")),
+                    DialogPage::NewFolder(..) => (fl!("new-folder"), fl!("create")),
+                    _ => (fl!("rename"), fl!("rename")),
+                };
+                let target_opt = dialog.name_target();
+                let exists = target_opt.as_ref().is_some_and(|target| target.exists());
+                // Renaming to the same name is not an error, but does nothing
+                let unchanged = matches!(dialog, DialogPage::Rename(path, _) if Some(path) == target_opt.as_ref());
+
+                let mut complete_button = widget::button::suggested(action);
+                if target_opt.is_some() && !exists {
+                    complete_button = complete_button.on_press(Message::DialogComplete);
+                }
+                let cancel_button =
+                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
+
+                let mut column = widget::column::with_capacity(2).spacing(space_xxs).push(
+                    widget::text_input::text_input(fl!("name"), name.as_str())
+                        .id(self.dialog_text_input_id.clone())
+                        .on_input(|name| Message::DialogUpdate(dialog.with_name(name)))
+                        .on_submit(|_| Message::DialogComplete),
+                );
+                if exists && !unchanged {
+                    column = column.push(widget::text::caption(fl!("name-exists")));
+                }
+
+                let dialog = widget::dialog()
+                    .title(title)
+                    .control(column)
+                    .primary_action(complete_button)
+                    .secondary_action(cancel_button);
+                Some(dialog.into())
+            }
+            DialogPage::OpenLocation(uri) => {
+                let mut complete_button = widget::button::suggested(fl!("open"));
+                if remote::is_uri(uri.trim()) {
+                    complete_button = complete_button.on_press(Message::DialogComplete);
+                }
+                let dialog = widget::dialog()
+                    .title(fl!("open-location"))
+                    .control(
+                        widget::text_input::text_input(fl!("location-placeholder"), uri.as_str())
+                            .id(self.dialog_text_input_id.clone())
+                            .on_input(|uri| Message::DialogUpdate(dialog.with_name(uri)))
+                            .on_submit(|_| Message::DialogComplete),
+                    )
+                    .primary_action(complete_button)
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::KeyboardShortcuts(search) => {
+                let query = search.to_lowercase();
+                let mut column =
+                    widget::column::with_capacity(command_palette::Category::all().len() * 2);
+                for category in command_palette::Category::all() {
+                    let mut rows = Vec::new();
+                    for (action, label) in self.command_palette.iter() {
+                        if command_palette::category(*action) != *category {
+                            continue;
+                        }
+                        let mut shortcuts: Vec<String> = self
+                            .key_binds
+                            .iter()
+                            .filter(|(_, key_action)| *key_action == action)
+                            .map(|(key_bind, _)| key_bind.to_string())
+                            .collect();
+                        shortcuts.sort();
+                        // Commands can be found by their shortcut too
+                        if fuzzy::score(search, label).is_none()
+                            && !shortcuts
+                                .iter()
+                                .any(|shortcut| shortcut.to_lowercase().contains(&query))
+                        {
+                            continue;
+                        }
+                        rows.push(
+                            widget::row::with_children(vec![
+                                widget::text(label).into(),
+                                widget::horizontal_space().into(),
+                                widget::text::caption(shortcuts.join(", ")).into(),
+                            ])
+                            .align_y(Alignment::Center)
+                            .spacing(space_xxs)
+                            .into(),
+                        );
+                    }
+                    if !rows.is_empty() {
+                        column = column
+                            .push(widget::text::heading(category.title()))
+                            .push(widget::column::with_children(rows).spacing(space_xxs));
+                    }
+                }
+                let dialog = widget::dialog()
+                    .title(fl!("keyboard-shortcuts"))
+                    .control(
+                        widget::column::with_children(vec![
+                            widget::text_input::search_input(
+                                fl!("keyboard-shortcuts-search"),
+                                search.as_str(),
+                            )
+                            .id(self.dialog_text_input_id.clone())
+                            .on_input(|search| Message::DialogUpdate(dialog.with_name(search)))
+                            .into(),
+                            widget::scrollable(column.spacing(space_xxs))
+                                .height(Length::Fixed(320.0))
+                                .into(),
+                        ])
+                        .spacing(space_xxs),
+                    )
+                    .secondary_action(
+                        widget::button::text(fl!("close")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::PasteHistory => {
+                let mut column = widget::column::with_capacity(self.clipboard_history.len());
+                for (i, value) in self.clipboard_history.iter().enumerate() {
+                    // Only the start of long texts is shown
+                    let first_line = value.lines().find(|line| !line.trim().is_empty());
+                    let mut label = first_line.unwrap_or_default().trim().to_string();
+                    if label.chars().count() > 80 {
+                        label = label.chars().take(80).collect();
+                        label.push('\u{2026}');
+                    }
+                    let line_count = value.lines().count();
+                    let mut row = widget::row::with_capacity(3)
+                        .push(widget::text(label).font(Font::MONOSPACE))
+                        .push(widget::horizontal_space());
+                    if line_count > 1 {
+                        row = row.push(widget::text::caption(fl!(
+                            "clipboard-lines",
+                            count = line_count
+                        )));
+                    }
+                    column = column.push(
+                        widget::button::custom(row.align_y(Alignment::Center).spacing(space_xxs))
+                            .on_press(Message::PasteHistory(i))
+                            .class(theme::Button::MenuItem)
+                            .width(Length::Fill),
+                    );
+                }
+                let dialog = widget::dialog()
+                    .title(fl!("paste-from-history-title"))
+                    .control(widget::scrollable(column).height(Length::Fixed(320.0)))
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::PlayMacro(times) => {
+                let mut complete_button = widget::button::suggested(fl!("play-macro"));
+                if times.trim().parse::<usize>().is_ok_and(|times| times > 0) {
+                    complete_button = complete_button.on_press(Message::DialogComplete);
+                }
+                let dialog = widget::dialog()
+                    .title(fl!("play-macro-repeatedly"))
+                    .control(
+                        widget::text_input::text_input(fl!("macro-times"), times.as_str())
+                            .id(self.dialog_text_input_id.clone())
+                            .on_input(|times| Message::DialogUpdate(dialog.with_name(times)))
+                            .on_submit(|_| Message::DialogComplete),
+                    )
+                    .primary_action(complete_button)
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::Filter(_, command) => {
+                let mut complete_button = widget::button::suggested(fl!("filter"));
+                if !command.trim().is_empty() {
+                    complete_button = complete_button.on_press(Message::DialogComplete);
+                }
+                let dialog = widget::dialog()
+                    .title(fl!("filter-title"))
+                    .body(fl!("filter-body"))
+                    .control(
+                        widget::text_input::text_input(fl!("filter-placeholder"), command.as_str())
+                            .id(self.dialog_text_input_id.clone())
+                            .on_input(|command| Message::DialogUpdate(dialog.with_name(command)))
+                            .on_submit(|_| Message::DialogComplete),
+                    )
+                    .primary_action(complete_button)
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::RenameSymbol(_, _, name) => {
+                let mut complete_button = widget::button::suggested(fl!("rename"));
+                if !name.trim().is_empty() {
+                    complete_button = complete_button.on_press(Message::DialogComplete);
+                }
+                let dialog = widget::dialog()
+                    .title(fl!("rename-symbol"))
+                    .control(
+                        widget::text_input::text_input(fl!("new-name"), name.as_str())
+                            .id(self.dialog_text_input_id.clone())
+                            .on_input(|name| Message::DialogUpdate(dialog.with_name(name)))
+                            .on_submit(|_| Message::DialogComplete),
+                    )
+                    .primary_action(complete_button)
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::Export(entity, format) => {
+                let unsupported_opt = match format {
+                    export::Format::Html => None,
+                    export::Format::Pdf => self.print_unsupported(*entity),
+                };
+                let export_button = widget::button::suggested(fl!("export-button")).on_press_maybe(
+                    unsupported_opt
+                        .is_none()
+                        .then_some(Message::ExportSubmit(*entity, *format)),
+                );
+                let cancel_button =
+                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
+                let title = match format {
+                    export::Format::Html => fl!("export-html-title"),
+                    export::Format::Pdf => fl!("export-pdf-title"),
+                };
+                let mut dialog = widget::dialog()
+                    .title(title)
+                    .control(self.print_options_section(*format == export::Format::Pdf))
+                    .primary_action(export_button)
+                    .secondary_action(cancel_button);
+                if let Some(c) = unsupported_opt {
+                    dialog = dialog.body(fl!("print-unsupported", character = c.to_string()));
+                }
+                Some(dialog.into())
+            }
+            DialogPage::PrintSetup(entity) => {
+                let unsupported_opt = self.print_unsupported(*entity);
+                let section = self.print_options_section(true);
+                let print_button = widget::button::suggested(fl!("print")).on_press_maybe(
+                    unsupported_opt
+                        .is_none()
+                        .then_some(Message::PrintSubmit(*entity)),
+                );
+                let cancel_button =
+                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
+                let mut dialog = widget::dialog()
+                    .title(fl!("page-setup"))
+                    .control(section)
+                    .primary_action(print_button)
+                    .secondary_action(cancel_button);
+                if let Some(c) = unsupported_opt {
+                    dialog = dialog.body(fl!("print-unsupported", character = c.to_string()));
+                }
+                Some(dialog.into())
+            }
+            DialogPage::PromptSaveClose(entity) => {
+                let save_button =
+                    widget::button::suggested(fl!("save")).on_press(Message::Save(Some(*entity)));
+                let discard_button = widget::button::destructive(fl!("discard"))
+                    .on_press(Message::TabCloseForce(*entity));
+                let cancel_button =
+                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
+                let dialog = widget::dialog()
+                    .title(fl!("prompt-save-changes-title"))
+                    .body(fl!("prompt-unsaved-changes"))
+                    .icon(icon::from_name("dialog-warning-symbolic").size(64))
+                    .primary_action(save_button)
+                    .secondary_action(discard_button)
+                    .tertiary_action(cancel_button);
+                Some(dialog.into())
+            }
+            DialogPage::PromptSaveElevated(entity) => {
+                let path_opt = match self.tab_model.data::<Tab>(*entity) {
+                    Some(Tab::Editor(tab)) => tab.path_opt.as_ref(),
+                    _ => None,
+                };
+                let save_button = widget::button::suggested(fl!("save-elevated"))
+                    .on_press(Message::SaveElevated(*entity));
+                let cancel_button =
+                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
+                let dialog = widget::dialog()
+                    .title(fl!("save-elevated-title"))
+                    .body(fl!(
+                        "save-elevated-body",
+                        path = path_opt.map_or(String::new(), |path| path.display().to_string())
+                    ))
+                    .icon(icon::from_name("dialog-password-symbolic").size(64))
+                    .primary_action(save_button)
+                    .secondary_action(cancel_button);
+                Some(dialog.into())
+            }
+            DialogPage::PromptSaveEncoding(entity, encoding) => {
+                let utf8_button = widget::button::suggested(fl!("save-as-utf8"))
+                    .on_press(Message::SaveWithEncodingForce(*entity, UTF_8));
+                let replace_button = widget::button::destructive(fl!("save-anyway"))
+                    .on_press(Message::SaveWithEncodingForce(*entity, encoding));
+                let cancel_button =
+                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
+                let dialog = widget::dialog()
+                    .title(fl!("save-encoding-title"))
+                    .body(fl!("save-encoding-body", encoding = encoding.name()))
+                    .icon(icon::from_name("dialog-warning-symbolic").size(64))
+                    .primary_action(utf8_button)
+                    .secondary_action(replace_button)
+                    .tertiary_action(cancel_button);
+                Some(dialog.into())
+            }
+            DialogPage::RunTask(dir, project_tasks) => {
+                let mut column = widget::column::with_capacity(project_tasks.len());
+                for task in project_tasks.iter() {
+                    let mut row = widget::row::with_capacity(3)
+                        .push(widget::text(&task.name))
+                        .push(widget::horizontal_space());
+                    if task.name != task.command {
+                        row = row.push(widget::text::caption(&task.command));
+                    }
+                    column = column.push(
+                        widget::button::custom(row.align_y(Alignment::Center).spacing(space_xxs))
+                            .on_press(Message::RunTask(dir.clone(), task.clone()))
+                            .class(theme::Button::MenuItem)
+                            .width(Length::Fill),
+                    );
+                }
+                let control: Element<_> = if project_tasks.is_empty() {
+                    widget::text(fl!("no-tasks", file = tasks::TASKS_FILE)).into()
+                } else {
+                    widget::scrollable(column).height(Length::Shrink).into()
+                };
+                let dialog = widget::dialog()
+                    .title(fl!("run-task-title"))
+                    .body(dir.display().to_string())
+                    .control(control)
+                    .secondary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+                Some(dialog.into())
+            }
+            DialogPage::Recover => {
+                let mut column =
+                    widget::column::with_capacity(self.recovery_orphans.len()).spacing(space_xxs);
+                for (_dir, snapshots) in self.recovery_orphans.iter() {
+                    for snapshot in snapshots.iter() {
+                        column = column.push(widget::text(match &snapshot.path_opt {
+                            Some(path) => path.display().to_string(),
+                            None => fl!("new-document"),
+                        }));
+                    }
+                }
+
+                let dialog = widget::dialog()
+                    .title(fl!("recover-title"))
+                    .body(fl!("recover-body"))
+                    .icon(icon::from_name("dialog-warning-symbolic").size(64))
+                    .control(column)
+                    .primary_action(
+                        widget::button::suggested(fl!("recover"))
+                            .on_press(Message::RecoveryRestore),
+                    )
+                    .secondary_action(
+                        widget::button::destructive(fl!("discard"))
+                            .on_press(Message::RecoveryDiscard),
+                    )
+                    .tertiary_action(
+                        widget::button::text(fl!("cancel")).on_press(Message::DialogCancel),
+                    );
+
+                Some(dialog.into())
+            }
+            DialogPage::PromptSaveQuit(entities) => {
+                let mut can_save_all = true;
+                let mut column = widget::column::with_capacity(entities.len()).spacing(space_xxs);
+                for entity in entities.iter() {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(*entity) {
+                        let mut row = widget::row::with_capacity(4)
+                            .align_y(Alignment::Center)
+                            .spacing(space_xxs);
+                        row = row.push(widget::text(tab.title()));
+                        row = row.push(widget::horizontal_space());
+                        row = row.push(
+                            widget::button::text(fl!("discard-one"))
+                                .on_press(Message::TabCloseForce(*entity)),
+                        );
+                        if let Some(_path) = &tab.path_opt {
+                            row = row.push(
+                                widget::button::standard(fl!("save"))
+                                    .on_press(Message::Save(Some(*entity))),
+                            );
+                            //TODO row = row.push(widget::text(format!("{}", path.display())));
+                        } else {
+                            row = row.push(
+                                widget::button::standard(fl!("save-as"))
+                                    .on_press(Message::SaveAsDialog(Some(*entity))),
+                            );
+                            can_save_all = false;
+                        }
+
+                        column = column.push(row);
+                    }
+                }
+
+                let mut save_button = widget::button::suggested(fl!("save-all"));
+                if can_save_all {
+                    save_button = save_button.on_press(Message::SaveAll);
+                }
+                let discard_button =
+                    widget::button::destructive(fl!("discard")).on_press(Message::QuitForce);
+                let cancel_button =
+                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
+                let dialog = widget::dialog()
+                    .title(fl!("prompt-save-changes-title"))
+                    .body(fl!("prompt-unsaved-changes"))
+                    .icon(icon::from_name("dialog-warning-symbolic").size(64))
+                    .control(column)
+                    .primary_action(save_button)
+                    .secondary_action(discard_button)
+                    .tertiary_action(cancel_button);
+
+                Some(dialog.into())
+            }
+        }
+    }
+
+    /// Contents of the editor window that has focus
+    fn editor_view(&self) -> Element<'_, Message> {
+        let cosmic_theme::Spacing {
+            space_none,
+            space_xxs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let mut tab_column = widget::column::with_capacity(4).padding([space_none, space_xxs]);
+
+        let window = self
+            .editor_window(self.focused_window_opt)
+            .unwrap_or(&self.main_window);
+        let mut tab_row = widget::row::with_capacity(3)
+            .align_y(Alignment::Center)
+            .push(self.tab_bar(window))
+            .push(
+                button::custom(icon_cache_get("list-add-symbolic", 16))
+                    .on_press(Message::NewFile)
+                    .padding(space_xxs)
+                    .class(style::Button::Icon),
+            );
+        if self.auto_save_pending.contains(&self.tab_model.active()) && !self.zen_mode {
+            tab_row = tab_row.push(widget::tooltip(
+                widget::container(icon_cache_get("document-save-symbolic", 16)).padding(space_xxs),
+                widget::text::body(fl!("auto-save-pending")),
+                widget::tooltip::Position::Bottom,
+            ));
+        }
+        if !self.zen_mode {
+            tab_column = tab_column.push(tab_row);
+        }
+
+        let active_pane = self.tab_view(self.tab_model.active(), true);
+        tab_column = tab_column.push(match self.split_opt {
+            Some(split) => {
+                let other_pane = self.tab_view(split.entity, false);
+                let (first, second) = if split.second_focused {
+                    (other_pane, active_pane)
+                } else {
+                    (active_pane, other_pane)
+                };
+                match split.axis {
+                    SplitAxis::Horizontal => widget::column::with_children(vec![
+                        first,
+                        widget::divider::horizontal::default().into(),
+                        second,
+                    ])
+                    .into(),
+                    SplitAxis::Vertical => widget::row::with_children(vec![
+                        first,
+                        widget::divider::vertical::default().into(),
+                        second,
+                    ])
+                    .into(),
+                }
+            }
+            None if self.markdown_preview_shown() => {
+                let preview = match self.active_tab() {
+                    Some(Tab::Editor(tab)) => markdown::view(
+                        &tab.markdown_blocks,
+                        tab.path_opt.as_deref().and_then(Path::parent),
+                    ),
+                    _ => widget::horizontal_space().into(),
+                };
+                widget::row::with_children(vec![
+                    active_pane,
+                    widget::divider::vertical::default().into(),
+                    widget::scrollable(preview)
+                        .id(self.markdown_preview_id.clone())
+                        .width(Length::Fill)
+                        .height(Length::Fill)
+                        .into(),
+                ])
+                .into()
+            }
+            None => active_pane,
+        });
+        if self.task_output_shown {
+            tab_column = tab_column
+                .push(widget::divider::horizontal::default())
+                .push(self.task_panel());
+        }
+
+        if let Some(FindField {
+            replace,
+            has_focus: _,
+        }) = &self.find_opt
+        {
+            let find_input =
+                widget::text_input::text_input(fl!("find-placeholder"), &self.find_search_value)
+                    .id(self.find_search_id.clone())
+                    .on_input(Message::FindSearchValueChanged)
+                    .on_submit(|_| {
+                        if self.modifiers.contains(Modifiers::SHIFT) {
+                            Message::FindPrevious
+                        } else {
+                            Message::FindNext
+                        }
+                    })
+                    .on_focus(Message::FindFocused(true))
+                    .width(Length::Fixed(320.0))
+                    .trailing_icon(
+                        button::custom(icon_cache_get("edit-clear-symbolic", 16))
+                            .on_press(Message::FindSearchValueChanged(String::new()))
+                            .class(style::Button::Icon)
+                            .into(),
+                    );
+            let find_count = if self.find_search_value.is_empty() {
+                String::new()
+            } else if self.find_matches.is_empty() {
+                fl!("find-no-matches")
+            } else {
+                match self.find_match_index() {
+                    Some(index) => fl!(
+                        "find-match-count",
+                        current = index + 1,
+                        total = self.find_matches.len()
+                    ),
+                    None => fl!("find-matches", total = self.find_matches.len()),
+                }
+            };
+            let find_widget = widget::row::with_children(vec![
+                find_input.into(),
+                widget::text::body(find_count).into(),
+                widget::tooltip(
+                    button::custom(icon_cache_get("go-up-symbolic", 16))
+                        .on_press(Message::FindPrevious)
+                        .padding(space_xxs)
+                        .class(style::Button::Icon),
+                    widget::text::body(fl!("find-previous")),
+                    widget::tooltip::Position::Top,
+                )
+                .into(),
+                widget::tooltip(
+                    button::custom(icon_cache_get("go-down-symbolic", 16))
+                        .on_press(Message::FindNext)
+                        .padding(space_xxs)
+                        .class(style::Button::Icon),
+                    widget::text::body(fl!("find-next")),
+                    widget::tooltip::Position::Top,
+                )
+                .into(),
+                widget::horizontal_space().into(),
+                button::custom(icon_cache_get("window-close-symbolic", 16))
+                    .on_press(Message::Find(None))
+                    .padding(space_xxs)
+                    .class(style::Button::Icon)
+                    .into(),
+            ])
+            .align_y(Alignment::Center)
+            .padding(space_xxs)
+            .spacing(space_xxs);
+
+            let mut column = widget::column::with_capacity(4).push(find_widget);
+            if *replace {
+                let replace_input = widget::text_input::text_input(
+                    fl!("replace-placeholder"),
+                    &self.find_replace_value,
+                )
+                .id(self.find_replace_id.clone())
+                .on_input(Message::FindReplaceValueChanged)
+                .on_focus(Message::FindReplaceFocused)
+                .on_submit(|_| Message::FindReplace)
+                .width(Length::Fixed(320.0))
+                .trailing_icon(
+                    button::custom(icon_cache_get("edit-clear-symbolic", 16))
+                        .on_press(Message::FindReplaceValueChanged(String::new()))
+                        .class(style::Button::Icon)
+                        .into(),
+                );
+                // Show what the selected match would be replaced with when using captures
+                let replace_preview = match (self.active_tab(), &self.find_regex_opt) {
+                    (Some(Tab::Editor(tab)), Some(regex)) if self.config.find_use_regex => tab
+                        .replace_preview(
+                            regex,
+                            &self.config.find_replace_template(&self.find_replace_value),
+                        )
+                        .map(|text| {
+                            fl!(
+                                "replace-preview",
+                                text = text.replace('\n', "\\n").replace('\t', "\\t")
+                            )
+                        }),
+                    _ => None,
+                };
+                let replace_widget = widget::row::with_children(vec![
+                    replace_input.into(),
+                    widget::tooltip(
+                        button::custom(icon_cache_get("replace-symbolic", 16))
+                            .on_press(Message::FindReplace)
+                            .padding(space_xxs)
+                            .class(style::Button::Icon),
+                        widget::text::body(fl!("replace")),
+                        widget::tooltip::Position::Top,
+                    )
+                    .into(),
+                    widget::tooltip(
+                        button::custom(icon_cache_get("replace-all-symbolic", 16))
+                            .on_press(Message::FindReplaceAll)
+                            .padding(space_xxs)
+                            .class(style::Button::Icon),
+                        widget::text::body(fl!("replace-all")),
+                        widget::tooltip::Position::Top,
+                    )
+                    .into(),
+                    widget::checkbox(fl!("all-open-documents"), self.find_all_tabs)
+                        .on_toggle(Message::FindAllTabs)
+                        .into(),
+                    widget::text::caption(replace_preview.unwrap_or_default()).into(),
+                ])
+                .align_y(Alignment::Center)
+                .padding(space_xxs)
+                .spacing(space_xxs);
+
+                column = column.push(replace_widget);
+
+                if !self.find_replace_counts.is_empty() {
+                    let counts: Vec<String> = self
+                        .find_replace_counts
+                        .iter()
+                        .map(|(title, count)| {
+                            fl!("replace-count", title = title.as_str(), count = *count)
+                        })
+                        .collect();
+                    column = column.push(
+                        widget::container(widget::text::caption(counts.join(", ")))
+                            .padding(space_xxs),
+                    );
+                }
+            }
+
+            column = column.push(
+                widget::row::with_children(vec![
+                    widget::checkbox(fl!("case-sensitive"), self.config.find_case_sensitive)
+                        .on_toggle(Message::FindCaseSensitive)
+                        .into(),
+                    widget::checkbox(fl!("use-regex"), self.config.find_use_regex)
+                        .on_toggle(Message::FindUseRegex)
+                        .into(),
+                    widget::checkbox(fl!("whole-words"), self.config.find_whole_words)
+                        .on_toggle(Message::FindWholeWords)
+                        .into(),
+                    widget::checkbox(fl!("wrap-around"), self.config.find_wrap_around)
+                        .on_toggle(Message::FindWrapAround)
+                        .into(),
+                    widget::checkbox(
+                        fl!("selection-only"),
+                        matches!(self.active_tab(), Some(Tab::Editor(tab)) if tab.has_find_scope()),
+                    )
+                    .on_toggle(Message::FindInSelection)
+                    .into(),
+                ])
+                .align_y(Alignment::Center)
+                .padding(space_xxs)
+                .spacing(space_xxs),
+            );
+
+            tab_column = tab_column
+                .push(widget::layer_container(column).layer(cosmic_theme::Layer::Primary));
+        }
+
+        if let Some(Tab::Editor(tab)) = self.active_tab()
+            && !self.zen_mode
+        {
+            tab_column = tab_column.push(self.status_bar(tab));
+        }
+
+        let content: Element<_> = tab_column.into();
+
+        // Uncomment to debug layout:
+        //content.explain(cosmic::iced::Color::WHITE)
+        content
+    }
+
+    /// Tab bar of an editor window, which shows the tabs of the tab model in its own order
+    fn tab_bar<'a>(&'a self, window: &'a EditorWindow) -> Element<'a, Message> {
+        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
+        let tabs: HashMap<_, _> = window
+            .tab_bar
+            .iter()
+            .filter_map(|item| Some((item, window.tab(item)?)))
+            .collect();
+        let tab = move |item: Entity| tabs.get(&item).copied().unwrap_or_default();
+        widget::tab_bar::horizontal(&window.tab_bar)
+            .button_height(32)
+            .button_spacing(space_xxs)
+            .close_icon(icon_cache_get("window-close-symbolic", 16))
+            //TODO: this causes issues with small window sizes .minimum_button_width(240)
+            .on_activate({
+                let tab = tab.clone();
+                move |item| Message::TabActivate(tab(item))
+            })
+            .on_close({
+                let tab = tab.clone();
+                move |item| Message::TabClose(tab(item))
+            })
+            .on_context({
+                let tab = tab.clone();
+                move |item| Message::TabBarContext(tab(item))
+            })
+            .enable_tab_drag(String::from(TAB_DRAG_MIME))
+            .on_reorder(move |event| {
+                Message::TabReorder(tab(event.dragged), tab(event.target), event.position)
+            })
+            .on_dnd_drop(|_entity, paste_opt: Option<ClipboardPaste>, _action| {
+                Message::DropFiles(paste_opt)
+            })
+            .context_menu(
+                self.tab_bar_context_opt
+                    .filter(|entity| window.item(*entity).is_some())
+                    .map(|entity| {
+                        menu::tab_context_menu(
+                            &self.key_binds,
+                            entity,
+                            self.pinned_tabs.contains(&entity),
+                        )
+                    }),
+            )
+            .width(Length::Shrink)
+            .into()
+    }
+
+    /// Tab bar and active tab of an editor window that does not have focus
+    fn window_view<'a>(&'a self, window: &'a EditorWindow) -> Element<'a, Message> {
+        let cosmic_theme::Spacing {
+            space_none,
+            space_xxs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let mut column = widget::column::with_capacity(2).padding([space_none, space_xxs]);
+        if !self.zen_mode {
+            column = column.push(self.tab_bar(window));
+        }
+        column = column.push(match window.active_tab() {
+            Some(entity) => self.tab_view(entity, false),
+            None => widget::horizontal_space().into(),
+        });
+        column.into()
+    }
+}
+
+/// Implement [`cosmic::Application`] to integrate with COSMIC.
+impl Application for App {
+    /// Default async executor to use with the app.
+    type Executor = executor::Default;
+
+    /// Argument received [`cosmic::Application::new`].
+    type Flags = Flags;
+
+    /// Message type specific to our [`App`].
+    type Message = Message;
+
+    /// The unique application ID to supply to the window manager.
+    const APP_ID: &'static str = "com.system76.CosmicEdit";
+
+    fn core(&self) -> &Core {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut Core {
+        &mut self.core
+    }
+
+    /// Creates the application, and optionally emits command on initialize.
+    fn init(mut core: Core, flags: Self::Flags) -> (Self, Task<Self::Message>) {
+        core.window.context_is_overlay = false;
+
+        // Update font name from config
+        {
+            let mut font_system = font_system().write().unwrap();
+            font_system
+                .raw()
+                .db_mut()
+                .set_monospace_family(&flags.config.font_name);
+        }
+
+        let app_themes = vec![fl!("match-desktop"), fl!("dark"), fl!("light")];
+        let keymap_names = Keymap::all().iter().map(Keymap::name).collect();
+        let paper_names = vec![fl!("paper-a4"), fl!("paper-letter")];
+
+        let font_names = {
+            let mut font_names = Vec::new();
+            let mut font_system = font_system().write().unwrap();
+            let attrs = monospace_attrs();
+            for face in font_system.raw().db().faces() {
+                if attrs.matches(face) && face.monospaced {
+                    //TODO: get localized name if possible
+                    let font_name = face
+                        .families
+                        .first()
+                        .map_or_else(|| face.post_script_name.to_string(), |x| x.0.to_string());
+                    if !font_names.contains(&font_name) {
+                        font_names.push(font_name);
+                    }
+                }
+            }
+            font_names.sort();
+            font_names
+        };
+
+        let mut font_size_names = Vec::new();
+        let mut font_sizes = Vec::new();
+        for font_size in 4..=32 {
+            font_size_names.push(format!("{}px", font_size));
+            font_sizes.push(font_size);
+        }
+
+        let theme_names = theme_names();
+
+        let syntax_names = syntax_names();
+
+        let mut zoom_step_names = Vec::new();
+        let mut zoom_steps = Vec::new();
+        for zoom_step in [25, 50, 75, 100, 150, 200] {
+            zoom_step_names.push(format!("{}px", f32::from(zoom_step) / 100.0));
+            zoom_steps.push(zoom_step);
+        }
+
+        let tab_width_names = (1..=8)
+            .map(|tab_width: u16| fl!("tab-width", tab_width = tab_width))
+            .collect();
+
+        let mut word_wrap_column_names = Vec::new();
+        let mut word_wrap_columns = Vec::new();
+        for word_wrap_column in [None, Some(72), Some(80), Some(100), Some(120)] {
+            word_wrap_column_names.push(match word_wrap_column {
+                Some(column) => fl!("word-wrap-at-column", column = column),
+                None => fl!("word-wrap-at-window"),
+            });
+            word_wrap_columns.push(word_wrap_column);
+        }
+
+        let mut auto_save_names = Vec::new();
+        let mut auto_save_intervals = Vec::new();
+        for auto_save_secs in [None, Some(10), Some(30), Some(60), Some(300)] {
+            auto_save_names.push(match auto_save_secs {
+                Some(secs) if secs >= 60 => fl!("auto-save-minutes", minutes = secs / 60),
+                Some(secs) => fl!("auto-save-seconds", seconds = secs),
+                None => fl!("auto-save-off"),
+            });
+            auto_save_intervals.push(auto_save_secs);
+        }
+
+        let mut settings_page_model = segmented_button::Model::builder().build();
+        for page in SettingsPage::all() {
+            let entity = settings_page_model
+                .insert()
+                .text(page.title())
+                .data(*page)
+                .id();
+            if *page == SettingsPage::Appearance {
+                settings_page_model.activate(entity);
+            }
+        }
+
+        // The commit is empty when building without git
+        let version = match env!("COSMIC_EDIT_GIT_COMMIT") {
+            "" => env!("CARGO_PKG_VERSION").to_string(),
+            commit => format!("{} ({})", env!("CARGO_PKG_VERSION"), commit),
+        };
+        let about = About::default()
+            .name(fl!("cosmic-text-editor"))
+            .icon(icon::from_name(Self::APP_ID))
+            .version(version)
+            .author("System76")
+            .comments(fl!("license-notice"))
+            .copyright("© 2023 System76")
+            .license("GPL-3.0-only")
+            .developers([("Jeremy Soller", "jeremy@system76.com")])
+            .links([
+                (fl!("repository"), "https://github.com/pop-os/cosmic-edit"),
+                (
+                    fl!("support"),
+                    "https://github.com/pop-os/cosmic-edit/issues",
+                ),
+            ]);
+
+        let key_binds = key_binds(
+            flags.config.keymap,
+            &flags.config.keybinds,
+            &flags.config.keybinds_removed,
+        );
+
+        let mut app = App {
+            core,
+            about,
+            nav_model: nav_bar::Model::builder().build(),
+            tab_model: segmented_button::Model::builder().build(),
+            main_window: EditorWindow::new(),
+            windows: HashMap::new(),
+            focused_window_opt: None,
+            config_handler: flags.config_handler,
+            config: flags.config,
+            config_state_handler: flags.config_state_handler,
+            config_state: flags.config_state,
+            key_binds,
+            zoom_step_names,
+            tab_width_names,
+            indentation_names: vec![fl!("indent-tabs"), fl!("indent-spaces")],
+            line_ending_names: vec!["LF".to_string(), "CRLF".to_string()],
+            zoom_steps,
+            word_wrap_column_names,
+            word_wrap_columns,
+            auto_save_names,
+            auto_save_intervals,
+            auto_save_pending: HashSet::new(),
+            pinned_tabs: HashSet::new(),
+            stdout_tab_opt: None,
+            stdout_failed: false,
+            #[cfg(unix)]
+            waiters: Vec::new(),
+            tab_bar_context_opt: None,
+            block_clipboard_opt: None,
+            kill_opt: None,
+            app_themes,
+            keymap_names,
+            paper_names,
+            font_names,
+            font_size_names,
+            font_sizes,
+            theme_names,
+            syntax_names,
+            context_page: ContextPage::About,
+            text_box_id: widget::Id::unique(),
+            split_opt: None,
+            split_text_box_id: widget::Id::unique(),
+            markdown_preview_id: widget::Id::unique(),
+            auto_scroll: None,
+            dialog_opt: None,
+            dialog_page_opt: None,
+            dialog_text_input_id: widget::Id::unique(),
+            print_options: PrintOptions::default(),
+            print_unsupported_opt: None,
+            document_type_search_id: widget::Id::unique(),
+            document_type_search_value: String::new(),
+            find_opt: None,
+            find_replace_id: widget::Id::unique(),
+            find_replace_value: String::new(),
+            find_search_id: widget::Id::unique(),
+            find_search_value: String::new(),
+            find_matches: Vec::new(),
+            find_regex_opt: None,
+            find_history: VecDeque::new(),
+            clipboard_history: VecDeque::new(),
+            replace_history: VecDeque::new(),
+            find_history_index: None,
+            find_replace_focused: false,
+            find_all_tabs: false,
+            find_replace_counts: Vec::new(),
+            git_project_status: None,
+            projects: Vec::new(),
+            project_replace_excluded: HashSet::new(),
+            project_replace_mode: false,
+            project_replace_value: String::new(),
+            project_search_id: widget::Id::unique(),
+            project_search_value: String::new(),
+            project_search_result: None,
+            project_search_generation: Arc::new(AtomicU64::new(0)),
+            quick_open_id: widget::Id::unique(),
+            quick_open_index: Vec::new(),
+            quick_open_results: Vec::new(),
+            quick_open_selected: 0,
+            quick_open_value: String::new(),
+            recovery_orphans: recovery::orphans(),
+            recovery_written: false,
+            jumps_back: Vec::new(),
+            jumps_forward: Vec::new(),
+            references: Vec::new(),
+            macro_recording: None,
+            macro_steps: Vec::new(),
+            filter_command: String::new(),
+            snippets: HashMap::new(),
+            tags: Vec::new(),
+            symbol_search_results: Vec::new(),
+            symbol_search_selected: 0,
+            symbol_search_value: String::new(),
+            ex_message_opt: None,
+            key_bind_recording_opt: None,
+            key_bind_conflict_opt: None,
+            key_bind_search_value: String::new(),
+            syntax_association_pattern: String::new(),
+            syntax_association_opt: None,
+            command_palette: command_palette::commands(),
+            command_palette_results: Vec::new(),
+            command_palette_selected: 0,
+            command_palette_value: String::new(),
+            highlight_worker_opt: None,
+            spell_dictionaries: spell::dictionaries(),
+            spelling_ignored: HashSet::new(),
+            spelling_words: spell::user_words(),
+            task_output_shown: false,
+            task_output: Vec::new(),
+            task_running_opt: None,
+            task_last_opt: None,
+            task_session: 0,
+            task_scrollable_id: widget::Id::unique(),
+            zen_mode: false,
+            zen_nav_bar: false,
+            window_mode_opt: None,
+            settings_page_model,
+            settings_window_opt: None,
+            lsp_worker_opt: None,
+            watcher_opt: None,
+            modifiers: Modifiers::empty(),
+        };
+
+        if app.config.save_find_history {
+            app.find_history = app.config_state.find_history.clone();
+            app.replace_history = app.config_state.replace_history.clone();
+        }
+        if app.config.save_clipboard_history {
+            app.clipboard_history = app.config_state.clipboard_history.clone();
+        }
+        app.prune_recent_files();
+
+        // Do not show nav bar by default. Will be opened by open_project if needed
+        app.core.nav_bar_set_toggled(false);
+
+        // Files from the last session are opened first so that arguments are activated
+        let restore_command = if app.config.restore_session {
+            app.restore_session()
+        } else {
+            Task::none()
+        };
+
+        let (arg_commands, _entities) = app.open_args(env::args().skip(1).collect());
+
+        let mut stdin_tab_opt = None;
+        if let Some(text) = flags.stdin_opt
+            && let Some(entity) = app.open_tab(None)
+            && let Some(Tab::Editor(tab)) = app.tab_model.data_mut::<Tab>(entity)
+        {
+            tab.replace_text(&text);
+            stdin_tab_opt = Some(entity);
+        }
+
+        app.update_nav_bar_placeholder();
+
+        // Open an empty file if no arguments provided
+        if app.tab_model.iter().next().is_none() && arg_commands.is_empty() {
+            app.open_tab(None);
+        }
+
+        // The tab read from stdin is written to stdout, otherwise the active one is
+        if flags.stdout {
+            app.stdout_tab_opt = stdin_tab_opt.or_else(|| {
+                let active = app.tab_model.active();
+                app.tab_model.contains_item(active).then_some(active)
+            });
+        }
+
+        // Offer to recover documents from a previous crash
+        if !app.recovery_orphans.is_empty() {
+            app.dialog_page_opt = Some(DialogPage::Recover);
+        }
+
+        //TODO: try update_config here? It breaks loading system theme by default
+        let command = Task::batch(
+            [restore_command, app.update_tab()]
+                .into_iter()
+                .chain(arg_commands),
+        );
+        (app, command)
+    }
+
+    // The default nav_bar widget needs to be condensed for cosmic-edit
+    fn nav_bar(&self) -> Option<Element<'_, action::Action<Self::Message>>> {
+        if !self.core().nav_bar_active() {
+            return None;
+        }
+
+        let nav_model = self.nav_model()?;
+
+        let cosmic_theme::Spacing {
+            space_none,
+            space_s,
+            space_xxxs,
+            ..
+        } = self.core().system_theme().cosmic().spacing;
+
+        let mut nav = segmented_button::vertical(nav_model)
+            .button_height(space_xxxs + 20 /* line height */ + space_xxxs)
+            .button_padding([space_s, space_xxxs, space_s, space_xxxs])
+            .button_spacing(space_xxxs)
+            .on_activate(|entity| action::cosmic(cosmic::app::Action::NavBar(entity)))
+            .on_context(|entity| action::cosmic(cosmic::app::Action::NavBarContext(entity)))
+            .context_menu(self.nav_context_menu(self.core().nav_bar_context()))
+            .spacing(space_none)
+            .style(theme::SegmentedButton::FileNav)
+            .apply(widget::container)
+            .padding(space_s)
+            .width(Length::Shrink);
+
+        if !self.core().is_condensed() {
+            nav = nav.max_width(280);
+        }
+
+        Some(
+            nav.apply(widget::scrollable)
+                .apply(widget::container)
+                .height(Length::Fill)
+                .class(theme::Container::custom(nav_bar::nav_bar_style))
+                .into(),
+        )
+    }
+
+    fn nav_context_menu(
+        &self,
+        id: nav_bar::Id,
+    ) -> Option<Vec<widget::menu::Tree<cosmic::Action<Message>>>> {
+        let node = self.nav_model.data::<ProjectNode>(id)?;
+        Some(menu::nav_context_menu(id, node))
+    }
+
+    fn nav_model(&self) -> Option<&nav_bar::Model> {
+        Some(&self.nav_model)
+    }
+
+    fn on_app_exit(&mut self) -> Option<Message> {
+        Some(Message::Quit)
+    }
+
+    fn on_context_drawer(&mut self) -> Task<Message> {
+        // Focus correct widget
+        self.update_focus()
+    }
+
+    //TODO: currently the first escape unfocuses, and the second calls this function
+    fn on_escape(&mut self) -> Task<Message> {
+        if self.core.window.show_context {
+            // Close context drawer if open
+            self.core.window.show_context = false;
+        } else if self.find_opt.is_some() {
+            // Close find if open
+            self.find_opt = None;
+        } else if self.zen_mode {
+            return self.update(Message::ToggleZenMode);
+        }
+
+        // Focus correct widget
+        self.update_focus()
+    }
+
+    fn on_nav_select(&mut self, id: nav_bar::Id) -> Task<Message> {
+        // Toggle open state and get clone of node data
+        let node_opt = match self.nav_model.data_mut::<ProjectNode>(id) {
+            Some(node) => {
+                if let ProjectNode::Folder { open, .. } = node {
+                    *open = !*open;
+                }
+                Some(node.clone())
+            }
+            None => None,
+        };
+
+        match node_opt {
+            Some(node) => {
+                // Update icon
+                self.nav_model.icon_set(id, node.icon(16));
+
+                match node {
+                    ProjectNode::Folder {
+                        path, open, root, ..
+                    } => {
+                        // Remember expanded folders, project roots are always expanded when opened
+                        if !root {
+                            if open {
+                                self.config_state.expanded_folders.insert(path.clone());
+                            } else {
+                                self.config_state.expanded_folders.remove(&path);
+                            }
+                            self.save_config_state();
+                        }
+
+                        let position = self.nav_model.position(id).unwrap_or(0);
+                        let indent = self.nav_model.indent(id).unwrap_or(0);
+                        if open {
+                            // Open folder
+                            self.open_folder(path, position + 1, indent + 1);
+                        } else {
+                            // Close folder
+                            while let Some(child_id) = self.nav_model.entity_at(position + 1) {
+                                if self.nav_model.indent(child_id).unwrap_or(0) > indent {
+                                    self.nav_model.remove(child_id);
+                                } else {
+                                    break;
+                                }
+                            }
+                        }
+
+                        // Prevent nav bar from closing when selecting a
+                        // folder in condensed mode.
+                        self.core_mut().nav_bar_set_toggled(true);
+
+                        Task::none()
+                    }
+                    ProjectNode::File { path, .. } => {
+                        //TODO: go to already open file if possible
+                        self.update(Message::OpenFile(path))
+                    }
+                }
+            }
+            None => {
+                // Open project
+                self.update(Message::OpenProjectDialog)
+            }
+        }
+    }
+
+    fn dialog(&self) -> Option<Element<'_, Self::Message>> {
+        // Dialogs are shown in the editor window that has focus
+        if self.focused_window_opt.is_some() {
+            return None;
+        }
+        self.dialog_view()
+    }
+
+    fn update(&mut self, message: Message) -> Task<Message> {
+        // Helper for updating config values efficiently
+        macro_rules! config_set {
+            ($name: ident, $value: expr) => {
+                match &self.config_handler {
+                    Some(config_handler) => {
+                        if let Err(err) =
+                            paste::paste! { self.config.[<set_ $name>](config_handler, $value) }
+                        {
+                            log::warn!("failed to save config {:?}: {}", stringify!($name), err);
+                        }
+                    }
+                    None => {
+                        self.config.$name = $value;
+                        log::warn!(
+                            "failed to save config {:?}: no config handler",
+                            stringify!($name)
+                        );
+                    }
+                }
+            };
+        }
+        // The text of a document is replaced as it loads, so it cannot be edited until then
+        if message.edits()
+            && let Some(Tab::Editor(tab)) = self.active_tab()
+            && tab.loading_opt.is_some()
+        {
+            log::info!("not editing {:?} before it is loaded", tab.path_opt);
+            return Task::none();
+        }
+
+        match message {
+            Message::AddCaret(below) => {
+                if self.config.vim_bindings {
+                    return Task::none();
+                }
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let mut editor = tab.editor.lock().unwrap();
+                    let mut font_system = font_system().write().unwrap();
+                    let mut editor = editor.borrow_with(font_system.raw());
+                    multi_cursor::add_adjacent(
+                        &mut editor,
+                        &mut tab.carets.lock().unwrap(),
+                        if below { Motion::Down } else { Motion::Up },
+                    );
+                    editor.set_redraw(true);
+                }
+            }
+            Message::AddNextOccurrence => {
+                if self.config.vim_bindings {
+                    return Task::none();
+                }
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let mut editor = tab.editor.lock().unwrap();
+                    multi_cursor::add_next_occurrence(&mut editor, &mut tab.carets.lock().unwrap());
+                    editor.set_redraw(true);
+                }
+            }
+            Message::AppTheme(app_theme) => {
+                config_set!(app_theme, app_theme);
+                return self.update_config();
+            }
+            Message::AtomicSave(atomic_save) => {
+                config_set!(atomic_save, atomic_save);
+                return self.update_config();
+            }
+            Message::SaveUndoHistoryPanel(save_undo_history_panel) => {
+                config_set!(save_undo_history_panel, save_undo_history_panel);
+                return self.update_config();
+            }
+            Message::AutoSave => {
+                let entities: Vec<_> = self.auto_save_pending.drain().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        let Some(path) = &tab.path_opt else {
+                            continue;
+                        };
+                        // Wait for the user to keep or reload the version changed on disk
+                        if tab.disk_changed {
+                            continue;
+                        }
+                        // Saving read-only files would prompt for a password
+                        if fs::metadata(path)
+                            .is_ok_and(|metadata| metadata.permissions().readonly())
+                        {
+                            log::info!("not autosaving read-only file {:?}", path);
+                            continue;
+                        }
+                        if tab.changed() {
+                            // Failures are logged, and saving is tried again at the next interval
+                            let _ = tab.save();
+                            let title = tab.title();
+                            self.tab_model.text_set(entity, title);
+                        }
+                    }
+                }
+                return Task::batch([self.update_dialogs(), self.save_undo_histories()]);
+            }
+            Message::AutoSaveInterval(index) => match self.auto_save_intervals.get(index) {
+                Some(auto_save_secs) => {
+                    config_set!(auto_save_secs, *auto_save_secs);
+                    self.auto_save_pending.clear();
+                    if self.config.auto_save_secs.is_some() {
+                        // Schedule documents that were already changed
+                        for entity in self.tab_model.iter() {
+                            if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                                if tab.path_opt.is_some() && tab.changed() && !tab.disk_changed {
+                                    self.auto_save_pending.insert(entity);
+                                }
+                            }
+                        }
+                    }
+                    return self.update_config();
+                }
+                None => {
+                    log::warn!("failed to find autosave interval with index {}", index);
+                }
+            },
+            Message::AutoScroll(auto_scroll) => {
+                self.auto_scroll = auto_scroll;
+            }
+            Message::ChangedLines(entity, request, changed_lines) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    // Results of older requests may arrive late
+                    if tab.changed_lines_request == request {
+                        tab.changed_lines = changed_lines;
+                    }
+                }
+            }
+            Message::ClearMark => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    tab.carets.lock().unwrap().clear();
+                    let mut editor = tab.editor.lock().unwrap();
+                    editor.set_selection(Selection::None);
+                    editor.set_redraw(true);
+                }
+            }
+            Message::ClearRecentFiles => {
+                self.config_state.recent_files.clear();
+                self.save_config_state();
+            }
+            Message::CommandPalette => {
+                self.dialog_page_opt = Some(DialogPage::CommandPalette);
+                self.command_palette_value.clear();
+                self.update_command_palette_results();
+                return widget::text_input::focus(self.dialog_text_input_id.clone());
+            }
+            Message::CommandPaletteSelect(index) => {
+                self.command_palette_selected = index;
+                return self.update(Message::CommandPaletteSubmit);
+            }
+            Message::CommandPaletteSubmit => {
+                if let Some(action) = self
+                    .command_palette_results
+                    .get(self.command_palette_selected)
+                    .map(|i| self.command_palette[*i].0)
+                {
+                    self.dialog_page_opt = None;
+                    return self.update(action.message(None));
+                }
+            }
+            Message::CommandPaletteValue(value) => {
+                self.command_palette_value = value;
+                self.update_command_palette_results();
+            }
+            Message::CompareDifference(forwards) => {
+                if let Some(Tab::Compare(tab)) = self.active_tab_mut()
+                    && let Some(row) = tab.go_to_difference(forwards)
+                {
+                    let y = row as f32 / tab.rows.len().saturating_sub(1).max(1) as f32;
+                    return iced::widget::scrollable::snap_to(
+                        tab.scrollable_id.clone(),
+                        iced::widget::scrollable::RelativeOffset { x: 0.0, y },
+                    );
+                }
+            }
+            Message::CompareFiles(old_path, new_path) => {
+                return self.compare_files(old_path, new_path);
+            }
+            Message::CompareFilesDialog => {
+                if self.dialog_opt.is_none() {
+                    let (dialog, command) = Dialog::new(
+                        DialogSettings::new().kind(DialogKind::OpenMultipleFiles),
+                        Message::DialogMessage,
+                        Message::CompareFilesResult,
+                    );
+                    self.dialog_opt = Some(dialog);
+                    return command;
+                }
+            }
+            Message::CompareFilesResult(result) => {
+                self.dialog_opt = None;
+                if let DialogResult::Open(mut paths) = result {
+                    // One file is compared with the active document
+                    if paths.len() == 1
+                        && let Some(Tab::Editor(tab)) = self.active_tab()
+                        && let Some(path) = &tab.path_opt
+                    {
+                        paths.insert(0, path.clone());
+                    }
+                    match <[PathBuf; 2]>::try_from(paths) {
+                        Ok([old_path, new_path]) => {
+                            return self.compare_files(old_path, new_path);
+                        }
+                        Err(paths) => {
+                            log::warn!("cannot compare {} files, select two", paths.len());
+                        }
+                    }
+                }
+            }
+            Message::CompareOpen(old_path, new_path, rows) => {
+                let icon =
+                    icon::icon(mime_icon(mime_for_path(&new_path, None, false), 16)).size(16);
+                let tab = Tab::Compare(CompareTab::new(old_path, new_path, rows));
+                self.tab_model
+                    .insert()
+                    .text(tab.title())
+                    .icon(icon)
+                    .data::<Tab>(tab)
+                    .closable()
+                    .activate();
+                return self.update_tab();
+            }
+            Message::Completion(key) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
+                    match key {
+                        CompletionKey::Up | CompletionKey::Down => {
+                            if let Some(completion) = &mut tab.completion_opt {
+                                completion.select(key == CompletionKey::Down);
+                            }
+                        }
+                        CompletionKey::Accept => {
+                            if let Some(completion) = &tab.completion_opt {
+                                let selected = completion.selected;
+                                return self.update(Message::CompletionSelect(selected));
+                            }
+                        }
+                        CompletionKey::Dismiss => {
+                            if let Some(completion) = tab.completion_opt.take() {
+                                tab.completion_closed = Some(completion.start);
+                            }
+                        }
+                    }
+                }
+            }
+            Message::CompletionSelect(match_i) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab_mut()
+                    && let Some(completion) = tab.completion_opt.take()
+                {
+                    tab.completion_closed = Some(completion.start);
+                    if let Some(item_i) = completion.matches.get(match_i) {
+                        let item = &completion.items[*item_i];
+                        {
+                            let mut editor = tab.editor.lock().unwrap();
+                            tab.snippet_stops_opt =
+                                completion::accept(&mut editor, completion.start, item);
+                            editor.set_redraw(true);
+                        }
+                        return self.update(Message::TabChanged(self.tab_model.active()));
+                    }
+                }
+            }
+            Message::Config(config) => {
+                if config != self.config {
+                    log::info!("update config");
+                    //TODO: update syntax theme by clearing tabs, only if needed
+                    self.config = config;
+                    return self.update_config();
+                }
+            }
+            Message::ConfigState(config_state) => {
+                if config_state != self.config_state {
+                    log::info!("update config state");
+                    self.config_state = config_state;
+                }
+            }
+            Message::CloseFile(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                return self.update(Message::TabClose(entity));
+            }
+            Message::CloseOtherTabs(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                self.tab_model.activate(entity);
+                let entities = self
+                    .window_tabs(entity)
+                    .into_iter()
+                    .filter(|other| *other != entity && !self.pinned_tabs.contains(other))
+                    .collect();
+                return self.close_tabs(entities);
+            }
+            Message::CloseSavedTabs => {
+                let entities = self
+                    .window_tabs(self.tab_model.active())
+                    .into_iter()
+                    .filter(|entity| {
+                        !self.pinned_tabs.contains(entity)
+                            && !matches!(
+                                self.tab_model.data::<Tab>(*entity),
+                                Some(Tab::Editor(tab)) if tab.changed()
+                            )
+                    })
+                    .collect();
+                return self.close_tabs(entities);
+            }
+            Message::CloseTabsToRight(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                let entities = self
+                    .window_tabs(entity)
+                    .into_iter()
+                    .skip_while(|other| *other != entity)
+                    .skip(1)
+                    .filter(|other| !self.pinned_tabs.contains(other))
+                    .collect();
+                return self.close_tabs(entities);
+            }
+            Message::CloseProject(project_i) => {
+                if project_i < self.projects.len() {
+                    let (_project_name, project_path) = self.projects.remove(project_i);
+                    self.update_watcher();
+                    let mut position = 0;
+                    let mut closing = false;
+                    while let Some(id) = self.nav_model.entity_at(position) {
+                        match self.nav_model.data::<ProjectNode>(id) {
+                            Some(node) => {
+                                if let ProjectNode::Folder { path, root, .. } = node {
+                                    if path == &project_path {
+                                        // Found the project root node, closing
+                                        closing = true;
+                                    } else if *root && closing {
+                                        // Found another project root node after closing, breaking
+                                        break;
+                                    }
+                                }
+                            }
+                            None => {
+                                if closing {
+                                    break;
+                                }
+                            }
+                        }
+                        if closing {
+                            self.nav_model.remove(id);
+                        } else {
+                            position += 1;
+                        }
+                    }
+                    self.update_nav_bar_placeholder();
+                }
+            }
+            Message::CloseWindow(window_id) => {
+                if Some(window_id) == self.core.main_window_id() {
+                    return self.update(Message::Quit);
+                }
+                if Some(window_id) == self.settings_window_opt {
+                    self.settings_window_opt = None;
+                    return window::close(window_id);
+                }
+                if let Some(window) = self.windows.get(&window_id) {
+                    let entities = window.tabs();
+                    if self.focused_window_opt == Some(window_id) {
+                        self.set_focused_window(None);
+                    }
+                    self.windows.remove(&window_id);
+                    let mut tasks = vec![window::close(window_id)];
+                    for entity in entities {
+                        match self.tab_model.data::<Tab>(entity) {
+                            // Tabs with unsaved changes are moved to the main window, where they
+                            // can still be saved
+                            Some(Tab::Editor(tab)) if tab.changed() => {
+                                self.main_window.insert(entity);
+                            }
+                            _ => tasks.push(self.update(Message::TabCloseForce(entity))),
+                        }
+                    }
+                    tasks.push(self.update_tab());
+                    return Task::batch(tasks);
+                }
+            }
+            Message::CopyPath(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    if let Some(path) = &tab.path_opt {
+                        return clipboard::write(path.display().to_string());
+                    }
+                }
+            }
+            Message::Copy => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let (selection_opt, block) = {
+                        let mut editor = tab.editor.lock().unwrap();
+                        let carets = tab.carets.lock().unwrap();
+                        if carets.is_empty() {
+                            (editor.copy_selection(), false)
+                        } else {
+                            (Some(multi_cursor::copy_block(&mut editor, &carets)), true)
+                        }
+                    };
+                    if let Some(selection) = selection_opt {
+                        self.block_clipboard_opt = block.then(|| selection.clone());
+                        self.add_clipboard_history(&selection);
+                        return clipboard::write(selection);
+                    }
+                }
+            }
+            Message::Cut => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let (selection_opt, block) = {
+                        let mut editor = tab.editor.lock().unwrap();
+                        let mut carets = tab.carets.lock().unwrap();
+                        let (selection_opt, block) = if carets.is_empty() {
+                            (editor.copy_selection(), false)
+                        } else {
+                            (Some(multi_cursor::copy_block(&mut editor, &carets)), true)
+                        };
+                        editor.start_change();
+                        multi_cursor::apply(&mut editor, &mut carets, |editor| {
+                            editor.delete_selection();
+                        });
+                        editor.finish_change();
+                        (selection_opt, block)
+                    };
+                    if let Some(selection) = selection_opt {
+                        self.block_clipboard_opt = block.then(|| selection.clone());
+                        self.add_clipboard_history(&selection);
+                        return Task::batch([
+                            clipboard::write(selection),
+                            self.update(Message::TabChanged(self.tab_model.active())),
+                        ]);
+                    }
+                }
+            }
+            #[cfg(unix)]
+            Message::DbusRequest(request) => match request {
+                dbus::Request::GetOpenFiles(reply) => {
+                    reply.send(
+                        self.tab_model
+                            .iter()
+                            .filter_map(|entity| match self.tab_model.data::<Tab>(entity) {
+                                Some(Tab::Editor(tab)) => {
+                                    tab.path_opt.as_ref().map(|path| path.display().to_string())
+                                }
+                                _ => None,
+                            })
+                            .collect(),
+                    );
+                }
+                dbus::Request::InsertText(text) => {
+                    return self.update(Message::PasteValue(text));
+                }
+                dbus::Request::OpenFile(path, location_opt) => {
+                    if let Some(entity) = self.open_tab(Some(path)) {
+                        let goto_task = match location_opt {
+                            Some((line, column)) => self.tab_go_to(entity, line, column),
+                            None => Task::none(),
+                        };
+                        return Task::batch([self.update_tab(), goto_task]);
+                    }
+                }
+                dbus::Request::SaveAll => {
+                    return self.update(Message::SaveAll);
+                }
+            },
+            Message::DefaultFont(index) => {
+                match self.font_names.get(index) {
+                    Some(font_name) => {
+                        if font_name != &self.config.font_name {
+                            // Update font name from config
+                            {
+                                let mut font_system = font_system().write().unwrap();
+                                font_system.raw().db_mut().set_monospace_family(font_name);
+                            }
+
+                            // Reset line number cache
+                            {
+                                let mut line_number_cache =
+                                    LINE_NUMBER_CACHE.get().unwrap().lock().unwrap();
+                                line_number_cache.clear();
+                            }
+
+                            // This does a complete reset of shaping data!
+                            let entities: Vec<_> = self.tab_model.iter().collect();
+                            for entity in entities {
+                                if let Some(Tab::Editor(tab)) =
+                                    self.tab_model.data_mut::<Tab>(entity)
+                                {
+                                    let mut editor = tab.editor.lock().unwrap();
+                                    editor.with_buffer_mut(|buffer| {
+                                        for line in buffer.lines.iter_mut() {
+                                            line.reset();
+                                        }
+                                    });
+                                }
+                            }
+
+                            config_set!(font_name, font_name.to_string());
+                            return self.update_config();
+                        }
+                    }
+                    None => {
+                        log::warn!("failed to find font with index {}", index);
+                    }
+                }
+            }
+            Message::DefaultFontSize(index) => match self.font_sizes.get(index) {
+                Some(font_size) => {
+                    config_set!(font_size, *font_size);
+                    self.reset_tabs_zoom();
+                    return self.update_config();
+                }
+                None => {
+                    log::warn!("failed to find font with index {}", index);
+                }
+            },
+            Message::ZoomIn => {
+                return self.update_render_active_tab_zoom(message);
+            }
+            Message::ZoomOut => {
+                return self.update_render_active_tab_zoom(message);
+            }
+            Message::ZoomReset => {
+                return self.update_render_active_tab_zoom(message);
+            }
+            Message::DefaultZoomStep(index) => match self.zoom_steps.get(index) {
+                Some(zoom_step) => {
+                    config_set!(font_size_zoom_step_mul_100, *zoom_step);
+                    self.reset_tabs_zoom(); // reset zoom
+                    return self.update_config();
+                }
+                None => {
+                    log::warn!("failed to find zoom step with index {}", index);
+                }
+            },
 
-                match node {
-                    ProjectNode::Folder { path, open, .. } => {
-                        let position = self.nav_model.position(id).unwrap_or(0);
-                        let indent = self.nav_model.indent(id).unwrap_or(0);
-                        if open {
-                            // Open folder
-                            self.open_folder(path, position + 1, indent + 1);
+            Message::DialogCancel => {
+                self.dialog_page_opt = None;
+            }
+            Message::DialogComplete => {
+                let Some(dialog_page) = self.dialog_page_opt.take() else {
+                    return Task::none();
+                };
+                if let DialogPage::OpenLocation(uri) = &dialog_page {
+                    let uri = uri.trim();
+                    if !remote::is_uri(uri) {
+                        self.dialog_page_opt = Some(dialog_page);
+                        return Task::none();
+                    }
+                    return match self.open_remote(uri.to_string()) {
+                        Some((_entity, task)) => Task::batch([task, self.update_tab()]),
+                        None => Task::none(),
+                    };
+                }
+                if let DialogPage::PlayMacro(times) = &dialog_page {
+                    match times.trim().parse::<usize>() {
+                        Ok(times) if times > 0 => {
+                            return self.update(Message::PlayMacro(times.min(MACRO_TIMES_MAX)));
+                        }
+                        _ => {
+                            self.dialog_page_opt = Some(dialog_page);
+                            return Task::none();
+                        }
+                    }
+                }
+                if let DialogPage::Filter(entity, command) = &dialog_page {
+                    let command = command.trim();
+                    if command.is_empty() {
+                        self.dialog_page_opt = Some(dialog_page);
+                        return Task::none();
+                    }
+                    self.filter_command = command.to_string();
+                    return self.filter_tab(*entity, self.filter_command.clone());
+                }
+                if let DialogPage::RenameSymbol(entity, cursor, name) = &dialog_page {
+                    let name = name.trim();
+                    if name.is_empty() {
+                        self.dialog_page_opt = Some(dialog_page);
+                    } else if let Some(worker) = &self.lsp_worker_opt {
+                        worker.rename(*entity, *cursor, name.to_string());
+                    }
+                    return Task::none();
+                }
+                let Some(target) = dialog_page.name_target() else {
+                    // Only dialogs with a valid name can be completed
+                    self.dialog_page_opt = Some(dialog_page);
+                    return Task::none();
+                };
+                match dialog_page {
+                    DialogPage::NewFile(parent, _) => {
+                        match fs::OpenOptions::new()
+                            .write(true)
+                            .create_new(true)
+                            .open(&target)
+                        {
+                            Ok(_) => {
+                                self.refresh_folder(&parent);
+                                return self.update(Message::OpenFile(target));
+                            }
+                            Err(err) => {
+                                log::error!("failed to create file {:?}: {}", target, err);
+                            }
+                        }
+                    }
+                    DialogPage::NewFolder(parent, _) => match fs::create_dir(&target) {
+                        Ok(()) => {
+                            self.refresh_folder(&parent);
+                        }
+                        Err(err) => {
+                            log::error!("failed to create folder {:?}: {}", target, err);
+                        }
+                    },
+                    DialogPage::Rename(path, _) => {
+                        if target.exists() {
+                            log::error!("failed to rename {:?}: {:?} already exists", path, target);
                         } else {
-                            // Close folder
-                            while let Some(child_id) = self.nav_model.entity_at(position + 1) {
-                                if self.nav_model.indent(child_id).unwrap_or(0) > indent {
-                                    self.nav_model.remove(child_id);
-                                } else {
-                                    break;
+                            match fs::rename(&path, &target) {
+                                Ok(()) => {
+                                    if let Some(parent) = path.parent() {
+                                        self.refresh_folder(parent);
+                                    }
+                                    return self.move_tab_paths(&path, &target);
+                                }
+                                Err(err) => {
+                                    log::error!(
+                                        "failed to rename {:?} to {:?}: {}",
+                                        path,
+                                        target,
+                                        err
+                                    );
                                 }
                             }
                         }
-
-                        // Prevent nav bar from closing when selecting a
-                        // folder in condensed mode.
-                        self.core_mut().nav_bar_set_toggled(true);
-
+                    }
+                    _ => {}
+                }
+            }
+            Message::DialogMessage(dialog_message) => {
+                if let Some(dialog) = &mut self.dialog_opt {
+                    return dialog.update(dialog_message);
+                }
+            }
+            Message::DeleteCharacter => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let mut editor = tab.editor.lock().unwrap();
+                    let mut font_system = font_system().write().unwrap();
+                    editor
+                        .borrow_with(font_system.raw())
+                        .action(cosmic_text::Action::Delete);
+                }
+                return self.update(Message::TabChanged(self.tab_model.active()));
+            }
+            Message::DeleteLines => {
+                return self.edit_lines(lines::delete_lines);
+            }
+            Message::DropFiles(paste_opt) => {
+                let Some(paste) = paste_opt else {
+                    return Task::none();
+                };
+                for path in paste.paths {
+                    if path.is_dir() {
+                        self.open_project(path);
+                    } else {
+                        self.open_tab(Some(path));
+                    }
+                }
+                return self.update_tab();
+            }
+            Message::DropFilesText(entity, paste_opt) => {
+                let Some(paste) = paste_opt else {
+                    return Task::none();
+                };
+                // Holding Ctrl inserts the contents of the files instead of their paths
+                let texts: Vec<String> = if self.modifiers.contains(Modifiers::CTRL) {
+                    paste
+                        .paths
+                        .iter()
+                        .filter_map(|path| match fs::read_to_string(path) {
+                            Ok(text) => Some(text),
+                            Err(err) => {
+                                log::warn!("failed to read {:?}: {}", path, err);
+                                None
+                            }
+                        })
+                        .collect()
+                } else {
+                    paste
+                        .paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect()
+                };
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    let mut editor = tab.editor.lock().unwrap();
+                    editor.start_change();
+                    editor.insert_string(&texts.join("\n"), None);
+                    editor.finish_change();
+                }
+                return self.update(Message::TabChanged(entity));
+            }
+            Message::DuplicateLines => {
+                return self.edit_lines(lines::duplicate_lines);
+            }
+            Message::DialogUpdate(dialog_page) => {
+                self.dialog_page_opt = Some(dialog_page);
+            }
+            Message::DocumentType(syntax_override) => {
+                let entity = self.tab_model.active();
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.syntax_override = syntax_override.clone();
+                    tab.update_syntax();
+                    let path_opt = tab.path_opt.clone();
+                    self.highlight_tab(entity);
+                    self.lsp_open(entity);
+                    self.update_markdown_preview(entity);
+
+                    // Remember override for this file
+                    if let Some(path) = path_opt {
+                        self.set_syntax_override(path, syntax_override);
+                    }
+                }
+            }
+            Message::HighlightSpans(entity, lines) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    tab.apply_highlight(lines);
+                }
+            }
+            Message::HighlightWorker(worker) => {
+                self.highlight_worker_opt = Some(worker);
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        tab.highlight_restart();
+                    }
+                    self.highlight_tab(entity);
+                }
+            }
+            Message::DocumentTypeSearchValue(value) => {
+                self.document_type_search_value = value;
+            }
+            Message::ExCommand(text, selection_opt) => {
+                self.ex_message_opt = None;
+                if text.trim().is_empty() {
+                    return Task::none();
+                }
+                return match self.ex_command(&text, selection_opt) {
+                    Ok(task) => task,
+                    Err(err) => {
+                        self.ex_message_opt = Some(err.message());
                         Task::none()
                     }
-                    ProjectNode::File { path, .. } => {
-                        //TODO: go to already open file if possible
-                        self.update(Message::OpenFile(path))
+                };
+            }
+            Message::Find(find_opt) => {
+                self.find_opt = find_opt.map(|f| FindField {
+                    replace: f,
+                    has_focus: true,
+                });
+                if self.find_opt.is_none() {
+                    if let Some(Tab::Editor(tab)) = self.active_tab() {
+                        tab.set_find_scope(false);
                     }
                 }
+                self.update_find_matches();
+
+                // Focus correct input
+                return self.update_focus();
             }
-            None => {
-                // Open project
-                self.update(Message::OpenProjectDialog)
+            Message::FindCaseSensitive(find_case_sensitive) => {
+                config_set!(find_case_sensitive, find_case_sensitive);
+                self.update_find_matches();
+                return self.update_config();
             }
-        }
-    }
+            Message::FindInSelection(find_in_selection) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    if !tab.set_find_scope(find_in_selection) {
+                        log::info!("find in selection needs a selection");
+                    }
+                }
+                self.update_find_matches();
+            }
+            Message::FindNext => {
+                self.add_find_history();
+                if !self.find_search_value.is_empty() {
+                    if let Some(Tab::Editor(tab)) = self.active_tab() {
+                        //TODO: do not compile find regex on every search?
+                        match self.config.find_regex(&self.find_search_value) {
+                            Ok(regex) => {
+                                tab.search(&regex, true, self.config.find_wrap_around);
+                            }
+                            Err(err) => {
+                                //TODO: put regex error in find box
+                                log::warn!(
+                                    "failed to compile regex {:?}: {}",
+                                    self.find_search_value,
+                                    err
+                                );
+                            }
+                        }
+                    }
+                }
 
-    fn dialog(&self) -> Option<Element<'_, Self::Message>> {
-        let Some(ref dialog) = self.dialog_page_opt else {
-            return None;
-        };
+                // Focus correct input
+                return self.update_focus();
+            }
+            Message::FindPrevious => {
+                self.add_find_history();
+                if !self.find_search_value.is_empty() {
+                    if let Some(Tab::Editor(tab)) = self.active_tab() {
+                        //TODO: do not compile find regex on every search?
+                        match self.config.find_regex(&self.find_search_value) {
+                            Ok(regex) => {
+                                tab.search(&regex, false, self.config.find_wrap_around);
+                            }
+                            Err(err) => {
+                                //TODO: put regex error in find box
+                                log::warn!(
+                                    "failed to compile regex {:?}: {}",
+                                    self.find_search_value,
+                                    err
+                                );
+                            }
+                        }
+                    }
+                }
 
-        let cosmic_theme::Spacing { space_xxs, .. } = self.core().system_theme().cosmic().spacing;
+                // Focus correct input
+                return self.update_focus();
+            }
+            Message::FindReplace => {
+                self.add_find_history();
+                if !self.find_search_value.is_empty() {
+                    if let Some(Tab::Editor(tab)) = self.active_tab() {
+                        //TODO: do not compile find regex on every search?
+                        match self.config.find_regex(&self.find_search_value) {
+                            Ok(regex) => {
+                                let template =
+                                    self.config.find_replace_template(&self.find_replace_value);
+                                tab.replace(&regex, &template, self.config.find_wrap_around);
+                                return self.update(Message::TabChanged(self.tab_model.active()));
+                            }
+                            Err(err) => {
+                                //TODO: put regex error in find box
+                                log::warn!(
+                                    "failed to compile regex {:?}: {}",
+                                    self.find_search_value,
+                                    err
+                                );
+                            }
+                        }
+                    }
+                }
 
-        match dialog {
-            DialogPage::PromptSaveClose(entity) => {
-                let save_button =
-                    widget::button::suggested(fl!("save")).on_press(Message::Save(Some(*entity)));
-                let discard_button = widget::button::destructive(fl!("discard"))
-                    .on_press(Message::TabCloseForce(*entity));
-                let cancel_button =
-                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
-                let dialog = widget::dialog()
-                    .title(fl!("prompt-save-changes-title"))
-                    .body(fl!("prompt-unsaved-changes"))
-                    .icon(icon::from_name("dialog-warning-symbolic").size(64))
-                    .primary_action(save_button)
-                    .secondary_action(discard_button)
-                    .tertiary_action(cancel_button);
-                Some(dialog.into())
+                // Focus correct input
+                return self.update_focus();
             }
-            DialogPage::PromptSaveQuit(entities) => {
-                let mut can_save_all = true;
-                let mut column = widget::column::with_capacity(entities.len()).spacing(space_xxs);
-                for entity in entities.iter() {
-                    if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(*entity) {
-                        let mut row = widget::row::with_capacity(3).align_y(Alignment::Center);
-                        row = row.push(widget::text(tab.title()));
-                        row = row.push(widget::horizontal_space());
-                        if let Some(_path) = &tab.path_opt {
-                            row = row.push(
-                                widget::button::standard(fl!("save"))
-                                    .on_press(Message::Save(Some(*entity))),
-                            );
-                            //TODO row = row.push(widget::text(format!("{}", path.display())));
-                        } else {
-                            row = row.push(
-                                widget::button::standard(fl!("save-as"))
-                                    .on_press(Message::SaveAsDialog(Some(*entity))),
+            Message::FindReplaceAll => {
+                self.add_find_history();
+                self.find_replace_counts.clear();
+                if !self.find_search_value.is_empty() {
+                    //TODO: do not compile find regex on every search?
+                    match self.config.find_regex(&self.find_search_value) {
+                        Ok(regex) => {
+                            let template =
+                                self.config.find_replace_template(&self.find_replace_value);
+                            let entities: Vec<_> = if self.find_all_tabs {
+                                self.tab_model.iter().collect()
+                            } else {
+                                vec![self.tab_model.active()]
+                            };
+                            let mut tasks = Vec::new();
+                            for entity in entities {
+                                let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                                else {
+                                    continue;
+                                };
+                                // Lines are still being added to large files
+                                if tab.loading_opt.is_some() {
+                                    continue;
+                                }
+                                let count = tab.replace_all(&regex, &template);
+                                if count > 0 {
+                                    self.find_replace_counts.push((tab.title(), count));
+                                    tasks.push(self.update(Message::TabChanged(entity)));
+                                }
+                            }
+                            if !tasks.is_empty() {
+                                return Task::batch(tasks);
+                            }
+                        }
+                        Err(err) => {
+                            //TODO: put regex error in find box
+                            log::warn!(
+                                "failed to compile regex {:?}: {}",
+                                self.find_search_value,
+                                err
                             );
-                            can_save_all = false;
                         }
+                    }
+                }
+
+                // Focus correct input
+                return self.update_focus();
+            }
+            Message::FindAllTabs(find_all_tabs) => {
+                self.find_all_tabs = find_all_tabs;
+                self.find_replace_counts.clear();
+            }
+            Message::FindReplaceFocused => {
+                if let Some(f) = self.find_opt.as_mut() {
+                    f.has_focus = true;
+                }
+                self.find_history_index = None;
+                self.find_replace_focused = true;
+            }
+            Message::FindReplaceValueChanged(value) => {
+                self.find_replace_value = value;
+                self.find_replace_counts.clear();
+                self.find_history_index = None;
+            }
+            Message::FindSearchValueChanged(value) => {
+                self.find_search_value = value;
+                self.find_replace_counts.clear();
+                self.find_history_index = None;
+                self.update_find_matches();
 
-                        column = column.push(row);
+                // Select the first match from the start of the previous one, so that it grows
+                // while typing
+                if !self.find_search_value.is_empty() {
+                    if let Some(Tab::Editor(tab)) = self.active_tab() {
+                        if let Ok(regex) = self.config.find_regex(&self.find_search_value) {
+                            {
+                                let mut editor = tab.editor.lock().unwrap();
+                                if let Some((start, _end)) = editor.selection_bounds() {
+                                    editor.set_cursor(start);
+                                }
+                                editor.set_selection(Selection::None);
+                            }
+                            tab.search(&regex, true, self.config.find_wrap_around);
+                        }
                     }
                 }
-
-                let mut save_button = widget::button::suggested(fl!("save-all"));
-                if can_save_all {
-                    save_button = save_button.on_press(Message::SaveAll);
+            }
+            Message::FindUseRegex(find_use_regex) => {
+                config_set!(find_use_regex, find_use_regex);
+                self.update_find_matches();
+                return self.update_config();
+            }
+            Message::FindWholeWords(find_whole_words) => {
+                config_set!(find_whole_words, find_whole_words);
+                self.update_find_matches();
+                return self.update_config();
+            }
+            Message::FindWrapAround(find_wrap_around) => {
+                config_set!(find_wrap_around, find_wrap_around);
+                return self.update_config();
+            }
+            Message::FilterDialog(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                if let Some(Tab::Editor(_)) = self.tab_model.data::<Tab>(entity) {
+                    self.dialog_page_opt =
+                        Some(DialogPage::Filter(entity, self.filter_command.clone()));
+                    return widget::text_input::focus(self.dialog_text_input_id.clone());
                 }
-                let discard_button =
-                    widget::button::destructive(fl!("discard")).on_press(Message::QuitForce);
-                let cancel_button =
-                    widget::button::text(fl!("cancel")).on_press(Message::DialogCancel);
-                let dialog = widget::dialog()
-                    .title(fl!("prompt-save-changes-title"))
-                    .body(fl!("prompt-unsaved-changes"))
-                    .icon(icon::from_name("dialog-warning-symbolic").size(64))
-                    .control(column)
-                    .primary_action(save_button)
-                    .secondary_action(discard_button)
-                    .tertiary_action(cancel_button);
-
-                Some(dialog.into())
             }
-        }
-    }
-
-    fn update(&mut self, message: Message) -> Task<Message> {
-        // Helper for updating config values efficiently
-        macro_rules! config_set {
-            ($name: ident, $value: expr) => {
-                match &self.config_handler {
-                    Some(config_handler) => {
-                        if let Err(err) =
-                            paste::paste! { self.config.[<set_ $name>](config_handler, $value) }
+            Message::FontLigatures(font_ligatures) => {
+                config_set!(font_ligatures, font_ligatures);
+                return self.update_config();
+            }
+            Message::FormatDocument(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                return self.format_tab(entity, false);
+            }
+            Message::Formatted(entity, request, edits_opt) => {
+                let mut save = false;
+                let mut tasks = Vec::with_capacity(2);
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    if tab.save_after_format == Some(request) {
+                        tab.save_after_format = None;
+                        save = true;
+                    }
+                    // Edits are dropped when the text changed while formatting
+                    if tab.format_request == request
+                        && let Some(edits) = edits_opt.filter(|edits| !edits.is_empty())
+                    {
                         {
-                            log::warn!("failed to save config {:?}: {}", stringify!($name), err);
+                            let mut editor = tab.editor.lock().unwrap();
+                            lsp::edit_editor(&mut editor, edits);
+                            editor.set_redraw(true);
                         }
+                        tab.carets.lock().unwrap().clear();
+                        tasks.push(self.update(Message::TabChanged(entity)));
                     }
-                    None => {
-                        self.config.$name = $value;
-                        log::warn!(
-                            "failed to save config {:?}: no config handler",
-                            stringify!($name)
-                        );
+                }
+                if save {
+                    tasks.push(self.save_tab(entity));
+                }
+                return Task::batch(tasks);
+            }
+            Message::FindFocused(has_focus) => {
+                if let Some(f) = self.find_opt.as_mut() {
+                    *f = FindField {
+                        replace: f.replace,
+                        has_focus,
+                    };
+                }
+                self.find_history_index = None;
+                self.find_replace_focused = false;
+            }
+            Message::Fullscreen(mode) => {
+                if let Some(window_id) = self.focused_window_id() {
+                    if mode == window::Mode::Fullscreen {
+                        // Made fullscreen by the compositor, there is nothing to restore
+                        return window::set_mode(window_id, window::Mode::Windowed);
                     }
+                    self.window_mode_opt = Some(mode);
+                    return window::set_mode(window_id, window::Mode::Fullscreen);
                 }
-            };
-        }
-        match message {
-            Message::AppTheme(app_theme) => {
-                config_set!(app_theme, app_theme);
-                return self.update_config();
             }
-            Message::AutoScroll(auto_scroll) => {
-                self.auto_scroll = auto_scroll;
+            Message::GitBlame(entity, request, git_blame_opt) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    // Results of older requests may arrive late
+                    if tab.git_blame_request == request && tab.git_blame_opt != git_blame_opt {
+                        tab.git_blame_opt = git_blame_opt;
+                        // The gutter is only drawn again when the editor is redrawn
+                        tab.editor.lock().unwrap().set_redraw(true);
+                    }
+                }
             }
-            Message::Config(config) => {
-                if config != self.config {
-                    log::info!("update config");
-                    //TODO: update syntax theme by clearing tabs, only if needed
-                    self.config = config;
-                    return self.update_config();
+            Message::GitChanges(entity, request, git_diff_opt) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    // Results of older requests may arrive late
+                    if tab.git_changes_request == request && tab.git_diff_opt != git_diff_opt {
+                        tab.git_changes = git_diff_opt
+                            .as_ref()
+                            .map_or_else(Vec::new, |diff| diff.line_changes());
+                        tab.git_diff_opt = git_diff_opt;
+                        // The previewed hunk may have changed
+                        tab.git_hunk_popup = None;
+                        // The gutter is only drawn again when the editor is redrawn
+                        tab.editor.lock().unwrap().set_redraw(true);
+                    }
                 }
             }
-            Message::ConfigState(config_state) => {
-                if config_state != self.config_state {
-                    log::info!("update config state");
-                    self.config_state = config_state;
+            Message::GitHunkPopup(entity, change_opt) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.git_hunk_popup = change_opt.and_then(|(line, point)| {
+                        let hunks = &tab.git_diff_opt.as_ref()?.hunks;
+                        let hunk_i = hunks.iter().position(|hunk| hunk.contains_line(line))?;
+                        Some((hunk_i, point))
+                    });
                 }
             }
-            Message::CloseFile => {
-                return self.update(Message::TabClose(self.tab_model.active()));
+            Message::GitProjectStatus(project_status) => {
+                self.git_project_status = Some(project_status);
             }
-            Message::CloseProject(project_i) => {
-                if project_i < self.projects.len() {
-                    let (_project_name, project_path) = self.projects.remove(project_i);
-                    self.update_watcher();
-                    let mut position = 0;
-                    let mut closing = false;
-                    while let Some(id) = self.nav_model.entity_at(position) {
-                        match self.nav_model.data::<ProjectNode>(id) {
-                            Some(node) => {
-                                if let ProjectNode::Folder { path, root, .. } = node {
-                                    if path == &project_path {
-                                        // Found the project root node, closing
-                                        closing = true;
-                                    } else if *root && closing {
-                                        // Found another project root node after closing, breaking
-                                        break;
-                                    }
+            Message::GoToConflict(forwards) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let mut editor = tab.editor.lock().unwrap();
+                    let line = editor.cursor().line;
+                    // Wrap around at the ends of the file
+                    let conflict_opt = if forwards {
+                        tab.conflicts
+                            .iter()
+                            .find(|conflict| conflict.start > line)
+                            .or(tab.conflicts.first())
+                    } else {
+                        tab.conflicts
+                            .iter()
+                            .rev()
+                            .find(|conflict| conflict.end < line)
+                            .or(tab.conflicts.last())
+                    };
+                    if let Some(conflict) = conflict_opt {
+                        editor.set_cursor(Cursor::new(conflict.start, 0));
+                        editor.set_selection(Selection::None);
+                        editor.set_redraw(true);
+                    }
+                }
+            }
+            Message::GoToDiagnostic(entity, cursor) => {
+                self.tab_model.activate(entity);
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    let mut editor = tab.editor.lock().unwrap();
+                    // The text may have changed since the diagnostic was reported
+                    let cursor = lines::clamp(&editor, cursor);
+                    editor.set_cursor(cursor);
+                    editor.set_selection(Selection::None);
+                }
+                return self.update_tab();
+            }
+            Message::GoToLocation(path, cursor) => {
+                return self.jump_to(path, cursor);
+            }
+            Message::GoToMatchingBracket => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let syntax = brackets::Syntax::new(tab.syntax_name());
+                    let mut editor = tab.editor.lock().unwrap();
+                    let cursor = editor.cursor();
+                    let pair_opt =
+                        editor.with_buffer(|buffer| brackets::pair(buffer, cursor, syntax));
+                    if let Some((open, close)) = pair_opt {
+                        // Jump to the closer from its opener, otherwise to the opener
+                        let at_open = (cursor.line, cursor.index) == (open.line, open.index);
+                        editor.set_cursor(if at_open { close } else { open });
+                        editor.set_selection(Selection::None);
+                    }
+                }
+            }
+            Message::GitStage(project_path, path) => {
+                return Task::perform(
+                    async move {
+                        //TODO: send errors to UI
+                        match GitRepository::new(&project_path) {
+                            Ok(repo) => match repo.stage(&path).await {
+                                Ok(()) => {
+                                    return action::app(Message::UpdateGitProjectStatus);
                                 }
-                            }
-                            None => {
-                                if closing {
-                                    break;
+                                Err(err) => {
+                                    log::error!(
+                                        "failed to stage {:?} in {:?}: {}",
+                                        path,
+                                        project_path,
+                                        err
+                                    );
                                 }
+                            },
+                            Err(err) => {
+                                log::error!(
+                                    "failed to open repository {:?}: {}",
+                                    project_path,
+                                    err
+                                );
                             }
                         }
-                        if closing {
-                            self.nav_model.remove(id);
-                        } else {
-                            position += 1;
+                        action::none()
+                    },
+                    |x| x,
+                );
+            }
+            Message::GitRevertHunk(entity) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    let hunk_opt = tab
+                        .git_hunk_popup
+                        .take()
+                        .and_then(|(hunk_i, _)| tab.git_diff_opt.as_ref()?.hunks.get(hunk_i));
+                    if let Some(hunk) = hunk_opt {
+                        if tab.revert_git_hunk(hunk) {
+                            return self.update(Message::TabChanged(entity));
                         }
+                        log::warn!("hunk to revert does not match the document");
                     }
-                    self.update_nav_bar_placeholder();
-                }
-            }
-            Message::CloseWindow(window_id) => {
-                if Some(window_id) == self.core.main_window_id() {
-                    return self.update(Message::Quit);
                 }
             }
-            Message::Copy => {
-                if let Some(Tab::Editor(tab)) = self.active_tab() {
-                    let editor = tab.editor.lock().unwrap();
-                    let selection_opt = editor.copy_selection();
-                    if let Some(selection) = selection_opt {
-                        return clipboard::write(selection);
+            Message::GitStageHunk(entity) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    let hunk_opt = tab
+                        .git_hunk_popup
+                        .take()
+                        .and_then(|(hunk_i, _)| tab.git_diff_opt.as_ref()?.hunks.get(hunk_i));
+                    if let (Some(path), Some(hunk)) = (tab.path_opt.clone(), hunk_opt.cloned()) {
+                        return Task::perform(
+                            async move {
+                                //TODO: send errors to UI
+                                match GitRepository::discover(&path) {
+                                    Ok(repo) => match repo.stage_hunk(&path, &hunk).await {
+                                        Ok(()) => {
+                                            return action::app(Message::UpdateGitProjectStatus);
+                                        }
+                                        Err(err) => {
+                                            log::error!(
+                                                "failed to stage hunk of {:?}: {}",
+                                                path,
+                                                err
+                                            );
+                                        }
+                                    },
+                                    Err(err) => {
+                                        log::error!(
+                                            "failed to open repository of {:?}: {}",
+                                            path,
+                                            err
+                                        );
+                                    }
+                                }
+                                action::none()
+                            },
+                            |x| x,
+                        );
                     }
                 }
             }
-            Message::Cut => {
-                if let Some(Tab::Editor(tab)) = self.active_tab() {
-                    let selection_opt = {
-                        let mut editor = tab.editor.lock().unwrap();
-                        let selection_opt = editor.copy_selection();
-                        editor.start_change();
-                        editor.delete_selection();
-                        editor.finish_change();
-                        selection_opt
-                    };
-                    if let Some(selection) = selection_opt {
-                        return Task::batch([
-                            clipboard::write(selection),
-                            self.update(Message::TabChanged(self.tab_model.active())),
-                        ]);
+            Message::GitUnstage(project_path, path) => {
+                return Task::perform(
+                    async move {
+                        //TODO: send errors to UI
+                        match GitRepository::new(&project_path) {
+                            Ok(repo) => match repo.unstage(&path).await {
+                                Ok(()) => {
+                                    return action::app(Message::UpdateGitProjectStatus);
+                                }
+                                Err(err) => {
+                                    log::error!(
+                                        "failed to unstage {:?} in {:?}: {}",
+                                        path,
+                                        project_path,
+                                        err
+                                    );
+                                }
+                            },
+                            Err(err) => {
+                                log::error!(
+                                    "failed to open repository {:?}: {}",
+                                    project_path,
+                                    err
+                                );
+                            }
+                        }
+                        action::none()
+                    },
+                    |x| x,
+                );
+            }
+            Message::Key(modifiers, key) => {
+                // Record a shortcut in the keyboard shortcuts page
+                if let Some(action) = self.key_bind_recording_opt {
+                    if self.settings_window_opt.is_none()
+                        || self.settings_page_model.active_data::<SettingsPage>()
+                            != Some(&SettingsPage::KeyboardShortcuts)
+                    {
+                        self.key_bind_recording_opt = None;
+                    } else if key == keyboard::Key::Named(keyboard::key::Named::Escape)
+                        && modifiers.is_empty()
+                    {
+                        self.key_bind_recording_opt = None;
+                        return Task::none();
+                    } else if let Some(key_bind) = key_bind::pressed_key_bind(modifiers, &key) {
+                        self.key_bind_recording_opt = None;
+                        let conflict_opt =
+                            self.key_binds.iter().find_map(|(other, other_action)| {
+                                (*other_action != action && other.matches(modifiers, &key))
+                                    .then(|| (other.clone(), *other_action))
+                            });
+                        return match conflict_opt {
+                            Some((other, other_action)) => {
+                                // The shortcut that matched is replaced, which may be written
+                                // differently than the pressed one
+                                self.key_bind_conflict_opt = Some((action, other, other_action));
+                                Task::none()
+                            }
+                            None => self.update(Message::KeyBindSet(action, key_bind)),
+                        };
+                    } else {
+                        // Modifiers are held before the key of the shortcut
+                        return Task::none();
                     }
                 }
-            }
-            Message::DefaultFont(index) => {
-                match self.font_names.get(index) {
-                    Some(font_name) => {
-                        if font_name != &self.config.font_name {
-                            // Update font name from config
-                            {
-                                let mut font_system = font_system().write().unwrap();
-                                font_system.raw().db_mut().set_monospace_family(font_name);
-                            }
 
-                            // Reset line number cache
+                // Move quick open selection
+                if self.dialog_page_opt == Some(DialogPage::CommandPalette) {
+                    match key {
+                        keyboard::Key::Named(keyboard::key::Named::ArrowDown) => {
+                            if self.command_palette_selected + 1
+                                < self.command_palette_results.len()
                             {
-                                let mut line_number_cache =
-                                    LINE_NUMBER_CACHE.get().unwrap().lock().unwrap();
-                                line_number_cache.clear();
+                                self.command_palette_selected += 1;
                             }
-
-                            // This does a complete reset of shaping data!
-                            let entities: Vec<_> = self.tab_model.iter().collect();
-                            for entity in entities {
-                                if let Some(Tab::Editor(tab)) =
-                                    self.tab_model.data_mut::<Tab>(entity)
-                                {
-                                    let mut editor = tab.editor.lock().unwrap();
-                                    editor.with_buffer_mut(|buffer| {
-                                        for line in buffer.lines.iter_mut() {
-                                            line.reset();
-                                        }
-                                    });
-                                }
+                            return Task::none();
+                        }
+                        keyboard::Key::Named(keyboard::key::Named::ArrowUp) => {
+                            self.command_palette_selected =
+                                self.command_palette_selected.saturating_sub(1);
+                            return Task::none();
+                        }
+                        _ => {}
+                    }
+                }
+                if self.dialog_page_opt == Some(DialogPage::SymbolSearch) {
+                    match key {
+                        keyboard::Key::Named(keyboard::key::Named::ArrowDown) => {
+                            if self.symbol_search_selected + 1 < self.symbol_search_results.len() {
+                                self.symbol_search_selected += 1;
                             }
+                            return Task::none();
+                        }
+                        keyboard::Key::Named(keyboard::key::Named::ArrowUp) => {
+                            self.symbol_search_selected =
+                                self.symbol_search_selected.saturating_sub(1);
+                            return Task::none();
+                        }
+                        _ => {}
+                    }
+                }
+                if self.dialog_page_opt == Some(DialogPage::QuickOpen) {
+                    match key {
+                        keyboard::Key::Named(keyboard::key::Named::ArrowDown) => {
+                            if self.quick_open_selected + 1 < self.quick_open_results.len() {
+                                self.quick_open_selected += 1;
+                            }
+                            return Task::none();
+                        }
+                        keyboard::Key::Named(keyboard::key::Named::ArrowUp) => {
+                            self.quick_open_selected = self.quick_open_selected.saturating_sub(1);
+                            return Task::none();
+                        }
+                        _ => {}
+                    }
+                }
 
-                            config_set!(font_name, font_name.to_string());
-                            return self.update_config();
+                // Recall find history
+                if self.find_opt.is_some_and(|find| find.has_focus) && modifiers.is_empty() {
+                    match key {
+                        keyboard::Key::Named(keyboard::key::Named::ArrowUp) => {
+                            return self.recall_find_history(true);
+                        }
+                        keyboard::Key::Named(keyboard::key::Named::ArrowDown) => {
+                            return self.recall_find_history(false);
                         }
+                        _ => {}
                     }
-                    None => {
-                        log::warn!("failed to find font with index {}", index);
+                }
+
+                for (key_bind, action) in self.key_binds.iter() {
+                    if key_bind.matches(modifiers, &key) {
+                        if let Some(steps) = &mut self.macro_recording
+                            && action.recordable()
+                        {
+                            steps.push(MacroStep::Action(*action));
+                        }
+                        return self.update(action.message(None));
                     }
                 }
             }
-            Message::DefaultFontSize(index) => match self.font_sizes.get(index) {
-                Some(font_size) => {
-                    config_set!(font_size, *font_size);
-                    self.reset_tabs_zoom();
-                    return self.update_config();
+            Message::KeyBindCancel => {
+                self.key_bind_recording_opt = None;
+                self.key_bind_conflict_opt = None;
+            }
+            Message::KeyBindRecord(action) => {
+                self.key_bind_recording_opt = Some(action);
+                self.key_bind_conflict_opt = None;
+            }
+            Message::KeyBindRemove(action) => {
+                let mut key_binds = self.key_binds.clone();
+                key_binds.retain(|_, key_action| *key_action != action);
+                let (custom, removed) = key_bind::customized(&key_binds, self.config.keymap);
+                config_set!(keybinds, custom);
+                config_set!(keybinds_removed, removed);
+                return self.update_config();
+            }
+            Message::KeyBindSearchValue(value) => {
+                self.key_bind_search_value = value;
+            }
+            Message::KeyBindSet(action, key_bind) => {
+                self.key_bind_conflict_opt = None;
+                // The new shortcut replaces the ones the action had
+                let mut key_binds = self.key_binds.clone();
+                key_binds.retain(|_, key_action| *key_action != action);
+                key_binds.insert(key_bind, action);
+                let (custom, removed) = key_bind::customized(&key_binds, self.config.keymap);
+                config_set!(keybinds, custom);
+                config_set!(keybinds_removed, removed);
+                return self.update_config();
+            }
+            Message::KeyboardShortcuts => {
+                self.dialog_page_opt = Some(DialogPage::KeyboardShortcuts(String::new()));
+                return widget::text_input::focus(self.dialog_text_input_id.clone());
+            }
+            Message::KeyBindsReset => {
+                self.key_bind_recording_opt = None;
+                self.key_bind_conflict_opt = None;
+                config_set!(keybinds, HashMap::new());
+                config_set!(keybinds_removed, Vec::new());
+                return self.update_config();
+            }
+            Message::KeyBindsExport => {
+                if self.dialog_opt.is_none() {
+                    let (dialog, command) = Dialog::new(
+                        DialogSettings::new().kind(DialogKind::SaveFile {
+                            filename: "keybindings.json".to_string(),
+                        }),
+                        Message::DialogMessage,
+                        Message::KeyBindsExportResult,
+                    );
+                    self.dialog_opt = Some(dialog);
+                    return command;
                 }
-                None => {
-                    log::warn!("failed to find font with index {}", index);
+            }
+            Message::KeyBindsExportResult(result) => {
+                self.dialog_opt = None;
+                if let DialogResult::Open(paths) = result
+                    && let Some(path) = paths.first()
+                {
+                    // Exported shortcuts change the COSMIC defaults, so that they are the same
+                    // whatever keymap they are imported with
+                    let (keybinds, removed) = key_bind::customized(&self.key_binds, Keymap::Cosmic);
+                    if let Err(err) = (KeyBindsFile { removed, keybinds }).write(path) {
+                        log::warn!("failed to export key bindings to {:?}: {}", path, err);
+                    }
                 }
-            },
-            Message::ZoomIn => {
-                return self.update_render_active_tab_zoom(message);
             }
-            Message::ZoomOut => {
-                return self.update_render_active_tab_zoom(message);
+            Message::KeyBindsImport => {
+                if self.dialog_opt.is_none() {
+                    let (dialog, command) = Dialog::new(
+                        DialogSettings::new().kind(DialogKind::OpenFile),
+                        Message::DialogMessage,
+                        Message::KeyBindsImportResult,
+                    );
+                    self.dialog_opt = Some(dialog);
+                    return command;
+                }
             }
-            Message::ZoomReset => {
-                self.reset_tabs_zoom();
+            Message::KeyBindsImportResult(result) => {
+                self.dialog_opt = None;
+                if let DialogResult::Open(paths) = result
+                    && let Some(path) = paths.first()
+                {
+                    match KeyBindsFile::read(path) {
+                        Ok(file) => {
+                            let key_binds =
+                                key_binds(Keymap::Cosmic, &file.keybinds, &file.removed);
+                            let (custom, removed) =
+                                key_bind::customized(&key_binds, self.config.keymap);
+                            config_set!(keybinds, custom);
+                            config_set!(keybinds_removed, removed);
+                            return self.update_config();
+                        }
+                        Err(err) => {
+                            log::warn!("failed to import key bindings from {:?}: {}", path, err);
+                        }
+                    }
+                }
+            }
+            Message::Keymap(keymap) => {
+                config_set!(keymap, keymap);
                 return self.update_config();
             }
-            Message::DefaultZoomStep(index) => match self.zoom_steps.get(index) {
-                Some(zoom_step) => {
-                    config_set!(font_size_zoom_step_mul_100, *zoom_step);
-                    self.reset_tabs_zoom(); // reset zoom
-                    return self.update_config();
+            Message::RevealInFiles(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    if let Some(parent) = tab.path_opt.as_ref().and_then(|path| path.parent()) {
+                        if let Err(err) = open::that_detached(parent) {
+                            log::warn!("failed to open {:?}: {}", parent, err);
+                        }
+                    }
                 }
-                None => {
-                    log::warn!("failed to find zoom step with index {}", index);
+            }
+            Message::LaunchUrl(url) => match open::that_detached(&url) {
+                Ok(()) => {}
+                Err(err) => {
+                    log::warn!("failed to open {:?}: {}", url, err);
                 }
             },
-
-            Message::DialogCancel => {
-                self.dialog_page_opt = None;
+            Message::LineHeight(line_height_mul_100) => {
+                config_set!(
+                    line_height_mul_100,
+                    line_height_mul_100.clamp(LINE_HEIGHT_MUL_100_MIN, LINE_HEIGHT_MUL_100_MAX)
+                );
             }
-            Message::DialogMessage(dialog_message) => {
-                if let Some(dialog) = &mut self.dialog_opt {
-                    return dialog.update(dialog_message);
+            Message::LineEnding(line_ending) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
+                    tab.set_line_ending(line_ending);
+                    return self.update(Message::TabChanged(self.tab_model.active()));
                 }
             }
-            Message::Find(find_opt) => {
-                self.find_opt = find_opt.map(|f| FindField {
-                    replace: f,
-                    has_focus: true,
-                });
-
-                // Focus correct input
-                return self.update_focus();
+            Message::ConvertIndentation(tabs) => {
+                let tab_width = self.config.tab_width.into();
+                return self
+                    .edit_lines(|editor| lines::convert_indentation(editor, tab_width, tabs));
             }
-            Message::FindCaseSensitive(find_case_sensitive) => {
-                config_set!(find_case_sensitive, find_case_sensitive);
-                return self.update_config();
+            Message::LineTool(line_tool) => {
+                return self.edit_lines(|editor| lines::line_tool(editor, line_tool));
             }
-            Message::FindNext => {
-                if !self.find_search_value.is_empty() {
-                    if let Some(Tab::Editor(tab)) = self.active_tab() {
-                        //TODO: do not compile find regex on every search?
-                        match self.config.find_regex(&self.find_search_value) {
-                            Ok(regex) => {
-                                tab.search(&regex, true, self.config.find_wrap_around);
-                            }
-                            Err(err) => {
-                                //TODO: put regex error in find box
-                                log::warn!(
-                                    "failed to compile regex {:?}: {}",
-                                    self.find_search_value,
-                                    err
-                                );
+            Message::KillLine => {
+                return self.kill(emacs::kill_line);
+            }
+            Message::KillWord => {
+                return self.kill(emacs::kill_word);
+            }
+            Message::ImportSyntaxTheme => {
+                if self.dialog_opt.is_none() {
+                    let (dialog, command) = Dialog::new(
+                        DialogSettings::new().kind(DialogKind::OpenFile),
+                        Message::DialogMessage,
+                        Message::ImportSyntaxThemeResult,
+                    );
+                    self.dialog_opt = Some(dialog);
+                    return command;
+                }
+            }
+            Message::ImportSyntaxThemeResult(result) => {
+                self.dialog_opt = None;
+                if let DialogResult::Open(paths) = result
+                    && let Some(path) = paths.first()
+                {
+                    match syntax::import_theme(path) {
+                        Ok(theme_name) => {
+                            syntax::reload();
+                            self.theme_names = theme_names();
+                            // Use the theme for the current light or dark mode
+                            if self.config.app_theme.theme().theme_type.is_dark() {
+                                config_set!(syntax_theme_dark, theme_name);
+                            } else {
+                                config_set!(syntax_theme_light, theme_name);
                             }
+                            return self.update_config();
+                        }
+                        Err(err) => {
+                            log::warn!("failed to import syntax theme from {:?}: {}", path, err);
                         }
                     }
                 }
-
-                // Focus correct input
-                return self.update_focus();
             }
-            Message::FindPrevious => {
-                if !self.find_search_value.is_empty() {
-                    if let Some(Tab::Editor(tab)) = self.active_tab() {
-                        //TODO: do not compile find regex on every search?
-                        match self.config.find_regex(&self.find_search_value) {
-                            Ok(regex) => {
-                                tab.search(&regex, false, self.config.find_wrap_around);
-                            }
-                            Err(err) => {
-                                //TODO: put regex error in find box
-                                log::warn!(
-                                    "failed to compile regex {:?}: {}",
-                                    self.find_search_value,
-                                    err
-                                );
-                            }
+            Message::JoinLines => {
+                return self.edit_lines(lines::join_lines);
+            }
+            Message::MoveCursor(movement) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let mut editor = tab.editor.lock().unwrap();
+                    let mut font_system = font_system().write().unwrap();
+                    emacs::move_cursor(&mut editor.borrow_with(font_system.raw()), movement);
+                }
+            }
+            Message::MoveLines(up) => {
+                return self.edit_lines(|editor| lines::move_lines(editor, up));
+            }
+            Message::Lsp(event) => match event {
+                lsp::Event::Diagnostics(entity, diagnostics) => {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                        && let Some(diagnostics_opt) = &mut tab.diagnostics_opt
+                    {
+                        *diagnostics_opt = diagnostics;
+                        tab.editor.lock().unwrap().set_redraw(true);
+                    }
+                }
+                lsp::Event::Locations(kind, locations) => match kind {
+                    lsp::LocationKind::Definition => {
+                        if let Some(location) = locations.into_iter().next() {
+                            return self.jump_to(location.path, location.start);
                         }
                     }
+                    lsp::LocationKind::References => {
+                        self.references = locations;
+                        self.context_page = ContextPage::References;
+                        self.core.window.show_context = true;
+                    }
+                },
+                lsp::Event::Hover(entity, request, text_opt) => {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                        && tab.hover_request == request
+                        && let Some((_cursor, _point, hover_text_opt)) = &mut tab.hover_opt
+                    {
+                        *hover_text_opt = text_opt;
+                    }
                 }
-
-                // Focus correct input
-                return self.update_focus();
-            }
-            Message::FindReplace => {
-                if !self.find_search_value.is_empty() {
-                    if let Some(Tab::Editor(tab)) = self.active_tab() {
-                        //TODO: do not compile find regex on every search?
-                        match self.config.find_regex(&self.find_search_value) {
-                            Ok(regex) => {
-                                //TODO: support captures
-                                tab.replace(
-                                    &regex,
-                                    &self.find_replace_value,
-                                    self.config.find_wrap_around,
-                                );
-                                return self.update(Message::TabChanged(self.tab_model.active()));
+                lsp::Event::SignatureHelp(entity, request, signature_opt) => {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                        && tab.signature_request == request
+                    {
+                        let line = tab.editor.lock().unwrap().cursor().line;
+                        tab.signature_opt = signature_opt.map(|signature| (line, signature));
+                    }
+                }
+                lsp::Event::Edits(file_edits) => {
+                    let mut tasks = Vec::with_capacity(file_edits.len());
+                    for (path, edits) in file_edits {
+                        if edits.is_empty() {
+                            continue;
+                        }
+                        // Open files are changed in their tabs, so that the edits can be undone
+                        let path = fs::canonicalize(&path).unwrap_or(path);
+                        let entity_opt = self.tab_model.iter().find(|entity| {
+                            matches!(
+                                self.tab_model.data::<Tab>(*entity),
+                                Some(Tab::Editor(tab)) if tab.path_opt.as_ref() == Some(&path)
+                            )
+                        });
+                        if let Some(entity) = entity_opt {
+                            if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                                let mut editor = tab.editor.lock().unwrap();
+                                lsp::edit_editor(&mut editor, edits);
+                                editor.set_redraw(true);
+                                tab.carets.lock().unwrap().clear();
                             }
-                            Err(err) => {
-                                //TODO: put regex error in find box
-                                log::warn!(
-                                    "failed to compile regex {:?}: {}",
-                                    self.find_search_value,
-                                    err
-                                );
+                            tasks.push(self.update(Message::TabChanged(entity)));
+                        } else {
+                            match fs::read_to_string(&path) {
+                                Ok(mut text) => {
+                                    lsp::edit_text(&mut text, edits);
+                                    if let Err(err) = fs::write(&path, text) {
+                                        log::error!("failed to write {:?}: {}", path, err);
+                                    }
+                                }
+                                Err(err) => {
+                                    log::error!("failed to read {:?}: {}", path, err);
+                                }
                             }
                         }
                     }
+                    return Task::batch(tasks);
                 }
-
-                // Focus correct input
-                return self.update_focus();
-            }
-            Message::FindReplaceAll => {
-                if !self.find_search_value.is_empty() {
-                    if let Some(Tab::Editor(tab)) = self.active_tab() {
-                        //TODO: do not compile find regex on every search?
-                        match self.config.find_regex(&self.find_search_value) {
-                            Ok(regex) => {
-                                //TODO: support captures
-                                {
-                                    let mut editor = tab.editor.lock().unwrap();
-                                    editor.set_cursor(cosmic_text::Cursor::new(0, 0));
-                                }
-                                while tab.replace(&regex, &self.find_replace_value, false) {}
-                                return self.update(Message::TabChanged(self.tab_model.active()));
-                            }
-                            Err(err) => {
-                                //TODO: put regex error in find box
-                                log::warn!(
-                                    "failed to compile regex {:?}: {}",
-                                    self.find_search_value,
-                                    err
-                                );
+                lsp::Event::Symbols(entity, request, symbols_opt) => {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                        && tab.outline_request == request
+                    {
+                        match symbols_opt {
+                            Some(symbols) => tab.outline_opt = Some(symbols),
+                            // Without symbols from the server, known document types have some
+                            None => tab.update_outline(),
+                        }
+                    }
+                }
+                lsp::Event::Format(entity, request, edits_opt) => {
+                    return self.update(Message::Formatted(entity, request, edits_opt));
+                }
+                lsp::Event::Completion(entity, request, items, incomplete) => {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                        && tab.completion_request == request
+                        && let Some(completion) = &mut tab.completion_opt
+                    {
+                        let word = {
+                            let editor = tab.editor.lock().unwrap();
+                            let cursor = editor.cursor();
+                            if cursor.line != completion.start.line
+                                || cursor.index < completion.start.index
+                            {
+                                tab.completion_opt = None;
+                                return Task::none();
                             }
+                            completion::word(&editor, completion.start, cursor)
+                        };
+                        completion.items = items;
+                        if let Some(snippets) = self.snippets.get(tab.syntax_name()) {
+                            let indentation = snippet::indentation(
+                                &tab.editor.lock().unwrap(),
+                                completion.start.line,
+                            );
+                            completion
+                                .items
+                                .extend(snippet::completion_items(snippets, &indentation));
+                        }
+                        completion.incomplete = incomplete;
+                        completion.filter(&word);
+                        if completion.point_opt.is_none() {
+                            completion.point_opt = *tab.caret_point.lock().unwrap();
                         }
                     }
                 }
-
-                // Focus correct input
-                return self.update_focus();
+            },
+            Message::Hover(entity, hover_opt) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.hover_request += 1;
+                    tab.hover_opt = hover_opt.map(|(cursor, point)| (cursor, point, None));
+                    if tab.hover_opt.is_some() {
+                        // Only ask once the mouse rests on the text
+                        let request = tab.hover_request;
+                        return Task::perform(
+                            async move {
+                                time::sleep(time::Duration::from_millis(HOVER_DELAY_MS)).await;
+                                action::app(Message::HoverDelay(entity, request))
+                            },
+                            |x| x,
+                        );
+                    }
+                }
             }
-            Message::FindReplaceValueChanged(value) => {
-                self.find_replace_value = value;
+            Message::HoverDelay(entity, request) => {
+                if let Some(worker) = &self.lsp_worker_opt
+                    && let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                    && tab.hover_request == request
+                    && let Some((cursor, _point, None)) = tab.hover_opt
+                {
+                    worker.hover(entity, cursor, request);
+                }
             }
-            Message::FindSearchValueChanged(value) => {
-                self.find_search_value = value;
+            Message::LspLocations(kind) => {
+                let entity = self.tab_model.active();
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    let editor = tab.editor.lock().unwrap();
+                    let cursor = editor.cursor();
+                    match &self.lsp_worker_opt {
+                        Some(worker) if tab.diagnostics_opt.is_some() => {
+                            worker.locations(entity, cursor, kind);
+                        }
+                        // Without a language server, definitions come from tags files
+                        _ if kind == lsp::LocationKind::Definition => {
+                            let name = completion::word_at(&editor, cursor);
+                            if !name.is_empty() {
+                                return self.go_to_tags(name);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
             }
-            Message::FindUseRegex(find_use_regex) => {
-                config_set!(find_use_regex, find_use_regex);
-                return self.update_config();
+            Message::LspWorker(worker) => {
+                self.lsp_worker_opt = Some(worker);
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    self.lsp_open(entity);
+                }
             }
-            Message::FindWrapAround(find_wrap_around) => {
-                config_set!(find_wrap_around, find_wrap_around);
-                return self.update_config();
+            Message::MarkdownScroll(line) => {
+                if self.markdown_preview_shown()
+                    && let Some(Tab::Editor(tab)) = self.active_tab()
+                {
+                    let y = markdown::scroll_offset(&tab.markdown_blocks, line);
+                    return iced::widget::scrollable::snap_to(
+                        self.markdown_preview_id.clone(),
+                        iced::widget::scrollable::RelativeOffset { x: 0.0, y },
+                    );
+                }
             }
-            Message::FindFocused(has_focus) => {
-                if let Some(f) = self.find_opt.as_mut() {
-                    *f = FindField {
-                        replace: f.replace,
-                        has_focus,
-                    };
+            Message::MacroKey(key) => {
+                if let Some(steps) = &mut self.macro_recording {
+                    steps.push(MacroStep::Key(key));
                 }
             }
-            Message::GitProjectStatus(project_status) => {
-                self.git_project_status = Some(project_status);
+            Message::Modifiers(modifiers) => {
+                self.modifiers = modifiers;
             }
-            Message::GitStage(project_path, path) => {
-                return Task::perform(
-                    async move {
-                        //TODO: send errors to UI
-                        match GitRepository::new(&project_path) {
-                            Ok(repo) => match repo.stage(&path).await {
-                                Ok(()) => {
-                                    return action::app(Message::UpdateGitProjectStatus);
-                                }
-                                Err(err) => {
-                                    log::error!(
-                                        "failed to stage {:?} in {:?}: {}",
-                                        path,
-                                        project_path,
-                                        err
-                                    );
+            Message::Navigate(forward) => {
+                let here_opt = self.jump_here();
+                let jump_opt = if forward {
+                    self.jumps_forward.pop()
+                } else {
+                    self.jumps_back.pop()
+                };
+                if let Some(jump) = jump_opt {
+                    if let Some(here) = here_opt {
+                        if forward {
+                            self.jumps_back.push(here);
+                        } else {
+                            self.jumps_forward.push(here);
+                        }
+                    }
+                    return self.show_jump(jump);
+                }
+            }
+            Message::NavMenuAction(nav_menu_action) => match nav_menu_action {
+                NavMenuAction::Duplicate(id) => {
+                    if let Some(node) = self.nav_model.data::<ProjectNode>(id) {
+                        let path = node.path().to_path_buf();
+                        let copy = project::copy_path(&path);
+                        match project::copy_recursive(&path, &copy) {
+                            Ok(()) => {
+                                if let Some(parent) = path.parent() {
+                                    self.refresh_folder(parent);
                                 }
-                            },
+                            }
                             Err(err) => {
                                 log::error!(
-                                    "failed to open repository {:?}: {}",
-                                    project_path,
+                                    "failed to duplicate {:?} to {:?}: {}",
+                                    path,
+                                    copy,
                                     err
                                 );
                             }
                         }
-                        action::none()
-                    },
-                    |x| x,
-                );
-            }
-            Message::GitUnstage(project_path, path) => {
-                return Task::perform(
-                    async move {
-                        //TODO: send errors to UI
-                        match GitRepository::new(&project_path) {
-                            Ok(repo) => match repo.unstage(&path).await {
-                                Ok(()) => {
-                                    return action::app(Message::UpdateGitProjectStatus);
+                    }
+                }
+                NavMenuAction::MoveToTrash(id) => {
+                    if let Some(node) = self.nav_model.data::<ProjectNode>(id) {
+                        let path = node.path().to_path_buf();
+                        match trash::delete(&path) {
+                            Ok(()) => {
+                                if let Some(parent) = path.parent() {
+                                    self.refresh_folder(parent);
                                 }
-                                Err(err) => {
-                                    log::error!(
-                                        "failed to unstage {:?} in {:?}: {}",
-                                        path,
-                                        project_path,
-                                        err
-                                    );
+
+                                // Open documents are kept, but marked as changed so they can be saved again
+                                let mut tasks = Vec::new();
+                                let entities: Vec<_> = self.tab_model.iter().collect();
+                                for entity in entities {
+                                    if let Some(Tab::Editor(tab)) =
+                                        self.tab_model.data::<Tab>(entity)
+                                    {
+                                        if tab
+                                            .path_opt
+                                            .as_ref()
+                                            .is_some_and(|tab_path| tab_path.starts_with(&path))
+                                        {
+                                            tab.editor.lock().unwrap().set_changed(true);
+                                            tasks.push(self.update(Message::TabChanged(entity)));
+                                        }
+                                    }
                                 }
-                            },
+                                return Task::batch(tasks);
+                            }
                             Err(err) => {
-                                log::error!(
-                                    "failed to open repository {:?}: {}",
-                                    project_path,
-                                    err
-                                );
+                                log::error!("failed to move {:?} to trash: {}", path, err);
                             }
                         }
-                        action::none()
-                    },
-                    |x| x,
-                );
-            }
-            Message::Key(modifiers, key) => {
-                for (key_bind, action) in self.key_binds.iter() {
-                    if key_bind.matches(modifiers, &key) {
-                        return self.update(action.message(None));
                     }
                 }
-            }
-            Message::LaunchUrl(url) => match open::that_detached(&url) {
-                Ok(()) => {}
-                Err(err) => {
-                    log::warn!("failed to open {:?}: {}", url, err);
+                NavMenuAction::NewFile(id) | NavMenuAction::NewFolder(id) => {
+                    // New items are created next to files, or inside of folders
+                    let parent_opt = match self.nav_model.data::<ProjectNode>(id) {
+                        Some(ProjectNode::Folder { path, .. }) => Some(path.clone()),
+                        Some(ProjectNode::File { path, .. }) => {
+                            path.parent().map(Path::to_path_buf)
+                        }
+                        None => None,
+                    };
+                    if let Some(parent) = parent_opt {
+                        self.dialog_page_opt = Some(match nav_menu_action {
+                            NavMenuAction::NewFolder(_) => {
+                                DialogPage::NewFolder(parent, String::new())
+                            }
+                            _ => DialogPage::NewFile(parent, String::new()),
+                        });
+                        return widget::text_input::focus(self.dialog_text_input_id.clone());
+                    }
+                }
+                NavMenuAction::Rename(id) => {
+                    if let Some(node) = self.nav_model.data::<ProjectNode>(id) {
+                        self.dialog_page_opt = Some(DialogPage::Rename(
+                            node.path().to_path_buf(),
+                            node.name().to_string(),
+                        ));
+                        return widget::text_input::focus(self.dialog_text_input_id.clone());
+                    }
                 }
             },
-            Message::Modifiers(modifiers) => {
-                self.modifiers = modifiers;
-            }
             Message::NewFile => {
                 self.open_tab(None);
                 return self.update_tab();
             }
             Message::NewWindow => {
-                //TODO: support multi-window in winit
-                match env::current_exe() {
-                    Ok(exe) => match process::Command::new(&exe).spawn() {
-                        Ok(_child) => {}
-                        Err(err) => {
-                            log::error!("failed to execute {:?}: {}", exe, err);
-                        }
-                    },
-                    Err(err) => {
-                        log::error!("failed to get current executable path: {}", err);
-                    }
+                let window_task = self.open_window();
+                self.open_tab(None);
+                return Task::batch([window_task, self.update_tab()]);
+            }
+            Message::MoveTabToNewWindow(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                // Moving the only tab of a window would leave it empty
+                if self.window_tabs(entity).len() < 2 {
+                    return Task::none();
                 }
+                self.tab_bar_remove(entity);
+                if self.split_opt.is_some_and(|split| split.entity == entity) {
+                    self.split_opt = None;
+                }
+                let window_task = self.open_window();
+                self.tab_model.activate(entity);
+                return Task::batch([window_task, self.update_tab()]);
             }
             Message::NotifyEvent(event) => {
                 // Reload tabs that changed
                 let mut tab_reload = Vec::new();
+                let mut tab_conflict = Vec::new();
                 for entity in self.tab_model.iter() {
                     if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
                         if let Some(path) = &tab.path_opt {
-                            if event.paths.contains(path) {
-                                if tab.changed() {
+                            // Events from our own saves do not change the modification time
+                            if event.paths.contains(path) && tab.modified_on_disk() {
+                                if tab.large {
+                                    // Reloading a large file is slow, so it is left to the user
+                                    tab_conflict.push(entity);
+                                } else if tab.changed() {
                                     log::warn!(
                                         "file changed externally before being saved: {:?}",
                                         path
                                     );
+                                    tab_conflict.push(entity);
                                 } else {
                                     tab_reload.push(entity);
                                 }
@@ -2162,6 +9428,12 @@ impl Application for App {
                         }
                     }
                 }
+                for entity in tab_conflict {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        tab.disk_changed = true;
+                    }
+                    self.auto_save_pending.remove(&entity);
+                }
                 for entity in tab_reload {
                     match self.tab_model.data_mut::<Tab>(entity) {
                         Some(Tab::Editor(tab)) => {
@@ -2282,6 +9554,19 @@ impl Application for App {
                 self.open_tab(Some(path));
                 return self.update_tab();
             }
+            #[cfg(unix)]
+            Message::OpenArgs(args, waiter_opt) => {
+                let (tasks, entities) = self.open_args(args);
+                if let Some(waiter) = waiter_opt {
+                    self.add_waiter(entities, waiter);
+                }
+                self.update_nav_bar_placeholder();
+                let focus_task = match self.focused_window_id() {
+                    Some(window_id) => window::gain_focus(window_id),
+                    None => Task::none(),
+                };
+                return Task::batch([self.update_tab(), focus_task].into_iter().chain(tasks));
+            }
             Message::OpenFileDialog => {
                 if self.dialog_opt.is_none() {
                     let (dialog, command) = Dialog::new(
@@ -2307,6 +9592,7 @@ impl Application for App {
                                 // a file are replaced
                                 Some(Tab::Editor(tab))
                                     if tab.path_opt.is_none()
+                                        && tab.uri_opt.is_none()
                                         && !tab.editor.lock().unwrap().changed() =>
                                 {
                                     self.replace_tab(path, self.tab_model.active());
@@ -2321,6 +9607,31 @@ impl Application for App {
                     }
                 }
             }
+            Message::OpenLocationDialog => {
+                self.dialog_page_opt = Some(DialogPage::OpenLocation(String::new()));
+                return widget::text_input::focus(self.dialog_text_input_id.clone());
+            }
+            Message::OpenDiskDiff(diff) => {
+                let title = format!(
+                    "{}: {}",
+                    fl!("changes-on-disk"),
+                    diff.path
+                        .file_name()
+                        .unwrap_or(diff.path.as_os_str())
+                        .to_string_lossy()
+                );
+                let icon =
+                    icon::icon(mime_icon(mime_for_path(&diff.path, None, false), 16)).size(16);
+                let tab = Tab::GitDiff(GitDiffTab { title, diff });
+                self.tab_model
+                    .insert()
+                    .text(tab.title())
+                    .icon(icon)
+                    .data::<Tab>(tab)
+                    .closable()
+                    .activate();
+                return self.update_tab();
+            }
             Message::OpenGitDiff(project_path, diff) => {
                 // Close any diff tabs with same path
                 {
@@ -2392,8 +9703,21 @@ impl Application for App {
                     }
                 }
             }
+            Message::ReopenClosedTab(index) => {
+                if let Some(session_tab) = self.config_state.closed_tabs.remove(index) {
+                    self.save_config_state();
+                    if let Some((_entity, task)) = self.open_session_tab(session_tab) {
+                        return Task::batch([task, self.update_tab()]);
+                    }
+                }
+            }
             Message::OpenRecentFile(index) => {
                 if let Some(path) = self.config_state.recent_files.get(index).cloned() {
+                    if !path.exists() {
+                        log::warn!("recent file {:?} no longer exists", path);
+                        self.prune_recent_files();
+                        return Task::none();
+                    }
                     self.open_tab(Some(path));
                     return self.update_tab();
                 }
@@ -2446,12 +9770,87 @@ impl Application for App {
                     None => action::none(),
                 });
             }
+            Message::PastePrimary => {
+                return clipboard::read_primary().map(|value_opt| match value_opt {
+                    Some(value) => action::app(Message::PasteValue(value)),
+                    None => action::none(),
+                });
+            }
+            Message::PasteHistory(index) => {
+                if self.dialog_page_opt == Some(DialogPage::PasteHistory) {
+                    self.dialog_page_opt = None;
+                }
+                if let Some(value) = self.clipboard_history.get(index).cloned() {
+                    // The chosen text is also pasted by the next paste
+                    self.add_clipboard_history(&value);
+                    self.block_clipboard_opt = None;
+                    return Task::batch([
+                        clipboard::write(value.clone()),
+                        self.update(Message::PasteValue(value)),
+                    ]);
+                }
+            }
+            Message::PasteHistoryDialog => {
+                if !self.clipboard_history.is_empty() {
+                    self.dialog_page_opt = Some(DialogPage::PasteHistory);
+                }
+            }
+            Message::PlayMacro(times) => {
+                return self.play_macro(times);
+            }
+            Message::PlayMacroDialog => {
+                if !self.macro_steps.is_empty() {
+                    self.dialog_page_opt = Some(DialogPage::PlayMacro("1".to_string()));
+                    return widget::text_input::focus(self.dialog_text_input_id.clone());
+                }
+            }
             Message::PasteValue(value) => {
                 if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let tab_width = usize::from(self.config.tab_width);
+                    let tabs_opt = self
+                        .config
+                        .paste_convert_indentation
+                        .then(|| tab.indent_tabs());
                     {
                         let mut editor = tab.editor.lock().unwrap();
+                        let mut carets = tab.carets.lock().unwrap();
+                        let lines: Vec<&str> = value.split('\n').collect();
                         editor.start_change();
-                        editor.insert_string(&value, None);
+                        if !carets.is_empty() && lines.len() == carets.len() + 1 {
+                            // Each caret gets one line of the clipboard
+                            multi_cursor::apply_indexed(
+                                &mut editor,
+                                &mut carets,
+                                |editor, index| editor.insert_string(lines[index], None),
+                            );
+                        } else if carets.is_empty()
+                            && self.block_clipboard_opt.as_ref() == Some(&value)
+                        {
+                            multi_cursor::paste_block(&mut editor, &value);
+                        } else {
+                            multi_cursor::apply(&mut editor, &mut carets, |editor| {
+                                if lines.len() < 2 {
+                                    editor.insert_string(&value, None);
+                                    return;
+                                }
+                                // Indentation is matched to the text before the pasted lines
+                                let start = editor
+                                    .selection_bounds()
+                                    .map_or_else(|| editor.cursor(), |(start, _end)| start);
+                                let before = editor.with_buffer(|buffer| {
+                                    let text = buffer.lines[start.line].text();
+                                    text[..start.index.min(text.len())].to_string()
+                                });
+                                let indented = reindent::indent(
+                                    &value,
+                                    &before,
+                                    tab_width,
+                                    self.config.paste_reindent,
+                                    tabs_opt,
+                                );
+                                editor.insert_string(&indented, None);
+                            });
+                        }
                         editor.finish_change();
                     }
                     return self.update(Message::TabChanged(self.tab_model.active()));
@@ -2488,34 +9887,372 @@ impl Application for App {
                     |x| x,
                 );
             }
-            Message::ProjectSearchResult(project_search_result) => {
-                self.project_search_result = Some(project_search_result);
+            Message::ExportDialog(entity_opt, format) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    self.print_unsupported_opt = match format {
+                        export::Format::Html => None,
+                        export::Format::Pdf => print::unsupported_char(&tab.text()),
+                    };
+                    self.dialog_page_opt = Some(DialogPage::Export(entity, format));
+                }
+            }
+            Message::ExportResult(entity, format, result) => {
+                self.dialog_opt = None;
+                if let DialogResult::Open(paths) = result
+                    && let Some(path) = paths.first().cloned()
+                    && let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                {
+                    let title = tab.title();
+                    let options = self.print_options;
+                    let syntax_system = syntax_system();
+                    // HTML has the colors of the editor, PDF the light ones of printed documents
+                    let theme_name = match format {
+                        export::Format::Html => self.config.syntax_theme(),
+                        export::Format::Pdf => self.config.syntax_theme_light.as_str(),
+                    };
+                    let highlight_opt = if options.syntax_colors {
+                        syntax_system
+                            .syntax_set
+                            .find_syntax_by_name(tab.syntax_name())
+                            .zip(syntax_system.theme_set.themes.get(theme_name))
+                            .map(|(syntax, theme)| (&syntax_system.syntax_set, syntax, theme))
+                    } else {
+                        None
+                    };
+                    match format {
+                        export::Format::Html => {
+                            let document = export::html(
+                                &tab.text(),
+                                &title,
+                                highlight_opt,
+                                self.config.tab_width.into(),
+                                options.line_numbers,
+                            );
+                            match fs::write(&path, document) {
+                                Ok(()) => {
+                                    log::info!("exported {:?} to {:?}", title, path);
+                                }
+                                Err(err) => {
+                                    log::error!("failed to export {:?}: {}", path, err);
+                                }
+                            }
+                        }
+                        export::Format::Pdf => {
+                            //TODO: render in the background
+                            let document = print::render(
+                                &tab.text(),
+                                &title,
+                                |page, pages| fl!("print-page", page = page, pages = pages),
+                                highlight_opt,
+                                self.config.tab_width.into(),
+                                &options,
+                            );
+                            return Task::perform(
+                                async move {
+                                    //TODO: send errors to UI
+                                    match export::pdf(document, &path).await {
+                                        Ok(()) => {
+                                            log::info!("exported {:?} to {:?}", title, path);
+                                        }
+                                        Err(err) => {
+                                            log::error!("failed to export {:?}: {}", path, err);
+                                        }
+                                    }
+                                    action::none()
+                                },
+                                |x| x,
+                            );
+                        }
+                    }
+                }
+            }
+            Message::ExternalTool(index) => {
+                let Some(tool) = self.config.external_tools.get(index).cloned() else {
+                    log::warn!("failed to find external tool with index {}", index);
+                    return Task::none();
+                };
+                let context = self.tool_context();
+                let Some(dir) = context.dir(&tool).or_else(dirs::home_dir) else {
+                    return Task::none();
+                };
+                let command = context.command_line(&tool);
+                if tool.capture_output {
+                    return self.update(Message::RunTask(
+                        dir,
+                        tasks::ProjectTask {
+                            name: tool.name,
+                            command,
+                        },
+                    ));
+                }
+                return Task::perform(
+                    async move {
+                        tools::spawn(command, dir).await;
+                        action::none()
+                    },
+                    |x| x,
+                );
+            }
+            Message::ExportSubmit(entity, format) => {
+                self.dialog_page_opt = None;
+                if self.dialog_opt.is_none()
+                    && let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                {
+                    // The exported file is named after the document, next to it
+                    let title = tab.title();
+                    let stem = Path::new(&title)
+                        .file_stem()
+                        .map_or(title.clone(), |stem| stem.to_string_lossy().into_owned());
+                    let mut settings = DialogSettings::new().kind(DialogKind::SaveFile {
+                        filename: format!("{}.{}", stem, format.extension()),
+                    });
+                    if let Some(parent) = tab.path_opt.as_deref().and_then(Path::parent) {
+                        settings = settings.path(parent.to_path_buf());
+                    }
+                    let (dialog, command) =
+                        Dialog::new(settings, Message::DialogMessage, move |result| {
+                            Message::ExportResult(entity, format, result)
+                        });
+                    self.dialog_opt = Some(dialog);
+                    return command;
+                }
+            }
+            Message::PrintDialog(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    self.print_unsupported_opt = print::unsupported_char(&tab.text());
+                    self.dialog_page_opt = Some(DialogPage::PrintSetup(entity));
+                }
+            }
+            Message::PrintOptions(options) => {
+                self.print_options = options;
+            }
+            Message::PrintSubmit(entity) => {
+                self.dialog_page_opt = None;
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    let title = tab.title();
+                    let options = self.print_options;
+
+                    // Always use the light syntax theme, as paper is light
+                    let syntax_system = syntax_system();
+                    let highlight_opt = if options.syntax_colors {
+                        syntax_system
+                            .syntax_set
+                            .find_syntax_by_name(tab.syntax_name())
+                            .zip(
+                                syntax_system
+                                    .theme_set
+                                    .themes
+                                    .get(&self.config.syntax_theme_light),
+                            )
+                            .map(|(syntax, theme)| (&syntax_system.syntax_set, syntax, theme))
+                    } else {
+                        None
+                    };
+
+                    //TODO: render in the background
+                    let document = print::render(
+                        &tab.text(),
+                        &title,
+                        |page, pages| fl!("print-page", page = page, pages = pages),
+                        highlight_opt,
+                        self.config.tab_width.into(),
+                        &options,
+                    );
+                    return Task::perform(
+                        async move {
+                            //TODO: send errors to UI
+                            match print::submit(&title, &options, document).await {
+                                Ok(()) => {
+                                    log::info!("printed {:?}", title);
+                                }
+                                Err(err) => {
+                                    log::error!("failed to print {:?}: {}", title, err);
+                                }
+                            }
+                            action::none()
+                        },
+                        |x| x,
+                    );
+                }
+            }
+            Message::ProjectReplaceAll => {
+                return self.project_replace(false);
+            }
+            Message::ProjectReplaceMode(project_replace_mode) => {
+                self.project_replace_mode = project_replace_mode;
+            }
+            Message::ProjectReplaceSelected => {
+                return self.project_replace(true);
+            }
+            Message::ProjectReplaceToggle(file_i, line_i, checked) => {
+                if checked {
+                    self.project_replace_excluded.remove(&(file_i, line_i));
+                } else {
+                    self.project_replace_excluded.insert((file_i, line_i));
+                }
+            }
+            Message::ProjectReplaceValue(value) => {
+                self.project_replace_value = value;
+            }
+            Message::ProjectSearchFile(generation, file_search_result) => {
+                // Ignore results from cancelled searches
+                if generation == self.project_search_generation.load(Ordering::SeqCst) {
+                    if let Some(project_search_result) = &mut self.project_search_result {
+                        project_search_result.files.push(file_search_result);
+                    }
+                }
+            }
+            Message::ProjectSearchFinished(generation) => {
+                if generation == self.project_search_generation.load(Ordering::SeqCst) {
+                    if let Some(project_search_result) = &mut self.project_search_result {
+                        project_search_result.in_progress = false;
+                    }
 
-                // Focus correct input
-                return self.update_focus();
+                    // Focus correct input
+                    return self.update_focus();
+                }
             }
             Message::ProjectSearchSubmit => {
                 //TODO: Figure out length requirements?
                 if !self.project_search_value.is_empty() {
+                    // This also cancels any search in progress
+                    let generation = self
+                        .project_search_generation
+                        .fetch_add(1, Ordering::SeqCst)
+                        .wrapping_add(1);
+                    let search_generation = self.project_search_generation.clone();
                     let projects = self.projects.clone();
                     let project_search_value = self.project_search_value.clone();
-                    let mut project_search_result = ProjectSearchResult {
+                    let project_search_result = ProjectSearchResult {
                         value: project_search_value.clone(),
                         in_progress: true,
                         files: Vec::new(),
                     };
                     self.project_search_result = Some(project_search_result.clone());
-                    return Task::perform(
+                    self.project_replace_excluded.clear();
+                    return Task::run(
+                        stream::channel(100, move |mut output| async move {
+                            // Results are streamed as each file is searched
+                            let mut file_output = output.clone();
+                            let task_res = tokio::task::spawn_blocking(move || {
+                                project_search_result.search_projects(
+                                    projects,
+                                    || search_generation.load(Ordering::SeqCst) != generation,
+                                    |file_search_result| {
+                                        futures::executor::block_on(async {
+                                            file_output
+                                                .send(Message::ProjectSearchFile(
+                                                    generation,
+                                                    file_search_result,
+                                                ))
+                                                .await
+                                        })
+                                        .is_ok()
+                                    },
+                                );
+                            })
+                            .await;
+                            if let Err(err) = task_res {
+                                log::error!("failed to run search task: {}", err);
+                            }
+                            if let Err(err) = output
+                                .send(Message::ProjectSearchFinished(generation))
+                                .await
+                            {
+                                log::warn!("failed to send search finished: {:?}", err);
+                            }
+                        }),
+                        action::app,
+                    );
+                }
+            }
+            Message::ProjectSearchValue(value) => {
+                self.project_search_value = value;
+            }
+            Message::PromptSaveChanges(entity) => {
+                self.dialog_page_opt = Some(DialogPage::PromptSaveClose(entity));
+            }
+            Message::QuickOpen => {
+                self.dialog_page_opt = Some(DialogPage::QuickOpen);
+                self.quick_open_value.clear();
+                self.update_quick_open_results();
+
+                // The cached index is shown until the projects are walked again
+                let projects = self.projects.clone();
+                return Task::batch([
+                    widget::text_input::focus(self.quick_open_id.clone()),
+                    Task::perform(
                         async move {
                             let task_res = tokio::task::spawn_blocking(move || {
-                                project_search_result.search_projects(projects);
-                                action::app(Message::ProjectSearchResult(project_search_result))
+                                let mut paths = Vec::new();
+                                for (_, project_path) in projects.iter() {
+                                    for entry_res in ignore::WalkBuilder::new(project_path).build()
+                                    {
+                                        let entry = match entry_res {
+                                            Ok(ok) => ok,
+                                            Err(err) => {
+                                                log::warn!(
+                                                    "failed to walk project {:?}: {}",
+                                                    project_path,
+                                                    err
+                                                );
+                                                continue;
+                                            }
+                                        };
+                                        if entry
+                                            .file_type()
+                                            .is_some_and(|file_type| file_type.is_file())
+                                        {
+                                            paths.push(entry.into_path());
+                                        }
+                                    }
+                                }
+                                paths
                             })
                             .await;
                             match task_res {
-                                Ok(message) => message,
+                                Ok(paths) => action::app(Message::QuickOpenIndex(paths)),
+                                Err(err) => {
+                                    log::error!("failed to run quick open index task: {}", err);
+                                    action::none()
+                                }
+                            }
+                        },
+                        |x| x,
+                    ),
+                ]);
+            }
+            Message::GoToSymbol => {
+                self.dialog_page_opt = Some(DialogPage::SymbolSearch);
+                self.symbol_search_value.clear();
+                self.update_symbol_search_results();
+                // The tags that were read before are shown until they are read again
+                return Task::batch([
+                    widget::text_input::focus(self.dialog_text_input_id.clone()),
+                    self.load_tags(Message::Tags),
+                ]);
+            }
+            Message::SymbolSearchSelect(index) => {
+                self.symbol_search_selected = index;
+                return self.update(Message::SymbolSearchSubmit);
+            }
+            Message::SymbolSearchSubmit => {
+                if let Some(tag) = self
+                    .symbol_search_results
+                    .get(self.symbol_search_selected)
+                    .map(|i| self.tags[*i].clone())
+                {
+                    self.dialog_page_opt = None;
+                    return Task::perform(
+                        async move {
+                            match tokio::task::spawn_blocking(move || tag.locate()).await {
+                                Ok(location_opt) => action::app(Message::TagLocations(
+                                    location_opt.into_iter().collect(),
+                                )),
                                 Err(err) => {
-                                    log::error!("failed to run search task: {}", err);
+                                    log::error!("failed to run tags task: {}", err);
                                     action::none()
                                 }
                             }
@@ -2524,11 +10261,49 @@ impl Application for App {
                     );
                 }
             }
-            Message::ProjectSearchValue(value) => {
-                self.project_search_value = value;
+            Message::SymbolSearchValue(value) => {
+                self.symbol_search_value = value;
+                self.update_symbol_search_results();
             }
-            Message::PromptSaveChanges(entity) => {
-                self.dialog_page_opt = Some(DialogPage::PromptSaveClose(entity));
+            Message::TagLocations(mut locations) => {
+                if locations.len() > 1 {
+                    // Names defined more than once are listed like references
+                    self.references = locations;
+                    self.context_page = ContextPage::References;
+                    self.core.window.show_context = true;
+                } else if let Some(location) = locations.pop() {
+                    return self.jump_to(location.path, location.start);
+                }
+            }
+            Message::Tags(tags) => {
+                self.tags = tags;
+                if self.dialog_page_opt == Some(DialogPage::SymbolSearch) {
+                    self.update_symbol_search_results();
+                }
+            }
+            Message::QuickOpenIndex(paths) => {
+                self.quick_open_index = paths;
+                if self.dialog_page_opt == Some(DialogPage::QuickOpen) {
+                    self.update_quick_open_results();
+                }
+            }
+            Message::QuickOpenSelect(index) => {
+                self.quick_open_selected = index;
+                return self.update(Message::QuickOpenSubmit);
+            }
+            Message::QuickOpenSubmit => {
+                if let Some(path) = self
+                    .quick_open_results
+                    .get(self.quick_open_selected)
+                    .cloned()
+                {
+                    self.dialog_page_opt = None;
+                    return self.update(Message::OpenFile(path));
+                }
+            }
+            Message::QuickOpenValue(value) => {
+                self.quick_open_value = value;
+                self.update_quick_open_results();
             }
             Message::Quit => {
                 // Create empty dialog
@@ -2537,8 +10312,110 @@ impl Application for App {
                 return self.update_dialogs();
             }
             Message::QuitForce => {
+                if self.config.restore_session {
+                    self.save_session();
+                }
+                if let Err(err) = recovery::clear() {
+                    log::warn!("failed to remove recovery snapshots: {}", err);
+                }
+                // Quitting before the tab for `--output -` was written fails the pipeline
+                if self.stdout_failed || self.stdout_tab_opt.is_some() {
+                    process::exit(1);
+                }
                 process::exit(0);
             }
+            Message::RecoveryDiscard => {
+                self.dialog_page_opt = None;
+                for (dir, _snapshots) in self.recovery_orphans.drain(..) {
+                    recovery::remove(&dir);
+                }
+            }
+            Message::RecoveryRestore => {
+                self.dialog_page_opt = None;
+                let mut tasks = Vec::new();
+                for (dir, snapshots) in mem::take(&mut self.recovery_orphans) {
+                    for snapshot in snapshots {
+                        if let Some(entity) = self.open_tab(snapshot.path_opt) {
+                            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                                tab.replace_text(&snapshot.text);
+                            }
+                            tasks.push(self.update(Message::TabChanged(entity)));
+                        }
+                    }
+                    recovery::remove(&dir);
+                }
+                tasks.push(self.update_tab());
+                return Task::batch(tasks);
+            }
+            Message::RecoverySnapshot => {
+                let mut snapshots = Vec::new();
+                for entity in self.tab_model.iter() {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                        if tab.changed() {
+                            snapshots.push(Snapshot {
+                                path_opt: tab.path_opt.clone(),
+                                text: tab.text(),
+                            });
+                        }
+                    }
+                }
+                if snapshots.is_empty() && !self.recovery_written {
+                    return Task::none();
+                }
+                self.recovery_written = !snapshots.is_empty();
+                return Task::perform(
+                    async move {
+                        match tokio::task::spawn_blocking(move || recovery::write(&snapshots)).await
+                        {
+                            Ok(Ok(())) => {}
+                            Ok(Err(err)) => {
+                                log::warn!("failed to write recovery snapshots: {}", err);
+                            }
+                            Err(err) => {
+                                log::warn!("failed to join recovery task: {}", err);
+                            }
+                        }
+                        action::none()
+                    },
+                    |x| x,
+                );
+            }
+            Message::RenameSymbol => {
+                let entity = self.tab_model.active();
+                if self.lsp_worker_opt.is_some()
+                    && let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                    && tab.diagnostics_opt.is_some()
+                {
+                    let (cursor, name) = {
+                        let editor = tab.editor.lock().unwrap();
+                        let cursor = editor.cursor();
+                        (cursor, completion::word_at(&editor, cursor))
+                    };
+                    self.dialog_page_opt = Some(DialogPage::RenameSymbol(entity, cursor, name));
+                    return widget::text_input::focus(self.dialog_text_input_id.clone());
+                }
+            }
+            Message::ResolveConflict(resolution) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab()
+                    && let Some(conflict) = tab.cursor_conflict()
+                {
+                    return self
+                        .edit_lines(|editor| conflict::resolve(editor, conflict, resolution));
+                }
+            }
+            Message::ReloadSyntaxes => {
+                syntax::reload();
+                self.theme_names = theme_names();
+                self.syntax_names = syntax_names();
+                // Detect the syntax again, files may have one from the user syntaxes now
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        tab.update_syntax();
+                    }
+                }
+                return self.update_config();
+            }
             Message::Redo => {
                 if let Some(Tab::Editor(tab)) = self.active_tab() {
                     {
@@ -2549,6 +10426,61 @@ impl Application for App {
                     return self.update(Message::TabChanged(self.tab_model.active()));
                 }
             }
+            Message::LanguageServers(lsp) => {
+                config_set!(lsp, lsp);
+                return self.update_config();
+            }
+            Message::RestoreSession(restore_session) => {
+                config_set!(restore_session, restore_session);
+                return self.update_config();
+            }
+            Message::SaveClipboardHistory(save_clipboard_history) => {
+                config_set!(save_clipboard_history, save_clipboard_history);
+                if save_clipboard_history {
+                    self.config_state.clipboard_history = self.clipboard_history.clone();
+                } else {
+                    self.config_state.clipboard_history.clear();
+                }
+                self.save_config_state();
+                return self.update_config();
+            }
+            Message::SaveFindHistory(save_find_history) => {
+                config_set!(save_find_history, save_find_history);
+                if save_find_history {
+                    self.config_state.find_history = self.find_history.clone();
+                    self.config_state.replace_history = self.replace_history.clone();
+                } else {
+                    self.config_state.find_history.clear();
+                    self.config_state.replace_history.clear();
+                }
+                self.save_config_state();
+                return self.update_config();
+            }
+            Message::RerunTask => {
+                return match self.task_last_opt.clone() {
+                    Some((task, dir)) => self.update(Message::RunTask(dir, task)),
+                    None => self.update(Message::RunTaskDialog),
+                };
+            }
+            Message::RunTask(dir, task) => {
+                if matches!(self.dialog_page_opt, Some(DialogPage::RunTask(..))) {
+                    self.dialog_page_opt = None;
+                }
+                // A new session stops the task that is running and starts this one
+                self.task_session += 1;
+                self.task_output.clear();
+                self.task_output_shown = true;
+                self.task_last_opt = Some((task.clone(), dir.clone()));
+                let line = format!("$ {}", task.command);
+                self.task_running_opt = Some((task, dir));
+                return self.task_push(line);
+            }
+            Message::RunTaskDialog => {
+                if let Some(dir) = self.project_dir() {
+                    let project_tasks = tasks::project_tasks(&dir);
+                    self.dialog_page_opt = Some(DialogPage::RunTask(dir, project_tasks));
+                }
+            }
             Message::RevertAllChanges => {
                 if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
                     tab.reload();
@@ -2557,32 +10489,120 @@ impl Application for App {
                 }
             }
             Message::Save(entity_opt) => {
-                let mut title_opt = None;
-
                 let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                    && tab.path_opt.is_some()
+                    && self
+                        .config
+                        .format_on_save
+                        .iter()
+                        .any(|name| name == tab.syntax_name())
+                {
+                    return self.format_tab(entity, true);
+                }
+                return self.save_tab(entity);
+            }
+            Message::SaveElevated(entity) => {
+                self.dialog_page_opt = None;
+                let mut tasks = vec![self.update_dialogs()];
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                    && let Some((path, bytes, hash)) = tab.save_elevated()
+                {
+                    // pkexec waits for the password, so it must not block the UI
+                    tasks.push(Task::perform(
+                        tab::write_elevated(path.clone(), bytes),
+                        move |res| {
+                            let saved = match res {
+                                Ok(()) => true,
+                                Err(err) => {
+                                    log::error!("failed to save {:?} with pkexec: {}", path, err);
+                                    false
+                                }
+                            };
+                            action::app(Message::SaveElevatedResult(entity, path, hash, saved))
+                        },
+                    ));
+                }
+                return Task::batch(tasks);
+            }
+            Message::SaveElevatedResult(entity, path, hash, saved) => {
+                if saved && let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.saved_elevated(&path, hash);
+                    let title = tab.title();
+                    self.tab_model.text_set(entity, title);
+                }
+                return self.save_undo_histories();
+            }
+            Message::SaveWithEncoding(encoding) => {
+                let entity = self.tab_model.active();
                 if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
-                    if tab.path_opt.is_none() {
-                        return self.update(Message::SaveAsDialog(Some(entity)));
+                    // The encoding is only changed once the text is known to fit in it
+                    if !tab.can_encode(encoding) {
+                        self.dialog_page_opt =
+                            Some(DialogPage::PromptSaveEncoding(entity, encoding));
+                        return self.update_dialogs();
                     }
-                    title_opt = Some(tab.title());
-                    tab.save();
+                    tab.encoding = encoding;
+                }
+                return self.update(Message::Save(Some(entity)));
+            }
+            Message::SaveWithEncodingForce(entity, encoding) => {
+                self.dialog_page_opt = None;
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.encoding = encoding;
+                    tab.lossy_encoding_opt = Some(encoding);
                 }
-                if let Some(title) = title_opt {
-                    self.tab_model.text_set(self.tab_model.active(), title);
+                return self.update(Message::Save(Some(entity)));
+            }
+            Message::SettingsPage(entity) => {
+                self.settings_page_model.activate(entity);
+            }
+            Message::SettingsWindow => {
+                if let Some(window_id) = self.settings_window_opt {
+                    return window::gain_focus(window_id);
                 }
-                return self.update_dialogs();
+                let (window_id, task) = window::open(window::Settings {
+                    size: Size::new(800.0, 600.0),
+                    min_size: Some(Size::new(480.0, 360.0)),
+                    // Closed by Message::CloseWindow
+                    exit_on_close_request: false,
+                    ..Default::default()
+                });
+                self.settings_window_opt = Some(window_id);
+                return Task::batch([
+                    task.discard(),
+                    self.set_window_title(fl!("settings"), window_id),
+                ]);
             }
             Message::SaveAll => {
                 let entities: Vec<_> = self.tab_model.iter().collect();
+                let mut dialog_page_opt = None;
                 for entity in entities {
                     if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
-                        if tab.path_opt.is_none() {
+                        if tab.path_opt.is_none() && tab.uri_opt.is_none() {
                             log::warn!("{} has no path when doing save all", tab.title());
                         }
-                        tab.save();
+                        if let Err(err) = tab.save() {
+                            match err.kind() {
+                                io::ErrorKind::PermissionDenied => {
+                                    dialog_page_opt
+                                        .get_or_insert(DialogPage::PromptSaveElevated(entity));
+                                }
+                                io::ErrorKind::InvalidData => {
+                                    dialog_page_opt.get_or_insert(DialogPage::PromptSaveEncoding(
+                                        entity,
+                                        tab.encoding,
+                                    ));
+                                }
+                                _ => {}
+                            }
+                        }
                     }
                 }
-                return self.update_dialogs();
+                if dialog_page_opt.is_some() {
+                    self.dialog_page_opt = dialog_page_opt;
+                }
+                return Task::batch([self.update_dialogs(), self.save_undo_histories()]);
             }
             Message::SaveAsDialog(entity_opt) => {
                 if self.dialog_opt.is_none() {
@@ -2612,50 +10632,238 @@ impl Application for App {
                     }
                 }
             }
-            Message::SaveAsResult(entity, result) => {
-                self.dialog_opt = None;
-                match result {
-                    DialogResult::Cancel => {}
-                    DialogResult::Open(mut paths) => {
-                        if !paths.is_empty() {
-                            let mut title_opt = None;
-                            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
-                                tab.path_opt = Some(paths.remove(0));
-                                title_opt = Some(tab.title());
-                                tab.save();
-                            }
-                            if let Some(title) = title_opt {
-                                self.tab_model.text_set(entity, title);
-                            }
-                            return self.update_dialogs();
-                        }
+            Message::SaveAsResult(entity, result) => {
+                self.dialog_opt = None;
+                match result {
+                    DialogResult::Cancel => {}
+                    DialogResult::Open(mut paths) => {
+                        // The file chooser asks before replacing an existing file
+                        if !paths.is_empty() {
+                            let path = paths.remove(0);
+                            let mut title_opt = None;
+                            if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                                tab.path_opt = Some(path.clone());
+                                tab.update_syntax();
+                                title_opt = Some(tab.title());
+                                if let Err(err) = tab.save() {
+                                    match err.kind() {
+                                        io::ErrorKind::PermissionDenied => {
+                                            self.dialog_page_opt =
+                                                Some(DialogPage::PromptSaveElevated(entity));
+                                        }
+                                        io::ErrorKind::InvalidData => {
+                                            self.dialog_page_opt =
+                                                Some(DialogPage::PromptSaveEncoding(
+                                                    entity,
+                                                    tab.encoding,
+                                                ));
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                            }
+                            if let Some(title) = title_opt {
+                                self.tab_model.text_set(entity, title);
+                                self.tab_model.icon_set(
+                                    entity,
+                                    icon::icon(mime_icon(mime_for_path(&path, None, false), 16))
+                                        .size(16),
+                                );
+                                self.add_recent_file(&path);
+                                self.update_watcher();
+                            }
+                            return Task::batch([
+                                self.update_dialogs(),
+                                self.update_tab(),
+                                self.save_undo_histories(),
+                            ]);
+                        }
+                    }
+                }
+            }
+            Message::SelectAll => {
+                if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
+                    let mut editor = tab.editor.lock().unwrap();
+
+                    // Set cursor to lowest possible value
+                    editor.set_cursor(Cursor::new(0, 0));
+
+                    // Set selection end to highest possible value
+                    let selection = editor.with_buffer(|buffer| {
+                        let last_line = buffer.lines.len().saturating_sub(1);
+                        let last_index = buffer.lines[last_line].text().len();
+                        Selection::Normal(Cursor::new(last_line, last_index))
+                    });
+                    editor.set_selection(selection);
+                }
+            }
+            Message::SetMark => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    tab.carets.lock().unwrap().clear();
+                    emacs::set_mark(&mut tab.editor.lock().unwrap());
+                }
+            }
+            Message::ShowCompletions => {
+                let entity = self.tab_model.active();
+                self.update_completion(entity, true);
+            }
+            Message::Scroll(auto_scroll) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
+                    let mut editor = tab.editor.lock().unwrap();
+                    editor.with_buffer_mut(|buffer| {
+                        let mut scroll = buffer.scroll();
+                        scroll.vertical += auto_scroll;
+                        buffer.set_scroll(scroll);
+                    });
+                }
+            }
+            Message::SnippetTab => {
+                let entity = self.tab_model.active();
+                let Some(syntax_name) = self.load_snippets(entity) else {
+                    return Task::none();
+                };
+                let snippets = &self.snippets[&syntax_name];
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    let mut editor = tab.editor.lock().unwrap();
+                    if let Some(stops) = &mut tab.snippet_stops_opt
+                        && stops.contains(&editor)
+                    {
+                        if !stops.next(&mut editor) {
+                            tab.snippet_stops_opt = None;
+                        }
+                        drop(editor);
+                        self.update_snippet_tab(entity);
+                        return Task::none();
+                    }
+                    tab.snippet_stops_opt = None;
+                    let cursor = editor.cursor();
+                    let before = editor.with_buffer(|buffer| {
+                        buffer.lines[cursor.line].text()[..cursor.index].to_string()
+                    });
+                    match snippet::find(snippets, &before) {
+                        Some(found) => {
+                            let start = Cursor::new(cursor.line, cursor.index - found.prefix.len());
+                            let body = snippet::indent(
+                                &found.body,
+                                &snippet::indentation(&editor, cursor.line),
+                            );
+                            tab.snippet_stops_opt = snippet::insert(&mut editor, start, &body);
+                        }
+                        None => {
+                            // The cursor moved away from the snippet, so Tab indents as usual
+                            let mut font_system = font_system().write().unwrap();
+                            editor
+                                .borrow_with(font_system.raw())
+                                .action(cosmic_text::Action::Indent);
+                        }
+                    }
+                    editor.set_redraw(true);
+                }
+                return self.update(Message::TabChanged(entity));
+            }
+            Message::SpellLanguage(index) => {
+                let Some(language) = self.spell_dictionaries.get(index).cloned() else {
+                    log::warn!("failed to find dictionary with index {}", index);
+                    return Task::none();
+                };
+                let default_languages = self.default_spell_languages();
+                let entity = self.tab_model.active();
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    // Choosing a language starts from the ones that were used
+                    let mut languages = if tab.spell_languages.is_empty() {
+                        default_languages
+                    } else {
+                        tab.spell_languages.clone()
+                    };
+                    match languages.iter().position(|name| *name == language) {
+                        Some(position) => {
+                            languages.remove(position);
+                        }
+                        None => languages.push(language),
+                    }
+                    tab.spell_languages = languages.clone();
+
+                    // Remember the languages of this file
+                    if let Some(path) = tab.path_opt.clone() {
+                        self.set_spell_languages(path, languages);
+                    }
+                    return self.update_spelling(entity);
+                }
+            }
+            Message::Spelling(entity, request, misspellings) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    // Results of older requests may arrive late
+                    if tab.spelling_request == request {
+                        tab.misspellings = misspellings;
+                        tab.editor.lock().unwrap().set_redraw(true);
+                    }
+                }
+            }
+            Message::SpellingAdd(entity, word) => {
+                self.forget_misspelling(entity, &word);
+                if let Err(err) = spell::add(&word) {
+                    log::warn!("failed to add {:?} to the dictionary: {}", word, err);
+                }
+                self.spelling_words.insert(word);
+            }
+            Message::SpellingIgnore(entity, word) => {
+                self.forget_misspelling(entity, &word);
+                self.spelling_ignored.insert(word);
+            }
+            Message::SpellingReplace(entity, replacement) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.context_menu = None;
+                    let Some((start, end)) = tab
+                        .cursor_misspelling()
+                        .map(|misspelling| (misspelling.start, misspelling.end))
+                    else {
+                        return Task::none();
+                    };
+                    {
+                        let mut editor = tab.editor.lock().unwrap();
+                        editor.start_change();
+                        editor.delete_range(start, end);
+                        let cursor = editor.insert_at(start, &replacement, None);
+                        editor.set_cursor(cursor);
+                        editor.finish_change();
                     }
+                    return self.update(Message::TabChanged(entity));
                 }
             }
-            Message::SelectAll => {
-                if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
-                    let mut editor = tab.editor.lock().unwrap();
-
-                    // Set cursor to lowest possible value
-                    editor.set_cursor(Cursor::new(0, 0));
-
-                    // Set selection end to highest possible value
-                    let selection = editor.with_buffer(|buffer| {
-                        let last_line = buffer.lines.len().saturating_sub(1);
-                        let last_index = buffer.lines[last_line].text().len();
-                        Selection::Normal(Cursor::new(last_line, last_index))
-                    });
-                    editor.set_selection(selection);
+            Message::SplitClose => {
+                if self.split_opt.take().is_some() {
+                    return self.update_focus();
                 }
             }
-            Message::Scroll(auto_scroll) => {
-                if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
-                    let mut editor = tab.editor.lock().unwrap();
-                    editor.with_buffer_mut(|buffer| {
-                        let mut scroll = buffer.scroll();
-                        scroll.vertical += auto_scroll;
-                        buffer.set_scroll(scroll);
-                    });
+            Message::SplitFocusNext => {
+                if let Some(split) = self.split_opt.as_mut() {
+                    // The active tab always belongs to the focused pane
+                    let entity = split.entity;
+                    split.entity = self.tab_model.active();
+                    split.second_focused = !split.second_focused;
+                    // With the same tab in both panes, the editor takes the view of the other pane
+                    if entity == split.entity
+                        && let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                        && let Some(split_view) = &tab.split_view_opt
+                    {
+                        split_view
+                            .lock()
+                            .unwrap()
+                            .swap(&mut tab.editor.lock().unwrap());
+                        tab.carets.lock().unwrap().clear();
+                    }
+                    return self.update(Message::TabActivate(entity));
+                }
+            }
+            Message::SplitHorizontal => {
+                return self.split(SplitAxis::Horizontal);
+            }
+            Message::SplitVertical => {
+                return self.split(SplitAxis::Vertical);
+            }
+            Message::StopTask => {
+                if self.task_running_opt.take().is_some() {
+                    return self.task_push(fl!("task-stopped"));
                 }
             }
             Message::Surface(a) => {
@@ -2666,6 +10874,34 @@ impl Application for App {
             Message::SystemThemeModeChange(_theme_mode) => {
                 return self.update_config();
             }
+            Message::SyntaxAssociationAdd => {
+                let pattern = self.syntax_association_pattern.trim().to_string();
+                if let Some(syntax_name) = self
+                    .syntax_association_opt
+                    .and_then(|index| self.syntax_names.get(index))
+                    && !pattern.is_empty()
+                {
+                    let mut syntax_associations = self.config.syntax_associations.clone();
+                    syntax_associations.insert(pattern, syntax_name.clone());
+                    config_set!(syntax_associations, syntax_associations);
+                    self.syntax_association_pattern.clear();
+                    self.syntax_association_opt = None;
+                    return self.update_syntax_associations();
+                }
+            }
+            Message::SyntaxAssociationPattern(pattern) => {
+                self.syntax_association_pattern = pattern;
+            }
+            Message::SyntaxAssociationRemove(pattern) => {
+                let mut syntax_associations = self.config.syntax_associations.clone();
+                if syntax_associations.remove(&pattern).is_some() {
+                    config_set!(syntax_associations, syntax_associations);
+                    return self.update_syntax_associations();
+                }
+            }
+            Message::SyntaxAssociationSyntax(index) => {
+                self.syntax_association_opt = Some(index);
+            }
             Message::SyntaxTheme(index, dark) => match self.theme_names.get(index) {
                 Some(theme_name) => {
                     if dark {
@@ -2689,34 +10925,82 @@ impl Application for App {
                 return self.update_tab();
             }
             Message::TabActivateJump(pos) => {
-                // Length is always at least one, so there shouldn't be a division by zero
-                let len = self.tab_model.iter().count();
+                let tabs = self.window_tabs(self.tab_model.active());
                 // Indices 1 to 8 jumps to tabs 1-8 while 9 jumps to the last
-                let pos = if pos >= 8 || pos > len - 1 {
-                    len - 1
-                } else {
-                    pos % len
-                };
+                let last = tabs.len().saturating_sub(1);
+                let pos = if pos >= 8 { last } else { pos.min(last) };
 
-                let entity = self.tab_model.iter().nth(pos);
-                if let Some(entity) = entity {
-                    return self.update(Message::TabActivate(entity));
+                if let Some(entity) = tabs.get(pos) {
+                    return self.update(Message::TabActivate(*entity));
                 }
             }
+            Message::TaskFinished(code_opt) => {
+                let line = match code_opt {
+                    Some(code) => fl!("task-finished", code = code),
+                    None => fl!("task-stopped"),
+                };
+                let task = self.task_push(line);
+                self.task_running_opt = None;
+                return task;
+            }
+            Message::TaskOutput(line) => {
+                return self.task_push(line);
+            }
             Message::TabChanged(entity) => {
-                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                self.ex_message_opt = None;
+                self.kill_opt = None;
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.update_new_lines();
+                    tab.update_conflicts();
+                    if let Some(worker) = &self.lsp_worker_opt
+                        && tab.diagnostics_opt.is_some()
+                    {
+                        worker.change(entity, tab.text());
+                    }
+                    tab.hover_opt = None;
+                    if !tab.large {
+                        tab.record_undo();
+                    }
+                    // Formatting that was asked for before this change is not applied
+                    tab.format_request += 1;
                     let mut title = tab.title();
                     //TODO: better way of adding change indicator
                     if tab.changed() {
                         title.push_str(" \u{2022}");
                     }
+                    if self.config.auto_save_secs.is_some()
+                        && tab.path_opt.is_some()
+                        && tab.changed()
+                        && !tab.disk_changed
+                    {
+                        self.auto_save_pending.insert(entity);
+                    } else {
+                        self.auto_save_pending.remove(&entity);
+                    }
                     self.tab_model.text_set(entity, title);
                 }
+                if entity == self.tab_model.active() {
+                    self.update_find_matches();
+                    self.update_completion(entity, false);
+                    self.update_snippet_tab(entity);
+                    self.update_signature_help(entity);
+                    self.update_outline(entity);
+                    self.update_markdown_preview(entity);
+                }
+                self.highlight_tab(entity);
+                return Task::batch([
+                    self.update_changed_lines(entity),
+                    self.update_git_changes(entity),
+                    self.update_git_blame(entity),
+                    self.update_spelling(entity),
+                ]);
             }
             Message::TabClose(entity) => {
                 match self.tab_model.data_mut::<Tab>(entity) {
-                    // Only match a changed editor tab...
-                    Some(Tab::Editor(tab)) if tab.changed() => {
+                    // Only match a changed editor tab, which is not piped to standard output...
+                    Some(Tab::Editor(tab))
+                        if tab.changed() && self.stdout_tab_opt != Some(entity) =>
+                    {
                         // The save prompt shouldn't be closed if `TabClose` is emitted again for
                         // the same tab.
                         //
@@ -2739,30 +11023,89 @@ impl Application for App {
                 }
             }
             Message::TabCloseForce(entity) => {
-                // Activate closest item
-                if let Some(position) = self.tab_model.position(entity) {
-                    if position > 0 {
-                        self.tab_model.activate_position(position - 1);
-                    } else {
-                        self.tab_model.activate_position(position + 1);
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity)
+                    && let Some(session_tab) = tab.session_tab()
+                {
+                    self.add_closed_tab(session_tab);
+                }
+
+                // Write the tab to standard output for `--output -`, and quit like a pipeline
+                let quit = self.stdout_tab_opt == Some(entity);
+                if quit {
+                    self.stdout_tab_opt = None;
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                        let mut stdout = io::stdout();
+                        if let Err(err) = stdout
+                            .write_all(tab.text().as_bytes())
+                            .and_then(|()| stdout.flush())
+                        {
+                            log::warn!("failed to write to standard output: {}", err);
+                            self.stdout_failed = true;
+                        }
                     }
                 }
 
-                // Remove item
+                // Remove item, activating the closest item of its window
+                let window_opt = self.tab_window(entity);
+                self.tab_bar_remove(entity);
                 self.tab_model.remove(entity);
+                self.auto_save_pending.remove(&entity);
+                self.pinned_tabs.remove(&entity);
+                #[cfg(unix)]
+                self.waiters.retain_mut(|(entities, waiter)| {
+                    entities.remove(&entity);
+                    if entities.is_empty() {
+                        waiter.finish();
+                    }
+                    !entities.is_empty()
+                });
+                if let Some(worker) = &self.highlight_worker_opt {
+                    worker.close(entity);
+                }
+                if let Some(worker) = &self.lsp_worker_opt {
+                    worker.close(entity);
+                }
                 self.update_watcher();
 
-                // If that was the last tab, make a new empty one
-                if self.tab_model.iter().next().is_none() {
-                    self.open_tab(None);
+                // Close the split if its other pane showed this tab
+                if self.split_opt.is_some_and(|split| split.entity == entity) {
+                    self.split_opt = None;
+                }
+
+                // If that was the last tab of the main window, make a new empty one. Other windows
+                // close with their last tab
+                if window_opt == Some(None)
+                    && self.main_window.tab_bar.iter().next().is_none()
+                    && let Some(empty) = self.open_tab(None)
+                {
+                    self.main_window.insert(empty);
                 }
 
-                // Close PromptSaveClose dialog if open for this entity
-                if self.dialog_page_opt == Some(DialogPage::PromptSaveClose(entity)) {
+                // Close the save prompts if open for this entity
+                if matches!(
+                    self.dialog_page_opt,
+                    Some(
+                        DialogPage::PromptSaveClose(prompt_entity)
+                        | DialogPage::PromptSaveElevated(prompt_entity)
+                        | DialogPage::PromptSaveEncoding(prompt_entity, _)
+                    ) if prompt_entity == entity
+                ) {
                     self.dialog_page_opt = None;
                 }
 
-                return self.update_tab();
+                if quit {
+                    return self.update(Message::Quit);
+                }
+
+                // Discarding a tab from PromptSaveQuit may resolve the last unsaved tab
+                return Task::batch([self.update_tab(), self.update_dialogs()]);
+            }
+            Message::TabBarContext(entity) => {
+                self.tab_bar_context_opt = Some(entity);
+            }
+            Message::TabMenuAction(TabMenuAction(entity, action)) => {
+                self.tab_bar_context_opt = None;
+                return self.update(action.message(Some(entity)));
             }
             Message::TabContextAction(entity, action) => {
                 if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
@@ -2778,36 +11121,142 @@ impl Application for App {
                     tab.context_menu = position_opt;
                 }
             }
+            Message::TabDiskCompare(entity) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    if let Some(path) = tab.path_opt.clone() {
+                        let text = tab.text();
+                        return Task::perform(
+                            async move {
+                                match git::diff_text(&path, &text).await {
+                                    Ok(diff) => action::app(Message::OpenDiskDiff(diff)),
+                                    Err(err) => {
+                                        log::error!(
+                                            "failed to compare {:?} with disk: {}",
+                                            path,
+                                            err
+                                        );
+                                        action::none()
+                                    }
+                                }
+                            },
+                            |x| x,
+                        );
+                    }
+                }
+            }
+            Message::TabDiskKeep(entity) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    tab.keep_changes();
+                    if self.config.auto_save_secs.is_some() && tab.changed() {
+                        self.auto_save_pending.insert(entity);
+                    }
+                }
+            }
+            Message::TabDiskReload(entity) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                    match tab.path_opt.clone().filter(|_| tab.large) {
+                        Some(path) => {
+                            tab.disk_changed = false;
+                            tab.open_large(path);
+                        }
+                        None => tab.reload(),
+                    }
+                    return self.update(Message::TabChanged(entity));
+                }
+            }
+            Message::TabLoadChunk(entity, request, lines, progress) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                    && tab.loading_request == request
+                {
+                    tab.append_lines(lines, progress);
+                }
+            }
+            Message::TabLoadFinished(entity, request) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity)
+                    && tab.loading_request == request
+                {
+                    tab.finish_loading();
+                }
+            }
+            Message::TabLoadRemote(entity, bytes_opt) => {
+                let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) else {
+                    return Task::none();
+                };
+                match bytes_opt {
+                    Some(bytes) => {
+                        tab.finish_remote(&bytes);
+                        self.highlight_tab(entity);
+                        return self.update_tab();
+                    }
+                    // The error was logged, and there is nothing to show
+                    None => return self.update(Message::TabCloseForce(entity)),
+                }
+            }
             Message::TabNext => {
-                let len = self.tab_model.iter().count();
+                let active = self.tab_model.active();
+                let tabs = self.window_tabs(active);
                 // Next tab position. Wraps around to 0 (the first tab) if the last tab is active.
-                let pos = self
-                    .tab_model
-                    .position(self.tab_model.active())
-                    .map(|i| (i as usize + 1) % len)
-                    .expect("at least one tab is always open");
+                let pos_opt = tabs
+                    .iter()
+                    .position(|entity| *entity == active)
+                    .map(|i| (i + 1) % tabs.len());
 
-                let entity = self.tab_model.iter().nth(pos);
-                if let Some(entity) = entity {
-                    return self.update(Message::TabActivate(entity));
+                if let Some(entity) = pos_opt.and_then(|pos| tabs.get(pos)) {
+                    return self.update(Message::TabActivate(*entity));
+                }
+            }
+            Message::TabReorder(dragged, target, insert_position) => {
+                // Pinned tabs stay before the others
+                let pinned_count = self
+                    .window_tabs(dragged)
+                    .iter()
+                    .filter(|entity| **entity != dragged && self.pinned_tabs.contains(entity))
+                    .count() as u16;
+                let pinned = self.pinned_tabs.contains(&dragged);
+                // Tabs are only reordered within the tab bar of their window
+                let Some(window) = self
+                    .tab_window(dragged)
+                    .and_then(|window_opt| self.editor_window_mut(window_opt))
+                else {
+                    return Task::none();
+                };
+                let (Some(dragged_item), Some(target_item)) =
+                    (window.item(dragged), window.item(target))
+                else {
+                    return Task::none();
+                };
+                let (Some(position), Some(target)) = (
+                    window.tab_bar.position(dragged_item),
+                    window.tab_bar.position(target_item),
+                ) else {
+                    return Task::none();
+                };
+                let mut target = match insert_position {
+                    segmented_button::InsertPosition::Before => target,
+                    segmented_button::InsertPosition::After => target + 1,
+                };
+                // Removing the dragged tab shifts the tabs after it
+                if target > position {
+                    target -= 1;
                 }
+                let target = if pinned {
+                    target.min(pinned_count)
+                } else {
+                    target.max(pinned_count)
+                };
+                window.tab_bar.position_set(dragged_item, target);
             }
             Message::TabPrev => {
-                let pos = self
-                    .tab_model
-                    .position(self.tab_model.active())
-                    .and_then(|i| (i as usize).checked_sub(1))
-                    .unwrap_or_else(|| {
-                        self.tab_model
-                            .iter()
-                            .count()
-                            .checked_sub(1)
-                            .unwrap_or_default()
-                    });
-
-                let entity = self.tab_model.iter().nth(pos);
-                if let Some(entity) = entity {
-                    return self.update(Message::TabActivate(entity));
+                let active = self.tab_model.active();
+                let tabs = self.window_tabs(active);
+                let pos = tabs
+                    .iter()
+                    .position(|entity| *entity == active)
+                    .and_then(|i| i.checked_sub(1))
+                    .unwrap_or_else(|| tabs.len().saturating_sub(1));
+
+                if let Some(entity) = tabs.get(pos) {
+                    return self.update(Message::TabActivate(*entity));
                 }
             }
             Message::TabSetCursor(entity, cursor) => {
@@ -2816,6 +11265,17 @@ impl Application for App {
                     editor.set_cursor(cursor);
                 }
             }
+            Message::TabSetScroll(entity, line) => {
+                if let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) {
+                    let mut editor = tab.editor.lock().unwrap();
+                    editor.with_buffer_mut(|buffer| {
+                        let mut scroll = buffer.scroll();
+                        scroll.line = line.min(buffer.lines.len().saturating_sub(1));
+                        scroll.vertical = 0.0;
+                        buffer.set_scroll(scroll);
+                    });
+                }
+            }
             Message::TabWidth(tab_width) => {
                 config_set!(tab_width, tab_width);
                 return self.update_config();
@@ -2823,10 +11283,66 @@ impl Application for App {
             Message::Todo => {
                 log::warn!("TODO");
             }
+            Message::ToggleComment => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    match comment::tokens(tab.syntax_name()) {
+                        Some(tokens) => {
+                            let mut editor = tab.editor.lock().unwrap();
+                            comment::toggle(&mut editor, tokens);
+                        }
+                        None => {
+                            log::info!("no comment tokens for syntax {:?}", tab.syntax_name());
+                            return Task::none();
+                        }
+                    }
+                    return self.update(Message::TabChanged(self.tab_model.active()));
+                }
+            }
+            Message::ToggleAutoClose => {
+                config_set!(auto_close_brackets, !self.config.auto_close_brackets);
+                return self.update_config();
+            }
+            Message::ToggleFormatOnSave => {
+                if let Some(Tab::Editor(tab)) = self.active_tab() {
+                    let syntax_name = tab.syntax_name().to_string();
+                    let mut format_on_save = self.config.format_on_save.clone();
+                    match format_on_save.iter().position(|name| *name == syntax_name) {
+                        Some(index) => {
+                            format_on_save.remove(index);
+                        }
+                        None => format_on_save.push(syntax_name),
+                    }
+                    config_set!(format_on_save, format_on_save);
+                    return self.update_config();
+                }
+            }
             Message::ToggleAutoIndent => {
                 config_set!(auto_indent, !self.config.auto_indent);
                 return self.update_config();
             }
+            Message::TogglePinTab(entity_opt) => {
+                let entity = entity_opt.unwrap_or_else(|| self.tab_model.active());
+                let pinned = !self.pinned_tabs.remove(&entity);
+                // Pinned tabs are kept before the others, and the position is counted without
+                // the tab itself
+                let position = self
+                    .window_tabs(entity)
+                    .iter()
+                    .filter(|other| **other != entity && self.pinned_tabs.contains(other))
+                    .count();
+                if pinned {
+                    self.pinned_tabs.insert(entity);
+                }
+                if let Some(window) = self
+                    .tab_window(entity)
+                    .and_then(|window_opt| self.editor_window_mut(window_opt))
+                    && let Some(item) = window.item(entity)
+                {
+                    window.tab_bar.position_set(item, position as u16);
+                }
+                self.tab_model.closable_set(entity, !pinned);
+                self.update_tab_bars();
+            }
             Message::ToggleContextPage(context_page) => {
                 if self.context_page == context_page {
                     self.core.window.show_context = !self.core.window.show_context;
@@ -2840,6 +11356,7 @@ impl Application for App {
                 {
                     return self.update(Message::UpdateGitProjectStatus);
                 }
+                self.update_outline(self.tab_model.active());
 
                 // Ensure focus of correct input
                 return self.update_focus();
@@ -2857,6 +11374,19 @@ impl Application for App {
 
                 return self.update_config();
             }
+            Message::ToggleIndentGuides => {
+                config_set!(indent_guides, !self.config.indent_guides);
+                // This forces a redraw of all buffers
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        let mut editor = tab.editor.lock().unwrap();
+                        editor.set_redraw(true);
+                    }
+                }
+
+                return self.update_config();
+            }
             Message::ToggleLineNumbers => {
                 config_set!(line_numbers, !self.config.line_numbers);
                 // This forces a redraw of all buffers
@@ -2870,10 +11400,119 @@ impl Application for App {
 
                 return self.update_config();
             }
+            Message::ToggleFullscreen => {
+                if let Some(window_id) = self.focused_window_id() {
+                    return match self.window_mode_opt.take() {
+                        Some(mode) => window::set_mode(window_id, mode),
+                        None => window::get_mode(window_id)
+                            .map(|mode| action::app(Message::Fullscreen(mode))),
+                    };
+                }
+            }
+            Message::ToggleGitBlame => {
+                config_set!(git_blame, !self.config.git_blame);
+                let mut tasks = Vec::new();
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        let mut editor = tab.editor.lock().unwrap();
+                        editor.set_redraw(true);
+                    }
+                    tasks.push(self.update_git_blame(entity));
+                }
+                tasks.push(self.update_config());
+                return Task::batch(tasks);
+            }
+            Message::ToggleGitGutter => {
+                config_set!(git_gutter, !self.config.git_gutter);
+                let mut tasks = Vec::new();
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        let mut editor = tab.editor.lock().unwrap();
+                        editor.set_redraw(true);
+                    }
+                    tasks.push(self.update_git_changes(entity));
+                }
+                tasks.push(self.update_config());
+                return Task::batch(tasks);
+            }
+            Message::ToggleMacroRecording => match self.macro_recording.take() {
+                Some(steps) => {
+                    // Stopping without recording anything keeps the last macro
+                    if !steps.is_empty() {
+                        self.macro_steps = steps;
+                    }
+                }
+                None => {
+                    self.macro_recording = Some(Vec::new());
+                }
+            },
+            Message::ToggleMinimap => {
+                config_set!(minimap, !self.config.minimap);
+                // This forces a redraw of all buffers
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        let mut editor = tab.editor.lock().unwrap();
+                        editor.set_redraw(true);
+                    }
+                }
+
+                return self.update_config();
+            }
+            Message::ToggleMarkdownPreview => {
+                config_set!(markdown_preview, !self.config.markdown_preview);
+                return self.update_config();
+            }
+            Message::ToggleTaskOutput => {
+                // Hiding the output does not stop the task
+                self.task_output_shown = !self.task_output_shown;
+            }
+            Message::ToggleSpellCheck => {
+                config_set!(spell_check, !self.config.spell_check);
+                let mut tasks = Vec::new();
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        let mut editor = tab.editor.lock().unwrap();
+                        editor.set_redraw(true);
+                    }
+                    tasks.push(self.update_spelling(entity));
+                }
+                tasks.push(self.update_config());
+                return Task::batch(tasks);
+            }
+            Message::ToggleWhitespace => {
+                config_set!(show_whitespace, !self.config.show_whitespace);
+                // This forces a redraw of all buffers
+                let entities: Vec<_> = self.tab_model.iter().collect();
+                for entity in entities {
+                    if let Some(Tab::Editor(tab)) = self.tab_model.data_mut::<Tab>(entity) {
+                        let mut editor = tab.editor.lock().unwrap();
+                        editor.set_redraw(true);
+                    }
+                }
+
+                return self.update_config();
+            }
             Message::ToggleWordWrap => {
                 config_set!(word_wrap, !self.config.word_wrap);
                 return self.update_config();
             }
+            Message::ToggleZenMode => {
+                self.zen_mode = !self.zen_mode;
+                if self.zen_mode {
+                    self.zen_nav_bar = self.core.nav_bar_active();
+                    self.core.nav_bar_set_toggled(false);
+                    self.core.window.show_context = false;
+                    self.core.window.show_headerbar = false;
+                } else {
+                    self.core.nav_bar_set_toggled(self.zen_nav_bar);
+                    self.core.window.show_headerbar = true;
+                }
+                return Task::batch([self.update_config(), self.update_focus()]);
+            }
             Message::Undo => {
                 if let Some(Tab::Editor(tab)) = self.active_tab() {
                     {
@@ -2884,6 +11523,15 @@ impl Application for App {
                     return self.update(Message::TabChanged(self.tab_model.active()));
                 }
             }
+            Message::UndoHistoryJump(index) => {
+                if let Some(Tab::Editor(tab)) = self.active_tab_mut() {
+                    // Going to a state is an edit itself, so it can be undone
+                    if let Some(text) = tab.undo_history.jump(index) {
+                        tab.replace_text(&text);
+                    }
+                    return self.update(Message::TabChanged(self.tab_model.active()));
+                }
+            }
             Message::UpdateGitProjectStatus => {
                 self.git_project_status = None;
                 let projects = self.projects.clone();
@@ -2929,12 +11577,42 @@ impl Application for App {
                 config_set!(vim_bindings, vim_bindings);
                 return self.update_config();
             }
+            Message::PrimarySelection(text) => {
+                return clipboard::write_primary(text);
+            }
+            Message::PrimarySelectionEnabled(primary_selection) => {
+                config_set!(primary_selection, primary_selection);
+                return self.update_config();
+            }
+            Message::WordCompletion(word_completion) => {
+                config_set!(word_completion, word_completion);
+                return self.update_config();
+            }
+            Message::WordWrapColumn(index) => match self.word_wrap_columns.get(index) {
+                Some(word_wrap_column) => {
+                    config_set!(word_wrap_column, *word_wrap_column);
+                    return self.update_config();
+                }
+                None => {
+                    log::warn!("failed to find word wrap column with index {}", index);
+                }
+            },
             Message::Focus(window_id) => {
-                if Some(window_id) == self.core.main_window_id() {
-                    // focus the text box if context page is not shown
-                    if !self.core.window.show_context {
-                        return self.update_focus();
-                    }
+                // Settings and file chooser windows keep the focused editor window
+                let window_opt = if self.windows.contains_key(&window_id) {
+                    Some(window_id)
+                } else if Some(window_id) == self.core.main_window_id() {
+                    None
+                } else {
+                    return Task::none();
+                };
+                if window_opt != self.focused_window_opt {
+                    self.set_focused_window(window_opt);
+                    return self.update_tab();
+                }
+                // focus the text box if context page is not shown
+                if !self.core.window.show_context {
+                    return self.update_focus();
                 }
             }
         }
@@ -2958,21 +11636,46 @@ impl Application for App {
                 Message::ToggleContextPage(ContextPage::DocumentStatistics),
             )
             .title(fl!("document-statistics")),
+            ContextPage::DocumentType => context_drawer::context_drawer(
+                self.document_type(),
+                Message::ToggleContextPage(ContextPage::DocumentType),
+            )
+            .title(fl!("document-type-title")),
+            ContextPage::Encoding => context_drawer::context_drawer(
+                self.encoding(),
+                Message::ToggleContextPage(ContextPage::Encoding),
+            )
+            .title(fl!("encoding-title")),
             ContextPage::GitManagement => context_drawer::context_drawer(
                 self.git_management(),
                 Message::ToggleContextPage(ContextPage::GitManagement),
             )
             .title(fl!("git-management")),
+            ContextPage::Outline => context_drawer::context_drawer(
+                self.outline(),
+                Message::ToggleContextPage(ContextPage::Outline),
+            )
+            .title(fl!("outline")),
+            ContextPage::Problems => context_drawer::context_drawer(
+                self.problems(),
+                Message::ToggleContextPage(ContextPage::Problems),
+            )
+            .title(fl!("problems")),
             ContextPage::ProjectSearch => context_drawer::context_drawer(
                 self.project_search(),
                 Message::ToggleContextPage(ContextPage::ProjectSearch),
             )
             .title(fl!("project-search")),
-            ContextPage::Settings => context_drawer::context_drawer(
-                self.settings(),
-                Message::ToggleContextPage(ContextPage::Settings),
+            ContextPage::References => context_drawer::context_drawer(
+                self.references(),
+                Message::ToggleContextPage(ContextPage::References),
+            )
+            .title(fl!("references")),
+            ContextPage::UndoHistory => context_drawer::context_drawer(
+                self.undo_history(),
+                Message::ToggleContextPage(ContextPage::UndoHistory),
             )
-            .title(fl!("settings")),
+            .title(fl!("undo-history")),
         })
     }
 
@@ -2983,282 +11686,58 @@ impl Application for App {
             &self.config_state,
             &self.key_binds,
             &self.projects,
+            match self.active_tab() {
+                Some(Tab::Editor(tab)) => Some(tab.syntax_name()),
+                _ => None,
+            },
+            self.macro_recording.is_some(),
+            self.task_output_shown,
+            &self.spell_dictionaries,
+            &match self.active_tab() {
+                Some(Tab::Editor(tab)) if !tab.spell_languages.is_empty() => {
+                    tab.spell_languages.clone()
+                }
+                _ => self.default_spell_languages(),
+            },
         )]
     }
 
     fn view(&self) -> Element<'_, Message> {
-        let cosmic_theme::Spacing {
-            space_none,
-            space_xxs,
-            ..
-        } = self.core().system_theme().cosmic().spacing;
-
-        let mut tab_column = widget::column::with_capacity(3).padding([space_none, space_xxs]);
-
-        tab_column = tab_column.push(
-            widget::row::with_capacity(2)
-                .align_y(Alignment::Center)
-                .push(
-                    widget::tab_bar::horizontal(&self.tab_model)
-                        .button_height(32)
-                        .button_spacing(space_xxs)
-                        .close_icon(icon_cache_get("window-close-symbolic", 16))
-                        //TODO: this causes issues with small window sizes .minimum_button_width(240)
-                        .on_activate(Message::TabActivate)
-                        .on_close(Message::TabClose)
-                        .width(Length::Shrink),
-                )
-                .push(
-                    button::custom(icon_cache_get("list-add-symbolic", 16))
-                        .on_press(Message::NewFile)
-                        .padding(space_xxs)
-                        .class(style::Button::Icon),
-                ),
-        );
-
-        let tab_id = self.tab_model.active();
-        match self.tab_model.data::<Tab>(tab_id) {
-            Some(Tab::Editor(tab)) => {
-                let mut text_box = text_box(&tab.editor, self.config.metrics(tab.zoom_adj()))
-                    .id(self.text_box_id.clone())
-                    .on_focus(Message::FindFocused(false))
-                    .on_auto_scroll(Message::AutoScroll)
-                    .on_changed(Message::TabChanged(tab_id))
-                    .has_context_menu(tab.context_menu.is_some())
-                    .on_context_menu(move |position_opt| {
-                        Message::TabContextMenu(tab_id, position_opt)
-                    });
-                if self.config.highlight_current_line {
-                    text_box = text_box.highlight_current_line();
-                }
-                if self.config.line_numbers {
-                    text_box = text_box.line_numbers();
-                }
-                let mut popover = widget::popover(text_box);
-                if let Some(point) = tab.context_menu {
-                    popover = popover
-                        .popup(menu::context_menu(&self.key_binds, tab_id))
-                        .position(widget::popover::Position::Point(point));
-                }
-                tab_column = tab_column.push(popover);
-                if self.config.vim_bindings {
-                    let status = {
-                        let editor = tab.editor.lock().unwrap();
-                        let parser = editor.parser();
-                        match &parser.mode {
-                            ViMode::Normal => {
-                                format!("{}", parser.cmd)
-                            }
-                            ViMode::Insert => "-- INSERT --".to_string(),
-                            ViMode::Extra(extra) => {
-                                format!("{}{}", parser.cmd, extra)
-                            }
-                            ViMode::Replace => "-- REPLACE --".to_string(),
-                            ViMode::Visual => {
-                                format!("-- VISUAL -- {}", parser.cmd)
-                            }
-                            ViMode::VisualLine => {
-                                format!("-- VISUAL LINE -- {}", parser.cmd)
-                            }
-                            ViMode::Command { value } => {
-                                format!(":{value}|")
-                            }
-                            ViMode::Search { value, forwards } => {
-                                if *forwards {
-                                    format!("/{value}|")
-                                } else {
-                                    format!("?{value}|")
-                                }
-                            }
-                        }
-                    };
-                    tab_column = tab_column.push(widget::text(status).font(Font::MONOSPACE));
-                }
-            }
-            Some(Tab::GitDiff(tab)) => {
-                let mut diff_widget = widget::column::with_capacity(tab.diff.hunks.len());
-                for hunk in tab.diff.hunks.iter() {
-                    let mut hunk_widget = widget::column::with_capacity(hunk.lines.len());
-                    for line in hunk.lines.iter() {
-                        let line_widget = match line {
-                            GitDiffLine::Context {
-                                old_line,
-                                new_line,
-                                text,
-                            } => widget::container(widget::text::monotext(format!(
-                                "{:4} {:4}   {}",
-                                old_line, new_line, text
-                            ))),
-                            GitDiffLine::Added { new_line, text } => {
-                                widget::container(widget::text::monotext(format!(
-                                    "{:4} {:4} + {}",
-                                    "", new_line, text
-                                )))
-                                .style(|_theme| {
-                                    //TODO: theme this color
-                                    widget::container::Style {
-                                        background: Some(Background::Color(Color::from_rgb8(
-                                            0x00, 0x40, 0x00,
-                                        ))),
-                                        ..Default::default()
-                                    }
-                                })
-                            }
-                            GitDiffLine::Deleted { old_line, text } => {
-                                widget::container(widget::text::monotext(format!(
-                                    "{:4} {:4} - {}",
-                                    old_line, "", text
-                                )))
-                                .style(|_theme| {
-                                    //TODO: theme this color
-                                    widget::container::Style {
-                                        background: Some(Background::Color(Color::from_rgb8(
-                                            0x40, 0x00, 0x00,
-                                        ))),
-                                        ..Default::default()
-                                    }
-                                })
-                            }
-                        };
-                        hunk_widget = hunk_widget.push(line_widget.width(Length::Fill));
-                    }
-                    diff_widget = diff_widget.push(hunk_widget);
-                }
-                tab_column = tab_column.push(widget::scrollable(
-                    widget::layer_container(diff_widget).layer(cosmic_theme::Layer::Primary),
-                ));
-            }
-            None => {}
+        match self.focused_window_opt {
+            Some(_) => self.window_view(&self.main_window),
+            None => self.editor_view(),
         }
+    }
 
-        if let Some(FindField {
-            replace,
-            has_focus: _,
-        }) = &self.find_opt
-        {
-            let find_input =
-                widget::text_input::text_input(fl!("find-placeholder"), &self.find_search_value)
-                    .id(self.find_search_id.clone())
-                    .on_input(Message::FindSearchValueChanged)
-                    .on_submit(|_| {
-                        if self.modifiers.contains(Modifiers::SHIFT) {
-                            Message::FindPrevious
-                        } else {
-                            Message::FindNext
-                        }
-                    })
-                    .on_focus(Message::FindFocused(true))
-                    .width(Length::Fixed(320.0))
-                    .trailing_icon(
-                        button::custom(icon_cache_get("edit-clear-symbolic", 16))
-                            .on_press(Message::FindSearchValueChanged(String::new()))
-                            .class(style::Button::Icon)
-                            .into(),
-                    );
-            let find_widget = widget::row::with_children(vec![
-                find_input.into(),
-                widget::tooltip(
-                    button::custom(icon_cache_get("go-up-symbolic", 16))
-                        .on_press(Message::FindPrevious)
-                        .padding(space_xxs)
-                        .class(style::Button::Icon),
-                    widget::text::body(fl!("find-previous")),
-                    widget::tooltip::Position::Top,
-                )
-                .into(),
-                widget::tooltip(
-                    button::custom(icon_cache_get("go-down-symbolic", 16))
-                        .on_press(Message::FindNext)
-                        .padding(space_xxs)
-                        .class(style::Button::Icon),
-                    widget::text::body(fl!("find-next")),
-                    widget::tooltip::Position::Top,
-                )
-                .into(),
-                widget::horizontal_space().into(),
-                button::custom(icon_cache_get("window-close-symbolic", 16))
-                    .on_press(Message::Find(None))
-                    .padding(space_xxs)
-                    .class(style::Button::Icon)
-                    .into(),
-            ])
-            .align_y(Alignment::Center)
-            .padding(space_xxs)
-            .spacing(space_xxs);
-
-            let mut column = widget::column::with_capacity(3).push(find_widget);
-            if *replace {
-                let replace_input = widget::text_input::text_input(
-                    fl!("replace-placeholder"),
-                    &self.find_replace_value,
-                )
-                .id(self.find_replace_id.clone())
-                .on_input(Message::FindReplaceValueChanged)
-                .on_submit(|_| Message::FindReplace)
-                .width(Length::Fixed(320.0))
-                .trailing_icon(
-                    button::custom(icon_cache_get("edit-clear-symbolic", 16))
-                        .on_press(Message::FindReplaceValueChanged(String::new()))
-                        .class(style::Button::Icon)
+    fn view_window(&self, window_id: window::Id) -> Element<'_, Message> {
+        if Some(window_id) == self.settings_window_opt {
+            return self.settings_window();
+        }
+        if let Some(window) = self.windows.get(&window_id) {
+            let content = if self.focused_window_opt == Some(window_id) {
+                let content = self.editor_view();
+                match self.dialog_view() {
+                    Some(dialog) => widget::popover(content)
+                        .modal(true)
+                        .popup(dialog)
+                        .position(widget::popover::Position::Center)
                         .into(),
-                );
-                let replace_widget = widget::row::with_children(vec![
-                    replace_input.into(),
-                    widget::tooltip(
-                        button::custom(icon_cache_get("replace-symbolic", 16))
-                            .on_press(Message::FindReplace)
-                            .padding(space_xxs)
-                            .class(style::Button::Icon),
-                        widget::text::body(fl!("replace")),
-                        widget::tooltip::Position::Top,
-                    )
-                    .into(),
-                    widget::tooltip(
-                        button::custom(icon_cache_get("replace-all-symbolic", 16))
-                            .on_press(Message::FindReplaceAll)
-                            .padding(space_xxs)
-                            .class(style::Button::Icon),
-                        widget::text::body(fl!("replace-all")),
-                        widget::tooltip::Position::Top,
-                    )
-                    .into(),
-                ])
-                .align_y(Alignment::Center)
-                .padding(space_xxs)
-                .spacing(space_xxs);
-
-                column = column.push(replace_widget);
+                    None => content,
+                }
+            } else {
+                self.window_view(window)
+            };
+            // Other windows have no header bar, so the menu bar is shown above the tabs
+            let mut column = widget::column::with_capacity(2);
+            if !self.zen_mode {
+                column = column.push(widget::row::with_children(self.header_start()));
             }
-
-            column = column.push(
-                widget::row::with_children(vec![
-                    widget::checkbox(fl!("case-sensitive"), self.config.find_case_sensitive)
-                        .on_toggle(Message::FindCaseSensitive)
-                        .into(),
-                    widget::checkbox(fl!("use-regex"), self.config.find_use_regex)
-                        .on_toggle(Message::FindUseRegex)
-                        .into(),
-                    widget::checkbox(fl!("wrap-around"), self.config.find_wrap_around)
-                        .on_toggle(Message::FindWrapAround)
-                        .into(),
-                ])
-                .align_y(Alignment::Center)
-                .padding(space_xxs)
-                .spacing(space_xxs),
-            );
-
-            tab_column = tab_column
-                .push(widget::layer_container(column).layer(cosmic_theme::Layer::Primary));
+            return widget::container(column.push(content))
+                .width(Length::Fill)
+                .height(Length::Fill)
+                .class(theme::Container::Background)
+                .into();
         }
-
-        let content: Element<_> = tab_column.into();
-
-        // Uncomment to debug layout:
-        //content.explain(cosmic::iced::Color::WHITE)
-        content
-    }
-
-    fn view_window(&self, window_id: window::Id) -> Element<'_, Message> {
         match &self.dialog_opt {
             Some(dialog) => dialog.view(window_id),
             None => widget::text("Unknown window ID").into(),
@@ -3270,6 +11749,14 @@ impl Application for App {
         struct ConfigSubscription;
         struct ConfigStateSubscription;
         struct ThemeSubscription;
+        struct LargeFileSubscription;
+        struct HighlightSubscription;
+        struct LspSubscription;
+        #[cfg(unix)]
+        struct InstanceSubscription;
+        #[cfg(unix)]
+        struct DbusSubscription;
+        struct TaskSubscription;
 
         let mut subscriptions = vec![
             event::listen_with(|event, status, window_id| match event {
@@ -3391,6 +11878,152 @@ impl Application for App {
             },
         ];
 
+        subscriptions.push(Subscription::run_with_id(
+            TypeId::of::<HighlightSubscription>(),
+            stream::channel(100, |mut output| async move {
+                let (worker, receiver) = highlight::worker();
+                if let Err(err) = output.send(Message::HighlightWorker(worker)).await {
+                    log::warn!("failed to send highlight worker: {:?}", err);
+                }
+
+                let res = tokio::task::spawn_blocking(move || {
+                    highlight::run(receiver, |entity, lines| {
+                        futures::executor::block_on(
+                            output.send(Message::HighlightSpans(entity, lines)),
+                        )
+                        .is_ok()
+                    });
+                })
+                .await;
+                if let Err(err) = res {
+                    log::error!("highlight worker failed: {}", err);
+                }
+            }),
+        ));
+
+        subscriptions.push(Subscription::run_with_id(
+            TypeId::of::<LspSubscription>(),
+            stream::channel(100, |mut output| async move {
+                let (worker, receiver) = lsp::worker();
+                if let Err(err) = output.send(Message::LspWorker(worker.clone())).await {
+                    log::warn!("failed to send language server worker: {:?}", err);
+                }
+
+                let res = tokio::task::spawn_blocking(move || {
+                    lsp::run(worker, receiver, |event| {
+                        futures::executor::block_on(output.send(Message::Lsp(event))).is_ok()
+                    });
+                })
+                .await;
+                if let Err(err) = res {
+                    log::error!("language server worker failed: {}", err);
+                }
+            }),
+        ));
+
+        #[cfg(unix)]
+        subscriptions.push(Subscription::run_with_id(
+            TypeId::of::<InstanceSubscription>(),
+            stream::channel(16, |mut output| async move {
+                let res = tokio::task::spawn_blocking(move || {
+                    instance::listen(|args, waiter_opt| {
+                        if let Err(err) = futures::executor::block_on(
+                            output.send(Message::OpenArgs(args, waiter_opt)),
+                        ) {
+                            log::warn!("failed to send arguments: {:?}", err);
+                        }
+                    });
+                })
+                .await;
+                if let Err(err) = res {
+                    log::error!("single instance listener failed: {}", err);
+                }
+            }),
+        ));
+
+        #[cfg(unix)]
+        subscriptions.push(Subscription::run_with_id(
+            TypeId::of::<DbusSubscription>(),
+            stream::channel(16, |output| async move {
+                match dbus::serve(output).await {
+                    Ok(_connection) => {
+                        // Requests are handled while the connection is kept
+                        futures::future::pending::<()>().await;
+                    }
+                    Err(err) => {
+                        // Only the first window gets the name
+                        log::info!("failed to serve D-Bus interface: {}", err);
+                    }
+                }
+            }),
+        ));
+
+        subscriptions.push(
+            iced::time::every(time::Duration::from_secs(recovery::INTERVAL_SECS))
+                .map(|_| Message::RecoverySnapshot),
+        );
+
+        if let Some(auto_save_secs) = self.config.auto_save_secs {
+            if !self.auto_save_pending.is_empty() {
+                subscriptions.push(
+                    iced::time::every(time::Duration::from_secs(auto_save_secs.max(1)))
+                        .map(|_| Message::AutoSave),
+                );
+            }
+        }
+
+        for entity in self.tab_model.iter() {
+            let Some(Tab::Editor(tab)) = self.tab_model.data::<Tab>(entity) else {
+                continue;
+            };
+            let Some(path) = tab.path_opt.clone().filter(|_| tab.loading_opt.is_some()) else {
+                continue;
+            };
+            let encoding = tab.encoding;
+            // Reloading the file during loading starts a new subscription
+            let request = tab.loading_request;
+            subscriptions.push(Subscription::run_with_id(
+                (TypeId::of::<LargeFileSubscription>(), entity, request),
+                stream::channel(4, move |output| async move {
+                    let res = tokio::task::spawn_blocking(move || {
+                        let mut output = output;
+                        let res = tab::read_chunks(&path, encoding, |lines, progress| {
+                            // Stops when the tab is closed
+                            futures::executor::block_on(
+                                output
+                                    .send(Message::TabLoadChunk(entity, request, lines, progress)),
+                            )
+                            .is_ok()
+                        });
+                        if let Err(err) = res {
+                            log::error!("failed to read {:?}: {}", path, err);
+                        }
+                        let _ = futures::executor::block_on(
+                            output.send(Message::TabLoadFinished(entity, request)),
+                        );
+                    })
+                    .await;
+                    if let Err(err) = res {
+                        log::error!("failed to load large file: {}", err);
+                    }
+                }),
+            ));
+        }
+
+        if let Some((task, dir)) = self.task_running_opt.clone() {
+            subscriptions.push(Subscription::run_with_id(
+                (TypeId::of::<TaskSubscription>(), self.task_session),
+                stream::channel(100, move |output| async move {
+                    let mut errors = output.clone();
+                    if let Err(err) = tasks::run(&task, &dir, output).await {
+                        log::error!("failed to run task {:?} in {:?}: {}", task.name, dir, err);
+                        let _ = errors.send(Message::TaskOutput(err.to_string())).await;
+                        let _ = errors.send(Message::TaskFinished(None)).await;
+                    }
+                }),
+            ));
+        }
+
         if let Some(auto_scroll) = self.auto_scroll {
             subscriptions.push(
                 iced::time::every(time::Duration::from_millis(10))
@@ -0,0 +1,597 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::{
+    Element, cosmic_theme,
+    iced::{
+        Background, Border, Color, Length, Padding,
+        font::{self, Font},
+        widget::{rich_text, span, text::Span},
+    },
+    theme, widget,
+};
+use std::{mem, path::Path};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::{FontStyle, Theme},
+    parsing::SyntaxSet,
+    util::LinesWithEndings,
+};
+
+use crate::{Message, remote};
+
+// Text sizes of headings, by level
+const HEADING_SIZES: [f32; 6] = [28.0, 24.0, 20.0, 18.0, 16.0, 14.0];
+
+/// Text with one style inside of a block
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Run {
+    pub text: String,
+    pub bold: bool,
+    pub italic: bool,
+    pub code: bool,
+    pub link_opt: Option<String>,
+    /// Color of highlighted code, other text has the color of the theme
+    pub color_opt: Option<Color>,
+}
+
+impl Run {
+    // Same style, so that text can be added to this run instead of starting another
+    fn same_style(&self, other: &Self) -> bool {
+        self.bold == other.bold
+            && self.italic == other.italic
+            && self.code == other.code
+            && self.link_opt == other.link_opt
+            && self.color_opt == other.color_opt
+    }
+}
+
+// Add the text that has the style of `style` as a run
+fn flush(runs: &mut Vec<Run>, current: &mut String, style: &Run) {
+    push_run(
+        runs,
+        Run {
+            text: mem::take(current),
+            ..style.clone()
+        },
+    );
+}
+
+// Add text to the last run if it has the same style
+fn push_run(runs: &mut Vec<Run>, run: Run) {
+    if run.text.is_empty() {
+        return;
+    }
+    match runs.last_mut() {
+        Some(last) if last.same_style(&run) => last.text.push_str(&run.text),
+        _ => runs.push(run),
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum BlockKind {
+    Heading(usize, Vec<Run>),
+    Paragraph(Vec<Run>),
+    /// List item with the number of lists it is inside of and its bullet or number
+    Item(usize, String, Vec<Run>),
+    Quote(Vec<Run>),
+    /// Lines of a fenced code block, with the background of the syntax theme
+    Code(Vec<Vec<Run>>, Option<Color>),
+    /// Image with its alt text and source, which is relative to the document
+    Image(String, String),
+    Rule,
+}
+
+/// A part of the preview, with the line of the document it starts on
+#[derive(Clone, Debug)]
+pub struct Block {
+    pub line: usize,
+    pub kind: BlockKind,
+}
+
+// Level of an ATX heading like `## Heading`, with its text
+fn heading(trimmed: &str) -> Option<(usize, &str)> {
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    let rest = &trimmed[level..];
+    if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+        Some((level, rest.trim().trim_end_matches('#').trim_end()))
+    } else {
+        None
+    }
+}
+
+// Lines like `---`, `***`, or `_ _ _`
+fn is_rule(trimmed: &str) -> bool {
+    let Some(c) = trimmed
+        .chars()
+        .next()
+        .filter(|c| matches!(c, '-' | '*' | '_'))
+    else {
+        return false;
+    };
+    trimmed.chars().filter(|x| *x == c).count() >= 3 && trimmed.chars().all(|x| x == c || x == ' ')
+}
+
+// Depth, marker, and text of a list item like `- item` or `1. item`
+fn item(line: &str) -> Option<(usize, String, &str)> {
+    let indent = line.len() - line.trim_start().len();
+    let trimmed = line.trim_start();
+    if let Some(rest) = trimmed
+        .strip_prefix("- ")
+        .or_else(|| trimmed.strip_prefix("* "))
+        .or_else(|| trimmed.strip_prefix("+ "))
+    {
+        return Some((indent / 2, "\u{2022}".to_string(), rest));
+    }
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    let rest = &trimmed[digits..];
+    if (1..=9).contains(&digits)
+        && let Some(rest) = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") "))
+    {
+        return Some((indent / 2, format!("{}.", &trimmed[..digits]), rest));
+    }
+    None
+}
+
+// Fence of a code block, ``` or ~~~ with the language after it
+fn fence(trimmed: &str) -> Option<(&str, &str)> {
+    ["```", "~~~"]
+        .into_iter()
+        .find(|fence| trimmed.starts_with(fence))
+        .map(|fence| (fence, trimmed.trim_start_matches(&fence[..1]).trim()))
+}
+
+// Lines that start a block of their own instead of continuing a paragraph
+fn starts_block(line: &str) -> bool {
+    let trimmed = line.trim();
+    trimmed.is_empty()
+        || fence(trimmed).is_some()
+        || heading(trimmed).is_some()
+        || is_rule(trimmed)
+        || item(line).is_some()
+        || trimmed.starts_with('>')
+}
+
+// An image that is all of a paragraph, like `![alt](source)`
+fn image(text: &str) -> Option<(String, String)> {
+    let rest = text.strip_prefix("![")?;
+    let (alt, rest) = rest.split_once("](")?;
+    let source = rest.strip_suffix(')')?;
+    if source.contains(')') {
+        return None;
+    }
+    Some((alt.to_string(), destination(source)))
+}
+
+// Destination of a link without its title, like `url` in `url "title"`
+fn destination(text: &str) -> String {
+    let text = text.trim();
+    let url = text.split_whitespace().next().unwrap_or(text);
+    url.trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+}
+
+// Split text into runs by emphasis, code spans, and links
+fn inline(text: &str) -> Vec<Run> {
+    let mut runs = Vec::new();
+    let mut style = Run::default();
+    let mut current = String::new();
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        let previous_opt = text[..i].chars().next_back();
+        let next_opt = rest[c.len_utf8()..].chars().next();
+
+        if c == '\\'
+            && let Some(next) = next_opt.filter(char::is_ascii_punctuation)
+        {
+            current.push(next);
+            i += 2;
+            continue;
+        }
+
+        if c == '`' {
+            let ticks = rest.chars().take_while(|x| *x == '`').count();
+            let marker = &rest[..ticks];
+            if let Some(end) = rest[ticks..].find(marker) {
+                flush(&mut runs, &mut current, &style);
+                push_run(
+                    &mut runs,
+                    Run {
+                        text: rest[ticks..ticks + end].trim().to_string(),
+                        code: true,
+                        ..style.clone()
+                    },
+                );
+                i += ticks + end + ticks;
+                continue;
+            }
+        }
+
+        if rest.starts_with("**") || rest.starts_with("__") {
+            flush(&mut runs, &mut current, &style);
+            style.bold = !style.bold;
+            i += 2;
+            continue;
+        }
+
+        // Underscores inside of words, like snake_case, are not emphasis
+        if c == '*'
+            || (c == '_'
+                && !(previous_opt.is_some_and(char::is_alphanumeric)
+                    && next_opt.is_some_and(char::is_alphanumeric)))
+        {
+            flush(&mut runs, &mut current, &style);
+            style.italic = !style.italic;
+            i += 1;
+            continue;
+        }
+
+        // Links and images in the middle of text, images are shown as their alt text
+        let is_image = rest.starts_with("![");
+        if (c == '[' || is_image)
+            && let Some(label_start) = rest.find('[').map(|start| start + 1)
+            && let Some(label_end) = rest[label_start..].find("](").map(|end| label_start + end)
+            && let Some(url_end) = rest[label_end + 2..]
+                .find(')')
+                .map(|end| label_end + 2 + end)
+        {
+            flush(&mut runs, &mut current, &style);
+            let label = &rest[label_start..label_end];
+            let url = destination(&rest[label_end + 2..url_end]);
+            if is_image {
+                push_run(
+                    &mut runs,
+                    Run {
+                        text: label.to_string(),
+                        italic: true,
+                        ..style.clone()
+                    },
+                );
+            } else {
+                for run in inline(label) {
+                    push_run(
+                        &mut runs,
+                        Run {
+                            bold: run.bold || style.bold,
+                            italic: run.italic || style.italic,
+                            link_opt: Some(url.clone()),
+                            ..run
+                        },
+                    );
+                }
+            }
+            i += url_end + 1;
+            continue;
+        }
+
+        // Links like <https://example.com>
+        if c == '<'
+            && let Some(end) = rest.find('>')
+            && remote::is_uri(&rest[1..end])
+        {
+            flush(&mut runs, &mut current, &style);
+            let url = &rest[1..end];
+            push_run(
+                &mut runs,
+                Run {
+                    text: url.to_string(),
+                    link_opt: Some(url.to_string()),
+                    ..style.clone()
+                },
+            );
+            i += end + 1;
+            continue;
+        }
+
+        current.push(c);
+        i += c.len_utf8();
+    }
+    push_run(
+        &mut runs,
+        Run {
+            text: current,
+            ..style
+        },
+    );
+    runs
+}
+
+// Runs of each line of a code block, colored by the syntax of its language
+fn code(lines: &[&str], language: &str, highlight_opt: Option<(&SyntaxSet, &Theme)>) -> BlockKind {
+    let text: String = lines.iter().map(|line| format!("{line}\n")).collect();
+    let plain = || {
+        lines
+            .iter()
+            .map(|line| {
+                vec![Run {
+                    text: line.to_string(),
+                    code: true,
+                    ..Default::default()
+                }]
+            })
+            .collect()
+    };
+    let Some((syntax_set, theme)) = highlight_opt else {
+        return BlockKind::Code(plain(), None);
+    };
+    let color = |color: syntect::highlighting::Color| {
+        Color::from_rgba8(color.r, color.g, color.b, f32::from(color.a) / 255.0)
+    };
+    let background_opt = theme.settings.background.map(color);
+    let Some(syntax) = (!language.is_empty())
+        .then(|| syntax_set.find_syntax_by_token(language))
+        .flatten()
+    else {
+        return BlockKind::Code(plain(), background_opt);
+    };
+
+    let mut highlighter = HighlightLines::new(syntax, theme);
+    let mut code_lines = Vec::with_capacity(lines.len());
+    for line in LinesWithEndings::from(&text) {
+        match highlighter.highlight_line(line, syntax_set) {
+            Ok(ranges) => {
+                let mut runs = Vec::new();
+                for (style, text) in ranges {
+                    push_run(
+                        &mut runs,
+                        Run {
+                            text: text.trim_end_matches('\n').to_string(),
+                            bold: style.font_style.contains(FontStyle::BOLD),
+                            italic: style.font_style.contains(FontStyle::ITALIC),
+                            code: true,
+                            link_opt: None,
+                            color_opt: Some(color(style.foreground)),
+                        },
+                    );
+                }
+                code_lines.push(runs);
+            }
+            Err(err) => {
+                log::warn!("failed to highlight {} code block: {}", language, err);
+                return BlockKind::Code(plain(), background_opt);
+            }
+        }
+    }
+    BlockKind::Code(code_lines, background_opt)
+}
+
+/// Documents of these syntaxes can be previewed
+pub fn is_markdown(syntax_name: &str) -> bool {
+    matches!(syntax_name, "Markdown" | "MultiMarkdown")
+}
+
+/// Split a Markdown document into the blocks of its preview
+pub fn parse(text: &str, highlight_opt: Option<(&SyntaxSet, &Theme)>) -> Vec<Block> {
+    let lines: Vec<&str> = text.lines().collect();
+    let mut blocks = Vec::new();
+    let mut line_i = 0;
+    while line_i < lines.len() {
+        let line = lines[line_i];
+        let trimmed = line.trim();
+        let start = line_i;
+        line_i += 1;
+
+        let kind = if trimmed.is_empty() {
+            continue;
+        } else if let Some((marker, language)) = fence(trimmed) {
+            let end = lines[line_i..]
+                .iter()
+                .position(|line| line.trim().starts_with(marker))
+                .map_or(lines.len(), |end| line_i + end);
+            let kind = code(&lines[line_i..end], language, highlight_opt);
+            // Code blocks that are not closed go to the end of the document
+            line_i = end + 1;
+            kind
+        } else if let Some((level, text)) = heading(trimmed) {
+            BlockKind::Heading(level, inline(text))
+        } else if is_rule(trimmed) {
+            BlockKind::Rule
+        } else if let Some((depth, marker, text)) = item(line) {
+            let mut text = text.trim().to_string();
+            while line_i < lines.len() && !starts_block(lines[line_i]) {
+                text.push(' ');
+                text.push_str(lines[line_i].trim());
+                line_i += 1;
+            }
+            BlockKind::Item(depth, marker, inline(&text))
+        } else if trimmed.starts_with('>') {
+            let mut text = String::new();
+            line_i = start;
+            while let Some(quoted) = lines.get(line_i).and_then(|line| {
+                let trimmed = line.trim();
+                trimmed
+                    .strip_prefix('>')
+                    .or_else(|| (!text.is_empty() && !starts_block(line)).then_some(trimmed))
+            }) {
+                if !text.is_empty() {
+                    text.push(' ');
+                }
+                text.push_str(quoted.trim());
+                line_i += 1;
+            }
+            BlockKind::Quote(inline(&text))
+        } else {
+            let mut text = trimmed.to_string();
+            let mut level_opt = None;
+            while let Some(next) = lines.get(line_i) {
+                // A paragraph can be underlined to make it a heading
+                let next_trimmed = next.trim();
+                if !next_trimmed.is_empty()
+                    && (next_trimmed.chars().all(|c| c == '=')
+                        || next_trimmed.chars().all(|c| c == '-'))
+                {
+                    level_opt = Some(if next_trimmed.starts_with('=') { 1 } else { 2 });
+                    line_i += 1;
+                    break;
+                }
+                if starts_block(next) {
+                    break;
+                }
+                text.push(' ');
+                text.push_str(next_trimmed);
+                line_i += 1;
+            }
+            match (level_opt, image(&text)) {
+                (Some(level), _) => BlockKind::Heading(level, inline(&text)),
+                (None, Some((alt, source))) => BlockKind::Image(alt, source),
+                (None, None) => BlockKind::Paragraph(inline(&text)),
+            }
+        };
+        blocks.push(Block { line: start, kind });
+    }
+    blocks
+}
+
+/// Part of the preview to scroll to for the first visible line of the document, from 0 to 1
+pub fn scroll_offset(blocks: &[Block], line: usize) -> f32 {
+    let Some(block_i) = blocks.iter().rposition(|block| block.line <= line) else {
+        return 0.0;
+    };
+    if blocks.len() < 2 {
+        return 0.0;
+    }
+    // Lines between the start of two blocks scroll part of the way between them
+    let start = blocks[block_i].line;
+    let fraction = match blocks.get(block_i + 1) {
+        Some(next) if next.line > start => (line - start) as f32 / (next.line - start) as f32,
+        _ => 0.0,
+    };
+    ((block_i as f32 + fraction) / (blocks.len() - 1) as f32).min(1.0)
+}
+
+fn runs_view<'a>(runs: &'a [Run], size_opt: Option<f32>) -> Element<'a, Message> {
+    let link_color = Color::from(theme::active().cosmic().accent.base);
+    let spans: Vec<Span<'a, String, Font>> = runs
+        .iter()
+        .map(|run| {
+            let mut font = if run.code {
+                Font::MONOSPACE
+            } else {
+                Font::default()
+            };
+            if run.bold {
+                font.weight = font::Weight::Bold;
+            }
+            if run.italic {
+                font.style = font::Style::Italic;
+            }
+            let mut span = span(run.text.as_str())
+                .font(font)
+                .color_maybe(run.color_opt);
+            if let Some(size) = size_opt {
+                span = span.size(size);
+            }
+            if let Some(url) = &run.link_opt {
+                span = span.link(url.clone()).color(link_color).underline(true);
+            }
+            span
+        })
+        .collect();
+    rich_text(spans)
+        .on_link_click(Message::LaunchUrl)
+        .width(Length::Fill)
+        .into()
+}
+
+/// Render the blocks with the fonts and colors of the theme, images are found next to the
+/// document
+pub fn view<'a>(blocks: &'a [Block], dir_opt: Option<&Path>) -> Element<'a, Message> {
+    let cosmic_theme::Spacing {
+        space_xxs,
+        space_xs,
+        space_s,
+        space_m,
+        ..
+    } = theme::active().cosmic().spacing;
+
+    let mut column = widget::column::with_capacity(blocks.len())
+        .padding(space_m)
+        .spacing(space_s);
+    for block in blocks {
+        let element: Element<'a, Message> = match &block.kind {
+            BlockKind::Heading(level, runs) => {
+                runs_view(runs, HEADING_SIZES.get(level - 1).copied())
+            }
+            BlockKind::Paragraph(runs) => runs_view(runs, None),
+            BlockKind::Item(depth, marker, runs) => widget::row::with_children(vec![
+                widget::text::body(marker.as_str()).into(),
+                runs_view(runs, None),
+            ])
+            .padding(Padding {
+                left: f32::from(space_m) * (*depth as f32),
+                ..Padding::ZERO
+            })
+            .spacing(space_xs)
+            .into(),
+            BlockKind::Quote(runs) => widget::container(runs_view(runs, None))
+                .padding([space_xs, space_s])
+                .style(|theme| {
+                    let cosmic = theme.cosmic();
+                    widget::container::Style {
+                        text_color: Some(cosmic.palette.neutral_7.into()),
+                        background: Some(Background::Color(
+                            cosmic.background.component.base.into(),
+                        )),
+                        border: Border {
+                            radius: cosmic.radius_s().into(),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    }
+                })
+                .width(Length::Fill)
+                .into(),
+            BlockKind::Code(lines, background_opt) => {
+                let background_opt = *background_opt;
+                widget::container(
+                    widget::column::with_children(
+                        lines.iter().map(|runs| runs_view(runs, None)).collect(),
+                    )
+                    .width(Length::Fill),
+                )
+                .padding(space_xs)
+                .style(move |theme| {
+                    let cosmic = theme.cosmic();
+                    widget::container::Style {
+                        background: Some(Background::Color(
+                            background_opt.unwrap_or(cosmic.background.component.base.into()),
+                        )),
+                        border: Border {
+                            radius: cosmic.radius_s().into(),
+                            ..Default::default()
+                        },
+                        ..Default::default()
+                    }
+                })
+                .into()
+            }
+            BlockKind::Image(alt, source) => match dir_opt {
+                // Images on the web are shown as links to them
+                Some(dir) if !remote::is_uri(source) => {
+                    widget::image(widget::image::Handle::from_path(dir.join(source)))
+                        .width(Length::Shrink)
+                        .into()
+                }
+                _ => image_link(alt, source),
+            },
+            BlockKind::Rule => widget::divider::horizontal::default().into(),
+        };
+        column = column.push(element);
+    }
+    widget::container(column)
+        .padding([space_xxs, 0])
+        .width(Length::Fill)
+        .into()
+}
+
+// Link to an image that is not loaded
+fn image_link<'a>(alt: &'a str, source: &'a str) -> Element<'a, Message> {
+    let label = if alt.is_empty() { source } else { alt };
+    rich_text([span(label)
+        .link(source.to_string())
+        .color(Color::from(theme::active().cosmic().accent.base))
+        .underline(true)])
+    .on_link_click(Message::LaunchUrl)
+    .into()
+}
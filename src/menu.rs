@@ -5,7 +5,7 @@ use cosmic::widget::menu::key_bind::KeyBind;
 use cosmic::{
     Element,
     app::Core,
-    iced::{Background, Length, advanced::widget::text::Style as TextStyle, widget::column},
+    iced::{Background, Length, advanced::widget::text::Style as TextStyle},
     iced_core::Border,
     theme,
     widget::{
@@ -16,14 +16,112 @@ use cosmic::{
 };
 use std::{collections::HashMap, path::PathBuf, sync::LazyLock};
 
-use crate::{Action, Config, ConfigState, Message, fl};
+use crate::{
+    Action, Config, ConfigState, Message, NavMenuAction, TabMenuAction, fl, project::ProjectNode,
+    spell::Misspelling,
+};
+
+// Suggestions for a misspelled word shown in the context menu
+const SUGGESTIONS_MAX: usize = 5;
 
 static MENU_ID: LazyLock<cosmic::widget::Id> =
     LazyLock::new(|| cosmic::widget::Id::new("responsive-menu"));
 
+pub fn nav_context_menu(
+    id: widget::nav_bar::Id,
+    node: &ProjectNode,
+) -> Vec<widget::menu::Tree<cosmic::Action<Message>>> {
+    let mut items = vec![
+        MenuItem::Button(fl!("new-file"), None, NavMenuAction::NewFile(id)),
+        MenuItem::Button(fl!("new-folder"), None, NavMenuAction::NewFolder(id)),
+    ];
+    // Project roots are closed instead of changed
+    if !matches!(node, ProjectNode::Folder { root: true, .. }) {
+        items.push(MenuItem::Divider);
+        items.push(MenuItem::Button(
+            fl!("rename"),
+            None,
+            NavMenuAction::Rename(id),
+        ));
+        items.push(MenuItem::Button(
+            fl!("duplicate"),
+            None,
+            NavMenuAction::Duplicate(id),
+        ));
+        items.push(MenuItem::Divider);
+        items.push(MenuItem::Button(
+            fl!("move-to-trash"),
+            None,
+            NavMenuAction::MoveToTrash(id),
+        ));
+    }
+    widget::menu::items(&HashMap::new(), items)
+}
+
+pub fn tab_context_menu(
+    key_binds: &HashMap<KeyBind, Action>,
+    entity: segmented_button::Entity,
+    pinned: bool,
+) -> Vec<widget::menu::Tree<Message>> {
+    // Show the shortcuts of the actions, which apply to the active tab when used
+    let tab_key_binds: HashMap<KeyBind, TabMenuAction> = key_binds
+        .iter()
+        .map(|(key_bind, action)| (key_bind.clone(), TabMenuAction(entity, *action)))
+        .collect();
+    widget::menu::items(
+        &tab_key_binds,
+        vec![
+            MenuItem::Button(
+                fl!("close-tab"),
+                None,
+                TabMenuAction(entity, Action::CloseFile),
+            ),
+            MenuItem::Button(
+                fl!("close-other-tabs"),
+                None,
+                TabMenuAction(entity, Action::CloseOtherTabs),
+            ),
+            MenuItem::Button(
+                fl!("close-tabs-to-right"),
+                None,
+                TabMenuAction(entity, Action::CloseTabsToRight),
+            ),
+            MenuItem::Button(
+                fl!("close-saved-tabs"),
+                None,
+                TabMenuAction(entity, Action::CloseSavedTabs),
+            ),
+            MenuItem::Divider,
+            MenuItem::Button(
+                fl!("copy-path"),
+                None,
+                TabMenuAction(entity, Action::CopyPath),
+            ),
+            MenuItem::Button(
+                fl!("reveal-in-files"),
+                None,
+                TabMenuAction(entity, Action::RevealInFiles),
+            ),
+            MenuItem::Divider,
+            MenuItem::Button(
+                fl!("move-tab-to-new-window"),
+                None,
+                TabMenuAction(entity, Action::MoveTabToNewWindow),
+            ),
+            MenuItem::CheckBox(
+                fl!("pin-tab"),
+                None,
+                pinned,
+                TabMenuAction(entity, Action::TogglePinTab),
+            ),
+        ],
+    )
+}
+
 pub fn context_menu<'a>(
     key_binds: &HashMap<KeyBind, Action>,
     entity: segmented_button::Entity,
+    misspelling_opt: Option<&Misspelling>,
 ) -> Element<'a, Message> {
     fn key_style(theme: &cosmic::Theme) -> TextStyle {
         let mut color = theme.cosmic().background.component.on;
@@ -51,34 +149,60 @@ pub fn context_menu<'a>(
         .on_press(Message::TabContextAction(entity, menu_action))
     };
 
-    widget::container(column!(
-        menu_item(fl!("undo"), Action::Undo),
-        menu_item(fl!("redo"), Action::Redo),
-        divider::horizontal::light(),
-        menu_item(fl!("cut"), Action::Cut),
-        menu_item(fl!("copy"), Action::Copy),
-        menu_item(fl!("paste"), Action::Paste),
-        menu_item(fl!("select-all"), Action::SelectAll),
-    ))
-    .padding(1)
-    //TODO: move style to libcosmic
-    .style(|theme| {
-        let cosmic = theme.cosmic();
-        let component = &cosmic.background.component;
-        widget::container::Style {
-            icon_color: Some(component.on.into()),
-            text_color: Some(component.on.into()),
-            background: Some(Background::Color(component.base.into())),
-            border: Border {
-                radius: cosmic.radius_s().map(|x| x + 1.0).into(),
-                width: 1.0,
-                color: component.divider.into(),
-            },
-            ..Default::default()
+    let mut column = widget::column::with_capacity(12);
+    // Suggestions for a misspelled word come first, like in other editors
+    if let Some(misspelling) = misspelling_opt {
+        if misspelling.suggestions.is_empty() {
+            column = column.push(menu_button(vec![
+                widget::text(fl!("no-suggestions")).into(),
+            ]));
         }
-    })
-    .width(Length::Fixed(240.0))
-    .into()
+        for suggestion in misspelling.suggestions.iter().take(SUGGESTIONS_MAX) {
+            column = column.push(
+                menu_button(vec![widget::text(suggestion.clone()).into()])
+                    .on_press(Message::SpellingReplace(entity, suggestion.clone())),
+            );
+        }
+        column = column
+            .push(
+                menu_button(vec![widget::text(fl!("add-to-dictionary")).into()])
+                    .on_press(Message::SpellingAdd(entity, misspelling.word.clone())),
+            )
+            .push(
+                menu_button(vec![widget::text(fl!("ignore-word")).into()])
+                    .on_press(Message::SpellingIgnore(entity, misspelling.word.clone())),
+            )
+            .push(divider::horizontal::light());
+    }
+    column = column
+        .push(menu_item(fl!("undo"), Action::Undo))
+        .push(menu_item(fl!("redo"), Action::Redo))
+        .push(divider::horizontal::light())
+        .push(menu_item(fl!("cut"), Action::Cut))
+        .push(menu_item(fl!("copy"), Action::Copy))
+        .push(menu_item(fl!("paste"), Action::Paste))
+        .push(menu_item(fl!("select-all"), Action::SelectAll));
+
+    widget::container(column)
+        .padding(1)
+        //TODO: move style to libcosmic
+        .style(|theme| {
+            let cosmic = theme.cosmic();
+            let component = &cosmic.background.component;
+            widget::container::Style {
+                icon_color: Some(component.on.into()),
+                text_color: Some(component.on.into()),
+                background: Some(Background::Color(component.base.into())),
+                border: Border {
+                    radius: cosmic.radius_s().map(|x| x + 1.0).into(),
+                    width: 1.0,
+                    color: component.divider.into(),
+                },
+                ..Default::default()
+            }
+        })
+        .width(Length::Fixed(240.0))
+        .into()
 }
 
 pub fn menu_bar<'a>(
@@ -87,6 +211,11 @@ pub fn menu_bar<'a>(
     config_state: &ConfigState,
     key_binds: &HashMap<KeyBind, Action>,
     projects: &Vec<(String, PathBuf)>,
+    syntax_name_opt: Option<&str>,
+    macro_recording: bool,
+    task_output_shown: bool,
+    spell_dictionaries: &[String],
+    spell_languages: &[String],
 ) -> Element<'a, Message> {
     //TODO: port to libcosmic
     let menu_tab_width = |tab_width: u16| {
@@ -108,7 +237,7 @@ pub fn menu_bar<'a>(
         path.display().to_string()
     };
 
-    let mut recent_files = Vec::with_capacity(config_state.recent_files.len());
+    let mut recent_files = Vec::with_capacity(config_state.recent_files.len() + 2);
     for (i, path) in config_state.recent_files.iter().enumerate() {
         recent_files.push(MenuItem::Button(
             format_path(path),
@@ -116,6 +245,40 @@ pub fn menu_bar<'a>(
             Action::OpenRecentFile(i),
         ));
     }
+    if !recent_files.is_empty() {
+        recent_files.push(MenuItem::Divider);
+    }
+    recent_files.push(MenuItem::Button(
+        fl!("clear-recent"),
+        None,
+        Action::ClearRecentFiles,
+    ));
+
+    let mut closed_tabs = Vec::with_capacity(config_state.closed_tabs.len());
+    for (i, session_tab) in config_state.closed_tabs.iter().enumerate() {
+        // Hide files that were moved or deleted, index is kept to match config_state
+        if !session_tab.path.exists() {
+            continue;
+        }
+        closed_tabs.push(MenuItem::Button(
+            format_path(&session_tab.path),
+            None,
+            Action::OpenClosedTab(i),
+        ));
+    }
+
+    let spell_language_items = spell_dictionaries
+        .iter()
+        .enumerate()
+        .map(|(i, name)| {
+            MenuItem::CheckBox(
+                name.clone(),
+                None,
+                spell_languages.contains(name),
+                Action::SpellLanguage(i),
+            )
+        })
+        .collect();
 
     let mut recent_projects = Vec::with_capacity(config_state.recent_projects.len());
     for (i, path) in config_state.recent_projects.iter().enumerate() {
@@ -126,6 +289,20 @@ pub fn menu_bar<'a>(
         ));
     }
 
+    let mut tools: Vec<_> = config
+        .external_tools
+        .iter()
+        .enumerate()
+        .map(|(i, tool)| MenuItem::Button(tool.name.clone(), None, Action::ExternalTool(i)))
+        .collect();
+    if tools.is_empty() {
+        tools.push(MenuItem::Button(
+            fl!("no-external-tools"),
+            None,
+            Action::Todo,
+        ));
+    }
+
     let mut close_projects = Vec::with_capacity(projects.len());
     for (project_i, (name, _path)) in projects.iter().enumerate() {
         close_projects.push(MenuItem::Button(
@@ -152,12 +329,27 @@ pub fn menu_bar<'a>(
                         MenuItem::Button(fl!("new-window"), None, Action::NewWindow),
                         MenuItem::Divider,
                         MenuItem::Button(fl!("open-file"), None, Action::OpenFileDialog),
+                        MenuItem::Button(
+                            fl!("menu-open-location"),
+                            None,
+                            Action::OpenLocationDialog,
+                        ),
                         MenuItem::Folder(fl!("open-recent-file"), recent_files),
                         MenuItem::Button(fl!("close-file"), None, Action::CloseFile),
+                        MenuItem::Button(fl!("reopen-closed-tab"), None, Action::ReopenClosedTab),
+                        MenuItem::Folder(fl!("recently-closed"), closed_tabs),
                         MenuItem::Divider,
                         MenuItem::Button(fl!("menu-open-project"), None, Action::OpenProjectDialog),
                         MenuItem::Folder(fl!("open-recent-project"), recent_projects),
                         MenuItem::Folder(fl!("close-project"), close_projects),
+                        MenuItem::Folder(
+                            fl!("tasks"),
+                            vec![
+                                MenuItem::Button(fl!("run-task"), None, Action::RunTaskDialog),
+                                MenuItem::Button(fl!("rerun-task"), None, Action::RerunTask),
+                                MenuItem::Button(fl!("stop-task"), None, Action::StopTask),
+                            ],
+                        ),
                         MenuItem::Divider,
                         MenuItem::Button(fl!("save"), None, Action::Save),
                         MenuItem::Button(fl!("save-as"), None, Action::SaveAsDialog),
@@ -169,14 +361,37 @@ pub fn menu_bar<'a>(
                             None,
                             Action::ToggleDocumentStatistics,
                         ),
-                        //TODO MenuItem::Button(fl!("document-type"), Action::Todo),
-                        //TODO MenuItem::Button(fl!("encoding"), Action::Todo),
+                        MenuItem::Button(fl!("document-type"), None, Action::ToggleDocumentType),
+                        MenuItem::Button(fl!("encoding"), None, Action::ToggleEncoding),
+                        MenuItem::Folder(
+                            fl!("line-endings"),
+                            vec![
+                                MenuItem::Button(
+                                    fl!("convert-to-lf"),
+                                    None,
+                                    Action::ConvertLineEndingsLf,
+                                ),
+                                MenuItem::Button(
+                                    fl!("convert-to-crlf"),
+                                    None,
+                                    Action::ConvertLineEndingsCrLf,
+                                ),
+                            ],
+                        ),
                         MenuItem::Button(
                             fl!("menu-git-management"),
                             None,
                             Action::ToggleGitManagement,
                         ),
-                        //TODO MenuItem::Button(fl!("print"), Action::Todo),
+                        MenuItem::Button(fl!("compare-files"), None, Action::CompareFilesDialog),
+                        MenuItem::Folder(
+                            fl!("export"),
+                            vec![
+                                MenuItem::Button(fl!("export-html"), None, Action::ExportHtml),
+                                MenuItem::Button(fl!("export-pdf"), None, Action::ExportPdf),
+                            ],
+                        ),
+                        MenuItem::Button(fl!("print"), None, Action::Print),
                         MenuItem::Divider,
                         MenuItem::Button(fl!("quit"), None, Action::Quit),
                     ],
@@ -190,15 +405,114 @@ pub fn menu_bar<'a>(
                         MenuItem::Button(fl!("cut"), None, Action::Cut),
                         MenuItem::Button(fl!("copy"), None, Action::Copy),
                         MenuItem::Button(fl!("paste"), None, Action::Paste),
+                        MenuItem::Button(fl!("paste-from-history"), None, Action::PasteFromHistory),
                         MenuItem::Button(fl!("select-all"), None, Action::SelectAll),
                         MenuItem::Divider,
+                        MenuItem::Button(fl!("add-caret-above"), None, Action::AddCaretAbove),
+                        MenuItem::Button(fl!("add-caret-below"), None, Action::AddCaretBelow),
+                        MenuItem::Button(
+                            fl!("add-next-occurrence"),
+                            None,
+                            Action::AddNextOccurrence,
+                        ),
+                        MenuItem::Divider,
+                        MenuItem::Button(fl!("show-completions"), None, Action::ShowCompletions),
+                        MenuItem::Button(fl!("toggle-comment"), None, Action::ToggleComment),
+                        MenuItem::Button(fl!("format-document"), None, Action::FormatDocument),
+                        MenuItem::Button(
+                            fl!("filter-through-command"),
+                            None,
+                            Action::FilterThroughCommand,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("format-on-save"),
+                            None,
+                            syntax_name_opt.is_some_and(|syntax_name| {
+                                config.format_on_save.iter().any(|name| name == syntax_name)
+                            }),
+                            Action::ToggleFormatOnSave,
+                        ),
+                        MenuItem::Button(
+                            fl!("go-to-matching-bracket"),
+                            None,
+                            Action::GoToMatchingBracket,
+                        ),
+                        MenuItem::Button(fl!("go-to-definition"), None, Action::GoToDefinition),
+                        MenuItem::Button(fl!("go-to-symbol"), None, Action::GoToSymbol),
+                        MenuItem::Button(fl!("find-references"), None, Action::FindReferences),
+                        MenuItem::Button(fl!("rename-symbol"), None, Action::RenameSymbol),
+                        MenuItem::Button(fl!("navigate-back"), None, Action::NavigateBack),
+                        MenuItem::Button(fl!("navigate-forward"), None, Action::NavigateForward),
+                        MenuItem::Button(fl!("move-line-up"), None, Action::MoveLineUp),
+                        MenuItem::Button(fl!("move-line-down"), None, Action::MoveLineDown),
+                        MenuItem::Button(fl!("duplicate-line"), None, Action::DuplicateLine),
+                        MenuItem::Button(fl!("join-lines"), None, Action::JoinLines),
+                        MenuItem::Button(fl!("delete-line"), None, Action::DeleteLine),
+                        MenuItem::Folder(
+                            fl!("merge-conflicts"),
+                            vec![
+                                MenuItem::Button(fl!("accept-ours"), None, Action::AcceptOurs),
+                                MenuItem::Button(fl!("accept-theirs"), None, Action::AcceptTheirs),
+                                MenuItem::Button(fl!("accept-both"), None, Action::AcceptBoth),
+                                MenuItem::Divider,
+                                MenuItem::Button(
+                                    fl!("previous-conflict"),
+                                    None,
+                                    Action::PreviousConflict,
+                                ),
+                                MenuItem::Button(fl!("next-conflict"), None, Action::NextConflict),
+                            ],
+                        ),
+                        MenuItem::Folder(
+                            fl!("line-tools"),
+                            vec![
+                                MenuItem::Button(
+                                    fl!("sort-lines-ascending"),
+                                    None,
+                                    Action::SortLinesAscending,
+                                ),
+                                MenuItem::Button(
+                                    fl!("sort-lines-descending"),
+                                    None,
+                                    Action::SortLinesDescending,
+                                ),
+                                MenuItem::Button(fl!("reverse-lines"), None, Action::ReverseLines),
+                                MenuItem::Button(
+                                    fl!("remove-duplicate-lines"),
+                                    None,
+                                    Action::RemoveDuplicateLines,
+                                ),
+                            ],
+                        ),
+                        MenuItem::Folder(
+                            fl!("macros"),
+                            vec![
+                                MenuItem::CheckBox(
+                                    fl!("record-macro"),
+                                    None,
+                                    macro_recording,
+                                    Action::ToggleMacroRecording,
+                                ),
+                                MenuItem::Button(fl!("play-macro"), None, Action::PlayMacro),
+                                MenuItem::Button(
+                                    fl!("menu-play-macro-repeatedly"),
+                                    None,
+                                    Action::PlayMacroDialog,
+                                ),
+                            ],
+                        ),
+                        MenuItem::Divider,
                         MenuItem::Button(fl!("find"), None, Action::Find),
                         MenuItem::Button(fl!("replace"), None, Action::FindAndReplace),
                         MenuItem::Button(fl!("find-in-project"), None, Action::ToggleProjectSearch),
-                        /*TODO: implement spell-check
                         MenuItem::Divider,
-                        MenuItem::Button(fl!("spell-check"), None, Action::Todo),
-                        */
+                        MenuItem::CheckBox(
+                            fl!("spell-check"),
+                            None,
+                            config.spell_check,
+                            Action::ToggleSpellCheck,
+                        ),
+                        MenuItem::Folder(fl!("spell-check-languages"), spell_language_items),
                     ],
                 ),
                 (
@@ -232,6 +546,13 @@ pub fn menu_bar<'a>(
                         MenuItem::Button(fl!("default-size"), None, Action::ZoomReset),
                         MenuItem::Button(fl!("zoom-out"), None, Action::ZoomOut),
                         MenuItem::Divider,
+                        MenuItem::Button(fl!("split-horizontal"), None, Action::SplitHorizontal),
+                        MenuItem::Button(fl!("split-vertical"), None, Action::SplitVertical),
+                        MenuItem::Button(fl!("focus-next-pane"), None, Action::SplitFocusNext),
+                        MenuItem::Button(fl!("close-split"), None, Action::SplitClose),
+                        MenuItem::Button(fl!("fullscreen"), None, Action::ToggleFullscreen),
+                        MenuItem::Button(fl!("zen-mode"), None, Action::ToggleZenMode),
+                        MenuItem::Divider,
                         MenuItem::CheckBox(
                             fl!("word-wrap"),
                             None,
@@ -250,15 +571,72 @@ pub fn menu_bar<'a>(
                             config.highlight_current_line,
                             Action::ToggleHighlightCurrentLine,
                         ),
+                        MenuItem::CheckBox(
+                            fl!("show-indent-guides"),
+                            None,
+                            config.indent_guides,
+                            Action::ToggleIndentGuides,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("show-whitespace"),
+                            None,
+                            config.show_whitespace,
+                            Action::ToggleWhitespace,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("show-git-changes"),
+                            None,
+                            config.git_gutter,
+                            Action::ToggleGitGutter,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("show-git-blame"),
+                            None,
+                            config.git_blame,
+                            Action::ToggleGitBlame,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("show-minimap"),
+                            None,
+                            config.minimap,
+                            Action::ToggleMinimap,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("markdown-preview"),
+                            None,
+                            config.markdown_preview,
+                            Action::ToggleMarkdownPreview,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("task-output"),
+                            None,
+                            task_output_shown,
+                            Action::ToggleTaskOutput,
+                        ),
+                        MenuItem::CheckBox(
+                            fl!("auto-close-brackets"),
+                            None,
+                            config.auto_close_brackets,
+                            Action::ToggleAutoClose,
+                        ),
+                        MenuItem::Button(fl!("reload-syntaxes"), None, Action::ReloadSyntaxes),
                         //TODO: MenuItem::CheckBox(fl!("syntax-highlighting"), Action::Todo),
                         MenuItem::Divider,
+                        MenuItem::Button(fl!("command-palette"), None, Action::CommandPalette),
+                        MenuItem::Button(fl!("menu-problems"), None, Action::ToggleProblems),
+                        MenuItem::Button(fl!("menu-outline"), None, Action::ToggleOutline),
+                        MenuItem::Button(fl!("menu-undo-history"), None, Action::ToggleUndoHistory),
                         MenuItem::Button(fl!("menu-settings"), None, Action::ToggleSettingsPage),
-                        //TODO MenuItem::Divider,
-                        //TODO MenuItem::Button(fl!("menu-keyboard-shortcuts"), Action::Todo),
+                        MenuItem::Button(
+                            fl!("menu-keyboard-shortcuts"),
+                            None,
+                            Action::ToggleKeyboardShortcuts,
+                        ),
                         MenuItem::Divider,
                         MenuItem::Button(fl!("menu-about"), None, Action::About),
                     ],
                 ),
+                ((fl!("tools")), tools),
             ],
         )
 }
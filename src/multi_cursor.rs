@@ -0,0 +1,422 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{
+    Action, BorrowedWithFontSystem, Buffer, Cursor, Edit, Motion, Selection, ViEditor,
+};
+use std::{cmp::Reverse, ops::DerefMut};
+use unicode_segmentation::UnicodeSegmentation;
+
+/// An additional caret, edits at the cursor of the editor are repeated at each one
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Caret {
+    pub cursor: Cursor,
+    pub selection: Selection,
+}
+
+impl Caret {
+    fn from_editor(editor: &ViEditor<'static, 'static>) -> Self {
+        Self {
+            cursor: editor.cursor(),
+            selection: editor.selection(),
+        }
+    }
+
+    fn set_editor(&self, editor: &mut ViEditor<'static, 'static>) {
+        editor.set_cursor(self.cursor);
+        editor.set_selection(self.selection);
+    }
+
+    /// Start and end of the selection, if there is one
+    pub fn selection_bounds(&self) -> Option<(Cursor, Cursor)> {
+        let anchor = match self.selection {
+            Selection::None => return None,
+            Selection::Normal(anchor) | Selection::Line(anchor) | Selection::Word(anchor) => anchor,
+        };
+        if position(anchor) <= position(self.cursor) {
+            Some((anchor, self.cursor))
+        } else {
+            Some((self.cursor, anchor))
+        }
+    }
+
+    fn start(&self) -> Cursor {
+        self.selection_bounds()
+            .map_or(self.cursor, |(start, _end)| start)
+    }
+}
+
+fn position(cursor: Cursor) -> (usize, usize) {
+    (cursor.line, cursor.index)
+}
+
+/// Lines from the end of the buffer and bytes from the end of the line, which do not change when
+/// text before the cursor is edited
+#[derive(Clone, Copy, Debug)]
+pub struct FromEnd {
+    lines: usize,
+    bytes: usize,
+}
+
+impl FromEnd {
+    pub fn new(buffer: &Buffer, cursor: Cursor) -> Self {
+        let line_len = buffer
+            .lines
+            .get(cursor.line)
+            .map_or(0, |line| line.text().len());
+        Self {
+            lines: buffer.lines.len().saturating_sub(cursor.line),
+            bytes: line_len.saturating_sub(cursor.index),
+        }
+    }
+
+    pub fn cursor(&self, buffer: &Buffer) -> Cursor {
+        let line = buffer.lines.len().saturating_sub(self.lines);
+        let line_len = buffer.lines.get(line).map_or(0, |line| line.text().len());
+        Cursor::new(line, line_len.saturating_sub(self.bytes))
+    }
+}
+
+fn selection_from_end(buffer: &Buffer, selection: Selection) -> (Selection, Option<FromEnd>) {
+    match selection {
+        Selection::None => (selection, None),
+        Selection::Normal(anchor) | Selection::Line(anchor) | Selection::Word(anchor) => {
+            (selection, Some(FromEnd::new(buffer, anchor)))
+        }
+    }
+}
+
+fn selection_to_cursor(
+    buffer: &Buffer,
+    selection: Selection,
+    anchor_opt: Option<FromEnd>,
+) -> Selection {
+    match (selection, anchor_opt) {
+        (Selection::Normal(_), Some(anchor)) => Selection::Normal(anchor.cursor(buffer)),
+        (Selection::Line(_), Some(anchor)) => Selection::Line(anchor.cursor(buffer)),
+        (Selection::Word(_), Some(anchor)) => Selection::Word(anchor.cursor(buffer)),
+        _ => Selection::None,
+    }
+}
+
+/// Run `f` at the cursor of the editor and at every caret
+pub fn apply<E>(editor: &mut E, carets: &mut Vec<Caret>, mut f: impl FnMut(&mut E))
+where
+    E: DerefMut<Target = ViEditor<'static, 'static>>,
+{
+    apply_indexed(editor, carets, |editor, _index| f(editor));
+}
+
+/// Run `f` at the cursor of the editor and at every caret, with the index of the caret in
+/// document order
+pub fn apply_indexed<E>(editor: &mut E, carets: &mut Vec<Caret>, mut f: impl FnMut(&mut E, usize))
+where
+    E: DerefMut<Target = ViEditor<'static, 'static>>,
+{
+    if carets.is_empty() {
+        f(editor, 0);
+        return;
+    }
+
+    // Carets are edited from last to first, so edits do not move the carets still to be edited
+    let mut all: Vec<(bool, Caret)> = carets.drain(..).map(|caret| (false, caret)).collect();
+    all.push((true, Caret::from_editor(editor)));
+    all.sort_by_key(|(_main, caret)| Reverse(position(caret.start())));
+
+    let count = all.len();
+    let mut moved = Vec::with_capacity(count);
+    editor.start_change();
+    for (i, (main, caret)) in all.into_iter().enumerate() {
+        caret.set_editor(editor);
+        f(editor, count - 1 - i);
+        let caret = Caret::from_editor(editor);
+        moved.push(editor.with_buffer(|buffer| {
+            let (selection, anchor_opt) = selection_from_end(buffer, caret.selection);
+            (
+                main,
+                FromEnd::new(buffer, caret.cursor),
+                selection,
+                anchor_opt,
+            )
+        }));
+    }
+    editor.finish_change();
+
+    // Carets that end up in the same place are merged
+    let mut main_opt = None;
+    editor.with_buffer(|buffer| {
+        for (main, cursor, selection, anchor_opt) in moved {
+            let caret = Caret {
+                cursor: cursor.cursor(buffer),
+                selection: selection_to_cursor(buffer, selection, anchor_opt),
+            };
+            if main {
+                main_opt = Some(caret);
+            } else if !carets.iter().any(|other| other.cursor == caret.cursor) {
+                carets.push(caret);
+            }
+        }
+    });
+    if let Some(main) = main_opt {
+        carets.retain(|caret| caret.cursor != main.cursor);
+        main.set_editor(editor);
+    }
+}
+
+/// Add a caret where the cursor is, then move the cursor
+pub fn add_adjacent(
+    editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
+    carets: &mut Vec<Caret>,
+    motion: Motion,
+) {
+    let caret = Caret::from_editor(editor);
+    editor.set_selection(Selection::None);
+    editor.action(Action::Motion(motion));
+    if editor.cursor() != caret.cursor && !carets.iter().any(|other| other.cursor == caret.cursor) {
+        carets.push(Caret {
+            cursor: caret.cursor,
+            selection: Selection::None,
+        });
+    }
+}
+
+/// Add a caret at the click position, keeping the cursor where it was
+pub fn add_at(editor: &ViEditor<'static, 'static>, carets: &mut Vec<Caret>, cursor: Cursor) {
+    if cursor == editor.cursor() {
+        return;
+    }
+    match carets.iter().position(|caret| caret.cursor == cursor) {
+        // Clicking a caret again removes it
+        Some(index) => {
+            carets.remove(index);
+        }
+        None => carets.push(Caret {
+            cursor,
+            selection: Selection::None,
+        }),
+    }
+}
+
+/// Select the word at the cursor, or select the next occurrence of the selection and add a caret
+/// for the previous one
+pub fn add_next_occurrence<E>(editor: &mut E, carets: &mut Vec<Caret>)
+where
+    E: DerefMut<Target = ViEditor<'static, 'static>>,
+{
+    let caret = Caret::from_editor(editor);
+    let Some((_start, end)) = caret.selection_bounds() else {
+        let word_opt = editor.with_buffer(|buffer| {
+            let text = buffer.lines.get(caret.cursor.line)?.text();
+            text.split_word_bound_indices()
+                .find(|(index, word)| {
+                    *index <= caret.cursor.index
+                        && caret.cursor.index <= index + word.len()
+                        && word.chars().any(char::is_alphanumeric)
+                })
+                .map(|(index, word)| (index, index + word.len()))
+        });
+        if let Some((start, end)) = word_opt {
+            editor.set_selection(Selection::Normal(Cursor::new(caret.cursor.line, start)));
+            editor.set_cursor(Cursor::new(caret.cursor.line, end));
+        }
+        return;
+    };
+    let Some(query) = editor.copy_selection().filter(|query| !query.is_empty()) else {
+        return;
+    };
+
+    let found_opt = editor.with_buffer(|buffer| {
+        // Search the whole text so that occurrences spanning lines are found
+        let mut text = String::new();
+        let mut line_starts = Vec::with_capacity(buffer.lines.len());
+        for line in buffer.lines.iter() {
+            line_starts.push(text.len());
+            text.push_str(line.text());
+            text.push('\n');
+        }
+        let offset = |cursor: Cursor| {
+            line_starts
+                .get(cursor.line)
+                .map_or(0, |start| start + cursor.index)
+        };
+        let to_cursor = |offset: usize| {
+            let line = line_starts
+                .partition_point(|start| *start <= offset)
+                .saturating_sub(1);
+            Cursor::new(line, offset - line_starts[line])
+        };
+
+        let selected: Vec<usize> = carets
+            .iter()
+            .chain(std::iter::once(&caret))
+            .map(|caret| offset(caret.start()))
+            .collect();
+        let from = offset(end);
+        // Wrap around to the start of the text
+        text[from..]
+            .match_indices(query.as_str())
+            .map(|(index, _)| from + index)
+            .chain(
+                text[..from]
+                    .match_indices(query.as_str())
+                    .map(|(index, _)| index),
+            )
+            .find(|start| !selected.contains(start))
+            .map(|start| (to_cursor(start), to_cursor(start + query.len())))
+    });
+
+    if let Some((start, end)) = found_opt {
+        carets.push(caret);
+        editor.set_selection(Selection::Normal(start));
+        editor.set_cursor(end);
+    }
+}
+
+/// Column of a byte index in a line, with tabs expanded
+pub fn column(text: &str, index: usize, tab_width: usize) -> usize {
+    let mut column = 0;
+    for c in text[..index.min(text.len())].chars() {
+        column += if c == '\t' {
+            tab_width - column % tab_width
+        } else {
+            1
+        };
+    }
+    column
+}
+
+// Byte index of a column in a line, or the end of the line if it is shorter
+fn column_index(text: &str, target: usize, tab_width: usize) -> usize {
+    let mut column = 0;
+    for (index, c) in text.char_indices() {
+        if column >= target {
+            return index;
+        }
+        column += if c == '\t' {
+            tab_width - column % tab_width
+        } else {
+            1
+        };
+    }
+    text.len()
+}
+
+/// Select the rectangle between two cursors, with a caret on every line and the cursor of the
+/// editor on the line of `end`
+pub fn select_block(
+    editor: &mut ViEditor<'static, 'static>,
+    carets: &mut Vec<Caret>,
+    start: Cursor,
+    end: Cursor,
+) {
+    let tab_width = usize::from(editor.tab_width()).max(1);
+    let block = editor.with_buffer(|buffer| {
+        let line_column = |cursor: Cursor| {
+            buffer
+                .lines
+                .get(cursor.line)
+                .map_or(0, |line| column(line.text(), cursor.index, tab_width))
+        };
+        let start_column = line_column(start);
+        let end_column = line_column(end);
+
+        let mut block = Vec::new();
+        let lines: Box<dyn Iterator<Item = usize>> = if start.line <= end.line {
+            Box::new(start.line..=end.line)
+        } else {
+            Box::new((end.line..=start.line).rev())
+        };
+        for line_i in lines {
+            let Some(line) = buffer.lines.get(line_i) else {
+                continue;
+            };
+            let anchor = Cursor::new(line_i, column_index(line.text(), start_column, tab_width));
+            let cursor = Cursor::new(line_i, column_index(line.text(), end_column, tab_width));
+            block.push(Caret {
+                cursor,
+                selection: if anchor == cursor {
+                    Selection::None
+                } else {
+                    Selection::Normal(anchor)
+                },
+            });
+        }
+        block
+    });
+
+    carets.clear();
+    let mut block = block.into_iter();
+    if let Some(main) = block.next_back() {
+        carets.extend(block);
+        main.set_editor(editor);
+    }
+}
+
+/// Selected text of the cursor and every caret in document order, one per line
+pub fn copy_block(editor: &mut ViEditor<'static, 'static>, carets: &[Caret]) -> String {
+    let main = Caret::from_editor(editor);
+    let mut all: Vec<Caret> = carets
+        .iter()
+        .copied()
+        .chain(std::iter::once(main))
+        .collect();
+    all.sort_by_key(|caret| position(caret.start()));
+    let mut text = String::new();
+    for (i, caret) in all.iter().enumerate() {
+        if i > 0 {
+            text.push('\n');
+        }
+        caret.set_editor(editor);
+        if let Some(selection) = editor.copy_selection() {
+            text.push_str(&selection);
+        }
+    }
+    main.set_editor(editor);
+    text
+}
+
+/// Insert each line of a rectangle copied by [`copy_block`] at the column of the cursor on
+/// following lines, adding lines and padding if needed
+pub fn paste_block(editor: &mut ViEditor<'static, 'static>, text: &str) {
+    let tab_width = usize::from(editor.tab_width()).max(1);
+    editor.delete_selection();
+    let cursor = editor.cursor();
+    let start_column = editor.with_buffer(|buffer| {
+        buffer
+            .lines
+            .get(cursor.line)
+            .map_or(0, |line| column(line.text(), cursor.index, tab_width))
+    });
+
+    let mut last_cursor = cursor;
+    for (i, line_text) in text.split('\n').enumerate() {
+        let line_i = cursor.line + i;
+        let line_opt = editor.with_buffer(|buffer| {
+            buffer.lines.get(line_i).map(|line| {
+                let text = line.text();
+                (text.len(), column(text, text.len(), tab_width))
+            })
+        });
+        let (index, padding) = match line_opt {
+            Some((len, columns)) if columns < start_column => (len, start_column - columns),
+            Some(_) => {
+                let index = editor.with_buffer(|buffer| {
+                    column_index(buffer.lines[line_i].text(), start_column, tab_width)
+                });
+                (index, 0)
+            }
+            None => {
+                // Add a line at the end of the buffer
+                let end = editor.with_buffer(|buffer| {
+                    let last = buffer.lines.len().saturating_sub(1);
+                    Cursor::new(last, buffer.lines[last].text().len())
+                });
+                editor.insert_at(end, "\n", None);
+                (0, start_column)
+            }
+        };
+        let mut insert = " ".repeat(padding);
+        insert.push_str(line_text);
+        last_cursor = editor.insert_at(Cursor::new(line_i, index), &insert, None);
+    }
+    editor.set_cursor(last_cursor);
+}
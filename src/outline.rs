@@ -0,0 +1,148 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::Cursor;
+
+/// A function, type, heading, or section in a document
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Symbol {
+    pub name: String,
+    pub detail_opt: Option<String>,
+    /// Number of symbols this one is inside of
+    pub depth: usize,
+    pub start: Cursor,
+}
+
+impl Symbol {
+    fn new(name: &str, depth: usize, start: Cursor) -> Self {
+        Self {
+            name: name.to_string(),
+            detail_opt: None,
+            depth,
+            start,
+        }
+    }
+}
+
+// Headings of Markdown, which are not found in code blocks
+fn markdown(text: &str) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut fence_opt: Option<&str> = None;
+    let mut previous_opt: Option<(usize, &str)> = None;
+    for (line_i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if let Some(fence) = fence_opt {
+            if trimmed.starts_with(fence) {
+                fence_opt = None;
+            }
+            continue;
+        }
+        if trimmed.starts_with("```") || trimmed.starts_with("~~~") {
+            fence_opt = Some(&trimmed[..3]);
+            previous_opt = None;
+            continue;
+        }
+
+        let level = trimmed.chars().take_while(|c| *c == '#').count();
+        let rest = &trimmed[level..];
+        if (1..=6).contains(&level) && (rest.is_empty() || rest.starts_with(' ')) {
+            let name = rest.trim().trim_end_matches('#').trim_end();
+            symbols.push(Symbol::new(name, level - 1, Cursor::new(line_i, 0)));
+            previous_opt = None;
+            continue;
+        }
+
+        // A line can be underlined to make it a heading
+        if let Some((previous_i, previous)) = previous_opt
+            && !trimmed.is_empty()
+            && (trimmed.chars().all(|c| c == '=') || trimmed.chars().all(|c| c == '-'))
+            && !line.starts_with("    ")
+        {
+            let depth = if trimmed.starts_with('=') { 0 } else { 1 };
+            symbols.push(Symbol::new(previous, depth, Cursor::new(previous_i, 0)));
+            previous_opt = None;
+            continue;
+        }
+        previous_opt = if trimmed.is_empty() || line.starts_with("    ") {
+            None
+        } else {
+            Some((line_i, trimmed))
+        };
+    }
+    symbols
+}
+
+// Sections of INI and TOML files, where the tables of TOML are inside of the ones they extend
+fn sections(text: &str) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    let mut tables: Vec<&str> = Vec::new();
+    for (line_i, line) in text.lines().enumerate() {
+        let trimmed = line.trim();
+        if !trimmed.starts_with('[') {
+            continue;
+        }
+        let Some(end) = trimmed.rfind(']') else {
+            continue;
+        };
+        let name = trimmed[..end]
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim();
+        if name.is_empty() {
+            continue;
+        }
+        while tables
+            .last()
+            .is_some_and(|table| !name.starts_with(&format!("{table}.")))
+        {
+            tables.pop();
+        }
+        symbols.push(Symbol::new(name, tables.len(), Cursor::new(line_i, 0)));
+        tables.push(name);
+    }
+    symbols
+}
+
+// Keys of YAML that contain other keys, along with the keys at the top
+fn yaml(text: &str) -> Vec<Symbol> {
+    let mut symbols = Vec::new();
+    // Indentation of the keys that the next lines are inside of
+    let mut indents: Vec<usize> = Vec::new();
+    for (line_i, line) in text.lines().enumerate() {
+        let trimmed = line.trim_start();
+        if trimmed.is_empty() || trimmed.starts_with(['#', '-']) {
+            continue;
+        }
+        // A key ends with a colon that is followed by a space or the end of the line
+        let Some((key, value)) = trimmed.split_once(':') else {
+            continue;
+        };
+        if !(value.is_empty() || value.starts_with([' ', '\t'])) {
+            continue;
+        }
+        let key = key.trim().trim_matches(['"', '\'']);
+        let value = value.trim();
+        let indent = line.len() - trimmed.len();
+        while indents.last().is_some_and(|last| *last >= indent) {
+            indents.pop();
+        }
+        let block = value.is_empty() || value.starts_with('#');
+        if key.is_empty() || !(block || indents.is_empty()) {
+            continue;
+        }
+        symbols.push(Symbol::new(key, indents.len(), Cursor::new(line_i, indent)));
+        if block {
+            indents.push(indent);
+        }
+    }
+    symbols
+}
+
+/// Symbols of document types that are not understood by a language server, if they are known
+pub fn symbols(syntax_name: &str, text: &str) -> Option<Vec<Symbol>> {
+    match syntax_name {
+        "Markdown" | "MultiMarkdown" => Some(markdown(text)),
+        "INI" | "TOML" | "Git Config" => Some(sections(text)),
+        "YAML" => Some(yaml(text)),
+        _ => None,
+    }
+}
@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{fmt::Write as _, io, mem, process::Stdio};
+use syntect::{
+    easy::HighlightLines,
+    highlighting::Theme,
+    parsing::{SyntaxReference, SyntaxSet},
+    util::LinesWithEndings,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+
+// All sizes are in points
+const MARGIN: f32 = 36.0;
+const FONT_SIZE: f32 = 10.0;
+const LINE_HEIGHT: f32 = 12.0;
+// Courier glyphs are 600 units wide
+const CHAR_WIDTH: f32 = FONT_SIZE * 0.6;
+
+const BLACK: [u8; 3] = [0, 0, 0];
+const GRAY: [u8; 3] = [128, 128, 128];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Paper {
+    A4,
+    Letter,
+}
+
+impl Paper {
+    fn size(self) -> (f32, f32) {
+        match self {
+            Self::A4 => (595.0, 842.0),
+            Self::Letter => (612.0, 792.0),
+        }
+    }
+
+    /// Media name used by CUPS
+    fn media(self) -> &'static str {
+        match self {
+            Self::A4 => "A4",
+            Self::Letter => "Letter",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct PrintOptions {
+    pub header_footer: bool,
+    pub line_numbers: bool,
+    pub paper: Paper,
+    pub syntax_colors: bool,
+}
+
+impl Default for PrintOptions {
+    fn default() -> Self {
+        Self {
+            header_footer: true,
+            line_numbers: true,
+            paper: Paper::A4,
+            syntax_colors: true,
+        }
+    }
+}
+
+struct Row {
+    line_number: Option<usize>,
+    spans: Vec<([u8; 3], Vec<u8>)>,
+}
+
+impl Row {
+    fn new(line_number: Option<usize>) -> Self {
+        Self {
+            line_number,
+            spans: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, color: [u8; 3], byte: u8) {
+        match self.spans.last_mut() {
+            Some((last_color, bytes)) if *last_color == color => bytes.push(byte),
+            _ => self.spans.push((color, vec![byte])),
+        }
+    }
+}
+
+//TODO: use a font with more than Latin-1 coverage
+fn latin1(c: char) -> u8 {
+    match u8::try_from(u32::from(c)) {
+        Ok(byte @ (0x20..=0x7E | 0xA0..=0xFF)) => byte,
+        _ => b'?',
+    }
+}
+
+/// First character of `text` that cannot be printed, which would be replaced by a question mark
+pub fn unsupported_char(text: &str) -> Option<char> {
+    text.chars()
+        .find(|c| !matches!(c, '\t' | '\n' | '\r' | '?') && latin1(*c) == b'?')
+}
+
+fn ps_string(bytes: impl IntoIterator<Item = u8>) -> String {
+    let mut string = String::from("(");
+    for byte in bytes {
+        match byte {
+            b'(' | b')' | b'\\' => {
+                string.push('\\');
+                string.push(char::from(byte));
+            }
+            0x20..=0x7E => string.push(char::from(byte)),
+            _ => {
+                let _ = write!(string, "\\{:03o}", byte);
+            }
+        }
+    }
+    string.push(')');
+    string
+}
+
+fn ps_color([r, g, b]: [u8; 3]) -> String {
+    format!(
+        "{:.3} {:.3} {:.3} C",
+        f32::from(r) / 255.0,
+        f32::from(g) / 255.0,
+        f32::from(b) / 255.0
+    )
+}
+
+/// Render `text` as a PostScript document with one row per wrapped line
+pub fn render(
+    text: &str,
+    title: &str,
+    page_label: impl Fn(usize, usize) -> String,
+    highlight_opt: Option<(&SyntaxSet, &SyntaxReference, &Theme)>,
+    tab_width: usize,
+    options: &PrintOptions,
+) -> String {
+    let (width, height) = options.paper.size();
+    let (top, bottom) = if options.header_footer {
+        (
+            height - MARGIN - 2.0 * LINE_HEIGHT,
+            MARGIN + 2.0 * LINE_HEIGHT,
+        )
+    } else {
+        (height - MARGIN, MARGIN)
+    };
+    let rows_per_page = (((top - bottom) / LINE_HEIGHT) as usize).max(1);
+
+    let line_count = text.lines().count().max(1);
+    let digits = line_count.to_string().len();
+    let gutter = if options.line_numbers { digits + 1 } else { 0 };
+    let columns = (((width - 2.0 * MARGIN) / CHAR_WIDTH) as usize)
+        .saturating_sub(gutter)
+        .max(1);
+    let tab_width = tab_width.max(1);
+
+    let mut highlighter_opt = highlight_opt
+        .map(|(syntax_set, syntax, theme)| (syntax_set, HighlightLines::new(syntax, theme)));
+
+    // Split lines into spans of color, then wrap them into rows
+    let mut rows = Vec::new();
+    for (line_i, line) in LinesWithEndings::from(text).enumerate() {
+        let spans = match &mut highlighter_opt {
+            Some((syntax_set, highlighter)) => match highlighter.highlight_line(line, syntax_set) {
+                Ok(ranges) => ranges
+                    .into_iter()
+                    .map(|(style, text)| {
+                        let color = style.foreground;
+                        ([color.r, color.g, color.b], text)
+                    })
+                    .collect(),
+                Err(err) => {
+                    log::warn!("failed to highlight line {}: {}", line_i + 1, err);
+                    vec![(BLACK, line)]
+                }
+            },
+            None => vec![(BLACK, line)],
+        };
+
+        let mut row = Row::new(Some(line_i + 1));
+        let mut column = 0;
+        for (color, text) in spans {
+            for c in text.chars() {
+                let (byte, count) = match c {
+                    '\n' | '\r' => continue,
+                    '\t' => (b' ', tab_width - column % tab_width),
+                    _ => (latin1(c), 1),
+                };
+                for _ in 0..count {
+                    if column >= columns {
+                        rows.push(mem::replace(&mut row, Row::new(None)));
+                        column = 0;
+                    }
+                    row.push(color, byte);
+                    column += 1;
+                }
+            }
+        }
+        rows.push(row);
+    }
+
+    let pages: Vec<&[Row]> = if rows.is_empty() {
+        vec![&rows[..]]
+    } else {
+        rows.chunks(rows_per_page).collect()
+    };
+
+    let mut ps = String::new();
+    let _ = writeln!(ps, "%!PS-Adobe-3.0");
+    let _ = writeln!(ps, "%%Title: {}", ps_string(title.chars().map(latin1)));
+    let _ = writeln!(ps, "%%Pages: {}", pages.len());
+    let _ = writeln!(ps, "%%BoundingBox: 0 0 {} {}", width as u32, height as u32);
+    let _ = writeln!(ps, "%%EndComments");
+    let _ = writeln!(ps, "%%BeginProlog");
+    // Courier is re-encoded so that Latin-1 characters are shown
+    let _ = writeln!(
+        ps,
+        "/Courier findfont dup length dict begin {{ 1 index /FID ne {{ def }} {{ pop pop }} ifelse }} forall /Encoding ISOLatin1Encoding def currentdict end /Courier-Latin1 exch definefont pop"
+    );
+    let _ = writeln!(ps, "/M {{ moveto }} bind def");
+    let _ = writeln!(ps, "/S {{ show }} bind def");
+    let _ = writeln!(ps, "/C {{ setrgbcolor }} bind def");
+    // Show string right aligned at the given baseline
+    let _ = writeln!(
+        ps,
+        "/R {{ dup stringwidth pop {} exch sub 3 -1 roll M S }} bind def",
+        width - MARGIN
+    );
+    let _ = writeln!(ps, "%%EndProlog");
+
+    for (page_i, page) in pages.iter().enumerate() {
+        let _ = writeln!(ps, "%%Page: {} {}", page_i + 1, page_i + 1);
+        let _ = writeln!(
+            ps,
+            "/Courier-Latin1 findfont {} scalefont setfont",
+            FONT_SIZE
+        );
+
+        if options.header_footer {
+            // File name in the header
+            let _ = writeln!(ps, "{}", ps_color(BLACK));
+            let _ = writeln!(
+                ps,
+                "{} {} M {} S",
+                MARGIN,
+                height - MARGIN - FONT_SIZE,
+                ps_string(title.chars().map(latin1))
+            );
+            // Page number in the footer
+            let _ = writeln!(
+                ps,
+                "{} {} R",
+                MARGIN,
+                ps_string(page_label(page_i + 1, pages.len()).chars().map(latin1))
+            );
+        }
+
+        let mut y = top - FONT_SIZE;
+        for row in page.iter() {
+            if let Some(line_number) = row.line_number.filter(|_| options.line_numbers) {
+                let _ = writeln!(
+                    ps,
+                    "{} {} {} M {} S",
+                    ps_color(GRAY),
+                    MARGIN,
+                    y,
+                    ps_string(format!("{:>digits$}", line_number).bytes())
+                );
+            }
+            let _ = write!(ps, "{} {} M", MARGIN + gutter as f32 * CHAR_WIDTH, y);
+            for (color, bytes) in row.spans.iter() {
+                let _ = write!(
+                    ps,
+                    " {} {} S",
+                    ps_color(*color),
+                    ps_string(bytes.iter().copied())
+                );
+            }
+            let _ = writeln!(ps);
+            y -= LINE_HEIGHT;
+        }
+
+        let _ = writeln!(ps, "showpage");
+    }
+    let _ = writeln!(ps, "%%EOF");
+    ps
+}
+
+/// Submit a PostScript document to the default printer using CUPS
+//TODO: support the print portal and printer selection
+pub async fn submit(title: &str, options: &PrintOptions, document: String) -> io::Result<()> {
+    let mut command = Command::new("lp");
+    command
+        .arg("-t")
+        .arg(title)
+        .arg("-o")
+        .arg(format!("media={}", options.paper.media()))
+        .arg("-")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    log::info!("{:?}", command);
+    let mut child = command.spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(document.as_bytes()).await?;
+    }
+    let output = child.wait_with_output().await?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "lp exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
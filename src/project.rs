@@ -73,6 +73,47 @@ impl ProjectNode {
             Self::File { name, .. } => name,
         }
     }
+
+    pub fn path(&self) -> &Path {
+        match self {
+            Self::Folder { path, .. } => path,
+            Self::File { path, .. } => path,
+        }
+    }
+}
+
+/// Find a path that does not exist yet for a copy of `path`
+pub fn copy_path(path: &Path) -> PathBuf {
+    let stem = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let mut i = 1;
+    loop {
+        let name = match path.extension() {
+            Some(extension) => format!("{} ({}).{}", stem, i, extension.to_string_lossy()),
+            None => format!("{} ({})", stem, i),
+        };
+        let copy = path.with_file_name(name);
+        if !copy.exists() {
+            return copy;
+        }
+        i += 1;
+    }
+}
+
+/// Copy a file, or a folder and all of its contents
+pub fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir(to)?;
+        for entry_res in fs::read_dir(from)? {
+            let entry = entry_res?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
 }
 
 impl Ord for ProjectNode {
@@ -0,0 +1,182 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process,
+};
+
+/// Seconds between writes of snapshots
+pub const INTERVAL_SECS: u64 = 30;
+
+/// Unsaved text of a document, written periodically so that it survives a crash
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snapshot {
+    pub path_opt: Option<PathBuf>,
+    pub text: String,
+}
+
+impl Snapshot {
+    // The first line is the path of the document, which is empty for new documents
+    fn parse(data: &str) -> Self {
+        let (path, text) = data.split_once('\n').unwrap_or((data, ""));
+        Self {
+            path_opt: (!path.is_empty()).then(|| PathBuf::from(path)),
+            text: text.to_string(),
+        }
+    }
+
+    fn serialize(&self) -> String {
+        let mut data = String::new();
+        if let Some(path) = &self.path_opt {
+            data.push_str(&path.to_string_lossy());
+        }
+        data.push('\n');
+        data.push_str(&self.text);
+        data
+    }
+}
+
+fn recovery_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("cosmic-edit").join("recovery"))
+}
+
+// Instances opened with --new-window are separate processes, so each one writes to a folder
+// named after its process ID
+fn process_dir() -> Option<PathBuf> {
+    recovery_dir().map(|dir| dir.join(process::id().to_string()))
+}
+
+//TODO: process IDs can be reused, and this only works where /proc exists
+fn process_running(pid: u32) -> bool {
+    pid == process::id() || Path::new("/proc").join(pid.to_string()).exists()
+}
+
+/// Replace the snapshots of this process
+pub fn write(snapshots: &[Snapshot]) -> io::Result<()> {
+    let Some(dir) = process_dir() else {
+        return Ok(());
+    };
+    if snapshots.is_empty() {
+        return clear();
+    }
+
+    // Write to a temporary folder first, then move the previous snapshots aside before the new
+    // ones take their place, so a crash at any point leaves one complete set behind
+    let temp_dir = dir.with_extension("tmp");
+    let old_dir = dir.with_extension("old");
+    for stale_dir in [&temp_dir, &old_dir] {
+        if stale_dir.exists() {
+            fs::remove_dir_all(stale_dir)?;
+        }
+    }
+    fs::create_dir_all(&temp_dir)?;
+    for (i, snapshot) in snapshots.iter().enumerate() {
+        fs::write(temp_dir.join(format!("{i}.txt")), snapshot.serialize())?;
+    }
+    if dir.exists() {
+        fs::rename(&dir, &old_dir)?;
+    }
+    fs::rename(&temp_dir, &dir)?;
+    if old_dir.exists() {
+        fs::remove_dir_all(&old_dir)?;
+    }
+    Ok(())
+}
+
+/// Remove the snapshots of this process, done when it exits normally
+pub fn clear() -> io::Result<()> {
+    match process_dir() {
+        Some(dir) if dir.exists() => fs::remove_dir_all(dir),
+        _ => Ok(()),
+    }
+}
+
+/// Remove a folder of snapshots returned by [`orphans`]
+pub fn remove(dir: &Path) {
+    if let Err(err) = fs::remove_dir_all(dir) {
+        log::warn!("failed to remove recovery folder {:?}: {}", dir, err);
+    }
+}
+
+/// Find snapshots left behind by processes that are no longer running
+pub fn orphans() -> Vec<(PathBuf, Vec<Snapshot>)> {
+    let Some(recovery_dir) = recovery_dir() else {
+        return Vec::new();
+    };
+    let entries = match fs::read_dir(&recovery_dir) {
+        Ok(ok) => ok,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::warn!("failed to read recovery folder {:?}: {}", recovery_dir, err);
+            }
+            return Vec::new();
+        }
+    };
+
+    let mut orphans = Vec::new();
+    for entry_res in entries {
+        let dir = match entry_res {
+            Ok(entry) => entry.path(),
+            Err(err) => {
+                log::warn!("failed to read recovery folder entry: {}", err);
+                continue;
+            }
+        };
+        let Some(name) = dir.file_name().and_then(|name| name.to_str()) else {
+            continue;
+        };
+        let Ok(pid) = name
+            .trim_end_matches(".tmp")
+            .trim_end_matches(".old")
+            .parse::<u32>()
+        else {
+            continue;
+        };
+        if process_running(pid) {
+            continue;
+        }
+        if name.ends_with(".tmp") {
+            // The process crashed while writing, the previous snapshots are still complete
+            remove(&dir);
+            continue;
+        }
+        if name.ends_with(".old") && dir.with_extension("").exists() {
+            // The process crashed after the new snapshots were in place
+            remove(&dir);
+            continue;
+        }
+
+        let mut files = match fs::read_dir(&dir) {
+            Ok(files) => files
+                .filter_map(|entry_res| entry_res.ok().map(|entry| entry.path()))
+                .collect::<Vec<_>>(),
+            Err(err) => {
+                log::warn!("failed to read recovery folder {:?}: {}", dir, err);
+                continue;
+            }
+        };
+        // Files are numbered in tab order
+        files.sort_by_key(|file| {
+            file.file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| stem.parse::<usize>().ok())
+        });
+
+        let mut snapshots = Vec::with_capacity(files.len());
+        for file in files {
+            match fs::read_to_string(&file) {
+                Ok(data) => snapshots.push(Snapshot::parse(&data)),
+                Err(err) => {
+                    log::warn!("failed to read recovery file {:?}: {}", file, err);
+                }
+            }
+        }
+        if snapshots.is_empty() {
+            remove(&dir);
+        } else {
+            orphans.push((dir, snapshots));
+        }
+    }
+    orphans
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+// Columns taken by the whitespace at the start of a line
+fn indent_width(indentation: &str, tab_width: usize) -> usize {
+    indentation.chars().fold(0, |width, c| match c {
+        '\t' => (width / tab_width + 1) * tab_width,
+        _ => width + 1,
+    })
+}
+
+fn split_indentation(line: &str) -> (&str, &str) {
+    line.split_at(line.len() - line.trim_start_matches([' ', '\t']).len())
+}
+
+fn make_indentation(width: usize, tab_width: usize, tabs: bool) -> String {
+    if tabs {
+        let mut indentation = "\t".repeat(width / tab_width);
+        indentation.push_str(&" ".repeat(width % tab_width));
+        indentation
+    } else {
+        " ".repeat(width)
+    }
+}
+
+/// Indentation of `line` changed to tabs or spaces of the same width, with the length of the
+/// indentation it replaces, or `None` if nothing changes
+pub fn convert(line: &str, tab_width: usize, tabs: bool) -> Option<(usize, String)> {
+    let tab_width = tab_width.max(1);
+    let (indentation, _) = split_indentation(line);
+    let converted = make_indentation(indent_width(indentation, tab_width), tab_width, tabs);
+    (converted != indentation).then(|| (indentation.len(), converted))
+}
+
+/// Indent pasted lines for the place they are pasted at. With `reindent`, the least indented
+/// line is moved to the indentation of `before`, which is the text of the line before the
+/// cursor. With `tabs_opt`, indentation is changed to tabs or spaces like the document.
+pub fn indent(
+    text: &str,
+    before: &str,
+    tab_width: usize,
+    reindent: bool,
+    tabs_opt: Option<bool>,
+) -> String {
+    let tab_width = tab_width.max(1);
+    let lines: Vec<&str> = text.split('\n').collect();
+    if lines.len() < 2 || (!reindent && tabs_opt.is_none()) {
+        return text.to_string();
+    }
+
+    let (first_indentation, first_text) = split_indentation(lines[0]);
+    // The first line is usually copied without its indentation, so the other lines are used to
+    // find where the text was indented unless it was copied from the start of a line
+    let base_opt = lines
+        .iter()
+        .enumerate()
+        .filter(|(i, line)| !line.trim().is_empty() && (*i > 0 || !first_indentation.is_empty()))
+        .map(|(_, line)| indent_width(split_indentation(line).0, tab_width))
+        .min();
+    let (target_indentation, before_text) = split_indentation(before);
+    let target = indent_width(target_indentation, tab_width);
+
+    let mut indented = String::with_capacity(text.len());
+    for (i, line) in lines.iter().enumerate() {
+        if i > 0 {
+            indented.push('\n');
+        }
+        let (indentation, line_text) = split_indentation(line);
+        if i == 0 {
+            // The cursor already has its indentation when only whitespace is before it
+            if reindent && before_text.is_empty() {
+                indented.push_str(first_text);
+            } else {
+                indented.push_str(line);
+            }
+            continue;
+        }
+        if line_text.is_empty() {
+            continue;
+        }
+        let width = indent_width(indentation, tab_width);
+        let width = match base_opt {
+            Some(base) if reindent => (target + width).saturating_sub(base),
+            _ => width,
+        };
+        let tabs = tabs_opt.unwrap_or_else(|| indentation.starts_with('\t'));
+        indented.push_str(&make_indentation(width, tab_width, tabs));
+        indented.push_str(line_text);
+    }
+    indented
+}
@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    io::{self, Write},
+    process::{self, Stdio},
+};
+use tokio::process::Command;
+
+/// True for locations like `sftp://host/file` or `smb://host/share/file`, which are read and
+/// written with GIO so that any GVFS backend can be used
+pub fn is_uri(arg: &str) -> bool {
+    match arg.split_once("://") {
+        Some((scheme, _)) => {
+            scheme != "file"
+                && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+        }
+        None => false,
+    }
+}
+
+/// Last part of the location, which is shown as the title of its tab
+pub fn file_name(uri: &str) -> &str {
+    let path = uri.split_once("://").map_or(uri, |(_, path)| path);
+    path.trim_end_matches('/')
+        .rsplit('/')
+        .next()
+        .filter(|name| !name.is_empty())
+        .unwrap_or(uri)
+}
+
+fn output_error(program: &str, output: process::Output) -> io::Error {
+    let mut msg = format!("{} exited with {}", program, output.status);
+    for line in String::from_utf8_lossy(&output.stderr).lines() {
+        msg.push_str("\nstderr> ");
+        msg.push_str(line);
+    }
+    io::Error::other(msg)
+}
+
+/// Read the contents of a remote file, mounting its location first
+pub async fn read(uri: String) -> io::Result<Vec<u8>> {
+    // Fails if the location is already mounted, so errors are found by gio cat instead
+    match Command::new("gio")
+        .arg("mount")
+        .arg("--")
+        .arg(&uri)
+        .stdin(Stdio::null())
+        .output()
+        .await
+    {
+        Ok(output) if !output.status.success() => {
+            log::debug!("{}", output_error("gio mount", output));
+        }
+        Ok(_) => {}
+        Err(err) => return Err(err),
+    }
+
+    let output = Command::new("gio")
+        .arg("cat")
+        .arg("--")
+        .arg(&uri)
+        .stdin(Stdio::null())
+        .kill_on_drop(true)
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(output_error("gio cat", output));
+    }
+    Ok(output.stdout)
+}
+
+/// Write the contents of a remote file over the protocol it was opened with
+pub fn write(uri: &str, bytes: &[u8]) -> io::Result<()> {
+    let mut child = process::Command::new("gio")
+        .arg("save")
+        .arg("--")
+        .arg(uri)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Closing stdin ends the input of gio save
+    let write_res = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(bytes),
+        None => Err(io::Error::other("no stdin for gio save")),
+    };
+    let output = child.wait_with_output()?;
+    write_res?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(output_error("gio save", output))
+    }
+}
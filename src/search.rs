@@ -3,7 +3,10 @@
 use grep::matcher::{Match, Matcher};
 use grep::regex::RegexMatcher;
 use grep::searcher::{Searcher, sinks::UTF8};
-use std::path::PathBuf;
+use regex::Regex;
+use std::{borrow::Cow, fs, io, path::PathBuf};
+
+use crate::{encoding, tab};
 
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct LineSearchResult {
@@ -18,6 +21,60 @@ pub struct FileSearchResult {
     pub lines: Vec<LineSearchResult>,
 }
 
+impl FileSearchResult {
+    /// Replace all matches of `regex` on the lines of the file on disk that still read as in
+    /// `lines`, returning the number of lines changed. The file keeps its encoding, and is
+    /// written atomically if `atomic` is set
+    pub fn replace_on_disk(
+        &self,
+        regex: &Regex,
+        replace: &str,
+        lines: &[LineSearchResult],
+        atomic: bool,
+    ) -> io::Result<usize> {
+        let bytes = fs::read(&self.path)?;
+        let encoding = encoding::detect(&bytes);
+        let text = encoding::decode(&bytes, encoding);
+        let mut new_text = String::with_capacity(text.len());
+        let mut count = 0;
+        for (line_i, line) in text.split_inclusive('\n').enumerate() {
+            let content = line.trim_end_matches(['\r', '\n']);
+            let ending = &line[content.len()..];
+            // Lines that changed since the search are left alone
+            if !lines
+                .iter()
+                .any(|result| result.number == line_i + 1 && result.text == content.trim_end())
+            {
+                new_text.push_str(line);
+                continue;
+            }
+
+            let replaced = regex.replace_all(content, replace);
+            if let Cow::Owned(_) = replaced {
+                count += 1;
+            }
+            new_text.push_str(&replaced);
+            new_text.push_str(ending);
+        }
+
+        if count > 0 {
+            let (new_bytes, had_errors) = encoding::encode(&new_text, encoding);
+            if had_errors {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("replacement cannot be encoded in {}", encoding.name()),
+                ));
+            }
+            if atomic {
+                tab::write_atomic(&self.path, &new_bytes)?;
+            } else {
+                fs::write(&self.path, new_bytes)?;
+            }
+        }
+        Ok(count)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct ProjectSearchResult {
     //TODO: should this be included?
@@ -27,7 +84,14 @@ pub struct ProjectSearchResult {
 }
 
 impl ProjectSearchResult {
-    pub fn search_projects(&mut self, projects: Vec<(String, PathBuf)>) {
+    /// Search projects, sending each file with matches to `f` until it returns false or
+    /// `cancelled` returns true
+    pub fn search_projects<C: Fn() -> bool, F: FnMut(FileSearchResult) -> bool>(
+        &self,
+        projects: Vec<(String, PathBuf)>,
+        cancelled: C,
+        mut f: F,
+    ) {
         //TODO: support literal search
         //TODO: use ignore::WalkParallel?
         match RegexMatcher::new(&self.value) {
@@ -46,6 +110,12 @@ impl ProjectSearchResult {
 
                 if let Some(walk_builder) = walk_builder_opt {
                     for entry_res in walk_builder.build() {
+                        // Checked for every entry, as walking large folders without matches
+                        // can take a long time
+                        if cancelled() {
+                            return;
+                        }
+
                         let entry = match entry_res {
                             Ok(ok) => ok,
                             Err(err) => {
@@ -87,15 +157,21 @@ impl ProjectSearchResult {
                                         log::error!("failed to convert file {:?} line {} to usize: {}", entry_path, number_u64, err);
                                     }
                                 }
-                                Ok(true)
+                                Ok(!cancelled())
                             }),
                         ) {
                             Ok(()) => {
-                                if !lines.is_empty() {
-                                    self.files.push(FileSearchResult {
+                                if cancelled() {
+                                    return;
+                                }
+                                if !lines.is_empty()
+                                    && !f(FileSearchResult {
                                         path: entry_path.to_path_buf(),
                                         lines,
-                                    });
+                                    })
+                                {
+                                    // Search was cancelled
+                                    return;
                                 }
                             }
                             Err(err) => {
@@ -113,6 +189,5 @@ impl ProjectSearchResult {
                 );
             }
         }
-        self.in_progress = false;
     }
 }
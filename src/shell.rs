@@ -0,0 +1,46 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+/// Quote an argument for the shell so that it is passed as it is
+pub fn quote(arg: &str) -> String {
+    // Single quotes keep everything but themselves from being interpreted by the shell
+    format!("'{}'", arg.replace('\'', "'\\''"))
+}
+
+/// Remove the escape sequences that programs use for colors and moving the cursor
+pub fn strip_escapes(line: &str) -> String {
+    let mut text = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\u{1b}' => match chars.next() {
+                // Control sequences end with a character from @ to ~
+                Some('[') => {
+                    for c in chars.by_ref() {
+                        if ('@'..='~').contains(&c) {
+                            break;
+                        }
+                    }
+                }
+                // Operating system commands like window titles end with BEL or ESC \
+                Some(']') => {
+                    while let Some(c) = chars.next() {
+                        if c == '\u{7}' || (c == '\u{1b}' && chars.next_if_eq(&'\\').is_some()) {
+                            break;
+                        }
+                    }
+                }
+                _ => {}
+            },
+            '\r' => {
+                // Progress bars redraw the line, only the last version is kept
+                if chars.peek().is_some() {
+                    text.clear();
+                }
+            }
+            '\t' => text.push_str("    "),
+            c if c.is_control() => {}
+            c => text.push(c),
+        }
+    }
+    text
+}
@@ -0,0 +1,325 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Cursor, Edit, Selection, ViEditor};
+use serde::Deserialize;
+use std::{collections::HashMap, fs, io, path::PathBuf};
+
+use crate::{lsp::CompletionItem, multi_cursor::FromEnd};
+
+// Snippets in this file are used for every language
+const GLOBAL_FILE: &str = "global";
+
+/// Text that is inserted when its prefix is typed, with tab stops like `$1` and `${1:name}`
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Snippet {
+    pub name: String,
+    pub prefix: String,
+    pub body: String,
+    pub description_opt: Option<String>,
+}
+
+// Snippet files use the format of Visual Studio Code, where text can be split into lines
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Lines {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Lines {
+    fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(line) => vec![line],
+            Self::Many(lines) => lines,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct SnippetFile {
+    prefix: Lines,
+    body: Lines,
+    description: Option<String>,
+}
+
+/// Folder of the snippet files, which are named after the language like `Rust.json`
+pub fn dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cosmic-edit").join("snippets"))
+}
+
+fn load_file(name: &str) -> Vec<Snippet> {
+    let Some(path) = dir().map(|dir| dir.join(format!("{name}.json"))) else {
+        return Vec::new();
+    };
+    let data = match fs::read_to_string(&path) {
+        Ok(data) => data,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            log::warn!("failed to read snippets {:?}: {}", path, err);
+            return Vec::new();
+        }
+    };
+    let files: HashMap<String, SnippetFile> = match serde_json::from_str(&data) {
+        Ok(files) => files,
+        Err(err) => {
+            log::warn!("failed to parse snippets {:?}: {}", path, err);
+            return Vec::new();
+        }
+    };
+    let mut snippets = Vec::new();
+    for (name, file) in files {
+        let body = file.body.into_vec().join("\n");
+        for prefix in file.prefix.into_vec() {
+            snippets.push(Snippet {
+                name: name.clone(),
+                prefix,
+                body: body.clone(),
+                description_opt: file.description.clone(),
+            });
+        }
+    }
+    snippets.sort_by(|a, b| a.prefix.cmp(&b.prefix).then_with(|| a.name.cmp(&b.name)));
+    snippets
+}
+
+/// Snippets of a language, along with the ones for every language
+pub fn load(syntax_name: &str) -> Vec<Snippet> {
+    let mut snippets = load_file(syntax_name);
+    snippets.extend(load_file(GLOBAL_FILE));
+    snippets
+}
+
+/// The snippet whose prefix is typed before the cursor, preferring the longest prefix
+pub fn find<'a>(snippets: &'a [Snippet], before: &str) -> Option<&'a Snippet> {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    snippets
+        .iter()
+        .filter(|snippet| {
+            let Some(rest) = before.strip_suffix(snippet.prefix.as_str()) else {
+                return false;
+            };
+            // A prefix that starts a word is not expanded in the middle of another word
+            !snippet.prefix.starts_with(is_word) || !rest.ends_with(is_word)
+        })
+        .max_by_key(|snippet| snippet.prefix.len())
+}
+
+/// Whitespace at the start of a line
+pub fn indentation(editor: &ViEditor<'static, 'static>, line: usize) -> String {
+    editor.with_buffer(|buffer| {
+        let text = buffer.lines[line].text();
+        text[..text.len() - text.trim_start().len()].to_string()
+    })
+}
+
+/// Indent the lines after the first like the line the snippet is inserted on
+pub fn indent(body: &str, indentation: &str) -> String {
+    body.replace('\n', &format!("\n{indentation}"))
+}
+
+/// Snippets shown along with the completions of the word being typed, which are only the ones
+/// whose prefix is a word
+pub fn completion_items(snippets: &[Snippet], indentation: &str) -> Vec<CompletionItem> {
+    snippets
+        .iter()
+        .filter(|snippet| {
+            !snippet.prefix.is_empty()
+                && snippet
+                    .prefix
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || c == '_')
+        })
+        .map(|snippet| CompletionItem {
+            label: snippet.prefix.clone(),
+            detail_opt: Some(snippet.name.clone()),
+            documentation_opt: snippet.description_opt.clone(),
+            insert_text: indent(&snippet.body, indentation),
+            snippet: true,
+            filter_text: snippet.prefix.clone(),
+            sort_text: snippet.prefix.clone(),
+            start_opt: None,
+        })
+        .collect()
+}
+
+// Text of a snippet without its tab stops, and the ranges of the tab stops in the order they are
+// visited
+fn expand(snippet: &str) -> (String, Vec<(usize, usize)>) {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+    let mut text = String::with_capacity(snippet.len());
+    // Tab stops by number, and the starts of the placeholders that are open
+    let mut stops: Vec<(usize, usize, usize)> = Vec::new();
+    let mut open: Vec<(usize, usize)> = Vec::new();
+    let mut chars = snippet.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.peek() {
+                Some(&next @ ('$' | '}' | '\\' | ',' | '|')) => {
+                    text.push(next);
+                    chars.next();
+                }
+                _ => text.push(c),
+            },
+            '$' if chars.peek().is_some_and(|c| c.is_ascii_digit()) => {
+                let mut number = 0;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    number = number * 10 + digit as usize;
+                    chars.next();
+                }
+                stops.push((number, text.len(), text.len()));
+            }
+            '$' if chars.peek() == Some(&'{') => {
+                chars.next();
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|c| is_word(**c)) {
+                    name.push(c);
+                    chars.next();
+                }
+                // Variables are not known, so they are treated as placeholders without a number
+                let number = name.parse().unwrap_or(usize::MAX);
+                match chars.next() {
+                    Some(':') => open.push((number, text.len())),
+                    Some('|') => {
+                        // The first of the choices is used
+                        let mut choice = true;
+                        for c in chars.by_ref() {
+                            match c {
+                                '|' => break,
+                                ',' => choice = false,
+                                _ if choice => text.push(c),
+                                _ => {}
+                            }
+                        }
+                        chars.next();
+                        stops.push((number, text.len(), text.len()));
+                    }
+                    _ => stops.push((number, text.len(), text.len())),
+                }
+            }
+            '$' if chars.peek().is_some_and(|c| is_word(*c)) => {
+                while chars.peek().is_some_and(|c| is_word(*c)) {
+                    chars.next();
+                }
+            }
+            '}' if !open.is_empty() => {
+                let (number, start) = open.pop().unwrap();
+                stops.push((number, start, text.len()));
+            }
+            _ => text.push(c),
+        }
+    }
+
+    // Numbered stops are visited first, and the text of a number after its first stop is not
+    // mirrored
+    stops.sort_by_key(|(number, start, _end)| (*number, *start));
+    stops.dedup_by_key(|(number, _start, _end)| *number);
+    // $0 is the final position, which is the end if it is not given
+    let last = match stops.first() {
+        Some((0, start, end)) => (*start, *end),
+        _ => (text.len(), text.len()),
+    };
+    let mut ranges: Vec<(usize, usize)> = stops
+        .into_iter()
+        .filter(|(number, _start, _end)| *number != 0)
+        .map(|(_number, start, end)| (start, end))
+        .collect();
+    ranges.push(last);
+    (text, ranges)
+}
+
+// Position of a byte index in text inserted at `start`
+fn offset_cursor(start: Cursor, text: &str, index: usize) -> Cursor {
+    let before = &text[..index];
+    match before.rfind('\n') {
+        Some(newline) => Cursor::new(
+            start.line + before.matches('\n').count(),
+            index - newline - 1,
+        ),
+        None => Cursor::new(start.line, start.index + index),
+    }
+}
+
+/// Tab stops of an inserted snippet that are visited with Tab
+#[derive(Clone, Debug)]
+pub struct TabStops {
+    // Start of the selected stop, which does not move while typing in it
+    current: Cursor,
+    // The stops after it, which move with edits before them
+    next: Vec<(FromEnd, FromEnd)>,
+}
+
+impl TabStops {
+    /// Whether the cursor is still in the selected stop, so that Tab can go to the next one
+    pub fn contains(&self, editor: &ViEditor<'static, 'static>) -> bool {
+        let cursor = editor.cursor();
+        editor.with_buffer(|buffer| {
+            let end = self
+                .next
+                .first()
+                .map_or(cursor, |(start, _end)| start.cursor(buffer));
+            self.current <= cursor && cursor <= end
+        })
+    }
+
+    /// Select the next stop, returning false if the last one was selected
+    pub fn next(&mut self, editor: &mut ViEditor<'static, 'static>) -> bool {
+        if self.next.is_empty() {
+            return false;
+        }
+        let (start, end) = self.next.remove(0);
+        let (start, end) = editor.with_buffer(|buffer| (start.cursor(buffer), end.cursor(buffer)));
+        self.current = start;
+        select(editor, start, end);
+        !self.next.is_empty()
+    }
+}
+
+fn select(editor: &mut ViEditor<'static, 'static>, start: Cursor, end: Cursor) {
+    editor.set_cursor(end);
+    if start == end {
+        editor.set_selection(Selection::None);
+    } else {
+        // Typing replaces the placeholder
+        editor.set_selection(Selection::Normal(start));
+    }
+    editor.set_redraw(true);
+}
+
+/// Replace the text from `start` to the cursor with a snippet, selecting its first tab stop and
+/// returning the other ones if there are some
+pub fn insert(
+    editor: &mut ViEditor<'static, 'static>,
+    start: Cursor,
+    snippet: &str,
+) -> Option<TabStops> {
+    let cursor = editor.cursor();
+    let (text, ranges) = expand(snippet);
+
+    editor.start_change();
+    editor.delete_range(start, cursor);
+    editor.insert_at(start, &text, None);
+    editor.finish_change();
+
+    let mut ranges = ranges.into_iter().map(|(stop_start, stop_end)| {
+        (
+            offset_cursor(start, &text, stop_start),
+            offset_cursor(start, &text, stop_end),
+        )
+    });
+    let (first_start, first_end) = ranges.next()?;
+    let next: Vec<(FromEnd, FromEnd)> = editor.with_buffer(|buffer| {
+        ranges
+            .map(|(stop_start, stop_end)| {
+                (
+                    FromEnd::new(buffer, stop_start),
+                    FromEnd::new(buffer, stop_end),
+                )
+            })
+            .collect()
+    });
+    select(editor, first_start, first_end);
+    (!next.is_empty()).then_some(TabStops {
+        current: first_start,
+        next,
+    })
+}
@@ -0,0 +1,350 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::iced::futures::future;
+use cosmic_text::Cursor;
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    io::{self, Write},
+    ops::Range,
+    path::PathBuf,
+    process::Stdio,
+};
+use tokio::{io::AsyncWriteExt, process::Command};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::comment::{self, Tokens};
+
+// Spell checkers with the pipe mode of ispell, the first one that is installed is used
+const PROGRAMS: &[&str] = &["hunspell", "enchant-2"];
+
+/// A word that the spell checker does not know, with the words it suggests instead
+#[derive(Clone, Debug)]
+pub struct Misspelling {
+    pub start: Cursor,
+    pub end: Cursor,
+    pub word: String,
+    pub suggestions: Vec<String>,
+}
+
+// Folders that hunspell and enchant find dictionaries in
+fn dictionary_dirs() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(data_dir) = dirs::data_dir() {
+        dirs.push(data_dir.join("hunspell"));
+    }
+    for dir in [
+        "/usr/share/hunspell",
+        "/usr/share/myspell",
+        "/usr/share/myspell/dicts",
+    ] {
+        dirs.push(PathBuf::from(dir));
+    }
+    dirs
+}
+
+/// Names of the installed dictionaries, like `en_US`, sorted
+pub fn dictionaries() -> Vec<String> {
+    let mut names = Vec::new();
+    for dir in dictionary_dirs() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_some_and(|extension| extension == "dic")
+                && let Some(name) = path.file_stem().and_then(|stem| stem.to_str())
+                // Hyphenation patterns are not spelling dictionaries
+                && !name.starts_with("hyph_")
+            {
+                names.push(name.to_string());
+            }
+        }
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// Dictionary of the language of the desktop, like `en_US` for `en_US.UTF-8` or `en_GB` for
+/// `en_IE.UTF-8` if there is no better one
+pub fn locale_dictionary(dictionaries: &[String]) -> Option<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .iter()
+        .find_map(|name| env::var(name).ok().filter(|value| !value.is_empty()))?;
+    let name = locale.split(['.', '@']).next()?;
+    let language = name.split(['_', '-']).next()?;
+    dictionaries
+        .iter()
+        .find(|dictionary| *dictionary == name)
+        .or_else(|| {
+            dictionaries
+                .iter()
+                .find(|dictionary| dictionary.split(['_', '-']).next() == Some(language))
+        })
+        .cloned()
+}
+
+// Words added to the dictionary, one on each line
+fn user_dictionary_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cosmic-edit").join("dictionary.txt"))
+}
+
+/// Words that were added to the user dictionary, which are never misspelled
+pub fn user_words() -> HashSet<String> {
+    let Some(path) = user_dictionary_path() else {
+        return HashSet::new();
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => text
+            .lines()
+            .map(str::trim)
+            .filter(|word| !word.is_empty())
+            .map(str::to_string)
+            .collect(),
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::warn!("failed to read {:?}: {}", path, err);
+            }
+            HashSet::new()
+        }
+    }
+}
+
+/// Add a word to the user dictionary, which is shared by all languages
+pub fn add(word: &str) -> io::Result<()> {
+    let path = user_dictionary_path()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no config directory"))?;
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    writeln!(file, "{word}")
+}
+
+/// Documents of these syntaxes are checked everywhere, others only in comments and strings
+pub fn is_prose(syntax_name: &str) -> bool {
+    matches!(
+        syntax_name,
+        "Plain Text"
+            | "Git Commit"
+            | "LaTeX"
+            | "Markdown"
+            | "MultiMarkdown"
+            | "reStructuredText"
+            | "TeX"
+            | "Textile"
+    )
+}
+
+// Parts of a line that are in comments or double quoted strings, `in_block` is true while a
+// block comment continues to the next line
+fn code_ranges(text: &str, tokens: Option<Tokens>, in_block: &mut bool) -> Vec<Range<usize>> {
+    let line_token = tokens.and_then(|tokens| tokens.line);
+    let block_tokens = tokens.and_then(|tokens| tokens.block);
+    let mut ranges = Vec::new();
+    let mut start = 0;
+    let mut quoted = false;
+    let mut i = 0;
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+        if *in_block {
+            if let Some((_, end)) = block_tokens
+                && rest.starts_with(end)
+            {
+                ranges.push(start..i);
+                *in_block = false;
+                i += end.len();
+                continue;
+            }
+        } else if quoted {
+            if c == '\\' {
+                // Skip the escaped character
+                i += c.len_utf8();
+                match text[i..].chars().next() {
+                    Some(escaped) => i += escaped.len_utf8(),
+                    None => break,
+                }
+                continue;
+            } else if c == '"' {
+                ranges.push(start..i);
+                quoted = false;
+            }
+        } else if line_token.is_some_and(|token| rest.starts_with(token)) {
+            ranges.push(i..text.len());
+            return ranges;
+        } else if let Some((begin, _)) = block_tokens
+            && rest.starts_with(begin)
+        {
+            i += begin.len();
+            start = i;
+            *in_block = true;
+            continue;
+        } else if c == '"' {
+            start = i + 1;
+            quoted = true;
+        }
+        i += c.len_utf8();
+    }
+    if *in_block {
+        ranges.push(start..text.len());
+    }
+    ranges
+}
+
+// Identifiers like camelCase, acronyms, and words with digits are not checked
+fn is_checked_word(word: &str) -> bool {
+    let mut chars = word.chars();
+    chars.next().is_some_and(char::is_alphabetic)
+        && chars.clone().next().is_some()
+        && chars.all(|c| (c.is_alphabetic() && !c.is_uppercase()) || c == '\'' || c == '\u{2019}')
+}
+
+/// Words to check in the lines of a document, with their positions
+pub fn words(lines: &[String], syntax_name: &str) -> Vec<(Cursor, Cursor, String)> {
+    let prose = is_prose(syntax_name);
+    let tokens = comment::tokens(syntax_name);
+    let mut in_block = false;
+    let mut words = Vec::new();
+    for (line_i, text) in lines.iter().enumerate() {
+        let ranges = if prose {
+            vec![0..text.len()]
+        } else {
+            code_ranges(text, tokens, &mut in_block)
+        };
+        for range in ranges {
+            let offset = range.start;
+            for (index, word) in text[range].split_word_bound_indices() {
+                if is_checked_word(word) {
+                    words.push((
+                        Cursor::new(line_i, offset + index),
+                        Cursor::new(line_i, offset + index + word.len()),
+                        word.to_string(),
+                    ));
+                }
+            }
+        }
+    }
+    words
+}
+
+// Run the first spell checker that is installed with the input on stdin
+async fn run(args: &[&str], input: String) -> io::Result<String> {
+    for program in PROGRAMS {
+        let mut command = Command::new(program);
+        command
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        let stdin_opt = child.stdin.take();
+        let input = input.clone();
+        let write = async move {
+            if let Some(mut stdin) = stdin_opt {
+                stdin.write_all(input.as_bytes()).await?;
+            }
+            Ok::<(), io::Error>(())
+        };
+        let (write_res, output_res) = future::join(write, child.wait_with_output()).await;
+        let output = output_res?;
+        if !output.status.success() {
+            let mut msg = format!("{} exited with {}", program, output.status);
+            for line in String::from_utf8_lossy(&output.stderr).lines() {
+                msg.push_str("\nstderr> ");
+                msg.push_str(line);
+            }
+            return Err(io::Error::other(msg));
+        }
+        write_res?;
+        return Ok(String::from_utf8_lossy(&output.stdout).into_owned());
+    }
+    Err(io::Error::new(
+        io::ErrorKind::NotFound,
+        format!("none of {:?} are installed", PROGRAMS),
+    ))
+}
+
+/// Find the words that are misspelled in every one of the dictionaries, with the suggestions of
+/// each, or in the default dictionary of the spell checker if none are given
+pub async fn check(
+    words: HashSet<String>,
+    dictionaries: Vec<String>,
+) -> io::Result<HashMap<String, Vec<String>>> {
+    if dictionaries.is_empty() {
+        return check_dictionary(words, None).await;
+    }
+    let mut misspelled_opt: Option<HashMap<String, Vec<String>>> = None;
+    for dictionary in dictionaries {
+        // Only the words that earlier dictionaries did not know are checked again
+        let words = match &misspelled_opt {
+            Some(misspelled) => misspelled.keys().cloned().collect(),
+            None => words.clone(),
+        };
+        let found = check_dictionary(words, Some(&dictionary)).await?;
+        misspelled_opt = Some(match misspelled_opt {
+            Some(mut misspelled) => {
+                misspelled.retain(|word, _| found.contains_key(word));
+                for (word, suggestions) in found {
+                    if let Some(all) = misspelled.get_mut(&word) {
+                        all.extend(suggestions.into_iter().filter(|s| !all.contains(s)));
+                    }
+                }
+                misspelled
+            }
+            None => found,
+        });
+    }
+    Ok(misspelled_opt.unwrap_or_default())
+}
+
+async fn check_dictionary(
+    words: HashSet<String>,
+    dictionary_opt: Option<&str>,
+) -> io::Result<HashMap<String, Vec<String>>> {
+    if words.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let words: Vec<String> = words.into_iter().collect();
+    // A leading ^ keeps words from being read as commands
+    let mut input = String::new();
+    for word in &words {
+        input.push('^');
+        input.push_str(word);
+        input.push('\n');
+    }
+    let output = match dictionary_opt {
+        Some(dictionary) => run(&["-a", "-d", dictionary], input).await?,
+        None => run(&["-a"], input).await?,
+    };
+
+    // After a line with the version, each line of input is answered by a line for each word in
+    // it and then an empty line
+    let mut misspelled = HashMap::new();
+    let mut lines = output.lines().skip(1);
+    for word in words {
+        for line in lines.by_ref() {
+            if line.is_empty() {
+                break;
+            }
+            if let Some(rest) = line.strip_prefix("& ") {
+                let suggestions = rest
+                    .split_once(": ")
+                    .map(|(_, list)| list.split(", ").map(str::to_string).collect())
+                    .unwrap_or_default();
+                misspelled.insert(word.clone(), suggestions);
+            } else if line.starts_with("# ") {
+                misspelled.insert(word.clone(), Vec::new());
+            }
+        }
+    }
+    Ok(misspelled)
+}
@@ -0,0 +1,264 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Buffer, SyntaxSystem};
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use syntect::{
+    highlighting::{Color, ThemeSet},
+    parsing::{SyntaxReference, SyntaxSet},
+};
+
+use crate::SYNTAX_SYSTEM;
+
+/// Folder of syntax themes added by the user, which are named after their `.tmTheme` files
+pub fn themes_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cosmic-edit").join("themes"))
+}
+
+/// Folder of syntax definitions added by the user, in `.sublime-syntax` files
+pub fn syntaxes_dir() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cosmic-edit").join("syntaxes"))
+}
+
+// Themes in the files of a folder with this extension, by file name
+fn load_themes(theme_set: &mut ThemeSet, dir: &Path, extension: &str) {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return,
+        Err(err) => {
+            log::warn!("failed to read {:?}: {}", dir, err);
+            return;
+        }
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.extension().is_none_or(|ext| ext != extension) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+        match ThemeSet::get_theme(&path) {
+            Ok(theme) => {
+                theme_set.themes.insert(name.to_string(), theme);
+            }
+            Err(err) => log::warn!("failed to load syntax theme {:?}: {}", path, err),
+        }
+    }
+}
+
+fn load() -> SyntaxSystem {
+    let lazy_theme_set = two_face::theme::LazyThemeSet::from(two_face::theme::extra());
+    let mut theme_set = ThemeSet::from(&lazy_theme_set);
+    // Hardcoded COSMIC themes
+    for (theme_name, theme_data) in &[
+        ("COSMIC Dark", cosmic_syntax_theme::COSMIC_DARK_TM_THEME),
+        ("COSMIC Light", cosmic_syntax_theme::COSMIC_LIGHT_TM_THEME),
+    ] {
+        let mut cursor = io::Cursor::new(theme_data);
+        match ThemeSet::load_from_reader(&mut cursor) {
+            Ok(mut theme) => {
+                // Use libcosmic theme for background and gutter
+                theme.settings.background = Some(Color {
+                    r: 0,
+                    g: 0,
+                    b: 0,
+                    a: 0,
+                });
+                theme.settings.gutter = Some(Color {
+                    r: 0,
+                    g: 0,
+                    b: 0,
+                    a: 0,
+                });
+                theme_set.themes.insert(theme_name.to_string(), theme);
+            }
+            Err(err) => {
+                log::error!("failed to load {:?} syntax theme: {}", theme_name, err);
+            }
+        }
+    }
+    if let Some(dir) = themes_dir() {
+        load_themes(&mut theme_set, &dir, "tmTheme");
+    }
+
+    //TODO: store newlines in buffer
+    let mut syntax_set = two_face::syntax::extra_no_newlines();
+    if let Some(dir) = syntaxes_dir().filter(|dir| dir.is_dir()) {
+        // Syntaxes of the user replace bundled ones with the same name
+        let mut builder = syntax_set.clone().into_builder();
+        match builder.add_from_folder(&dir, false) {
+            Ok(()) => syntax_set = builder.build(),
+            Err(err) => log::warn!("failed to load syntaxes from {:?}: {}", dir, err),
+        }
+    }
+    SyntaxSystem {
+        syntax_set,
+        theme_set,
+    }
+}
+
+/// Load the syntaxes and themes again, for the highlighting and the editors created after this.
+/// Editors that were already created keep the theme colors of the previous ones, which are leaked.
+pub fn reload() {
+    let syntax_system: &'static SyntaxSystem = Box::leak(Box::new(load()));
+    *SYNTAX_SYSTEM.write().unwrap() = Some(syntax_system);
+}
+
+// Match `*` to any characters and `?` to one character
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut p, mut t) = (0, 0);
+    // Position after the last star and the text it matched up to, where matching is retried
+    let mut star_opt = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            p += 1;
+            star_opt = Some((p, t));
+        } else if let Some((star_p, star_t)) = star_opt {
+            p = star_p;
+            t = star_t + 1;
+            star_opt = Some((star_p, t));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Syntax name of the longest glob pattern that matches the file name, or the whole path for
+/// patterns with a slash
+pub fn association<'a>(associations: &'a HashMap<String, String>, path: &Path) -> Option<&'a str> {
+    let file_name = path.file_name()?.to_str()?;
+    let path = path.to_str()?;
+    associations
+        .iter()
+        .filter(|(pattern, _)| {
+            glob_match(
+                pattern,
+                if pattern.contains('/') {
+                    path
+                } else {
+                    file_name
+                },
+            )
+        })
+        .max_by_key(|(pattern, _)| pattern.len())
+        .map(|(_, syntax_name)| syntax_name.as_str())
+}
+
+/// Lines at the start and end of a file that are searched for Vim modelines, like Vim does
+const MODELINES: usize = 5;
+
+// File type of a Vim modeline like `vim: set ft=python:` or `vi: syntax=sh`
+fn vim_file_type(line: &str) -> Option<&str> {
+    for marker in ["vim:", "vi:", "ex:"] {
+        let Some(index) = line.find(marker) else {
+            continue;
+        };
+        // The marker must start a word
+        if index > 0 && !line[..index].ends_with(char::is_whitespace) {
+            continue;
+        }
+        let options = line[index + marker.len()..].trim_start();
+        let options = options
+            .strip_prefix("set ")
+            .or_else(|| options.strip_prefix("se "))
+            .unwrap_or(options);
+        for option in options.split(|c: char| c == ':' || c.is_whitespace()) {
+            if let Some((name, value)) = option.split_once('=')
+                && matches!(name, "ft" | "filetype" | "syn" | "syntax")
+                && !value.is_empty()
+            {
+                return Some(value);
+            }
+        }
+    }
+    None
+}
+
+// Mode of an Emacs header like `-*- mode: python -*-` or `-*- python -*-`
+fn emacs_mode(line: &str) -> Option<&str> {
+    let start = line.find("-*-")? + 3;
+    let end = start + line[start..].find("-*-")?;
+    let header = line[start..end].trim();
+    if !header.contains(':') {
+        return Some(header).filter(|mode| !mode.is_empty());
+    }
+    header.split(';').find_map(|variable| {
+        let (name, value) = variable.split_once(':')?;
+        name.trim()
+            .eq_ignore_ascii_case("mode")
+            .then(|| value.trim())
+    })
+}
+
+// Syntax for a Vim file type or Emacs mode, which are mostly extensions or syntax names
+fn find_file_type<'a>(syntax_set: &'a SyntaxSet, file_type: &str) -> Option<&'a SyntaxReference> {
+    let file_type = file_type.strip_suffix("-mode").unwrap_or(file_type);
+    let file_type = match file_type {
+        "shell-script" => "sh",
+        other => other,
+    };
+    syntax_set.find_syntax_by_token(file_type)
+}
+
+/// Syntax named by a Vim modeline or an Emacs header in the text
+pub fn modeline<'a>(syntax_set: &'a SyntaxSet, buffer: &Buffer) -> Option<&'a SyntaxReference> {
+    let lines = &buffer.lines;
+    // Emacs headers are on the first line, or the second after a shebang
+    let emacs_opt = lines
+        .iter()
+        .take(2)
+        .find_map(|line| emacs_mode(line.text()))
+        .and_then(|mode| find_file_type(syntax_set, mode));
+    emacs_opt.or_else(|| {
+        let end = lines.len().saturating_sub(MODELINES).max(MODELINES);
+        lines
+            .iter()
+            .take(MODELINES)
+            .chain(lines.iter().skip(end))
+            .find_map(|line| vim_file_type(line.text()))
+            .and_then(|file_type| find_file_type(syntax_set, file_type))
+    })
+}
+
+/// Syntax matching the first line of the text, like a shebang such as `#!/usr/bin/env python3`
+pub fn first_line<'a>(syntax_set: &'a SyntaxSet, buffer: &Buffer) -> Option<&'a SyntaxReference> {
+    let line = buffer.lines.first()?.text();
+    syntax_set.find_syntax_by_first_line(line).or_else(|| {
+        // Interpreters without a first line pattern, found by name without the version
+        let command = line.strip_prefix("#!")?.trim();
+        let mut words = command.split_whitespace();
+        let mut program = words.next()?.rsplit('/').next()?;
+        if program == "env" {
+            program = words.find(|word| !word.starts_with('-'))?;
+        }
+        let program = program.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+        find_file_type(syntax_set, program)
+    })
+}
+
+/// Copy a theme file to the themes folder, returning the name of the theme
+pub fn import_theme(path: &Path) -> io::Result<String> {
+    let Some(dir) = themes_dir() else {
+        return Err(io::Error::other("no config folder"));
+    };
+    // Check that the theme loads before copying it
+    ThemeSet::get_theme(path).map_err(io::Error::other)?;
+    let name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .ok_or_else(|| io::Error::other("invalid theme file name"))?
+        .to_string();
+    fs::create_dir_all(&dir)?;
+    fs::copy(path, dir.join(format!("{name}.tmTheme")))?;
+    Ok(name)
+}
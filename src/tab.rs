@@ -2,35 +2,296 @@
 
 use cosmic::{
     iced::{Point, advanced::graphics::text::font_system},
-    widget::icon,
+    widget::{self, icon, segmented_button},
 };
 use cosmic_files::mime_icon::{FALLBACK_MIME_ICON, mime_for_path, mime_icon};
-use cosmic_text::{Attrs, Buffer, Cursor, Edit, Selection, Shaping, SyntaxEditor, ViEditor, Wrap};
+use cosmic_text::{
+    Attrs, AttrsList, Buffer, BufferLine, Color, Cursor, Edit, LineEnding, Selection, Shaping,
+    Style, SyntaxEditor, ViEditor, Weight, Wrap,
+};
+use encoding_rs::{Encoding, UTF_8};
 use regex::Regex;
 use std::{
+    borrow::Cow,
+    collections::HashMap,
     fs,
-    io::{self, Write},
-    path::{self, PathBuf},
-    process::{Command, Stdio},
+    hash::{BuildHasher, BuildHasherDefault, DefaultHasher},
+    io::{self, Read, Write},
+    mem,
+    path::{self, Path, PathBuf},
+    process::{self, Stdio},
     sync::{Arc, Mutex},
+    time::SystemTime,
 };
 
-use crate::{Config, SYNTAX_SYSTEM, fl, git::GitDiff};
+use syntect::highlighting::FontStyle;
+use tokio::{io::AsyncWriteExt, process::Command};
+
+use crate::{
+    Config,
+    compare::{self, CompareRow},
+    completion::Completion,
+    config::SessionTab,
+    conflict::{self, Conflict},
+    encoding, fl,
+    git::{GitBlame, GitDiff, GitDiffHunk, GitLineChange},
+    highlight,
+    lsp::{Diagnostic, Signature},
+    markdown::Block,
+    multi_cursor::{Caret, FromEnd},
+    outline::{self, Symbol},
+    remote,
+    search::LineSearchResult,
+    snippet::TabStops,
+    spell::Misspelling,
+    syntax,
+    text_box::EditorView,
+    undo_history::{self, UndoHistory},
+    vim::Vim,
+};
 
-fn editor_text(editor: &ViEditor<'static, 'static>) -> String {
+// Lines keep their own endings, except that LF endings are written as `lf_opt` if it is set
+fn editor_text(editor: &ViEditor<'static, 'static>, lf_opt: Option<LineEnding>) -> String {
     editor.with_buffer(|buffer| {
         let mut text = String::new();
         for line in buffer.lines.iter() {
             text.push_str(line.text());
-            text.push_str(line.ending().as_str());
+            match (line.ending(), lf_opt) {
+                (LineEnding::Lf, Some(line_ending)) => text.push_str(line_ending.as_str()),
+                (ending, _) => text.push_str(ending.as_str()),
+            }
         }
         text
     })
 }
 
+// Hashes of the text and endings of lines, to find the lines that changed. Documents with LF
+// endings need none, as new lines already have LF endings
+fn line_hashes(buffer: &Buffer, line_ending: LineEnding) -> Vec<(u64, LineEnding)> {
+    if line_ending == LineEnding::Lf {
+        return Vec::new();
+    }
+    buffer
+        .lines
+        .iter()
+        .map(|line| (highlight::text_hash(line.text()), line.ending()))
+        .collect()
+}
+
+// Most common line ending of the first lines, LF if there are none
+fn detect_line_ending(buffer: &Buffer) -> LineEnding {
+    let mut crlf = 0;
+    let mut lf = 0;
+    for line in buffer.lines.iter().take(SAMPLE_LINES) {
+        match line.ending() {
+            LineEnding::CrLf => crlf += 1,
+            LineEnding::Lf => lf += 1,
+            _ => {}
+        }
+    }
+    if crlf > lf {
+        LineEnding::CrLf
+    } else {
+        LineEnding::Lf
+    }
+}
+
+/// Files larger than this are loaded in chunks, without syntax highlighting and word wrap
+pub const LARGE_FILE_SIZE: u64 = 32 * 1024 * 1024;
+// Bytes read at a time when loading large files
+const CHUNK_SIZE: usize = 1024 * 1024;
+// Lines looked at to guess the indentation and line ending of a document
+const SAMPLE_LINES: usize = 1000;
+
+/// Read the lines of a large file in chunks, calling `f` with each chunk and the fraction of the
+/// file read so far until it returns false
+pub fn read_chunks(
+    path: &Path,
+    encoding: &'static Encoding,
+    mut f: impl FnMut(Vec<(String, LineEnding)>, f32) -> bool,
+) -> io::Result<()> {
+    let mut file = fs::File::open(path)?;
+    let len = file.metadata()?.len().max(1);
+    let mut decoder = encoding.new_decoder_with_bom_removal();
+    let mut bytes = vec![0; CHUNK_SIZE];
+    let mut read = 0;
+    let mut text = String::new();
+    loop {
+        let count = file.read(&mut bytes)?;
+        let last = count == 0;
+        read += count as u64;
+
+        if let Some(max_len) = decoder.max_utf8_buffer_length(count) {
+            text.reserve(max_len);
+        }
+        let (_result, _read, had_errors) =
+            decoder.decode_to_string(&bytes[..count], &mut text, last);
+        if had_errors {
+            log::warn!("replaced invalid {} sequences", encoding.name());
+        }
+
+        // Only complete lines are sent, the rest is kept for the next chunk
+        let complete = if last {
+            text.len()
+        } else {
+            text.rfind('\n').map_or(0, |i| i + 1)
+        };
+        if complete > 0 {
+            let rest = text.split_off(complete);
+            let mut lines = Vec::new();
+            for line in text.split_inclusive('\n') {
+                lines.push(if let Some(line) = line.strip_suffix("\r\n") {
+                    (line.to_string(), LineEnding::CrLf)
+                } else if let Some(line) = line.strip_suffix('\n') {
+                    (line.to_string(), LineEnding::Lf)
+                } else {
+                    (line.to_string(), LineEnding::None)
+                });
+            }
+            if !f(lines, read as f32 / len as f32) {
+                return Ok(());
+            }
+            text = rest;
+        }
+
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+// Copy ownership and extended attributes, which may fail without affecting the saved text
+#[cfg(unix)]
+fn copy_unix_metadata(from: &Path, metadata: &fs::Metadata, to: &Path) {
+    use std::os::unix::fs::MetadataExt;
+
+    // Changing the owner needs privileges, but the group can often still be set
+    if let Err(err) = std::os::unix::fs::chown(to, Some(metadata.uid()), Some(metadata.gid())) {
+        log::debug!("failed to copy owner of {:?}: {}", from, err);
+        if let Err(err) = std::os::unix::fs::chown(to, None, Some(metadata.gid())) {
+            log::debug!("failed to copy group of {:?}: {}", from, err);
+        }
+    }
+
+    match xattr::list(from) {
+        Ok(names) => {
+            for name in names {
+                match xattr::get(from, &name) {
+                    Ok(Some(value)) => {
+                        if let Err(err) = xattr::set(to, &name, &value) {
+                            log::debug!(
+                                "failed to copy attribute {:?} of {:?}: {}",
+                                name,
+                                from,
+                                err
+                            );
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(err) => {
+                        log::debug!("failed to read attribute {:?} of {:?}: {}", name, from, err);
+                    }
+                }
+            }
+        }
+        Err(err) => {
+            log::debug!("failed to list attributes of {:?}: {}", from, err);
+        }
+    }
+}
+
+/// Write to a temporary file that replaces `path` when complete, so that a crash while writing
+/// cannot truncate the file
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let metadata_opt = match fs::metadata(path) {
+        Ok(metadata) => Some(metadata),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+        Err(err) => return Err(err),
+    };
+
+    #[cfg(unix)]
+    if let Some(metadata) = &metadata_opt {
+        use std::os::unix::fs::MetadataExt;
+
+        // Replacing the file would separate it from its other hard links
+        if metadata.nlink() > 1 {
+            log::info!("writing {:?} in place as it has hard links", path);
+            return fs::write(path, bytes);
+        }
+    }
+
+    let (Some(parent), Some(file_name)) = (path.parent(), path.file_name()) else {
+        return fs::write(path, bytes);
+    };
+    let temp_path = parent.join(format!(
+        ".{}.{}.tmp",
+        file_name.to_string_lossy(),
+        process::id()
+    ));
+    let mut file = match fs::File::create_new(&temp_path) {
+        Ok(ok) => ok,
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            // The folder may not be writable even if the file is
+            log::info!("writing {:?} in place: {}", path, err);
+            return fs::write(path, bytes);
+        }
+        Err(err) => return Err(err),
+    };
+
+    let res = file
+        .write_all(bytes)
+        .and_then(|()| file.sync_all())
+        .and_then(|()| match &metadata_opt {
+            Some(metadata) => {
+                // Ownership is copied first as changing it can clear permission bits
+                #[cfg(unix)]
+                copy_unix_metadata(path, metadata, &temp_path);
+                fs::set_permissions(&temp_path, metadata.permissions())
+            }
+            None => Ok(()),
+        })
+        .and_then(|()| fs::rename(&temp_path, path));
+    if res.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    res
+}
+
+/// Write with `pkexec tee`, as polkit can let an administrator change files of other users. This
+/// waits for the password to be entered, so it runs as a task
+pub async fn write_elevated(path: PathBuf, bytes: Vec<u8>) -> io::Result<()> {
+    let mut child = Command::new("pkexec")
+        .arg("tee")
+        .arg("--")
+        .arg(&path)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::inherit())
+        .spawn()?;
+    // Closing stdin ends the input of tee
+    let write_res = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(&bytes).await,
+        None => Err(io::Error::other("no stdin for pkexec")),
+    };
+    let status = child.wait().await?;
+    write_res?;
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("pkexec exited with {status}")))
+    }
+}
+
 pub enum Tab {
     Editor(EditorTab),
     GitDiff(GitDiffTab),
+    Compare(CompareTab),
 }
 
 impl Tab {
@@ -38,6 +299,7 @@ impl Tab {
         match self {
             Self::Editor(tab) => tab.title(),
             Self::GitDiff(tab) => tab.title.clone(),
+            Self::Compare(tab) => tab.title.clone(),
         }
     }
 }
@@ -47,17 +309,151 @@ pub struct GitDiffTab {
     pub diff: GitDiff,
 }
 
+/// Two files side by side, with their differences aligned
+pub struct CompareTab {
+    pub title: String,
+    pub old_path: PathBuf,
+    pub new_path: PathBuf,
+    pub rows: Vec<CompareRow>,
+    // Rows where each difference starts, and the one that was navigated to
+    pub differences: Vec<usize>,
+    pub difference_opt: Option<usize>,
+    pub scrollable_id: widget::Id,
+}
+
+impl CompareTab {
+    pub fn new(old_path: PathBuf, new_path: PathBuf, rows: Vec<CompareRow>) -> Self {
+        let file_name = |path: &PathBuf| {
+            path.file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .to_string()
+        };
+        Self {
+            title: format!("{} \u{2194} {}", file_name(&old_path), file_name(&new_path)),
+            old_path,
+            new_path,
+            differences: compare::differences(&rows),
+            rows,
+            difference_opt: None,
+            scrollable_id: widget::Id::unique(),
+        }
+    }
+
+    /// Move to the next or previous difference, returning its row
+    pub fn go_to_difference(&mut self, forwards: bool) -> Option<usize> {
+        let len = self.differences.len();
+        if len == 0 {
+            return None;
+        }
+        let difference = match self.difference_opt {
+            Some(difference) if forwards => (difference + 1) % len,
+            Some(difference) => (difference + len - 1) % len,
+            None if forwards => 0,
+            None => len - 1,
+        };
+        self.difference_opt = Some(difference);
+        Some(self.differences[difference])
+    }
+}
+
 pub struct EditorTab {
     pub path_opt: Option<PathBuf>,
+    // Location of a file opened with GIO, like sftp://host/file, which has no path
+    pub uri_opt: Option<String>,
+    atomic_save: bool,
+    save_undo_history_panel: bool,
     attrs: Attrs<'static>,
     pub editor: Mutex<ViEditor<'static, 'static>>,
+    // Carets in addition to the cursor of the editor
+    pub carets: Mutex<Vec<Caret>>,
+    // View of the pane without focus, when both panes of a split show this tab
+    pub split_view_opt: Option<Mutex<EditorView>>,
+    // Registers, marks, and the visual block of Vim bindings
+    pub vim: Mutex<Vim>,
+    // Find and replace only look between these positions, the end moves with edits before it
+    find_scope: Mutex<Option<(Cursor, FromEnd)>>,
     pub context_menu: Option<Point>,
+    // Lines that differ from the file on disk, and the request they came from
+    pub changed_lines: Vec<usize>,
+    pub changed_lines_request: u64,
+    // Differences from the file at HEAD, its changed lines, and the request they came from
+    pub git_diff_opt: Option<GitDiff>,
+    pub git_changes: Vec<(usize, GitLineChange)>,
+    pub git_changes_request: u64,
+    // Commits that last changed each line, and the request they came from
+    pub git_blame_opt: Option<GitBlame>,
+    pub git_blame_request: u64,
+    // Index of the hunk previewed from the gutter, and where the preview is
+    pub git_hunk_popup: Option<(usize, Point)>,
+    // Merge conflicts in the text, these are not searched in large files
+    pub conflicts: Vec<Conflict>,
+    // Problems reported by the language server, which is only set while one is used
+    pub diagnostics_opt: Option<Vec<Diagnostic>>,
+    // Completions shown while typing, and the request they come from
+    pub completion_opt: Option<Completion>,
+    pub completion_request: u64,
+    // Start of the word whose completions were accepted or dismissed, so they stay closed
+    pub completion_closed: Option<Cursor>,
+    // Tab stops of the snippet being filled in, and whether Tab is used for snippets
+    pub snippet_stops_opt: Option<TabStops>,
+    pub snippet_tab: bool,
+    // Where the cursor was last drawn, so that popups can be shown next to it
+    pub caret_point: Mutex<Option<Point>>,
+    // Text position and point under the mouse, with the information about it once known
+    pub hover_opt: Option<(Cursor, Point, Option<String>)>,
+    pub hover_request: u64,
+    // Signature of the call at the cursor, with the line it was asked for on
+    pub signature_opt: Option<(usize, Signature)>,
+    pub signature_request: u64,
+    // Formatting in progress, and the request that the file is saved after
+    pub format_request: u64,
+    pub save_after_format: Option<u64>,
+    // Symbols shown in the outline, and the request they come from
+    pub outline_opt: Option<Vec<Symbol>>,
+    pub outline_request: u64,
+    // Rendered parts of a Markdown document, which are only found while the preview is shown
+    pub markdown_blocks: Vec<Block>,
+    // Words that the spell checker does not know, sorted by their start
+    pub misspellings: Vec<Misspelling>,
+    pub spelling_request: u64,
+    // Dictionaries chosen for this document, the default ones are used when empty
+    pub spell_languages: Vec<String>,
+    // Set when the file changed on disk while there were unsaved changes
+    pub disk_changed: bool,
+    // Modification time of the file when it was last loaded or saved
+    modified_opt: Option<SystemTime>,
+    pub encoding: &'static Encoding,
+    // Encoding that the user agreed to save with, even though it cannot encode all characters
+    pub lossy_encoding_opt: Option<&'static Encoding>,
+    // Line ending used when saving, and the one the file had on disk
+    line_ending: LineEnding,
+    saved_line_ending: LineEnding,
+    // Lines as of the previous change, see EditorTab::update_new_lines
+    line_hashes: Vec<(u64, LineEnding)>,
+    // Large files are loaded in chunks and have expensive features disabled
+    pub large: bool,
+    // Fraction of a large file loaded, until loading is finished
+    pub loading_opt: Option<f32>,
+    // Loading starts over when the file is reloaded, chunks of earlier requests are ignored
+    pub loading_request: u64,
+    loaded_lines: usize,
+    pub syntax_override: Option<String>,
+    syntax_associations: HashMap<String, String>,
+    syntax_name: String,
+    // Set when the editor replaced the colors of all lines
+    highlight_reset: bool,
+    // Hashes of the lines sent to the highlight worker, to find the lines that changed since
+    highlight_hashes: Vec<u64>,
     pub zoom_adj: i8,
+    pub undo_history: UndoHistory,
+    // Set when the file was saved, until the undo history is written to the cache
+    undo_history_unsaved: bool,
 }
 
 impl EditorTab {
     pub fn new(config: &Config) -> Self {
-        let attrs = crate::monospace_attrs();
+        let attrs = config.attrs();
         let zoom_adj = Default::default();
         let mut buffer = Buffer::new_empty(config.metrics(zoom_adj));
         buffer.set_text(
@@ -70,17 +466,74 @@ impl EditorTab {
 
         let editor = SyntaxEditor::new(
             Arc::new(buffer),
-            SYNTAX_SYSTEM.get().unwrap(),
+            crate::syntax_system(),
             config.syntax_theme(),
         )
         .unwrap();
 
         let mut tab = Self {
             path_opt: None,
+            uri_opt: None,
+            atomic_save: config.atomic_save,
+            save_undo_history_panel: config.save_undo_history_panel,
             attrs,
             editor: Mutex::new(ViEditor::new(editor)),
+            carets: Mutex::new(Vec::new()),
+            split_view_opt: None,
+            vim: Mutex::new(Vim::default()),
+            find_scope: Mutex::new(None),
             context_menu: None,
+            changed_lines: Vec::new(),
+            changed_lines_request: 0,
+            git_diff_opt: None,
+            git_changes: Vec::new(),
+            git_changes_request: 0,
+            git_blame_opt: None,
+            git_blame_request: 0,
+            git_hunk_popup: None,
+            conflicts: Vec::new(),
+            diagnostics_opt: None,
+            completion_opt: None,
+            completion_request: 0,
+            completion_closed: None,
+            snippet_stops_opt: None,
+            snippet_tab: false,
+            caret_point: Mutex::new(None),
+            hover_opt: None,
+            hover_request: 0,
+            signature_opt: None,
+            signature_request: 0,
+            format_request: 0,
+            save_after_format: None,
+            outline_opt: None,
+            outline_request: 0,
+            markdown_blocks: Vec::new(),
+            misspellings: Vec::new(),
+            spelling_request: 0,
+            spell_languages: Vec::new(),
+            disk_changed: false,
+            modified_opt: None,
+            encoding: UTF_8,
+            lossy_encoding_opt: None,
+            line_ending: LineEnding::Lf,
+            saved_line_ending: LineEnding::Lf,
+            line_hashes: Vec::new(),
+            large: false,
+            loading_opt: None,
+            loading_request: 0,
+            loaded_lines: 0,
+            syntax_override: None,
+            syntax_associations: config.syntax_associations.clone(),
+            syntax_name: crate::syntax_system()
+                .syntax_set
+                .find_syntax_plain_text()
+                .name
+                .clone(),
+            highlight_reset: true,
+            highlight_hashes: Vec::new(),
             zoom_adj,
+            undo_history: UndoHistory::default(),
+            undo_history_unsaved: false,
         };
 
         // Update any other config settings
@@ -90,6 +543,13 @@ impl EditorTab {
     }
 
     pub fn set_config(&mut self, config: &Config) {
+        self.atomic_save = config.atomic_save;
+        self.save_undo_history_panel = config.save_undo_history_panel;
+        self.syntax_associations
+            .clone_from(&config.syntax_associations);
+        let attrs = config.attrs();
+        let attrs_changed = attrs != self.attrs;
+        self.attrs = attrs;
         let mut editor = self.editor.lock().unwrap();
         let mut font_system = font_system().write().unwrap();
         let mut editor = editor.borrow_with(font_system.raw());
@@ -97,7 +557,14 @@ impl EditorTab {
         editor.set_passthrough(!config.vim_bindings);
         editor.set_tab_width(config.tab_width);
         editor.with_buffer_mut(|buffer| {
-            buffer.set_wrap(if config.word_wrap {
+            // Lines are shaped again with the new font features, highlighting is redone below
+            if attrs_changed {
+                for line in buffer.lines.iter_mut() {
+                    line.set_attrs_list(AttrsList::new(&self.attrs));
+                }
+                buffer.set_redraw(true);
+            }
+            buffer.set_wrap(if config.word_wrap && !self.large {
                 Wrap::WordOrGlyph
             } else {
                 Wrap::None
@@ -105,12 +572,10 @@ impl EditorTab {
         });
         //TODO: dynamically discover light/dark changes
         editor.update_theme(config.syntax_theme());
+        self.highlight_reset = true;
     }
 
     pub fn open(&mut self, path: PathBuf) {
-        let mut editor = self.editor.lock().unwrap();
-        let mut font_system = font_system().write().unwrap();
-        let mut editor = editor.borrow_with(font_system.raw());
         let absolute = match fs::canonicalize(&path) {
             Ok(ok) => ok,
             Err(err) => match path::absolute(&path) {
@@ -121,16 +586,50 @@ impl EditorTab {
                 }
             },
         };
-        match editor.load_text(&absolute, self.attrs.clone()) {
+        let res = {
+            let mut editor = self.editor.lock().unwrap();
+            let mut font_system = font_system().write().unwrap();
+            let mut editor = editor.borrow_with(font_system.raw());
+            match fs::read(&absolute) {
+                Ok(bytes) => {
+                    self.encoding = encoding::detect(&bytes);
+                    if self.encoding == UTF_8 {
+                        editor.load_text(&absolute, self.attrs.clone())
+                    } else {
+                        // Text that is not UTF-8 is converted before loading
+                        let text = encoding::decode(&bytes, self.encoding);
+                        editor.with_buffer_mut(|buffer| {
+                            buffer.set_text(&text, &self.attrs, Shaping::Advanced, None)
+                        });
+                        Ok(())
+                    }
+                }
+                Err(err) => Err(err),
+            }
+        };
+        match res {
             Ok(()) => {
-                log::info!("opened {:?}", absolute);
+                log::info!("opened {:?} as {}", absolute, self.encoding.name());
+                self.modified_opt = modified_time(&absolute);
+                self.update_line_ending();
                 self.path_opt = Some(absolute);
+                // Modelines and the first line are read from the loaded text
+                self.update_syntax();
+                self.update_conflicts();
+                let editor = self.editor.lock().unwrap();
+                self.undo_history = editor.with_buffer(|buffer| {
+                    self.path_opt
+                        .as_deref()
+                        .filter(|_| self.save_undo_history_panel)
+                        .and_then(|path| undo_history::load(path, buffer))
+                        .unwrap_or_else(|| UndoHistory::new(buffer))
+                });
             }
             Err(err) => {
                 if err.kind() == io::ErrorKind::NotFound {
                     log::warn!("opened non-existant file {:?}", absolute);
                     self.path_opt = Some(absolute);
-                    editor.set_changed(true);
+                    self.editor.lock().unwrap().set_changed(true);
                 } else {
                     log::error!("failed to open {:?}: {}", absolute, err);
                     self.path_opt = None;
@@ -139,6 +638,284 @@ impl EditorTab {
         }
     }
 
+    /// Open a file that is too large to load at once, its lines are added by
+    /// [`EditorTab::append_lines`]
+    pub fn open_large(&mut self, path: PathBuf) {
+        // The encoding is detected from the start of the file
+        let mut bytes = Vec::new();
+        match fs::File::open(&path)
+            .and_then(|file| file.take(CHUNK_SIZE as u64).read_to_end(&mut bytes))
+        {
+            Ok(_) => self.encoding = encoding::detect_start(&bytes),
+            Err(err) => {
+                log::warn!("failed to detect encoding of {:?}: {}", path, err);
+            }
+        }
+        log::info!("opening large file {:?} as {}", path, self.encoding.name());
+
+        self.modified_opt = modified_time(&path);
+        self.path_opt = Some(path);
+        self.large = true;
+        self.loading_opt = Some(0.0);
+        self.loading_request += 1;
+        self.loaded_lines = 0;
+
+        let mut editor = self.editor.lock().unwrap();
+        let mut font_system = font_system().write().unwrap();
+        let mut editor = editor.borrow_with(font_system.raw());
+        editor.with_buffer_mut(|buffer| buffer.set_wrap(Wrap::None));
+    }
+
+    /// Open a remote file, its contents are read with GIO and added by
+    /// [`EditorTab::finish_remote`]
+    pub fn open_remote(&mut self, uri: String) {
+        log::info!("opening remote file {}", uri);
+        self.uri_opt = Some(uri);
+        self.loading_opt = Some(0.0);
+    }
+
+    pub fn finish_remote(&mut self, bytes: &[u8]) {
+        self.encoding = encoding::detect(bytes);
+        let text = encoding::decode(bytes, self.encoding);
+        {
+            let mut editor = self.editor.lock().unwrap();
+            let mut font_system = font_system().write().unwrap();
+            let mut editor = editor.borrow_with(font_system.raw());
+            editor.with_buffer_mut(|buffer| {
+                buffer.set_text(&text, &self.attrs, Shaping::Advanced, None)
+            });
+        }
+        log::info!(
+            "opened {} as {}",
+            self.uri_opt.as_deref().unwrap_or_default(),
+            self.encoding.name()
+        );
+        self.loading_opt = None;
+        self.update_line_ending();
+        self.update_syntax();
+        self.update_conflicts();
+    }
+
+    /// Add lines read by [`read_chunks`], the text cannot be edited until
+    /// [`EditorTab::finish_loading`]
+    pub fn append_lines(&mut self, lines: Vec<(String, LineEnding)>, progress: f32) {
+        let mut editor = self.editor.lock().unwrap();
+        editor.with_buffer_mut(|buffer| {
+            // Replace the empty line of a new buffer
+            if self.loaded_lines == 0 {
+                buffer.lines.clear();
+            }
+            self.loaded_lines += lines.len();
+            for (text, ending) in lines {
+                buffer.lines.push(BufferLine::new(
+                    text,
+                    ending,
+                    AttrsList::new(&self.attrs),
+                    Shaping::Advanced,
+                ));
+            }
+            buffer.set_redraw(true);
+        });
+        self.loading_opt = Some(progress);
+    }
+
+    pub fn finish_loading(&mut self) {
+        let mut editor = self.editor.lock().unwrap();
+        editor.with_buffer_mut(|buffer| {
+            // Like set_text, the last line has no line ending
+            if buffer
+                .lines
+                .last()
+                .is_none_or(|line| line.ending() != LineEnding::None)
+            {
+                buffer.lines.push(BufferLine::new(
+                    "",
+                    LineEnding::None,
+                    AttrsList::new(&self.attrs),
+                    Shaping::Advanced,
+                ));
+            }
+            buffer.set_redraw(true);
+        });
+        self.line_ending = editor.with_buffer(detect_line_ending);
+        self.saved_line_ending = self.line_ending;
+        self.loading_opt = None;
+        log::info!("loaded {} lines", self.loaded_lines);
+    }
+
+    /// Name of the syntax used for highlighting
+    pub fn syntax_name(&self) -> &str {
+        &self.syntax_name
+    }
+
+    /// Select syntax highlighting from the override, the associations, or the extension of the
+    /// current path
+    pub fn update_syntax(&mut self) {
+        let syntax_set = &crate::syntax_system().syntax_set;
+        let path_opt = self.path_opt.as_deref().or_else(|| {
+            self.uri_opt
+                .as_deref()
+                .map(|uri| Path::new(remote::file_name(uri)))
+        });
+        let syntax = if self.large {
+            // Highlighting is too slow for large files
+            syntax_set.find_syntax_plain_text()
+        } else {
+            self.syntax_override
+                .as_ref()
+                .and_then(|name| syntax_set.find_syntax_by_name(name))
+                .or_else(|| {
+                    path_opt
+                        .and_then(|path| syntax::association(&self.syntax_associations, path))
+                        .and_then(|name| syntax_set.find_syntax_by_name(name))
+                })
+                .or_else(|| {
+                    let editor = self.editor.lock().unwrap();
+                    editor.with_buffer(|buffer| syntax::modeline(syntax_set, buffer))
+                })
+                .or_else(|| {
+                    // Files like Makefile are found by their whole name
+                    let path = path_opt?;
+                    path.file_name()
+                        .and_then(|file_name| file_name.to_str())
+                        .and_then(|file_name| syntax_set.find_syntax_by_extension(file_name))
+                        .or_else(|| {
+                            path.extension()
+                                .and_then(|extension| extension.to_str())
+                                .and_then(|extension| {
+                                    syntax_set.find_syntax_by_extension(extension)
+                                })
+                        })
+                })
+                .or_else(|| {
+                    let editor = self.editor.lock().unwrap();
+                    editor.with_buffer(|buffer| syntax::first_line(syntax_set, buffer))
+                })
+                .unwrap_or_else(|| syntax_set.find_syntax_plain_text())
+        };
+
+        self.syntax_name = syntax.name.clone();
+        self.set_plain_editor_syntax();
+    }
+
+    // Highlighting is done by the highlight worker, so the editor only applies theme colors
+    fn set_plain_editor_syntax(&mut self) {
+        let syntax_set = &crate::syntax_system().syntax_set;
+        // The editor selects syntax by extension
+        match syntax_set.find_syntax_plain_text().file_extensions.first() {
+            Some(extension) => {
+                let mut editor = self.editor.lock().unwrap();
+                editor.syntax_by_extension(extension);
+                editor.set_redraw(true);
+            }
+            None => {
+                log::warn!("plain text syntax has no file extensions");
+            }
+        }
+        self.highlight_reset = true;
+    }
+
+    /// Lines that changed since the previous request, to send to the highlight worker. Large
+    /// files are not highlighted
+    pub fn highlight_request(
+        &mut self,
+        entity: segmented_button::Entity,
+        config: &Config,
+    ) -> Option<highlight::Request> {
+        if self.large {
+            return None;
+        }
+        let editor = self.editor.lock().unwrap();
+        let hashes: Vec<u64> = editor.with_buffer(|buffer| {
+            buffer
+                .lines
+                .iter()
+                .map(|line| highlight::text_hash(line.text()))
+                .collect()
+        });
+        let old_hashes = &self.highlight_hashes;
+        let start = hashes
+            .iter()
+            .zip(old_hashes.iter())
+            .position(|(hash, old_hash)| hash != old_hash)
+            .unwrap_or_else(|| hashes.len().min(old_hashes.len()));
+        let unchanged_end = hashes[start..]
+            .iter()
+            .rev()
+            .zip(old_hashes[start..].iter().rev())
+            .take_while(|(hash, old_hash)| hash == old_hash)
+            .count();
+        let removed = old_hashes.len() - start - unchanged_end;
+        let lines = editor.with_buffer(|buffer| {
+            buffer.lines[start..hashes.len() - unchanged_end]
+                .iter()
+                .map(|line| line.text().to_string())
+                .collect()
+        });
+        drop(editor);
+        self.highlight_hashes = hashes;
+
+        Some(highlight::Request {
+            entity,
+            syntax: self.syntax_name.clone(),
+            theme: config.syntax_theme().to_string(),
+            reset: mem::take(&mut self.highlight_reset),
+            start,
+            removed,
+            lines,
+        })
+    }
+
+    /// Send every line with the next request, for a new highlight worker
+    pub fn highlight_restart(&mut self) {
+        self.highlight_hashes.clear();
+        self.highlight_reset = true;
+    }
+
+    /// Apply colors from the highlight worker to lines that have not changed since
+    pub fn apply_highlight(&self, lines: Vec<highlight::LineSpans>) {
+        let mut editor = self.editor.lock().unwrap();
+        editor.with_buffer_mut(|buffer| {
+            for line_spans in lines {
+                let Some(line) = buffer.lines.get_mut(line_spans.line_i) else {
+                    continue;
+                };
+                if highlight::text_hash(line.text()) != line_spans.text_hash {
+                    continue;
+                }
+
+                let attrs = line.attrs_list().defaults();
+                let mut attrs_list = AttrsList::new(&attrs);
+                for (range, style) in line_spans.spans {
+                    let color = style.foreground;
+                    attrs_list.add_span(
+                        range,
+                        &attrs
+                            .clone()
+                            .color(Color::rgba(color.r, color.g, color.b, color.a))
+                            .weight(if style.font_style.contains(FontStyle::BOLD) {
+                                Weight::BOLD
+                            } else {
+                                Weight::NORMAL
+                            })
+                            .style(if style.font_style.contains(FontStyle::ITALIC) {
+                                Style::Italic
+                            } else {
+                                Style::Normal
+                            }),
+                    );
+                }
+                // The editor only highlights lines without metadata, which is kept here
+                let metadata_opt = line.metadata();
+                line.set_attrs_list(attrs_list);
+                if let Some(metadata) = metadata_opt {
+                    line.set_metadata(metadata);
+                }
+            }
+            buffer.set_redraw(true);
+        });
+    }
+
     pub fn reload(&mut self) {
         let mut editor = self.editor.lock().unwrap();
         let mut font_system = font_system().write().unwrap();
@@ -148,18 +925,22 @@ impl EditorTab {
             let scroll = editor.with_buffer(|buffer| buffer.scroll());
             //TODO: save/restore more?
 
-            match std::fs::read_to_string(path) {
-                Ok(file_content) => {
+            match fs::read(path) {
+                Ok(bytes) => {
+                    let file_content = encoding::decode(&bytes, self.encoding);
                     log::info!("reloaded {:?}", path);
+                    self.modified_opt = modified_time(path);
+                    self.disk_changed = false;
 
                     //TODO: compare using line iterator to prevent allocations
-                    if file_content == editor_text(&editor) {
+                    if file_content == editor_text(&editor, self.new_line_ending()) {
                         log::info!("text not changed");
                         return;
                     }
 
                     // Store the entire operation as a single change for undo
                     editor.start_change();
+                    self.highlight_reset = true;
 
                     // Grab everything in the buffer
                     let cursor_start: Cursor = cosmic_text::Cursor::new(0, 0);
@@ -205,6 +986,11 @@ impl EditorTab {
 
                     editor.finish_change();
                     editor.set_changed(false);
+                    self.line_ending = editor.with_buffer(detect_line_ending);
+                    self.saved_line_ending = self.line_ending;
+                    self.line_hashes =
+                        editor.with_buffer(|buffer| line_hashes(buffer, self.line_ending));
+                    self.conflicts = editor.with_buffer(conflict::conflicts);
                 }
                 Err(err) => {
                     log::error!("failed to reload {:?}: {}", path, err);
@@ -218,75 +1004,307 @@ impl EditorTab {
         }
     }
 
-    pub fn save(&mut self) {
+    /// Write the text to the file, returning the error so that saving as an administrator can be
+    /// offered when permission is denied
+    pub fn save(&mut self) -> io::Result<()> {
+        if let Some(uri) = self.uri_opt.clone() {
+            return self.save_remote(&uri);
+        }
+        let Some(path) = self.path_opt.clone() else {
+            log::warn!("tab has no path yet");
+            return Ok(());
+        };
+        if self.loading_opt.is_some() {
+            log::warn!("tried to save {:?} before it was loaded", path);
+            return Ok(());
+        }
+        let res = self.encoded_text().and_then(|bytes| {
+            if self.atomic_save {
+                write_atomic(&path, &bytes)
+            } else {
+                fs::write(&path, &bytes)
+            }
+        });
+        match &res {
+            Ok(()) => {
+                log::info!("saved {:?}", path);
+                self.saved(&path);
+            }
+            Err(err) => {
+                log::warn!("failed to save {:?}: {}", path, err);
+            }
+        }
+        res
+    }
+
+    /// Path and text to write with [`write_elevated`], and the hash of the text that is passed to
+    /// [`Self::saved_elevated`]
+    pub fn save_elevated(&self) -> Option<(PathBuf, Vec<u8>, u64)> {
+        let path = self.path_opt.clone()?;
+        let bytes = self.encoded_text().ok()?;
+        let hash = BuildHasherDefault::<DefaultHasher>::default().hash_one(&bytes);
+        Some((path, bytes, hash))
+    }
+
+    /// Mark the text written by [`write_elevated`] as saved, unless it was edited while the
+    /// password was entered
+    pub fn saved_elevated(&mut self, path: &Path, hash: u64) {
+        log::info!("saved {:?} with pkexec", path);
+        if self.encoded_text().is_ok_and(|bytes| {
+            BuildHasherDefault::<DefaultHasher>::default().hash_one(&bytes) == hash
+        }) {
+            self.saved(path);
+        } else {
+            self.modified_opt = modified_time(path);
+        }
+    }
+
+    // Write back over the protocol the file was opened with
+    fn save_remote(&mut self, uri: &str) -> io::Result<()> {
+        if self.loading_opt.is_some() {
+            log::warn!("tried to save {} before it was loaded", uri);
+            return Ok(());
+        }
+        let res = self
+            .encoded_text()
+            .and_then(|bytes| remote::write(uri, &bytes));
+        match &res {
+            Ok(()) => {
+                log::info!("saved {}", uri);
+                let mut editor = self.editor.lock().unwrap();
+                editor.save_point();
+                self.saved_line_ending = self.line_ending;
+            }
+            Err(err) => {
+                log::warn!("failed to save {}: {}", uri, err);
+            }
+        }
+        res
+    }
+
+    /// Check that the text can be saved with `encoding` without replacing characters
+    pub fn can_encode(&self, encoding: &'static Encoding) -> bool {
+        let editor = self.editor.lock().unwrap();
+        let text = editor_text(&editor, self.new_line_ending());
+        !encoding::encode(&text, encoding).1
+    }
+
+    // Fails when characters would be replaced, unless the user agreed to that for this encoding
+    fn encoded_text(&self) -> io::Result<Vec<u8>> {
+        let editor = self.editor.lock().unwrap();
+        let text = editor_text(&editor, self.new_line_ending());
+        let (bytes, had_errors) = encoding::encode(&text, self.encoding);
+        if had_errors && self.lossy_encoding_opt != Some(self.encoding) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("text cannot be encoded in {}", self.encoding.name()),
+            ));
+        }
+        Ok(bytes.into_owned())
+    }
+
+    // Mark the text as the contents of the file that was just written
+    fn saved(&mut self, path: &Path) {
+        let mut editor = self.editor.lock().unwrap();
+        editor.save_point();
+        self.saved_line_ending = self.line_ending;
+        self.modified_opt = modified_time(path);
+        if !editor.changed() {
+            self.disk_changed = false;
+            // The saved file has no differences, so results still to come are outdated
+            self.changed_lines.clear();
+            self.changed_lines_request += 1;
+        }
+        if self.save_undo_history_panel && !self.large {
+            self.undo_history_unsaved = true;
+        }
+    }
+
+    /// Replace all text as a single change that can be undone
+    pub fn replace_text(&mut self, text: &str) {
+        self.highlight_reset = true;
+        let mut editor = self.editor.lock().unwrap();
+        let mut font_system = font_system().write().unwrap();
+        let mut editor = editor.borrow_with(font_system.raw());
+        editor.start_change();
+        let cursor_start = Cursor::new(0, 0);
+        let cursor_end = editor.with_buffer(|buffer| {
+            let last_line = buffer.lines.len().saturating_sub(1);
+            Cursor::new(
+                last_line,
+                buffer
+                    .lines
+                    .get(last_line)
+                    .map(|line| line.text().len())
+                    .unwrap_or(0),
+            )
+        });
+        editor.delete_range(cursor_start, cursor_end);
+        editor.insert_at(cursor_start, text, None);
+        editor.set_cursor(cursor_start);
+        editor.finish_change();
+        editor.set_changed(true);
+    }
+
+    /// True if the file was modified by something else since it was last loaded or saved
+    pub fn modified_on_disk(&self) -> bool {
+        match &self.path_opt {
+            Some(path) => modified_time(path) != self.modified_opt,
+            None => false,
+        }
+    }
+
+    /// Dismiss a change on disk, the buffer will overwrite it when saved
+    pub fn keep_changes(&mut self) {
         if let Some(path) = &self.path_opt {
-            let mut editor = self.editor.lock().unwrap();
-            let text = editor_text(&editor);
-            match fs::write(path, &text) {
-                Ok(()) => {
-                    editor.save_point();
-                    log::info!("saved {:?}", path);
-                }
-                Err(err) => {
-                    if err.kind() == std::io::ErrorKind::PermissionDenied {
-                        log::warn!("Permission denied. Attempting to save with pkexec.");
-
-                        if let Ok(mut output) = Command::new("pkexec")
-                            .arg("tee")
-                            .arg(path)
-                            .stdin(Stdio::piped())
-                            .stdout(Stdio::null()) // Redirect stdout to /dev/null
-                            .stderr(Stdio::inherit()) // Retain stderr for error visibility
-                            .spawn()
-                        {
-                            if let Some(mut stdin) = output.stdin.take() {
-                                if let Err(e) = stdin.write_all(text.as_bytes()) {
-                                    log::error!("Failed to write to stdin: {}", e);
-                                }
-                            } else {
-                                log::error!("Failed to access stdin of pkexec process.");
-                            }
+            self.modified_opt = modified_time(path);
+        }
+        self.disk_changed = false;
+    }
 
-                            // Ensure the child process is reaped
-                            match output.wait() {
-                                Ok(status) => {
-                                    if status.success() {
-                                        // Mark the editor's state as saved if the process succeeds
-                                        editor.save_point();
-                                        log::info!("File saved successfully with pkexec.");
-                                    } else {
-                                        log::error!(
-                                            "pkexec process exited with a non-zero status: {:?}",
-                                            status
-                                        );
-                                    }
-                                }
-                                Err(e) => {
-                                    log::error!("Failed to wait on pkexec process: {}", e);
-                                }
-                            }
-                        } else {
-                            log::error!(
-                                "Failed to spawn pkexec process. Check permissions or path."
-                            );
-                        }
-                    }
+    pub fn text(&self) -> String {
+        let editor = self.editor.lock().unwrap();
+        editor_text(&editor, self.new_line_ending())
+    }
+
+    /// Find the symbols of document types that have an outline without a language server
+    pub fn update_outline(&mut self) {
+        self.outline_opt = if self.large {
+            None
+        } else {
+            outline::symbols(&self.syntax_name, &self.text())
+        };
+    }
+
+    /// Find the merge conflicts in the text again
+    pub fn update_conflicts(&mut self) {
+        self.conflicts = if self.large {
+            Vec::new()
+        } else {
+            self.editor.lock().unwrap().with_buffer(conflict::conflicts)
+        };
+    }
+
+    /// The merge conflict around the cursor
+    pub fn cursor_conflict(&self) -> Option<Conflict> {
+        let line = self.editor.lock().unwrap().cursor().line;
+        self.conflicts
+            .iter()
+            .find(|conflict| conflict.contains(line))
+            .copied()
+    }
+
+    pub fn changed(&self) -> bool {
+        let editor = self.editor.lock().unwrap();
+        editor.changed() || self.line_ending != self.saved_line_ending
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    // Detect the line ending of text that was loaded from disk
+    fn update_line_ending(&mut self) {
+        let editor = self.editor.lock().unwrap();
+        self.line_ending = editor.with_buffer(detect_line_ending);
+        self.saved_line_ending = self.line_ending;
+        self.line_hashes = editor.with_buffer(|buffer| line_hashes(buffer, self.line_ending));
+    }
+
+    /// Convert every line of the document to `line_ending`
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        let mut editor = self.editor.lock().unwrap();
+        editor.with_buffer_mut(|buffer| {
+            for line in buffer.lines.iter_mut() {
+                // The last line has no line ending
+                if line.ending() != LineEnding::None {
+                    line.set_ending(line_ending);
                 }
             }
-        } else {
-            log::warn!("tab has no path yet");
+        });
+        self.line_ending = line_ending;
+        self.line_hashes = editor.with_buffer(|buffer| line_hashes(buffer, line_ending));
+    }
+
+    // Ending that LF endings are written with. Lines of large files are not tracked, so all of
+    // their LF endings are written with the line ending of the document
+    fn new_line_ending(&self) -> Option<LineEnding> {
+        self.large.then_some(self.line_ending)
+    }
+
+    /// Undo history to write to the cache after the file was saved
+    pub fn take_undo_history(&mut self) -> Option<(PathBuf, UndoHistory)> {
+        if !mem::take(&mut self.undo_history_unsaved) {
+            return None;
         }
+        let path = self.path_opt.clone()?;
+        Some((path, self.undo_history.clone()))
     }
 
-    pub fn changed(&self) -> bool {
+    /// Add the last change to the undo history, which only stores the lines it changed
+    pub fn record_undo(&mut self) {
         let editor = self.editor.lock().unwrap();
-        editor.changed()
+        editor.with_buffer(|buffer| self.undo_history.record(buffer));
+    }
+
+    /// Give lines added by the last change the line ending of the document, as the editor adds
+    /// lines with LF endings. Other lines keep their own endings
+    pub fn update_new_lines(&mut self) {
+        if self.large || self.line_ending == LineEnding::Lf {
+            return;
+        }
+        let line_ending = self.line_ending;
+        let old_hashes = &self.line_hashes;
+        let mut editor = self.editor.lock().unwrap();
+        let hashes = editor.with_buffer_mut(|buffer| {
+            let mut hashes = line_hashes(buffer, line_ending);
+            let start = hashes
+                .iter()
+                .zip(old_hashes.iter())
+                .position(|(hash, old_hash)| hash != old_hash)
+                .unwrap_or_else(|| hashes.len().min(old_hashes.len()));
+            let unchanged_end = hashes[start..]
+                .iter()
+                .rev()
+                .zip(old_hashes[start..].iter().rev())
+                .take_while(|(hash, old_hash)| hash == old_hash)
+                .count();
+            let end = hashes.len() - unchanged_end;
+            let old_lines = &old_hashes[start..old_hashes.len() - unchanged_end];
+            for (i, line_i) in (start..end).enumerate() {
+                // The text after a split keeps the ending of the line it came from, and the other
+                // changed lines take the place of the old lines in order
+                let old_line_opt = if line_i + 1 == end {
+                    old_lines.last()
+                } else {
+                    old_lines.get(i)
+                };
+                let line = &mut buffer.lines[line_i];
+                if line.ending() == LineEnding::Lf
+                    && old_line_opt.is_none_or(|(_, ending)| *ending != LineEnding::Lf)
+                {
+                    line.set_ending(line_ending);
+                    hashes[line_i].1 = line_ending;
+                }
+            }
+            hashes
+        });
+        drop(editor);
+        self.line_hashes = hashes;
     }
 
     pub fn icon(&self, size: u16) -> icon::Icon {
-        match &self.path_opt {
-            Some(path) => icon::icon(mime_icon(mime_for_path(path, None, false), size)).size(size),
-            None => icon::from_name(FALLBACK_MIME_ICON).size(size).icon(),
+        match (&self.path_opt, &self.uri_opt) {
+            (Some(path), _) => {
+                icon::icon(mime_icon(mime_for_path(path, None, false), size)).size(size)
+            }
+            // The type is guessed from the name, as the file is not on disk
+            (None, Some(uri)) => icon::icon(mime_icon(
+                mime_for_path(Path::new(remote::file_name(uri)), None, false),
+                size,
+            ))
+            .size(size),
+            (None, None) => icon::from_name(FALLBACK_MIME_ICON).size(size).icon(),
         }
     }
 
@@ -303,31 +1321,172 @@ impl EditorTab {
                 },
                 None => format!("{}", path.display()),
             }
+        } else if let Some(uri) = &self.uri_opt {
+            remote::file_name(uri).to_string()
         } else {
             fl!("new-document")
         }
     }
 
-    pub fn replace(&self, regex: &Regex, replace: &str, wrap_around: bool) -> bool {
+    /// Replace all matches of `regex` on the lines of `results` that still read as they did when
+    /// searched, returning the number of lines changed
+    pub fn replace_matches(
+        &self,
+        regex: &Regex,
+        replace: &str,
+        results: &[LineSearchResult],
+    ) -> usize {
+        let mut editor = self.editor.lock().unwrap();
+        // The search ran on the file on disk, so lines that differ in the document are skipped
+        let mut lines: Vec<usize> = editor.with_buffer(|buffer| {
+            results
+                .iter()
+                .filter_map(|result| {
+                    let line_i = result.number.checked_sub(1)?;
+                    let line = buffer.lines.get(line_i)?;
+                    (line.text().trim_end() == result.text).then_some(line_i)
+                })
+                .collect()
+        });
+        lines.sort_unstable();
+        lines.dedup();
+
+        let mut count = 0;
+        editor.start_change();
+        // Replace from the bottom, in case replacements insert new lines
+        for line_i in lines.into_iter().rev() {
+            let Some((len, text)) = editor.with_buffer(|buffer| {
+                let text = buffer.lines.get(line_i)?.text();
+                match regex.replace_all(text, replace) {
+                    Cow::Borrowed(_) => None,
+                    Cow::Owned(new_text) => Some((text.len(), new_text)),
+                }
+            }) else {
+                continue;
+            };
+            let start = Cursor::new(line_i, 0);
+            editor.delete_range(start, Cursor::new(line_i, len));
+            editor.insert_at(start, &text, None);
+            count += 1;
+        }
+        editor.finish_change();
+
+        // Keep cursor inside of the changed text
+        let mut cursor = editor.cursor();
+        editor.with_buffer(|buffer| {
+            cursor.line = cursor.line.min(buffer.lines.len().saturating_sub(1));
+            if let Some(line) = buffer.lines.get(cursor.line) {
+                let text = line.text();
+                cursor.index = cursor.index.min(text.len());
+                while !text.is_char_boundary(cursor.index) {
+                    cursor.index -= 1;
+                }
+            }
+        });
+        editor.set_cursor(cursor);
+        editor.set_selection(Selection::None);
+
+        count
+    }
+
+    /// Replace the lines of a hunk with the text they had before, returns false if the hunk does
+    /// not match the text anymore
+    pub fn revert_git_hunk(&self, hunk: &GitDiffHunk) -> bool {
+        let mut editor = self.editor.lock().unwrap();
+        let (first, count) = hunk.new_lines();
+        let new_text = hunk.new_text();
+        let matches = editor.with_buffer(|buffer| {
+            count == new_text.len()
+                && buffer.lines.len() >= first + count
+                && buffer.lines[first..first + count]
+                    .iter()
+                    .zip(new_text.iter())
+                    .all(|(line, text)| line.text() == *text)
+        });
+        if !matches {
+            return false;
+        }
+
+        let old_text = hunk.old_text().join("\n");
+        editor.start_change();
+        if count > 0 {
+            let last = first + count - 1;
+            let last_len = editor.with_buffer(|buffer| buffer.lines[last].text().len());
+            let start = Cursor::new(first, 0);
+            editor.delete_range(start, Cursor::new(last, last_len));
+            editor.insert_at(start, &old_text, None);
+        } else if first < editor.with_buffer(|buffer| buffer.lines.len()) {
+            editor.insert_at(Cursor::new(first, 0), &format!("{old_text}\n"), None);
+        } else {
+            // Lines deleted at the end of the file go after the last line
+            let end = editor.with_buffer(|buffer| {
+                let last = buffer.lines.len().saturating_sub(1);
+                Cursor::new(
+                    last,
+                    buffer.lines.get(last).map_or(0, |line| line.text().len()),
+                )
+            });
+            editor.insert_at(end, &format!("\n{old_text}"), None);
+        }
+        editor.finish_change();
+        editor.set_cursor(Cursor::new(first, 0));
+        editor.set_selection(Selection::None);
+        true
+    }
+
+    /// Limit find and replace to the selection, returns false if nothing is selected
+    pub fn set_find_scope(&self, enabled: bool) -> bool {
+        let scope_opt = if enabled {
+            let editor = self.editor.lock().unwrap();
+            editor.selection_bounds().map(|(start, end)| {
+                (
+                    start,
+                    editor.with_buffer(|buffer| FromEnd::new(buffer, end)),
+                )
+            })
+        } else {
+            None
+        };
+        *self.find_scope.lock().unwrap() = scope_opt;
+        scope_opt.is_some() || !enabled
+    }
+
+    pub fn has_find_scope(&self) -> bool {
+        self.find_scope.lock().unwrap().is_some()
+    }
+
+    fn find_scope_bounds(&self, buffer: &Buffer) -> Option<(Cursor, Cursor)> {
+        let find_scope = self.find_scope.lock().unwrap();
+        find_scope.map(|(start, end)| (start, end.cursor(buffer)))
+    }
+
+    /// Replace the next match of `regex` with `template`, expanding capture groups as in
+    /// [`regex::Captures::expand`]
+    pub fn replace(&self, regex: &Regex, template: &str, wrap_around: bool) -> bool {
+        if self.has_find_scope() {
+            return self.replace_in_scope(regex, template, wrap_around);
+        }
+
         let mut editor = self.editor.lock().unwrap();
         let mut cursor = editor.cursor();
         let mut wrapped = false; // Keeps track of whether the search has wrapped around yet.
         let start_line = cursor.line;
         while cursor.line < editor.with_buffer(|buffer| buffer.lines.len()) {
-            if let Some((index, len)) = editor.with_buffer(|buffer| {
+            if let Some((index, len, replace)) = editor.with_buffer(|buffer| {
                 regex
-                    .find_iter(buffer.lines[cursor.line].text())
-                    .filter_map(|m| {
-                        if cursor.line != start_line
+                    .captures_iter(buffer.lines[cursor.line].text())
+                    .find(|captures| {
+                        let m = captures.get(0).unwrap();
+                        cursor.line != start_line
                             || m.start() >= cursor.index
                             || m.start() < cursor.index && wrapped == true
-                        {
-                            Some((m.start(), m.len()))
-                        } else {
-                            None
-                        }
                     })
-                    .next()
+                    .map(|captures| {
+                        let m = captures.get(0).unwrap();
+                        let mut replace = String::new();
+                        captures.expand(template, &mut replace);
+                        (m.start(), m.len(), replace)
+                    })
             }) {
                 cursor.index = index;
                 let mut end = cursor;
@@ -349,7 +1508,7 @@ impl EditorTab {
                     }
                 }
                 editor.delete_range(cursor, end);
-                cursor = editor.insert_at(cursor, replace, None);
+                cursor = editor.insert_at(cursor, &replace, None);
                 editor.set_cursor(cursor);
                 // Need to disable selection to prevent the new cursor showing selection to old location
                 editor.set_selection(Selection::None);
@@ -372,6 +1531,85 @@ impl EditorTab {
         false
     }
 
+    /// Path with the cursor and scroll positions, to reopen the file where it was left
+    pub fn session_tab(&self) -> Option<SessionTab> {
+        let path = self.path_opt.clone()?;
+        let editor = self.editor.lock().unwrap();
+        let cursor = editor.cursor();
+        let scroll = editor.with_buffer(|buffer| buffer.scroll());
+        Some(SessionTab {
+            path,
+            cursor_line: cursor.line,
+            cursor_index: cursor.index,
+            scroll_line: scroll.line,
+            zoom_adj: self.zoom_adj,
+        })
+    }
+
+    /// Misspelled word that the cursor is in, to offer its suggestions
+    pub fn cursor_misspelling(&self) -> Option<&Misspelling> {
+        let cursor = self.editor.lock().unwrap().cursor();
+        self.misspellings.iter().find(|misspelling| {
+            misspelling.start.line == cursor.line
+                && misspelling.start.index <= cursor.index
+                && misspelling.end.index >= cursor.index
+        })
+    }
+
+    /// Line and column of the cursor, starting at one, and the number of selected characters
+    pub fn cursor_status(&self) -> (usize, usize, usize) {
+        let editor = self.editor.lock().unwrap();
+        let cursor = editor.cursor();
+        let selection_bounds = editor.selection_bounds();
+        editor.with_buffer(|buffer| {
+            let column = buffer.lines.get(cursor.line).map_or(0, |line| {
+                line.text()
+                    .get(..cursor.index)
+                    .map_or(0, |text| text.chars().count())
+            });
+            let mut selected = 0;
+            if let Some((start, end)) = selection_bounds {
+                for line_i in start.line..=end.line {
+                    let Some(line) = buffer.lines.get(line_i) else {
+                        break;
+                    };
+                    let text = line.text();
+                    let start_index = if line_i == start.line { start.index } else { 0 };
+                    let end_index = if line_i == end.line {
+                        end.index
+                    } else {
+                        text.len()
+                    };
+                    selected += text
+                        .get(start_index..end_index)
+                        .map_or(0, |text| text.chars().count());
+                    if line_i != end.line {
+                        // Line break
+                        selected += 1;
+                    }
+                }
+            }
+            (cursor.line + 1, column + 1, selected)
+        })
+    }
+
+    /// True if more of the first lines are indented with tabs than with spaces
+    pub fn indent_tabs(&self) -> bool {
+        let editor = self.editor.lock().unwrap();
+        editor.with_buffer(|buffer| {
+            let mut tabs = 0;
+            let mut spaces = 0;
+            for line in buffer.lines.iter().take(SAMPLE_LINES) {
+                match line.text().chars().next() {
+                    Some('\t') => tabs += 1,
+                    Some(' ') => spaces += 1,
+                    _ => {}
+                }
+            }
+            tabs > spaces
+        })
+    }
+
     pub fn zoom_adj(&self) -> i8 {
         self.zoom_adj
     }
@@ -380,8 +1618,139 @@ impl EditorTab {
         self.zoom_adj = value;
     }
 
+    /// Replace every match of `regex`, returning the number of replacements
+    pub fn replace_all(&self, regex: &Regex, template: &str) -> usize {
+        {
+            let mut editor = self.editor.lock().unwrap();
+            editor.set_cursor(Cursor::new(0, 0));
+        }
+        let mut count = 0;
+        while self.replace(regex, template, false) {
+            count += 1;
+        }
+        count
+    }
+
+    fn replace_in_scope(&self, regex: &Regex, template: &str, wrap_around: bool) -> bool {
+        let matches = self.find_matches(regex);
+        let mut editor = self.editor.lock().unwrap();
+        let cursor = editor.cursor();
+        let Some((start, end)) = matches
+            .iter()
+            .find(|(start, _end)| (start.line, start.index) >= (cursor.line, cursor.index))
+            .or_else(|| matches.first().filter(|_| wrap_around))
+            .copied()
+        else {
+            return false;
+        };
+
+        let Some(replace) = editor.with_buffer(|buffer| {
+            let captures = regex.captures_at(buffer.lines[start.line].text(), start.index)?;
+            let mut replace = String::new();
+            captures.expand(template, &mut replace);
+            Some(replace)
+        }) else {
+            return false;
+        };
+        editor.start_change();
+        editor.delete_range(start, end);
+        let cursor = editor.insert_at(start, &replace, None);
+        editor.set_cursor(cursor);
+        editor.set_selection(Selection::None);
+        editor.finish_change();
+        true
+    }
+
+    /// Expansion of `template` for the selected match of `regex`, if the selection is a match
+    pub fn replace_preview(&self, regex: &Regex, template: &str) -> Option<String> {
+        let editor = self.editor.lock().unwrap();
+        let (start, end) = editor.selection_bounds()?;
+        if start.line != end.line {
+            return None;
+        }
+        editor.with_buffer(|buffer| {
+            let text = buffer.lines.get(start.line)?.text();
+            let captures = regex.captures_at(text, start.index)?;
+            let m = captures.get(0).unwrap();
+            if m.start() != start.index || m.end() != end.index {
+                return None;
+            }
+            let mut replace = String::new();
+            captures.expand(template, &mut replace);
+            Some(replace)
+        })
+    }
+
+    /// Start and end of every non-empty match of `regex`, in document order
+    pub fn find_matches(&self, regex: &Regex) -> Vec<(Cursor, Cursor)> {
+        let editor = self.editor.lock().unwrap();
+        editor.with_buffer(|buffer| {
+            let scope_opt = self.find_scope_bounds(buffer);
+            let mut matches = Vec::new();
+            for (line_i, line) in buffer.lines.iter().enumerate() {
+                if let Some((start, end)) = scope_opt {
+                    if line_i < start.line || line_i > end.line {
+                        continue;
+                    }
+                }
+                for m in regex.find_iter(line.text()) {
+                    if m.is_empty() {
+                        continue;
+                    }
+                    if let Some((start, end)) = scope_opt {
+                        if (line_i, m.start()) < (start.line, start.index)
+                            || (line_i, m.end()) > (end.line, end.index)
+                        {
+                            continue;
+                        }
+                    }
+                    matches.push((Cursor::new(line_i, m.start()), Cursor::new(line_i, m.end())));
+                }
+            }
+            matches
+        })
+    }
+
+    // Select the next match from the scope, like search does for the whole document
+    fn search_in_scope(&self, regex: &Regex, forwards: bool, wrap_around: bool) -> bool {
+        let matches = self.find_matches(regex);
+        let mut editor = self.editor.lock().unwrap();
+        let cursor = editor.cursor();
+        let cursor = (cursor.line, cursor.index);
+        // The match at the cursor is skipped if it is already selected
+        let at_cursor = editor.selection() == Selection::None;
+        let next = if forwards {
+            matches
+                .iter()
+                .find(|(start, _end)| {
+                    let start = (start.line, start.index);
+                    start > cursor || start == cursor && at_cursor
+                })
+                .or_else(|| matches.first().filter(|_| wrap_around))
+        } else {
+            matches
+                .iter()
+                .rev()
+                .find(|(start, _end)| {
+                    let start = (start.line, start.index);
+                    start < cursor || start == cursor && at_cursor
+                })
+                .or_else(|| matches.last().filter(|_| wrap_around))
+        };
+        let Some((start, end)) = next.copied() else {
+            return false;
+        };
+        editor.set_cursor(start);
+        editor.set_selection(Selection::Normal(end));
+        true
+    }
+
     // Code adapted from cosmic-text ViEditor search
     pub fn search(&self, regex: &Regex, forwards: bool, wrap_around: bool) -> bool {
+        if self.has_find_scope() {
+            return self.search_in_scope(regex, forwards, wrap_around);
+        }
+
         let mut editor = self.editor.lock().unwrap();
         let mut cursor = editor.cursor();
         let mut wrapped = false; // Keeps track of whether the search has wrapped around yet.
@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::Cursor;
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::lsp::Location;
+
+// Names of the tags files that ctags writes in a project
+const TAGS_FILES: &[&str] = &["tags", ".tags"];
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+enum Address {
+    Line(usize),
+    // Text of the line, and whether it is the whole line
+    Pattern(String, bool),
+}
+
+/// A definition listed in a tags file
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Tag {
+    pub name: String,
+    pub path: PathBuf,
+    address: Address,
+    pub kind_opt: Option<String>,
+}
+
+// Text of a search pattern like /^fn main() {$/
+fn pattern(address: &str) -> Option<Address> {
+    let delimiter = address.chars().next().filter(|c| *c == '/' || *c == '?')?;
+    let inner = address[1..].strip_suffix(delimiter)?;
+    let inner = inner.strip_prefix('^').unwrap_or(inner);
+    let (inner, whole) = match inner.strip_suffix('$') {
+        Some(inner) if !inner.ends_with('\\') => (inner, true),
+        _ => (inner, false),
+    };
+    let mut text = String::with_capacity(inner.len());
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some(next @ ('/' | '?' | '\\' | '$' | '^')) => text.push(next),
+                Some(next) => {
+                    text.push(c);
+                    text.push(next);
+                }
+                None => text.push(c),
+            }
+        } else {
+            text.push(c);
+        }
+    }
+    Some(Address::Pattern(text, whole))
+}
+
+// A line of a tags file, like "main\tsrc/main.rs\t/^fn main() {$/;\"\tf"
+fn parse_line(line: &str, base: &Path) -> Option<Tag> {
+    if line.starts_with("!_") {
+        return None;
+    }
+    let mut parts = line.splitn(3, '\t');
+    let name = parts.next()?;
+    let file = parts.next()?;
+    let rest = parts.next()?;
+    // Extension fields follow the address after ;"
+    let (address, fields) = match rest.find(";\"") {
+        Some(end) => (&rest[..end], &rest[end + 2..]),
+        None => (rest, ""),
+    };
+    let address = match address.parse::<usize>() {
+        Ok(line) => Address::Line(line.saturating_sub(1)),
+        Err(_) => pattern(address)?,
+    };
+    // The kind is a field without a name, or the kind field
+    let kind_opt = fields
+        .split('\t')
+        .filter(|field| !field.is_empty())
+        .find_map(|field| match field.split_once(':') {
+            Some(("kind", kind)) => Some(kind),
+            Some(_) => None,
+            None => Some(field),
+        })
+        .map(str::to_string);
+    Some(Tag {
+        name: name.to_string(),
+        path: base.join(file),
+        address,
+        kind_opt,
+    })
+}
+
+/// Tags of a project, which are empty if it has no tags file
+pub fn load(project_path: &Path) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    for name in TAGS_FILES {
+        let path = project_path.join(name);
+        match fs::read(&path) {
+            Ok(bytes) => {
+                let text = String::from_utf8_lossy(&bytes);
+                tags.extend(
+                    text.lines()
+                        .filter_map(|line| parse_line(line, project_path)),
+                );
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => {
+                log::warn!("failed to read tags file {:?}: {}", path, err);
+            }
+        }
+    }
+    tags
+}
+
+impl Tag {
+    /// Find the definition in its file, whose text may have changed since it was tagged
+    pub fn locate(&self) -> Option<Location> {
+        let text = match fs::read(&self.path) {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(err) => {
+                log::warn!("failed to read {:?}: {}", self.path, err);
+                return None;
+            }
+        };
+        let (line_i, line) = match &self.address {
+            Address::Line(line_i) => (*line_i, text.lines().nth(*line_i)?),
+            Address::Pattern(pattern, whole) => text.lines().enumerate().find(|(_, line)| {
+                if *whole {
+                    line == pattern
+                } else {
+                    line.starts_with(pattern.as_str())
+                }
+            })?,
+        };
+        let start = Cursor::new(line_i, line.find(&self.name).unwrap_or(0));
+        Some(Location {
+            path: self.path.clone(),
+            start,
+            end: start,
+            line_text: line.trim().to_string(),
+        })
+    }
+}
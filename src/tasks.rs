@@ -0,0 +1,156 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::iced::futures::{SinkExt, StreamExt, channel::mpsc, stream};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::LazyLock,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, BufReader},
+    process::Command,
+};
+
+use crate::{Message, shell};
+
+/// Lines of output kept in the task panel, older ones are removed
+pub const SCROLLBACK: usize = 10_000;
+
+/// File in a project that defines its tasks as a JSON list of objects with a `name` and a
+/// `command`, relative to the project folder
+pub const TASKS_FILE: &str = ".cosmic-edit/tasks.json";
+
+/// A named shell command that is run in the folder of a project
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ProjectTask {
+    pub name: String,
+    pub command: String,
+}
+
+impl ProjectTask {
+    fn new(name: &str, command: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            command: command.to_string(),
+        }
+    }
+}
+
+/// Tasks of the project in `dir`, from its tasks file or else guessed from its build files
+pub fn project_tasks(dir: &Path) -> Vec<ProjectTask> {
+    let tasks_path = dir.join(TASKS_FILE);
+    match fs::read_to_string(&tasks_path) {
+        Ok(data) => match serde_json::from_str(&data) {
+            Ok(tasks) => return tasks,
+            Err(err) => log::warn!("failed to parse {:?}: {}", tasks_path, err),
+        },
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => log::warn!("failed to read {:?}: {}", tasks_path, err),
+    }
+
+    let mut tasks = Vec::new();
+    if dir.join("Cargo.toml").is_file() {
+        tasks.push(ProjectTask::new("cargo build", "cargo build"));
+        tasks.push(ProjectTask::new("cargo test", "cargo test"));
+        tasks.push(ProjectTask::new("cargo run", "cargo run"));
+    }
+    if dir.join("justfile").is_file() || dir.join("Justfile").is_file() {
+        tasks.push(ProjectTask::new("just", "just"));
+    }
+    if dir.join("Makefile").is_file() || dir.join("makefile").is_file() {
+        tasks.push(ProjectTask::new("make", "make"));
+    }
+    if dir.join("meson.build").is_file() {
+        tasks.push(ProjectTask::new("meson compile", "meson compile -C build"));
+    }
+    if dir.join("package.json").is_file() {
+        tasks.push(ProjectTask::new("npm run build", "npm run build"));
+    }
+    tasks
+}
+
+// Paths with a line and maybe a column like `src/main.rs:10:5`, as compilers print them
+static LOCATION_REGEX: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"(?:^|[\s('\x22])((?:[\w.~+\-]*/)*[\w.+\-]+\.\w+):(\d+)(?::(\d+))?").unwrap()
+});
+
+/// Find the first location of an existing file in a line of output, with the line and column
+/// starting at zero
+pub fn location(line: &str, dir: &Path) -> Option<(PathBuf, usize, usize)> {
+    for captures in LOCATION_REGEX.captures_iter(line) {
+        let path = Path::new(&captures[1]);
+        let path = if path.is_absolute() {
+            path.to_path_buf()
+        } else {
+            dir.join(path)
+        };
+        if !path.is_file() {
+            continue;
+        }
+        let line_number: usize = captures[2].parse().ok()?;
+        let column = captures
+            .get(3)
+            .and_then(|column| column.as_str().parse::<usize>().ok())
+            .unwrap_or(1);
+        return Some((
+            path,
+            line_number.saturating_sub(1),
+            column.saturating_sub(1),
+        ));
+    }
+    None
+}
+
+fn lines(reader: impl AsyncRead + Unpin) -> impl stream::Stream<Item = String> {
+    stream::unfold(BufReader::new(reader).lines(), |mut lines| async move {
+        match lines.next_line().await {
+            Ok(Some(line)) => Some((line, lines)),
+            Ok(None) => None,
+            Err(err) => {
+                log::warn!("failed to read task output: {}", err);
+                None
+            }
+        }
+    })
+}
+
+/// Run `task` with the shell in `dir` until it exits or is stopped, sending its output as messages
+pub async fn run(
+    task: &ProjectTask,
+    dir: &Path,
+    mut output: mpsc::Sender<Message>,
+) -> io::Result<()> {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(&task.command)
+        .current_dir(dir)
+        .env("TERM", "dumb")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+    log::info!("{:?}", command);
+    let mut child = command.spawn()?;
+    let (Some(stdout), Some(stderr)) = (child.stdout.take(), child.stderr.take()) else {
+        return Err(io::Error::other("no pipes for task"));
+    };
+
+    let mut output_lines = stream::select(lines(stdout), lines(stderr));
+    while let Some(line) = output_lines.next().await {
+        if output
+            .send(Message::TaskOutput(shell::strip_escapes(&line)))
+            .await
+            .is_err()
+        {
+            return Ok(());
+        }
+    }
+
+    let status = child.wait().await?;
+    let _ = output.send(Message::TaskFinished(status.code())).await;
+    Ok(())
+}
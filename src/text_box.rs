@@ -2,13 +2,14 @@
 
 use cosmic::{
     Renderer,
-    cosmic_theme::palette::{WithAlpha, blend::Compose},
+    cosmic_theme::palette::{Srgba, WithAlpha, blend::Compose},
     iced::{
         Color, Element, Length, Padding, Point, Rectangle, Size, Vector,
         advanced::graphics::text::{Raw, font_system},
         event::{Event, Status},
         keyboard::{Event as KeyEvent, Modifiers},
         mouse::{self, Button, Event as MouseEvent, ScrollDelta},
+        window,
     },
     iced_core::{
         Border, Radians, Shell, Transformation,
@@ -27,31 +28,179 @@ use cosmic::{
     theme::Theme,
 };
 use cosmic_text::{
-    Action, BorrowedWithFontSystem, Edit, Metrics, Motion, Renderer as _, Scroll, Selection,
-    ViEditor,
+    Action, Align, AttrsList, BorrowedWithFontSystem, BufferLine, Cursor, Edit, FontSystem,
+    LayoutLine, LayoutRun, LineEnding, Metrics, Motion, Renderer as _, Scroll, Selection, Shaping,
+    ViEditor, ViMode, Wrap,
 };
 use std::{
     cell::Cell,
     cmp,
-    sync::{Arc, Mutex},
+    ops::{Deref, DerefMut},
+    sync::{Arc, Mutex, MutexGuard},
     time::{Duration, Instant},
 };
 
-use crate::{LINE_NUMBER_CACHE, SWASH_CACHE, line_number::LineNumberKey};
+use crate::{
+    LINE_NUMBER_CACHE, SWASH_CACHE, auto_close, brackets,
+    config::CursorShape,
+    conflict::Conflict,
+    git::{GitBlame, GitLineChange},
+    line_number::LineNumberKey,
+    lines,
+    lsp::{Diagnostic, Severity},
+    multi_cursor::{self, Caret},
+    spell::Misspelling,
+    vim::{self, Vim},
+};
+
+/// Kinds of lines marked on the vertical scrollbar
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Marker {
+    Change,
+    Error,
+    Match,
+    Warning,
+}
+
+/// Keys that choose a completion while completions are shown
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum CompletionKey {
+    Up,
+    Down,
+    Accept,
+    Dismiss,
+}
+
+/// A key that edits the text or moves the cursor, which is recorded in macros
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum MacroKey {
+    Named(Named, Modifiers),
+    Character(char),
+}
+
+/// Cursor, selection, and scroll position of a text box that shows the same editor as another
+/// one. They are swapped into the editor while that text box uses it
+#[derive(Clone, Copy, Debug)]
+pub struct EditorView {
+    cursor: Cursor,
+    selection: Selection,
+    scroll: Scroll,
+}
+
+impl EditorView {
+    pub fn new(editor: &ViEditor<'static, 'static>) -> Self {
+        Self {
+            cursor: editor.cursor(),
+            selection: editor.selection(),
+            scroll: editor.with_buffer(|buffer| buffer.scroll()),
+        }
+    }
+
+    /// Exchange this view with the one of `editor`
+    pub fn swap(&mut self, editor: &mut ViEditor<'static, 'static>) {
+        let mut font_system = font_system().write().unwrap();
+        // A moved cursor is scrolled to when the editor is shaped, which must happen before the
+        // view changes so that it scrolls the view that moved it
+        editor.borrow_with(font_system.raw()).shape_as_needed(false);
+        let view = Self::new(editor);
+
+        // The text may have been changed using the other view
+        let cursor = lines::clamp(editor, self.cursor);
+        let selection = match self.selection {
+            Selection::None => Selection::None,
+            Selection::Normal(select) => Selection::Normal(lines::clamp(editor, select)),
+            Selection::Line(select) => Selection::Line(lines::clamp(editor, select)),
+            Selection::Word(select) => Selection::Word(lines::clamp(editor, select)),
+        };
+        let mut scroll = self.scroll;
+        scroll.line = lines::clamp(editor, Cursor::new(scroll.line, 0)).line;
+
+        editor.set_cursor(cursor);
+        editor.set_selection(selection);
+        editor.borrow_with(font_system.raw()).shape_as_needed(false);
+        editor.with_buffer_mut(|buffer| buffer.set_scroll(scroll));
+        *self = view;
+    }
+}
+
+/// Editor locked by a text box, which has its view swapped in until this is dropped
+struct LockedEditor<'a> {
+    editor: MutexGuard<'a, ViEditor<'static, 'static>>,
+    view_opt: Option<MutexGuard<'a, EditorView>>,
+}
+
+impl Deref for LockedEditor<'_> {
+    type Target = ViEditor<'static, 'static>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.editor
+    }
+}
+
+impl DerefMut for LockedEditor<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.editor
+    }
+}
+
+impl Drop for LockedEditor<'_> {
+    fn drop(&mut self) {
+        if let Some(view) = &mut self.view_opt {
+            view.swap(&mut self.editor);
+        }
+    }
+}
 
 pub struct TextBox<'a, Message> {
     editor: &'a Mutex<ViEditor<'static, 'static>>,
+    // View of this text box, when another text box shows the same editor with its view
+    view: Option<&'a Mutex<EditorView>>,
+    carets: Option<&'a Mutex<Vec<Caret>>>,
+    vim: Option<&'a Mutex<Vim>>,
+    caret_point: Option<&'a Mutex<Option<Point>>>,
+    auto_close: bool,
+    brackets: Option<brackets::Syntax>,
+    conflicts: &'a [Conflict],
+    diagnostics: Option<&'a [Diagnostic]>,
+    find_matches: &'a [(Cursor, Cursor)],
+    git_blame: Option<&'a GitBlame>,
+    git_changes: Option<&'a [(usize, GitLineChange)]>,
+    markers: Vec<(usize, Marker)>,
     metrics: Metrics,
+    misspellings: &'a [Misspelling],
     id: Option<Id>,
     padding: Padding,
+    rulers: &'a [u16],
+    wrap_column: Option<u16>,
     on_auto_scroll: Option<Box<dyn Fn(Option<f32>) -> Message + 'a>>,
     on_changed: Option<Message>,
+    on_scroll: Option<Box<dyn Fn(usize) -> Message + 'a>>,
     on_focus: Option<Message>,
+    on_select: Option<Box<dyn Fn(String) -> Message + 'a>>,
+    on_middle_click: Option<Message>,
+    on_zoom: Option<(Message, Message)>,
     click_timing: Duration,
     has_context_menu: bool,
     on_context_menu: Option<Box<dyn Fn(Option<Point>) -> Message + 'a>>,
+    on_git_gutter: Option<Box<dyn Fn(Option<(usize, Point)>) -> Message + 'a>>,
+    on_completion: Option<Box<dyn Fn(CompletionKey) -> Message + 'a>>,
+    on_definition: Option<Message>,
+    on_hover: Option<Box<dyn Fn(Option<(Cursor, Point)>) -> Message + 'a>>,
+    on_snippet: Option<Message>,
+    on_key: Option<Box<dyn Fn(MacroKey) -> Message + 'a>>,
+    on_ex_command: Option<Box<dyn Fn(String, Option<(usize, usize)>) -> Message + 'a>>,
+    read_only: bool,
     highlight_current_line: bool,
+    indent_guides: bool,
     line_numbers: bool,
+    minimap: bool,
+    shared: bool,
+    show_whitespace: bool,
+    centered: bool,
+    cursor_shape: CursorShape,
+    vim_cursor_shape: CursorShape,
+    cursor_width: u16,
+    cursor_blink_opt: Option<Duration>,
 }
 
 impl<'a, Message> TextBox<'a, Message>
@@ -61,20 +210,129 @@ where
     pub fn new(editor: &'a Mutex<ViEditor<'static, 'static>>, metrics: Metrics) -> Self {
         Self {
             editor,
+            view: None,
+            carets: None,
+            vim: None,
+            caret_point: None,
+            auto_close: false,
+            brackets: None,
+            conflicts: &[],
+            diagnostics: None,
+            find_matches: &[],
+            git_blame: None,
+            git_changes: None,
+            markers: Vec::new(),
+            misspellings: &[],
             metrics,
             id: None,
             padding: Padding::new(0.0),
+            rulers: &[],
+            wrap_column: None,
             on_auto_scroll: None,
             on_changed: None,
+            on_scroll: None,
             on_focus: None,
+            on_select: None,
+            on_middle_click: None,
+            on_zoom: None,
             click_timing: Duration::from_millis(500),
             has_context_menu: false,
             on_context_menu: None,
+            on_git_gutter: None,
+            on_completion: None,
+            on_definition: None,
+            on_hover: None,
+            on_snippet: None,
+            on_key: None,
+            on_ex_command: None,
+            read_only: false,
             highlight_current_line: false,
+            indent_guides: false,
             line_numbers: false,
+            minimap: false,
+            shared: false,
+            show_whitespace: false,
+            centered: false,
+            cursor_shape: CursorShape::Bar,
+            vim_cursor_shape: CursorShape::Block,
+            cursor_width: 1,
+            cursor_blink_opt: None,
         }
     }
 
+    /// Additional carets, which receive the same edits as the cursor
+    pub fn carets(mut self, carets: &'a Mutex<Vec<Caret>>) -> Self {
+        self.carets = Some(carets);
+        self
+    }
+
+    /// Registers, marks, and the visual block of Vim bindings, where carets are only used for the
+    /// visual block
+    pub fn vim(mut self, vim: &'a Mutex<Vim>) -> Self {
+        self.vim = Some(vim);
+        self
+    }
+
+    /// Where the cursor is drawn, relative to the text box
+    pub fn caret_point(mut self, caret_point: &'a Mutex<Option<Point>>) -> Self {
+        self.caret_point = Some(caret_point);
+        self
+    }
+
+    /// Close brackets and quotes as they are typed
+    pub fn auto_close(mut self) -> Self {
+        self.auto_close = true;
+        self
+    }
+
+    /// Highlight the bracket pair at the cursor, skipping the strings and comments of a syntax
+    pub fn brackets(mut self, syntax: brackets::Syntax) -> Self {
+        self.brackets = Some(syntax);
+        self
+    }
+
+    /// Matches of the find query, sorted by their start
+    pub fn find_matches(mut self, find_matches: &'a [(Cursor, Cursor)]) -> Self {
+        self.find_matches = find_matches;
+        self
+    }
+
+    /// Merge conflicts to highlight, sorted by line
+    pub fn conflicts(mut self, conflicts: &'a [Conflict]) -> Self {
+        self.conflicts = conflicts;
+        self
+    }
+
+    /// Problems to underline and mark in the gutter, sorted by their start
+    pub fn diagnostics(mut self, diagnostics: &'a [Diagnostic]) -> Self {
+        self.diagnostics = Some(diagnostics);
+        self
+    }
+
+    /// Commits that last changed each line, to show in the gutter
+    pub fn git_blame(mut self, git_blame: &'a GitBlame) -> Self {
+        self.git_blame = Some(git_blame);
+        self
+    }
+
+    /// Lines that differ from the last commit, sorted by line, to mark in the gutter
+    pub fn git_changes(mut self, git_changes: &'a [(usize, GitLineChange)]) -> Self {
+        self.git_changes = Some(git_changes);
+        self
+    }
+
+    /// Words that the spell checker does not know, sorted by their start
+    pub fn misspellings(mut self, misspellings: &'a [Misspelling]) -> Self {
+        self.misspellings = misspellings;
+        self
+    }
+
+    /// Lines to mark on the vertical scrollbar, with the lines of each kind sorted
+    pub fn markers(mut self, markers: Vec<(usize, Marker)>) -> Self {
+        self.markers = markers;
+        self
+    }
+
     pub fn id(mut self, id: Id) -> Self {
         self.id = Some(id);
         self
@@ -85,6 +343,18 @@ where
         self
     }
 
+    /// Columns to draw vertical guide lines at
+    pub fn rulers(mut self, rulers: &'a [u16]) -> Self {
+        self.rulers = rulers;
+        self
+    }
+
+    /// Wrap lines at a column instead of the width of the text box
+    pub fn wrap_column(mut self, wrap_column: u16) -> Self {
+        self.wrap_column = Some(wrap_column);
+        self
+    }
+
     pub fn on_auto_scroll(mut self, on_auto_scroll: impl Fn(Option<f32>) -> Message + 'a) -> Self {
         self.on_auto_scroll = Some(Box::new(on_auto_scroll));
         self
@@ -95,6 +365,30 @@ where
         self
     }
 
+    /// Report the selected text when selecting with the mouse ends, for the primary selection
+    pub fn on_select(mut self, on_select: impl Fn(String) -> Message + 'a) -> Self {
+        self.on_select = Some(Box::new(on_select));
+        self
+    }
+
+    /// Move the cursor to a middle click and report it, for pasting the primary selection
+    pub fn on_middle_click(mut self, on_middle_click: Message) -> Self {
+        self.on_middle_click = Some(on_middle_click);
+        self
+    }
+
+    /// Zoom in and out with Ctrl+scroll instead of scrolling
+    pub fn on_zoom(mut self, zoom_in: Message, zoom_out: Message) -> Self {
+        self.on_zoom = Some((zoom_in, zoom_out));
+        self
+    }
+
+    /// Report the first line that is shown when the text box scrolls
+    pub fn on_scroll(mut self, on_scroll: impl Fn(usize) -> Message + 'a) -> Self {
+        self.on_scroll = Some(Box::new(on_scroll));
+        self
+    }
+
     pub fn click_timing(mut self, click_timing: Duration) -> Self {
         self.click_timing = click_timing;
         self
@@ -113,11 +407,69 @@ where
         self
     }
 
+    /// Called with the line and position of clicks on git changes in the gutter, and with None
+    /// for other clicks
+    pub fn on_git_gutter(
+        mut self,
+        on_git_gutter: impl Fn(Option<(usize, Point)>) -> Message + 'a,
+    ) -> Self {
+        self.on_git_gutter = Some(Box::new(on_git_gutter));
+        self
+    }
+
+    /// Called with keys that choose a completion, instead of giving them to the editor, which
+    /// should only be set while completions are shown
+    pub fn on_completion(mut self, on_completion: impl Fn(CompletionKey) -> Message + 'a) -> Self {
+        self.on_completion = Some(Box::new(on_completion));
+        self
+    }
+
+    /// Sent when Ctrl+Click moved the cursor, which then does not add a caret
+    pub fn on_definition(mut self, on_definition: Message) -> Self {
+        self.on_definition = Some(on_definition);
+        self
+    }
+
+    /// Called with the text position and point of the mouse when it moves to other text, and
+    /// with None when it leaves the text
+    pub fn on_hover(mut self, on_hover: impl Fn(Option<(Cursor, Point)>) -> Message + 'a) -> Self {
+        self.on_hover = Some(Box::new(on_hover));
+        self
+    }
+
+    /// Sent when Tab is pressed, instead of indenting, which should only be set while a snippet
+    /// can be expanded at the cursor or its tab stops are visited
+    pub fn on_snippet(mut self, on_snippet: Message) -> Self {
+        self.on_snippet = Some(on_snippet);
+        self
+    }
+
+    /// Called with the keys that edited the text or moved the cursor
+    pub fn on_key(mut self, on_key: impl Fn(MacroKey) -> Message + 'a) -> Self {
+        self.on_key = Some(Box::new(on_key));
+        self
+    }
+
+    /// Called with the text of the Vim command line and the lines of the selection, instead of the
+    /// editor running it
+    pub fn on_ex_command(
+        mut self,
+        on_ex_command: impl Fn(String, Option<(usize, usize)>) -> Message + 'a,
+    ) -> Self {
+        self.on_ex_command = Some(Box::new(on_ex_command));
+        self
+    }
+
     pub fn highlight_current_line(mut self) -> Self {
         self.highlight_current_line = true;
         self
     }
 
+    pub fn indent_guides(mut self) -> Self {
+        self.indent_guides = true;
+        self
+    }
+
     pub fn line_numbers(mut self) -> Self {
         self.line_numbers = true;
         self
@@ -127,6 +479,66 @@ where
         self.on_focus = Some(on_focus);
         self
     }
+
+    /// Only move the cursor and select text, as the text cannot be edited yet
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Show an overview of the document next to the scrollbar
+    pub fn minimap(mut self) -> Self {
+        self.minimap = true;
+        self
+    }
+
+    pub fn show_whitespace(mut self) -> Self {
+        self.show_whitespace = true;
+        self
+    }
+
+    /// The editor is also shown by another text box, so the cached image cannot be reused
+    pub fn shared(mut self) -> Self {
+        self.shared = true;
+        self
+    }
+
+    /// Use a cursor, selection, and scroll position of its own instead of those of the editor,
+    /// which are used by another text box
+    pub fn view(mut self, view: &'a Mutex<EditorView>) -> Self {
+        self.view = Some(view);
+        self.shared()
+    }
+
+    fn lock_editor(&self) -> LockedEditor<'a> {
+        let mut editor = self.editor.lock().unwrap();
+        let mut view_opt = self.view.map(|view| view.lock().unwrap());
+        if let Some(view) = &mut view_opt {
+            view.swap(&mut editor);
+        }
+        LockedEditor { editor, view_opt }
+    }
+
+    /// Put the text in the middle when it wraps at a column narrower than the text box
+    pub fn centered(mut self) -> Self {
+        self.centered = true;
+        self
+    }
+
+    /// Draw the cursor as `shape`, or as `vim_shape` outside of insert mode with Vim bindings,
+    /// where bars and underlines are `width` logical pixels thick
+    pub fn cursor(mut self, shape: CursorShape, vim_shape: CursorShape, width: u16) -> Self {
+        self.cursor_shape = shape;
+        self.vim_cursor_shape = vim_shape;
+        self.cursor_width = width;
+        self
+    }
+
+    /// Blink the cursor while focused, showing and then hiding it for `interval`
+    pub fn cursor_blink(mut self, interval: Duration) -> Self {
+        self.cursor_blink_opt = Some(interval);
+        self
+    }
 }
 
 pub fn text_box<'a, Message>(
@@ -235,10 +647,39 @@ fn draw_rect(
 struct CustomRenderer<'a> {
     renderer: &'a mut Renderer,
     pos: Point,
+    // Rectangles at this position and with this height and at most this width are not drawn
+    skip_opt: Option<(i32, i32, u32, u32)>,
+}
+
+impl CustomRenderer<'_> {
+    // Wavy line ending at `bottom`, made of steps that are `wave` pixels high
+    fn wavy_line(&mut self, x: i32, w: i32, bottom: i32, wave: i32, color: cosmic_text::Color) {
+        use cosmic_text::Renderer as _;
+
+        let w = w.max(wave * 2);
+        let mut offset = 0;
+        while offset < w {
+            let up = (offset / (wave * 2)) % 2 == 0;
+            self.rectangle(
+                x + offset,
+                if up { bottom } else { bottom + wave },
+                (wave * 2).min(w - offset) as u32,
+                wave as u32,
+                color,
+            );
+            offset += wave * 2;
+        }
+    }
 }
 
 impl<'a> cosmic_text::Renderer for CustomRenderer<'a> {
     fn rectangle(&mut self, x: i32, y: i32, w: u32, h: u32, color: cosmic_text::Color) {
+        if let Some((skip_x, skip_y, skip_w, skip_h)) = self.skip_opt
+            && (x, y, h) == (skip_x, skip_y, skip_h)
+            && w <= skip_w
+        {
+            return;
+        }
         self.renderer.fill_quad(
             Quad {
                 bounds: Rectangle::new(
@@ -280,7 +721,7 @@ where
     ) -> layout::Node {
         let limits = limits.width(Length::Fill).height(Length::Fill);
 
-        let mut editor = self.editor.lock().unwrap();
+        let mut editor = self.lock_editor();
         //TODO: set size?
         editor
             .borrow_with(font_system().write().unwrap().raw())
@@ -331,7 +772,7 @@ where
         if let Some(p) = cursor_position.position_in(layout.bounds()) {
             let editor_offset_x = state.editor_offset_x.get();
             let scale_factor = state.scale_factor.get();
-            let editor = self.editor.lock().unwrap();
+            let editor = self.lock_editor();
             let buffer_size = editor.with_buffer(|buffer| buffer.size());
 
             let x_logical = p.x - self.padding.left;
@@ -364,15 +805,18 @@ where
 
         let state = tree.state.downcast_ref::<State>();
 
-        let mut editor = self.editor.lock().unwrap();
+        let mut editor = self.lock_editor();
 
         let cosmic_theme = theme.cosmic();
         let scrollbar_size = cosmic_theme.spacing.space_xxs as i32;
 
+        let minimap_w = if self.minimap { MINIMAP_WIDTH } else { 0 };
+
         let view_position = layout.position() + [self.padding.left, self.padding.top].into();
         let view_w = cmp::min(viewport.width as i32, layout.bounds().width as i32)
             - self.padding.horizontal() as i32
-            - scrollbar_size;
+            - scrollbar_size
+            - minimap_w;
         let view_h = cmp::min(viewport.height as i32, layout.bounds().height as i32)
             - self.padding.vertical() as i32;
 
@@ -446,18 +890,62 @@ where
             (0, 0)
         };
 
+        // Reserve a column before the line numbers for git blame
+        let char_w = char_width(font_system.raw(), metrics);
+        let blame_w = if self.git_blame.is_some() {
+            (BLAME_CHARS as f32 * char_w + 8.0).ceil() as i32
+        } else {
+            0
+        };
+        let editor_offset_x = blame_w + editor_offset_x;
+
+        // Reserve a column after the line numbers for diagnostics
+        let diagnostic_x = editor_offset_x;
+        let diagnostic_w = if self.diagnostics.is_some() {
+            (metrics.line_height * 0.75).ceil() as i32
+        } else {
+            0
+        };
+        let editor_offset_x = editor_offset_x + diagnostic_w;
+
+        // Reserve a strip after the line numbers for git changes
+        let git_gutter_x = editor_offset_x;
+        let git_gutter_w = (GIT_GUTTER_WIDTH as f32 * scale_factor).ceil() as i32;
+        let editor_offset_x = if self.git_changes.is_some() {
+            editor_offset_x + git_gutter_w + (GIT_GUTTER_GAP as f32 * scale_factor).ceil() as i32
+        } else {
+            editor_offset_x
+        };
+
+        // Leave the same space on both sides of centered text
+        let editor_offset_x = match self.wrap_column {
+            Some(wrap_column) if self.centered && char_w > 0.0 => {
+                let text_w = (f32::from(wrap_column) * char_w).ceil() as i32;
+                editor_offset_x + ((image_w - editor_offset_x - text_w) / 2).max(0)
+            }
+            _ => editor_offset_x,
+        };
+
         // Save editor offset in state
-        if state.editor_offset_x.replace(editor_offset_x) != editor_offset_x {
-            // Mark buffer as needing redraw if editor offset has changed
+        state.git_gutter_x.set(git_gutter_x);
+        if state.editor_offset_x.replace(editor_offset_x) != editor_offset_x || self.shared {
+            // Mark buffer as needing redraw if editor offset has changed or another text box
+            // may have cleared the redraw flag
             editor.set_redraw(true);
         }
 
-        // Set metrics and size
+        // Set metrics and size, narrowing the buffer to wrap at a column
+        let mut buffer_w = (image_w - editor_offset_x) as f32;
+        if let Some(wrap_column) = self.wrap_column
+            && char_w > 0.0
+        {
+            buffer_w = buffer_w.min(f32::from(wrap_column) * char_w);
+        }
         editor.with_buffer_mut(|buffer| {
             buffer.set_metrics_and_size(
                 font_system.raw(),
                 metrics,
-                Some((image_w - editor_offset_x) as f32),
+                Some(buffer_w),
                 Some(image_h as f32),
             )
         });
@@ -466,11 +954,13 @@ where
         editor.shape_as_needed(font_system.raw(), true);
 
         let mut handle_opt = state.handle_opt.lock().unwrap();
+        let mut minimap_handle_opt = state.minimap_handle_opt.lock().unwrap();
         let image_canvas = Canvas {
             w: editor_offset_x,
             h: image_h,
         };
-        if editor.redraw() || handle_opt.is_none() {
+        if editor.redraw() || handle_opt.is_none() || (self.minimap && minimap_handle_opt.is_none())
+        {
             // Draw to pixel buffer
             let mut pixels_u8 = vec![0; image_canvas.w as usize * image_canvas.h as usize * 4];
             {
@@ -484,7 +974,7 @@ where
                 };
 
                 //TODO: draw line numbers using iced functions for performance
-                if self.line_numbers {
+                if self.line_numbers || self.git_blame.is_some() || self.diagnostics.is_some() {
                     let (gutter, gutter_foreground) = {
                         let convert_color = |color: syntect::highlighting::Color| {
                             cosmic_text::Color::rgba(color.r, color.g, color.b, color.a)
@@ -513,64 +1003,235 @@ where
                         gutter,
                     );
 
+                    // Draw blame annotations on the first line of each commit
+                    if let Some(git_blame) = self.git_blame {
+                        editor.with_buffer(|buffer| {
+                            let mut last_line = None;
+                            for run in buffer.layout_runs() {
+                                if last_line == Some(run.line_i) {
+                                    continue;
+                                }
+                                last_line = Some(run.line_i);
+                                let Some(annotation) = git_blame.annotation(run.line_i) else {
+                                    continue;
+                                };
+                                let text: String = annotation.chars().take(BLAME_CHARS).collect();
+                                for layout_line in layout_text(font_system.raw(), text).iter() {
+                                    let max_ascent = layout_line.max_ascent * metrics.font_size;
+                                    let max_descent = layout_line.max_descent * metrics.font_size;
+                                    let glyph_height = max_ascent + max_descent;
+                                    let centering_offset =
+                                        (metrics.line_height - glyph_height) / 2.0;
+                                    let line_y = run.line_top + centering_offset + max_ascent;
+
+                                    for layout_glyph in layout_line.glyphs.iter() {
+                                        // Wide characters may not fit in the column
+                                        let glyph_end =
+                                            (layout_glyph.x + layout_glyph.w) * metrics.font_size;
+                                        if glyph_end + 4.0 > blame_w as f32 {
+                                            break;
+                                        }
+                                        let physical_glyph =
+                                            layout_glyph.physical((4.0, line_y), metrics.font_size);
+                                        swash_cache.with_pixels(
+                                            font_system.raw(),
+                                            physical_glyph.cache_key,
+                                            gutter_foreground,
+                                            |x, y, color| {
+                                                draw_rect(
+                                                    pixels,
+                                                    image_canvas,
+                                                    Canvas { w: 1, h: 1 },
+                                                    Offset {
+                                                        x: physical_glyph.x + x,
+                                                        y: physical_glyph.y + y,
+                                                    },
+                                                    color,
+                                                );
+                                            },
+                                        );
+                                    }
+                                }
+                            }
+                        });
+                    }
+
                     // Draw line numbers
                     //TODO: move to cosmic-text?
+                    if self.line_numbers {
+                        editor.with_buffer(|buffer| {
+                            let mut line_number_cache =
+                                LINE_NUMBER_CACHE.get().unwrap().lock().unwrap();
+                            let mut last_line_number = 0;
+                            for run in buffer.layout_runs() {
+                                let line_number = run.line_i.saturating_add(1);
+                                if line_number == last_line_number {
+                                    // Skip duplicate lines
+                                    continue;
+                                } else {
+                                    last_line_number = line_number;
+                                }
+
+                                if let Some(layout_line) = line_number_cache
+                                    .get(
+                                        font_system.raw(),
+                                        LineNumberKey {
+                                            number: line_number,
+                                            width: line_number_chars,
+                                        },
+                                    )
+                                    .first()
+                                {
+                                    // These values must be scaled since layout is done at font size 1.0
+                                    let max_ascent = layout_line.max_ascent * metrics.font_size;
+                                    let max_descent = layout_line.max_descent * metrics.font_size;
+
+                                    // This code comes from cosmic_text::LayoutRunIter
+                                    let glyph_height = max_ascent + max_descent;
+                                    let centering_offset =
+                                        (metrics.line_height - glyph_height) / 2.0;
+                                    let line_y = run.line_top + centering_offset + max_ascent;
+
+                                    for layout_glyph in layout_line.glyphs.iter() {
+                                        let physical_glyph = layout_glyph
+                                            .physical((blame_w as f32, line_y), metrics.font_size);
+
+                                        swash_cache.with_pixels(
+                                            font_system.raw(),
+                                            physical_glyph.cache_key,
+                                            gutter_foreground,
+                                            |x, y, color| {
+                                                draw_rect(
+                                                    pixels,
+                                                    image_canvas,
+                                                    Canvas { w: 1, h: 1 },
+                                                    Offset {
+                                                        x: physical_glyph.x + x,
+                                                        y: physical_glyph.y + y,
+                                                    },
+                                                    color,
+                                                );
+                                            },
+                                        );
+                                    }
+                                }
+                            }
+                        });
+                    }
+                }
+
+                // Draw a dot for the most severe diagnostic of each line
+                if let Some(diagnostics) = self.diagnostics
+                    && !diagnostics.is_empty()
+                {
+                    let size = (metrics.line_height / 3.0).ceil() as i32;
+                    let radius = size as f32 / 2.0;
                     editor.with_buffer(|buffer| {
-                        let mut line_number_cache =
-                            LINE_NUMBER_CACHE.get().unwrap().lock().unwrap();
-                        let mut last_line_number = 0;
+                        let mut last_line = None;
                         for run in buffer.layout_runs() {
-                            let line_number = run.line_i.saturating_add(1);
-                            if line_number == last_line_number {
-                                // Skip duplicate lines
+                            if last_line == Some(run.line_i) {
+                                continue;
+                            }
+                            last_line = Some(run.line_i);
+                            let start = diagnostics.partition_point(|d| d.start.line < run.line_i);
+                            let Some(severity) = diagnostics[start..]
+                                .iter()
+                                .take_while(|d| d.start.line == run.line_i)
+                                .map(|d| d.severity)
+                                .min()
+                            else {
                                 continue;
-                            } else {
-                                last_line_number = line_number;
+                            };
+                            let color = severity_color(cosmic_theme, severity);
+                            let x = diagnostic_x + (diagnostic_w - size) / 2;
+                            let y = run.line_top as i32 + (metrics.line_height as i32 - size) / 2;
+                            for dy in 0..size {
+                                for dx in 0..size {
+                                    let (cx, cy) =
+                                        (dx as f32 + 0.5 - radius, dy as f32 + 0.5 - radius);
+                                    if cx * cx + cy * cy <= radius * radius {
+                                        draw_rect(
+                                            pixels,
+                                            image_canvas,
+                                            Canvas { w: 1, h: 1 },
+                                            Offset {
+                                                x: x + dx,
+                                                y: y + dy,
+                                            },
+                                            color,
+                                        );
+                                    }
+                                }
                             }
+                        }
+                    });
+                }
 
-                            if let Some(layout_line) = line_number_cache
-                                .get(
-                                    font_system.raw(),
-                                    LineNumberKey {
-                                        number: line_number,
-                                        width: line_number_chars,
-                                    },
-                                )
-                                .first()
+                // Draw git changes
+                if let Some(git_changes) = self.git_changes
+                    && !git_changes.is_empty()
+                {
+                    let theme_color = |color: Srgba| {
+                        let color = color.into_format::<u8, u8>();
+                        cosmic_text::Color::rgba(color.red, color.green, color.blue, color.alpha)
+                    };
+                    let added = theme_color(cosmic_theme.success.base);
+                    let modified = theme_color(cosmic_theme.warning.base);
+                    let deleted = theme_color(cosmic_theme.destructive.base);
+                    let line_h = metrics.line_height as i32;
+                    // Deletions are a short bar where the lines used to be
+                    let deleted_h = (line_h / 4).max(2);
+                    editor.with_buffer(|buffer| {
+                        let line_count = buffer.lines.len();
+                        let mut last_line = None;
+                        let mut last_bottom = 0;
+                        for run in buffer.layout_runs() {
+                            let first_run = last_line != Some(run.line_i);
+                            last_line = Some(run.line_i);
+                            let top = run.line_top as i32;
+                            last_bottom = top + line_h;
+                            let start = git_changes.partition_point(|(line, _)| *line < run.line_i);
+                            for (_, change) in git_changes[start..]
+                                .iter()
+                                .take_while(|(line, _)| *line == run.line_i)
                             {
-                                // These values must be scaled since layout is done at font size 1.0
-                                let max_ascent = layout_line.max_ascent * metrics.font_size;
-                                let max_descent = layout_line.max_descent * metrics.font_size;
-
-                                // This code comes from cosmic_text::LayoutRunIter
-                                let glyph_height = max_ascent + max_descent;
-                                let centering_offset = (metrics.line_height - glyph_height) / 2.0;
-                                let line_y = run.line_top + centering_offset + max_ascent;
-
-                                for layout_glyph in layout_line.glyphs.iter() {
-                                    let physical_glyph =
-                                        layout_glyph.physical((0., line_y), metrics.font_size);
-
-                                    swash_cache.with_pixels(
-                                        font_system.raw(),
-                                        physical_glyph.cache_key,
-                                        gutter_foreground,
-                                        |x, y, color| {
-                                            draw_rect(
-                                                pixels,
-                                                image_canvas,
-                                                Canvas { w: 1, h: 1 },
-                                                Offset {
-                                                    x: physical_glyph.x + x,
-                                                    y: physical_glyph.y + y,
-                                                },
-                                                color,
-                                            );
-                                        },
-                                    );
-                                }
+                                let (color, y, h) = match change {
+                                    GitLineChange::Added => (added, top, line_h),
+                                    GitLineChange::Modified => (modified, top, line_h),
+                                    GitLineChange::Deleted if first_run => {
+                                        (deleted, top - deleted_h / 2, deleted_h)
+                                    }
+                                    GitLineChange::Deleted => continue,
+                                };
+                                draw_rect(
+                                    pixels,
+                                    image_canvas,
+                                    Canvas { w: git_gutter_w, h },
+                                    Offset { x: git_gutter_x, y },
+                                    color,
+                                );
                             }
                         }
+                        // Lines deleted at the end of the file
+                        if last_line.is_some_and(|line| line + 1 == line_count)
+                            && git_changes
+                                .last()
+                                .is_some_and(|(line, _)| *line >= line_count)
+                        {
+                            draw_rect(
+                                pixels,
+                                image_canvas,
+                                Canvas {
+                                    w: git_gutter_w,
+                                    h: deleted_h,
+                                },
+                                Offset {
+                                    x: git_gutter_x,
+                                    y: last_bottom - deleted_h / 2,
+                                },
+                                deleted,
+                            );
+                        }
                     });
                 }
 
@@ -595,7 +1256,11 @@ where
                     let end_y = ((end_line + 1) * image_h as usize) / lines;
 
                     let rect = Rectangle::new(
-                        [image_w as f32 / scale_factor, start_y as f32 / scale_factor].into(),
+                        [
+                            image_w as f32 / scale_factor + minimap_w as f32,
+                            start_y as f32 / scale_factor,
+                        ]
+                        .into(),
                         Size::new(
                             scrollbar_size as f32,
                             (end_y as f32 - start_y as f32) / scale_factor,
@@ -626,6 +1291,96 @@ where
                 });
             }
 
+            // Draw minimap, with a block for each character
+            if self.minimap {
+                let minimap_canvas = Canvas {
+                    w: (minimap_w as f32 * scale_factor) as i32,
+                    h: image_h,
+                };
+                let mut minimap_pixels_u8 =
+                    vec![0; minimap_canvas.w as usize * minimap_canvas.h as usize * 4];
+                let minimap_pixels = unsafe {
+                    std::slice::from_raw_parts_mut(
+                        minimap_pixels_u8.as_mut_ptr() as *mut u32,
+                        minimap_pixels_u8.len() / 4,
+                    )
+                };
+                let foreground = editor.foreground_color();
+                let tab_width = usize::from(editor.tab_width()).max(1);
+                let char_w = scale_factor.round().max(1.0) as i32;
+                let line_h = char_w * 2;
+                editor.with_buffer(|buffer| {
+                    let mut start_line_opt = None;
+                    let mut end_line = 0;
+                    for run in buffer.layout_runs() {
+                        end_line = run.line_i;
+                        start_line_opt.get_or_insert(end_line);
+                    }
+                    let start_line = start_line_opt.unwrap_or(end_line);
+
+                    // Scroll the minimap with the editor when the document does not fit
+                    let lines = buffer.lines.len();
+                    let rows = (image_h / line_h) as usize;
+                    let first_line = if lines > rows {
+                        let max_scroll = lines.saturating_sub(end_line + 1 - start_line).max(1);
+                        start_line.min(max_scroll) * (lines - rows) / max_scroll
+                    } else {
+                        0
+                    };
+
+                    for (row, line) in buffer.lines[first_line..].iter().take(rows).enumerate() {
+                        let attrs_list = line.attrs_list();
+                        let mut column = 0;
+                        for (index, c) in line.text().char_indices() {
+                            let x = column as i32 * char_w;
+                            if x >= minimap_canvas.w {
+                                break;
+                            }
+                            if c == '\t' {
+                                column = (column / tab_width + 1) * tab_width;
+                                continue;
+                            }
+                            if !c.is_whitespace() {
+                                draw_rect(
+                                    minimap_pixels,
+                                    minimap_canvas,
+                                    Canvas {
+                                        w: char_w,
+                                        h: line_h - 1,
+                                    },
+                                    Offset {
+                                        x,
+                                        y: row as i32 * line_h,
+                                    },
+                                    attrs_list.get_span(index).color_opt.unwrap_or(foreground),
+                                );
+                            }
+                            column += 1;
+                        }
+                    }
+
+                    state.minimap_first_line.set(first_line);
+                    state.minimap_line_h.set(line_h);
+                    state.minimap_viewport.set(Rectangle::new(
+                        [
+                            image_w as f32 / scale_factor,
+                            (start_line.saturating_sub(first_line) as i32 * line_h) as f32
+                                / scale_factor,
+                        ]
+                        .into(),
+                        Size::new(
+                            minimap_w as f32,
+                            ((end_line + 1 - start_line) as i32 * line_h) as f32 / scale_factor,
+                        ),
+                    ));
+                });
+                *minimap_handle_opt = Some(image::Handle::from_rgba(
+                    minimap_canvas.w as u32,
+                    minimap_canvas.h as u32,
+                    minimap_pixels_u8,
+                ));
+            }
+
             // Clear redraw flag
             editor.set_redraw(false);
 
@@ -660,14 +1415,45 @@ where
                     );
                 }
 
-                // Calculate editor position
-                let scroll_x = editor.with_buffer(|buffer| buffer.scroll().horizontal);
-                let pos = Point::new(editor_offset_x as f32 - scroll_x, 0.0);
+                // Draw cached minimap
+                if let Some(ref handle) = *minimap_handle_opt
+                    && self.minimap
+                {
+                    let image_size = image::Renderer::measure_image(renderer, handle);
+                    image::Renderer::draw_image(
+                        renderer,
+                        handle.clone(),
+                        image::FilterMethod::Nearest,
+                        Rectangle::new(
+                            Point::new(image_w as f32, 0.0),
+                            Size::new(image_size.width as f32, image_size.height as f32),
+                        ),
+                        Radians(0.0),
+                        1.0,
+                        [0.0; 4],
+                    );
+                }
+
+                // Calculate editor position
+                let scroll_x = editor.with_buffer(|buffer| buffer.scroll().horizontal);
+                let pos = Point::new(editor_offset_x as f32 - scroll_x, 0.0);
+                if let Some(caret_point) = self.caret_point {
+                    *caret_point.lock().unwrap() = editor.cursor_position().map(|(x, y)| {
+                        Point::new(
+                            self.padding.left + (pos.x + x as f32) / scale_factor,
+                            self.padding.top + (y as f32 + metrics.line_height) / scale_factor,
+                        )
+                    });
+                }
                 let size = Size::new((image_w - editor_offset_x) as f32, image_h as f32);
                 let clip_bounds = Rectangle::new(Point::new(editor_offset_x as f32, 0.0), size);
                 renderer.with_layer(clip_bounds, |renderer| {
                     // Create custom renderer for rectangles
-                    let mut custom_renderer = CustomRenderer { renderer, pos };
+                    let mut custom_renderer = CustomRenderer {
+                        renderer,
+                        pos,
+                        skip_opt: None,
+                    };
 
                     // Draw line highlight
                     if self.highlight_current_line {
@@ -701,8 +1487,443 @@ where
                         });
                     }
 
-                    // Draw editor selection, cursor, etc.
+                    // Draw merge conflicts, with our lines and their lines in different colors
+                    if !self.conflicts.is_empty() {
+                        let theme_color = |color: Srgba, alpha: f32| {
+                            let color = color
+                                .without_alpha()
+                                .with_alpha(alpha)
+                                .into_format::<u8, u8>();
+                            cosmic_text::Color::rgba(
+                                color.red,
+                                color.green,
+                                color.blue,
+                                color.alpha,
+                            )
+                        };
+                        let marker = theme_color(cosmic_theme.palette.neutral_5, 0.4);
+                        let ours = theme_color(cosmic_theme.accent.base, 0.15);
+                        let base = theme_color(cosmic_theme.palette.neutral_5, 0.15);
+                        let theirs = theme_color(cosmic_theme.success.base, 0.15);
+                        editor.with_buffer(|buffer| {
+                            let mut conflict_i = 0;
+                            for run in buffer.layout_runs() {
+                                while self
+                                    .conflicts
+                                    .get(conflict_i)
+                                    .is_some_and(|conflict| conflict.end < run.line_i)
+                                {
+                                    conflict_i += 1;
+                                }
+                                let Some(conflict) = self
+                                    .conflicts
+                                    .get(conflict_i)
+                                    .filter(|conflict| conflict.contains(run.line_i))
+                                else {
+                                    continue;
+                                };
+
+                                let line = run.line_i;
+                                let color = if line == conflict.start
+                                    || Some(line) == conflict.base_opt
+                                    || line == conflict.separator
+                                    || line == conflict.end
+                                {
+                                    marker
+                                } else if line > conflict.separator {
+                                    theirs
+                                } else if conflict.base_opt.is_some_and(|base| line > base) {
+                                    base
+                                } else {
+                                    ours
+                                };
+                                custom_renderer.rectangle(
+                                    0,
+                                    run.line_top as i32,
+                                    (image_w - editor_offset_x) as u32,
+                                    metrics.line_height as u32,
+                                    color,
+                                );
+                            }
+                        });
+                    }
+
+                    // Draw wavy underlines below diagnostics
+                    if let Some(diagnostics) = self.diagnostics
+                        && !diagnostics.is_empty()
+                    {
+                        let wave = scale_factor.ceil().max(1.0) as i32;
+                        editor.with_buffer(|buffer| {
+                            for run in buffer.layout_runs() {
+                                // Diagnostics can span lines, so every earlier one may overlap
+                                let end =
+                                    diagnostics.partition_point(|d| d.start.line <= run.line_i);
+                                for diagnostic in diagnostics[..end]
+                                    .iter()
+                                    .filter(|d| d.end.line >= run.line_i)
+                                {
+                                    let Some((x, w)) =
+                                        run.highlight(diagnostic.start, diagnostic.end)
+                                    else {
+                                        continue;
+                                    };
+                                    let color = severity_color(cosmic_theme, diagnostic.severity);
+                                    let bottom =
+                                        (run.line_top + metrics.line_height) as i32 - 2 * wave;
+                                    custom_renderer
+                                        .wavy_line(x as i32, w as i32, bottom, wave, color);
+                                }
+                            }
+                        });
+                    }
+
+                    // Draw wavy underlines below misspelled words
+                    if !self.misspellings.is_empty() {
+                        let wave = scale_factor.ceil().max(1.0) as i32;
+                        let color = severity_color(cosmic_theme, Severity::Error);
+                        editor.with_buffer(|buffer| {
+                            for run in buffer.layout_runs() {
+                                // Misspellings are single words, so they start on this line
+                                let first = self
+                                    .misspellings
+                                    .partition_point(|m| m.start.line < run.line_i);
+                                for misspelling in self.misspellings[first..]
+                                    .iter()
+                                    .take_while(|m| m.start.line == run.line_i)
+                                {
+                                    let Some((x, w)) =
+                                        run.highlight(misspelling.start, misspelling.end)
+                                    else {
+                                        continue;
+                                    };
+                                    let bottom =
+                                        (run.line_top + metrics.line_height) as i32 - 2 * wave;
+                                    custom_renderer
+                                        .wavy_line(x as i32, w as i32, bottom, wave, color);
+                                }
+                            }
+                        });
+                    }
+
+                    // Draw find matches
+                    if !self.find_matches.is_empty() {
+                        let find_highlight = {
+                            let convert_color = |color: syntect::highlighting::Color| {
+                                cosmic_text::Color::rgba(color.r, color.g, color.b, color.a)
+                            };
+                            let syntax_theme = editor.theme();
+                            syntax_theme
+                                .settings
+                                .find_highlight
+                                .or(syntax_theme.settings.selection)
+                                .map_or(editor.foreground_color(), convert_color)
+                        };
+
+                        editor.with_buffer(|buffer| {
+                            for run in buffer.layout_runs() {
+                                // Only the matches that start on this line
+                                let first = self
+                                    .find_matches
+                                    .partition_point(|(start, _end)| start.line < run.line_i);
+                                for (start, end) in self.find_matches[first..]
+                                    .iter()
+                                    .take_while(|(start, _end)| start.line == run.line_i)
+                                {
+                                    if let Some((x, w)) = run.highlight(*start, *end) {
+                                        custom_renderer.rectangle(
+                                            x as i32,
+                                            run.line_top as i32,
+                                            w as u32,
+                                            metrics.line_height as u32,
+                                            find_highlight,
+                                        );
+                                    }
+                                }
+                            }
+                        });
+                    }
+
+                    // Draw matching brackets
+                    if let Some(syntax) = self.brackets {
+                        let bracket_highlight = {
+                            let convert_color = |color: syntect::highlighting::Color| {
+                                cosmic_text::Color::rgba(color.r, color.g, color.b, color.a)
+                            };
+                            let syntax_theme = editor.theme();
+                            syntax_theme
+                                .settings
+                                .brackets_background
+                                .or(syntax_theme.settings.selection)
+                                .map_or(editor.foreground_color(), convert_color)
+                        };
+
+                        let cursor = editor.cursor();
+                        editor.with_buffer(|buffer| {
+                            let Some((open, close)) = brackets::pair(buffer, cursor, syntax) else {
+                                return;
+                            };
+                            for run in buffer.layout_runs() {
+                                for position in [open, close] {
+                                    if position.line != run.line_i {
+                                        continue;
+                                    }
+                                    // Brackets are all one byte long
+                                    let end = Cursor::new(position.line, position.index + 1);
+                                    if let Some((x, w)) = run.highlight(position, end) {
+                                        custom_renderer.rectangle(
+                                            x as i32,
+                                            run.line_top as i32,
+                                            w as u32,
+                                            metrics.line_height as u32,
+                                            bracket_highlight,
+                                        );
+                                    }
+                                }
+                            }
+                        });
+                    }
+
+                    // Draw editor selection, etc. The cursor of the editor, which is a bar or
+                    // a block as wide as a character, is replaced by one in the configured shape
+                    custom_renderer.skip_opt = editor.cursor_position().map(|(x, y)| {
+                        (
+                            x,
+                            y,
+                            (char_w * 2.0).ceil() as u32,
+                            metrics.line_height as u32,
+                        )
+                    });
                     editor.render(&mut custom_renderer);
+                    custom_renderer.skip_opt = None;
+
+                    let convert_color = |color: syntect::highlighting::Color| {
+                        cosmic_text::Color::rgba(color.r, color.g, color.b, color.a)
+                    };
+                    let faint_color = |alpha: u8| {
+                        let color = editor.foreground_color();
+                        cosmic_text::Color::rgba(color.r(), color.g(), color.b(), alpha)
+                    };
+
+                    // Draw the cursor
+                    let cursor_shape =
+                        if self.vim.is_some() && !matches!(editor.parser().mode, ViMode::Insert) {
+                            self.vim_cursor_shape
+                        } else {
+                            self.cursor_shape
+                        };
+                    let cursor_thickness = (f32::from(self.cursor_width) * scale_factor)
+                        .round()
+                        .max(1.0) as u32;
+                    let caret_color = editor
+                        .theme()
+                        .settings
+                        .caret
+                        .map_or(editor.foreground_color(), convert_color);
+                    let cursor_visible = match self.cursor_blink_opt {
+                        Some(interval) if state.is_focused => {
+                            let elapsed = state.blink_start.elapsed().as_millis();
+                            (elapsed / interval.as_millis().max(1)) % 2 == 0
+                        }
+                        _ => true,
+                    };
+                    if cursor_visible {
+                        let cursor = editor.cursor();
+                        editor.with_buffer(|buffer| {
+                            for run in buffer.layout_runs() {
+                                if let Some((x, y, w, h)) = caret_rect(
+                                    &run,
+                                    cursor,
+                                    cursor_shape,
+                                    cursor_thickness,
+                                    char_w,
+                                    metrics.line_height,
+                                ) {
+                                    custom_renderer.rectangle(
+                                        x,
+                                        y,
+                                        w,
+                                        h,
+                                        caret_shape_color(caret_color, cursor_shape),
+                                    );
+                                }
+                            }
+                        });
+                    }
+
+                    // Draw rulers
+                    if !self.rulers.is_empty() {
+                        let ruler_color = editor
+                            .theme()
+                            .settings
+                            .guide
+                            .map_or(faint_color(0x30), convert_color);
+                        for column in self.rulers.iter() {
+                            custom_renderer.rectangle(
+                                (f32::from(*column) * char_w) as i32,
+                                0,
+                                1,
+                                image_h as u32,
+                                ruler_color,
+                            );
+                        }
+                    }
+
+                    // Draw a hook after each run that continues on the next one
+                    {
+                        let wrap_color = faint_color(0x60);
+                        let hook = (char_w / 2.0).ceil().max(2.0);
+                        editor.with_buffer(|buffer| {
+                            let mut runs = buffer.layout_runs().peekable();
+                            while let Some(run) = runs.next() {
+                                if runs.peek().is_none_or(|next| next.line_i != run.line_i) {
+                                    continue;
+                                }
+                                let x = (run.line_w + char_w / 4.0) as i32;
+                                let y = (run.line_top + metrics.line_height / 2.0) as i32;
+                                custom_renderer.rectangle(x, y, hook as u32, 1, wrap_color);
+                                custom_renderer.rectangle(
+                                    x + hook as i32 - 1,
+                                    y - hook as i32,
+                                    1,
+                                    hook as u32,
+                                    wrap_color,
+                                );
+                            }
+                        });
+                    }
+
+                    // Draw indentation guides
+                    if self.indent_guides {
+                        let guide_color = editor
+                            .theme()
+                            .settings
+                            .guide
+                            .map_or(faint_color(0x30), convert_color);
+                        let tab_width = usize::from(editor.tab_width()).max(1);
+                        editor.with_buffer(|buffer| {
+                            // Guides of the last line with text, which blank lines continue
+                            let mut guides = Vec::new();
+                            let mut guides_line = None;
+                            for run in buffer.layout_runs() {
+                                if guides_line != Some(run.line_i) {
+                                    if run.text.trim().is_empty() {
+                                        let next_level = buffer.lines[run.line_i + 1..]
+                                            .iter()
+                                            .take(GUIDE_SCAN_LINES)
+                                            .find(|line| !line.text().trim().is_empty())
+                                            .map_or(0, |line| indent_level(line.text(), tab_width));
+                                        guides.truncate(next_level);
+                                    } else {
+                                        guides = indent_guides(&run, tab_width);
+                                    }
+                                    guides_line = Some(run.line_i);
+                                }
+                                for x in guides.iter() {
+                                    custom_renderer.rectangle(
+                                        *x as i32,
+                                        run.line_top as i32,
+                                        1,
+                                        metrics.line_height as u32,
+                                        guide_color,
+                                    );
+                                }
+                            }
+                        });
+                    }
+
+                    // Draw spaces as dots and tabs as lines, with trailing whitespace stronger
+                    if self.show_whitespace {
+                        let whitespace_color = faint_color(0x40);
+                        let trailing_color = faint_color(0x90);
+                        let dot = (metrics.font_size / 8.0).ceil().max(1.0);
+                        editor.with_buffer(|buffer| {
+                            for run in buffer.layout_runs() {
+                                let trailing_start = run.text.trim_end().len();
+                                let y = run.line_top + (metrics.line_height - dot) / 2.0;
+                                for glyph in run.glyphs.iter() {
+                                    let color = if glyph.start >= trailing_start {
+                                        trailing_color
+                                    } else {
+                                        whitespace_color
+                                    };
+                                    match run.text[glyph.start..].chars().next() {
+                                        Some(' ') => custom_renderer.rectangle(
+                                            (glyph.x + (glyph.w - dot) / 2.0) as i32,
+                                            y as i32,
+                                            dot as u32,
+                                            dot as u32,
+                                            color,
+                                        ),
+                                        Some('\t') => {
+                                            let margin = (glyph.w / 8.0).max(1.0);
+                                            custom_renderer.rectangle(
+                                                (glyph.x + margin) as i32,
+                                                y as i32,
+                                                (glyph.w - 2.0 * margin).max(1.0) as u32,
+                                                1,
+                                                color,
+                                            );
+                                            // Tick at the end of the tab
+                                            custom_renderer.rectangle(
+                                                (glyph.x + glyph.w - margin) as i32 - 1,
+                                                (y - dot) as i32,
+                                                1,
+                                                (dot * 2.0 + 1.0) as u32,
+                                                color,
+                                            );
+                                        }
+                                        _ => {}
+                                    }
+                                }
+                            }
+                        });
+                    }
+
+                    // Draw additional carets and their selections
+                    if let Some(carets) = self.carets {
+                        let carets = carets.lock().unwrap();
+                        if !carets.is_empty() {
+                            let selection_color = editor
+                                .theme()
+                                .settings
+                                .selection
+                                .map_or(editor.foreground_color(), convert_color);
+                            editor.with_buffer(|buffer| {
+                                for run in buffer.layout_runs() {
+                                    for caret in carets.iter() {
+                                        if let Some((start, end)) = caret.selection_bounds() {
+                                            if let Some((x, w)) = run.highlight(start, end) {
+                                                custom_renderer.rectangle(
+                                                    x as i32,
+                                                    run.line_top as i32,
+                                                    w as u32,
+                                                    metrics.line_height as u32,
+                                                    selection_color,
+                                                );
+                                            }
+                                        }
+                                        if let Some((x, y, w, h)) = caret_rect(
+                                            &run,
+                                            caret.cursor,
+                                            cursor_shape,
+                                            cursor_thickness,
+                                            char_w,
+                                            metrics.line_height,
+                                        ) && cursor_visible
+                                        {
+                                            custom_renderer.rectangle(
+                                                x,
+                                                y,
+                                                w,
+                                                h,
+                                                caret_shape_color(caret_color, cursor_shape),
+                                            );
+                                        }
+                                    }
+                                }
+                            });
+                        }
+                    }
 
                     // Draw editor text
                     match editor.buffer_ref() {
@@ -722,6 +1943,24 @@ where
             })
         });
 
+        // Draw the part of the minimap that is visible in the editor
+        if self.minimap {
+            // neutral_6, 0.2
+            let viewport_color = cosmic_theme
+                .palette
+                .neutral_6
+                .without_alpha()
+                .with_alpha(0.2);
+            renderer.fill_quad(
+                Quad {
+                    bounds: state.minimap_viewport.get()
+                        + Vector::new(image_position.x, image_position.y),
+                    ..Default::default()
+                },
+                Color::from(viewport_color),
+            );
+        }
+
         // Draw vertical scrollbar
         {
             let scrollbar_v_rect = state.scrollbar_v_rect.get();
@@ -750,6 +1989,39 @@ where
                 Color::from(track_color),
             );
 
+            // Draw markers over the track, at most one for each kind and pixel
+            if !self.markers.is_empty() {
+                let lines = editor.with_buffer(|buffer| buffer.lines.len()).max(1);
+                let track_h = layout.bounds().height;
+                let mut last_opt = None;
+                for (line, marker) in self.markers.iter() {
+                    let y = ((*line as f32 / lines as f32) * track_h).floor();
+                    if last_opt == Some((y, *marker)) {
+                        continue;
+                    }
+                    last_opt = Some((y, *marker));
+                    let color = match marker {
+                        Marker::Change => cosmic_theme.warning.base,
+                        Marker::Error => cosmic_theme.destructive.base,
+                        Marker::Match => cosmic_theme.accent.base,
+                        Marker::Warning => cosmic_theme.warning.base,
+                    };
+                    renderer.fill_quad(
+                        Quad {
+                            bounds: Rectangle::new(
+                                Point::new(
+                                    image_position.x + scrollbar_v_rect.x,
+                                    image_position.y + y,
+                                ),
+                                Size::new(scrollbar_v_rect.width, 2.0),
+                            ),
+                            ..Default::default()
+                        },
+                        Color::from(color),
+                    );
+                }
+            }
+
             let pressed = matches!(&state.dragging, Some(Dragging::ScrollbarV { .. }));
 
             let mut hover = false;
@@ -926,7 +2198,7 @@ where
         let editor_offset_x = state.editor_offset_x.get();
         let scale_factor = state.scale_factor.get();
         let scrollbar_v_rect = state.scrollbar_v_rect.get();
-        let mut editor = self.editor.lock().unwrap();
+        let mut editor = self.lock_editor();
         let (buffer_size, buffer_scroll) =
             editor.with_buffer(|buffer| (buffer.size(), buffer.scroll()));
         let last_changed = editor.changed();
@@ -937,136 +2209,203 @@ where
         };
         let mut font_system = font_system().write().unwrap();
         let mut editor = editor.borrow_with(font_system.raw());
+        let mut carets_guard = self.carets.map(|carets| carets.lock().unwrap());
+        let mut no_carets = Vec::new();
+        let carets = carets_guard.as_deref_mut().unwrap_or(&mut no_carets);
+        let mut vim_guard = self.vim.map(|vim| vim.lock().unwrap());
 
-        // Adjust motions based on Ctrl and Shift
-        fn motion_modifiers(
-            editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
-            original_motion: Motion,
-            modifiers: Modifiers,
-        ) {
-            let motion = if modifiers.control() {
-                match original_motion {
-                    Motion::Left => Motion::LeftWord,
-                    Motion::Right => Motion::RightWord,
-                    Motion::Home => Motion::BufferStart,
-                    Motion::End => Motion::BufferEnd,
-                    _ => original_motion,
-                }
-            } else {
-                original_motion
-            };
-            let cursor = editor.cursor();
-            match editor.selection() {
-                Selection::None => {
-                    if modifiers.shift() {
-                        //TODO: Selection::Word if ctrl held?
-                        editor.set_selection(Selection::Normal(cursor));
-                    }
-                }
-                _ => {
-                    if !modifiers.shift() {
-                        editor.set_selection(Selection::None)
-                    }
-                }
-            }
-            editor.action(Action::Motion(motion));
+        if let Some(on_focus) = self.on_focus.as_ref()
+            && state.emit_focus
+        {
+            state.emit_focus = false;
+            shell.publish(on_focus.clone());
         }
 
-        // Pre-select word for CTRL+<backspace> and CTRL+<delete>
-        fn delete_modifiers(
-            editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
-            motion_to_apply: Motion,
-            modifiers: Modifiers,
-        ) {
-            if modifiers.control() && editor.selection() == Selection::None {
-                let cursor = editor.cursor();
-                editor.set_selection(Selection::Normal(cursor));
-                editor.action(Action::Motion(motion_to_apply));
+        if let Some(interval) = self.cursor_blink_opt {
+            match &event {
+                Event::Keyboard(KeyEvent::KeyPressed { .. })
+                | Event::Mouse(MouseEvent::ButtonPressed(_)) => {
+                    state.blink_start = Instant::now();
+                }
+                // Draw again when the cursor is shown or hidden next
+                Event::Window(window::Event::RedrawRequested(now)) if state.is_focused => {
+                    let interval_ms = interval.as_millis().max(1);
+                    let blinks = now.saturating_duration_since(state.blink_start).as_millis()
+                        / interval_ms
+                        + 1;
+                    let next = Duration::from_millis((blinks * interval_ms) as u64);
+                    shell.request_redraw(window::RedrawRequest::At(state.blink_start + next));
+                }
+                _ => {}
             }
         }
 
-        if let Some(on_focus) = self.on_focus.as_ref()
-            && state.emit_focus
+        if let Some(on_completion) = &self.on_completion
+            && state.is_focused
         {
-            state.emit_focus = false;
-            shell.publish(on_focus.clone());
+            match &event {
+                Event::Keyboard(KeyEvent::KeyPressed {
+                    modified_key: Key::Named(key),
+                    modifiers,
+                    ..
+                }) if modifiers.is_empty() => {
+                    let key_opt = match key {
+                        Named::ArrowUp => Some(CompletionKey::Up),
+                        Named::ArrowDown => Some(CompletionKey::Down),
+                        Named::Enter | Named::Tab => Some(CompletionKey::Accept),
+                        Named::Escape => Some(CompletionKey::Dismiss),
+                        // Other ways of moving the cursor close the completions
+                        Named::ArrowLeft
+                        | Named::ArrowRight
+                        | Named::Home
+                        | Named::End
+                        | Named::PageUp
+                        | Named::PageDown => {
+                            shell.publish(on_completion(CompletionKey::Dismiss));
+                            None
+                        }
+                        _ => None,
+                    };
+                    if let Some(key) = key_opt {
+                        shell.publish(on_completion(key));
+                        return Status::Captured;
+                    }
+                }
+                // Popups over the text box do not give it the cursor position
+                Event::Mouse(MouseEvent::ButtonPressed(_))
+                    if cursor_position.position_in(layout.bounds()).is_some() =>
+                {
+                    shell.publish(on_completion(CompletionKey::Dismiss));
+                }
+                _ => {}
+            }
         }
 
         let mut status = Status::Ignored;
+        // Set by keys that may have edited the text, which does not always change the changed flag
+        let mut edited = false;
         match event {
             Event::Keyboard(KeyEvent::KeyPressed {
                 modified_key: Key::Named(key),
                 modifiers,
                 ..
             }) if state.is_focused && !matches!(key, Named::Space) => match key {
-                Named::ArrowLeft => {
-                    motion_modifiers(&mut editor, Motion::Left, modifiers);
-                    status = Status::Captured;
-                }
-                Named::ArrowRight => {
-                    motion_modifiers(&mut editor, Motion::Right, modifiers);
-                    status = Status::Captured;
-                }
-                Named::ArrowUp => {
-                    motion_modifiers(&mut editor, Motion::Up, modifiers);
-                    status = Status::Captured;
-                }
-                Named::ArrowDown => {
-                    motion_modifiers(&mut editor, Motion::Down, modifiers);
-                    status = Status::Captured;
-                }
-                Named::Home => {
-                    motion_modifiers(&mut editor, Motion::Home, modifiers);
-                    status = Status::Captured;
-                }
-                Named::End => {
-                    motion_modifiers(&mut editor, Motion::End, modifiers);
-                    status = Status::Captured;
-                }
-                Named::PageUp => {
-                    motion_modifiers(&mut editor, Motion::PageUp, modifiers);
-                    status = Status::Captured;
-                }
-                Named::PageDown => {
-                    motion_modifiers(&mut editor, Motion::PageDown, modifiers);
+                // Alt+Left and Alt+Right are left for key bindings
+                Named::ArrowLeft | Named::ArrowRight
+                    if modifiers.alt() && !modifiers.shift() && !modifiers.control() => {}
+                // Alt+Up and Alt+Down are left for key bindings
+                Named::ArrowUp | Named::ArrowDown
+                    if modifiers.alt() && !modifiers.shift() && !modifiers.control() => {}
+                Named::ArrowUp | Named::ArrowDown
+                    if self.carets.is_some() && modifiers.alt() && modifiers.shift() =>
+                {
+                    let motion = if key == Named::ArrowUp {
+                        Motion::Up
+                    } else {
+                        Motion::Down
+                    };
+                    multi_cursor::add_adjacent(&mut editor, carets, motion);
                     status = Status::Captured;
                 }
-                Named::Escape => {
+                Named::Enter
+                    if self.on_ex_command.is_some()
+                        && matches!(editor.parser().mode, ViMode::Command { .. }) =>
+                {
+                    let value = match &editor.parser().mode {
+                        ViMode::Command { value } => value.clone(),
+                        _ => String::new(),
+                    };
+                    let selection_opt = editor
+                        .selection_bounds()
+                        .map(|(start, end)| (start.line, end.line));
+                    // Escape leaves the command line without the editor running it
                     editor.action(Action::Escape);
+                    if let Some(on_ex_command) = &self.on_ex_command {
+                        shell.publish(on_ex_command(value, selection_opt));
+                    }
                     status = Status::Captured;
                 }
-                Named::Enter => {
-                    editor.action(Action::Enter);
-                    status = Status::Captured;
-                }
-                Named::Backspace => {
-                    delete_modifiers(&mut editor, Motion::LeftWord, modifiers);
-                    editor.action(Action::Backspace);
-                    status = Status::Captured;
-                }
-                Named::Delete => {
-                    delete_modifiers(&mut editor, Motion::RightWord, modifiers);
-                    editor.action(Action::Delete);
+                Named::Tab if self.on_snippet.is_some() && modifiers.is_empty() => {
+                    if let Some(on_snippet) = &self.on_snippet {
+                        shell.publish(on_snippet.clone());
+                    }
                     status = Status::Captured;
                 }
-                Named::Tab => {
-                    if !modifiers.control() && !modifiers.alt() {
-                        if modifiers.shift() {
-                            editor.action(Action::Unindent);
-                        } else {
-                            editor.action(Action::Indent);
+                // Other keys may edit the text
+                _ if self.read_only
+                    && !matches!(
+                        key,
+                        Named::ArrowLeft
+                            | Named::ArrowRight
+                            | Named::ArrowUp
+                            | Named::ArrowDown
+                            | Named::Home
+                            | Named::End
+                            | Named::PageUp
+                            | Named::PageDown
+                    ) => {}
+                _ => {
+                    let macro_key = MacroKey::Named(key, modifiers);
+                    let key_edited_opt = match vim_guard.as_deref_mut() {
+                        Some(vim) => {
+                            vim::apply_key(&mut editor, carets, vim, macro_key, self.auto_close)
+                        }
+                        None => apply_key(&mut editor, carets, macro_key, self.auto_close),
+                    };
+                    if let Some(key_edited) = key_edited_opt {
+                        if let Some(on_key) = &self.on_key {
+                            shell.publish(on_key(macro_key));
                         }
+                        edited |= key_edited;
                         status = Status::Captured;
                     }
                 }
-                _ => (),
             },
-            Event::Keyboard(KeyEvent::KeyPressed { text, .. }) if state.is_focused => {
+            // Ctrl+V selects a visual block with Vim bindings, instead of pasting
+            Event::Keyboard(KeyEvent::KeyPressed {
+                key: Key::Character(c),
+                modifiers,
+                ..
+            }) if state.is_focused
+                && self.vim.is_some()
+                && modifiers == Modifiers::CTRL
+                && c.as_str() == "v"
+                && matches!(
+                    editor.parser().mode,
+                    ViMode::Normal | ViMode::Visual | ViMode::VisualLine
+                ) =>
+            {
+                if let Some(vim) = vim_guard.as_deref_mut() {
+                    vim::toggle_block(&mut editor, carets, vim);
+                }
+                status = Status::Captured;
+            }
+            Event::Keyboard(KeyEvent::KeyPressed { text, .. })
+                if state.is_focused && !self.read_only =>
+            {
                 let character = text.unwrap_or_default().chars().next().unwrap_or_default();
                 // Only parse keys when Super, Ctrl, and Alt are not pressed
                 if !state.modifiers.logo() && !state.modifiers.control() && !state.modifiers.alt() {
                     if !character.is_control() {
-                        editor.action(Action::Insert(character));
+                        let macro_key = MacroKey::Character(character);
+                        match vim_guard.as_deref_mut() {
+                            Some(vim) => {
+                                vim::apply_key(
+                                    &mut editor,
+                                    carets,
+                                    vim,
+                                    macro_key,
+                                    self.auto_close,
+                                );
+                            }
+                            None => {
+                                apply_key(&mut editor, carets, macro_key, self.auto_close);
+                            }
+                        }
+                        if let Some(on_key) = &self.on_key {
+                            shell.publish(on_key(macro_key));
+                        }
+                        edited = true;
                     }
                     status = Status::Captured;
                 }
@@ -1122,11 +2461,55 @@ where
                                 } else {
                                     ClickKind::Single
                                 };
+                            let mut block_start_opt = None;
                             match click_kind {
-                                ClickKind::Single => editor.action(Action::Click {
-                                    x: x as i32,
-                                    y: y as i32,
-                                }),
+                                ClickKind::Single
+                                    if self.carets.is_some() && state.modifiers.alt() =>
+                                {
+                                    if let Some(cursor) =
+                                        editor.with_buffer(|buffer| buffer.hit(x, y))
+                                    {
+                                        multi_cursor::select_block(
+                                            &mut editor,
+                                            carets,
+                                            cursor,
+                                            cursor,
+                                        );
+                                        block_start_opt = Some(cursor);
+                                    }
+                                }
+                                // Ctrl+Click goes to a definition when a language server is used
+                                ClickKind::Single
+                                    if self.on_definition.is_some()
+                                        && state.modifiers.control() =>
+                                {
+                                    carets.clear();
+                                    editor.action(Action::Click {
+                                        x: x as i32,
+                                        y: y as i32,
+                                    });
+                                    if let Some(on_definition) = &self.on_definition {
+                                        shell.publish(on_definition.clone());
+                                    }
+                                }
+                                // Ctrl+Click adds a caret instead of moving the cursor
+                                ClickKind::Single
+                                    if self.carets.is_some() && state.modifiers.control() =>
+                                {
+                                    if let Some(cursor) =
+                                        editor.with_buffer(|buffer| buffer.hit(x, y))
+                                    {
+                                        multi_cursor::add_at(&editor, carets, cursor);
+                                        editor.set_redraw(true);
+                                    }
+                                }
+                                ClickKind::Single => {
+                                    carets.clear();
+                                    editor.action(Action::Click {
+                                        x: x as i32,
+                                        y: y as i32,
+                                    })
+                                }
                                 ClickKind::Double => editor.action(Action::DoubleClick {
                                     x: x as i32,
                                     y: y as i32,
@@ -1137,7 +2520,21 @@ where
                                 }),
                             }
                             state.click = Some((click_kind, Instant::now()));
-                            state.dragging = Some(Dragging::Buffer);
+                            state.dragging = Some(match block_start_opt {
+                                Some(start) => Dragging::Block { start },
+                                None => Dragging::Buffer,
+                            });
+                        } else if self.minimap
+                            && x_logical >= scrollbar_v_rect.x - MINIMAP_WIDTH as f32
+                            && x_logical < scrollbar_v_rect.x
+                        {
+                            minimap_scroll(
+                                &mut editor,
+                                state.minimap_first_line.get(),
+                                state.minimap_line_h.get(),
+                                y,
+                            );
+                            state.dragging = Some(Dragging::Minimap);
                         } else if scrollbar_v_rect.contains(Point::new(x_logical, y_logical)) {
                             state.dragging = Some(Dragging::ScrollbarV {
                                 start_y: y,
@@ -1162,6 +2559,62 @@ where
                         }
                     }
 
+                    // Open the hunk of a git change, or close it
+                    if let Some(on_git_gutter) = &self.on_git_gutter {
+                        let x = (p.x - self.padding.left) * scale_factor;
+                        let y = (p.y - self.padding.top) * scale_factor;
+                        let mut change_opt = None;
+                        if let Some(git_changes) = self.git_changes
+                            && button == Button::Left
+                            && x >= state.git_gutter_x.get() as f32
+                            && x < editor_offset_x as f32
+                            && let Some(cursor) = editor.with_buffer(|buffer| buffer.hit(0.0, y))
+                            && git_changes.iter().any(|(line, _)| *line == cursor.line)
+                        {
+                            change_opt = Some((cursor.line, p));
+                        }
+                        shell.publish(on_git_gutter(change_opt));
+                    }
+
+                    // Right click moves the cursor when nothing is selected, so that the context
+                    // menu can offer suggestions for a misspelled word
+                    if button == Button::Right
+                        && !self.has_context_menu
+                        && editor.selection_bounds().is_none()
+                    {
+                        let x = (p.x - self.padding.left) * scale_factor - editor_offset_x as f32;
+                        let y = (p.y - self.padding.top) * scale_factor;
+                        if x >= 0.0
+                            && x < buffer_size.0.unwrap_or(0.0)
+                            && y >= 0.0
+                            && y < buffer_size.1.unwrap_or(0.0)
+                        {
+                            editor.action(Action::Click {
+                                x: (x + buffer_scroll.horizontal) as i32,
+                                y: y as i32,
+                            });
+                        }
+                    }
+
+                    if button == Button::Middle
+                        && let Some(on_middle_click) = &self.on_middle_click
+                    {
+                        let x = (p.x - self.padding.left) * scale_factor - editor_offset_x as f32;
+                        let y = (p.y - self.padding.top) * scale_factor;
+                        if x >= 0.0
+                            && x < buffer_size.0.unwrap_or(0.0)
+                            && y >= 0.0
+                            && y < buffer_size.1.unwrap_or(0.0)
+                        {
+                            carets.clear();
+                            editor.action(Action::Click {
+                                x: (x + buffer_scroll.horizontal) as i32,
+                                y: y as i32,
+                            });
+                            shell.publish(on_middle_click.clone());
+                        }
+                    }
+
                     // Update context menu state
                     if let Some(on_context_menu) = &self.on_context_menu {
                         shell.publish((on_context_menu)(if self.has_context_menu {
@@ -1180,7 +2633,17 @@ where
                 }
             }
             Event::Mouse(MouseEvent::ButtonReleased(Button::Left)) => {
-                state.dragging = None;
+                let dragging_opt = state.dragging.take();
+                if let Some(on_select) = &self.on_select
+                    && matches!(
+                        dragging_opt,
+                        Some(Dragging::Buffer | Dragging::Block { .. })
+                    )
+                    && let Some(selection) = editor.copy_selection()
+                    && !selection.is_empty()
+                {
+                    shell.publish(on_select(selection));
+                }
                 status = Status::Captured;
                 if let Some(on_auto_scroll) = &self.on_auto_scroll {
                     shell.publish(on_auto_scroll(None));
@@ -1215,6 +2678,21 @@ where
                                     shell.publish(on_auto_scroll(auto_scroll));
                                 }
                             }
+                            Dragging::Block { start } => {
+                                x += buffer_scroll.horizontal;
+                                if let Some(cursor) = editor.with_buffer(|buffer| buffer.hit(x, y))
+                                {
+                                    multi_cursor::select_block(&mut editor, carets, *start, cursor);
+                                }
+                            }
+                            Dragging::Minimap => {
+                                minimap_scroll(
+                                    &mut editor,
+                                    state.minimap_first_line.get(),
+                                    state.minimap_line_h.get(),
+                                    y,
+                                );
+                            }
                             Dragging::ScrollbarV {
                                 start_y,
                                 start_scroll,
@@ -1257,6 +2735,52 @@ where
                         }
                     }
                     status = Status::Captured;
+                } else if let Some(on_hover) = &self.on_hover {
+                    let mut hover_opt = None;
+                    if let Some(p) = cursor_position.position_in(layout.bounds()) {
+                        let x = (p.x - self.padding.left) * scale_factor - editor_offset_x as f32
+                            + buffer_scroll.horizontal;
+                        let y = (p.y - self.padding.top) * scale_factor;
+                        if x >= buffer_scroll.horizontal
+                            && y >= 0.0
+                            && let Some(cursor) = editor.with_buffer(|buffer| {
+                                buffer
+                                    .hit(x, y)
+                                    .filter(|_| y < buffer.size().1.unwrap_or(0.0))
+                            })
+                        {
+                            hover_opt = Some((cursor, p));
+                        }
+                    }
+                    let cursor_opt = hover_opt.map(|(cursor, _p)| cursor);
+                    if cursor_opt != state.hover {
+                        state.hover = cursor_opt;
+                        shell.publish(on_hover(hover_opt));
+                    }
+                }
+            }
+            Event::Mouse(MouseEvent::WheelScrolled { delta })
+                if state.modifiers.control() && self.on_zoom.is_some() =>
+            {
+                if let Some((zoom_in, zoom_out)) = &self.on_zoom
+                    && cursor_position.position_in(layout.bounds()).is_some()
+                {
+                    // Touchpads scroll by pixels, which zoom by a step for each line
+                    state.zoom_scroll += match delta {
+                        ScrollDelta::Lines { y, .. } => y,
+                        ScrollDelta::Pixels { y, .. } => {
+                            y / editor.with_buffer(|buffer| buffer.metrics()).line_height
+                        }
+                    };
+                    while state.zoom_scroll >= 1.0 {
+                        state.zoom_scroll -= 1.0;
+                        shell.publish(zoom_in.clone());
+                    }
+                    while state.zoom_scroll <= -1.0 {
+                        state.zoom_scroll += 1.0;
+                        shell.publish(zoom_out.clone());
+                    }
+                    status = Status::Captured;
                 }
             }
             Event::Mouse(MouseEvent::WheelScrolled { delta }) => {
@@ -1297,17 +2821,313 @@ where
         if let Some(on_changed) = &self.on_changed {
             //TODO: better handling of status line update
             let parser = editor.parser();
-            if editor.changed() != last_changed
+            if edited
+                || editor.changed() != last_changed
                 || (&parser.mode, &parser.cmd) != (&last_parser_mode, &last_parser_cmd)
             {
                 shell.publish(on_changed.clone());
             }
         }
 
+        // Scrolling to the cursor happens while drawing, so this is checked on every event
+        if let Some(on_scroll) = &self.on_scroll {
+            let line = editor.with_buffer(|buffer| buffer.scroll().line);
+            if state.scroll_line != Some(line) {
+                state.scroll_line = Some(line);
+                shell.publish(on_scroll(line));
+            }
+        }
+
         status
     }
 }
 
+// Logical width of the minimap
+const MINIMAP_WIDTH: i32 = 100;
+
+// Logical width of the git change markers in the gutter, and the space after them
+const GIT_GUTTER_WIDTH: i32 = 3;
+const GIT_GUTTER_GAP: i32 = 2;
+
+// Scroll so that the line at a physical height of the minimap is centered
+// Adjust motions based on Ctrl and Shift
+fn motion_modifiers(
+    editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
+    original_motion: Motion,
+    modifiers: Modifiers,
+) {
+    let motion = if modifiers.control() {
+        match original_motion {
+            Motion::Left => Motion::LeftWord,
+            Motion::Right => Motion::RightWord,
+            Motion::Home => Motion::BufferStart,
+            Motion::End => Motion::BufferEnd,
+            _ => original_motion,
+        }
+    } else {
+        original_motion
+    };
+    let cursor = editor.cursor();
+    match editor.selection() {
+        Selection::None => {
+            if modifiers.shift() {
+                //TODO: Selection::Word if ctrl held?
+                editor.set_selection(Selection::Normal(cursor));
+            }
+        }
+        _ => {
+            if !modifiers.shift() {
+                editor.set_selection(Selection::None)
+            }
+        }
+    }
+    editor.action(Action::Motion(motion));
+}
+
+// Pre-select word for CTRL+<backspace> and CTRL+<delete>
+fn delete_modifiers(
+    editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
+    motion_to_apply: Motion,
+    modifiers: Modifiers,
+) {
+    if modifiers.control() && editor.selection() == Selection::None {
+        let cursor = editor.cursor();
+        editor.set_selection(Selection::Normal(cursor));
+        editor.action(Action::Motion(motion_to_apply));
+    }
+}
+
+/// Edit the text or move the cursor like a key does, at every caret, returning whether the text
+/// may have changed or None if the key does nothing
+pub fn apply_key(
+    editor: &mut BorrowedWithFontSystem<'_, ViEditor<'static, 'static>>,
+    carets: &mut Vec<Caret>,
+    key: MacroKey,
+    auto_close: bool,
+) -> Option<bool> {
+    let (key, modifiers) = match key {
+        MacroKey::Named(key, modifiers) => (key, modifiers),
+        MacroKey::Character(character) => {
+            multi_cursor::apply(editor, carets, |editor| {
+                if !(auto_close && auto_close::insert(editor, character)) {
+                    editor.action(Action::Insert(character))
+                }
+            });
+            return Some(true);
+        }
+    };
+    let motion = match key {
+        Named::ArrowLeft => Motion::Left,
+        Named::ArrowRight => Motion::Right,
+        Named::ArrowUp => Motion::Up,
+        Named::ArrowDown => Motion::Down,
+        Named::Home => Motion::Home,
+        Named::End => Motion::End,
+        Named::PageUp => Motion::PageUp,
+        Named::PageDown => Motion::PageDown,
+        Named::Escape => {
+            if carets.is_empty() {
+                editor.action(Action::Escape);
+            } else {
+                carets.clear();
+                editor.set_redraw(true);
+            }
+            return Some(false);
+        }
+        Named::Enter => {
+            multi_cursor::apply(editor, carets, |editor| editor.action(Action::Enter));
+            return Some(true);
+        }
+        Named::Backspace => {
+            multi_cursor::apply(editor, carets, |editor| {
+                delete_modifiers(editor, Motion::LeftWord, modifiers);
+                editor.action(Action::Backspace);
+            });
+            return Some(true);
+        }
+        Named::Delete => {
+            multi_cursor::apply(editor, carets, |editor| {
+                delete_modifiers(editor, Motion::RightWord, modifiers);
+                editor.action(Action::Delete);
+            });
+            return Some(true);
+        }
+        Named::Tab if !modifiers.control() && !modifiers.alt() => {
+            let action = if modifiers.shift() {
+                Action::Unindent
+            } else {
+                Action::Indent
+            };
+            multi_cursor::apply(editor, carets, |editor| editor.action(action));
+            return Some(true);
+        }
+        _ => return None,
+    };
+    multi_cursor::apply(editor, carets, |editor| {
+        motion_modifiers(editor, motion, modifiers)
+    });
+    Some(false)
+}
+
+fn minimap_scroll(editor: &mut ViEditor<'static, 'static>, first_line: usize, line_h: i32, y: f32) {
+    let line = first_line + y.max(0.0) as usize / line_h.max(1) as usize;
+    editor.with_buffer_mut(|buffer| {
+        let rows = buffer.size().1.unwrap_or(0.0) / buffer.metrics().line_height;
+        let mut scroll = buffer.scroll();
+        scroll.line = line
+            .saturating_sub(rows as usize / 2)
+            .min(buffer.lines.len().saturating_sub(1));
+        buffer.set_scroll(scroll);
+    });
+}
+
+// Width of a character of the monospace font
+fn char_width(font_system: &mut FontSystem, metrics: Metrics) -> f32 {
+    let mut line_number_cache = LINE_NUMBER_CACHE.get().unwrap().lock().unwrap();
+    line_number_cache
+        .get(
+            font_system,
+            LineNumberKey {
+                number: 0,
+                width: 1,
+            },
+        )
+        .first()
+        .map_or(0.0, |layout_line| layout_line.w * metrics.font_size)
+}
+
+// Color of the underlines and gutter dots of diagnostics
+fn severity_color(
+    cosmic_theme: &cosmic::cosmic_theme::Theme,
+    severity: Severity,
+) -> cosmic_text::Color {
+    let color = match severity {
+        Severity::Error => cosmic_theme.destructive.base,
+        Severity::Warning => cosmic_theme.warning.base,
+        Severity::Information | Severity::Hint => cosmic_theme.accent.base,
+    }
+    .into_format::<u8, u8>();
+    cosmic_text::Color::rgba(color.red, color.green, color.blue, color.alpha)
+}
+
+// Characters of git blame annotations shown in the gutter
+const BLAME_CHARS: usize = 40;
+
+// Lay out a line of monospace text at font size 1.0, like the line number cache
+fn layout_text(font_system: &mut FontSystem, text: String) -> Vec<LayoutLine> {
+    let attrs = crate::monospace_attrs();
+    let mut buffer_line = BufferLine::new(
+        text,
+        LineEnding::default(),
+        AttrsList::new(&attrs),
+        Shaping::Advanced,
+    );
+    buffer_line.set_align(Some(Align::Left));
+    buffer_line
+        .layout(
+            font_system,
+            1.0,
+            None,
+            Wrap::None,
+            None,
+            8,
+            Default::default(),
+        )
+        .to_vec()
+}
+
+// Lines searched below a blank line for the indentation it continues
+const GUIDE_SCAN_LINES: usize = 100;
+
+// Number of indentation levels of a line, counting partial levels
+fn indent_level(text: &str, tab_width: usize) -> usize {
+    let mut column = 0;
+    for c in text.chars() {
+        match c {
+            ' ' => column += 1,
+            '\t' => column = (column / tab_width + 1) * tab_width,
+            _ => break,
+        }
+    }
+    column.div_ceil(tab_width)
+}
+
+// Horizontal positions of the indentation levels of a layout run
+fn indent_guides(run: &LayoutRun, tab_width: usize) -> Vec<f32> {
+    let mut guides = Vec::new();
+    let mut column = 0;
+    for glyph in run.glyphs.iter() {
+        let c = run.text[glyph.start..].chars().next();
+        if c != Some(' ') && c != Some('\t') {
+            break;
+        }
+        if column % tab_width == 0 {
+            guides.push(glyph.x);
+        }
+        if c == Some('\t') {
+            column = (column / tab_width + 1) * tab_width;
+        } else {
+            column += 1;
+        }
+    }
+    guides
+}
+
+// Horizontal position of a caret in a layout run
+fn caret_x(run: &LayoutRun, cursor: Cursor) -> Option<f32> {
+    if run.line_i != cursor.line {
+        return None;
+    }
+    for glyph in run.glyphs.iter() {
+        if cursor.index >= glyph.start && cursor.index < glyph.end {
+            return Some(glyph.x);
+        }
+    }
+    match run.glyphs.last() {
+        Some(glyph) if cursor.index >= glyph.end => Some(glyph.x + glyph.w),
+        Some(_) => None,
+        None => Some(0.0),
+    }
+}
+
+// Rectangle of a caret in a layout run, where bars and underlines are `thickness` pixels thick
+// and blocks and underlines are as wide as the character after the caret
+fn caret_rect(
+    run: &LayoutRun,
+    cursor: Cursor,
+    shape: CursorShape,
+    thickness: u32,
+    char_w: f32,
+    line_height: f32,
+) -> Option<(i32, i32, u32, u32)> {
+    let x = caret_x(run, cursor)? as i32;
+    let w = run
+        .glyphs
+        .iter()
+        .find(|glyph| cursor.index >= glyph.start && cursor.index < glyph.end)
+        .map_or(char_w, |glyph| glyph.w)
+        .ceil()
+        .max(1.0) as u32;
+    let y = run.line_top as i32;
+    let h = line_height as u32;
+    Some(match shape {
+        CursorShape::Bar => (x, y, thickness, h),
+        CursorShape::Block => (x, y, w, h),
+        CursorShape::Underline => {
+            let thickness = thickness.min(h);
+            (x, y + (h - thickness) as i32, w, thickness)
+        }
+    })
+}
+
+// A block is see-through so that the character under it can be read
+fn caret_shape_color(color: cosmic_text::Color, shape: CursorShape) -> cosmic_text::Color {
+    match shape {
+        CursorShape::Block => cosmic_text::Color::rgba(color.r(), color.g(), color.b(), 0x60),
+        _ => color,
+    }
+}
+
 impl<'a, Message> From<TextBox<'a, Message>> for Element<'a, Message, cosmic::Theme, Renderer>
 where
     Message: Clone + 'a,
@@ -1326,6 +3146,9 @@ enum ClickKind {
 #[derive(Debug)]
 enum Dragging {
     Buffer,
+    // Alt+drag selects a rectangle starting from this cursor
+    Block { start: Cursor },
+    Minimap,
     ScrollbarV { start_y: f32, start_scroll: Scroll },
     ScrollbarH { start_x: f32, start_scroll: Scroll },
 }
@@ -1334,13 +3157,26 @@ pub struct State {
     modifiers: Modifiers,
     click: Option<(ClickKind, Instant)>,
     dragging: Option<Dragging>,
+    // Text position under the mouse, when hovering is reported
+    hover: Option<Cursor>,
     editor_offset_x: Cell<i32>,
+    git_gutter_x: Cell<i32>,
     is_focused: bool,
     emit_focus: bool,
     scale_factor: Cell<f32>,
     scrollbar_v_rect: Cell<Rectangle<f32>>,
     scrollbar_h_rect: Cell<Option<Rectangle<f32>>>,
     handle_opt: Mutex<Option<image::Handle>>,
+    minimap_handle_opt: Mutex<Option<image::Handle>>,
+    minimap_first_line: Cell<usize>,
+    minimap_line_h: Cell<i32>,
+    minimap_viewport: Cell<Rectangle<f32>>,
+    // First line that was shown when scrolling was last reported
+    scroll_line: Option<usize>,
+    // A blinking cursor is shown again after typing or clicking
+    blink_start: Instant,
+    // Lines scrolled with Ctrl that did not zoom yet
+    zoom_scroll: f32,
 }
 
 impl State {
@@ -1350,13 +3186,22 @@ impl State {
             modifiers: Modifiers::empty(),
             click: None,
             dragging: None,
+            hover: None,
             editor_offset_x: Cell::new(0),
+            git_gutter_x: Cell::new(0),
             is_focused: false,
             emit_focus: false,
             scale_factor: Cell::new(1.0),
             scrollbar_v_rect: Cell::new(Rectangle::default()),
             scrollbar_h_rect: Cell::new(None),
             handle_opt: Mutex::new(None),
+            minimap_handle_opt: Mutex::new(None),
+            minimap_first_line: Cell::new(0),
+            minimap_line_h: Cell::new(1),
+            minimap_viewport: Cell::new(Rectangle::default()),
+            scroll_line: None,
+            blink_start: Instant::now(),
+            zoom_scroll: 0.0,
         }
     }
 }
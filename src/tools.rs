@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use std::{
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+use tokio::process::Command;
+
+use crate::{config::ExternalTool, shell};
+
+/// Values of the placeholders in the command, arguments, and folder of external tools
+pub struct ToolContext {
+    pub file_opt: Option<PathBuf>,
+    pub line: usize,
+    pub column: usize,
+    pub selection: String,
+    pub project_opt: Option<PathBuf>,
+}
+
+fn path_string(path_opt: Option<&Path>) -> String {
+    path_opt
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+impl ToolContext {
+    fn value(&self, name: &str) -> Option<String> {
+        Some(match name {
+            "FILE" => path_string(self.file_opt.as_deref()),
+            "FILE_DIR" => path_string(self.file_opt.as_deref().and_then(Path::parent)),
+            "FILE_NAME" => path_string(
+                self.file_opt
+                    .as_deref()
+                    .and_then(Path::file_name)
+                    .map(Path::new),
+            ),
+            "LINE" => self.line.to_string(),
+            "COLUMN" => self.column.to_string(),
+            "SELECTION" => self.selection.clone(),
+            "PROJECT" => path_string(self.project_opt.as_deref()),
+            _ => return None,
+        })
+    }
+
+    /// Replace `$FILE`, `$FILE_DIR`, `$FILE_NAME`, `$LINE`, `$COLUMN`, `$SELECTION`, and
+    /// `$PROJECT`, other words after `$` are kept
+    pub fn expand(&self, arg: &str) -> String {
+        let mut expanded = String::with_capacity(arg.len());
+        let mut rest = arg;
+        while let Some(i) = rest.find('$') {
+            expanded.push_str(&rest[..i]);
+            rest = &rest[i + 1..];
+            let len = rest
+                .find(|c: char| !(c.is_ascii_uppercase() || c == '_'))
+                .unwrap_or(rest.len());
+            let (name, after) = rest.split_at(len);
+            match self.value(name) {
+                Some(value) => expanded.push_str(&value),
+                None => {
+                    expanded.push('$');
+                    expanded.push_str(name);
+                }
+            }
+            rest = after;
+        }
+        expanded.push_str(rest);
+        expanded
+    }
+
+    /// Folder that a tool runs in, which is the project or else the folder of the file when the
+    /// tool does not have one
+    pub fn dir(&self, tool: &ExternalTool) -> Option<PathBuf> {
+        match &tool.dir {
+            Some(dir) => Some(PathBuf::from(self.expand(dir))),
+            None => self.project_opt.clone().or_else(|| {
+                self.file_opt
+                    .as_deref()
+                    .and_then(Path::parent)
+                    .map(Path::to_path_buf)
+            }),
+        }
+    }
+
+    /// Shell command line of a tool, with each argument quoted so that it is passed as it is
+    pub fn command_line(&self, tool: &ExternalTool) -> String {
+        let mut line = shell::quote(&self.expand(&tool.command));
+        for arg in tool.args.iter() {
+            line.push(' ');
+            line.push_str(&shell::quote(&self.expand(arg)));
+        }
+        line
+    }
+}
+
+/// Run a tool whose output is not shown until it exits, logging when it fails
+pub async fn spawn(command_line: String, dir: PathBuf) {
+    let mut command = Command::new("sh");
+    command
+        .arg("-c")
+        .arg(&command_line)
+        .current_dir(&dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped());
+    log::info!("{:?}", command);
+    match command.output().await {
+        Ok(output) if output.status.success() => {}
+        Ok(output) => log::warn!(
+            "{} exited with {}: {}",
+            command_line,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ),
+        Err(err) => log::error!("failed to run {} in {:?}: {}", command_line, dir, err),
+    }
+}
@@ -0,0 +1,513 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic_text::{Buffer, BufferLine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    fs,
+    hash::{DefaultHasher, Hasher},
+    io,
+    path::{Path, PathBuf},
+    process,
+    sync::atomic::{AtomicU64, Ordering},
+    time::{Duration, SystemTime},
+};
+
+/// States of a document that are kept, the oldest ones are removed first
+pub const MAX_STATES: usize = 100;
+
+/// Bytes of changed lines in all states of a document that are kept, at least the current one is
+/// kept
+pub const MAX_BYTES: usize = 64 * 1024 * 1024;
+
+/// Bytes of changed lines in all states of a document that are written to the cache
+pub const MAX_SAVED_BYTES: usize = 8 * 1024 * 1024;
+
+/// Files in the cache, the ones that were written longest ago are removed first
+pub const MAX_FILES: usize = 1000;
+
+/// Files in the cache that were not written for this long are removed
+pub const MAX_AGE: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+// Edits made this soon after the last one are merged into its state, so typing is one state
+const MERGE_TIME: Duration = Duration::from_secs(1);
+
+// Text of a line with its ending, as it is written to the file
+fn line_text(line: &BufferLine) -> String {
+    let mut text = String::with_capacity(line.text().len() + line.ending().as_str().len());
+    text.push_str(line.text());
+    text.push_str(line.ending().as_str());
+    text
+}
+
+fn line_eq(text: &str, line: &BufferLine) -> bool {
+    let ending = line.ending().as_str();
+    text.len() == line.text().len() + ending.len()
+        && text.starts_with(line.text())
+        && text.ends_with(ending)
+}
+
+fn text_hash(lines: &[String]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for line in lines {
+        hasher.write(line.as_bytes());
+    }
+    hasher.finish()
+}
+
+// Lines replaced by an edit, which can be applied in both directions
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+struct Diff {
+    start: usize,
+    removed: Vec<String>,
+    inserted: Vec<String>,
+}
+
+impl Diff {
+    fn apply(&self, lines: &mut Vec<String>) -> bool {
+        let end = self.start + self.removed.len();
+        if end > lines.len() {
+            return false;
+        }
+        lines.splice(self.start..end, self.inserted.iter().cloned());
+        true
+    }
+
+    fn revert(&self, lines: &mut Vec<String>) -> bool {
+        let end = self.start + self.inserted.len();
+        if end > lines.len() {
+            return false;
+        }
+        lines.splice(self.start..end, self.removed.iter().cloned());
+        true
+    }
+
+    fn reversed(&self) -> Self {
+        Self {
+            start: self.start,
+            removed: self.inserted.clone(),
+            inserted: self.removed.clone(),
+        }
+    }
+
+    fn bytes(&self) -> usize {
+        self.removed
+            .iter()
+            .chain(self.inserted.iter())
+            .map(String::len)
+            .sum()
+    }
+
+    // One diff for this one followed by `next`, where `lines` are the lines after both
+    fn then(&self, next: &Self, lines: &[String]) -> Self {
+        // Lines of the text between both diffs
+        let between = |i: usize| -> String {
+            if i < next.start {
+                lines[i].clone()
+            } else if i < next.start + next.removed.len() {
+                next.removed[i - next.start].clone()
+            } else {
+                lines[i - next.removed.len() + next.inserted.len()].clone()
+            }
+        };
+        let start = self.start.min(next.start);
+        let end = (self.start + self.inserted.len()).max(next.start + next.removed.len());
+        let mut removed: Vec<String> = (start..self.start).map(between).collect();
+        removed.extend(self.removed.iter().cloned());
+        removed.extend((self.start + self.inserted.len()..end).map(between));
+        let inserted = lines[start..end - next.removed.len() + next.inserted.len()].to_vec();
+        Self {
+            start,
+            removed,
+            inserted,
+        }
+    }
+}
+
+/// Edit of a document, which stores the lines it changed
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UndoState {
+    /// State that the edit was made in, none for the oldest states
+    pub parent_opt: Option<usize>,
+    // Lines changed from the parent state, empty for the oldest state
+    diff: Diff,
+    // States with the same text are found by this instead of comparing the text
+    #[serde(skip)]
+    hash: u64,
+    pub time: SystemTime,
+}
+
+/// States of a document as a tree, so that edits made after undoing do not lose the undone ones
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct UndoHistory {
+    states: Vec<UndoState>,
+    current: usize,
+    // Lines of the current state with their endings, the diffs lead from it to the other states
+    lines: Vec<String>,
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self::from_lines(vec![String::new()])
+    }
+}
+
+impl UndoHistory {
+    pub fn new(buffer: &Buffer) -> Self {
+        Self::from_lines(buffer.lines.iter().map(line_text).collect())
+    }
+
+    fn from_lines(lines: Vec<String>) -> Self {
+        Self {
+            states: vec![UndoState {
+                parent_opt: None,
+                diff: Diff::default(),
+                hash: text_hash(&lines),
+                time: SystemTime::now(),
+            }],
+            current: 0,
+            lines,
+        }
+    }
+
+    /// States from oldest to newest
+    pub fn states(&self) -> &[UndoState] {
+        &self.states
+    }
+
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    /// Record the text after a change, which goes back to a state if it has the same text, like
+    /// after an undo or redo
+    pub fn record(&mut self, buffer: &Buffer) {
+        // Only the lines between the ones that the text starts and ends with changed
+        let (old_len, new_len) = (self.lines.len(), buffer.lines.len());
+        let mut start = 0;
+        while start < old_len.min(new_len) && line_eq(&self.lines[start], &buffer.lines[start]) {
+            start += 1;
+        }
+        let mut end = 0;
+        while end < old_len.min(new_len) - start
+            && line_eq(
+                &self.lines[old_len - 1 - end],
+                &buffer.lines[new_len - 1 - end],
+            )
+        {
+            end += 1;
+        }
+        if start + end == old_len && start + end == new_len {
+            return;
+        }
+        let inserted: Vec<String> = buffer.lines[start..new_len - end]
+            .iter()
+            .map(line_text)
+            .collect();
+        let removed = self
+            .lines
+            .splice(start..old_len - end, inserted.iter().cloned())
+            .collect();
+        let diff = Diff {
+            start,
+            removed,
+            inserted,
+        };
+
+        let hash = text_hash(&self.lines);
+        if let Some(index) = self.states.iter().position(|state| state.hash == hash) {
+            self.current = index;
+            return;
+        }
+
+        let now = SystemTime::now();
+        // Only a state that has no edits after it can be changed
+        let is_leaf = !self
+            .states
+            .iter()
+            .any(|state| state.parent_opt == Some(self.current));
+        let current = &mut self.states[self.current];
+        if current.parent_opt.is_some()
+            && is_leaf
+            && now
+                .duration_since(current.time)
+                .is_ok_and(|elapsed| elapsed < MERGE_TIME)
+        {
+            current.diff = current.diff.then(&diff, &self.lines);
+            current.hash = hash;
+            current.time = now;
+        } else {
+            self.states.push(UndoState {
+                parent_opt: Some(self.current),
+                diff,
+                hash,
+                time: now,
+            });
+            self.current = self.states.len() - 1;
+        }
+
+        self.trim(MAX_BYTES);
+    }
+
+    // Remove the oldest states until there are not too many or too big ones
+    fn trim(&mut self, max_bytes: usize) {
+        while self.states.len() > MAX_STATES
+            || (self.states.len() > 1
+                && self
+                    .states
+                    .iter()
+                    .map(|state| state.diff.bytes())
+                    .sum::<usize>()
+                    > max_bytes)
+        {
+            self.remove_oldest();
+        }
+    }
+
+    // Change the lines of one state into those of another, through the state that both come from
+    fn walk(&self, from: usize, to: usize, lines: &mut Vec<String>) -> bool {
+        let path = |mut index: usize| {
+            let mut path = vec![index];
+            while let Some(parent) = self.states[index].parent_opt {
+                path.push(parent);
+                index = parent;
+            }
+            path
+        };
+        let (from_path, to_path) = (path(from), path(to));
+        let Some(common) = from_path.iter().find(|index| to_path.contains(*index)) else {
+            return false;
+        };
+        from_path
+            .iter()
+            .take_while(|index| *index != common)
+            .all(|index| self.states[*index].diff.revert(lines))
+            && to_path
+                .iter()
+                .take_while(|index| *index != common)
+                .collect::<Vec<_>>()
+                .into_iter()
+                .rev()
+                .all(|index| self.states[*index].diff.apply(lines))
+    }
+
+    // Lines of a state, found from the lines of the current one
+    fn lines_at(&self, index: usize) -> Vec<String> {
+        let mut lines = self.lines.clone();
+        self.walk(self.current, index, &mut lines);
+        lines
+    }
+
+    /// Go to a state, returning its text
+    pub fn jump(&mut self, index: usize) -> Option<String> {
+        if index >= self.states.len() {
+            return None;
+        }
+        let mut lines = self.lines.clone();
+        if !self.walk(self.current, index, &mut lines) {
+            log::warn!("failed to go to undo state {}", index);
+            return None;
+        }
+        self.lines = lines;
+        self.current = index;
+        Some(self.lines.concat())
+    }
+
+    // Remove the oldest state that is not the current one, the states after it are kept
+    fn remove_oldest(&mut self) {
+        let Some(index) = (0..self.states.len()).find(|index| *index != self.current) else {
+            return;
+        };
+        // The diffs of the states after it are joined with its own, so they lead to its parent.
+        // Without a parent, the first state after it becomes the oldest one instead
+        let parent_opt = self.states[index].parent_opt;
+        let mut diff = self.states[index].diff.clone();
+        // Lines are found before any diff changes, while the states still lead to each other
+        let children: Vec<(usize, Vec<String>)> = (0..self.states.len())
+            .filter(|child| self.states[*child].parent_opt == Some(index))
+            .map(|child| (child, self.lines_at(child)))
+            .collect();
+        let mut root_opt = None;
+        for (child, lines) in children {
+            let (child_parent_opt, child_diff) = match (parent_opt, root_opt) {
+                (None, None) => {
+                    root_opt = Some(child);
+                    diff = self.states[child].diff.reversed();
+                    (None, Diff::default())
+                }
+                _ => (
+                    parent_opt.or(root_opt),
+                    diff.then(&self.states[child].diff, &lines),
+                ),
+            };
+            self.states[child].parent_opt = child_parent_opt;
+            self.states[child].diff = child_diff;
+        }
+
+        self.states.remove(index);
+        for state in self.states.iter_mut() {
+            state.parent_opt = match state.parent_opt {
+                Some(parent) if parent > index => Some(parent - 1),
+                parent_opt => parent_opt,
+            };
+        }
+        if self.current > index {
+            self.current -= 1;
+        }
+    }
+
+    /// Column of each state in a timeline, where the first edit after a state continues its
+    /// column and other edits after it start new ones
+    pub fn columns(&self) -> Vec<usize> {
+        let mut columns: Vec<usize> = Vec::with_capacity(self.states.len());
+        let mut continued = vec![false; self.states.len()];
+        let mut next_column = 0;
+        for state in self.states.iter() {
+            let column = match state.parent_opt {
+                Some(parent) if !continued[parent] => {
+                    continued[parent] = true;
+                    columns[parent]
+                }
+                _ => {
+                    next_column += 1;
+                    next_column - 1
+                }
+            };
+            columns.push(column);
+        }
+        columns
+    }
+}
+
+// The history of a file in the cache, which is stored with its path in case file names collide
+#[derive(Deserialize, Serialize)]
+struct SavedHistory {
+    path: PathBuf,
+    history: UndoHistory,
+}
+
+fn cache_dir() -> Option<PathBuf> {
+    dirs::cache_dir().map(|dir| dir.join("cosmic-edit").join("undo"))
+}
+
+// File in the cache for the history of a file, named after a digest of its path that stays the
+// same between versions
+fn cache_path(path: &Path) -> Option<PathBuf> {
+    let digest = Sha256::digest(path.as_os_str().as_encoded_bytes());
+    cache_dir().map(|dir| dir.join(format!("{:x}.json", digest)))
+}
+
+/// Write the history of a file to the cache, without the oldest states if it is too big. This
+/// serializes the whole history, so it runs as a task
+pub fn save(path: &Path, mut history: UndoHistory) -> io::Result<()> {
+    let Some(cache_path) = cache_path(path) else {
+        return Ok(());
+    };
+    history.trim(MAX_SAVED_BYTES);
+    let data = serde_json::to_vec(&SavedHistory {
+        path: path.to_path_buf(),
+        history,
+    })?;
+    if let Some(dir) = cache_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Each save gets its own temporary file, as saves of the same file can run at once
+    static SAVES: AtomicU64 = AtomicU64::new(0);
+    let temp_path = cache_path.with_extension(format!(
+        "{}.{}.tmp",
+        process::id(),
+        SAVES.fetch_add(1, Ordering::Relaxed)
+    ));
+    let res = fs::write(&temp_path, data).and_then(|()| fs::rename(&temp_path, &cache_path));
+    if res.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    res
+}
+
+/// Remove the files in the cache that are too old, and the oldest ones if there are too many
+pub fn prune() {
+    let Some(dir) = cache_dir() else {
+        return;
+    };
+    let entries = match fs::read_dir(&dir) {
+        Ok(ok) => ok,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::warn!("failed to read undo history folder {:?}: {}", dir, err);
+            }
+            return;
+        }
+    };
+    // Files being saved at the same time can disappear while they are listed
+    let mut files: Vec<(SystemTime, PathBuf)> = entries
+        .filter_map(|entry_res| {
+            let entry = entry_res.ok()?;
+            let modified = entry
+                .metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()?;
+            Some((modified, entry.path()))
+        })
+        .collect();
+    // Newest first, so the files past the limit are the oldest ones
+    files.sort_by(|a, b| b.0.cmp(&a.0));
+    let now = SystemTime::now();
+    for (i, (modified, file)) in files.into_iter().enumerate() {
+        let too_old = now
+            .duration_since(modified)
+            .is_ok_and(|elapsed| elapsed > MAX_AGE);
+        if (i >= MAX_FILES || too_old)
+            && let Err(err) = fs::remove_file(&file)
+        {
+            log::warn!("failed to remove undo history {:?}: {}", file, err);
+        }
+    }
+}
+
+/// History of a file from the cache, where the text that was loaded is the current state
+pub fn load(path: &Path, buffer: &Buffer) -> Option<UndoHistory> {
+    let cache_path = cache_path(path)?;
+    let data = match fs::read(&cache_path) {
+        Ok(data) => data,
+        Err(err) => {
+            if err.kind() != io::ErrorKind::NotFound {
+                log::warn!("failed to read undo history {:?}: {}", cache_path, err);
+            }
+            return None;
+        }
+    };
+    let saved: SavedHistory = match serde_json::from_slice(&data) {
+        Ok(saved) => saved,
+        Err(err) => {
+            log::warn!("failed to parse undo history {:?}: {}", cache_path, err);
+            return None;
+        }
+    };
+    let mut history = saved.history;
+    // States come after their parents
+    if saved.path != path
+        || history.current >= history.states.len()
+        || history
+            .states
+            .iter()
+            .enumerate()
+            .any(|(index, state)| state.parent_opt.is_some_and(|parent| parent >= index))
+    {
+        return None;
+    }
+    // Hashes are not saved, so each state is visited to find them
+    let mut lines = history.lines.clone();
+    let mut from = history.current;
+    for index in 0..history.states.len() {
+        if !history.walk(from, index, &mut lines) {
+            log::warn!("undo history {:?} does not match its text", cache_path);
+            return None;
+        }
+        history.states[index].hash = text_hash(&lines);
+        from = index;
+    }
+    // A file changed by something else gets a new state after the one it was saved in
+    history.record(buffer);
+    Some(history)
+}
@@ -0,0 +1,893 @@
+// SPDX-License-Identifier: GPL-3.0-only
+
+use cosmic::iced_core::keyboard::key::Named;
+use cosmic_text::{
+    Action, BorrowedWithFontSystem, Buffer, Cursor, Edit, Selection, ViEditor, ViMode,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+    sync::Mutex,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    ex,
+    multi_cursor::{self, Caret},
+    text_box::{self, MacroKey},
+};
+
+type Editor<'a> = BorrowedWithFontSystem<'a, ViEditor<'static, 'static>>;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Kind {
+    Chars,
+    Lines,
+    Block,
+}
+
+#[derive(Clone, Debug)]
+struct Register {
+    kind: Kind,
+    text: String,
+}
+
+// Registers are shared by all documents, like in Vim
+static REGISTERS: Mutex<BTreeMap<char, Register>> = Mutex::new(BTreeMap::new());
+
+// The register that is used when none is given
+const UNNAMED: char = '"';
+
+fn is_register(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '"' | '-' | '_')
+}
+
+// Save text in a register, where an uppercase name adds to the lowercase one
+fn store(name: char, kind: Kind, text: String) {
+    if name == '_' {
+        return;
+    }
+    let mut registers = REGISTERS.lock().unwrap();
+    if name.is_ascii_uppercase() {
+        let register = registers
+            .entry(name.to_ascii_lowercase())
+            .or_insert(Register {
+                kind,
+                text: String::new(),
+            });
+        if kind == Kind::Lines && !register.text.is_empty() {
+            register.text.push('\n');
+        }
+        register.text.push_str(&text);
+        if kind == Kind::Lines {
+            register.kind = kind;
+        }
+    } else {
+        registers.insert(name, Register { kind, text });
+    }
+}
+
+fn load(name: char) -> Option<Register> {
+    REGISTERS
+        .lock()
+        .unwrap()
+        .get(&name.to_ascii_lowercase())
+        .cloned()
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+enum Pending {
+    #[default]
+    None,
+    Register,
+    Mark,
+    // Jump to a mark, at its exact position or at the start of its line
+    Jump {
+        exact: bool,
+    },
+    Operator {
+        op: char,
+        count: usize,
+    },
+    // Motion typed after an operator, from where the cursor was and with its first key
+    Motion {
+        op: char,
+        start: Cursor,
+        first: char,
+    },
+    TextObject {
+        op: char,
+        around: bool,
+    },
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+struct Block {
+    anchor: Cursor,
+    cursor: Cursor,
+}
+
+/// Vim commands that the editor does not have: the visual block, named registers, marks, and
+/// repeating the last change
+#[derive(Debug, Default)]
+pub struct Vim {
+    block_opt: Option<Block>,
+    // Carets of a visual block are kept while inserting, until Escape
+    block_insert: bool,
+    marks: HashMap<char, Cursor>,
+    pending: Pending,
+    register_opt: Option<char>,
+    count: usize,
+    // Set when the unnamed register holds a visual block, which the editor cannot paste
+    unnamed_block: bool,
+    // Keys of the command being typed, and those of the last change that `.` repeats
+    keys: Vec<MacroKey>,
+    last_change: Vec<MacroKey>,
+}
+
+impl Vim {
+    /// Text shown in the status line instead of the mode of the editor
+    pub fn status(&self) -> Option<String> {
+        let register = self
+            .register_opt
+            .map(|name| format!("\"{name}"))
+            .unwrap_or_default();
+        let count = if self.count > 0 {
+            self.count.to_string()
+        } else {
+            String::new()
+        };
+        let pending = match self.pending {
+            Pending::None if register.is_empty() => None,
+            Pending::None => Some(format!("{register}{count}")),
+            Pending::Register => Some("\"".to_string()),
+            Pending::Mark => Some("m".to_string()),
+            Pending::Jump { exact: true } => Some("`".to_string()),
+            Pending::Jump { exact: false } => Some("'".to_string()),
+            Pending::Operator { op, .. } | Pending::Motion { op, .. } => {
+                Some(format!("{register}{count}{op}"))
+            }
+            Pending::TextObject { op, around } => Some(format!(
+                "{register}{count}{op}{}",
+                if around { 'a' } else { 'i' }
+            )),
+        };
+        match (self.block_opt, pending) {
+            (Some(_), Some(pending)) => Some(format!("-- VISUAL BLOCK -- {pending}")),
+            (Some(_), None) => Some("-- VISUAL BLOCK --".to_string()),
+            (None, pending) => pending,
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pending = Pending::None;
+        self.register_opt = None;
+        self.count = 0;
+    }
+}
+
+// Whether the editor is waiting for the next key of a command, like after `f`
+fn cmd_pending(editor: &ViEditor<'static, 'static>) -> bool {
+    !format!("{}", editor.parser().cmd).is_empty()
+}
+
+fn line_len(buffer: &Buffer, line: usize) -> usize {
+    buffer.lines.get(line).map_or(0, |line| line.text().len())
+}
+
+// A position moved into the text, for positions kept while the text changed
+fn clamp(buffer: &Buffer, cursor: Cursor) -> Cursor {
+    let line = cursor.line.min(buffer.lines.len().saturating_sub(1));
+    Cursor::new(line, cursor.index.min(line_len(buffer, line)))
+}
+
+// Index after the character at a position, which is the end for an empty line
+fn next_index(buffer: &Buffer, cursor: Cursor) -> usize {
+    let text = buffer.lines[cursor.line].text();
+    text[cursor.index.min(text.len())..]
+        .chars()
+        .next()
+        .map_or(text.len(), |c| cursor.index + c.len_utf8())
+}
+
+fn text_between(buffer: &Buffer, start: Cursor, end: Cursor) -> String {
+    let mut text = String::new();
+    for line_i in start.line..=end.line.min(buffer.lines.len().saturating_sub(1)) {
+        let line = buffer.lines[line_i].text();
+        let from = if line_i == start.line { start.index } else { 0 };
+        let to = if line_i == end.line {
+            end.index
+        } else {
+            line.len()
+        };
+        if line_i > start.line {
+            text.push('\n');
+        }
+        text.push_str(&line[from.min(line.len())..to.min(line.len()).max(from.min(line.len()))]);
+    }
+    text
+}
+
+fn enter_insert(editor: &mut Editor) {
+    editor.action(Action::Insert('i'));
+}
+
+/// Start or end a visual block, as Ctrl+V does
+pub fn toggle_block(editor: &mut Editor, carets: &mut Vec<Caret>, vim: &mut Vim) {
+    if vim.block_opt.take().is_some() {
+        carets.clear();
+        editor.set_selection(Selection::None);
+        editor.set_redraw(true);
+        return;
+    }
+    // A visual selection becomes a block with the same corners
+    let anchor = match editor.selection() {
+        Selection::Normal(anchor) | Selection::Line(anchor) | Selection::Word(anchor) => anchor,
+        Selection::None => editor.cursor(),
+    };
+    let cursor = editor.cursor();
+    if matches!(editor.parser().mode, ViMode::Visual | ViMode::VisualLine) {
+        editor.action(Action::Escape);
+    }
+    vim.reset();
+    vim.block_opt = Some(Block { anchor, cursor });
+    select_block(editor, carets, vim);
+}
+
+// Select the block with carets, which includes the columns of both its corners like in Vim
+fn select_block(editor: &mut Editor, carets: &mut Vec<Caret>, vim: &Vim) {
+    let Some(Block {
+        mut anchor,
+        mut cursor,
+    }) = vim.block_opt
+    else {
+        return;
+    };
+    let tab_width = usize::from(editor.tab_width()).max(1);
+    editor.with_buffer(|buffer| {
+        let column = |cursor: Cursor| {
+            multi_cursor::column(buffer.lines[cursor.line].text(), cursor.index, tab_width)
+        };
+        if column(cursor) >= column(anchor) {
+            cursor.index = next_index(buffer, cursor);
+        } else {
+            anchor.index = next_index(buffer, anchor);
+        }
+    });
+    multi_cursor::select_block(editor, carets, anchor, cursor);
+}
+
+// Cursor and carets of the block, with their selections
+fn block_carets(editor: &ViEditor<'static, 'static>, carets: &[Caret]) -> Vec<Caret> {
+    carets
+        .iter()
+        .copied()
+        .chain(std::iter::once(Caret {
+            cursor: editor.cursor(),
+            selection: editor.selection(),
+        }))
+        .collect()
+}
+
+// Commands typed while a visual block is selected
+fn block_key(editor: &mut Editor, carets: &mut Vec<Caret>, vim: &mut Vim, key: MacroKey) {
+    let Some(mut block) = vim.block_opt else {
+        return;
+    };
+    let character = match key {
+        MacroKey::Character(character) if !cmd_pending(editor) => character,
+        MacroKey::Named(Named::Escape, _) => {
+            toggle_block(editor, carets, vim);
+            return;
+        }
+        _ => '\0',
+    };
+    match character {
+        '"' => {
+            vim.pending = Pending::Register;
+            return;
+        }
+        'o' => {
+            vim.block_opt = Some(Block {
+                anchor: block.cursor,
+                cursor: block.anchor,
+            });
+            select_block(editor, carets, vim);
+            return;
+        }
+        'y' | 'd' | 'x' | 'X' | 'c' | 's' => {
+            let text = multi_cursor::copy_block(editor, carets);
+            let name = vim.register_opt.unwrap_or(UNNAMED);
+            store(name, Kind::Block, text);
+            vim.unnamed_block = name == UNNAMED;
+            vim.reset();
+            vim.block_opt = None;
+            if character == 'y' {
+                carets.clear();
+                let start = editor.with_buffer(|buffer| {
+                    let line = block.anchor.line.min(block.cursor.line);
+                    Cursor::new(
+                        line,
+                        block
+                            .anchor
+                            .index
+                            .min(block.cursor.index)
+                            .min(line_len(buffer, line)),
+                    )
+                });
+                editor.set_selection(Selection::None);
+                editor.set_cursor(start);
+            } else {
+                multi_cursor::apply(editor, carets, |editor| {
+                    editor.delete_selection();
+                });
+                if matches!(character, 'c' | 's') {
+                    vim.block_insert = true;
+                    enter_insert(editor);
+                } else {
+                    carets.clear();
+                }
+            }
+            editor.set_redraw(true);
+            return;
+        }
+        'I' | 'A' => {
+            let mut all = block_carets(editor, carets);
+            for caret in all.iter_mut() {
+                if let Some((start, end)) = caret.selection_bounds() {
+                    caret.cursor = if character == 'I' { start } else { end };
+                }
+                caret.selection = Selection::None;
+            }
+            if let Some(main) = all.pop() {
+                editor.set_cursor(main.cursor);
+                editor.set_selection(main.selection);
+            }
+            *carets = all;
+            vim.reset();
+            vim.block_opt = None;
+            vim.block_insert = true;
+            enter_insert(editor);
+            return;
+        }
+        _ => {}
+    }
+
+    // Other keys move the corner of the block like they move the cursor
+    carets.clear();
+    editor.set_selection(Selection::None);
+    editor.set_cursor(block.cursor);
+    text_box::apply_key(editor, carets, key, false);
+    if !matches!(editor.parser().mode, ViMode::Normal | ViMode::Extra(_)) {
+        // Keys that start inserting or another visual mode end the block
+        vim.block_opt = None;
+        return;
+    }
+    if matches!(editor.parser().mode, ViMode::Normal) && !cmd_pending(editor) {
+        block.cursor = editor.cursor();
+        vim.block_opt = Some(block);
+        select_block(editor, carets, vim);
+    }
+}
+
+// Insert the text of a register after or before the cursor
+fn put(editor: &mut Editor, name: char, after: bool, count: usize) {
+    let Some(register) = load(name) else {
+        return;
+    };
+    let count = count.max(1);
+    let cursor = editor.cursor();
+    editor.start_change();
+    match register.kind {
+        Kind::Chars => {
+            let text = register.text.repeat(count);
+            let index = if after {
+                editor.with_buffer(|buffer| next_index(buffer, cursor))
+            } else {
+                cursor.index
+            };
+            let end = editor.insert_at(Cursor::new(cursor.line, index), &text, None);
+            // The cursor is left on the last character that was put
+            let last = editor.with_buffer(|buffer| {
+                let line = buffer.lines[end.line].text();
+                line[..end.index]
+                    .chars()
+                    .next_back()
+                    .map_or(end, |c| Cursor::new(end.line, end.index - c.len_utf8()))
+            });
+            editor.set_cursor(last);
+        }
+        Kind::Lines => {
+            let text = vec![register.text.as_str(); count].join("\n");
+            let line = if after {
+                let end = editor.with_buffer(|buffer| line_len(buffer, cursor.line));
+                editor.insert_at(Cursor::new(cursor.line, end), &format!("\n{text}"), None);
+                cursor.line + 1
+            } else {
+                editor.insert_at(Cursor::new(cursor.line, 0), &format!("{text}\n"), None);
+                cursor.line
+            };
+            ex::go_to_line(editor, line);
+        }
+        Kind::Block => {
+            if after {
+                let index = editor.with_buffer(|buffer| next_index(buffer, cursor));
+                editor.set_cursor(Cursor::new(cursor.line, index));
+            }
+            // Copies are put side by side, with the lines padded to the width of the block
+            let width = register
+                .text
+                .split('\n')
+                .map(|line| line.chars().count())
+                .max()
+                .unwrap_or(0);
+            let text = register
+                .text
+                .split('\n')
+                .map(|line| format!("{}{line}", format!("{line:width$}").repeat(count - 1)))
+                .collect::<Vec<_>>()
+                .join("\n");
+            multi_cursor::paste_block(editor, &text);
+            editor.set_cursor(cursor);
+        }
+    }
+    editor.finish_change();
+    editor.set_redraw(true);
+}
+
+// Yank, delete, or change the text between two positions
+fn operate(editor: &mut Editor, vim: &mut Vim, op: char, start: Cursor, end: Cursor, kind: Kind) {
+    let (start, end) = editor.with_buffer(|buffer| (clamp(buffer, start), clamp(buffer, end)));
+    let (start, end) = if (start.line, start.index) <= (end.line, end.index) {
+        (start, end)
+    } else {
+        (end, start)
+    };
+    let name = vim.register_opt.unwrap_or(UNNAMED);
+    vim.reset();
+    if kind == Kind::Lines {
+        let text = editor.with_buffer(|buffer| {
+            (start.line..=end.line)
+                .map(|line_i| buffer.lines[line_i].text())
+                .collect::<Vec<_>>()
+                .join("\n")
+        });
+        store(name, kind, text);
+        match op {
+            'd' => ex::delete(
+                editor,
+                ex::Range {
+                    start: start.line,
+                    end: end.line,
+                },
+            ),
+            'c' => {
+                let (from, to) = editor.with_buffer(|buffer| {
+                    let text = buffer.lines[start.line].text();
+                    let indent = text.len() - text.trim_start().len();
+                    (
+                        Cursor::new(start.line, indent),
+                        Cursor::new(end.line, line_len(buffer, end.line)),
+                    )
+                });
+                editor.start_change();
+                editor.delete_range(from, to);
+                editor.finish_change();
+                editor.set_cursor(from);
+                enter_insert(editor);
+            }
+            _ => editor.set_cursor(start),
+        }
+    } else {
+        let text = editor.with_buffer(|buffer| text_between(buffer, start, end));
+        store(name, kind, text);
+        if op == 'y' {
+            editor.set_cursor(start);
+        } else {
+            editor.start_change();
+            editor.delete_range(start, end);
+            editor.finish_change();
+            editor.set_cursor(start);
+            if op == 'c' {
+                enter_insert(editor);
+            }
+        }
+    }
+    editor.set_selection(Selection::None);
+    editor.set_redraw(true);
+}
+
+// Word at the cursor, with the whitespace after it for `aw`
+fn word_object(buffer: &Buffer, cursor: Cursor, around: bool, big: bool) -> (Cursor, Cursor) {
+    let text = buffer.lines[cursor.line].text();
+    let mut segments: Vec<(usize, &str)> = text.split_word_bound_indices().collect();
+    if big {
+        // WORDs are only separated by whitespace
+        let mut merged: Vec<(usize, &str)> = Vec::new();
+        for (index, segment) in segments {
+            let blank = segment.trim().is_empty();
+            match merged.last_mut() {
+                Some((start, last)) if !blank && !last.trim().is_empty() => {
+                    *last = &text[*start..index + segment.len()];
+                }
+                _ => merged.push((index, segment)),
+            }
+        }
+        segments = merged;
+    }
+    let i = segments
+        .iter()
+        .position(|(index, segment)| cursor.index < index + segment.len())
+        .unwrap_or(segments.len().saturating_sub(1));
+    let Some((index, segment)) = segments.get(i) else {
+        return (cursor, cursor);
+    };
+    let mut end = index + segment.len();
+    if around
+        && let Some((next, segment)) = segments.get(i + 1)
+        && segment.trim().is_empty()
+    {
+        end = next + segment.len();
+    }
+    (
+        Cursor::new(cursor.line, *index),
+        Cursor::new(cursor.line, end),
+    )
+}
+
+// Finish an operator once its motion was typed
+fn finish_motion(editor: &mut Editor, vim: &mut Vim, op: char, start: Cursor, first: char) {
+    let mut end = editor.cursor();
+    let kind = if matches!(first, 'j' | 'k' | 'G' | 'g') {
+        Kind::Lines
+    } else {
+        Kind::Chars
+    };
+    // Motions that include the character they end on
+    if matches!(first, 'e' | 'E' | 'f' | 't' | '$' | '%')
+        && (end.line, end.index) >= (start.line, start.index)
+    {
+        end.index = editor.with_buffer(|buffer| next_index(buffer, end));
+    }
+    operate(editor, vim, op, start, end, kind);
+}
+
+// Keys after `"` with a register, which are only the commands that use it
+fn register_key(editor: &mut Editor, vim: &mut Vim, key: MacroKey) -> bool {
+    let MacroKey::Character(character) = key else {
+        if let MacroKey::Named(Named::Escape, _) = key {
+            vim.reset();
+            return true;
+        }
+        // Keys like the arrows are motions for an operator
+        if let Pending::Operator { op, count } = vim.pending {
+            let first = match key {
+                MacroKey::Named(Named::ArrowUp | Named::ArrowDown, _) => 'j',
+                _ => '\0',
+            };
+            start_motion(editor, vim, op, count, key, first);
+            return true;
+        }
+        if let Pending::Motion { op, start, first } = vim.pending {
+            continue_motion(editor, vim, op, start, first, key);
+            return true;
+        }
+        vim.reset();
+        return false;
+    };
+    let cursor = editor.cursor();
+    match vim.pending {
+        Pending::None => match character {
+            '1'..='9' => vim.count = vim.count * 10 + character.to_digit(10).unwrap() as usize,
+            '0' if vim.count > 0 => vim.count *= 10,
+            'p' | 'P' => {
+                let name = vim.register_opt.unwrap_or(UNNAMED);
+                let count = vim.count;
+                vim.reset();
+                put(editor, name, character == 'p', count);
+            }
+            'y' | 'd' | 'c' => {
+                vim.pending = Pending::Operator {
+                    op: character,
+                    count: vim.count.max(1),
+                }
+            }
+            // Shortcuts for an operator with a motion
+            'x' => {
+                let count = vim.count.max(1);
+                let end = editor.with_buffer(|buffer| {
+                    let mut end = cursor;
+                    for _ in 0..count {
+                        end.index = next_index(buffer, end);
+                    }
+                    end
+                });
+                operate(editor, vim, 'd', cursor, end, Kind::Chars);
+            }
+            'D' | 'C' => {
+                let end = editor
+                    .with_buffer(|buffer| Cursor::new(cursor.line, line_len(buffer, cursor.line)));
+                let op = character.to_ascii_lowercase();
+                operate(editor, vim, op, cursor, end, Kind::Chars);
+            }
+            'Y' => {
+                let count = vim.count.max(1);
+                let end = Cursor::new(cursor.line + count - 1, 0);
+                operate(editor, vim, 'y', cursor, end, Kind::Lines);
+            }
+            _ => {
+                vim.reset();
+                return false;
+            }
+        },
+        Pending::Operator { op, count } => match character {
+            _ if character == op => {
+                let end = Cursor::new(cursor.line + count - 1, 0);
+                operate(editor, vim, op, cursor, end, Kind::Lines);
+            }
+            'i' | 'a' => {
+                vim.pending = Pending::TextObject {
+                    op,
+                    around: character == 'a',
+                }
+            }
+            _ => start_motion(editor, vim, op, count, key, character),
+        },
+        Pending::Motion { op, start, first } => continue_motion(editor, vim, op, start, first, key),
+        Pending::TextObject { op, around } => match character {
+            'w' | 'W' => {
+                let (start, end) = editor
+                    .with_buffer(|buffer| word_object(buffer, cursor, around, character == 'W'));
+                operate(editor, vim, op, start, end, Kind::Chars);
+            }
+            _ => vim.reset(),
+        },
+        Pending::Register | Pending::Mark | Pending::Jump { .. } => vim.reset(),
+    }
+    true
+}
+
+fn start_motion(
+    editor: &mut Editor,
+    vim: &mut Vim,
+    op: char,
+    count: usize,
+    key: MacroKey,
+    first: char,
+) {
+    let start = editor.cursor();
+    vim.pending = Pending::Motion { op, start, first };
+    if count > 1 {
+        for digit in count.to_string().chars() {
+            editor.action(Action::Insert(digit));
+        }
+    }
+    continue_motion(editor, vim, op, start, first, key);
+}
+
+fn continue_motion(
+    editor: &mut Editor,
+    vim: &mut Vim,
+    op: char,
+    start: Cursor,
+    first: char,
+    key: MacroKey,
+) {
+    let mut no_carets = Vec::new();
+    text_box::apply_key(editor, &mut no_carets, key, false);
+    match editor.parser().mode {
+        ViMode::Normal if !cmd_pending(editor) => finish_motion(editor, vim, op, start, first),
+        ViMode::Normal | ViMode::Extra(_) => {}
+        // The key was not a motion
+        _ => vim.reset(),
+    }
+}
+
+// Keys after `m`, `'`, and `` ` ``
+fn mark_key(editor: &mut Editor, vim: &mut Vim, character: char) {
+    let cursor = editor.cursor();
+    match vim.pending {
+        Pending::Mark if character.is_ascii_lowercase() => {
+            vim.marks.insert(character, cursor);
+        }
+        Pending::Jump { exact } => {
+            // Both `` ` `` and `'` jump back to where the last jump was from
+            let name = if character == '`' { '\'' } else { character };
+            if let Some(mark) = vim.marks.get(&name).copied() {
+                vim.marks.insert('\'', cursor);
+                let mark = editor.with_buffer(|buffer| clamp(buffer, mark));
+                if exact {
+                    editor.set_selection(Selection::None);
+                    editor.set_cursor(mark);
+                    editor.set_redraw(true);
+                } else {
+                    ex::go_to_line(editor, mark.line);
+                }
+            }
+        }
+        _ => {}
+    }
+    vim.pending = Pending::None;
+}
+
+// Whether a command changes the text, so that `.` repeats it
+fn is_change(keys: &[MacroKey]) -> bool {
+    keys.iter()
+        .find_map(|key| match key {
+            MacroKey::Character(c) if c.is_ascii_digit() => None,
+            MacroKey::Character(c) => Some(*c),
+            MacroKey::Named(..) => Some('\0'),
+        })
+        .is_some_and(|c| "xXdDcCsSrRiIaAoOpPJ~<>".contains(c))
+}
+
+// The unnamed register of the editor is replaced by commands like these
+fn yanks(keys: &[MacroKey]) -> bool {
+    matches!(
+        keys.iter()
+            .find(|key| !matches!(key, MacroKey::Character(c) if c.is_ascii_digit())),
+        Some(MacroKey::Character(
+            'y' | 'd' | 'c' | 'x' | 'X' | 's' | 'S' | 'D' | 'C' | 'Y'
+        ))
+    )
+}
+
+/// Run a key with the Vim commands that the editor does not have, returning like
+/// [`text_box::apply_key`] does
+pub fn apply_key(
+    editor: &mut Editor,
+    carets: &mut Vec<Caret>,
+    vim: &mut Vim,
+    key: MacroKey,
+    auto_close: bool,
+) -> Option<bool> {
+    match editor.parser().mode {
+        ViMode::Insert | ViMode::Replace => {
+            let escape = matches!(key, MacroKey::Named(Named::Escape, _));
+            if escape && vim.block_insert {
+                // Leave insert mode at once, instead of first removing the carets
+                vim.block_insert = false;
+                carets.clear();
+            }
+            if !vim.keys.is_empty() {
+                vim.keys.push(key);
+            }
+            let edited = text_box::apply_key(editor, carets, key, auto_close);
+            if escape && !vim.keys.is_empty() {
+                vim.last_change = mem::take(&mut vim.keys);
+            }
+            return edited;
+        }
+        ViMode::Normal => {}
+        ViMode::Extra(_) => return record(editor, carets, vim, key, auto_close),
+        ViMode::Visual | ViMode::VisualLine => {
+            if let MacroKey::Character(character) = key
+                && !cmd_pending(editor)
+            {
+                if vim.pending == Pending::Register {
+                    vim.pending = Pending::None;
+                    vim.register_opt = Some(character).filter(|c| is_register(*c));
+                    return Some(false);
+                }
+                if character == '"' {
+                    vim.pending = Pending::Register;
+                    return Some(false);
+                }
+                if matches!(character, 'y' | 'd' | 'x' | 'c' | 's') {
+                    let linewise = matches!(editor.parser().mode, ViMode::VisualLine);
+                    if let Some(name) = vim.register_opt.take() {
+                        let text_opt = editor.copy_selection();
+                        if let Some(mut text) = text_opt {
+                            if linewise && text.ends_with('\n') {
+                                text.pop();
+                            }
+                            let kind = if linewise { Kind::Lines } else { Kind::Chars };
+                            store(name, kind, text);
+                        }
+                    } else {
+                        vim.unnamed_block = false;
+                    }
+                }
+            }
+            return text_box::apply_key(editor, carets, key, auto_close);
+        }
+        _ => {
+            vim.reset();
+            return text_box::apply_key(editor, carets, key, auto_close);
+        }
+    }
+
+    if vim.pending == Pending::Register {
+        vim.pending = Pending::None;
+        if let MacroKey::Character(character) = key
+            && is_register(character)
+        {
+            vim.register_opt = Some(character);
+        }
+        return Some(false);
+    }
+    if vim.block_opt.is_some() {
+        block_key(editor, carets, vim, key);
+        return Some(true);
+    }
+    if vim.register_opt.is_some() || vim.pending != Pending::None {
+        if let Pending::Mark | Pending::Jump { .. } = vim.pending {
+            if let MacroKey::Character(character) = key {
+                mark_key(editor, vim, character);
+            } else {
+                vim.reset();
+            }
+            return Some(false);
+        }
+        if register_key(editor, vim, key) {
+            return Some(true);
+        }
+    }
+
+    // Commands of the editor are not repeated at carets, which are only used for blocks
+    if !carets.is_empty() {
+        carets.clear();
+        editor.set_redraw(true);
+    }
+    let idle = !cmd_pending(editor) && vim.keys.is_empty();
+    if let MacroKey::Character(character) = key
+        && idle
+    {
+        match character {
+            '"' => {
+                vim.pending = Pending::Register;
+                return Some(false);
+            }
+            'm' => {
+                vim.pending = Pending::Mark;
+                return Some(false);
+            }
+            '\'' | '`' => {
+                vim.pending = Pending::Jump {
+                    exact: character == '`',
+                };
+                return Some(false);
+            }
+            '.' => {
+                let keys = vim.last_change.clone();
+                for key in keys {
+                    text_box::apply_key(editor, carets, key, false);
+                }
+                return Some(true);
+            }
+            'p' | 'P' if vim.unnamed_block => {
+                put(editor, UNNAMED, character == 'p', 1);
+                return Some(true);
+            }
+            _ => {}
+        }
+    }
+
+    record(editor, carets, vim, key, auto_close)
+}
+
+// Run a key of a command of the editor, keeping the keys of the command until it is done
+fn record(
+    editor: &mut Editor,
+    carets: &mut Vec<Caret>,
+    vim: &mut Vim,
+    key: MacroKey,
+    auto_close: bool,
+) -> Option<bool> {
+    vim.keys.push(key);
+    let edited = text_box::apply_key(editor, carets, key, auto_close);
+    match editor.parser().mode {
+        ViMode::Insert | ViMode::Replace | ViMode::Extra(_) => {}
+        ViMode::Normal if cmd_pending(editor) => {}
+        _ => {
+            let keys = mem::take(&mut vim.keys);
+            if yanks(&keys) {
+                vim.unnamed_block = false;
+            }
+            if is_change(&keys) {
+                vim.last_change = keys;
+            }
+        }
+    }
+    edited
+}